@@ -17,7 +17,8 @@ fi
 # 构建 LSP 服务器
 echo ""
 echo "📦 构建 LSP 服务器..."
-cargo build --release
+# 把提交哈希写入 `aether-lsp --version` 和 `aether/serverStatus`
+AETHER_LSP_GIT_HASH=$(git rev-parse --short HEAD 2>/dev/null || true) cargo build --release
 
 echo ""
 echo "✅ LSP 服务器编译完成"
@@ -0,0 +1,186 @@
+//! Constant indices past the end of an array literal
+//!
+//! After `Set ARR [1, 2, 3]`, `ARR[5]` fails every time it runs. The literal's
+//! length is only trusted while nothing in the file can change it: no later
+//! `Set ARR ...` or `Set ARR[...] ...` (a Write occurrence), no second binding
+//! of the name, and no `PUSH`/`POP` call on it.
+
+#[cfg(feature = "server")]
+use lsp_types::*;
+
+#[cfg(feature = "server")]
+use crate::ast::NodePath;
+use crate::ast::{self, BinOp, Expr, ExprKind, Node, UnaryOp};
+#[cfg(feature = "server")]
+use crate::locale::Locale;
+use crate::occurrences::Role;
+use crate::parser::ParsedDocument;
+
+/// Builtins that change the length of the array passed first
+const RESIZING_BUILTINS: &[&str] = &["PUSH", "POP"];
+
+/// The value of `expr` if it is arithmetic on number literals
+pub fn constant_index(expr: &Expr) -> Option<f64> {
+    match &expr.kind {
+        ExprKind::Number(number) => Some(*number),
+        ExprKind::Unary {
+            op: UnaryOp::Minus,
+            expr,
+        } => constant_index(expr).map(|value| -value),
+        ExprKind::Binary { left, op, right } => {
+            let (left, right) = (constant_index(left)?, constant_index(right)?);
+            match op {
+                BinOp::Add => Some(left + right),
+                BinOp::Subtract => Some(left - right),
+                BinOp::Multiply => Some(left * right),
+                BinOp::Divide if right != 0.0 => Some(left / right),
+                BinOp::Modulo if right != 0.0 => Some(left % right),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The element count of the array literal bound to `name`, if the file never
+/// changes it
+pub fn known_length(doc: &ParsedDocument, name: &str) -> Option<usize> {
+    let length = *doc.symbols.array_lengths.get(name)?;
+    let occurrences = doc.occurrences.get(name)?;
+    let definitions = occurrences
+        .iter()
+        .filter(|occurrence| occurrence.role == Role::Definition)
+        .count();
+    let rebound = occurrences
+        .iter()
+        .any(|occurrence| matches!(occurrence.role, Role::Write | Role::Import));
+    if definitions != 1 || rebound || resized(doc, name) {
+        return None;
+    }
+    Some(length)
+}
+
+/// Whether `index` certainly misses an array of `length` elements; a fractional
+/// index is left to the runtime
+pub fn out_of_range(index: f64, length: usize) -> bool {
+    index.fract() == 0.0 && (index < 0.0 || index >= length as f64)
+}
+
+/// Whether a `PUSH` or `POP` call in the file takes `name` as its array
+fn resized(doc: &ParsedDocument, name: &str) -> bool {
+    RESIZING_BUILTINS
+        .iter()
+        .filter_map(|builtin| doc.occurrences.get(builtin))
+        .flatten()
+        .filter(|occurrence| occurrence.role == Role::Call)
+        .any(|occurrence| {
+            let Some(path) = ast::node_at_position(&doc.ast, occurrence.span.start) else {
+                // Can't see the call, so can't rule it out
+                return true;
+            };
+            path.nodes.iter().rev().any(|node| match node {
+                Node::Expr(Expr {
+                    kind: ExprKind::Call { func, args },
+                    ..
+                }) if func.span == occurrence.span => matches!(
+                    args.first().map(|arg| &arg.kind),
+                    Some(ExprKind::Identifier(arg)) if arg.as_str() == name
+                ),
+                _ => false,
+            })
+        })
+}
+
+#[cfg(feature = "server")]
+/// Hover for an index into an array of known length, when the cursor is on the
+/// brackets or the index rather than the array's name
+pub fn index_hover(path: &NodePath, doc: &ParsedDocument, locale: Locale) -> Option<Hover> {
+    let (index_expr, object, index) = path.nodes.iter().rev().find_map(|node| match node {
+        Node::Expr(
+            expr @ Expr {
+                kind: ExprKind::Index { object, index },
+                ..
+            },
+        ) => Some((*expr, object, index)),
+        _ => None,
+    })?;
+    let ExprKind::Identifier(name) = &object.kind else {
+        return None;
+    };
+    if path
+        .innermost_expr()
+        .is_some_and(|innermost| std::ptr::eq(innermost, object.as_ref()))
+    {
+        return None;
+    }
+    let length = known_length(doc, name)?;
+
+    let mut value = match locale {
+        Locale::Zh => format!("`{}` 由数组字面量创建，共 {} 个元素", name, length),
+        Locale::En => format!(
+            "`{}` comes from an array literal with {} elements",
+            name, length
+        ),
+    };
+    if let Some(constant) = constant_index(index)
+        && out_of_range(constant, length)
+    {
+        value.push_str(&match locale {
+            Locale::Zh => format!("；索引 {} 超出范围", constant),
+            Locale::En => format!("; index {} is out of range", constant),
+        });
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(index_expr.span),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_known_length() {
+        let length = |text: &str| known_length(&Parser::new(text).parse(), "ARR");
+        assert_eq!(length("Set ARR [1, 2, 3]\nPRINTLN(ARR[0])"), Some(3));
+        assert_eq!(length("Set ARR [1, 2, 3]\nPUSH(ARR, 4)"), None);
+        assert_eq!(length("Set ARR [1, 2, 3]\nSet LAST POP(ARR)"), None);
+        assert_eq!(length("Set ARR [1, 2, 3]\nSet ARR[0] 9"), None);
+        assert_eq!(length("Set ARR [1, 2, 3]\nSet ARR [1]"), None);
+        assert_eq!(
+            length("Set ARR [1]\nFunc F(ARR) {\n    Return ARR\n}"),
+            None
+        );
+        // Pushing onto another array, or reading ARR elsewhere, changes nothing
+        assert_eq!(
+            length("Set ARR [1, 2, 3]\nSet OTHER []\nPUSH(OTHER, ARR)\nPRINTLN(LEN(ARR))"),
+            Some(3)
+        );
+        assert_eq!(length("Set ARR {\"a\": 1}"), None);
+    }
+
+    #[test]
+    fn test_constant_index() {
+        let index = |text: &str| {
+            let doc = Parser::new(&format!("Set X {}", text)).parse();
+            match &doc.ast[0].kind {
+                ast::StmtKind::Set { value, .. } => constant_index(value),
+                other => panic!("expected a Set, got {:?}", other),
+            }
+        };
+        assert_eq!(index("5"), Some(5.0));
+        assert_eq!(index("-1"), Some(-1.0));
+        assert_eq!(index("(2 * 3) - 1"), Some(5.0));
+        assert_eq!(index("1 / 0"), None);
+        assert_eq!(index("N + 1"), None);
+        assert!(out_of_range(3.0, 3));
+        assert!(out_of_range(-1.0, 3));
+        assert!(!out_of_range(2.0, 3));
+        assert!(!out_of_range(3.5, 3));
+    }
+}
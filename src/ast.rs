@@ -1,13 +1,163 @@
 //! Abstract Syntax Tree (AST) definitions for the Aether language
 
+use lsp_types::{Position, Range};
 use serde::{Deserialize, Serialize};
 
-pub type Program = Vec<Stmt>;
+use crate::intern::Name;
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+
+/// Source range covered by a node (zero-based line and character)
+pub type Span = Range;
+
+/// A parsed file: its statements plus file-level metadata collected while parsing
+///
+/// Derefs to the top-level statements, so it can be walked like a slice.
+/// Equality only compares the statements.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    statements: Vec<Stmt>,
+    comments: Vec<Comment>,
+    imports: Vec<ImportInfo>,
+    exports: Vec<Ident>,
+}
+
+/// A `//` or `/* */` comment, with its delimiters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+    pub block: bool,
+    /// Follows code on its line, like `Set LIMIT 3 // attempts`
+    pub trailing: bool,
+}
+
+/// One `Import` statement
+#[derive(Debug, Clone)]
+pub struct ImportInfo {
+    pub path: String,
+    pub names: Vec<Ident>,
+    pub aliases: Vec<Option<Ident>>,
+    /// `Import {A, B} From ...` as opposed to the whole-module `Import A From ...`
+    pub braced: bool,
+    pub span: Span,
+}
+
+impl ImportInfo {
+    /// The local names the import binds (the alias where there is one)
+    pub fn bindings(&self) -> impl Iterator<Item = &Ident> {
+        self.names
+            .iter()
+            .zip(&self.aliases)
+            .map(|(name, alias)| alias.as_ref().unwrap_or(name))
+    }
+}
+
+impl Program {
+    /// Build a program, collecting its top-level imports and exports
+    pub fn new(statements: Vec<Stmt>, comments: Vec<Comment>) -> Self {
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        for stmt in &statements {
+            match &stmt.kind {
+                StmtKind::Import {
+                    names,
+                    path,
+                    aliases,
+                    braced,
+                } => imports.push(ImportInfo {
+                    path: path.clone(),
+                    names: names.clone(),
+                    aliases: aliases.clone(),
+                    braced: *braced,
+                    span: stmt.span,
+                }),
+                StmtKind::Export(name) => exports.push(name.clone()),
+                _ => {}
+            }
+        }
+        Program {
+            statements,
+            comments,
+            imports,
+            exports,
+        }
+    }
+
+    /// Swap in the comments of a text that differs from this one only in comments
+    pub fn set_comments(&mut self, comments: Vec<Comment>) {
+        self.comments = comments;
+    }
+
+    pub fn statements(&self) -> &[Stmt] {
+        &self.statements
+    }
+
+    /// Every comment in source order, including ones inside blocks
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Top-level `Import` statements in source order
+    pub fn imports(&self) -> &[ImportInfo] {
+        &self.imports
+    }
+
+    /// The whole-module import bound to `name`, as in `Import UTILS From "./utils.ae"`
+    pub fn namespace_import(&self, name: &str) -> Option<&ImportInfo> {
+        self.imports
+            .iter()
+            .find(|import| !import.braced && import.bindings().any(|binding| binding.name == name))
+    }
+
+    /// Names listed by top-level `Export` statements
+    pub fn exports(&self) -> &[Ident] {
+        &self.exports
+    }
+}
+
+impl std::ops::Deref for Program {
+    type Target = [Stmt];
+
+    fn deref(&self) -> &[Stmt] {
+        &self.statements
+    }
+}
+
+impl<'a> IntoIterator for &'a Program {
+    type Item = &'a Stmt;
+    type IntoIter = std::slice::Iter<'a, Stmt>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.statements.iter()
+    }
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
+}
+
+/// An identifier together with the span of the token it was read from
+///
+/// Equality only compares the name, so spans never affect AST comparisons.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ident {
+    pub name: Name,
+    pub span: Span,
+}
+
+/// A statement and the source range it was parsed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Stmt {
+pub enum StmtKind {
     Set {
-        name: String,
+        name: Ident,
         value: Expr,
     },
     SetIndex {
@@ -16,17 +166,17 @@ pub enum Stmt {
         value: Expr,
     },
     FuncDef {
-        name: String,
-        params: Vec<String>,
+        name: Ident,
+        params: Vec<Ident>,
         body: Vec<Stmt>,
     },
     GeneratorDef {
-        name: String,
-        params: Vec<String>,
+        name: Ident,
+        params: Vec<Ident>,
         body: Vec<Stmt>,
     },
     LazyDef {
-        name: String,
+        name: Ident,
         expr: Expr,
     },
     Return(Expr),
@@ -38,13 +188,13 @@ pub enum Stmt {
         body: Vec<Stmt>,
     },
     For {
-        var: String,
+        var: Ident,
         iterable: Expr,
         body: Vec<Stmt>,
     },
     ForIndexed {
-        index_var: String,
-        value_var: String,
+        index_var: Ident,
+        value_var: Ident,
         iterable: Expr,
         body: Vec<Stmt>,
     },
@@ -54,25 +204,40 @@ pub enum Stmt {
         default: Option<Vec<Stmt>>,
     },
     Import {
-        names: Vec<String>,
+        names: Vec<Ident>,
         path: String,
-        aliases: Vec<Option<String>>,
+        aliases: Vec<Option<Ident>>,
+        /// `Import {A, B} From ...` as opposed to `Import A From ...`
+        braced: bool,
     },
-    Export(String),
+    Export(Ident),
     Throw(Expr),
+    /// `Try { body } Catch (ERROR) { handler }`; the error variable is optional
+    Try {
+        body: Vec<Stmt>,
+        error_var: Option<Ident>,
+        handler: Vec<Stmt>,
+    },
     Expression(Expr),
 }
 
+/// An expression and the source range it was parsed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Expr {
+pub enum ExprKind {
     Number(f64),
     BigInteger(String),
     String(String),
     Boolean(bool),
     Null,
-    Identifier(String),
+    Identifier(Name),
     Array(Vec<Expr>),
-    Dict(Vec<(String, Expr)>),
+    Dict(Vec<(DictKey, Expr)>),
     Binary {
         left: Box<Expr>,
         op: BinOp,
@@ -90,6 +255,13 @@ pub enum Expr {
         object: Box<Expr>,
         index: Box<Expr>,
     },
+    /// `Force(expr)`, evaluating a `Lazy` binding
+    Force(Box<Expr>),
+    /// `UTILS.PARSE_CONFIG`, a member of an imported module
+    Member {
+        object: Box<Expr>,
+        member: Ident,
+    },
     If {
         condition: Box<Expr>,
         then_branch: Vec<Stmt>,
@@ -97,11 +269,34 @@ pub enum Expr {
         else_branch: Option<Vec<Stmt>>,
     },
     Lambda {
-        params: Vec<String>,
+        params: Vec<Ident>,
         body: Vec<Stmt>,
     },
 }
 
+/// The key of one entry in a dict literal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DictKey {
+    /// `{NAME: ...}`, the name as a string key
+    Ident(String),
+    /// `{"name": ...}`
+    Str(String),
+    /// `{1: ...}`
+    Num(f64),
+    /// `{[EXPR]: ...}`, whatever the expression evaluates to
+    Computed(Expr),
+}
+
+impl DictKey {
+    /// The string an identifier or string key stands for
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            DictKey::Ident(key) | DictKey::Str(key) => Some(key),
+            DictKey::Num(_) | DictKey::Computed(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BinOp {
     Add,
@@ -125,9 +320,383 @@ pub enum UnaryOp {
     Not,
 }
 
+/// Check whether a position lies within a span (both ends inclusive)
+pub fn span_contains(span: Span, pos: Position) -> bool {
+    (span.start.line, span.start.character) <= (pos.line, pos.character)
+        && (pos.line, pos.character) <= (span.end.line, span.end.character)
+}
+
+/// A statement or expression, as found by [`node_at_position`]
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+impl<'a> Node<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Stmt(stmt) => stmt.span,
+            Node::Expr(expr) => expr.span,
+        }
+    }
+
+    /// Direct children in source order
+    fn children(&self) -> Vec<Node<'a>> {
+        let mut children = Vec::new();
+        match self {
+            Node::Stmt(stmt) => match &stmt.kind {
+                StmtKind::Set { value, .. } => children.push(Node::Expr(value)),
+                StmtKind::SetIndex {
+                    object,
+                    index,
+                    value,
+                } => children.extend([Node::Expr(object), Node::Expr(index), Node::Expr(value)]),
+                StmtKind::FuncDef { body, .. } | StmtKind::GeneratorDef { body, .. } => {
+                    children.extend(body.iter().map(Node::Stmt))
+                }
+                StmtKind::LazyDef { expr, .. }
+                | StmtKind::Return(expr)
+                | StmtKind::Yield(expr)
+                | StmtKind::Throw(expr)
+                | StmtKind::Expression(expr) => children.push(Node::Expr(expr)),
+                StmtKind::While { condition, body } => {
+                    children.push(Node::Expr(condition));
+                    children.extend(body.iter().map(Node::Stmt));
+                }
+                StmtKind::For { iterable, body, .. }
+                | StmtKind::ForIndexed { iterable, body, .. } => {
+                    children.push(Node::Expr(iterable));
+                    children.extend(body.iter().map(Node::Stmt));
+                }
+                StmtKind::Switch {
+                    expr,
+                    cases,
+                    default,
+                } => {
+                    children.push(Node::Expr(expr));
+                    for (case_expr, case_body) in cases {
+                        children.push(Node::Expr(case_expr));
+                        children.extend(case_body.iter().map(Node::Stmt));
+                    }
+                    if let Some(default_body) = default {
+                        children.extend(default_body.iter().map(Node::Stmt));
+                    }
+                }
+                StmtKind::Try { body, handler, .. } => {
+                    children.extend(body.iter().chain(handler).map(Node::Stmt))
+                }
+                StmtKind::Import { .. }
+                | StmtKind::Export(_)
+                | StmtKind::Break
+                | StmtKind::Continue => {}
+            },
+            Node::Expr(expr) => match &expr.kind {
+                ExprKind::Array(elements) => children.extend(elements.iter().map(Node::Expr)),
+                ExprKind::Dict(pairs) => {
+                    for (key, value) in pairs {
+                        if let DictKey::Computed(key) = key {
+                            children.push(Node::Expr(key));
+                        }
+                        children.push(Node::Expr(value));
+                    }
+                }
+                ExprKind::Binary { left, right, .. } => {
+                    children.extend([Node::Expr(left), Node::Expr(right)])
+                }
+                ExprKind::Unary { expr, .. } => children.push(Node::Expr(expr)),
+                ExprKind::Call { func, args } => {
+                    children.push(Node::Expr(func));
+                    children.extend(args.iter().map(Node::Expr));
+                }
+                ExprKind::Index { object, index } => {
+                    children.extend([Node::Expr(object), Node::Expr(index)])
+                }
+                ExprKind::Member { object, .. } => children.push(Node::Expr(object)),
+                ExprKind::Force(expr) => children.push(Node::Expr(expr)),
+                ExprKind::If {
+                    condition,
+                    then_branch,
+                    elif_branches,
+                    else_branch,
+                } => {
+                    children.push(Node::Expr(condition));
+                    children.extend(then_branch.iter().map(Node::Stmt));
+                    for (elif_condition, elif_body) in elif_branches {
+                        children.push(Node::Expr(elif_condition));
+                        children.extend(elif_body.iter().map(Node::Stmt));
+                    }
+                    if let Some(else_body) = else_branch {
+                        children.extend(else_body.iter().map(Node::Stmt));
+                    }
+                }
+                ExprKind::Lambda { body, .. } => children.extend(body.iter().map(Node::Stmt)),
+                ExprKind::Number(_)
+                | ExprKind::BigInteger(_)
+                | ExprKind::String(_)
+                | ExprKind::Boolean(_)
+                | ExprKind::Null
+                | ExprKind::Identifier(_) => {}
+            },
+        }
+        children
+    }
+}
+
+/// The chain of nodes from a top-level statement down to the innermost node at a position
+#[derive(Debug, Clone)]
+pub struct NodePath<'a> {
+    /// Outermost first; never empty
+    pub nodes: Vec<Node<'a>>,
+}
+
+impl<'a> NodePath<'a> {
+    /// Innermost expression on the path, if the position is inside one
+    pub fn innermost_expr(&self) -> Option<&'a Expr> {
+        self.nodes.iter().rev().find_map(|node| match node {
+            Node::Expr(expr) => Some(*expr),
+            Node::Stmt(_) => None,
+        })
+    }
+}
+
+/// Find the nodes enclosing `pos`, outermost first
+///
+/// Nodes are matched half-open, so a position on the punctuation right after
+/// a child resolves to the enclosing expression, and one in the whitespace
+/// between a block's statements to the statement owning the block. A position
+/// at the very end of a top-level statement still finds that statement.
+/// Top-level statements are found by binary search over their spans.
+pub fn node_at_position(program: &[Stmt], pos: Position) -> Option<NodePath<'_>> {
+    let index = program.partition_point(|stmt| !position_before(pos, stmt.span.end));
+    let stmt = program
+        .get(index)
+        .filter(|stmt| covers(stmt.span, pos))
+        .or_else(|| {
+            index
+                .checked_sub(1)
+                .and_then(|previous| program.get(previous))
+                .filter(|stmt| span_contains(stmt.span, pos))
+        })?;
+
+    let mut nodes = vec![Node::Stmt(stmt)];
+    while let Some(child) = nodes
+        .last()
+        .unwrap()
+        .children()
+        .into_iter()
+        .find(|child| covers(child.span(), pos))
+    {
+        nodes.push(child);
+    }
+    Some(NodePath { nodes })
+}
+
+fn position_before(a: Position, b: Position) -> bool {
+    (a.line, a.character) < (b.line, b.character)
+}
+
+/// Half-open containment: `start <= pos < end`
+fn covers(span: Span, pos: Position) -> bool {
+    !position_before(pos, span.start) && position_before(pos, span.end)
+}
+
+/// The innermost expression whose span contains `pos`
+pub fn find_expr_at(program: &[Stmt], pos: Position) -> Option<&Expr> {
+    node_at_position(program, pos)?.innermost_expr()
+}
+
+/// Call `f` on every expression in `body`, outer expressions before inner ones
+pub fn for_each_expr<'a>(body: &'a [Stmt], f: &mut impl FnMut(&'a Expr)) {
+    struct ExprVisitor<'f, F>(&'f mut F);
+
+    impl<'a, F: FnMut(&'a Expr)> Visitor<'a> for ExprVisitor<'_, F> {
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            (self.0)(expr);
+            walk_expr(self, expr);
+        }
+    }
+
+    ExprVisitor(f).visit_block(body);
+}
+
+/// Call `f` on every statement, nested blocks included, in source order
+pub fn for_each_stmt<'a>(body: &'a [Stmt], f: &mut impl FnMut(&'a Stmt)) {
+    struct StmtVisitor<'f, F>(&'f mut F);
+
+    impl<'a, F: FnMut(&'a Stmt)> Visitor<'a> for StmtVisitor<'_, F> {
+        fn visit_stmt(&mut self, stmt: &'a Stmt) {
+            (self.0)(stmt);
+            walk_stmt(self, stmt);
+        }
+    }
+
+    StmtVisitor(f).visit_block(body);
+}
+
+/// How deeply `If`/`While`/`For`/`Switch` blocks nest in `body`: 0 for straight-line
+/// code, 1 for a single `If`. Elif and else branches sit at their `If`'s level, and
+/// function and lambda bodies don't add a level of their own.
+pub fn nesting_depth(body: &[Stmt]) -> usize {
+    #[derive(Default)]
+    struct DepthVisitor {
+        depth: usize,
+        deepest: usize,
+    }
+
+    impl DepthVisitor {
+        fn nested(&mut self, walk: impl FnOnce(&mut Self)) {
+            self.depth += 1;
+            self.deepest = self.deepest.max(self.depth);
+            walk(self);
+            self.depth -= 1;
+        }
+    }
+
+    impl<'a> Visitor<'a> for DepthVisitor {
+        fn visit_stmt(&mut self, stmt: &'a Stmt) {
+            match &stmt.kind {
+                StmtKind::While { .. }
+                | StmtKind::For { .. }
+                | StmtKind::ForIndexed { .. }
+                | StmtKind::Switch { .. } => self.nested(|visitor| walk_stmt(visitor, stmt)),
+                _ => walk_stmt(self, stmt),
+            }
+        }
+
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            match &expr.kind {
+                ExprKind::If { .. } => self.nested(|visitor| walk_expr(visitor, expr)),
+                _ => walk_expr(self, expr),
+            }
+        }
+    }
+
+    let mut visitor = DepthVisitor::default();
+    visitor.visit_block(body);
+    visitor.deepest
+}
+
+/// Number of statements in `body`, nested blocks included
+pub fn statement_count(body: &[Stmt]) -> usize {
+    let mut count = 0;
+    for_each_stmt(body, &mut |_| count += 1);
+    count
+}
+
+impl Ident {
+    pub fn new(name: impl Into<Name>, span: Span) -> Self {
+        Ident {
+            name: name.into(),
+            span,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Ident {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl PartialEq<str> for Ident {
+    fn eq(&self, other: &str) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<&str> for Ident {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+impl std::ops::Deref for Ident {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+
+    /// Statement blocks nested inside a control-flow statement
+    ///
+    /// Function and generator bodies are not included; callers that descend
+    /// into definitions handle them explicitly.
+    pub fn child_blocks(&self) -> Vec<&[Stmt]> {
+        match &self.kind {
+            StmtKind::While { body, .. }
+            | StmtKind::For { body, .. }
+            | StmtKind::ForIndexed { body, .. } => vec![body.as_slice()],
+            StmtKind::Switch { cases, default, .. } => cases
+                .iter()
+                .map(|(_, body)| body.as_slice())
+                .chain(default.as_deref())
+                .collect(),
+            StmtKind::Try { body, handler, .. } => vec![body.as_slice(), handler.as_slice()],
+            StmtKind::Expression(Expr {
+                kind:
+                    ExprKind::If {
+                        then_branch,
+                        elif_branches,
+                        else_branch,
+                        ..
+                    },
+                ..
+            }) => std::iter::once(then_branch.as_slice())
+                .chain(elif_branches.iter().map(|(_, body)| body.as_slice()))
+                .chain(else_branch.as_deref())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl PartialEq for Stmt {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
 impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+}
+
+impl ExprKind {
     pub fn binary(left: Expr, op: BinOp, right: Expr) -> Self {
-        Expr::Binary {
+        ExprKind::Binary {
             left: Box::new(left),
             op,
             right: Box::new(right),
@@ -135,23 +704,207 @@ impl Expr {
     }
 
     pub fn unary(op: UnaryOp, expr: Expr) -> Self {
-        Expr::Unary {
+        ExprKind::Unary {
             op,
             expr: Box::new(expr),
         }
     }
 
     pub fn call(func: Expr, args: Vec<Expr>) -> Self {
-        Expr::Call {
+        ExprKind::Call {
             func: Box::new(func),
             args,
         }
     }
 
     pub fn index(object: Expr, index: Expr) -> Self {
-        Expr::Index {
+        ExprKind::Index {
             object: Box::new(object),
             index: Box::new(index),
         }
     }
+
+    pub fn member(object: Expr, member: Ident) -> Self {
+        ExprKind::Member {
+            object: Box::new(object),
+            member,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn kind_at(text: &str, line: u32, character: u32) -> Option<ExprKind> {
+        let doc = Parser::new(text).parse();
+        find_expr_at(&doc.ast, Position::new(line, character)).map(|expr| expr.kind.clone())
+    }
+
+    #[test]
+    fn test_find_expr_at_returns_innermost() {
+        let text = "Set TOTAL ADD(12, PRICE * 3)";
+        assert_eq!(kind_at(text, 0, 15), Some(ExprKind::Number(12.0)));
+        assert_eq!(
+            kind_at(text, 0, 19),
+            Some(ExprKind::Identifier("PRICE".into()))
+        );
+        assert!(matches!(
+            kind_at(text, 0, 11),
+            Some(ExprKind::Identifier(name)) if name == "ADD"
+        ));
+        assert_eq!(kind_at(text, 0, 1), None);
+    }
+
+    #[test]
+    fn test_find_expr_at_descends_into_blocks() {
+        let text = "Func F(X) {\n    Return MAP(X, Lambda Y -> Y * 2)\n}";
+        assert_eq!(kind_at(text, 1, 34), Some(ExprKind::Number(2.0)));
+    }
+
+    const NESTED: &str = "Func OUTER(X) {
+    Set Y (X + 1)
+
+    If (Y > 2) {
+        Return MAP([Y], Lambda Z -> Z * 2)
+    }
+    Return Y
+}
+
+Set TOTAL OUTER(3)";
+
+    fn label(node: &Node) -> &'static str {
+        match node {
+            Node::Stmt(stmt) => match stmt.kind {
+                StmtKind::Set { .. } => "Set",
+                StmtKind::FuncDef { .. } => "Func",
+                StmtKind::Return(_) => "Return",
+                StmtKind::Expression(_) => "Expression",
+                _ => "Stmt",
+            },
+            Node::Expr(expr) => match expr.kind {
+                ExprKind::Number(_) => "Number",
+                ExprKind::Identifier(_) => "Identifier",
+                ExprKind::Array(_) => "Array",
+                ExprKind::Binary { .. } => "Binary",
+                ExprKind::Call { .. } => "Call",
+                ExprKind::If { .. } => "If",
+                ExprKind::Lambda { .. } => "Lambda",
+                _ => "Expr",
+            },
+        }
+    }
+
+    fn path_at(program: &[Stmt], line: u32, character: u32) -> Option<Vec<&'static str>> {
+        node_at_position(program, Position::new(line, character))
+            .map(|path| path.nodes.iter().map(label).collect())
+    }
+
+    #[test]
+    fn test_node_at_position_paths() {
+        let doc = Parser::new(NESTED).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let ast = &doc.ast;
+
+        assert_eq!(path_at(ast, 9, 12), Some(vec!["Set", "Call", "Identifier"]));
+        assert_eq!(
+            path_at(ast, 1, 11),
+            Some(vec!["Func", "Set", "Binary", "Identifier"])
+        );
+        assert_eq!(
+            path_at(ast, 4, 40),
+            Some(vec![
+                "Func",
+                "Expression",
+                "If",
+                "Return",
+                "Call",
+                "Lambda",
+                "Return",
+                "Binary",
+                "Number"
+            ])
+        );
+        assert_eq!(
+            path_at(ast, 4, 20),
+            Some(vec![
+                "Func",
+                "Expression",
+                "If",
+                "Return",
+                "Call",
+                "Array",
+                "Identifier"
+            ])
+        );
+        assert_eq!(
+            path_at(ast, 6, 11),
+            Some(vec!["Func", "Return", "Identifier"])
+        );
+    }
+
+    #[test]
+    fn test_node_at_position_on_punctuation() {
+        let doc = Parser::new(NESTED).parse();
+        // The `(` of `OUTER(3)` and the `,` in `MAP([Y], ...)`
+        assert_eq!(path_at(&doc.ast, 9, 15), Some(vec!["Set", "Call"]));
+        assert_eq!(
+            path_at(&doc.ast, 4, 22),
+            Some(vec!["Func", "Expression", "If", "Return", "Call"])
+        );
+        // The operator of `X + 1`
+        assert_eq!(
+            path_at(&doc.ast, 1, 13),
+            Some(vec!["Func", "Set", "Binary"])
+        );
+    }
+
+    #[test]
+    fn test_node_at_position_in_whitespace() {
+        let doc = Parser::new(NESTED).parse();
+        // Blank line inside the function body: the function itself
+        assert_eq!(path_at(&doc.ast, 2, 0), Some(vec!["Func"]));
+        // Just past the last character of a statement
+        assert_eq!(path_at(&doc.ast, 9, 18), Some(vec!["Set"]));
+        // Between top-level statements and past the end of the file
+        assert_eq!(path_at(&doc.ast, 8, 0), None);
+        assert_eq!(path_at(&doc.ast, 40, 0), None);
+        assert_eq!(path_at(&[], 0, 0), None);
+    }
+
+    #[test]
+    fn test_node_at_position_binary_search_matches_scan() {
+        let text: String = (0..50)
+            .map(|i| format!("Set V{} ({} + {})\n", i, i, i + 1))
+            .collect();
+        let doc = Parser::new(&text).parse();
+        for line in 0..50u32 {
+            let path = node_at_position(&doc.ast, Position::new(line, 1)).unwrap();
+            let Node::Stmt(stmt) = path.nodes[0] else {
+                panic!("path must start at a statement");
+            };
+            assert_eq!(stmt.span.start.line, line);
+        }
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let depth = |text: &str| nesting_depth(&Parser::new(text).parse().ast);
+        assert_eq!(depth("Set X 1"), 0);
+        assert_eq!(
+            depth("If (A) {\n    Set X 1\n} Elif (B) {\n    Set X 2\n} Else {\n    Set X 3\n}"),
+            1
+        );
+        assert_eq!(
+            depth(
+                "For I In [1] {\n    While (I) {\n        If (I) {\n            Break\n        }\n    }\n}\nIf (A) {\n    Set X 1\n}"
+            ),
+            3
+        );
+        assert_eq!(
+            depth("Func F(L) {\n    Return MAP(L, Lambda V -> If (V) {\n        V\n    })\n}"),
+            1
+        );
+    }
 }
@@ -1,61 +1,336 @@
-use dashmap::DashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use dashmap::mapref::one::Ref;
+use dashmap::{DashMap, DashSet};
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-use crate::completion::get_completions;
-use crate::diagnostics::DiagnosticEngine;
+use crate::array_bounds;
+use crate::ast::{self, ExprKind, ImportInfo, Span, StmtKind, span_contains};
+use crate::builtins::{self, BuiltinDocParams, CatalogEntry, CatalogParams};
+use crate::call_hierarchy::CallGraph;
+use crate::cells;
+use crate::client_caps::ClientCaps;
+use crate::code_actions;
+use crate::completion::{
+    ImportContext, dict_key_completions, dict_key_context, get_completions, get_completions_with,
+    import_completions, import_context, member_completions, member_context, namespace_import_path,
+};
+use crate::config::Settings;
+use crate::crash;
+use crate::cross_file::{self, CrossFileOptions};
+use crate::diagnostic_codes::{self, DiagnosticCode, DiagnosticCodeEntry};
+use crate::diagnostics::{AnalysisOptions, DiagnosticEngine};
+use crate::dict_key_rename;
+use crate::doc_skeleton;
+use crate::document_key::document_key;
+use crate::extra_builtins;
+use crate::folding::{self, FallbackFunction};
+use crate::generators;
+use crate::header::{self, Directive};
+use crate::ignore::{self, IgnoreRules};
+use crate::keywords;
+use crate::line_index::LineIndex;
+use crate::literals;
+use crate::locale::Locale;
+use crate::logging;
+use crate::loops;
+use crate::mask::Mask;
+use crate::modules::{self, ImportBase, ModuleCache};
+use crate::occurrences::{self, Role};
 use crate::parser::{ParsedDocument, Parser};
-use crate::symbols::SymbolTable;
+use crate::perf::{HandlerPerf, PerfStats};
+use crate::request_error::RequestError;
+use crate::set_runs;
+use crate::signature_help;
+use crate::snippets::{at_statement_start, snippet_completions};
+use crate::suppression;
+use crate::token_diff;
+use crate::workspace::{self, ModuleSummary, WorkspaceIndex};
 
 pub struct AetherLspBackend {
     client: Client,
+    /// Keyed by [`document_key`], as are the other per-document maps
     documents: DashMap<String, ParsedDocument>,
+    /// The URI the client last named each document by, for anything sent back
+    client_uris: DashMap<String, Url>,
+    /// Documents read from disk to answer a request before (or without) `didOpen`
+    disk_loaded: DashSet<String>,
+    settings: RwLock<Settings>,
+    /// Locale reported by the client in `initialize`
+    client_locale: RwLock<Locale>,
+    /// Set by `initialize` and `$/setTrace`; anything but `off` sends `$/logTrace`
+    trace: RwLock<TraceValue>,
+    /// What the client announced in `initialize`
+    client_caps: RwLock<ClientCaps>,
+    /// Last diagnostics of each document, reused while its text is unchanged
+    diagnostics: DashMap<String, CachedDiagnostics>,
+    next_result_id: AtomicU64,
+    modules: ModuleCache,
+    /// Summaries of every module under the workspace root, filled in the background
+    workspace_index: Arc<WorkspaceIndex>,
+    /// Bounds how many documents are parsed and linted at once
+    analysis_slots: Semaphore,
+    /// The latest analysis requested for each document; older jobs publish nothing
+    analysis_generation: DashMap<String, u64>,
+    next_generation: AtomicU64,
+    /// The names each open document offered importers at its last clean parse,
+    /// with what their `@deprecated` tags say
+    provided: DashMap<String, BTreeMap<String, Option<String>>>,
+    started: Instant,
+    /// How long each handler took, for `aether/perf`
+    perf: PerfStats,
 }
 
-/// Extract the word (identifier) at the given position
-fn extract_word_at_position(text: &str, position: Position) -> Option<String> {
-    let lines: Vec<&str> = text.lines().collect();
-    if position.line as usize >= lines.len() {
-        return None;
-    }
+/// `aether/serverStatus`: what a bug report needs to know about the running server
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub version: String,
+    pub uptime_seconds: u64,
+    /// Documents kept analyzed in memory, whether open or read from disk
+    pub documents: usize,
+    pub open_documents: usize,
+    pub workspace_roots: Vec<PathBuf>,
+    pub settings: Settings,
+    /// Diagnostics last reported for the open documents
+    pub diagnostics: SeverityCounts,
+}
 
-    let line = lines[position.line as usize];
-    let char_pos = position.character as usize;
+/// `aether/documentMeta` parameters
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetaParams {
+    pub text_document: TextDocumentIdentifier,
+}
 
-    if char_pos > line.len() {
-        return None;
-    }
+/// One `//! key: value` line of a document's header
+#[derive(Debug, Serialize)]
+pub struct MetaEntry {
+    pub key: String,
+    pub value: String,
+}
 
-    // 找到单词的起始和结束位置
-    let mut start = char_pos;
-    let mut end = char_pos;
+#[derive(Debug, Default, Serialize)]
+pub struct SeverityCounts {
+    pub error: usize,
+    pub warning: usize,
+    pub information: usize,
+    pub hint: usize,
+}
 
-    // 向左查找单词开始
-    while start > 0 {
-        let ch = line.chars().nth(start - 1)?;
-        if ch.is_alphanumeric() || ch == '_' {
-            start -= 1;
-        } else {
-            break;
-        }
+/// Diagnostics computed for one version of a document, per tier
+#[derive(Debug, Clone)]
+struct CachedDiagnostics {
+    content_hash: u64,
+    /// Changes whenever either tier is recomputed, for `previousResultId`
+    result_id: String,
+    /// Per-document lints, recomputed on every change
+    fast: Vec<Diagnostic>,
+    /// Cross-file checks from the last save; kept across edits until the next one
+    slow: Vec<Diagnostic>,
+}
+
+impl CachedDiagnostics {
+    fn all(&self) -> Vec<Diagnostic> {
+        self.fast.iter().chain(&self.slow).cloned().collect()
     }
+}
 
-    // 向右查找单词结束
-    while end < line.len() {
-        let ch = line.chars().nth(end)?;
-        if ch.is_alphanumeric() || ch == '_' {
-            end += 1;
-        } else {
-            break;
-        }
+/// What the editor may type into linked ranges and still keep them linked
+const IDENTIFIER_PATTERN: &str = "[A-Za-z_][A-Za-z0-9_]*";
+
+/// Open dependents whose cross-file checks run again when a module's exports change
+const MAX_DEPENDENTS_RECHECKED: usize = 32;
+
+/// The names importers may take from `doc`: its exports, or with
+/// `implicit_exports` every top-level definition as well; each with the
+/// deprecation note of the function it names, if any
+fn provided_names(
+    doc: &ParsedDocument,
+    implicit_exports: bool,
+) -> BTreeMap<String, Option<String>> {
+    let mut names: BTreeSet<String> = doc
+        .ast
+        .exports()
+        .iter()
+        .map(|name| name.as_str().to_string())
+        .collect();
+    if implicit_exports {
+        names.extend(doc.ast.iter().filter_map(|stmt| match &stmt.kind {
+            StmtKind::Set { name, .. }
+            | StmtKind::LazyDef { name, .. }
+            | StmtKind::FuncDef { name, .. }
+            | StmtKind::GeneratorDef { name, .. } => Some(name.as_str().to_string()),
+            _ => None,
+        }));
     }
+    names
+        .into_iter()
+        .map(|name| {
+            let deprecated = doc
+                .symbols
+                .functions
+                .iter()
+                .find(|function| function.name == name.as_str())
+                .and_then(|function| function.deprecated.clone());
+            (name, deprecated)
+        })
+        .collect()
+}
+
+/// The `aether-disable-next-line` directives of `doc`, as the line each one
+/// silences and the codes it lists
+fn suppressions(doc: &ParsedDocument) -> Vec<(u32, Vec<&str>)> {
+    doc.ast
+        .comments()
+        .iter()
+        .filter(|comment| !comment.block)
+        .filter_map(|comment| {
+            Some((
+                comment.span.end.line + 1,
+                suppression::directive_codes(&comment.text)?,
+            ))
+        })
+        .collect()
+}
+
+/// The leading whitespace of each line a comment opens, which the
+/// indentation lint checks like that of code
+fn comment_indents(doc: &ParsedDocument) -> Vec<(u32, &str)> {
+    doc.ast
+        .comments()
+        .iter()
+        .filter(|comment| !comment.trailing)
+        .filter_map(|comment| {
+            let line = comment.span.start.line;
+            let text = doc.line_index.line_text(&doc.text, line)?;
+            let code = text.trim_start_matches([' ', '\t']);
+            Some((line, &text[..text.len() - code.len()]))
+        })
+        .collect()
+}
+
+/// Whether `current`, the program of `previous` with other comments or line
+/// breaks, gets the diagnostics of `previous` moved by `shift`: none of the
+/// comments the analysis reads changed, nor with `indentation` linted the
+/// indentation of a comment line
+fn comments_inert(
+    shift: &token_diff::LineShift,
+    previous: &ParsedDocument,
+    current: &ParsedDocument,
+    indentation: bool,
+) -> bool {
+    let moved = |line: u32| shift.position(Position::new(line, 0)).line;
+    let deprecations = |doc: &ParsedDocument| {
+        doc.symbols
+            .functions
+            .iter()
+            .map(|function| (function.name.clone(), function.deprecated.clone()))
+            .collect::<Vec<_>>()
+    };
+    suppressions(previous)
+        .into_iter()
+        .map(|(line, codes)| (moved(line), codes))
+        .eq(suppressions(current))
+        && deprecations(previous) == deprecations(current)
+        && previous
+            .directives()
+            .into_iter()
+            .map(|directive| Directive {
+                span: shift.range(directive.span),
+                ..directive
+            })
+            .eq(current.directives())
+        && (!indentation
+            || comment_indents(previous)
+                .into_iter()
+                .map(|(line, indent)| (moved(line), indent))
+                .eq(comment_indents(current)))
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
 
-    if start < end {
-        Some(line[start..end].to_string())
+/// The quoted module path of `import`, which ends its statement
+fn import_path_range(doc: &ParsedDocument, import: &ImportInfo) -> Option<Range> {
+    let line_number = import.span.end.line;
+    let line = doc.line_index.line_text(&doc.text, line_number)?;
+    let quoted = format!("\"{}\"", import.path);
+    let line_start = doc
+        .line_index
+        .position_to_offset(&doc.text, Position::new(line_number, 0));
+    let start = line_start + line.rfind(&quoted)? + 1;
+    let end = start + import.path.len();
+    Some(Range::new(
+        doc.line_index.offset_to_position(&doc.text, start),
+        doc.line_index.offset_to_position(&doc.text, end),
+    ))
+}
+
+/// The line opening the hover of a function marked `@deprecated`
+fn deprecation_banner(note: &str, locale: Locale) -> String {
+    let label = locale.pick("已弃用", "Deprecated");
+    if note.is_empty() {
+        format!("> **{}**\n\n", label)
     } else {
-        None
+        format!("> **{}**: {}\n\n", label, note)
+    }
+}
+
+/// Extract the word (identifier) at the given position, with its range; none
+/// inside a string or comment
+fn extract_word_at_position(
+    lines: &LineIndex,
+    text: &str,
+    mask: &Mask,
+    position: Position,
+) -> Option<(String, Range)> {
+    if mask.contains(position) {
+        return None;
+    }
+    let line = lines.line_text(text, position.line)?;
+    if position.character > lines.line_len(text, position.line) {
+        return None;
     }
+    let line_start = lines.position_to_offset(text, Position::new(position.line, 0));
+    let cursor = lines.position_to_offset(text, position) - line_start;
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+
+    // 向左查找单词开始
+    let start = line[..cursor]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| is_word(ch))
+        .last()
+        .map_or(cursor, |(index, _)| index);
+
+    // 向右查找单词结束
+    let end = line[cursor..]
+        .char_indices()
+        .find(|&(_, ch)| !is_word(ch))
+        .map_or(line.len(), |(index, _)| cursor + index);
+
+    (start < end).then(|| {
+        let range = Range::new(
+            lines.offset_to_position(text, line_start + start),
+            lines.offset_to_position(text, line_start + end),
+        );
+        (line[start..end].to_string(), range)
+    })
 }
 
 impl AetherLspBackend {
@@ -63,206 +338,2222 @@ impl AetherLspBackend {
         AetherLspBackend {
             client,
             documents: DashMap::new(),
+            client_uris: DashMap::new(),
+            disk_loaded: DashSet::new(),
+            settings: RwLock::new(Settings::default()),
+            client_locale: RwLock::new(Locale::default()),
+            trace: RwLock::new(TraceValue::Off),
+            client_caps: RwLock::new(ClientCaps::default()),
+            diagnostics: DashMap::new(),
+            next_result_id: AtomicU64::new(0),
+            modules: ModuleCache::new(),
+            workspace_index: Arc::new(WorkspaceIndex::new()),
+            analysis_slots: Semaphore::new(
+                std::thread::available_parallelism().map_or(1, |count| count.get()),
+            ),
+            analysis_generation: DashMap::new(),
+            next_generation: AtomicU64::new(0),
+            provided: DashMap::new(),
+            started: Instant::now(),
+            perf: PerfStats::default(),
         }
     }
 
-    async fn parse_and_diagnose(&self, uri: Url, text: String) {
-        // 记录日志
-        self.client
-            .log_message(MessageType::INFO, format!("Parsing document: {}", uri))
+    /// Snapshot of the current settings
+    fn settings(&self) -> Settings {
+        self.settings
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// The `aether.locale` setting if present, otherwise the client's locale
+    fn locale(&self) -> Locale {
+        match self.settings().locale {
+            Some(tag) => Locale::from_tag(&tag),
+            None => self
+                .client_locale
+                .read()
+                .map(|locale| *locale)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn client_caps(&self) -> ClientCaps {
+        self.client_caps
+            .read()
+            .map(|caps| *caps)
+            .unwrap_or_default()
+    }
+
+    /// The first workspace folder, which paths in the settings are relative to
+    fn workspace_root(&self) -> Option<PathBuf> {
+        self.workspace_index.roots().into_iter().next()
+    }
+
+    /// Replace the settings, falling back to defaults (and warning) on malformed input
+    async fn update_settings(&self, value: &serde_json::Value) {
+        let settings = match Settings::from_json(value) {
+            Ok(settings) => settings,
+            Err(err) => {
+                let message = format!("Invalid aether settings, using defaults: {}", err);
+                logging::warn(&message);
+                self.client.log_message(MessageType::WARNING, message).await;
+                Settings::default()
+            }
+        };
+        if let Ok(mut current) = self.settings.write() {
+            *current = settings;
+        }
+    }
+
+    /// Report a routine event: to the server log, and as `$/logTrace` when the client traces
+    async fn trace(&self, message: String, verbose: impl FnOnce() -> String) {
+        logging::debug(&message);
+        let trace = self
+            .trace
+            .read()
+            .map(|trace| *trace)
+            .unwrap_or(TraceValue::Off);
+        if trace != TraceValue::Off {
+            let verbose = (trace == TraceValue::Verbose).then(verbose);
+            self.client
+                .send_notification::<notification::LogTrace>(LogTraceParams { message, verbose })
+                .await;
+        }
+    }
+
+    /// `$/setTrace`: how much the client wants to see through `$/logTrace`
+    pub async fn set_trace(&self, params: SetTraceParams) {
+        if let Ok(mut trace) = self.trace.write() {
+            *trace = params.value;
+        }
+    }
+
+    /// Load `aether.extraBuiltins` into the registry, warning the user if it can't be read
+    async fn reload_extra_builtins(&self) {
+        let specs = match &self.settings().extra_builtins {
+            Some(source) => match extra_builtins::load(source, self.workspace_root().as_deref()) {
+                Ok(specs) => specs,
+                Err(err) => {
+                    let message = match self.locale() {
+                        Locale::Zh => format!("无法加载 extraBuiltins: {}", err),
+                        Locale::En => format!("Could not load extraBuiltins: {}", err),
+                    };
+                    self.client
+                        .show_message(MessageType::WARNING, message)
+                        .await;
+                    return;
+                }
+            },
+            None => Vec::new(),
+        };
+        extra_builtins::install(specs);
+    }
+
+    /// `aether/builtins`: the builtin catalog for documentation panels
+    pub async fn builtin_catalog(&self, params: CatalogParams) -> Result<Vec<CatalogEntry>> {
+        Ok(builtins::catalog(&params))
+    }
+
+    /// `aether/diagnosticCodes`: every code diagnostics can carry, for a problems-panel legend
+    pub async fn diagnostic_codes(&self) -> Result<Vec<DiagnosticCodeEntry>> {
+        Ok(diagnostic_codes::catalog())
+    }
+
+    /// `aether/serverStatus`: version, uptime, tracked documents and settings, for triage
+    pub async fn server_status(&self) -> Result<ServerStatus> {
+        let mut diagnostics = SeverityCounts::default();
+        let open = self
+            .diagnostics
+            .iter()
+            .filter(|entry| !self.disk_loaded.contains(entry.key()));
+        for diagnostic in open.flat_map(|entry| entry.all()) {
+            let count = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => &mut diagnostics.warning,
+                Some(DiagnosticSeverity::INFORMATION) => &mut diagnostics.information,
+                Some(DiagnosticSeverity::HINT) => &mut diagnostics.hint,
+                _ => &mut diagnostics.error,
+            };
+            *count += 1;
+        }
+        Ok(ServerStatus {
+            version: crate::server::version(),
+            uptime_seconds: self.started.elapsed().as_secs(),
+            documents: self.documents.len(),
+            open_documents: self
+                .documents
+                .iter()
+                .filter(|entry| !self.disk_loaded.contains(entry.key()))
+                .count(),
+            workspace_roots: self.workspace_index.roots(),
+            settings: self.settings(),
+            diagnostics,
+        })
+    }
+
+    /// `aether/documentMeta`: the `//!` header of a document, in source order
+    pub async fn document_meta(&self, params: DocumentMetaParams) -> Result<Vec<MetaEntry>> {
+        let uri = params.text_document.uri;
+        let doc = self.document(&uri).ok_or_else(|| {
+            RequestError::DocumentNotOpen(uri.clone()).into_jsonrpc(self.locale())
+        })?;
+        Ok(doc
+            .metadata
+            .iter()
+            .map(|(key, value)| MetaEntry {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect())
+    }
+
+    /// `aether/perf`: how long each handler took, by name
+    pub async fn perf_report(&self) -> Result<BTreeMap<&'static str, HandlerPerf>> {
+        Ok(self.perf.report())
+    }
+
+    /// `aether/builtinDoc`: the markdown hover shows for a builtin
+    pub async fn builtin_doc(&self, params: BuiltinDocParams) -> Result<Option<String>> {
+        Ok(
+            builtins::builtin_hover(&params.name, self.locale()).and_then(|hover| {
+                match hover.contents {
+                    HoverContents::Markup(content) => Some(content.value),
+                    _ => None,
+                }
+            }),
+        )
+    }
+
+    /// Ask the client to report changes to `.ae` and ignore files made outside the editor
+    async fn register_file_watcher(&self) {
+        if !self.client_caps().watch_registration {
+            return;
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!(
+                        "**/*.{}",
+                        modules::MODULE_EXTENSION
+                    )),
+                    kind: None,
+                },
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{}", ignore::IGNORE_FILE)),
+                    kind: None,
+                },
+            ],
+        };
+        let registration = Registration {
+            id: "aether/watchedFiles".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            logging::warn(&format!("Could not register file watcher: {}", err));
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Could not register file watcher: {}", err),
+                )
+                .await;
+        }
+    }
+
+    /// Open documents importing one of `changed`, directly or through other modules
+    fn dependents_of(&self, changed: &HashSet<PathBuf>) -> Vec<(Url, String)> {
+        let roots = self.workspace_index.roots();
+        self.documents
+            .iter()
+            .filter(|doc| !self.disk_loaded.contains(doc.key()))
+            .filter_map(|doc| {
+                let uri = self.client_uri(doc.key())?;
+                let path = uri.to_file_path().ok()?;
+                let base_dir = path.parent()?;
+                // The editor's copy of a changed file is newer than the disk
+                if changed.contains(&path) {
+                    return None;
+                }
+                let imports = doc
+                    .ast
+                    .imports()
+                    .iter()
+                    .map(|import| modules::resolve_import_in(base_dir, &roots, &import.path))
+                    .collect();
+                self.workspace_index
+                    .reaches(imports, changed)
+                    .then(|| (uri, doc.text.clone()))
+            })
+            .collect()
+    }
+
+    /// Start indexing a workspace folder in the background, with its ignore
+    /// file read again
+    fn spawn_workspace_indexing(&self, root: PathBuf) {
+        tokio::spawn(index_workspace(
+            self.client.clone(),
+            self.workspace_index.clone(),
+            root,
+            self.settings().index.exclude,
+            self.client_caps().work_done_progress,
+            self.locale(),
+        ));
+    }
+
+    /// Where an imported `name` is defined in its module, via the workspace index
+    fn imported_definition(&self, uri: &Url, doc: &ParsedDocument, name: &str) -> Option<Location> {
+        let document_path = uri.to_file_path().ok()?;
+        let (path, original) = doc.ast.imports().iter().find_map(|import| {
+            import
+                .names
+                .iter()
+                .zip(&import.aliases)
+                .find(|(imported, alias)| alias.as_ref().unwrap_or(imported).name == name)
+                .map(|(imported, _)| (&import.path, imported))
+        })?;
+        let module = modules::resolve_import_in(
+            document_path.parent()?,
+            &self.workspace_index.roots(),
+            path,
+        );
+
+        let summary = self.module_summary(&module)?;
+        let symbol = summary.symbol(&original.name)?;
+        Some(Location {
+            uri: Url::from_file_path(&module).ok()?,
+            range: symbol.selection_range,
+        })
+    }
+
+    /// Organize the imports of a document and ask the client to apply the edit
+    async fn organize_imports(&self, uri: Url) {
+        let remove_unused = self.settings().organize_imports.remove_unused;
+        let edit = match self.documents.get(&document_key(&uri)) {
+            Some(doc) if !doc.errors.is_empty() => {
+                drop(doc);
+                self.client
+                    .show_message(MessageType::WARNING, "文件存在语法错误，无法整理导入")
+                    .await;
+                return;
+            }
+            Some(doc) => code_actions::organize_imports(&doc, remove_unused),
+            None => None,
+        };
+
+        if let Some(edit) = edit {
+            let mut changes = HashMap::new();
+            changes.insert(uri, vec![edit]);
+            let _ = self
+                .client
+                .apply_edit(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                })
+                .await;
+        }
+    }
+
+    /// `aether.explainDiagnostic` with argument `[code]`: open the code's page
+    /// when `lint.explainWithDocs` is on and the client can, otherwise show the
+    /// explanation in a message. Neither is awaited, so the command returns at once
+    fn explain_diagnostic(&self, code: &'static DiagnosticCode) {
+        let client = self.client.clone();
+        let docs = Url::parse(&code.docs_url())
+            .ok()
+            .filter(|_| self.settings().lint.explain_with_docs && self.client_caps().show_document);
+        let text = code_actions::explanation_text(code, self.locale());
+        tokio::spawn(async move {
+            let shown = match docs {
+                Some(uri) => client
+                    .show_document(ShowDocumentParams {
+                        uri,
+                        external: Some(true),
+                        take_focus: Some(true),
+                        selection: None,
+                    })
+                    .await
+                    .map(|_| ()),
+                None => client
+                    .show_message_request(MessageType::INFO, text, None)
+                    .await
+                    .map(|_| ()),
+            };
+            if let Err(err) = shown {
+                logging::warn(&format!("Could not explain {}: {}", code.code, err));
+            }
+        });
+    }
+
+    /// `aether.renameDictKey` with arguments `[uri, position, newKey]`: apply the
+    /// rename, and tell the user about the accesses it left alone
+    async fn rename_dict_key(&self, arguments: Vec<serde_json::Value>) -> Result<()> {
+        let (uri, position, new_key): (Url, Position, String) =
+            serde_json::from_value(serde_json::Value::Array(arguments)).map_err(|err| {
+                tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "expected [uri, position, newKey]: {}",
+                    err
+                ))
+            })?;
+        let locale = self.locale();
+        let rename = self
+            .document_at(&uri, position)
+            .and_then(|doc| dict_key_rename::rename_dict_key(&doc, &uri, position, &new_key))
+            .map_err(|error| error.into_jsonrpc(locale))?;
+
+        if !rename.skipped.is_empty() {
+            let lines = rename
+                .skipped
+                .iter()
+                .map(|span| (span.start.line + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = match locale {
+                Locale::Zh => format!(
+                    "有 {} 处键无法确定是否相同，未修改（第 {} 行）",
+                    rename.skipped.len(),
+                    lines
+                ),
+                Locale::En => format!(
+                    "Left {} key(s) unchanged that may or may not be the same one (lines {})",
+                    rename.skipped.len(),
+                    lines
+                ),
+            };
+            self.client.show_message(MessageType::INFO, message).await;
+        }
+        if !rename.edits.is_empty() {
+            let mut changes = HashMap::new();
+            changes.insert(uri, rename.edits);
+            let _ = self
+                .client
+                .apply_edit(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                })
+                .await;
+        }
+        Ok(())
+    }
+
+    fn next_result_id(&self) -> String {
+        self.next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string()
+    }
+
+    /// Run the diagnostic engine on a parsed document and remember the result
+    fn compute_diagnostics(&self, uri: &Url, parsed: &ParsedDocument) -> CachedDiagnostics {
+        let fast = self.fast_analysis(uri)(parsed);
+        self.cache_diagnostics(uri, parsed, fast)
+    }
+
+    /// The per-document lints of `uri` as a job owning everything it needs, so
+    /// it can run on a blocking thread
+    fn fast_analysis(
+        &self,
+        uri: &Url,
+    ) -> impl FnOnce(&ParsedDocument) -> Vec<Diagnostic> + Send + 'static {
+        let settings = self.settings();
+        let locale = self.locale();
+        let uri = uri.clone();
+        let roots = self.workspace_index.roots();
+        #[cfg(test)]
+        let injected = crate::diagnostics::INJECTED_RULE.get();
+        move |parsed| {
+            // Tests install rules per thread; carry the caller's over to the worker
+            #[cfg(test)]
+            crate::diagnostics::INJECTED_RULE.set(injected);
+            let document_path = uri.to_file_path().ok();
+            let options = AnalysisOptions {
+                locale,
+                report_io: settings.lint.io,
+                report_uncaught_throw: settings.lint.uncaught_throw,
+                base_dir: document_path.as_deref().and_then(Path::parent),
+                roots: &roots,
+                uri: Some(&uri),
+                complexity: settings.lint.complexity,
+                indentation: settings.lint.indentation,
+                heuristics: settings.lint.heuristics,
+                shadowing: settings.lint.shadowing,
+            };
+            DiagnosticEngine::analyze(parsed, &options)
+        }
+    }
+
+    /// Remember `fast` with the cross-file tier, which is carried over from the
+    /// previous run unless configured to run on change
+    fn cache_diagnostics(
+        &self,
+        uri: &Url,
+        parsed: &ParsedDocument,
+        fast: Vec<Diagnostic>,
+    ) -> CachedDiagnostics {
+        let entry = self.diagnostics_entry(uri, parsed, fast);
+        self.diagnostics.insert(document_key(uri), entry.clone());
+        entry
+    }
+
+    /// `fast` with the cross-file tier, as [`Self::cache_diagnostics`] would remember them
+    fn diagnostics_entry(
+        &self,
+        uri: &Url,
+        parsed: &ParsedDocument,
+        fast: Vec<Diagnostic>,
+    ) -> CachedDiagnostics {
+        let slow = if self.settings().analysis.slow_checks_on_change {
+            self.slow_diagnostics(uri, parsed)
+        } else {
+            self.diagnostics
+                .get(&document_key(uri))
+                .map(|previous| previous.slow.clone())
+                .unwrap_or_default()
+        };
+        CachedDiagnostics {
+            content_hash: content_hash(&parsed.text),
+            result_id: self.next_result_id(),
+            fast,
+            slow,
+        }
+    }
+
+    /// Both tiers of diagnostics as sent to the client, capped at `maxProblems`
+    fn reported(&self, entry: &CachedDiagnostics) -> Vec<Diagnostic> {
+        let diagnostics = DiagnosticEngine::limit_problems(
+            entry.all(),
+            self.settings().max_problems(),
+            self.locale(),
+        );
+        self.client_caps().adapt_diagnostics(diagnostics)
+    }
+
+    /// Parse `text` as the document at `uri`, the way the settings ask for
+    fn parse(&self, uri: &Url, text: &str) -> ParsedDocument {
+        self.parser(uri)(text)
+    }
+
+    /// The parse [`Self::parse`] runs, for a blocking thread: REPL buffers are
+    /// split into cells ([`cells`])
+    fn parser(&self, uri: &Url) -> impl Fn(&str) -> ParsedDocument + Send + 'static {
+        let settings = self.settings();
+        let lenient_keywords = settings.lenient_keywords;
+        let split = cells::applies(uri, settings.analysis.cells);
+        move |text| {
+            let parse = |text: &str| Parser::new(text).lenient_keywords(lenient_keywords).parse();
+            if split {
+                cells::parse(text, parse)
+            } else {
+                parse(text)
+            }
+        }
+    }
+
+    /// The cached document at `uri`, reading and parsing a `file://` document from disk
+    /// if the editor hasn't opened it (yet). `didOpen` later replaces the disk copy.
+    fn document(&self, uri: &Url) -> Option<Ref<'_, String, ParsedDocument>> {
+        let key = document_key(uri);
+        if let Some(doc) = self.documents.get(&key) {
+            return Some(doc);
+        }
+        if uri.scheme() != "file" {
+            return None;
+        }
+        let text = std::fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+        logging::debug(&format!("Loaded unopened document {} from disk", uri));
+        self.disk_loaded.insert(key.clone());
+        self.client_uris
+            .entry(key.clone())
+            .or_insert_with(|| uri.clone());
+        self.documents
+            .entry(key.clone())
+            .or_insert_with(|| self.parse(uri, &text));
+        self.documents.get(&key)
+    }
+
+    /// The summary of a module open in the editor. Doesn't wait on a locked shard,
+    /// so it's safe while holding another document.
+    fn open_module_summary(&self, module: &Path) -> Option<ModuleSummary> {
+        let uri = Url::from_file_path(module).ok()?;
+        let doc = self.documents.try_get(&document_key(&uri)).try_unwrap()?;
+        Some(workspace::summarize(
+            module,
+            &doc,
+            &self.workspace_index.roots(),
+        ))
+    }
+
+    /// The summary of a module, preferring the editor's copy over the index
+    fn module_summary(&self, module: &Path) -> Option<ModuleSummary> {
+        self.open_module_summary(module)
+            .or_else(|| self.workspace_index.summary_or_load(module))
+    }
+
+    /// The module imported whole as `namespace` by the document at `uri`, with its import path
+    fn namespace_module(
+        &self,
+        uri: &Url,
+        doc: &ParsedDocument,
+        namespace: &str,
+    ) -> Option<(String, ModuleSummary)> {
+        let path = match doc.ast.namespace_import(namespace) {
+            Some(import) => import.path.clone(),
+            None => namespace_import_path(&doc.text, namespace)?,
+        };
+        let document_path = uri.to_file_path().ok()?;
+        let module = modules::resolve_import_in(
+            document_path.parent()?,
+            &self.workspace_index.roots(),
+            &path,
+        );
+        let summary = self.module_summary(&module)?;
+        Some((path, summary))
+    }
+
+    /// The cross-file checks; only documents on disk have modules to resolve against
+    fn slow_diagnostics(&self, uri: &Url, parsed: &ParsedDocument) -> Vec<Diagnostic> {
+        let Ok(path) = uri.to_file_path() else {
+            return Vec::new();
+        };
+        let options = CrossFileOptions {
+            locale: self.locale(),
+            implicit_exports: self.settings().analysis.implicit_exports,
+            open_module: &|module| self.open_module_summary(module),
+            roots: &self.workspace_index.roots(),
+        };
+        let mut diagnostics = cross_file::analyze(parsed, &path, &self.workspace_index, &options);
+        suppression::retain_unsuppressed(parsed.ast.comments(), &mut diagnostics);
+        diagnostics
+    }
+
+    /// The cached diagnostics of a document if its text hasn't changed since, otherwise fresh ones
+    fn diagnostics_for(&self, uri: &Url, parsed: &ParsedDocument) -> CachedDiagnostics {
+        if let Some(cached) = self.diagnostics.get(&document_key(uri))
+            && cached.content_hash == content_hash(&parsed.text)
+        {
+            return cached.clone();
+        }
+        self.compute_diagnostics(uri, parsed)
+    }
+
+    /// The diagnostics of a document the editor hasn't opened, read and analyzed
+    /// on a blocking thread. Left uncached, since no `didClose` would evict them.
+    async fn unopened_diagnostics(&self, uri: &Url) -> Option<CachedDiagnostics> {
+        let path = uri.to_file_path().ok()?;
+        let analyze = self.fast_analysis(uri);
+        let parse = self.parser(uri);
+        let job = tokio::task::spawn_blocking(move || {
+            let text = std::fs::read_to_string(path).ok()?;
+            let parsed = parse(&text);
+            let fast = analyze(&parsed);
+            Some((parsed, fast))
+        });
+        let (parsed, fast) = match job.await {
+            Ok(analyzed) => analyzed?,
+            // Let `guarded` report a panic in the worker as its own
+            Err(error) => std::panic::resume_unwind(error.try_into_panic().ok()?),
+        };
+        Some(self.diagnostics_entry(uri, &parsed, fast))
+    }
+
+    /// Run the cross-file checks for an open document and republish its diagnostics
+    async fn run_slow_checks(&self, uri: Url) {
+        let Some(doc) = self
+            .documents
+            .get(&document_key(&uri))
+            .map(|doc| doc.clone())
+        else {
+            return;
+        };
+        let checked = self.guarded("cross-file checks", &uri, None, async {
+            Some(self.slow_diagnostics(&uri, &doc))
+        });
+        let Some(slow) = checked.await else {
+            return;
+        };
+        let all = match self.diagnostics.get_mut(&document_key(&uri)) {
+            Some(mut entry) => {
+                entry.slow = slow;
+                entry.result_id = self.next_result_id();
+                self.reported(&entry)
+            }
+            None => return,
+        };
+        self.trace(format!("Ran cross-file checks for {}", uri), String::new)
             .await;
+        self.publish(uri, all).await;
+    }
 
-        let mut parser = Parser::new(&text);
-        let parsed = parser.parse();
+    /// Push diagnostics, or ask a pulling client to fetch them again
+    async fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) {
+        if self.client_caps().pull_diagnostics {
+            self.refresh_pulled_diagnostics().await;
+        } else {
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
 
-        // 生成诊断信息
-        let diagnostics = DiagnosticEngine::analyze(&parsed, &text);
+    /// The URI the client uses for the document under `key`
+    fn client_uri(&self, key: &str) -> Option<Url> {
+        match self.client_uris.get(key) {
+            Some(uri) => Some(uri.clone()),
+            None => Url::parse(key).ok(),
+        }
+    }
+
+    /// Parse and diagnose every open document again; copies read from disk
+    /// for a request are left alone
+    async fn recheck_open_documents(&self) {
+        let open: Vec<(Url, String)> = self
+            .documents
+            .iter()
+            .filter(|doc| !self.disk_loaded.contains(doc.key()))
+            .filter_map(|doc| Some((self.client_uri(doc.key())?, doc.text.clone())))
+            .collect();
+        for (uri, text) in open {
+            self.parse_and_diagnose(uri, None, text).await;
+        }
+        self.refresh_pulled_diagnostics().await;
+    }
+
+    /// Analyze `text` as the latest version of `uri`. At most one job per
+    /// available core parses and lints at a time, on a blocking thread; a job
+    /// that a newer one for the same document overtook, while waiting or while
+    /// running, caches and publishes nothing.
+    async fn parse_and_diagnose(&self, uri: Url, version: Option<i32>, text: String) {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.analysis_generation
+            .insert(document_key(&uri), generation);
+        let Ok(_slot) = self.analysis_slots.acquire().await else {
+            return;
+        };
+        if self.superseded(&uri, generation) {
+            return;
+        }
+        if self.reuse_analysis(&uri, generation, version, &text).await {
+            return;
+        }
+
+        // 记录日志
+        self.trace(format!("Parsing document: {}", uri), String::new)
+            .await;
+
+        // 解析并生成诊断信息; after a panic the previous version stays cached
+        let analyze = self.fast_analysis(&uri);
+        let parse = self.parser(&uri);
+        let job = tokio::task::spawn_blocking({
+            let text = text.clone();
+            move || {
+                let started = Instant::now();
+                let parsed = parse(&text);
+                let parsing = started.elapsed();
+                let fast = analyze(&parsed);
+                (parsed, fast, parsing, started.elapsed() - parsing)
+            }
+        });
+        let analyzed = match job.await {
+            Ok((parsed, fast, parsing, linting)) if !self.superseded(&uri, generation) => {
+                self.record_timing("parse", parsing).await;
+                self.record_timing("diagnostics", linting).await;
+                crash::catch_unwind(async {
+                    let entry = self.diagnostics_entry(&uri, &parsed, fast);
+                    let diagnostics = self.reported(&entry);
+                    (parsed, entry, diagnostics)
+                })
+                .await
+            }
+            Ok(_) => return,
+            // Only cancelled when the runtime shuts down
+            Err(error) => match error.try_into_panic() {
+                Ok(payload) => Err(payload),
+                Err(_) => return,
+            },
+        };
+        let (parsed, entry, diagnostics) = match analyzed {
+            Ok(analyzed) => analyzed,
+            Err(payload) => {
+                self.report_panic(
+                    "analysis",
+                    &uri,
+                    Some(&text),
+                    crash::panic_message(&*payload),
+                )
+                .await;
+                return;
+            }
+        };
 
         // 记录诊断数量
-        self.client
-            .log_message(
-                MessageType::INFO,
-                format!("Found {} diagnostics for {}", diagnostics.len(), uri),
+        self.trace(
+            format!("Found {} diagnostics for {}", diagnostics.len(), uri),
+            || {
+                diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.message.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+        )
+        .await;
+
+        // 缓存解析结果; read-only features fall back to the last good parse while this one has no AST
+        let mut parsed = parsed;
+        if let Some(previous) = self.documents.get(&document_key(&uri)) {
+            parsed.keep_last_good(&previous);
+        }
+        // Half-typed code says nothing about the exports, so only clean parses count
+        let names = parsed
+            .errors
+            .is_empty()
+            .then(|| provided_names(&parsed, self.settings().analysis.implicit_exports));
+        let mut exports_changed = false;
+        let current = self.cache_if_current(&uri, generation, || {
+            if let Some(names) = names {
+                exports_changed = self
+                    .provided
+                    .insert(document_key(&uri), names.clone())
+                    .is_some_and(|previous| previous != names);
+            }
+            self.diagnostics.insert(document_key(&uri), entry);
+            self.disk_loaded.remove(&document_key(&uri));
+            self.documents.insert(document_key(&uri), parsed);
+            self.client_uris.insert(document_key(&uri), uri.clone());
+        });
+        if !current {
+            return;
+        }
+
+        // 发送诊断信息到客户端; pulling clients ask for them instead
+        if !self.client_caps().pull_diagnostics {
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, version)
+                .await;
+        }
+        if exports_changed {
+            self.recheck_dependents(&uri).await;
+        }
+    }
+
+    /// Run the cross-file checks again for the open documents importing `uri`,
+    /// directly or through other modules, which just changed its exports
+    async fn recheck_dependents(&self, uri: &Url) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let mut dependents: Vec<Url> = self
+            .dependents_of(&HashSet::from([path]))
+            .into_iter()
+            .map(|(dependent, _)| dependent)
+            .collect();
+        if dependents.is_empty() {
+            return;
+        }
+        dependents.sort();
+        if dependents.len() > MAX_DEPENDENTS_RECHECKED {
+            self.trace(
+                format!(
+                    "Exports of {} changed; checking {} of its {} open dependents",
+                    uri,
+                    MAX_DEPENDENTS_RECHECKED,
+                    dependents.len()
+                ),
+                String::new,
             )
             .await;
+            dependents.truncate(MAX_DEPENDENTS_RECHECKED);
+        }
+        for dependent in dependents {
+            self.run_slow_checks(dependent).await;
+        }
+    }
+
+    /// Whether a newer analysis of `uri` was requested after the one numbered
+    /// `generation`, or the document was closed since
+    fn superseded(&self, uri: &Url, generation: u64) -> bool {
+        self.analysis_generation
+            .get(&document_key(uri))
+            .is_none_or(|latest| *latest != generation)
+    }
+
+    /// Run `cache` unless [`Self::superseded`], holding the generation's entry
+    /// meanwhile so a newer analysis can't start and finish between the check
+    /// and the inserts only to be overwritten. Whether it ran.
+    fn cache_if_current(&self, uri: &Url, generation: u64, cache: impl FnOnce()) -> bool {
+        let Some(latest) = self.analysis_generation.get_mut(&document_key(uri)) else {
+            return false;
+        };
+        if *latest != generation {
+            return false;
+        }
+        cache();
+        true
+    }
 
-        // 缓存解析结果
-        self.documents.insert(uri.to_string(), parsed);
+    /// Keep the analysis of the cached version when `text` differs from it only in
+    /// comments and line breaks between lines, moving its diagnostics to the
+    /// lines they are on now. Republishes only if a diagnostic moved. Edits to
+    /// comments the analysis reads, suppression directives, `//!` header
+    /// directives and `@deprecated` tags, are analyzed as usual.
+    async fn reuse_analysis(
+        &self,
+        uri: &Url,
+        generation: u64,
+        version: Option<i32>,
+        text: &str,
+    ) -> bool {
+        let Some(previous) = self
+            .documents
+            .get(&document_key(uri))
+            .map(|doc| doc.clone())
+        else {
+            return false;
+        };
+        // The same text is analyzed again on purpose, e.g. after a settings change.
+        // In a buffer of cells a changed comment may be a new marker.
+        if previous.text == text || cells::applies(uri, self.settings().analysis.cells) {
+            return false;
+        }
+        let Some(mut entry) = self
+            .diagnostics
+            .get(&document_key(uri))
+            .map(|entry| entry.clone())
+            .filter(|entry| entry.content_hash == content_hash(&previous.text))
+        else {
+            return false;
+        };
+        let Some(shift) = token_diff::line_shift(&previous.text, text) else {
+            return false;
+        };
 
-        // 发送诊断信息到客户端
-        self.client
-            .publish_diagnostics(uri, diagnostics, None)
+        let moved = !shift.is_identity();
+        let parsed = if moved {
+            // Spans throughout the AST moved with the lines; parsing again is cheaper than
+            // shifting them. A panicking parse is left to the full analysis to report.
+            let parse = self.parser(uri);
+            let text = text.to_string();
+            let Ok(mut parsed) = tokio::task::spawn_blocking(move || parse(&text)).await else {
+                return false;
+            };
+            parsed.keep_last_good(&previous);
+            entry.fast = entry
+                .fast
+                .into_iter()
+                .map(|diagnostic| shift.diagnostic(uri, diagnostic))
+                .collect();
+            entry.slow = entry
+                .slow
+                .into_iter()
+                .map(|diagnostic| shift.diagnostic(uri, diagnostic))
+                .collect();
+            entry.result_id = self.next_result_id();
+            parsed
+        } else {
+            previous.with_comments_from(text)
+        };
+        let indentation = self.settings().lint.indentation.enabled;
+        if !comments_inert(&shift, &previous, &parsed, indentation) {
+            return false;
+        }
+        entry.content_hash = content_hash(text);
+        let current = self.cache_if_current(uri, generation, || {
+            self.diagnostics.insert(document_key(uri), entry.clone());
+            self.documents.insert(document_key(uri), parsed);
+            self.client_uris.insert(document_key(uri), uri.clone());
+        });
+        if !current {
+            // Overtaken: the newer analysis caches and publishes instead
+            return true;
+        }
+
+        self.trace(
+            format!("Only comments or line breaks changed in {}", uri),
+            String::new,
+        )
+        .await;
+        if moved && !self.client_caps().pull_diagnostics {
+            self.client
+                .publish_diagnostics(uri.clone(), self.reported(&entry), version)
+                .await;
+        }
+        true
+    }
+
+    /// The document at `uri`, if `position` is inside it
+    fn document_at(
+        &self,
+        uri: &Url,
+        position: Position,
+    ) -> std::result::Result<Ref<'_, String, ParsedDocument>, RequestError> {
+        let doc = self
+            .document(uri)
+            .ok_or_else(|| RequestError::DocumentNotOpen(uri.clone()))?;
+        let on_a_line = doc.line_index.line_text(&doc.text, position.line).is_some();
+        if !on_a_line || position.character > doc.line_index.line_len(&doc.text, position.line) {
+            return Err(RequestError::PositionOutOfRange(position));
+        }
+        Ok(doc)
+    }
+
+    /// Note why `what` had nothing to answer
+    async fn trace_miss(&self, what: &str, error: &RequestError) {
+        self.trace(format!("No {} result: {}", what, error), String::new)
             .await;
     }
+
+    /// The edit renaming the symbol at `position` to `new_name`
+    fn rename_edit(
+        &self,
+        uri: &Url,
+        position: Position,
+        new_name: &str,
+    ) -> std::result::Result<WorkspaceEdit, RequestError> {
+        let doc = self.document_at(uri, position)?;
+        let (name, _) = rename_target(&doc, uri, position)?;
+        if !is_valid_aether_name(new_name) {
+            return Err(RequestError::InvalidName(new_name.to_string()));
+        }
+
+        // An import binds a local name but the imported one belongs to the
+        // other module: rename the alias, or add one where there is none
+        let unaliased: Vec<Span> = doc
+            .ast
+            .imports()
+            .iter()
+            .flat_map(|import| import.names.iter().zip(&import.aliases))
+            .filter(|(imported, alias)| alias.is_none() && imported.name == name)
+            .map(|(imported, _)| imported.span)
+            .collect();
+
+        let edits = doc.occurrences[name]
+            .iter()
+            .map(|occurrence| TextEdit {
+                range: occurrence.span,
+                new_text: if occurrence.role == Role::Import && unaliased.contains(&occurrence.span)
+                {
+                    format!("{} As {}", name, new_name)
+                } else {
+                    new_name.to_string()
+                },
+            })
+            .collect();
+        Ok(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        })
+    }
+
+    /// Quick fixes importing an undefined name from each workspace module that
+    /// exports it, for the undefined-identifier diagnostics among `diagnostics`
+    fn import_actions(
+        &self,
+        doc: &ParsedDocument,
+        uri: &Url,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeAction> {
+        let document_path = uri.to_file_path().ok();
+        let implicit_exports = self.settings().analysis.implicit_exports;
+        let roots = self.workspace_index.roots();
+        diagnostics
+            .iter()
+            .flat_map(|diagnostic| {
+                let Some(name) = code_actions::undefined_name(diagnostic) else {
+                    return Vec::new();
+                };
+                let mut modules = self.workspace_index.exporters(name, implicit_exports);
+                modules.retain(|module| Some(module) != document_path.as_ref());
+                code_actions::import_actions(doc, uri, diagnostic, &modules, &roots)
+            })
+            .collect()
+    }
+
+    /// Run a request handler, timing it, and answering `fallback` if it panics
+    async fn guarded<T>(
+        &self,
+        what: &'static str,
+        uri: &Url,
+        fallback: T,
+        handler: impl Future<Output = T>,
+    ) -> T {
+        let started = Instant::now();
+        let answer = crash::catch_unwind(handler).await;
+        self.record_timing(what, started.elapsed()).await;
+        match answer {
+            Ok(answer) => answer,
+            Err(payload) => {
+                let text = self
+                    .documents
+                    .get(&document_key(uri))
+                    .map(|doc| doc.text.clone());
+                self.report_panic(what, uri, text.as_deref(), crash::panic_message(&*payload))
+                    .await;
+                fallback
+            }
+        }
+    }
+
+    /// Note how long `what` took, and log it if it took longer than the
+    /// `perf.slowThresholdMs` setting allows
+    async fn record_timing(&self, what: &'static str, elapsed: Duration) {
+        self.perf.record(what, elapsed);
+        let threshold = self
+            .settings
+            .read()
+            .map(|settings| settings.perf.slow_threshold_ms)
+            .unwrap_or_default();
+        if threshold == 0 || elapsed < Duration::from_millis(threshold) {
+            return;
+        }
+        let Some(usual) = self.perf.handler(what) else {
+            return;
+        };
+        let message = format!(
+            "{} took {:.0} ms (p50 {:.1} ms, p95 {:.1} ms over {} runs)",
+            what,
+            elapsed.as_secs_f64() * 1000.0,
+            usual.p50_ms,
+            usual.p95_ms,
+            usual.count
+        );
+        logging::warn(&message);
+        self.client.log_message(MessageType::WARNING, message).await;
+    }
+
+    /// Log a panic caught in a handler and save the document if crash dumps are enabled
+    async fn report_panic(&self, what: &str, uri: &Url, text: Option<&str>, panic: String) {
+        let mut message = format!("Internal error in {} for {}: {}", what, uri, panic);
+        match text.and_then(|text| crash::dump(uri.as_str(), text)) {
+            Some(Ok(path)) => message.push_str(&format!(" (document saved to {})", path.display())),
+            Some(Err(err)) => logging::warn(&format!("Could not write crash dump: {}", err)),
+            None => {}
+        }
+        logging::error(&message);
+        self.client.log_message(MessageType::ERROR, message).await;
+    }
+
+    /// Tell a pulling client that diagnostics changed without an edit, e.g. after a dependency changed
+    async fn refresh_pulled_diagnostics(&self) {
+        if self.client_caps().pull_diagnostics && self.client_caps().diagnostic_refresh {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for AetherLspBackend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        if let Some(options) = &params.initialization_options {
+            self.update_settings(options).await;
+        }
+
+        if let Some(tag) = &params.locale
+            && let Ok(mut locale) = self.client_locale.write()
+        {
+            *locale = Locale::from_tag(tag);
+        }
+        if let Some(value) = params.trace
+            && let Ok(mut trace) = self.trace.write()
+        {
+            *trace = value;
+        }
+
+        let roots: Vec<PathBuf> = match &params.workspace_folders {
+            Some(folders) if !folders.is_empty() => folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect(),
+            _ => params
+                .root_uri
+                .iter()
+                .filter_map(|uri| uri.to_file_path().ok())
+                .collect(),
+        };
+        for root in roots {
+            self.workspace_index.add_root(root);
+        }
+
+        if let Ok(mut caps) = self.client_caps.write() {
+            *caps = ClientCaps::from_capabilities(&params.capabilities);
+        }
+
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "Aether LSP Server".to_string(),
-                version: Some("0.1.0".to_string()),
+                version: Some(crate::server::version()),
             }),
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::FULL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(true),
+                        })),
+                        ..Default::default()
+                    },
                 )),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
-                    trigger_characters: Some(vec![".".to_string(), " ".to_string()]),
+                    trigger_characters: Some(vec![
+                        ".".to_string(),
+                        "\"".to_string(),
+                        "[".to_string(),
+                    ]),
                     ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
                 definition_provider: Some(OneOf::Left(true)),
                 document_symbol_provider: Some(OneOf::Left(true)),
-                rename_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                references_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("aether".to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: false,
+                        ..Default::default()
+                    },
+                )),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                rename_provider: Some(if self.client_caps().prepare_rename {
+                    OneOf::Right(RenameOptions {
+                        prepare_provider: Some(true),
+                        work_done_progress_options: Default::default(),
+                    })
+                } else {
+                    OneOf::Left(true)
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        code_actions::ORGANIZE_IMPORTS_COMMAND.to_string(),
+                        code_actions::EXPLAIN_DIAGNOSTIC_COMMAND.to_string(),
+                        dict_key_rename::RENAME_DICT_KEY_COMMAND.to_string(),
+                    ],
+                    ..Default::default()
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
                 ..Default::default()
             },
         })
     }
 
     async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "Aether LSP Server initialized!")
-            .await;
+        self.reload_extra_builtins().await;
+        self.register_file_watcher().await;
+        for root in self.workspace_index.roots() {
+            self.spawn_workspace_indexing(root);
+        }
+        logging::info("Aether LSP Server initialized");
     }
 
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
 
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let exclude = self.settings().index.exclude;
+        self.update_settings(&params.settings).await;
+        self.reload_extra_builtins().await;
+        if self.settings().index.exclude != exclude {
+            for root in self.workspace_index.roots() {
+                self.spawn_workspace_indexing(root);
+            }
+        }
+
+        // Builtins and lint options may have changed
+        self.recheck_open_documents().await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        for folder in params.event.removed {
+            if let Ok(root) = folder.uri.to_file_path() {
+                self.workspace_index.remove_root(&root);
+            }
+        }
+        for folder in params.event.added {
+            if let Ok(root) = folder.uri.to_file_path()
+                && self.workspace_index.add_root(root.clone())
+            {
+                self.spawn_workspace_indexing(root);
+            }
+        }
+
+        // Imports may now resolve under a different root, or not at all
+        self.recheck_open_documents().await;
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.parse_and_diagnose(params.text_document.uri, params.text_document.text)
-            .await;
+        self.parse_and_diagnose(
+            params.text_document.uri,
+            Some(params.text_document.version),
+            params.text_document.text,
+        )
+        .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         if let Some(change) = params.content_changes.into_iter().next() {
-            self.parse_and_diagnose(params.text_document.uri, change.text)
-                .await;
+            self.parse_and_diagnose(
+                params.text_document.uri,
+                Some(params.text_document.version),
+                change.text,
+            )
+            .await;
         }
     }
 
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if let Some(text) = params.text
+            && self
+                .documents
+                .get(&document_key(&uri))
+                .is_none_or(|doc| doc.text != text)
+        {
+            self.parse_and_diagnose(uri.clone(), None, text).await;
+        }
+        // Dependents see the saved version from now on
+        if let Ok(path) = uri.to_file_path() {
+            self.modules.invalidate(&path);
+            self.workspace_index.index_file(&path);
+        }
+        self.run_slow_checks(uri).await;
+    }
+
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.documents.remove(&params.text_document.uri.to_string());
+        self.disk_loaded
+            .remove(&document_key(&params.text_document.uri));
+        self.documents
+            .remove(&document_key(&params.text_document.uri));
+        self.client_uris
+            .remove(&document_key(&params.text_document.uri));
+        self.diagnostics
+            .remove(&document_key(&params.text_document.uri));
+        self.provided
+            .remove(&document_key(&params.text_document.uri));
+        // An analysis still running for it has nothing to publish
+        self.analysis_generation
+            .remove(&document_key(&params.text_document.uri));
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded(
+            "diagnostics",
+            &guarded_uri,
+            Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport::default()),
+            )),
+            async move {
+                let uri = params.text_document.uri;
+                let open = self
+                    .documents
+                    .get(&document_key(&uri))
+                    .map(|doc| doc.clone());
+                let entry = match open {
+                    Some(doc) => self.diagnostics_for(&uri, &doc),
+                    // Not open: analyze what is on disk, if anything
+                    None => match self.unopened_diagnostics(&uri).await {
+                        Some(entry) => entry,
+                        None => {
+                            return Ok(DocumentDiagnosticReportResult::Report(
+                                DocumentDiagnosticReport::Full(
+                                    RelatedFullDocumentDiagnosticReport::default(),
+                                ),
+                            ));
+                        }
+                    },
+                };
+
+                let report = if params.previous_result_id.as_deref()
+                    == Some(entry.result_id.as_str())
+                {
+                    DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                        related_documents: None,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id: entry.result_id,
+                        },
+                    })
+                } else {
+                    DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                        related_documents: None,
+                        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                            items: self.reported(&entry),
+                            result_id: Some(entry.result_id),
+                        },
+                    })
+                };
+                Ok(DocumentDiagnosticReportResult::Report(report))
+            },
+        )
+        .await
     }
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let uri = params.text_document_position.text_document.uri.to_string();
+        let guarded_uri = params.text_document_position.text_document.uri.clone();
+        self.guarded("completion", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position.text_document.uri;
+            let position = params.text_document_position.position;
+            // `None` when invoked manually or re-requested for an incomplete list
+            let trigger = params
+                .context
+                .filter(|context| context.trigger_kind == CompletionTriggerKind::TRIGGER_CHARACTER)
+                .and_then(|context| context.trigger_character);
 
-        let completions = if let Some(doc) = self.documents.get(&uri) {
-            get_completions(&doc, params.text_document_position.position)
-        } else {
-            get_completions(
-                &ParsedDocument::default(),
-                params.text_document_position.position,
-            )
-        };
+            // Inside a string or comment, only the key or module path that string is
+            // being typed as gets completions
+            if let Some(doc) = self.document(&uri)
+                && let Some(region) = doc.mask.region_at(position)
+            {
+                let opened_at = |start: u32| {
+                    region.start == Position::new(position.line, start.saturating_sub(1))
+                };
+                let items = match import_context(&doc, position) {
+                    Some(context @ ImportContext::Path { start, .. }) if opened_at(start) => {
+                        let document_path = uri.to_file_path().ok();
+                        Some(import_completions(
+                            &context,
+                            position,
+                            document_path.as_deref(),
+                            &self.workspace_index.roots(),
+                            &self.workspace_index,
+                            &self.modules,
+                        ))
+                    }
+                    _ => dict_key_context(&doc, position)
+                        .filter(|context| context.quoted && opened_at(context.start))
+                        .and_then(|_| dict_key_completions(&doc.usable(), position)),
+                };
+                return Ok(items.map(CompletionResponse::Array));
+            }
+
+            // Import statements get module-aware completions instead of the general list
+            if let Some(context) = self
+                .document(&uri)
+                .and_then(|doc| import_context(&doc, position))
+            {
+                let document_path = uri.to_file_path().ok();
+                let items = import_completions(
+                    &context,
+                    position,
+                    document_path.as_deref(),
+                    &self.workspace_index.roots(),
+                    &self.workspace_index,
+                    &self.modules,
+                );
+                return Ok(Some(CompletionResponse::Array(items)));
+            }
+
+            // `UTILS.` offers the exports of the module imported as UTILS
+            if matches!(trigger.as_deref(), None | Some("."))
+                && let Some(doc) = self.document(&uri)
+                && let Some(namespace) = member_context(&doc, position)
+            {
+                let items = self
+                    .namespace_module(&uri, &doc, &namespace)
+                    .map(|(path, summary)| member_completions(&path, &summary))
+                    .unwrap_or_default();
+                return Ok(Some(CompletionResponse::Array(items)));
+            }
 
-        Ok(Some(CompletionResponse::Array(completions)))
+            // Any other trigger character only ever opens the dict key list;
+            // typing `.` or `"` elsewhere shouldn't pop up every name in scope
+            if trigger.is_some() {
+                let items = self
+                    .document(&uri)
+                    .filter(|_| matches!(trigger.as_deref(), Some("[" | "\"")))
+                    .and_then(|doc| dict_key_completions(&doc.usable(), position));
+                return Ok(items.map(CompletionResponse::Array));
+            }
+
+            let settings = self.settings().completion;
+            let max_items = settings.max_items;
+            let locale = self.locale();
+            let completions = match self.document(&uri) {
+                Some(doc) => {
+                    let snippets =
+                        if self.client_caps().snippets && at_statement_start(&doc.text, position) {
+                            snippet_completions(&settings.snippets, locale)
+                        } else {
+                            Vec::new()
+                        };
+                    let usable = doc.usable();
+                    let visible = cells::applies(&uri, self.settings().analysis.cells)
+                        .then(|| cells::without_later_cells(&usable, position))
+                        .flatten();
+                    let mut completions = get_completions_with(
+                        visible.as_ref().unwrap_or(&usable),
+                        position,
+                        max_items,
+                        locale,
+                        snippets,
+                    );
+                    // Ask again once the text parses; the names may have moved on
+                    completions.is_incomplete |= usable.stale;
+                    completions
+                }
+                None => {
+                    let error = RequestError::DocumentNotOpen(uri.clone());
+                    self.trace(
+                        format!("Completing keywords and builtins only: {}", error),
+                        String::new,
+                    )
+                    .await;
+                    get_completions(&ParsedDocument::default(), position, max_items, locale)
+                }
+            };
+
+            Ok(Some(CompletionResponse::List(completions)))
+        })
+        .await
+        .map(|response| response.map(|response| self.client_caps().adapt_completion(response)))
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let uri = params
+        let guarded_uri = params
             .text_document_position_params
             .text_document
             .uri
-            .to_string();
-        let position = params.text_document_position_params.position;
+            .clone();
+        self.guarded("hover", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
 
-        // 记录日志
-        self.client
-            .log_message(
-                MessageType::INFO,
+            // 记录日志
+            self.trace(
                 format!(
                     "Hover requested at {}:{}",
                     position.line, position.character
                 ),
+                String::new,
             )
             .await;
 
-        if let Some(doc) = self.documents.get(&uri) {
+            let doc = match self.document_at(&uri, position) {
+                Ok(doc) => doc,
+                Err(error) => {
+                    self.trace_miss("hover", &error).await;
+                    return Ok(None);
+                }
+            };
+            let doc = doc.usable();
+            // The header's first line stands for the whole header
+            if position.line == 0
+                && let Some(hover) = header::header_hover(&doc.metadata, self.locale())
+            {
+                return Ok(Some(hover));
+            }
+
             // 先查找用户定义的符号
-            if let Some(symbol_info) = doc.symbols.find_at_position(position) {
+            if let Some((name, occurrence)) = occurrences::find_at(&doc.occurrences, position)
+                && let Some(symbol_info) = doc
+                    .symbols
+                    .find_at_position(position)
+                    .or_else(|| doc.symbols.find_by_name(name))
+            {
+                let mut value = if symbol_info.documentation.is_empty() {
+                    symbol_info.detail.clone().unwrap_or_default()
+                } else {
+                    symbol_info.documentation.clone()
+                };
+                if let Some(note) = &symbol_info.deprecated {
+                    value.insert_str(0, &deprecation_banner(note, self.locale()));
+                }
+                if generators::generator_called_at(&doc.ast, position).is_some() {
+                    value.push_str(self.locale().pick(
+                        "\n\n---\n调用结果：**生成器对象**，用 `For` 遍历它",
+                        "\n\n---\nThis call gives a **Generator object**; iterate it with `For`",
+                    ));
+                }
+                return Ok(Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(occurrence.span),
+                }));
+            }
+
+            // `UTILS.PARSE_CONFIG`: documentation from the imported module
+            if let Some(expr) = ast::find_expr_at(&doc.ast, position)
+                && let ExprKind::Member { object, member } = &expr.kind
+                && span_contains(member.span, position)
+                && let ExprKind::Identifier(namespace) = &object.kind
+                && let Some((_, summary)) = self.namespace_module(&uri, &doc, namespace)
+                && let Some(symbol) = summary.symbol(&member.name)
+            {
+                let mut value = if symbol.documentation.is_empty() {
+                    symbol.detail.clone().unwrap_or_default()
+                } else {
+                    symbol.documentation.clone()
+                };
+                if let Some(note) = &symbol.deprecated {
+                    value.insert_str(0, &deprecation_banner(note, self.locale()));
+                }
                 return Ok(Some(Hover {
                     contents: HoverContents::Markup(MarkupContent {
                         kind: MarkupKind::Markdown,
-                        value: symbol_info.documentation.clone(),
+                        value,
                     }),
-                    range: Some(symbol_info.range),
+                    range: Some(member.span),
                 }));
             }
 
+            if let Some(path) = ast::node_at_position(&doc.ast, position)
+                && let Some(hover) = array_bounds::index_hover(&path, &doc, self.locale())
+                    .or_else(|| {
+                        path.innermost_expr()
+                            .and_then(|expr| literals::literal_hover(expr, self.locale()))
+                    })
+                    .or_else(|| literals::template_hover(&path, &doc.symbols, self.locale()))
+            {
+                return Ok(Some(hover));
+            }
+
             // 查找内置函数 - 需要从文档中提取当前位置的标识符
-            if let Some(word) = extract_word_at_position(&doc.text, position) {
-                self.client
-                    .log_message(MessageType::INFO, format!("Looking for builtin: {}", word))
+            if let Some((word, range)) =
+                extract_word_at_position(&doc.line_index, &doc.text, &doc.mask, position)
+            {
+                self.trace(format!("Looking for builtin: {}", word), String::new)
                     .await;
 
-                if let Some(builtin) = crate::builtins::find_builtin(&word) {
-                    return Ok(Some(crate::builtins::builtin_to_hover(&builtin)));
+                if let Some(hover) = builtins::builtin_hover(&word, self.locale()) {
+                    return Ok(Some(hover));
+                }
+
+                if let Some(keyword) = keywords::find_keyword(&word) {
+                    let mut value = keyword.markdown(self.locale());
+                    if keyword.keyword == "For"
+                        && self.settings().lint.heuristics.dict_iteration
+                        && let Some(dict) = loops::dict_loop_at(&doc.ast, position)
+                    {
+                        value.push_str(&match self.locale() {
+                            Locale::Zh => format!(
+                                "\n\n---\n`{}` 是字典，遍历得到的是它的**键**；需要键和值时请遍历 `ITEMS({})`",
+                                dict, dict
+                            ),
+                            Locale::En => format!(
+                                "\n\n---\n`{}` is a dict, so this loop gives its **keys**; iterate `ITEMS({})` for keys and values",
+                                dict, dict
+                            ),
+                        });
+                    }
+                    return Ok(Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: Some(range),
+                    }));
                 }
             }
-        }
 
-        Ok(None)
+            Ok(None)
+        })
+        .await
+        .map(|hover| hover.map(|hover| self.client_caps().adapt_hover(hover)))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let guarded_uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("signature help", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            match self.document_at(&uri, position) {
+                Ok(doc) => Ok(signature_help::signature_help(
+                    &doc,
+                    position,
+                    self.locale(),
+                )),
+                Err(error) => {
+                    self.trace_miss("signature help", &error).await;
+                    Ok(None)
+                }
+            }
+        })
+        .await
     }
 
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
-        let uri = params
+        let guarded_uri = params
             .text_document_position_params
             .text_document
             .uri
-            .to_string();
-
-        if let Some(doc) = self.documents.get(&uri) {
+            .clone();
+        self.guarded("definition", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
             let position = params.text_document_position_params.position;
+            let doc = match self.document_at(&uri, position) {
+                Ok(doc) => doc,
+                Err(error) => {
+                    self.trace_miss("definition", &error).await;
+                    return Ok(None);
+                }
+            };
 
-            if let Some(location) = doc.symbols.find_definition(position) {
-                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            if let Some((name, _)) = occurrences::find_at(&doc.occurrences, position)
+                && let Some(definition) = occurrences::definition(&doc.occurrences, name)
+            {
+                // Follow an import into the module that defines the name
+                if definition.role == Role::Import
+                    && let Some(location) = self.imported_definition(&uri, &doc, name)
+                {
+                    return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+                }
+                return Ok(Some(GotoDefinitionResponse::Scalar(Location {
+                    uri,
+                    range: definition.span,
+                })));
             }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    #[allow(deprecated)] // `SymbolInformation::deprecated` must still be initialized
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let symbols = self
+            .workspace_index
+            .search(&params.query)
+            .into_iter()
+            .filter_map(|(path, symbol)| {
+                Some(SymbolInformation {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location {
+                        uri: Url::from_file_path(&path).ok()?,
+                        range: symbol.selection_range,
+                    },
+                    container_name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned()),
+                })
+            })
+            .collect();
+        Ok(Some(symbols))
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let roots = self.workspace_index.roots();
+        let mut changed = HashSet::new();
+        let mut reindex = HashSet::new();
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            if path
+                .file_name()
+                .is_some_and(|name| name == ignore::IGNORE_FILE)
+            {
+                if let Some(root) = path
+                    .parent()
+                    .filter(|dir| roots.iter().any(|root| root == dir))
+                {
+                    reindex.insert(root.to_path_buf());
+                }
+                continue;
+            }
+            if path
+                .extension()
+                .is_none_or(|ext| ext != modules::MODULE_EXTENSION)
+            {
+                continue;
+            }
+            self.modules.invalidate(&path);
+            // A copy read from disk for an earlier request is stale now
+            if self
+                .disk_loaded
+                .remove(&document_key(&change.uri))
+                .is_some()
+            {
+                self.documents.remove(&document_key(&change.uri));
+                self.client_uris.remove(&document_key(&change.uri));
+            }
+            if change.typ == FileChangeType::DELETED || self.workspace_index.is_excluded(&path) {
+                self.workspace_index.remove(&path);
+            } else if !self.workspace_index.index_file(&path) {
+                let message = format!("Could not read changed module {}", path.display());
+                logging::warn(&message);
+                self.client.log_message(MessageType::WARNING, message).await;
+            }
+            changed.insert(path);
+        }
+        for root in reindex {
+            self.spawn_workspace_indexing(root);
         }
 
-        Ok(None)
+        for (uri, text) in self.dependents_of(&changed) {
+            self.parse_and_diagnose(uri, None, text).await;
+        }
+        self.refresh_pulled_diagnostics().await;
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded("document links", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document.uri;
+            let (Some(doc), Ok(document_path)) = (self.document(&uri), uri.to_file_path()) else {
+                return Ok(None);
+            };
+            let Some(base_dir) = document_path.parent() else {
+                return Ok(None);
+            };
+            let roots = self.workspace_index.roots();
+            let locale = self.locale();
+
+            // Each import path that resolves, saying where it was found
+            let links = doc
+                .ast
+                .imports()
+                .iter()
+                .filter_map(|import| {
+                    let (module, base) = modules::locate_import(base_dir, &roots, &import.path);
+                    if !module.is_file() {
+                        return None;
+                    }
+                    let tooltip = match (base, locale) {
+                        (ImportBase::Document, Locale::Zh) => "相对于当前文件解析".to_string(),
+                        (ImportBase::Document, Locale::En) => {
+                            "Resolved relative to this file".to_string()
+                        }
+                        (ImportBase::Root(root), Locale::Zh) => {
+                            format!("从工作区文件夹 {} 解析", root.display())
+                        }
+                        (ImportBase::Root(root), Locale::En) => {
+                            format!("Resolved from workspace folder {}", root.display())
+                        }
+                    };
+                    Some(DocumentLink {
+                        range: import_path_range(&doc, import)?,
+                        target: Url::from_file_path(&module).ok(),
+                        tooltip: Some(tooltip),
+                        data: None,
+                    })
+                })
+                .collect();
+            Ok(Some(links))
+        })
+        .await
     }
 
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
     ) -> Result<Option<DocumentSymbolResponse>> {
-        let uri = params.text_document.uri.to_string();
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded("document symbols", &guarded_uri, Ok(None), async move {
+            if let Some(doc) = self.document(&params.text_document.uri) {
+                let doc = doc.usable();
+                // Without an AST only function names are known, listed flat
+                if let Some(structure) = folding::fallback_structure(&doc) {
+                    let functions = structure.functions.iter();
+                    if self.client_caps().hierarchical_symbols {
+                        let symbols = functions.map(FallbackFunction::document_symbol).collect();
+                        return Ok(Some(DocumentSymbolResponse::Nested(symbols)));
+                    }
+                    let uri = &params.text_document.uri;
+                    let symbols = functions
+                        .map(|function| function.symbol_information(uri))
+                        .collect();
+                    return Ok(Some(DocumentSymbolResponse::Flat(symbols)));
+                }
+                if self.client_caps().hierarchical_symbols {
+                    let mut symbols = doc.symbols.to_nested_document_symbols();
+                    if self.settings().outline.group_sets {
+                        let runs = set_runs::set_runs(&doc.ast);
+                        symbols = set_runs::group_runs(symbols, &runs, self.locale());
+                    }
+                    return Ok(Some(DocumentSymbolResponse::Nested(symbols)));
+                }
+                let symbols = doc.symbols.to_document_symbols(&params.text_document.uri);
+                return Ok(Some(DocumentSymbolResponse::Flat(symbols)));
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded("folding ranges", &guarded_uri, Ok(None), async move {
+            Ok(self
+                .document(&params.text_document.uri)
+                .map(|doc| folding::folding_ranges(&doc.usable())))
+        })
+        .await
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let guarded_uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("call hierarchy", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
+
+            if let Some(doc) = self.documents.get(&document_key(&uri)) {
+                let position = params.text_document_position_params.position;
+                let graph = CallGraph::build(&doc.ast);
+                return Ok(graph.prepare(&uri, position).map(|item| vec![item]));
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let guarded_uri = params.item.uri.clone();
+        self.guarded("call hierarchy", &guarded_uri, Ok(None), async move {
+            let uri = params.item.uri.clone();
+
+            if let Some(doc) = self.documents.get(&document_key(&uri)) {
+                let graph = CallGraph::build(&doc.ast);
+                return Ok(Some(graph.incoming_calls(&uri, &params.item)));
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let guarded_uri = params.item.uri.clone();
+        self.guarded("call hierarchy", &guarded_uri, Ok(None), async move {
+            let uri = params.item.uri.clone();
+
+            if let Some(doc) = self.documents.get(&document_key(&uri)) {
+                let graph = CallGraph::build(&doc.ast);
+                return Ok(Some(graph.outgoing_calls(&uri, &params.item)));
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded("code actions", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document.uri;
+            let only = params.context.only.as_ref();
+            let mut actions = Vec::new();
+
+            if let Some(doc) = self.documents.get(&document_key(&uri)) {
+                let remove_unused = self.settings().organize_imports.remove_unused;
+                if code_actions::kind_requested(only, &CodeActionKind::SOURCE_ORGANIZE_IMPORTS)
+                    && let Some(action) =
+                        code_actions::organize_imports_action(&doc, &uri, remove_unused)
+                {
+                    actions.push(CodeActionOrCommand::CodeAction(action));
+                }
+
+                if code_actions::kind_requested(only, &CodeActionKind::QUICKFIX) {
+                    actions.extend(
+                        self.import_actions(&doc, &uri, &params.context.diagnostics)
+                            .into_iter()
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        code_actions::keyword_casing_actions(
+                            &doc,
+                            &uri,
+                            params.range,
+                            &params.context.diagnostics,
+                        )
+                        .into_iter()
+                        .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        code_actions::indentation_actions(&uri, &params.context.diagnostics)
+                            .into_iter()
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        code_actions::stray_assign_actions(
+                            &doc,
+                            &uri,
+                            params.range,
+                            &params.context.diagnostics,
+                        )
+                        .into_iter()
+                        .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        code_actions::deprecated_call_actions(&uri, &params.context.diagnostics)
+                            .into_iter()
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        code_actions::explain_actions(&doc, &uri, &params.context.diagnostics)
+                            .into_iter()
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                }
+
+                if code_actions::kind_requested(only, &CodeActionKind::REFACTOR_REWRITE) {
+                    actions.extend(
+                        code_actions::lambda_conversion_actions(&doc, &uri, params.range.start)
+                            .into_iter()
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                    actions.extend(
+                        doc_skeleton::doc_comment_action(&doc, &uri, params.range.start)
+                            .map(CodeActionOrCommand::CodeAction),
+                    );
+                }
+
+                if code_actions::kind_requested(only, &CodeActionKind::REFACTOR_EXTRACT)
+                    && let Some(action) =
+                        code_actions::extract_function_action(&doc, &uri, params.range)
+                {
+                    actions.push(CodeActionOrCommand::CodeAction(action));
+                }
+
+                if code_actions::kind_requested(only, &CodeActionKind::REFACTOR)
+                    && self.client_caps().dict_key_prompt
+                    && let Some(action) =
+                        dict_key_rename::rename_dict_key_action(&doc, &uri, params.range.start)
+                {
+                    actions.push(CodeActionOrCommand::CodeAction(action));
+                }
+            }
 
-        if let Some(doc) = self.documents.get(&uri) {
-            let symbols = doc.symbols.to_document_symbols();
-            return Ok(Some(DocumentSymbolResponse::Flat(symbols)));
+            Ok(Some(actions))
+        })
+        .await
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command == code_actions::ORGANIZE_IMPORTS_COMMAND {
+            let uri = params
+                .arguments
+                .first()
+                .and_then(|arg| arg.as_str())
+                .and_then(|arg| Url::parse(arg).ok());
+            if let Some(uri) = uri {
+                self.organize_imports(uri).await;
+            }
+        } else if params.command == dict_key_rename::RENAME_DICT_KEY_COMMAND {
+            self.rename_dict_key(params.arguments).await?;
+        } else if params.command == code_actions::EXPLAIN_DIAGNOSTIC_COMMAND
+            && let Some(code) = params
+                .arguments
+                .first()
+                .and_then(|arg| arg.as_str())
+                .and_then(diagnostic_codes::by_code)
+        {
+            self.explain_diagnostic(code);
         }
 
         Ok(None)
     }
 
-    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
-        let uri = params.text_document_position.text_document.uri.to_string();
-
-        if let Some(doc) = self.documents.get(&uri) {
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let guarded_uri = params.text_document_position.text_document.uri.clone();
+        self.guarded("references", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position.text_document.uri;
             let position = params.text_document_position.position;
-            let new_name = params.new_name;
+            let Some(doc) = self.documents.get(&document_key(&uri)) else {
+                return Ok(None);
+            };
+            let Some((name, _)) = occurrences::find_at(&doc.occurrences, position) else {
+                return Ok(None);
+            };
+
+            let locations = doc.occurrences[name]
+                .iter()
+                .filter(|occurrence| {
+                    params.context.include_declaration || occurrence.role != Role::Definition
+                })
+                .map(|occurrence| Location {
+                    uri: uri.clone(),
+                    range: occurrence.span,
+                })
+                .collect();
+            Ok(Some(locations))
+        })
+        .await
+    }
 
-            // 验证新名称符合命名约定
-            if !is_valid_aether_name(&new_name) {
+    async fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> Result<Option<LinkedEditingRanges>> {
+        let guarded_uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("linked editing", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            let Some(doc) = self.documents.get(&document_key(&uri)) else {
                 return Ok(None);
+            };
+
+            Ok(
+                occurrences::linked_editing_ranges(&doc.ast, &doc.occurrences, position).map(
+                    |ranges| LinkedEditingRanges {
+                        ranges,
+                        word_pattern: Some(IDENTIFIER_PATTERN.to_string()),
+                    },
+                ),
+            )
+        })
+        .await
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let guarded_uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("highlights", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            let Some(doc) = self.documents.get(&document_key(&uri)) else {
+                return Ok(None);
+            };
+            let Some((name, _)) = occurrences::find_at(&doc.occurrences, position) else {
+                return Ok(None);
+            };
+
+            let highlights = doc.occurrences[name]
+                .iter()
+                .map(|occurrence| DocumentHighlight {
+                    range: occurrence.span,
+                    kind: Some(occurrence.role.highlight_kind()),
+                })
+                .collect();
+            Ok(Some(highlights))
+        })
+        .await
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let guarded_uri = params.text_document.uri.clone();
+        self.guarded("prepare rename", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document.uri;
+            let position = params.position;
+            let target = self
+                .document_at(&uri, position)
+                .and_then(|doc| rename_target(&doc, &uri, position).map(|(_, span)| span));
+            match target {
+                Ok(span) => Ok(Some(PrepareRenameResponse::Range(span))),
+                Err(error) => {
+                    self.trace_miss("prepare rename", &error).await;
+                    Err(error.into_jsonrpc(self.locale()))
+                }
             }
+        })
+        .await
+    }
 
-            if let Some(edit) = doc.symbols.rename_symbol(position, &new_name, &uri) {
-                return Ok(Some(edit));
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let guarded_uri = params.text_document_position.text_document.uri.clone();
+        self.guarded("rename", &guarded_uri, Ok(None), async move {
+            let uri = params.text_document_position.text_document.uri;
+            let position = params.text_document_position.position;
+            match self.rename_edit(&uri, position, &params.new_name) {
+                Ok(edit) => Ok(Some(edit)),
+                Err(error) => {
+                    self.trace_miss("rename", &error).await;
+                    Err(error.into_jsonrpc(self.locale()))
+                }
             }
-        }
+        })
+        .await
+    }
+}
 
-        Ok(None)
+/// The name a rename at `position` would change, and its span under the cursor
+fn rename_target<'d>(
+    doc: &'d ParsedDocument,
+    uri: &Url,
+    position: Position,
+) -> std::result::Result<(&'d str, Span), RequestError> {
+    // Partial results would rename some occurrences and miss others
+    if doc.lost_ast() {
+        return Err(RequestError::AnalysisUnavailable(uri.clone()));
+    }
+    let (name, occurrence) = occurrences::find_at(&doc.occurrences, position)
+        .ok_or(RequestError::NotARenamableSymbol(position))?;
+    // Builtins are not defined anywhere, so renaming them would break the file
+    if occurrences::definition(&doc.occurrences, name)
+        .is_none_or(|definition| !matches!(definition.role, Role::Definition | Role::Import))
+    {
+        return Err(RequestError::NotARenamableSymbol(position));
     }
+    Ok((name, occurrence.span))
 }
 
 fn is_valid_aether_name(name: &str) -> bool {
@@ -272,3 +2563,87 @@ fn is_valid_aether_name(name: &str) -> bool {
             .all(|c| c.is_ascii_uppercase() || c == '_' || c.is_ascii_digit())
         && !name.chars().next().unwrap().is_ascii_digit()
 }
+
+/// Index every module under `root`, reporting `$/progress` when the client supports it
+async fn index_workspace(
+    client: Client,
+    index: Arc<WorkspaceIndex>,
+    root: PathBuf,
+    exclude: Vec<String>,
+    supports_progress: bool,
+    locale: Locale,
+) {
+    let files = {
+        let (index, root) = (index.clone(), root.clone());
+        tokio::task::spawn_blocking(move || {
+            let rules = IgnoreRules::load(&root, &exclude);
+            let files = modules::discover_workspace(&root, &rules);
+            index.set_ignore_rules(&root, rules);
+            files
+        })
+        .await
+        .unwrap_or_default()
+    };
+    let total = files.len();
+
+    let token = NumberOrString::String("aether/indexing".to_string());
+    let report = supports_progress
+        && client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .is_ok();
+    let progress = |value: WorkDoneProgress| ProgressParams {
+        token: token.clone(),
+        value: ProgressParamsValue::WorkDone(value),
+    };
+
+    if report {
+        client
+            .send_notification::<notification::Progress>(progress(WorkDoneProgress::Begin(
+                WorkDoneProgressBegin {
+                    title: locale
+                        .pick("正在索引 Aether 模块", "Indexing Aether modules")
+                        .to_string(),
+                    cancellable: Some(false),
+                    message: Some(format!("0/{}", total)),
+                    percentage: Some(0),
+                },
+            )))
+            .await;
+    }
+
+    let mut tasks = workspace::spawn_indexing(index, files);
+    let mut done = 0;
+    let mut reported = 0;
+    while tasks.join_next().await.is_some() {
+        done += 1;
+        let percentage = (done * 100 / total.max(1)) as u32;
+        if report && percentage > reported {
+            reported = percentage;
+            client
+                .send_notification::<notification::Progress>(progress(WorkDoneProgress::Report(
+                    WorkDoneProgressReport {
+                        cancellable: Some(false),
+                        message: Some(format!("{}/{}", done, total)),
+                        percentage: Some(percentage),
+                    },
+                )))
+                .await;
+        }
+    }
+
+    if report {
+        client
+            .send_notification::<notification::Progress>(progress(WorkDoneProgress::End(
+                WorkDoneProgressEnd { message: None },
+            )))
+            .await;
+    }
+    logging::info(&format!(
+        "Indexed {} module(s) under {}",
+        total,
+        root.display()
+    ));
+}
@@ -2,446 +2,1228 @@
 //!
 //! This module contains definitions for all 200+ built-in functions
 
-use tower_lsp::lsp_types::*;
+use crate::locale::Locale;
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock};
 
 pub struct BuiltinFunction {
     pub name: &'static str,
     pub signature: &'static str,
     pub description: &'static str,
+    pub description_en: &'static str,
     pub category: &'static str,
     pub examples: &'static [&'static str],
+    /// Set for builtins kept only for compatibility; names the replacement
+    pub deprecated: Option<&'static str>,
 }
 
-/// Get all built-in functions
-pub fn get_builtin_functions() -> Vec<BuiltinFunction> {
+/// One parameter as written in a builtin's signature
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuiltinParam {
+    pub name: &'static str,
+    /// Written as `[name]`
+    pub optional: bool,
+    /// Written as `name...`; accepts any number of arguments
+    pub variadic: bool,
+}
+
+impl BuiltinFunction {
+    /// Parameters parsed from the signature, e.g. `PADSTART(string, length, [pad])`
+    pub fn params(&self) -> Vec<BuiltinParam> {
+        let Some(list) = self
+            .signature
+            .split_once('(')
+            .and_then(|(_, rest)| rest.strip_suffix(')'))
+        else {
+            return Vec::new();
+        };
+
+        list.split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (param, optional) = match param.strip_prefix('[') {
+                    Some(inner) => (inner.trim_end_matches(']'), true),
+                    None => (param, false),
+                };
+                let (name, variadic) = match param.strip_suffix("...") {
+                    Some(name) => (name, true),
+                    None => (param, false),
+                };
+                BuiltinParam {
+                    name,
+                    optional,
+                    variadic,
+                }
+            })
+            .collect()
+    }
+
+    /// Accepted argument counts as `(min, max)`; `max` is `None` for variadics
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        let params = self.params();
+        let min = params
+            .iter()
+            .filter(|param| !param.optional && !param.variadic)
+            .count();
+        let max = if params.iter().any(|param| param.variadic) {
+            None
+        } else {
+            Some(params.len())
+        };
+        (min, max)
+    }
+
+    pub fn description(&self, locale: Locale) -> &'static str {
+        locale.pick(self.description, self.description_en)
+    }
+
+    /// Entry of the `aether/builtins` catalog
+    pub fn catalog_entry(&self) -> CatalogEntry {
+        CatalogEntry {
+            name: self.name,
+            signature: self.signature,
+            params: self.params(),
+            category: self.category,
+            description: self.description,
+            description_en: self.description_en,
+            examples: self.examples,
+            deprecated: self.deprecated,
+        }
+    }
+
+    /// File and System builtins touch the world outside the script
+    pub fn performs_io(&self) -> bool {
+        matches!(self.category, "File" | "System")
+    }
+
+    /// Whether the result is always a Number: the math functions, and counts,
+    /// positions and conversions such as `LENGTH`, `INDEXOF` and `NUMBER`
+    pub fn returns_number(&self) -> bool {
+        NUMBER_RESULTS.contains(&self.name)
+    }
+
+    /// Type predicates such as `ISNUMBER` always return a Boolean
+    pub fn is_type_predicate(&self) -> bool {
+        self.category == "Type" && self.name.starts_with("IS")
+    }
+
+    /// The function argument this builtin calls back, if it takes one
+    pub fn callback(&self) -> Option<Callback> {
+        CALLBACKS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, callback)| *callback)
+    }
+}
+
+/// A function passed to a builtin, which calls it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Callback {
+    /// Position of the argument among the builtin's
+    pub arg: usize,
+    /// How many arguments the builtin calls it with
+    pub arity: usize,
+}
+
+/// The builtins taking a callback: MAP and FILTER pass the element, REDUCE
+/// the accumulator and the element
+const CALLBACKS: &[(&str, Callback)] = &[
+    ("MAP", Callback { arg: 1, arity: 1 }),
+    ("FILTER", Callback { arg: 1, arity: 1 }),
+    ("REDUCE", Callback { arg: 1, arity: 2 }),
+];
+
+/// The builtins whose result is always a Number
+const NUMBER_RESULTS: &[&str] = &[
+    "LENGTH", "SUM", "COUNT", "INDEXOF", "ABS", "FLOOR", "CEIL", "ROUND", "SQRT", "POW", "LOG",
+    "LOG10", "SIN", "COS", "TAN", "RANDOM", "ATAN2", "EXP", "SIGN", "CLAMP", "GCD", "LCM",
+    "NUMBER", "NOW",
+];
+
+/// Parameters of the `aether/builtins` request
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CatalogParams {
+    /// Only list builtins of this category
+    pub category: Option<String>,
+}
+
+/// Parameters of the `aether/builtinDoc` request
+#[derive(Debug, Deserialize)]
+pub struct BuiltinDocParams {
+    pub name: String,
+}
+
+/// A builtin as serialized for the `aether/builtins` request
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogEntry {
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub params: Vec<BuiltinParam>,
+    pub category: &'static str,
+    pub description: &'static str,
+    pub description_en: &'static str,
+    pub examples: &'static [&'static str],
+    pub deprecated: Option<&'static str>,
+}
+
+/// The builtin catalog, optionally restricted to one category
+pub fn catalog(params: &CatalogParams) -> Vec<CatalogEntry> {
+    match &params.category {
+        Some(category) => by_category(category)
+            .map(BuiltinFunction::catalog_entry)
+            .collect(),
+        None => all().iter().map(BuiltinFunction::catalog_entry).collect(),
+    }
+}
+
+/// A predefined value such as `PI`, referenced without parentheses
+pub struct BuiltinConstant {
+    pub name: &'static str,
+    /// Display form of the value
+    pub value: &'static str,
+    pub description: &'static str,
+    pub description_en: &'static str,
+}
+
+impl BuiltinConstant {
+    pub fn description(&self, locale: Locale) -> &'static str {
+        locale.pick(self.description, self.description_en)
+    }
+}
+
+/// All built-in constants
+pub const BUILTIN_CONSTANTS: &[BuiltinConstant] = &[
+    BuiltinConstant {
+        name: "PI",
+        value: "3.141592653589793",
+        description: "圆周率 π",
+        description_en: "The ratio of a circle's circumference to its diameter (π)",
+    },
+    BuiltinConstant {
+        name: "E",
+        value: "2.718281828459045",
+        description: "自然对数的底 e",
+        description_en: "Euler's number, the base of the natural logarithm",
+    },
+    BuiltinConstant {
+        name: "TAU",
+        value: "6.283185307179586",
+        description: "τ = 2π",
+        description_en: "The circle constant τ = 2π",
+    },
+    BuiltinConstant {
+        name: "INFINITY",
+        value: "Infinity",
+        description: "正无穷大",
+        description_en: "Positive infinity",
+    },
+    BuiltinConstant {
+        name: "NAN",
+        value: "NaN",
+        description: "非数字值",
+        description_en: "Not-a-number",
+    },
+];
+
+/// Builtin functions indexed by name, built once and rebuilt only when the
+/// host-provided extras change
+struct Registry {
+    functions: Vec<BuiltinFunction>,
+    by_name: HashMap<&'static str, usize>,
+    /// Number of entries that came from `aether.extraBuiltins`
+    extras: usize,
+}
+
+impl Registry {
+    fn build(extra: Vec<BuiltinFunction>) -> Self {
+        let extras = extra.len();
+        let mut functions: Vec<BuiltinFunction> = builtin_definitions()
+            .into_iter()
+            .filter(|func| !extra.iter().any(|custom| custom.name == func.name))
+            .collect();
+        functions.extend(extra);
+        let by_name = functions
+            .iter()
+            .enumerate()
+            .map(|(index, func)| (func.name, index))
+            .collect();
+        Registry {
+            functions,
+            by_name,
+            extras,
+        }
+    }
+}
+
+static REGISTRY: RwLock<Option<&'static Registry>> = RwLock::new(None);
+
+fn registry() -> &'static Registry {
+    if let Some(registry) = *REGISTRY.read().unwrap_or_else(PoisonError::into_inner) {
+        return registry;
+    }
+    let mut slot = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    let registry: &'static Registry =
+        slot.get_or_insert_with(|| Box::leak(Box::new(Registry::build(Vec::new()))));
+    registry
+}
+
+/// Replace the host-provided builtins, overriding core entries of the same name
+///
+/// Entries handed out earlier stay valid, so the previous registry is leaked;
+/// `extra_builtins::install` only calls this when the definitions change.
+pub fn set_extra_builtins(extra: Vec<BuiltinFunction>) {
+    if extra.is_empty() && registry().extras == 0 {
+        return;
+    }
+    let registry: &'static Registry = Box::leak(Box::new(Registry::build(extra)));
+    *REGISTRY.write().unwrap_or_else(PoisonError::into_inner) = Some(registry);
+}
+
+/// All builtin functions, in registry order
+pub fn all() -> &'static [BuiltinFunction] {
+    &registry().functions
+}
+
+/// Builtin functions of one category, e.g. `"String"` (case-insensitive)
+pub fn by_category(category: &str) -> impl Iterator<Item = &'static BuiltinFunction> {
+    all()
+        .iter()
+        .filter(move |func| func.category.eq_ignore_ascii_case(category))
+}
+
+/// Find a builtin function by name (case-insensitive)
+pub fn lookup(name: &str) -> Option<&'static BuiltinFunction> {
+    let registry = registry();
+    with_uppercase(name, |key| registry.by_name.get(key)).map(|&index| &registry.functions[index])
+}
+
+/// Find a builtin constant by name (case-insensitive)
+pub fn lookup_constant(name: &str) -> Option<&'static BuiltinConstant> {
+    BUILTIN_CONSTANTS
+        .iter()
+        .find(|constant| constant.name.eq_ignore_ascii_case(name))
+}
+
+/// Whether `name` refers to a builtin function or constant
+pub fn is_builtin_name(name: &str) -> bool {
+    lookup(name).is_some() || lookup_constant(name).is_some()
+}
+
+/// Call `f` with `name` upper-cased; short ASCII names are converted on the
+/// stack so hot lookups never allocate
+fn with_uppercase<R>(name: &str, f: impl FnOnce(&str) -> R) -> R {
+    const MAX_INLINE: usize = 32;
+    if !name.bytes().any(|byte| byte.is_ascii_lowercase()) {
+        return f(name);
+    }
+    if name.is_ascii() && name.len() <= MAX_INLINE {
+        let mut buffer = [0u8; MAX_INLINE];
+        let upper = &mut buffer[..name.len()];
+        upper.copy_from_slice(name.as_bytes());
+        upper.make_ascii_uppercase();
+        return f(std::str::from_utf8(upper).expect("ASCII stays valid UTF-8"));
+    }
+    f(&name.to_uppercase())
+}
+
+/// Characters that accept a function completion when typed
+///
+/// Function items insert just their name so that typing `(` composes the call.
+pub fn function_commit_characters() -> Vec<String> {
+    vec!["(".to_string()]
+}
+
+/// Definitions of all built-in functions; use [`all`] or [`lookup`] instead
+fn builtin_definitions() -> Vec<BuiltinFunction> {
     vec![
         // === I/O Functions ===
         BuiltinFunction {
             name: "PRINTLN",
             signature: "PRINTLN(value...)",
             description: "打印值到控制台并换行",
+            description_en: "Print values to the console followed by a newline",
             category: "IO",
             examples: &["PRINTLN(\"Hello World\")", "PRINTLN(MY_VAR, MY_VAR2)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "PRINT",
             signature: "PRINT(value...)",
             description: "打印值到控制台(不换行)",
+            description_en: "Print values to the console without a newline",
             category: "IO",
             examples: &["PRINT(\"Result: \")", "PRINT(RESULT)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "INPUT",
             signature: "INPUT(prompt)",
             description: "读取用户输入",
+            description_en: "Read a line of user input",
             category: "IO",
             examples: &["Set NAME INPUT(\"Enter your name: \")"],
+            deprecated: None,
         },
         // === Array Functions ===
         BuiltinFunction {
             name: "MAP",
             signature: "MAP(array, function)",
             description: "对数组每个元素应用函数",
+            description_en: "Apply a function to every element of an array",
             category: "Array",
             examples: &["Set DOUBLED MAP(NUMBERS, Lambda X -> (X * 2))"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "FILTER",
             signature: "FILTER(array, predicate)",
             description: "过滤数组元素",
+            description_en: "Keep the array elements that satisfy a predicate",
             category: "Array",
             examples: &["Set EVENS FILTER(NUMBERS, Lambda X -> ((X % 2) == 0))"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "REDUCE",
             signature: "REDUCE(array, function, initial)",
             description: "归约数组为单一值",
+            description_en: "Reduce an array to a single value",
             category: "Array",
             examples: &["Set SUM REDUCE(NUMBERS, Lambda (ACC, X) -> (ACC + X), 0)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "LENGTH",
             signature: "LENGTH(array_or_string)",
             description: "返回数组或字符串的长度",
+            description_en: "Return the length of an array or string",
             category: "Array",
             examples: &["Set LEN LENGTH([1, 2, 3])", "Set STR_LEN LENGTH(\"hello\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "PUSH",
             signature: "PUSH(array, element)",
             description: "添加元素到数组末尾",
+            description_en: "Append an element to the end of an array",
             category: "Array",
             examples: &["PUSH(MY_ARR, 42)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "POP",
             signature: "POP(array)",
             description: "移除并返回数组最后一个元素",
+            description_en: "Remove and return the last element of an array",
             category: "Array",
             examples: &["Set LAST POP(MY_ARR)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SORT",
             signature: "SORT(array)",
             description: "排序数组(升序)",
+            description_en: "Sort an array in ascending order",
             category: "Array",
             examples: &["Set SORTED SORT([3, 1, 4, 1, 5])"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "REVERSE",
             signature: "REVERSE(array)",
             description: "反转数组",
+            description_en: "Reverse an array",
             category: "Array",
             examples: &["Set REVERSED REVERSE([1, 2, 3])"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "JOIN",
             signature: "JOIN(array, separator)",
             description: "用分隔符连接数组元素为字符串",
+            description_en: "Join array elements into a string with a separator",
             category: "Array",
             examples: &["Set CSV JOIN([\"a\", \"b\", \"c\"], \",\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "RANGE",
             signature: "RANGE(start, end)",
             description: "生成数字范围数组",
+            description_en: "Generate an array of numbers in a range",
             category: "Array",
             examples: &["Set NUMS RANGE(1, 10)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SUM",
             signature: "SUM(array)",
             description: "计算数组元素总和",
+            description_en: "Sum the elements of an array",
             category: "Array",
             examples: &["Set TOTAL SUM([1, 2, 3, 4, 5])"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "MIN",
             signature: "MIN(array)",
             description: "返回数组最小值",
+            description_en: "Return the smallest element of an array",
             category: "Array",
             examples: &["Set MINIMUM MIN([3, 1, 4, 1, 5])"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "MAX",
             signature: "MAX(array)",
             description: "返回数组最大值",
+            description_en: "Return the largest element of an array",
             category: "Array",
             examples: &["Set MAXIMUM MAX([3, 1, 4, 1, 5])"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "SLICE",
+            signature: "SLICE(array_or_string, start, [end])",
+            description: "截取数组或字符串的一段",
+            description_en: "Take a section of an array or string",
+            category: "Array",
+            examples: &[
+                "Set FIRST_TWO SLICE([1, 2, 3, 4], 0, 2)",
+                "Set TAIL SLICE(\"hello\", 1)",
+            ],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "CONCAT",
+            signature: "CONCAT(array, others...)",
+            description: "连接多个数组",
+            description_en: "Concatenate arrays",
+            category: "Array",
+            examples: &["Set ALL CONCAT([1, 2], [3], [4, 5])"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "FLATTEN",
+            signature: "FLATTEN(array)",
+            description: "将嵌套数组展开一层",
+            description_en: "Flatten nested arrays by one level",
+            category: "Array",
+            examples: &["Set FLAT FLATTEN([[1, 2], [3, 4]])"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "UNIQUE",
+            signature: "UNIQUE(array)",
+            description: "去除数组中的重复元素",
+            description_en: "Remove duplicate elements from an array",
+            category: "Array",
+            examples: &["Set DISTINCT UNIQUE([1, 2, 2, 3, 1])"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "ZIP",
+            signature: "ZIP(array1, array2)",
+            description: "将两个数组按位置配对",
+            description_en: "Pair up elements of two arrays by position",
+            category: "Array",
+            examples: &["Set PAIRS ZIP([\"a\", \"b\"], [1, 2])"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "ENUMERATE",
+            signature: "ENUMERATE(array)",
+            description: "返回 [索引, 元素] 对组成的数组",
+            description_en: "Return an array of [index, element] pairs",
+            category: "Array",
+            examples: &[
+                "For PAIR In ENUMERATE([\"x\", \"y\"]) {\n    PRINTLN(PAIR[0], PAIR[1])\n}",
+            ],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "COUNT",
+            signature: "COUNT(array, value)",
+            description: "统计数组中某个值出现的次数",
+            description_en: "Count how often a value occurs in an array",
+            category: "Array",
+            examples: &["Set ONES COUNT([1, 0, 1, 1], 1)"],
+            deprecated: None,
         },
         // === String Functions ===
         BuiltinFunction {
             name: "SPLIT",
             signature: "SPLIT(string, separator)",
             description: "分割字符串为数组",
+            description_en: "Split a string into an array",
             category: "String",
             examples: &["Set PARTS SPLIT(\"a,b,c\", \",\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "UPPER",
             signature: "UPPER(string)",
             description: "转换为大写",
+            description_en: "Convert to uppercase",
             category: "String",
             examples: &["Set UPPER UPPER(\"hello\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "LOWER",
             signature: "LOWER(string)",
             description: "转换为小写",
+            description_en: "Convert to lowercase",
             category: "String",
             examples: &["Set LOWER LOWER(\"HELLO\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "TRIM",
             signature: "TRIM(string)",
             description: "去除首尾空格",
+            description_en: "Trim leading and trailing whitespace",
             category: "String",
             examples: &["Set TRIMMED TRIM(\"  hello  \")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "REPLACE",
             signature: "REPLACE(string, old, new)",
             description: "替换子串",
+            description_en: "Replace occurrences of a substring",
             category: "String",
             examples: &["Set REPLACED REPLACE(\"hello\", \"l\", \"r\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "STARTSWITH",
             signature: "STARTSWITH(string, prefix)",
             description: "检查是否以指定前缀开始",
+            description_en: "Check whether a string starts with a prefix",
             category: "String",
             examples: &["Set IS_PREFIX STARTSWITH(\"hello\", \"he\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ENDSWITH",
             signature: "ENDSWITH(string, suffix)",
             description: "检查是否以指定后缀结束",
+            description_en: "Check whether a string ends with a suffix",
             category: "String",
             examples: &["Set IS_SUFFIX ENDSWITH(\"hello\", \"lo\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SUBSTRING",
             signature: "SUBSTRING(string, start, length)",
             description: "提取子串",
+            description_en: "Extract a substring",
             category: "String",
             examples: &["Set SUB SUBSTRING(\"hello\", 1, 3)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "FORMAT",
             signature: "FORMAT(template, args...)",
             description: "格式化字符串",
+            description_en: "Format a string",
             category: "String",
             examples: &["Set MSG FORMAT(\"Hello {}, you are {} years old\", NAME, AGE)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "CONTAINS",
+            signature: "CONTAINS(string_or_array, value)",
+            description: "检查字符串或数组是否包含指定值",
+            description_en: "Check whether a string or array contains a value",
+            category: "String",
+            examples: &[
+                "Set HAS_AT CONTAINS(\"user@example.com\", \"@\")",
+                "Set HAS_TWO CONTAINS([1, 2, 3], 2)",
+            ],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "INDEXOF",
+            signature: "INDEXOF(string_or_array, value)",
+            description: "返回值首次出现的位置,未找到时返回 -1",
+            description_en: "Return the position of the first occurrence, or -1 if absent",
+            category: "String",
+            examples: &["Set POS INDEXOF(\"hello\", \"l\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "REPEAT",
+            signature: "REPEAT(string, count)",
+            description: "将字符串重复指定次数",
+            description_en: "Repeat a string a number of times",
+            category: "String",
+            examples: &["Set LINE REPEAT(\"-\", 20)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "PADSTART",
+            signature: "PADSTART(string, length, [pad])",
+            description: "在开头填充字符串至指定长度",
+            description_en: "Pad the start of a string to a given length",
+            category: "String",
+            examples: &["Set ID PADSTART(\"42\", 5, \"0\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "PADEND",
+            signature: "PADEND(string, length, [pad])",
+            description: "在末尾填充字符串至指定长度",
+            description_en: "Pad the end of a string to a given length",
+            category: "String",
+            examples: &["Set CELL PADEND(\"name\", 10)"],
+            deprecated: None,
         },
         // === Math Functions ===
         BuiltinFunction {
             name: "ABS",
             signature: "ABS(number)",
             description: "返回绝对值",
+            description_en: "Return the absolute value",
             category: "Math",
             examples: &["Set ABSOLUTE ABS(-5)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "FLOOR",
             signature: "FLOOR(number)",
             description: "向下取整",
+            description_en: "Round down to the nearest integer",
             category: "Math",
             examples: &["Set FLOORED FLOOR(3.7)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "CEIL",
             signature: "CEIL(number)",
             description: "向上取整",
+            description_en: "Round up to the nearest integer",
             category: "Math",
             examples: &["Set CEILED CEIL(3.2)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ROUND",
             signature: "ROUND(number)",
             description: "四舍五入",
+            description_en: "Round to the nearest integer",
             category: "Math",
             examples: &["Set ROUNDED ROUND(3.5)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SQRT",
             signature: "SQRT(number)",
             description: "计算平方根",
+            description_en: "Compute the square root",
             category: "Math",
             examples: &["Set ROOT SQRT(16)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "POW",
             signature: "POW(base, exponent)",
             description: "计算幂",
+            description_en: "Raise a number to a power",
             category: "Math",
             examples: &["Set POWER POW(2, 3)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "LOG",
             signature: "LOG(number)",
             description: "计算自然对数",
+            description_en: "Compute the natural logarithm",
             category: "Math",
             examples: &["Set LN LOG(2.718)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "LOG10",
             signature: "LOG10(number)",
             description: "计算以10为底的对数",
+            description_en: "Compute the base-10 logarithm",
             category: "Math",
             examples: &["Set LG LOG10(100)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SIN",
             signature: "SIN(radians)",
             description: "计算正弦值",
+            description_en: "Compute the sine",
             category: "Math",
             examples: &["Set SINE SIN(1.57)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "COS",
             signature: "COS(radians)",
             description: "计算余弦值",
+            description_en: "Compute the cosine",
             category: "Math",
             examples: &["Set COSINE COS(0)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "TAN",
             signature: "TAN(radians)",
             description: "计算正切值",
+            description_en: "Compute the tangent",
             category: "Math",
             examples: &["Set TANGENT TAN(0.785)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "RANDOM",
             signature: "RANDOM()",
             description: "生成 0-1 之间的随机数",
+            description_en: "Generate a random number between 0 and 1",
             category: "Math",
             examples: &["Set RAND RANDOM()"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "ATAN2",
+            signature: "ATAN2(y, x)",
+            description: "计算 y/x 的反正切值(弧度),考虑象限",
+            description_en: "Compute the angle of the point (x, y) in radians",
+            category: "Math",
+            examples: &["Set ANGLE ATAN2(1, 1)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "EXP",
+            signature: "EXP(number)",
+            description: "计算 e 的幂",
+            description_en: "Raise e to a power",
+            category: "Math",
+            examples: &["Set GROWTH EXP(0.5)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "SIGN",
+            signature: "SIGN(number)",
+            description: "返回数字的符号: -1、0 或 1",
+            description_en: "Return the sign of a number: -1, 0 or 1",
+            category: "Math",
+            examples: &["Set DIRECTION SIGN(DELTA)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "CLAMP",
+            signature: "CLAMP(value, min, max)",
+            description: "将数值限制在区间内",
+            description_en: "Limit a number to a range",
+            category: "Math",
+            examples: &["Set VOLUME CLAMP(LEVEL, 0, 100)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "GCD",
+            signature: "GCD(a, b)",
+            description: "计算最大公约数",
+            description_en: "Compute the greatest common divisor",
+            category: "Math",
+            examples: &["Set DIVISOR GCD(12, 18)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "LCM",
+            signature: "LCM(a, b)",
+            description: "计算最小公倍数",
+            description_en: "Compute the least common multiple",
+            category: "Math",
+            examples: &["Set MULTIPLE LCM(4, 6)"],
+            deprecated: None,
         },
         // === Type Functions ===
         BuiltinFunction {
             name: "TYPE",
             signature: "TYPE(value)",
             description: "返回值的类型字符串",
+            description_en: "Return the type of a value as a string",
             category: "Type",
             examples: &["Set T TYPE(42)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "STRING",
             signature: "STRING(value)",
             description: "转换为字符串",
+            description_en: "Convert to a string",
             category: "Type",
             examples: &["Set STR STRING(42)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "NUMBER",
             signature: "NUMBER(string_or_value)",
             description: "转换为数字",
+            description_en: "Convert to a number",
             category: "Type",
             examples: &["Set NUM NUMBER(\"42\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ISNUMBER",
             signature: "ISNUMBER(value)",
             description: "检查是否为数字",
+            description_en: "Check whether a value is a number",
             category: "Type",
             examples: &["Set IS_NUM ISNUMBER(42)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ISSTRING",
             signature: "ISSTRING(value)",
             description: "检查是否为字符串",
+            description_en: "Check whether a value is a string",
             category: "Type",
             examples: &["Set IS_STR ISSTRING(\"hello\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ISARRAY",
             signature: "ISARRAY(value)",
             description: "检查是否为数组",
+            description_en: "Check whether a value is an array",
             category: "Type",
             examples: &["Set IS_ARR ISARRAY([1, 2])"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ISDICT",
             signature: "ISDICT(value)",
             description: "检查是否为字典",
+            description_en: "Check whether a value is a dict",
             category: "Type",
             examples: &["Set IS_DICT ISDICT({\"key\": \"value\"})"],
+            deprecated: None,
         },
         // === Dict Functions ===
         BuiltinFunction {
             name: "KEYS",
             signature: "KEYS(dict)",
             description: "返回字典所有键",
+            description_en: "Return all keys of a dict",
             category: "Dict",
             examples: &["Set ALL_KEYS KEYS(MY_DICT)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "VALUES",
             signature: "VALUES(dict)",
             description: "返回字典所有值",
+            description_en: "Return all values of a dict",
             category: "Dict",
             examples: &["Set ALL_VALUES VALUES(MY_DICT)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "ITEMS",
             signature: "ITEMS(dict)",
             description: "返回键值对数组",
+            description_en: "Return an array of key-value pairs",
             category: "Dict",
             examples: &["Set PAIRS ITEMS(MY_DICT)"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "HASKEY",
             signature: "HASKEY(dict, key)",
             description: "检查字典是否包含指定键",
+            description_en: "Check whether a dict contains a key",
             category: "Dict",
             examples: &["Set HAS HASKEY(MY_DICT, \"name\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "GET",
+            signature: "GET(dict, key, default)",
+            description: "读取字典中的值,键不存在时返回默认值",
+            description_en: "Read a value from a dict, or the default when the key is missing",
+            category: "Dict",
+            examples: &["Set PORT GET(CONFIG, \"port\", 8080)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "SETKEY",
+            signature: "SETKEY(dict, key, value)",
+            description: "返回设置了指定键的新字典",
+            description_en: "Return a copy of the dict with a key set",
+            category: "Dict",
+            examples: &["Set CONFIG SETKEY(CONFIG, \"debug\", True)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "DELETE",
+            signature: "DELETE(dict, key)",
+            description: "返回删除了指定键的新字典",
+            description_en: "Return a copy of the dict without a key",
+            category: "Dict",
+            examples: &["Set CONFIG DELETE(CONFIG, \"debug\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "MERGE",
+            signature: "MERGE(a, b)",
+            description: "合并两个字典,键冲突时以第二个为准",
+            description_en: "Merge two dicts; keys in the second win",
+            category: "Dict",
+            examples: &["Set SETTINGS MERGE(DEFAULTS, OVERRIDES)"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "CLEAR",
+            signature: "CLEAR(dict)",
+            description: "返回空字典",
+            description_en: "Return an empty dict",
+            category: "Dict",
+            examples: &["Set CACHE CLEAR(CACHE)"],
+            deprecated: None,
         },
         // === JSON Functions ===
         BuiltinFunction {
             name: "JSONPARSE",
             signature: "JSONPARSE(json_string)",
             description: "解析JSON字符串",
+            description_en: "Parse a JSON string",
             category: "JSON",
             examples: &["Set DATA JSONPARSE(\"{\\\"name\\\": \\\"Alice\\\"}\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "JSONSTRINGIFY",
             signature: "JSONSTRINGIFY(value)",
             description: "将值转换为JSON字符串",
+            description_en: "Convert a value to a JSON string",
             category: "JSON",
             examples: &["Set JSON JSONSTRINGIFY(MY_DATA)"],
+            deprecated: None,
         },
         // === Date/Time Functions ===
         BuiltinFunction {
             name: "NOW",
             signature: "NOW()",
             description: "返回当前时间戳",
+            description_en: "Return the current timestamp",
             category: "DateTime",
             examples: &["Set TIMESTAMP NOW()"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "FORMATDATE",
             signature: "FORMATDATE(timestamp, format)",
             description: "格式化时间戳",
+            description_en: "Format a timestamp",
             category: "DateTime",
             examples: &["Set DATE_STR FORMATDATE(NOW(), \"%Y-%m-%d\")"],
+            deprecated: None,
         },
         BuiltinFunction {
             name: "SLEEP",
             signature: "SLEEP(seconds)",
             description: "暂停执行指定秒数",
+            description_en: "Pause execution for the given number of seconds",
             category: "DateTime",
             examples: &["SLEEP(1)"],
+            deprecated: None,
+        },
+        // === File Functions ===
+        BuiltinFunction {
+            name: "READFILE",
+            signature: "READFILE(path)",
+            description: "读取文件全部内容为字符串",
+            description_en: "Read a whole file into a string",
+            category: "File",
+            examples: &["Set CONTENT READFILE(\"config.json\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "WRITEFILE",
+            signature: "WRITEFILE(path, content)",
+            description: "将字符串写入文件(覆盖)",
+            description_en: "Write a string to a file, replacing its contents",
+            category: "File",
+            examples: &["WRITEFILE(\"out.txt\", \"done\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "APPENDFILE",
+            signature: "APPENDFILE(path, content)",
+            description: "将字符串追加到文件末尾",
+            description_en: "Append a string to the end of a file",
+            category: "File",
+            examples: &["APPENDFILE(\"log.txt\", \"started\\n\")"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "EXISTS",
+            signature: "EXISTS(path)",
+            description: "检查文件或目录是否存在",
+            description_en: "Check whether a file or directory exists",
+            category: "File",
+            examples: &["If (EXISTS(\"data.csv\")) {\n    PRINTLN(\"found\")\n}"],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "LISTDIR",
+            signature: "LISTDIR(path)",
+            description: "返回目录中的条目名称数组",
+            description_en: "Return the names of the entries in a directory",
+            category: "File",
+            examples: &["For NAME In LISTDIR(\".\") {\n    PRINTLN(NAME)\n}"],
+            deprecated: None,
+        },
+        // === System Functions ===
+        BuiltinFunction {
+            name: "GETENV",
+            signature: "GETENV(name, [default])",
+            description: "读取环境变量,不存在时返回默认值或 Null",
+            description_en: "Read an environment variable, or the default / Null when unset",
+            category: "System",
+            examples: &[
+                "Set HOME GETENV(\"HOME\")",
+                "Set MODE GETENV(\"APP_MODE\", \"dev\")",
+            ],
+            deprecated: None,
+        },
+        BuiltinFunction {
+            name: "EXIT",
+            signature: "EXIT([code])",
+            description: "以指定退出码结束脚本",
+            description_en: "Terminate the script with an exit code",
+            category: "System",
+            examples: &["EXIT(1)"],
+            deprecated: None,
         },
     ]
 }
 
-/// Convert builtin functions to LSP completion items
-pub fn builtin_to_completion_items() -> Vec<CompletionItem> {
-    get_builtin_functions()
-        .into_iter()
-        .map(|func| {
-            let detail = format!("{} - {}", func.signature, func.category);
-            let doc = format!(
-                "{}\n\n**分类**: {}\n\n**示例**:\n```aether\n{}\n```",
-                func.description,
-                func.category,
-                func.examples.join("\n")
-            );
-
-            CompletionItem {
-                label: func.name.to_string(),
-                kind: Some(CompletionItemKind::FUNCTION),
-                detail: Some(detail),
-                documentation: Some(Documentation::MarkupContent(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: doc,
-                })),
-                insert_text: Some(format!("{}($1)", func.name)),
-                insert_text_format: Some(InsertTextFormat::SNIPPET),
-                ..Default::default()
-            }
-        })
+/// Convert builtin functions and constants to LSP completion items
+pub fn builtin_to_completion_items(locale: Locale) -> Vec<CompletionItem> {
+    all()
+        .iter()
+        .map(|func| function_completion_item(func, locale))
+        .chain(
+            BUILTIN_CONSTANTS
+                .iter()
+                .map(|constant| constant_completion_item(constant, locale)),
+        )
         .collect()
 }
 
-/// Find a builtin function by name (case-insensitive)
-pub fn find_builtin(name: &str) -> Option<BuiltinFunction> {
-    let name_upper = name.to_uppercase();
-    get_builtin_functions()
-        .into_iter()
-        .find(|f| f.name.to_uppercase() == name_upper)
+fn function_completion_item(func: &BuiltinFunction, locale: Locale) -> CompletionItem {
+    let detail = format!("{} - {}", func.signature, func.category);
+    let doc = format!(
+        "{}\n\n**{}**: {}\n\n**{}**:\n```aether\n{}\n```",
+        func.description(locale),
+        locale.pick("分类", "Category"),
+        func.category,
+        locale.pick("示例", "Examples"),
+        func.examples.join("\n")
+    );
+
+    CompletionItem {
+        label: func.name.to_string(),
+        label_details: Some(CompletionItemLabelDetails {
+            detail: func.is_type_predicate().then(|| " → Boolean".to_string()),
+            description: Some(func.category.to_string()),
+        }),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: Some(detail),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: doc,
+        })),
+        insert_text: Some(func.name.to_string()),
+        insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+        commit_characters: Some(function_commit_characters()),
+        tags: func.deprecated.map(|_| vec![CompletionItemTag::DEPRECATED]),
+        ..Default::default()
+    }
+}
+
+/// Constants insert their bare name and are never committed by `(`
+fn constant_completion_item(constant: &BuiltinConstant, locale: Locale) -> CompletionItem {
+    CompletionItem {
+        label: constant.name.to_string(),
+        label_details: Some(CompletionItemLabelDetails {
+            detail: None,
+            description: Some("Math".to_string()),
+        }),
+        kind: Some(CompletionItemKind::CONSTANT),
+        detail: Some(format!("{} = {}", constant.name, constant.value)),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: constant.description(locale).to_string(),
+        })),
+        insert_text: Some(constant.name.to_string()),
+        insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+        ..Default::default()
+    }
+}
+
+/// Hover for a builtin function or constant named `name`
+pub fn builtin_hover(name: &str, locale: Locale) -> Option<Hover> {
+    lookup(name)
+        .map(|func| builtin_to_hover(func, locale))
+        .or_else(|| lookup_constant(name).map(|constant| constant_to_hover(constant, locale)))
+}
+
+/// Create hover content for a builtin constant, showing its value
+pub fn constant_to_hover(constant: &BuiltinConstant, locale: Locale) -> Hover {
+    let content = format!(
+        "## {} ({})\n\n```aether\n{} = {}\n```\n\n{}",
+        constant.name,
+        locale.pick("内置常量", "builtin constant"),
+        constant.name,
+        constant.value,
+        constant.description(locale)
+    );
+
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: content,
+        }),
+        range: None,
+    }
 }
 
 /// Create hover content for a builtin function
-pub fn builtin_to_hover(func: &BuiltinFunction) -> Hover {
+pub fn builtin_to_hover(func: &BuiltinFunction, locale: Locale) -> Hover {
+    let banner = match func.deprecated {
+        Some(replacement) => match locale {
+            Locale::Zh => format!("> **已弃用**: 请改用 `{}`\n\n", replacement),
+            Locale::En => format!("> **Deprecated**: use `{}`\n\n", replacement),
+        },
+        None => String::new(),
+    };
     let content = format!(
-        "## {} (内置函数)\n\n**签名**: `{}`\n\n**描述**: {}\n\n**分类**: {}\n\n**示例**:\n```aether\n{}\n```",
+        "{}## {} ({})\n\n**{}**: `{}`\n\n**{}**: {}\n\n**{}**: {}\n\n**{}**:\n```aether\n{}\n```",
+        banner,
         func.name,
+        locale.pick("内置函数", "builtin"),
+        locale.pick("签名", "Signature"),
         func.signature,
-        func.description,
+        locale.pick("描述", "Description"),
+        func.description(locale),
+        locale.pick("分类", "Category"),
         func.category,
+        locale.pick("示例", "Examples"),
         func.examples.join("\n")
     );
 
@@ -453,3 +1235,187 @@ pub fn builtin_to_hover(func: &BuiltinFunction) -> Hover {
         range: None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc;
+
+    fn completion(name: &str) -> CompletionItem {
+        builtin_to_completion_items(Locale::Zh)
+            .into_iter()
+            .find(|item| item.label == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_builtin_completion_kinds_and_labels() {
+        let map = completion("MAP");
+        assert_eq!(map.kind, Some(CompletionItemKind::FUNCTION));
+        assert_eq!(map.commit_characters, Some(vec!["(".to_string()]));
+        let details = map.label_details.unwrap();
+        assert_eq!(details.description.as_deref(), Some("Array"));
+        assert_eq!(details.detail, None);
+
+        let predicate = completion("ISNUMBER");
+        assert_eq!(predicate.kind, Some(CompletionItemKind::FUNCTION));
+        let details = predicate.label_details.unwrap();
+        assert_eq!(details.description.as_deref(), Some("Type"));
+        assert_eq!(details.detail.as_deref(), Some(" → Boolean"));
+
+        assert_eq!(
+            completion("UPPER")
+                .label_details
+                .unwrap()
+                .description
+                .as_deref(),
+            Some("String")
+        );
+    }
+
+    fn hover_text(name: &str, locale: Locale) -> String {
+        let HoverContents::Markup(content) =
+            builtin_to_hover(lookup(name).unwrap(), locale).contents
+        else {
+            panic!("expected markdown hover");
+        };
+        content.value
+    }
+
+    #[test]
+    fn test_builtin_hover_is_localized() {
+        let zh = hover_text("MAP", Locale::Zh);
+        assert!(zh.starts_with("## MAP (内置函数)"));
+        assert!(zh.contains("**描述**: 对数组每个元素应用函数"));
+
+        let en = hover_text("MAP", Locale::En);
+        assert!(en.starts_with("## MAP (builtin)"));
+        assert!(en.contains("**Description**: Apply a function to every element of an array"));
+        assert!(en.contains("**Signature**: `MAP(array, function)`"));
+    }
+
+    #[test]
+    fn test_registry_has_no_duplicate_names() {
+        let mut seen = std::collections::HashSet::new();
+        for func in builtin_definitions() {
+            assert!(seen.insert(func.name), "duplicate builtin {}", func.name);
+        }
+    }
+
+    #[test]
+    fn test_registry_examples_parse() {
+        for func in all() {
+            assert!(!func.examples.is_empty(), "{} has no example", func.name);
+            for example in func.examples {
+                let doc = crate::parser::Parser::new(example).parse();
+                assert!(
+                    doc.errors.is_empty(),
+                    "example of {} does not parse: {}\n{:?}",
+                    func.name,
+                    example,
+                    doc.errors
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_params_from_signature() {
+        let pad = lookup("PADSTART").unwrap();
+        assert_eq!(
+            pad.params(),
+            vec![
+                BuiltinParam {
+                    name: "string",
+                    optional: false,
+                    variadic: false,
+                },
+                BuiltinParam {
+                    name: "length",
+                    optional: false,
+                    variadic: false,
+                },
+                BuiltinParam {
+                    name: "pad",
+                    optional: true,
+                    variadic: false,
+                },
+            ]
+        );
+        assert_eq!(pad.arity(), (2, Some(3)));
+        assert_eq!(lookup("CONCAT").unwrap().arity(), (1, None));
+        assert_eq!(lookup("NOW").unwrap().arity(), (0, Some(0)));
+        assert_eq!(lookup("PRINTLN").unwrap().arity(), (0, None));
+    }
+
+    #[test]
+    fn test_file_and_system_builtins() {
+        let read = lookup("READFILE").unwrap();
+        assert_eq!(read.category, "File");
+        assert!(read.performs_io());
+        assert_eq!(read.arity(), (1, Some(1)));
+
+        let getenv = lookup("GETENV").unwrap();
+        assert_eq!(getenv.category, "System");
+        assert_eq!(getenv.arity(), (1, Some(2)));
+        assert_eq!(lookup("EXIT").unwrap().arity(), (0, Some(1)));
+
+        assert!(!lookup("PRINTLN").unwrap().performs_io());
+        assert!(
+            builtin_to_completion_items(Locale::Zh)
+                .iter()
+                .any(|item| item.label == "LISTDIR")
+        );
+    }
+
+    #[test]
+    fn test_constants_complete_without_parens() {
+        let pi = completion("PI");
+        assert_eq!(pi.kind, Some(CompletionItemKind::CONSTANT));
+        assert_eq!(pi.insert_text.as_deref(), Some("PI"));
+        assert_eq!(pi.insert_text_format, Some(InsertTextFormat::PLAIN_TEXT));
+        assert_eq!(pi.commit_characters, None);
+
+        let HoverContents::Markup(content) =
+            constant_to_hover(lookup_constant("TAU").unwrap(), Locale::En).contents
+        else {
+            panic!("expected markdown hover");
+        };
+        assert!(content.value.contains("TAU = 6.283185307179586"));
+        assert!(content.value.contains("The circle constant"));
+
+        assert_eq!(lookup("CLAMP").unwrap().arity(), (3, Some(3)));
+        assert!(is_builtin_name("NAN") && is_builtin_name("GCD"));
+    }
+
+    #[test]
+    fn test_lookup_does_not_allocate() {
+        let names = ["MAP", "println", "Substring", "PI", "not_a_builtin", "Gcd"];
+        // Build the registry before counting
+        assert!(lookup("MAP").is_some());
+
+        let before = test_alloc::stats().allocations;
+        let mut hits = 0;
+        for _ in 0..10_000 {
+            for name in names {
+                hits += usize::from(lookup(name).is_some());
+                hits += usize::from(lookup_constant(name).is_some());
+            }
+        }
+        assert_eq!(test_alloc::stats().allocations, before, "lookup allocated");
+        assert_eq!(hits, 5 * 10_000);
+    }
+
+    #[test]
+    fn test_category_queries() {
+        let dict: Vec<&str> = by_category("Dict").map(|func| func.name).collect();
+        assert!(dict.contains(&"KEYS") && dict.contains(&"HASKEY"));
+        assert!(by_category("dict").all(|func| func.category == "Dict"));
+        assert_eq!(by_category("Dict").count(), dict.len());
+
+        for func in all() {
+            assert!(by_category(func.category).any(|other| other.name == func.name));
+        }
+        assert_eq!(by_category("Nope").count(), 0);
+    }
+}
@@ -0,0 +1,490 @@
+//! Call graph and call hierarchy support
+//!
+//! Calls are attributed to the innermost enclosing `Func`/`Generator`
+//! definition; calls outside any definition come from a synthetic `<module>`
+//! item. Only calls whose callee names a user-defined function are recorded.
+//! A name is looked up from the caller outwards, so a function nested in the
+//! caller or one of its enclosing functions wins over a top-level one; among
+//! definitions in the same scope, the last one before the call is taken.
+
+use std::collections::HashSet;
+
+use crate::ast::{Expr, ExprKind, Program, Stmt, StmtKind, span_contains};
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+use lsp_types::*;
+
+/// Name of the synthetic item representing top-level code
+#[cfg(feature = "server")]
+pub const MODULE_ITEM_NAME: &str = "<module>";
+
+#[derive(Debug, Clone)]
+struct FunctionNode {
+    name: String,
+    /// Index of the function this one is defined in, `None` at top level
+    parent: Option<usize>,
+    range: Range,
+    #[cfg(feature = "server")]
+    selection_range: Range,
+}
+
+#[derive(Debug, Clone)]
+struct CallSite {
+    /// Index of the calling function, `None` for top-level code
+    caller: Option<usize>,
+    callee: usize,
+    range: Range,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    functions: Vec<FunctionNode>,
+    calls: Vec<CallSite>,
+    module_range: Range,
+}
+
+impl CallGraph {
+    pub fn build(program: &Program) -> Self {
+        let mut graph = CallGraph::default();
+        if let (Some(first), Some(last)) = (program.first(), program.last()) {
+            graph.module_range = Range {
+                start: first.span.start,
+                end: last.span.end,
+            };
+        }
+
+        let mut functions = FunctionCollector::default();
+        functions.visit_block(program);
+        graph.functions = functions.functions;
+
+        let mut collector = CallCollector {
+            functions: &graph.functions,
+            next_function: 0,
+            caller: None,
+            calls: Vec::new(),
+        };
+        collector.visit_block(program);
+        graph.calls = collector.calls;
+        graph
+    }
+
+    /// The innermost function whose definition contains `position`; `None` at top level
+    pub fn enclosing_function(&self, position: Position) -> Option<&str> {
+        self.functions
+            .iter()
+            .rev()
+            .find(|function| span_contains(function.range, position))
+            .map(|function| function.name.as_str())
+    }
+
+    /// Names of the functions called at a site `from` accepts, and of every function
+    /// those call in turn
+    pub fn reachable_from(&self, from: impl Fn(Range) -> bool) -> HashSet<&str> {
+        let mut reached: HashSet<usize> = HashSet::new();
+        let mut pending: Vec<usize> = self
+            .calls
+            .iter()
+            .filter(|call| from(call.range))
+            .map(|call| call.callee)
+            .collect();
+        while let Some(index) = pending.pop() {
+            if reached.insert(index) {
+                pending.extend(
+                    self.calls
+                        .iter()
+                        .filter(|call| call.caller == Some(index))
+                        .map(|call| call.callee),
+                );
+            }
+        }
+        reached
+            .into_iter()
+            .map(|index| self.functions[index].name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(feature = "server")]
+impl CallGraph {
+    /// The item for the function whose name or call site is under `position`
+    pub fn prepare(&self, uri: &Url, position: Position) -> Option<CallHierarchyItem> {
+        let index = self
+            .functions
+            .iter()
+            .position(|function| span_contains(function.selection_range, position))
+            .or_else(|| {
+                self.calls
+                    .iter()
+                    .find(|call| span_contains(call.range, position))
+                    .map(|call| call.callee)
+            })?;
+        Some(self.item(uri, Some(index)))
+    }
+
+    /// Callers of `item`, each with the ranges of its calls into `item`
+    pub fn incoming_calls(
+        &self,
+        uri: &Url,
+        item: &CallHierarchyItem,
+    ) -> Vec<CallHierarchyIncomingCall> {
+        let Some(Some(callee)) = self.resolve(item) else {
+            return Vec::new();
+        };
+
+        let mut callers: Vec<(Option<usize>, Vec<Range>)> = Vec::new();
+        for call in self.calls.iter().filter(|call| call.callee == callee) {
+            match callers
+                .iter_mut()
+                .find(|(caller, _)| *caller == call.caller)
+            {
+                Some((_, ranges)) => ranges.push(call.range),
+                None => callers.push((call.caller, vec![call.range])),
+            }
+        }
+
+        callers
+            .into_iter()
+            .map(|(caller, from_ranges)| CallHierarchyIncomingCall {
+                from: self.item(uri, caller),
+                from_ranges,
+            })
+            .collect()
+    }
+
+    /// Functions called from `item`, each with the ranges of the calls
+    pub fn outgoing_calls(
+        &self,
+        uri: &Url,
+        item: &CallHierarchyItem,
+    ) -> Vec<CallHierarchyOutgoingCall> {
+        let Some(caller) = self.resolve(item) else {
+            return Vec::new();
+        };
+
+        let mut callees: Vec<(usize, Vec<Range>)> = Vec::new();
+        for call in self.calls.iter().filter(|call| call.caller == caller) {
+            match callees
+                .iter_mut()
+                .find(|(callee, _)| *callee == call.callee)
+            {
+                Some((_, ranges)) => ranges.push(call.range),
+                None => callees.push((call.callee, vec![call.range])),
+            }
+        }
+
+        callees
+            .into_iter()
+            .map(|(callee, from_ranges)| CallHierarchyOutgoingCall {
+                to: self.item(uri, Some(callee)),
+                from_ranges,
+            })
+            .collect()
+    }
+
+    /// Map an item back to a function index; `Some(None)` is the module item
+    fn resolve(&self, item: &CallHierarchyItem) -> Option<Option<usize>> {
+        if item.name == MODULE_ITEM_NAME {
+            return Some(None);
+        }
+        // Same-name functions are told apart by where they are defined
+        self.functions
+            .iter()
+            .position(|function| {
+                function.name == item.name && function.selection_range == item.selection_range
+            })
+            .map(Some)
+    }
+
+    fn item(&self, uri: &Url, index: Option<usize>) -> CallHierarchyItem {
+        match index {
+            Some(index) => {
+                let function = &self.functions[index];
+                CallHierarchyItem {
+                    name: function.name.clone(),
+                    kind: SymbolKind::FUNCTION,
+                    tags: None,
+                    detail: None,
+                    uri: uri.clone(),
+                    range: function.range,
+                    selection_range: function.selection_range,
+                    data: None,
+                }
+            }
+            None => CallHierarchyItem {
+                name: MODULE_ITEM_NAME.to_string(),
+                kind: SymbolKind::MODULE,
+                tags: None,
+                detail: None,
+                uri: uri.clone(),
+                range: self.module_range,
+                selection_range: Range {
+                    start: self.module_range.start,
+                    end: self.module_range.start,
+                },
+                data: None,
+            },
+        }
+    }
+}
+
+/// Records every `Func`/`Generator` definition, including nested ones, in
+/// the order a [`Visitor`] meets them
+#[derive(Default)]
+struct FunctionCollector {
+    functions: Vec<FunctionNode>,
+    /// The function whose body is being walked, `None` at top level
+    parent: Option<usize>,
+}
+
+impl<'a> Visitor<'a> for FunctionCollector {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::FuncDef { name, .. } | StmtKind::GeneratorDef { name, .. } => {
+                self.functions.push(FunctionNode {
+                    name: name.name.to_string(),
+                    parent: self.parent,
+                    range: stmt.span,
+                    #[cfg(feature = "server")]
+                    selection_range: name.span,
+                });
+                let outer = self.parent;
+                self.parent = Some(self.functions.len() - 1);
+                walk_stmt(self, stmt);
+                self.parent = outer;
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+}
+
+/// Records calls to known functions; calls made from a lambda belong to the
+/// function it is written in
+struct CallCollector<'a> {
+    functions: &'a [FunctionNode],
+    /// Definitions are met in the order [`FunctionCollector`] recorded them,
+    /// so the next one met has this index
+    next_function: usize,
+    /// The function whose body is being walked, `None` at top level
+    caller: Option<usize>,
+    calls: Vec<CallSite>,
+}
+
+impl CallCollector<'_> {
+    /// The function a call to `name` at `at` reaches from the current caller
+    fn callee(&self, name: &str, at: Position) -> Option<usize> {
+        let mut scope = self.caller;
+        loop {
+            let defined_here = || {
+                self.functions
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, function)| function.parent == scope && function.name == name)
+                    .map(|(index, _)| index)
+            };
+            // A call before every definition, e.g. in a function defined
+            // earlier, still reaches the first one
+            let found = defined_here()
+                .rev()
+                .find(|&index| self.functions[index].range.start <= at)
+                .or_else(|| defined_here().next());
+            if found.is_some() {
+                return found;
+            }
+            scope = self.functions[scope?].parent;
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for CallCollector<'_> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::FuncDef { .. } | StmtKind::GeneratorDef { .. } => {
+                let outer = self.caller;
+                self.caller = Some(self.next_function);
+                self.next_function += 1;
+                walk_stmt(self, stmt);
+                self.caller = outer;
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Call { func, .. } = &expr.kind
+            && let ExprKind::Identifier(name) = &func.kind
+            && let Some(callee) = self.callee(name.as_str(), func.span.start)
+        {
+            self.calls.push(CallSite {
+                caller: self.caller,
+                callee,
+                range: func.span,
+            });
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const DIAMOND: &str = "Func TOP(X) {
+    Return LEFT(X) + RIGHT(X)
+}
+
+Func LEFT(X) {
+    Return BOTTOM(X - 1)
+}
+
+Func RIGHT(X) {
+    Return BOTTOM(X + 1) * BOTTOM(X)
+}
+
+Func BOTTOM(X) {
+    Return X * 2
+}
+
+PRINTLN(TOP(1))";
+
+    fn graph() -> (CallGraph, Url) {
+        let doc = Parser::new(DIAMOND).parse();
+        assert!(doc.errors.is_empty());
+        (
+            CallGraph::build(&doc.ast),
+            Url::parse("file:///diamond.ae").unwrap(),
+        )
+    }
+
+    #[cfg(feature = "server")]
+    fn item(graph: &CallGraph, uri: &Url, line: u32, character: u32) -> CallHierarchyItem {
+        graph
+            .prepare(uri, Position { line, character })
+            .expect("function under cursor")
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_prepare_on_definition_and_call_site() {
+        let (graph, uri) = graph();
+        let bottom = item(&graph, &uri, 12, 6);
+        assert_eq!(bottom.name, "BOTTOM");
+        assert_eq!(bottom.selection_range.start, Position::new(12, 5));
+        assert_eq!(bottom.range.start, Position::new(12, 0));
+
+        assert_eq!(item(&graph, &uri, 1, 13).name, "LEFT");
+        assert!(graph.prepare(&uri, Position::new(13, 11)).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_incoming_calls_in_diamond() {
+        let (graph, uri) = graph();
+        let bottom = item(&graph, &uri, 12, 6);
+        let incoming = graph.incoming_calls(&uri, &bottom);
+
+        let callers: Vec<(&str, usize)> = incoming
+            .iter()
+            .map(|call| (call.from.name.as_str(), call.from_ranges.len()))
+            .collect();
+        assert_eq!(callers, vec![("LEFT", 1), ("RIGHT", 2)]);
+        assert_eq!(
+            incoming[0].from_ranges[0],
+            Range::new(Position::new(5, 11), Position::new(5, 17))
+        );
+
+        let top = item(&graph, &uri, 0, 6);
+        let incoming = graph.incoming_calls(&uri, &top);
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].from.name, MODULE_ITEM_NAME);
+        assert_eq!(incoming[0].from.kind, SymbolKind::MODULE);
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_outgoing_calls_in_diamond() {
+        let (graph, uri) = graph();
+        let top = item(&graph, &uri, 0, 6);
+        let callees: Vec<String> = graph
+            .outgoing_calls(&uri, &top)
+            .into_iter()
+            .map(|call| call.to.name)
+            .collect();
+        assert_eq!(callees, vec!["LEFT", "RIGHT"]);
+
+        let bottom = item(&graph, &uri, 12, 6);
+        assert!(graph.outgoing_calls(&uri, &bottom).is_empty());
+
+        let module = graph.incoming_calls(&uri, &top).remove(0).from;
+        let from_module: Vec<String> = graph
+            .outgoing_calls(&uri, &module)
+            .into_iter()
+            .map(|call| call.to.name)
+            .collect();
+        assert_eq!(from_module, vec!["TOP"]);
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_same_name_functions_stay_apart() {
+        let text = "Func STEP() {
+    Return 1
+}
+Func FIRST() {
+    Return STEP()
+}
+Func STEP() {
+    Return 2
+}
+Func OUTER() {
+    Func STEP() {
+        Return 3
+    }
+    Return STEP()
+}
+PRINTLN(STEP(), FIRST(), OUTER())";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let graph = CallGraph::build(&doc.ast);
+        let uri = Url::parse("file:///steps.ae").unwrap();
+        let callers = |line, character| -> Vec<String> {
+            graph
+                .incoming_calls(&uri, &item(&graph, &uri, line, character))
+                .into_iter()
+                .map(|call| call.from.name)
+                .collect()
+        };
+
+        // The redefinition replaces the first STEP for calls after it
+        assert_eq!(callers(0, 6), vec!["FIRST"]);
+        assert_eq!(callers(6, 6), vec![MODULE_ITEM_NAME]);
+        // A nested STEP is only reached from the function it is defined in
+        assert_eq!(callers(10, 10), vec!["OUTER"]);
+
+        let outer = item(&graph, &uri, 9, 6);
+        let callees: Vec<u32> = graph
+            .outgoing_calls(&uri, &outer)
+            .into_iter()
+            .map(|call| call.to.selection_range.start.line)
+            .collect();
+        assert_eq!(callees, vec![10]);
+    }
+
+    #[test]
+    fn test_reachable_from_and_enclosing_function() {
+        let (graph, _) = graph();
+        // From the calls on RIGHT's body line
+        let mut reached: Vec<&str> = graph
+            .reachable_from(|range| range.start.line == 9)
+            .into_iter()
+            .collect();
+        reached.sort();
+        assert_eq!(reached, vec!["BOTTOM"]);
+        assert_eq!(
+            graph.reachable_from(|range| range.start.line == 16).len(),
+            4
+        );
+
+        assert_eq!(graph.enclosing_function(Position::new(5, 4)), Some("LEFT"));
+        assert_eq!(graph.enclosing_function(Position::new(16, 3)), None);
+    }
+}
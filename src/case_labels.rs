@@ -0,0 +1,433 @@
+//! Completion of `Case` labels
+//!
+//! After `Case ` inside `Switch (STATUS) { ... }` the useful candidates are the
+//! values `STATUS` can hold: the literals it is `Set` to, compared with (`==`,
+//! `!=`) or matched against in another `Switch` anywhere in the file. Labels
+//! the switch already has are left out. When nothing is known about the
+//! switched value, the file's ALL_CAPS variables stand in for an enum.
+
+use lsp_types::*;
+
+use crate::ast::{BinOp, Expr, ExprKind, Program, Stmt, StmtKind, UnaryOp};
+use crate::lexer::Lexer;
+use crate::parser::ParsedDocument;
+use crate::symbols::SymbolFlavor;
+use crate::token::Token;
+use crate::types::{Types, ValueType};
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+
+/// A literal a `Case` can match
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaseValue {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl CaseValue {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match &expr.kind {
+            ExprKind::String(value) => Some(CaseValue::String(value.clone())),
+            ExprKind::Number(value) => Some(CaseValue::Number(*value)),
+            ExprKind::Boolean(value) => Some(CaseValue::Boolean(*value)),
+            ExprKind::Unary {
+                op: UnaryOp::Minus,
+                expr,
+            } => match expr.kind {
+                ExprKind::Number(value) => Some(CaseValue::Number(-value)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The value as it is written in source
+    pub fn source(&self) -> String {
+        match self {
+            CaseValue::String(value) => format!(
+                "\"{}\"",
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            ),
+            CaseValue::Number(value) => value.to_string(),
+            CaseValue::Boolean(true) => "True".to_string(),
+            CaseValue::Boolean(false) => "False".to_string(),
+        }
+    }
+
+    fn value_type(&self) -> ValueType {
+        match self {
+            CaseValue::String(_) => ValueType::String,
+            CaseValue::Number(_) => ValueType::Number,
+            CaseValue::Boolean(_) => ValueType::Boolean,
+        }
+    }
+}
+
+/// The cursor right after `Case ` in the body of a `Switch`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseContext {
+    /// The switched variable, when the switch is on a bare name
+    pub scrutinee: Option<String>,
+    /// Labels the switch already has
+    pub existing: Vec<CaseValue>,
+    pub has_default: bool,
+    /// Where the `Case` keyword starts, so `Default:` can replace it
+    pub keyword: Position,
+    /// Whether the line already goes on with the `:`
+    pub colon_follows: bool,
+}
+
+/// Recognize `Case |` or `Case PARTIAL|` directly inside a `Switch` body
+pub fn case_context(text: &str, position: Position) -> Option<CaseContext> {
+    let lines: Vec<&str> = text.lines().collect();
+    let line = lines.get(position.line as usize)?;
+    let prefix: String = line.chars().take(position.character as usize).collect();
+    let before_word = prefix.trim_end_matches(|ch: char| ch.is_alphanumeric() || ch == '_');
+    if !before_word.ends_with(char::is_whitespace) || before_word.trim() != "Case" {
+        return None;
+    }
+    let indent = before_word.len() - before_word.trim_start().len();
+    let keyword = Position::new(position.line, before_word[..indent].chars().count() as u32);
+    let suffix: String = line.chars().skip(position.character as usize).collect();
+    let colon_follows = suffix
+        .trim_start_matches(|ch: char| ch.is_alphanumeric() || ch == '_')
+        .trim_start()
+        .starts_with(':');
+
+    let frame = enclosing_block(text, keyword)?;
+    let Block::Switch {
+        scrutinee,
+        existing,
+        has_default,
+    } = frame
+    else {
+        return None;
+    };
+    Some(CaseContext {
+        scrutinee,
+        existing,
+        has_default,
+        keyword,
+        colon_follows,
+    })
+}
+
+/// A `{ ... }` block
+enum Block {
+    Other,
+    Switch {
+        scrutinee: Option<String>,
+        existing: Vec<CaseValue>,
+        has_default: bool,
+    },
+}
+
+/// Where a `Switch (...) {` header is in being read
+enum Header {
+    None,
+    Keyword,
+    Scrutinee { depth: usize, tokens: Vec<Token> },
+    Closed(Vec<Token>),
+}
+
+/// The innermost block around `at`, with everything it holds up to its `}`
+fn enclosing_block(text: &str, at: Position) -> Option<Block> {
+    let mut lexer = Lexer::new(text);
+    let mut blocks: Vec<Block> = Vec::new();
+    // How many blocks were open at `at`, once the lexer gets there
+    let mut depth: Option<usize> = None;
+    let mut header = Header::None;
+    // The two tokens before the current one, newlines skipped
+    let mut previous: [Option<Token>; 2] = [None, None];
+    loop {
+        let token = lexer.next_token();
+        if depth.is_none() && lexer.token_span().start >= at {
+            depth = Some(blocks.len());
+        }
+        match token {
+            Token::EOF => break,
+            Token::Newline => continue,
+            _ => {}
+        }
+        header = match (header, &token) {
+            (_, Token::Switch) => Header::Keyword,
+            (Header::Keyword, Token::LeftParen) => Header::Scrutinee {
+                depth: 0,
+                tokens: Vec::new(),
+            },
+            (Header::Scrutinee { depth: 0, tokens }, Token::RightParen) => Header::Closed(tokens),
+            (Header::Scrutinee { depth, mut tokens }, _) => {
+                let depth = match token {
+                    Token::LeftParen => depth + 1,
+                    Token::RightParen => depth - 1,
+                    _ => depth,
+                };
+                tokens.push(token.clone());
+                Header::Scrutinee { depth, tokens }
+            }
+            (Header::Closed(tokens), Token::LeftBrace) => {
+                blocks.push(Block::Switch {
+                    scrutinee: match tokens.as_slice() {
+                        [Token::Identifier(name)] => Some(name.to_string()),
+                        _ => None,
+                    },
+                    existing: Vec::new(),
+                    has_default: false,
+                });
+                header = Header::None;
+                previous = [None, Some(token)];
+                continue;
+            }
+            _ => Header::None,
+        };
+        match &token {
+            Token::LeftBrace => blocks.push(Block::Other),
+            Token::RightBrace => {
+                let closed = blocks.pop();
+                if depth.is_some_and(|depth| blocks.len() + 1 == depth) {
+                    return closed;
+                }
+            }
+            _ => {}
+        }
+        if let Some(Block::Switch {
+            existing,
+            has_default,
+            ..
+        }) = blocks.last_mut()
+        {
+            let label = match (&previous, &token) {
+                ([_, Some(Token::Case)], Token::String(value)) => {
+                    Some(CaseValue::String(value.clone()))
+                }
+                ([_, Some(Token::Case)], Token::Number(value)) => Some(CaseValue::Number(*value)),
+                ([_, Some(Token::Case)], Token::Boolean(value)) => Some(CaseValue::Boolean(*value)),
+                ([Some(Token::Case), Some(Token::Minus)], Token::Number(value)) => {
+                    Some(CaseValue::Number(-value))
+                }
+                _ => None,
+            };
+            existing.extend(label);
+            *has_default |= token == Token::Default;
+        }
+        previous = [previous[1].take(), Some(token)];
+    }
+    // Unclosed at the end of the file
+    let depth = depth?;
+    (depth > 0).then(|| blocks.swap_remove(depth - 1))
+}
+
+/// The literals `name` is set to, compared with or matched against, in order
+pub fn known_values(program: &Program, name: &str) -> Vec<CaseValue> {
+    let mut collector = ValueCollector {
+        name,
+        values: Vec::new(),
+    };
+    collector.visit_block(program);
+    let types = Types::new(program);
+    let known = types.of(&Expr {
+        kind: ExprKind::Identifier(name.into()),
+        span: Default::default(),
+    });
+    let mut values: Vec<CaseValue> = Vec::new();
+    for value in collector.values {
+        if (known == ValueType::Unknown || value.value_type() == known) && !values.contains(&value)
+        {
+            values.push(value);
+        }
+    }
+    values
+}
+
+struct ValueCollector<'n> {
+    name: &'n str,
+    values: Vec<CaseValue>,
+}
+
+impl ValueCollector<'_> {
+    fn is_name(&self, expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Identifier(name) if name.as_str() == self.name)
+    }
+}
+
+impl<'a> Visitor<'a> for ValueCollector<'_> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, value } if name.as_str() == self.name => {
+                self.values.extend(CaseValue::from_expr(value));
+            }
+            StmtKind::Switch { expr, cases, .. } if self.is_name(expr) => {
+                self.values.extend(
+                    cases
+                        .iter()
+                        .filter_map(|(label, _)| CaseValue::from_expr(label)),
+                );
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Binary {
+            left,
+            op: BinOp::Equal | BinOp::NotEqual,
+            right,
+        } = &expr.kind
+        {
+            if self.is_name(left) {
+                self.values.extend(CaseValue::from_expr(right));
+            } else if self.is_name(right) {
+                self.values.extend(CaseValue::from_expr(left));
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// The label candidates for `context`: the switched value's known literals, or
+/// else the file's ALL_CAPS variables; then `Default:` if the switch has none
+pub fn case_completions(
+    doc: &ParsedDocument,
+    context: &CaseContext,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let colon = if context.colon_follows { "" } else { ":" };
+    let values: Vec<CaseValue> = context
+        .scrutinee
+        .as_deref()
+        .map(|name| known_values(&doc.ast, name))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|value| !context.existing.contains(value))
+        .collect();
+
+    let mut items: Vec<CompletionItem> = if values.is_empty() {
+        doc.symbols
+            .variables
+            .iter()
+            .filter(|var| var.flavor != SymbolFlavor::Lambda)
+            .filter(|var| Some(var.name.as_str()) != context.scrutinee.as_deref())
+            .filter(|var| is_all_caps(&var.name))
+            .map(|var| CompletionItem {
+                label: var.name.to_string(),
+                kind: Some(CompletionItemKind::CONSTANT),
+                detail: var
+                    .detail
+                    .clone()
+                    .or_else(|| Some(format!("Variable: {}", var.name))),
+                insert_text: Some(format!("{}{}", var.name, colon)),
+                insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                ..Default::default()
+            })
+            .collect()
+    } else {
+        let name = context.scrutinee.as_deref().unwrap_or_default();
+        values
+            .iter()
+            .map(|value| {
+                let source = value.source();
+                CompletionItem {
+                    label: source.clone(),
+                    kind: Some(CompletionItemKind::VALUE),
+                    detail: Some(format!("Value of {}", name)),
+                    filter_text: Some(source.clone()),
+                    insert_text: Some(format!("{}{}", source, colon)),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    };
+    for (index, item) in items.iter_mut().enumerate() {
+        item.sort_text = Some(format!("0_{:04}", index));
+    }
+
+    if !context.has_default {
+        items.push(CompletionItem {
+            label: "Default:".to_string(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some("Default branch".to_string()),
+            sort_text: Some("1_Default".to_string()),
+            // The edit starts at `Case`, so filter on the text it replaces
+            filter_text: Some("Case Default:".to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: Range::new(context.keyword, position),
+                new_text: format!("Default{}\n\t$0", colon),
+            })),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        });
+    }
+    items
+}
+
+fn is_all_caps(name: &str) -> bool {
+    name.chars().any(|ch| ch.is_alphabetic())
+        && name
+            .chars()
+            .all(|ch| ch.is_uppercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn context(text: &str, position: Position) -> Option<CaseContext> {
+        case_context(text, position)
+    }
+
+    #[test]
+    fn test_case_context() {
+        let text =
+            "Switch (STATUS) {\n    Case \"done\":\n        Break\n    Case -1:\n    Case \n}";
+        let found = context(text, Position::new(4, 9)).unwrap();
+        assert_eq!(found.scrutinee.as_deref(), Some("STATUS"));
+        assert_eq!(
+            found.existing,
+            vec![CaseValue::String("done".into()), CaseValue::Number(-1.0)]
+        );
+        assert!(!found.has_default);
+        assert_eq!(found.keyword, Position::new(4, 4));
+        assert!(!found.colon_follows);
+
+        // A partial word, and a switch on something other than a name
+        let text = "Switch (ITEMS[0]) {\n    Case PE:\n}";
+        let found = context(text, Position::new(1, 11)).unwrap();
+        assert_eq!(found.scrutinee, None);
+        assert!(found.colon_follows);
+
+        // Not in a switch body: nested in a case's own block, or after it closed
+        let text = "Switch (X) {\n    Case 1:\n        If (Y) {\n            Case \n        }\n}";
+        assert_eq!(context(text, Position::new(3, 17)), None);
+        assert_eq!(context("Switch (X) {\n}\nCase ", Position::new(2, 5)), None);
+        assert_eq!(context("Switch (X) {\n    Case", Position::new(1, 8)), None);
+    }
+
+    #[test]
+    fn test_known_values() {
+        let doc = Parser::new(
+            "Set STATUS \"pending\"\nIf (READY) {\n    Set STATUS \"done\"\n}\n\
+             If (STATUS != \"failed\") {\n    Set STATUS LOAD()\n}\n\
+             Switch (STATUS) {\n    Case \"done\":\n        Break\n    Case 3:\n        Break\n}",
+        )
+        .parse();
+        let values: Vec<String> = known_values(&doc.ast, "STATUS")
+            .iter()
+            .map(CaseValue::source)
+            .collect();
+        // STATUS isn't always a String, so the number stays
+        assert_eq!(values, vec!["\"pending\"", "\"done\"", "\"failed\"", "3"]);
+
+        let doc = Parser::new("Set MODE \"a\"\nSet MODE \"b\"\nPRINTLN(MODE == 1)").parse();
+        let values: Vec<String> = known_values(&doc.ast, "MODE")
+            .iter()
+            .map(CaseValue::source)
+            .collect();
+        assert_eq!(values, vec!["\"a\"", "\"b\""]);
+    }
+}
@@ -0,0 +1,228 @@
+//! REPL buffers: independent cells separated by `// ---` lines
+//!
+//! The companion extension sends the REPL history as one `aether-repl:`
+//! document, each cell a snippet the user ran. Parsed as one program, an
+//! unclosed brace in one cell would swallow all the later ones, so every cell
+//! is parsed on its own and the results are merged into one
+//! [`ParsedDocument`]. The other cells' lines are blanked rather than cut out,
+//! so positions come out in buffer coordinates without any shifting. A later
+//! cell sees an earlier cell's definitions like any later statement does;
+//! completion leaves out the definitions of later cells.
+
+use lsp_types::{Position, Url};
+
+use crate::ast::{Comment, Program, Span};
+use crate::line_index::LineIndex;
+use crate::mask::Mask;
+use crate::occurrences;
+use crate::parser::ParsedDocument;
+use crate::symbols::SymbolTable;
+
+/// The scheme of the REPL buffers, always split into cells
+pub const REPL_SCHEME: &str = "aether-repl";
+
+/// The line separating two cells; more dashes may follow, or text after a
+/// space, like `// --- 3`
+pub const CELL_MARKER: &str = "// ---";
+
+/// Lines `start..end` of a buffer, the cell between two markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Whether the document at `uri` is split into cells: REPL buffers always,
+/// others when `split_all` (the `analysis.cells` setting) asks for it
+pub fn applies(uri: &Url, split_all: bool) -> bool {
+    split_all || uri.scheme() == REPL_SCHEME
+}
+
+fn is_marker(line: &str) -> bool {
+    line.trim().strip_prefix(CELL_MARKER).is_some_and(|rest| {
+        rest.is_empty() || rest.starts_with(|ch: char| ch == '-' || ch.is_whitespace())
+    })
+}
+
+/// The cells of `text`, in order; one covering everything without markers
+pub fn split(text: &str) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut line_count = 0;
+    for (line, content) in text.split('\n').enumerate() {
+        let line = line as u32;
+        if is_marker(content) {
+            cells.push(Cell { start, end: line });
+            start = line + 1;
+        }
+        line_count = line + 1;
+    }
+    cells.push(Cell {
+        start,
+        end: line_count,
+    });
+    cells
+}
+
+/// Parse each cell of `text` with `parse` and merge the results
+pub fn parse(text: &str, parse: impl Fn(&str) -> ParsedDocument) -> ParsedDocument {
+    let cells = split(text);
+    if cells.len() == 1 {
+        return parse(text);
+    }
+    let docs: Vec<ParsedDocument> = cells
+        .iter()
+        .map(|cell| parse(&isolate(text, *cell)))
+        .collect();
+    merge(text, docs)
+}
+
+/// `text` with every line outside `cell` emptied, line breaks kept
+fn isolate(text: &str, cell: Cell) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(line, content)| {
+            if (cell.start..cell.end).contains(&(line as u32)) {
+                content
+            } else {
+                ""
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One document for the whole buffer from the documents of its cells, in order
+fn merge(text: &str, docs: Vec<ParsedDocument>) -> ParsedDocument {
+    let mut statements = Vec::new();
+    let mut comments = Vec::new();
+    let mut merged = ParsedDocument::default();
+    let mut masks = vec![marker_mask(text)];
+    for (index, doc) in docs.into_iter().enumerate() {
+        statements.extend_from_slice(doc.ast.statements());
+        comments.extend_from_slice(doc.ast.comments());
+        masks.push(doc.mask);
+        merged.errors.extend(doc.errors);
+        merged.naming_issues.extend(doc.naming_issues);
+        merged.keyword_casings.extend(doc.keyword_casings);
+        merged.stray_assigns.extend(doc.stray_assigns);
+        // Only the first cell can start with a header
+        if index == 0 {
+            merged.metadata = doc.metadata;
+        }
+    }
+    let ast = Program::new(statements, comments);
+    let line_index = LineIndex::new(text);
+    ParsedDocument {
+        text: text.to_string(),
+        mask: Mask::merged(masks),
+        symbols: SymbolTable::from_ast(&ast, text, &line_index),
+        occurrences: occurrences::collect(&ast),
+        line_index,
+        ast,
+        ..merged
+    }
+}
+
+/// The marker lines are comments too, though no cell holds them
+fn marker_mask(text: &str) -> Mask {
+    let markers: Vec<Comment> = text
+        .split('\n')
+        .enumerate()
+        .filter(|(_, content)| is_marker(content))
+        .map(|(line, content)| {
+            let line = line as u32;
+            let indent = (content.len() - content.trim_start().len()) as u32;
+            let end = content.trim_end().chars().count() as u32;
+            Comment {
+                text: content.trim().to_string(),
+                span: Span {
+                    start: Position::new(line, indent),
+                    end: Position::new(line, end),
+                },
+                block: false,
+                trailing: false,
+            }
+        })
+        .collect();
+    Mask::new(&[], &markers)
+}
+
+/// `doc` as seen from `position`: without the definitions of the cells after
+/// the one holding it. `None` when no cell follows.
+pub fn without_later_cells(doc: &ParsedDocument, position: Position) -> Option<ParsedDocument> {
+    let cells = split(&doc.text);
+    let cell = cells
+        .iter()
+        .find(|cell| position.line < cell.end)
+        .filter(|cell| cell.end < cells.last().map_or(0, |last| last.start))?;
+    let visible: Vec<_> = doc
+        .ast
+        .statements()
+        .iter()
+        .filter(|stmt| stmt.span.start.line < cell.end)
+        .cloned()
+        .collect();
+    let ast = Program::new(visible, doc.ast.comments().to_vec());
+    Some(ParsedDocument {
+        symbols: SymbolTable::from_ast(&ast, &doc.text, &doc.line_index),
+        occurrences: occurrences::collect(&ast),
+        ast,
+        ..doc.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const BUFFER: &str = "Func DOUBLE(N) {\n    Return N * 2\n}\n// ---\nSet BROKEN (1 +\n// --- 3\nSet RESULT DOUBLE(21)\nPRINTLN(RESULT)";
+
+    fn parsed(text: &str) -> ParsedDocument {
+        parse(text, |cell| Parser::new(cell).parse())
+    }
+
+    #[test]
+    fn test_split_on_marker_lines() {
+        assert_eq!(
+            split(BUFFER),
+            vec![
+                Cell { start: 0, end: 3 },
+                Cell { start: 4, end: 5 },
+                Cell { start: 6, end: 8 }
+            ]
+        );
+        assert_eq!(
+            split("Set X 1\n// ---- \n"),
+            vec![Cell { start: 0, end: 1 }, Cell { start: 2, end: 3 }]
+        );
+        // Not markers: other text right after the dashes, or code before them
+        assert_eq!(
+            split("// ---x\nSet X 1 // ---"),
+            vec![Cell { start: 0, end: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_cells_parse_independently() {
+        let doc = parsed(BUFFER);
+        // The unclosed parenthesis stays in cell 2
+        assert_eq!(doc.errors.len(), 1, "{:?}", doc.errors);
+        assert_eq!(doc.errors[0].span.start.line, 4);
+        let statements: Vec<u32> = doc.ast.iter().map(|stmt| stmt.span.start.line).collect();
+        assert_eq!(statements, vec![0, 6, 7]);
+        assert!(doc.symbols.find_by_name("RESULT").is_some());
+        assert!(doc.mask.contains(Position::new(3, 4)));
+        assert!(!doc.mask.contains(Position::new(6, 4)));
+    }
+
+    #[test]
+    fn test_later_cells_are_hidden() {
+        let doc = parsed(BUFFER);
+        let seen = without_later_cells(&doc, Position::new(1, 4)).unwrap();
+        assert!(seen.symbols.find_by_name("DOUBLE").is_some());
+        assert!(seen.symbols.find_by_name("RESULT").is_none());
+        assert!(without_later_cells(&doc, Position::new(7, 0)).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! What the client said it can handle in `initialize`
+//!
+//! Providers build the richest form of a response: Markdown documentation,
+//! snippets, related information. [`ClientCaps`] downgrades a response just
+//! before it is sent to a client that didn't announce support for those.
+
+use std::collections::HashMap;
+
+use lsp_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientCaps {
+    /// Accepts `window/workDoneProgress/create`
+    pub work_done_progress: bool,
+    /// Accepts a dynamically registered file watcher
+    pub watch_registration: bool,
+    /// Pulls diagnostics; pushing them as well would report twice
+    pub pull_diagnostics: bool,
+    /// Accepts `workspace/diagnostic/refresh`
+    pub diagnostic_refresh: bool,
+    /// Renders nested `DocumentSymbol`s, which carry a detail
+    pub hierarchical_symbols: bool,
+    pub markdown_hover: bool,
+    /// Markdown in completion item documentation
+    pub markdown_documentation: bool,
+    pub snippets: bool,
+    pub related_information: bool,
+    /// Sends `textDocument/prepareRename` before renaming
+    pub prepare_rename: bool,
+    /// Accepts `window/showDocument`
+    pub show_document: bool,
+    /// Asks the user for the new key before running `aether.renameDictKey`,
+    /// announced as the experimental `renameDictKeyPrompt` capability
+    pub dict_key_prompt: bool,
+}
+
+impl Default for ClientCaps {
+    /// A client that announced nothing
+    fn default() -> Self {
+        ClientCaps::from_capabilities(&ClientCapabilities::default())
+    }
+}
+
+impl ClientCaps {
+    /// A client that doesn't list content formats is assumed to render Markdown,
+    /// which every mainstream editor does; other features must be announced
+    pub fn from_capabilities(capabilities: &ClientCapabilities) -> Self {
+        let text_document = capabilities.text_document.as_ref();
+        let workspace = capabilities.workspace.as_ref();
+        let completion_item = text_document
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref());
+        let accepts_markdown = |formats: Option<&Vec<MarkupKind>>| {
+            formats.is_none_or(|formats| formats.contains(&MarkupKind::Markdown))
+        };
+
+        ClientCaps {
+            work_done_progress: capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false),
+            watch_registration: workspace
+                .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+                .and_then(|watched| watched.dynamic_registration)
+                .unwrap_or(false),
+            pull_diagnostics: text_document
+                .is_some_and(|text_document| text_document.diagnostic.is_some()),
+            diagnostic_refresh: workspace
+                .and_then(|workspace| workspace.diagnostic.as_ref())
+                .and_then(|diagnostic| diagnostic.refresh_support)
+                .unwrap_or(false),
+            hierarchical_symbols: text_document
+                .and_then(|text_document| text_document.document_symbol.as_ref())
+                .and_then(|symbol| symbol.hierarchical_document_symbol_support)
+                .unwrap_or(false),
+            markdown_hover: accepts_markdown(
+                text_document
+                    .and_then(|text_document| text_document.hover.as_ref())
+                    .and_then(|hover| hover.content_format.as_ref()),
+            ),
+            markdown_documentation: accepts_markdown(
+                completion_item.and_then(|item| item.documentation_format.as_ref()),
+            ),
+            snippets: completion_item
+                .and_then(|item| item.snippet_support)
+                .unwrap_or(false),
+            related_information: text_document
+                .and_then(|text_document| text_document.publish_diagnostics.as_ref())
+                .and_then(|publish| publish.related_information)
+                .unwrap_or(false),
+            prepare_rename: text_document
+                .and_then(|text_document| text_document.rename.as_ref())
+                .and_then(|rename| rename.prepare_support)
+                .unwrap_or(false),
+            show_document: capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.show_document.as_ref())
+                .is_some_and(|show_document| show_document.support),
+            dict_key_prompt: capabilities
+                .experimental
+                .as_ref()
+                .and_then(|experimental| experimental.get("renameDictKeyPrompt"))
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false),
+        }
+    }
+
+    pub fn adapt_hover(&self, mut hover: Hover) -> Hover {
+        if !self.markdown_hover
+            && let HoverContents::Markup(content) = &mut hover.contents
+            && content.kind == MarkupKind::Markdown
+        {
+            content.kind = MarkupKind::PlainText;
+            content.value = markdown_to_plaintext(&content.value);
+        }
+        hover
+    }
+
+    pub fn adapt_completion(&self, response: CompletionResponse) -> CompletionResponse {
+        match response {
+            CompletionResponse::Array(mut items) => {
+                items
+                    .iter_mut()
+                    .for_each(|item| self.adapt_completion_item(item));
+                CompletionResponse::Array(items)
+            }
+            CompletionResponse::List(mut list) => {
+                list.items
+                    .iter_mut()
+                    .for_each(|item| self.adapt_completion_item(item));
+                CompletionResponse::List(list)
+            }
+        }
+    }
+
+    fn adapt_completion_item(&self, item: &mut CompletionItem) {
+        if !self.markdown_documentation
+            && let Some(Documentation::MarkupContent(content)) = &item.documentation
+            && content.kind == MarkupKind::Markdown
+        {
+            item.documentation = Some(Documentation::String(markdown_to_plaintext(&content.value)));
+        }
+        if !self.snippets && item.insert_text_format == Some(InsertTextFormat::SNIPPET) {
+            item.insert_text_format = Some(InsertTextFormat::PLAIN_TEXT);
+            if let Some(text) = &mut item.insert_text {
+                *text = snippet_to_plaintext(text);
+            }
+            if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+                edit.new_text = snippet_to_plaintext(&edit.new_text);
+            }
+        }
+    }
+
+    pub fn adapt_diagnostics(&self, mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        if !self.related_information {
+            for diagnostic in &mut diagnostics {
+                diagnostic.related_information = None;
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Drop heading marks, code fences, emphasis, inline code ticks and rules, keeping the text
+fn markdown_to_plaintext(markdown: &str) -> String {
+    markdown
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(|line| {
+            if line.trim() == "---" {
+                return String::new();
+            }
+            let line = match line.trim_start_matches('#') {
+                heading if heading.len() < line.len() => heading.trim_start(),
+                _ => line,
+            };
+            line.replace("**", "").replace('`', "")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// The text a snippet inserts with every placeholder left at its default; a
+/// tab stop repeating a placeholder gets the same default
+pub fn snippet_to_plaintext(snippet: &str) -> String {
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    let mut text = String::new();
+    // Tab stops whose placeholder may come later, with where their text goes
+    let mut mirrors: Vec<(usize, String)> = Vec::new();
+    let mut chars = snippet.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                // `${1:default}` keeps `default`, `${1}` keeps nothing of its own
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+                if chars.next_if_eq(&':').is_some() {
+                    let mut default = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != '}') {
+                        default.push(c);
+                    }
+                    text.push_str(&default);
+                    defaults.insert(number, default);
+                } else {
+                    mirrors.push((text.len(), number));
+                }
+                chars.next();
+            }
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+                mirrors.push((text.len(), number));
+            }
+            _ => text.push(c),
+        }
+    }
+    for (at, number) in mirrors.into_iter().rev() {
+        if let Some(default) = defaults.get(&number) {
+            text.insert_str(at, default);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plaintext_conversions() {
+        assert_eq!(
+            markdown_to_plaintext(
+                "## MAP\n**MAP**(arr, f)\n\n```aether\nMAP([1], F)\n```\n---\nUse `F`"
+            ),
+            "MAP\nMAP(arr, f)\n\nMAP([1], F)\n\nUse F"
+        );
+        assert_eq!(
+            snippet_to_plaintext("For ${1:ITEM} In ${2:LIST} {\n    $0\n\\}${3}"),
+            "For ITEM In LIST {\n    \n}"
+        );
+        assert_eq!(
+            snippet_to_plaintext("Set ${1:I} $2\nYield $1 + ${2:START}"),
+            "Set I START\nYield I + START"
+        );
+    }
+}
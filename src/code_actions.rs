@@ -0,0 +1,1547 @@
+//! Code actions and refactorings for Aether documents
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Expr, ExprKind, Stmt, StmtKind, span_contains};
+use crate::diagnostic_codes::{self as codes, DiagnosticCode};
+use crate::diagnostics::DiagnosticEngine;
+use crate::intern::Name;
+use crate::locale::Locale;
+use crate::modules;
+use crate::parser::{ParsedDocument, StrayAssignKind};
+use crate::printer;
+use crate::suppression;
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+use lsp_types::*;
+
+/// Command id used by clients to trigger "organize imports" explicitly
+pub const ORGANIZE_IMPORTS_COMMAND: &str = "aether.organizeImports";
+
+/// Command showing the long-form explanation of a diagnostic code, its argument
+pub const EXPLAIN_DIAGNOSTIC_COMMAND: &str = "aether.explainDiagnostic";
+
+/// Check whether a client-side `only` filter accepts the given action kind
+pub fn kind_requested(only: Option<&Vec<CodeActionKind>>, kind: &CodeActionKind) -> bool {
+    match only {
+        Some(kinds) => kinds.iter().any(|requested| {
+            kind.as_str() == requested.as_str()
+                || kind
+                    .as_str()
+                    .starts_with(&format!("{}.", requested.as_str()))
+        }),
+        None => true,
+    }
+}
+
+/// One merged `Import` statement in the organized block
+#[derive(Default)]
+struct ImportGroup {
+    /// (name, alias) pairs, deduplicated
+    entries: Vec<(String, Option<String>)>,
+    leading_comments: Vec<String>,
+    trailing_comments: Vec<String>,
+}
+
+/// Rewrite the leading import block: merge imports from the same path, sort
+/// names and paths, and optionally drop unused names.
+///
+/// Returns `None` when the document has parse errors, has no leading imports,
+/// or is already organized.
+pub fn organize_imports(doc: &ParsedDocument, remove_unused: bool) -> Option<TextEdit> {
+    if !doc.errors.is_empty() {
+        return None;
+    }
+
+    let imports: Vec<&Stmt> = doc
+        .ast
+        .iter()
+        .take_while(|stmt| matches!(stmt.kind, StmtKind::Import { .. }))
+        .collect();
+    let first = imports.first()?;
+    let last = imports.last()?;
+
+    let lines: Vec<&str> = doc.text.lines().collect();
+    let block_start = first.span.start.line as usize;
+    let block_end = last.span.end.line as usize;
+
+    // Another statement sharing the last import's line cannot be moved safely
+    if let Some(next) = doc.ast.get(imports.len())
+        && next.span.start.line as usize <= block_end
+    {
+        return None;
+    }
+
+    let unused: HashSet<Name> = if remove_unused {
+        DiagnosticEngine::unused_imports(&doc.ast)
+            .into_iter()
+            .map(|ident| ident.name.clone())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    // Groups keyed by (path, braced) so `Import NAME From` never becomes `Import {NAME}`
+    let mut groups: BTreeMap<(String, bool), ImportGroup> = BTreeMap::new();
+    let mut prev_end_line: Option<usize> = None;
+
+    for stmt in &imports {
+        let StmtKind::Import {
+            names,
+            path,
+            aliases,
+            braced,
+        } = &stmt.kind
+        else {
+            continue;
+        };
+
+        let start_line = stmt.span.start.line as usize;
+        let end_line = stmt.span.end.line as usize;
+
+        let mut leading = Vec::new();
+        if let Some(prev) = prev_end_line {
+            for line in lines.iter().take(start_line).skip(prev + 1) {
+                let trimmed = line.trim();
+                if !trimmed.is_empty() {
+                    leading.push(trimmed.to_string());
+                }
+            }
+        }
+
+        let end = doc.line_index.position_to_offset(&doc.text, stmt.span.end);
+        let trailing = doc.text[end..]
+            .lines()
+            .next()
+            .map(|rest| rest.trim().trim_start_matches(';').trim())
+            .filter(|rest| rest.starts_with("//") || rest.starts_with("/*"))
+            .map(str::to_string);
+
+        prev_end_line = Some(end_line);
+
+        let entries: Vec<(String, Option<String>)> = names
+            .iter()
+            .zip(aliases)
+            .filter(|(name, alias)| {
+                let binding = alias.as_ref().unwrap_or(name);
+                !unused.contains(binding.as_str())
+            })
+            .map(|(name, alias)| {
+                (
+                    name.name.to_string(),
+                    alias.as_ref().map(|a| a.name.to_string()),
+                )
+            })
+            .collect();
+
+        // Statements whose names were all removed take their comments with them
+        if entries.is_empty() {
+            continue;
+        }
+
+        let group = groups.entry((path.clone(), *braced)).or_default();
+        for entry in entries {
+            if !group.entries.contains(&entry) {
+                group.entries.push(entry);
+            }
+        }
+        group.leading_comments.extend(leading);
+        group.trailing_comments.extend(trailing);
+    }
+
+    let indent: String = lines
+        .get(block_start)
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+        .unwrap_or_default();
+
+    let mut output = Vec::new();
+    for ((path, braced), mut group) in groups {
+        group.entries.sort();
+
+        if group.trailing_comments.len() > 1 {
+            group.leading_comments.append(&mut group.trailing_comments);
+        }
+        for comment in &group.leading_comments {
+            output.push(format!("{}{}", indent, comment));
+        }
+
+        let rendered_path = path.replace('\\', "\\\\").replace('"', "\\\"");
+        // Unbraced imports bind a single name, so each entry stays its own statement
+        let statements: Vec<String> = if braced {
+            let names: Vec<String> = group.entries.iter().map(render_import_entry).collect();
+            vec![format!(
+                "{}Import {{{}}} From \"{}\"",
+                indent,
+                names.join(", "),
+                rendered_path
+            )]
+        } else {
+            group
+                .entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{}Import {} From \"{}\"",
+                        indent,
+                        render_import_entry(entry),
+                        rendered_path
+                    )
+                })
+                .collect()
+        };
+
+        let count = statements.len();
+        for (i, statement) in statements.into_iter().enumerate() {
+            match group.trailing_comments.first() {
+                Some(comment) if i + 1 == count => {
+                    output.push(format!("{} {}", statement, comment))
+                }
+                _ => output.push(statement),
+            }
+        }
+    }
+
+    let end_character = doc.line_index.line_len(&doc.text, block_end as u32);
+    let new_text = output.join("\n");
+    let old_text = lines[block_start..=block_end].join("\n");
+    if new_text == old_text {
+        return None;
+    }
+
+    Some(TextEdit {
+        range: Range {
+            start: Position {
+                line: block_start as u32,
+                character: 0,
+            },
+            end: Position {
+                line: block_end as u32,
+                character: end_character,
+            },
+        },
+        new_text,
+    })
+}
+
+fn render_import_entry((name, alias): &(String, Option<String>)) -> String {
+    match alias {
+        Some(alias) => format!("{} As {}", name, alias),
+        None => name.clone(),
+    }
+}
+
+/// Build the organize-imports source action for a document, if it applies
+pub fn organize_imports_action(
+    doc: &ParsedDocument,
+    uri: &Url,
+    remove_unused: bool,
+) -> Option<CodeAction> {
+    let edit = organize_imports(doc, remove_unused)?;
+    let mut changes = std::collections::HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: "Organize imports".to_string(),
+        kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Quick fixes respelling each miscased keyword in `range` the way the
+/// language does, e.g. `while` as `While`
+pub fn keyword_casing_actions(
+    doc: &ParsedDocument,
+    uri: &Url,
+    range: Range,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeAction> {
+    doc.keyword_casings
+        .iter()
+        .filter(|casing| casing.span.start <= range.end && range.start <= casing.span.end)
+        .map(|casing| {
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(
+                uri.clone(),
+                vec![TextEdit {
+                    range: casing.span,
+                    new_text: casing.keyword.to_string(),
+                }],
+            );
+            let fixed: Vec<Diagnostic> = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.range == casing.span)
+                .cloned()
+                .collect();
+            CodeAction {
+                title: format!("Write `{}`", casing.keyword),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: (!fixed.is_empty()).then_some(fixed),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Quick fixes for the `=` signs the parser read past within `range`: drop it
+/// from `Set X = 10`, compare with `==`, or assign with `Set`
+pub fn stray_assign_actions(
+    doc: &ParsedDocument,
+    uri: &Url,
+    range: Range,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeAction> {
+    doc.stray_assigns
+        .iter()
+        .filter(|stray| stray.span.start <= range.end && range.start <= stray.span.end)
+        .map(|stray| {
+            let (title, edit) = match &stray.kind {
+                StrayAssignKind::InSet => (
+                    "Remove '='".to_string(),
+                    TextEdit {
+                        range: Range::new(stray.span.start, stray.next),
+                        new_text: String::new(),
+                    },
+                ),
+                StrayAssignKind::InCondition => (
+                    "Compare with '=='".to_string(),
+                    TextEdit {
+                        range: stray.span,
+                        new_text: "==".to_string(),
+                    },
+                ),
+                StrayAssignKind::WithoutSet { name, .. } => (
+                    format!("Write `Set {}`", name),
+                    TextEdit {
+                        range: Range::new(name.span.start, stray.next),
+                        new_text: format!("Set {} ", name),
+                    },
+                ),
+            };
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(uri.clone(), vec![edit]);
+            let fixed: Vec<Diagnostic> = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.range == stray.span)
+                .cloned()
+                .collect();
+            CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: (!fixed.is_empty()).then_some(fixed),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Quick fixes rewriting the indentation of each line an indentation
+/// diagnostic among `diagnostics` flags, to the style its data gives
+pub fn indentation_actions(uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeAction> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.code == codes::INDENTATION.lsp_code())
+        .filter_map(|diagnostic| {
+            let indent = diagnostic.data.as_ref()?.get("indent")?.as_str()?;
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(
+                uri.clone(),
+                vec![TextEdit {
+                    range: diagnostic.range,
+                    new_text: indent.to_string(),
+                }],
+            );
+            Some(CodeAction {
+                title: "Fix indentation".to_string(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Quick fixes for W038 calls whose deprecation note names a replacement
+/// function: call that one instead
+pub fn deprecated_call_actions(uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeAction> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.code == codes::DEPRECATED_FUNCTION.lsp_code())
+        .filter_map(|diagnostic| {
+            let replacement = diagnostic.data.as_ref()?.get("replacement")?.as_str()?;
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(
+                uri.clone(),
+                vec![TextEdit {
+                    range: diagnostic.range,
+                    new_text: replacement.to_string(),
+                }],
+            );
+            Some(CodeAction {
+                title: format!("Call `{}` instead", replacement),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// For each coded diagnostic among `diagnostics`, an action running the explain
+/// command, and for a suppressible one a quick fix adding (or extending) the
+/// `aether-disable-next-line` comment above its line
+pub fn explain_actions(
+    doc: &ParsedDocument,
+    uri: &Url,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+    let mut explained = HashSet::new();
+    let mut disabled = HashSet::new();
+    for diagnostic in diagnostics {
+        let Some(NumberOrString::String(id)) = &diagnostic.code else {
+            continue;
+        };
+        let Some(code) = codes::by_code(id) else {
+            continue;
+        };
+        if explained.insert(code.code) {
+            let title = format!(
+                "Explain {}({})",
+                diagnostic.source.as_deref().unwrap_or("aether-lint"),
+                code.code
+            );
+            actions.push(CodeAction {
+                title: title.clone(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                command: Some(Command {
+                    title,
+                    command: EXPLAIN_DIAGNOSTIC_COMMAND.to_string(),
+                    arguments: Some(vec![serde_json::json!(code.code)]),
+                }),
+                ..Default::default()
+            });
+        }
+        let line = diagnostic.range.start.line;
+        if code.suppressible() && disabled.insert((line, code.code)) {
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(uri.clone(), vec![suppression_edit(doc, line, code)]);
+            actions.push(CodeAction {
+                title: format!("Disable {} for this line", code.code),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+    }
+    actions
+}
+
+/// Add `code` to the directive just above `line`, or put one there, indented
+/// like the line
+fn suppression_edit(doc: &ParsedDocument, line: u32, code: &DiagnosticCode) -> TextEdit {
+    let text = |line| {
+        doc.line_index
+            .line_text(&doc.text, line)
+            .unwrap_or_default()
+    };
+    if let Some(above) = line.checked_sub(1)
+        && suppression::directive_codes(text(above).trim()).is_some()
+    {
+        let end = Position::new(above, doc.line_index.line_len(&doc.text, above));
+        return TextEdit {
+            range: Range::new(end, end),
+            new_text: format!(" {}", code.code),
+        };
+    }
+    let current = text(line);
+    let indent = &current[..current.len() - current.trim_start().len()];
+    let start = Position::new(line, 0);
+    TextEdit {
+        range: Range::new(start, start),
+        new_text: format!("{}{}\n", indent, suppression::comment(code)),
+    }
+}
+
+/// What the explain command shows for `code` when not opening its docs page
+pub fn explanation_text(code: &DiagnosticCode, locale: Locale) -> String {
+    let mut text = format!(
+        "{}: {}",
+        code.code,
+        locale.pick(code.description, code.description_en)
+    );
+    if let Some(explanation) = code.explanation() {
+        text.push_str(&format!(
+            "\n\n{}\n\n{}\n{}",
+            locale.pick(explanation.rationale, explanation.rationale_en),
+            locale.pick("示例：", "Example:"),
+            explanation.example
+        ));
+    }
+    if code.suppressible() {
+        text.push_str(&format!(
+            "\n\n{}\n{}",
+            locale.pick(
+                "在该行上方加入以下注释即可忽略此处的问题：",
+                "To silence it on one line, put this comment above the line:"
+            ),
+            suppression::comment(code)
+        ));
+    }
+    text
+}
+
+/// The name an undefined-identifier diagnostic is about, from its data
+pub fn undefined_name(diagnostic: &Diagnostic) -> Option<&str> {
+    if diagnostic.code != codes::UNDEFINED_IDENTIFIER.lsp_code() {
+        return None;
+    }
+    diagnostic.data.as_ref()?.get("name")?.as_str()
+}
+
+/// Quick fixes for an undefined-identifier `diagnostic` whose data names the
+/// identifier: one per module in `modules`, importing the name from there. The
+/// name joins an `Import {...}` of the same module if there is one; otherwise
+/// a new `Import` goes after the leading imports, or before the first statement.
+pub fn import_actions(
+    doc: &ParsedDocument,
+    uri: &Url,
+    diagnostic: &Diagnostic,
+    modules: &[PathBuf],
+    roots: &[PathBuf],
+) -> Vec<CodeAction> {
+    let Some(name) = undefined_name(diagnostic) else {
+        return Vec::new();
+    };
+    let Some(document_dir) = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+    else {
+        return Vec::new();
+    };
+    let imports = doc.ast.imports();
+
+    modules
+        .iter()
+        .map(|module| {
+            let path = modules::relative_import_path(&document_dir, module);
+            let same_module = imports.iter().find(|import| {
+                import.braced
+                    && !import.names.is_empty()
+                    && modules::resolve_import_in(&document_dir, roots, &import.path) == *module
+            });
+            let edit = match same_module {
+                Some(import) => {
+                    let after = match (import.aliases.last(), import.names.last()) {
+                        (Some(Some(alias)), _) => alias.span.end,
+                        (_, Some(last)) => last.span.end,
+                        _ => import.span.end,
+                    };
+                    TextEdit {
+                        range: Range::new(after, after),
+                        new_text: format!(", {}", name),
+                    }
+                }
+                None => {
+                    let statement = format!("Import {{{}}} From \"{}\"", name, path);
+                    match imports.last() {
+                        Some(last) => TextEdit {
+                            range: Range::new(last.span.end, last.span.end),
+                            new_text: format!("\n{}", statement),
+                        },
+                        None => {
+                            let line = doc.ast.first().map_or(0, |stmt| stmt.span.start.line);
+                            let start = Position::new(line, 0);
+                            TextEdit {
+                                range: Range::new(start, start),
+                                new_text: format!("{}\n", statement),
+                            }
+                        }
+                    }
+                }
+            };
+
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(uri.clone(), vec![edit]);
+            CodeAction {
+                title: format!("Import {} from {}", name, path),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                // Only one candidate is the obvious fix
+                is_preferred: Some(modules.len() == 1),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Offer conversions between `Lambda X -> expr` and `Func(X) { Return expr }`
+/// for the innermost lambda enclosing `position`
+pub fn lambda_conversion_actions(
+    doc: &ParsedDocument,
+    uri: &Url,
+    position: Position,
+) -> Vec<CodeAction> {
+    let Some(lambda) = find_enclosing_lambda(&doc.ast, position) else {
+        return Vec::new();
+    };
+    let ExprKind::Lambda { params, body } = &lambda.kind else {
+        return Vec::new();
+    };
+
+    let lines: Vec<&str> = doc.text.lines().collect();
+    let Some(line) = lines.get(lambda.span.start.line as usize) else {
+        return Vec::new();
+    };
+    let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+    let start = doc
+        .line_index
+        .position_to_offset(&doc.text, lambda.span.start);
+    let is_arrow = doc.text[start..].starts_with("Lambda");
+
+    let (title, new_text) = if is_arrow {
+        (
+            "Convert to block lambda",
+            printer::block_lambda_source(params, body, &indent),
+        )
+    } else if let Some(expr) = printer::single_return(body) {
+        (
+            "Convert to arrow lambda",
+            printer::arrow_lambda_source(params, expr, &indent),
+        )
+    } else {
+        // Multi-statement bodies can only stay in block form
+        return Vec::new();
+    };
+
+    let mut changes = std::collections::HashMap::new();
+    changes.insert(
+        uri.clone(),
+        vec![TextEdit {
+            range: lambda.span,
+            new_text,
+        }],
+    );
+
+    vec![CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }]
+}
+
+/// Find the innermost lambda expression whose span contains `position`
+fn find_enclosing_lambda(program: &[Stmt], position: Position) -> Option<&Expr> {
+    let mut finder = LambdaFinder {
+        position,
+        found: None,
+    };
+    finder.visit_block(program);
+    finder.found
+}
+
+/// Descends only into nodes around `position`, so the last lambda it meets is
+/// the innermost
+struct LambdaFinder<'a> {
+    position: Position,
+    found: Option<&'a Expr>,
+}
+
+impl<'a> Visitor<'a> for LambdaFinder<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if span_contains(stmt.span, self.position) {
+            walk_stmt(self, stmt);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if !span_contains(expr.span, self.position) {
+            return;
+        }
+        if let ExprKind::Lambda { .. } = expr.kind {
+            self.found = Some(expr);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Placeholder name for extracted functions; users rename it afterwards
+const EXTRACTED_FUNCTION_NAME: &str = "NEW_FUNCTION";
+
+/// A variable read or write, in evaluation order
+enum Access<'a> {
+    Read(&'a str, Position),
+    Write(&'a str),
+}
+
+/// Offer "Extract to function" when `range` covers whole statements inside a
+/// function body.
+///
+/// Variables read before being written in the selection become parameters; a
+/// variable written in the selection and read afterwards becomes the return
+/// value. Selections that would need several return values, or that contain
+/// `Return`/`Yield`/`Break`/`Continue`, produce a disabled action explaining
+/// why.
+pub fn extract_function_action(
+    doc: &ParsedDocument,
+    uri: &Url,
+    range: Range,
+) -> Option<CodeAction> {
+    if range.start == range.end || !doc.errors.is_empty() {
+        return None;
+    }
+
+    let (function, params, body) = doc.ast.iter().find_map(|stmt| match &stmt.kind {
+        StmtKind::FuncDef { params, body, .. } | StmtKind::GeneratorDef { params, body, .. }
+            if stmt.span.start <= range.start && range.end <= stmt.span.end =>
+        {
+            Some((stmt, params, body))
+        }
+        _ => None,
+    })?;
+    let selected = select_statements(body, range)?;
+    let first = selected.first()?;
+    let last = selected.last()?;
+    let selection = Range {
+        start: first.span.start,
+        end: last.span.end,
+    };
+
+    if contains_control_transfer(selected, false) {
+        return Some(disabled_extract_action(
+            "选中的语句包含 Return、Yield、Break 或 Continue",
+        ));
+    }
+
+    // Only the enclosing function's own variables need to be passed in;
+    // builtins and top-level definitions stay reachable from the new function
+    let function_accesses = collect_accesses(body);
+    let mut locals: HashSet<&str> = params.iter().map(|param| param.as_str()).collect();
+    for access in &function_accesses {
+        if let Access::Write(name) = access {
+            locals.insert(name);
+        }
+    }
+
+    let selection_accesses = collect_accesses(selected);
+    let mut inputs: Vec<&str> = Vec::new();
+    let mut written: HashSet<&str> = HashSet::new();
+    for access in &selection_accesses {
+        match *access {
+            Access::Read(name, _) => {
+                if locals.contains(name) && !written.contains(name) && !inputs.contains(&name) {
+                    inputs.push(name);
+                }
+            }
+            Access::Write(name) => {
+                written.insert(name);
+            }
+        }
+    }
+
+    let mut outputs: Vec<&str> = Vec::new();
+    for access in &function_accesses {
+        if let Access::Read(name, position) = *access
+            && position > selection.end
+            && written.contains(name)
+            && !outputs.contains(&name)
+        {
+            outputs.push(name);
+        }
+    }
+    if outputs.len() > 1 {
+        return Some(disabled_extract_action(&format!(
+            "选中的语句修改了多个之后仍被使用的变量: {}",
+            outputs.join(", ")
+        )));
+    }
+
+    let lines: Vec<&str> = doc.text.lines().collect();
+    let line_indent = |line: u32| -> String {
+        lines
+            .get(line as usize)
+            .map(|text| text.chars().take_while(|c| c.is_whitespace()).collect())
+            .unwrap_or_default()
+    };
+    let function_indent = line_indent(function.span.start.line);
+    let body_indent = line_indent(first.span.start.line);
+    let new_body_indent = format!("{}{}", function_indent, "    ");
+
+    let name = unique_function_name(&doc.ast);
+    let call = format!("{}({})", name, inputs.join(", "));
+
+    let mut definition = format!("Func {}({}) {{\n", name, inputs.join(", "));
+    for (i, line) in text_in_range(doc, selection).lines().enumerate() {
+        let line = if i == 0 {
+            line
+        } else {
+            line.strip_prefix(body_indent.as_str()).unwrap_or(line)
+        };
+        if line.trim().is_empty() {
+            definition.push('\n');
+        } else {
+            definition.push_str(&format!("{}{}\n", new_body_indent, line));
+        }
+    }
+    let replacement = match outputs.first() {
+        Some(output) => {
+            definition.push_str(&format!("{}Return {}\n", new_body_indent, output));
+            format!("Set {} {}", output, call)
+        }
+        None => call,
+    };
+    definition.push_str(&format!("{}}}\n\n{}", function_indent, function_indent));
+
+    let insert_at = function.span.start;
+    let mut changes = std::collections::HashMap::new();
+    changes.insert(
+        uri.clone(),
+        vec![
+            TextEdit {
+                range: Range {
+                    start: insert_at,
+                    end: insert_at,
+                },
+                new_text: definition,
+            },
+            TextEdit {
+                range: selection,
+                new_text: replacement,
+            },
+        ],
+    );
+
+    Some(CodeAction {
+        title: "Extract to function".to_string(),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn disabled_extract_action(reason: &str) -> CodeAction {
+    CodeAction {
+        title: "Extract to function".to_string(),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        disabled: Some(CodeActionDisabled {
+            reason: reason.to_string(),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Find the run of sibling statements exactly covered by `range`, descending
+/// into nested blocks when the range lies within a single statement
+fn select_statements(body: &[Stmt], range: Range) -> Option<&[Stmt]> {
+    let inside = |stmt: &Stmt| range.start <= stmt.span.start && stmt.span.end <= range.end;
+    let overlaps = |stmt: &Stmt| stmt.span.start < range.end && range.start < stmt.span.end;
+
+    let first = body.iter().position(inside);
+    let Some(first) = first else {
+        let container = body
+            .iter()
+            .find(|stmt| stmt.span.start <= range.start && range.end <= stmt.span.end)?;
+        return container
+            .child_blocks()
+            .into_iter()
+            .find_map(|block| select_statements(block, range));
+    };
+    let count = body[first..].iter().take_while(|stmt| inside(stmt)).count();
+    let selected = &body[first..first + count];
+
+    // Reject selections that cut through a statement on either side
+    let partial = body[..first]
+        .iter()
+        .chain(&body[first + count..])
+        .any(overlaps);
+    (!partial).then_some(selected)
+}
+
+/// Whether extracting `body` would change where control flow goes
+fn contains_control_transfer(body: &[Stmt], in_loop: bool) -> bool {
+    body.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(_) | StmtKind::Yield(_) => true,
+        StmtKind::Break | StmtKind::Continue => !in_loop,
+        StmtKind::While { body, .. }
+        | StmtKind::For { body, .. }
+        | StmtKind::ForIndexed { body, .. } => contains_control_transfer(body, true),
+        _ => stmt
+            .child_blocks()
+            .into_iter()
+            .any(|block| contains_control_transfer(block, in_loop)),
+    })
+}
+
+fn unique_function_name(program: &[Stmt]) -> String {
+    let taken: HashSet<&str> = program
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::Set { name, .. }
+            | StmtKind::FuncDef { name, .. }
+            | StmtKind::GeneratorDef { name, .. }
+            | StmtKind::LazyDef { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut name = EXTRACTED_FUNCTION_NAME.to_string();
+    let mut suffix = 2;
+    while taken.contains(name.as_str()) {
+        name = format!("{}_{}", EXTRACTED_FUNCTION_NAME, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+/// Slice the document text covered by `range`
+fn text_in_range(doc: &ParsedDocument, range: Range) -> &str {
+    let start = doc.line_index.position_to_offset(&doc.text, range.start);
+    let end = doc.line_index.position_to_offset(&doc.text, range.end);
+    &doc.text[start..end.max(start)]
+}
+
+/// The variable reads and writes of `body`, in evaluation order
+fn collect_accesses(body: &[Stmt]) -> Vec<Access<'_>> {
+    let mut collector = AccessCollector::default();
+    collector.visit_block(body);
+    collector.accesses
+}
+
+#[derive(Default)]
+struct AccessCollector<'a> {
+    accesses: Vec<Access<'a>>,
+}
+
+impl<'a> Visitor<'a> for AccessCollector<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, .. } | StmtKind::LazyDef { name, .. } => {
+                walk_stmt(self, stmt);
+                self.accesses.push(Access::Write(name.as_str()));
+            }
+            StmtKind::FuncDef { name, .. } | StmtKind::GeneratorDef { name, .. } => {
+                self.accesses.push(Access::Write(name.as_str()));
+                walk_stmt(self, stmt);
+            }
+            // Loop and error variables are bound between the header and the body
+            StmtKind::For {
+                var,
+                iterable,
+                body,
+            } => {
+                self.visit_expr(iterable);
+                self.accesses.push(Access::Write(var.as_str()));
+                self.visit_block(body);
+            }
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                iterable,
+                body,
+            } => {
+                self.visit_expr(iterable);
+                self.accesses.push(Access::Write(index_var.as_str()));
+                self.accesses.push(Access::Write(value_var.as_str()));
+                self.visit_block(body);
+            }
+            StmtKind::Try {
+                body,
+                error_var,
+                handler,
+            } => {
+                self.visit_block(body);
+                if let Some(error_var) = error_var {
+                    self.accesses.push(Access::Write(error_var.as_str()));
+                }
+                self.visit_block(handler);
+            }
+            StmtKind::Export(name) => {
+                self.accesses
+                    .push(Access::Read(name.as_str(), name.span.start));
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                self.accesses
+                    .push(Access::Read(name.as_str(), expr.span.start));
+            }
+            // Lambda parameters only shadow within the lambda, which is close
+            // enough to treat as writes for data-flow purposes here
+            ExprKind::Lambda { params, .. } => {
+                for param in params {
+                    self.accesses.push(Access::Write(param.as_str()));
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IndentationSettings;
+    use crate::diagnostics::AnalysisOptions;
+    use crate::parser::Parser;
+
+    fn apply(text: &str, edit: &TextEdit) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        let start = edit.range.start.line as usize;
+        let end = edit.range.end.line as usize;
+        let mut result: Vec<String> = lines[..start].iter().map(|l| l.to_string()).collect();
+        result.push(edit.new_text.clone());
+        result.extend(lines[end + 1..].iter().map(|l| l.to_string()));
+        result.join("\n")
+    }
+
+    fn organize(text: &str, remove_unused: bool) -> Option<String> {
+        let doc = Parser::new(text).parse();
+        organize_imports(&doc, remove_unused).map(|edit| apply(text, &edit))
+    }
+
+    const MESSY: &str = r#"// Module header
+Import {PARSE, FORMAT_DATE} From "./utils.ae"
+// math helpers
+Import {SQUARE_ALL As SQ, CUBE} From "./math.ae" // geometry
+Import {DEBUG_LOG} From "./utils.ae"
+Import HTTP From "./net.ae"
+
+Set X PARSE(SQ(CUBE([1])))
+DEBUG_LOG(HTTP, X)"#;
+
+    #[test]
+    fn test_organize_imports_merges_and_sorts() {
+        let expected = r#"// Module header
+// math helpers
+Import {CUBE, SQUARE_ALL As SQ} From "./math.ae" // geometry
+Import HTTP From "./net.ae"
+Import {DEBUG_LOG, FORMAT_DATE, PARSE} From "./utils.ae"
+
+Set X PARSE(SQ(CUBE([1])))
+DEBUG_LOG(HTTP, X)"#;
+
+        assert_eq!(organize(MESSY, false).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn test_organize_imports_removes_unused_when_enabled() {
+        let expected = r#"// Module header
+// math helpers
+Import {CUBE, SQUARE_ALL As SQ} From "./math.ae" // geometry
+Import HTTP From "./net.ae"
+Import {DEBUG_LOG, PARSE} From "./utils.ae"
+
+Set X PARSE(SQ(CUBE([1])))
+DEBUG_LOG(HTTP, X)"#;
+
+        assert_eq!(organize(MESSY, true).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn test_organize_imports_is_idempotent() {
+        let organized = organize(MESSY, false).unwrap();
+        assert_eq!(organize(&organized, false), None);
+    }
+
+    #[test]
+    fn test_organize_imports_keeps_comments_after_non_ascii_paths() {
+        let text = "Import {B, A} From \"./数学/🙂.ae\" // 几何\nPRINTLN(A, B)";
+        let doc = Parser::new(text).parse();
+        let edit = organize_imports(&doc, false).unwrap();
+        assert_eq!(edit.new_text, "Import {A, B} From \"./数学/🙂.ae\" // 几何");
+        // The line is 36 characters, with `🙂` taking two UTF-16 units
+        assert_eq!(edit.range.end, Position::new(0, 37));
+    }
+
+    #[test]
+    fn test_organize_imports_refuses_on_parse_errors() {
+        let text = "Import {B, A} From \"./lib.ae\"\nSet X (1 +\n";
+        assert_eq!(organize(text, false), None);
+    }
+
+    #[test]
+    fn test_kind_requested_matches_hierarchy() {
+        let source = vec![CodeActionKind::SOURCE];
+        assert!(kind_requested(
+            Some(&source),
+            &CodeActionKind::SOURCE_ORGANIZE_IMPORTS
+        ));
+        let quickfix = vec![CodeActionKind::QUICKFIX];
+        assert!(!kind_requested(
+            Some(&quickfix),
+            &CodeActionKind::SOURCE_ORGANIZE_IMPORTS
+        ));
+        assert!(kind_requested(
+            None,
+            &CodeActionKind::SOURCE_ORGANIZE_IMPORTS
+        ));
+    }
+
+    #[test]
+    fn test_keyword_casing_quick_fix() {
+        let doc = Parser::new(
+            "set X 1
+WHILE (X < 3) {
+    Set X (X + 1)
+}",
+        )
+        .lenient_keywords(true)
+        .parse();
+        let uri = Url::parse("file:///a.ae").unwrap();
+        let line = |line| Range::new(Position::new(line, 0), Position::new(line, 3));
+
+        let actions = keyword_casing_actions(&doc, &uri, line(1), &[]);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Write `While`");
+        assert_eq!(actions[0].kind, Some(CodeActionKind::QUICKFIX));
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "While");
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 0), Position::new(1, 5))
+        );
+
+        assert_eq!(keyword_casing_actions(&doc, &uri, line(0), &[]).len(), 1);
+        assert!(keyword_casing_actions(&doc, &uri, line(2), &[]).is_empty());
+    }
+
+    /// Apply an edit that starts and ends on one line
+    fn apply_inline(text: &str, edit: &TextEdit) -> String {
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let line = edit.range.start.line as usize;
+        if line == lines.len() {
+            lines.push(String::new());
+        }
+        lines[line].replace_range(
+            edit.range.start.character as usize..edit.range.end.character as usize,
+            &edit.new_text,
+        );
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_stray_assign_quick_fixes() {
+        let text = "Set X = 10\nY = X + 1\nIf (X = 5) {\n    PRINTLN(X)\n}";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///a.ae").unwrap();
+        let fix = |line| {
+            let actions = stray_fixes(&doc, &uri, line);
+            assert_eq!(actions.len(), 1);
+            let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+            (
+                actions[0].title.clone(),
+                apply_inline(text, &edits[0])
+                    .lines()
+                    .nth(line as usize)
+                    .unwrap()
+                    .to_string(),
+            )
+        };
+        assert_eq!(fix(0), ("Remove '='".to_string(), "Set X 10".to_string()));
+        assert_eq!(
+            fix(1),
+            ("Write `Set Y`".to_string(), "Set Y X + 1".to_string())
+        );
+        assert_eq!(
+            fix(2),
+            ("Compare with '=='".to_string(), "If (X == 5) {".to_string())
+        );
+        assert!(stray_fixes(&doc, &uri, 3).is_empty());
+    }
+
+    #[test]
+    fn test_explain_and_disable_quick_fixes() {
+        let text = "Func F() {\n    Set lowered 1\n    // aether-disable-next-line W006\n    Set other 2\n}";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///a.ae").unwrap();
+        let diagnostic = |line| Diagnostic {
+            range: Range::new(Position::new(line, 8), Position::new(line, 13)),
+            code: codes::NAMING_CONVENTION.lsp_code(),
+            source: Some("aether-lint".to_string()),
+            ..Default::default()
+        };
+        let actions = explain_actions(&doc, &uri, &[diagnostic(1), diagnostic(3)]);
+        let titles: Vec<&str> = actions.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Explain aether-lint(W001)",
+                "Disable W001 for this line",
+                "Disable W001 for this line"
+            ]
+        );
+        let command = actions[0].command.as_ref().unwrap();
+        assert_eq!(command.command, EXPLAIN_DIAGNOSTIC_COMMAND);
+        assert_eq!(command.arguments, Some(vec![serde_json::json!("W001")]));
+
+        let disabled = |action: &CodeAction| {
+            let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+            apply_inline(text, &edits[0])
+        };
+        assert_eq!(
+            disabled(&actions[1]),
+            "Func F() {\n    // aether-disable-next-line W001\n    Set lowered 1\n    // aether-disable-next-line W006\n    Set other 2\n}"
+        );
+        assert_eq!(
+            disabled(&actions[2]),
+            "Func F() {\n    Set lowered 1\n    // aether-disable-next-line W006 W001\n    Set other 2\n}"
+        );
+
+        // Parse errors can be explained but not silenced
+        let syntax = Diagnostic {
+            code: codes::SYNTAX_ERROR.lsp_code(),
+            ..diagnostic(1)
+        };
+        let actions = explain_actions(&doc, &uri, &[syntax]);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Explain aether-lint(E000)");
+    }
+
+    #[test]
+    fn test_explanation_text() {
+        let text = explanation_text(&codes::NAMING_CONVENTION, Locale::En);
+        assert!(text.starts_with("W001: "), "{}", text);
+        assert!(text.contains("Example:"), "{}", text);
+        assert!(
+            text.contains("// aether-disable-next-line W001"),
+            "{}",
+            text
+        );
+        let text = explanation_text(&codes::SYNTAX_ERROR, Locale::En);
+        assert!(!text.contains("aether-disable-next-line"), "{}", text);
+    }
+
+    fn stray_fixes(doc: &ParsedDocument, uri: &Url, line: u32) -> Vec<CodeAction> {
+        let range = Range::new(Position::new(line, 0), Position::new(line, 20));
+        stray_assign_actions(doc, uri, range, &[])
+    }
+
+    #[test]
+    fn test_indentation_quick_fix() {
+        let text = "Func F() {\n \tReturn 1\n}";
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            indentation: IndentationSettings {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let diagnostics = DiagnosticEngine::analyze(&parsed, &options);
+        let uri = Url::parse("file:///a.ae").unwrap();
+
+        let actions = indentation_actions(&uri, &diagnostics);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Fix indentation");
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(apply_inline(text, &edits[0]), "Func F() {\n    Return 1\n}");
+    }
+
+    fn undefined(name: &str) -> Diagnostic {
+        Diagnostic {
+            code: codes::UNDEFINED_IDENTIFIER.lsp_code(),
+            data: Some(serde_json::json!({ "name": name })),
+            ..Default::default()
+        }
+    }
+
+    fn import_edits(text: &str, dir: &Path, modules: &[PathBuf]) -> Vec<(String, String)> {
+        let doc = Parser::new(text).parse();
+        let uri = Url::from_file_path(dir.join("main.ae")).unwrap();
+        import_actions(&doc, &uri, &undefined("HELPER"), modules, &[])
+            .into_iter()
+            .map(|action| {
+                assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+                assert_eq!(action.is_preferred, Some(modules.len() == 1));
+                let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+                (action.title, apply_inline(text, &edits[0]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import_fix_inserts_a_new_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = vec![dir.path().join("lib.ae")];
+        std::fs::write(&lib[0], "Func HELPER() {\n    Return 1\n}").unwrap();
+
+        let fixes = import_edits("// main\nSet X HELPER()", dir.path(), &lib);
+        assert_eq!(
+            fixes,
+            vec![(
+                "Import HELPER from ./lib.ae".to_string(),
+                "// main\nImport {HELPER} From \"./lib.ae\"\nSet X HELPER()".to_string()
+            )]
+        );
+
+        // After the existing imports of other modules
+        std::fs::write(dir.path().join("other.ae"), "Set A 1").unwrap();
+        let fixes = import_edits(
+            "Import {A} From \"./other.ae\"\nSet X HELPER(A)",
+            dir.path(),
+            &lib,
+        );
+        assert_eq!(
+            fixes[0].1,
+            "Import {A} From \"./other.ae\"\nImport {HELPER} From \"./lib.ae\"\nSet X HELPER(A)"
+        );
+    }
+
+    #[test]
+    fn test_import_fix_merges_into_an_existing_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib.ae");
+        std::fs::write(&lib, "Set A 1\nFunc HELPER() {\n    Return A\n}").unwrap();
+
+        let fixes = import_edits(
+            "Import {A As B} From \"./lib.ae\"\nSet X HELPER(B)",
+            dir.path(),
+            &[lib],
+        );
+        assert_eq!(
+            fixes[0].1,
+            "Import {A As B, HELPER} From \"./lib.ae\"\nSet X HELPER(B)"
+        );
+    }
+
+    #[test]
+    fn test_import_fix_offers_every_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("util")).unwrap();
+        let modules = vec![
+            dir.path().join("lib.ae"),
+            dir.path().join("util").join("helpers.ae"),
+        ];
+        for module in &modules {
+            std::fs::write(module, "Func HELPER() {\n    Return 1\n}").unwrap();
+        }
+
+        let titles: Vec<String> = import_edits("Set X HELPER()", dir.path(), &modules)
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Import HELPER from ./lib.ae",
+                "Import HELPER from ./util/helpers.ae"
+            ]
+        );
+
+        // Nothing to offer without the name in the diagnostic's data
+        let doc = Parser::new("Set X HELPER()").parse();
+        let uri = Url::from_file_path(dir.path().join("main.ae")).unwrap();
+        let bare = Diagnostic {
+            data: None,
+            ..undefined("HELPER")
+        };
+        assert!(import_actions(&doc, &uri, &bare, &modules, &[]).is_empty());
+    }
+
+    fn convert_lambda(text: &str, line: u32, character: u32) -> Vec<(String, String)> {
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///test.ae").unwrap();
+        lambda_conversion_actions(&doc, &uri, Position { line, character })
+            .into_iter()
+            .map(|action| {
+                let edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+                (action.title, edits[0].new_text.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_convert_block_lambda_to_arrow() {
+        let text = "Set DOUBLE Func(X) { Return X * 2 }";
+        assert_eq!(
+            convert_lambda(text, 0, 14),
+            vec![(
+                "Convert to arrow lambda".to_string(),
+                "Lambda X -> X * 2".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_convert_arrow_lambda_to_block() {
+        let text = "    Set DOUBLE Lambda X -> (X * 2)";
+        assert_eq!(
+            convert_lambda(text, 0, 20),
+            vec![(
+                "Convert to block lambda".to_string(),
+                "Func(X) {\n        Return X * 2\n    }".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_convert_multi_parameter_lambda() {
+        let text = "Set SUM REDUCE(XS, Lambda (ACC, X) -> ACC + X, 0)";
+        let actions = convert_lambda(text, 0, 20);
+        assert_eq!(
+            actions[0].1,
+            "Func(ACC, X) {\n    Return ACC + X\n}".to_string()
+        );
+
+        let block = "Set SUM REDUCE(XS, Func(ACC, X) { Return ACC + X }, 0)";
+        let actions = convert_lambda(block, 0, 20);
+        assert_eq!(actions[0].1, "Lambda (ACC, X) -> ACC + X".to_string());
+    }
+
+    #[test]
+    fn test_multi_statement_lambda_offers_no_arrow_form() {
+        let text = "Set F Func(X) {\n    Set Y (X * 2)\n    Return Y\n}";
+        assert!(convert_lambda(text, 1, 6).is_empty());
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range {
+            start: Position {
+                line: start.0,
+                character: start.1,
+            },
+            end: Position {
+                line: end.0,
+                character: end.1,
+            },
+        }
+    }
+
+    const EXTRACT_SOURCE: &str = "Func TOTAL_PRICE(PRICE, COUNT, RATE) {
+    Set BASE (PRICE * COUNT)
+    Set TAX (BASE * RATE)
+    Set TOTAL (BASE + TAX)
+    Return TOTAL
+}";
+
+    #[test]
+    fn test_extract_function_with_inputs_and_output() {
+        let doc = Parser::new(EXTRACT_SOURCE).parse();
+        let uri = Url::parse("file:///test.ae").unwrap();
+        let action = extract_function_action(&doc, &uri, range((2, 0), (3, 26))).unwrap();
+        assert!(action.disabled.is_none());
+
+        let edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].range, range((0, 0), (0, 0)));
+        assert_eq!(
+            edits[0].new_text,
+            "Func NEW_FUNCTION(BASE, RATE) {
+    Set TAX (BASE * RATE)
+    Set TOTAL (BASE + TAX)
+    Return TOTAL
+}
+
+"
+        );
+        assert_eq!(edits[1].range, range((2, 4), (3, 26)));
+        assert_eq!(edits[1].new_text, "Set TOTAL NEW_FUNCTION(BASE, RATE)");
+    }
+
+    #[test]
+    fn test_extract_function_after_astral_characters() {
+        let text = "Func LABEL(BASE) {
+    Set TAG (\"😀\" + BASE) // note
+    Return TAG
+}";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///test.ae").unwrap();
+        let action = extract_function_action(&doc, &uri, range((1, 4), (1, 25))).unwrap();
+        let edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+        assert_eq!(
+            edits[0].new_text,
+            "Func NEW_FUNCTION(BASE) {
+    Set TAG (\"😀\" + BASE)
+    Return TAG
+}
+
+"
+        );
+    }
+
+    #[test]
+    fn test_extract_function_rejects_multiple_outputs() {
+        let text = "Func SPLIT(PRICE, RATE) {
+    Set TAX (PRICE * RATE)
+    Set NET (PRICE - TAX)
+    Return [TAX, NET]
+}";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///test.ae").unwrap();
+        let action = extract_function_action(&doc, &uri, range((1, 4), (2, 25))).unwrap();
+        assert!(action.edit.is_none());
+        assert!(action.disabled.unwrap().reason.contains("TAX, NET"));
+    }
+
+    #[test]
+    fn test_extract_function_requires_statement_boundaries() {
+        let doc = Parser::new(EXTRACT_SOURCE).parse();
+        let uri = Url::parse("file:///test.ae").unwrap();
+        assert!(extract_function_action(&doc, &uri, range((2, 10), (3, 26))).is_none());
+    }
+
+    #[test]
+    fn test_call_the_replacement_of_a_deprecated_function() {
+        let text = "// @deprecated use NEW_TOTAL instead\nFunc OLD_TOTAL(XS) {\n    Return 0\n}\n\
+                    Func NEW_TOTAL(XS) {\n    Return 1\n}\nPRINTLN(OLD_TOTAL([1]))";
+        let uri = Url::parse("file:///test.ae").unwrap();
+        let doc = Parser::new(text).parse();
+        let diagnostics =
+            crate::diagnostics::DiagnosticEngine::analyze(&doc, &AnalysisOptions::default());
+        let actions = deprecated_call_actions(&uri, &diagnostics);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Call `NEW_TOTAL` instead");
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &vec![TextEdit {
+                range: Range::new(Position::new(7, 8), Position::new(7, 17)),
+                new_text: "NEW_TOTAL".to_string(),
+            }]
+        );
+    }
+}
@@ -1,71 +1,535 @@
 //! Completion provider for Aether language
 
+use std::path::{Path, PathBuf};
+
+use crate::ast::DictKey;
 use crate::builtins;
+use crate::case_labels::{case_completions, case_context};
+use crate::keywords::KEYWORDS;
+use crate::lexer::Lexer;
+use crate::locale::Locale;
+use crate::modules::{self, ModuleCache};
 use crate::parser::ParsedDocument;
-use crate::symbols::SymbolTable;
-use tower_lsp::lsp_types::*;
+use crate::symbols::{SymbolFlavor, SymbolTable};
+use crate::token::Token;
+use crate::workspace::{ModuleSummary, WorkspaceIndex};
+use lsp_types::*;
+
+/// Where the cursor sits within an `Import` statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportContext {
+    /// Inside the name list, with the module path given later on the line
+    Names {
+        path: String,
+        /// Names already listed, which are not offered again
+        existing: Vec<String>,
+    },
+    /// Inside the `From "..."` string; `start` is the column after the quote
+    Path { typed: String, start: u32 },
+}
+
+/// Inspect the line around the cursor for an import context
+pub fn import_context(doc: &ParsedDocument, position: Position) -> Option<ImportContext> {
+    let (line, cursor) = line_at_cursor(doc, position)?;
+    let trimmed = line.trim_start();
+    if !trimmed.starts_with("Import") {
+        return None;
+    }
+    let indent = line.len() - trimmed.len();
+    let (prefix, suffix) = line.split_at(cursor);
+
+    // `From "./li|` — an unterminated string after `From`
+    if let Some(from) = prefix.rfind("From") {
+        let after_from = prefix[from + "From".len()..].trim_start();
+        if let Some(typed) = after_from.strip_prefix('"')
+            && !typed.contains('"')
+        {
+            let start = prefix[..prefix.len() - typed.len()].encode_utf16().count();
+            return Some(ImportContext::Path {
+                typed: typed.to_string(),
+                start: start as u32,
+            });
+        }
+        return None;
+    }
+
+    // `Import {A, |} From "./lib.ae"` — the path must follow the cursor
+    let from = suffix.find("From")?;
+    let path = suffix[from + "From".len()..]
+        .trim_start()
+        .strip_prefix('"')?
+        .split('"')
+        .next()?
+        .to_string();
+    if prefix.len() < indent + "Import".len() || prefix.contains('}') {
+        return None;
+    }
+
+    // The name being typed isn't listed yet
+    let before_typed = prefix.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let names_text = format!("{}{}", before_typed, &suffix[..from]);
+    let existing = names_text
+        .trim_start()
+        .trim_start_matches("Import")
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty() && *word != "As")
+        .map(|word| word.to_string())
+        .collect();
+
+    Some(ImportContext::Names { path, existing })
+}
+
+/// Completions for an import context: exported names of the target module, or
+/// relative paths to modules near the document and under the workspace root.
+/// The workspace index answers when it covers the module; the disk is the fallback.
+pub fn import_completions(
+    context: &ImportContext,
+    position: Position,
+    document_path: Option<&Path>,
+    roots: &[PathBuf],
+    index: &WorkspaceIndex,
+    cache: &ModuleCache,
+) -> Vec<CompletionItem> {
+    let Some(base_dir) = document_path.and_then(|path| path.parent()) else {
+        return Vec::new();
+    };
+
+    match context {
+        ImportContext::Names { path, existing } => {
+            let module = modules::resolve_import_in(base_dir, roots, path);
+            index
+                .summary(&module)
+                .map(|summary| summary.exports)
+                .or_else(|| cache.exports(&module))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|export| !existing.contains(&export.name))
+                .map(|export| CompletionItem {
+                    label: export.name.clone(),
+                    kind: Some(symbol_to_completion_kind(export.kind)),
+                    detail: export
+                        .detail
+                        .or_else(|| Some(format!("Exported from {}", path))),
+                    insert_text: Some(export.name),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                })
+                .collect()
+        }
+        ImportContext::Path { start, .. } => {
+            let indexed = !index.is_empty() && roots.iter().any(|root| base_dir.starts_with(root));
+            let mut search = vec![base_dir];
+            search.extend(roots.iter().map(PathBuf::as_path));
+            let range = Range {
+                start: Position {
+                    line: position.line,
+                    character: *start,
+                },
+                end: position,
+            };
+
+            let candidates = if indexed {
+                index.paths()
+            } else {
+                modules::discover_modules(&search)
+                    .into_iter()
+                    .filter(|module| !index.is_excluded(module))
+                    .collect()
+            };
+            let mut labels: Vec<String> = candidates
+                .into_iter()
+                .filter(|module| Some(module.as_path()) != document_path)
+                .map(|module| modules::relative_import_path(base_dir, &module))
+                .collect();
+            labels.sort();
+            labels.dedup();
+
+            labels
+                .into_iter()
+                .map(|label| CompletionItem {
+                    label: label.clone(),
+                    kind: Some(CompletionItemKind::MODULE),
+                    filter_text: Some(label.clone()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range,
+                        new_text: label,
+                    })),
+                    ..Default::default()
+                })
+                .collect()
+        }
+    }
+}
+
+/// The namespace before the cursor in `NAME.` or `NAME.PARTIAL`
+pub fn member_context(doc: &ParsedDocument, position: Position) -> Option<String> {
+    let (line, cursor) = line_at_cursor(doc, position)?;
+    let prefix: Vec<char> = line[..cursor].chars().collect();
+    let typed = word_before(doc, position).chars().count();
+    let before_dot = prefix[..prefix.len() - typed].strip_suffix(&['.'])?;
+    let start = before_dot
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |index| index + 1);
+    let namespace: String = before_dot[start..].iter().collect();
+    namespace
+        .starts_with(|c: char| c.is_alphabetic() || c == '_')
+        .then_some(namespace)
+}
+
+/// The path of `Import NAMESPACE From "..."` found line by line, for documents that
+/// don't parse while a member access is being typed
+pub fn namespace_import_path(text: &str, namespace: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| line.trim_start().starts_with("Import"))
+        .find_map(|line| {
+            let mut lexer = Lexer::new(line);
+            let mut tokens = Vec::new();
+            loop {
+                match lexer.next_token() {
+                    Token::EOF | Token::Newline => break,
+                    token => tokens.push(token),
+                }
+            }
+            let (binding, path) = match tokens.as_slice() {
+                [
+                    Token::Import,
+                    Token::Identifier(name),
+                    Token::From,
+                    Token::String(path),
+                    ..,
+                ] => (name, path),
+                [
+                    Token::Import,
+                    Token::Identifier(_),
+                    Token::As,
+                    Token::Identifier(alias),
+                    Token::From,
+                    Token::String(path),
+                    ..,
+                ] => (alias, path),
+                _ => return None,
+            };
+            (binding == namespace).then(|| path.clone())
+        })
+}
+
+/// The exports of the module imported from `path`, as members of its namespace
+pub fn member_completions(path: &str, summary: &ModuleSummary) -> Vec<CompletionItem> {
+    summary
+        .exports
+        .iter()
+        .map(|export| CompletionItem {
+            label: export.name.clone(),
+            kind: Some(symbol_to_completion_kind(export.kind)),
+            detail: export
+                .detail
+                .clone()
+                .or_else(|| Some(format!("Exported from {}", path))),
+            documentation: summary
+                .symbol(&export.name)
+                .filter(|symbol| !symbol.documentation.is_empty())
+                .map(|symbol| {
+                    Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: symbol.documentation.clone(),
+                    })
+                }),
+            insert_text: Some(export.name.clone()),
+            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            tags: summary
+                .symbol(&export.name)
+                .and_then(|symbol| symbol.deprecated.as_ref())
+                .map(|_| vec![CompletionItemTag::DEPRECATED]),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn symbol_to_completion_kind(kind: SymbolKind) -> CompletionItemKind {
+    match kind {
+        SymbolKind::FUNCTION => CompletionItemKind::FUNCTION,
+        SymbolKind::CONSTANT => CompletionItemKind::CONSTANT,
+        _ => CompletionItemKind::VARIABLE,
+    }
+}
+
+/// Ranking buckets: user symbols first, then keywords, then builtins, then templates
+const LOCAL_RANK: u8 = 0;
+const KEYWORD_RANK: u8 = 1;
+const BUILTIN_RANK: u8 = 2;
+const SNIPPET_RANK: u8 = 3;
+
+/// Build the completion list at `position`
+///
+/// When more than `max_items` candidates exist, the list is narrowed on the
+/// server to labels starting with the word being typed and marked incomplete
+/// so the client asks again as the word grows. `0` disables pre-filtering.
+pub fn get_completions(
+    doc: &ParsedDocument,
+    position: Position,
+    max_items: usize,
+    locale: Locale,
+) -> CompletionList {
+    get_completions_with(doc, position, max_items, locale, Vec::new())
+}
+
+/// [`get_completions`] with `snippets` ranked after everything else
+pub fn get_completions_with(
+    doc: &ParsedDocument,
+    position: Position,
+    max_items: usize,
+    locale: Locale,
+    snippets: Vec<CompletionItem>,
+) -> CompletionList {
+    // 字典键补全: CONFIG["|
+    if let Some(items) = dict_key_completions(doc, position) {
+        return CompletionList {
+            is_incomplete: false,
+            items,
+        };
+    }
+
+    // Case 标签补全: Switch (STATUS) { Case |
+    if let Some(context) = case_context(&doc.text, position) {
+        return CompletionList {
+            is_incomplete: false,
+            items: case_completions(doc, &context, position),
+        };
+    }
+
+    let mut ranked = Vec::new();
 
-pub fn get_completions(doc: &ParsedDocument, _position: Position) -> Vec<CompletionItem> {
-    let mut completions = Vec::new();
+    // 用户定义的变量和函数补全
+    ranked.extend(
+        get_variable_completions(&doc.symbols)
+            .into_iter()
+            .chain(get_function_completions(&doc.symbols))
+            .map(|item| (LOCAL_RANK, item)),
+    );
 
     // 关键字补全
-    completions.extend(get_keyword_completions());
+    ranked.extend(
+        get_keyword_completions(locale)
+            .into_iter()
+            .map(|item| (KEYWORD_RANK, item)),
+    );
 
     // 内置函数补全 (从 builtins 模块自动生成)
-    completions.extend(builtins::builtin_to_completion_items());
+    ranked.extend(
+        builtins::builtin_to_completion_items(locale)
+            .into_iter()
+            .map(|item| (BUILTIN_RANK, item)),
+    );
+
+    ranked.extend(snippets.into_iter().map(|item| (SNIPPET_RANK, item)));
+
+    let mut items = deduplicate(ranked);
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+
+    let mut is_incomplete = false;
+    if max_items > 0 && items.len() > max_items {
+        let typed = word_before(doc, position).to_uppercase();
+        items.retain(|item| item.label.to_uppercase().starts_with(&typed));
+        items.truncate(max_items);
+        is_incomplete = true;
+    }
+
+    CompletionList {
+        is_incomplete,
+        items,
+    }
+}
+
+/// Keep one item per label, preferring the highest-ranked source; a user
+/// symbol that shadows a builtin notes the builtin in its documentation
+fn deduplicate(ranked: Vec<(u8, CompletionItem)>) -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = Vec::new();
+    let mut ranks: Vec<u8> = Vec::new();
+
+    for (rank, mut item) in ranked {
+        if let Some(index) = items
+            .iter()
+            .position(|existing| existing.label == item.label)
+        {
+            if ranks[index] == LOCAL_RANK && rank == BUILTIN_RANK {
+                note_shadowed_builtin(&mut items[index], &item);
+            }
+            continue;
+        }
+        item.sort_text = Some(format!("{}_{}", rank, item.label));
+        item.filter_text = Some(item.label.clone());
+        items.push(item);
+        ranks.push(rank);
+    }
+
+    items
+}
+
+fn note_shadowed_builtin(item: &mut CompletionItem, builtin: &CompletionItem) {
+    let note = format!(
+        "遮蔽了内置函数 `{}`: {}",
+        builtin.label,
+        builtin.detail.as_deref().unwrap_or_default()
+    );
+    let value = match item.documentation.take() {
+        Some(Documentation::MarkupContent(content)) => {
+            format!("{}\n\n---\n\n{}", content.value, note)
+        }
+        Some(Documentation::String(text)) => format!("{}\n\n---\n\n{}", text, note),
+        None => note,
+    };
+    item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }));
+}
+
+/// The cursor's line and the byte offset of the cursor in it
+fn line_at_cursor(doc: &ParsedDocument, position: Position) -> Option<(&str, usize)> {
+    let (text, lines) = (&doc.text, &doc.line_index);
+    let line = lines.line_text(text, position.line)?;
+    let start = lines.position_to_offset(text, Position::new(position.line, 0));
+    Some((line, lines.position_to_offset(text, position) - start))
+}
 
-    // 用户定义的变量补全
-    completions.extend(get_variable_completions(&doc.symbols));
+/// The identifier characters immediately before the cursor
+fn word_before(doc: &ParsedDocument, position: Position) -> String {
+    let Some((line, cursor)) = line_at_cursor(doc, position) else {
+        return String::new();
+    };
+    let prefix: Vec<char> = line[..cursor].chars().collect();
+    let start = prefix
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |index| index + 1);
+    prefix[start..].iter().collect()
+}
 
-    // 用户定义的函数补全
-    completions.extend(get_function_completions(&doc.symbols));
+/// Cursor inside `NAME[` or `NAME["partial`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictKeyContext {
+    pub name: String,
+    /// Whether a string literal has already been opened
+    pub quoted: bool,
+    /// Column where the typed key text starts
+    pub start: u32,
+}
+
+/// Detect an index expression being typed just before the cursor
+pub fn dict_key_context(doc: &ParsedDocument, position: Position) -> Option<DictKeyContext> {
+    let (line, cursor) = line_at_cursor(doc, position)?;
+    let prefix: Vec<char> = line[..cursor].chars().collect();
+
+    let typed_len = prefix
+        .iter()
+        .rev()
+        .take_while(|c| **c != '"' && **c != '[')
+        .count();
+    let mut end = prefix.len() - typed_len;
+    let quoted = end > 0 && prefix[end - 1] == '"';
+    if quoted {
+        end -= 1;
+    } else if typed_len > 0 {
+        // `NAME[partial` is an identifier index, not a key
+        return None;
+    }
+    if end == 0 || prefix[end - 1] != '[' {
+        return None;
+    }
 
-    completions
+    let name_end = end - 1;
+    let name_len = prefix[..name_end]
+        .iter()
+        .rev()
+        .take_while(|c| c.is_alphanumeric() || **c == '_')
+        .count();
+    if name_len == 0 {
+        return None;
+    }
+
+    Some(DictKeyContext {
+        name: prefix[name_end - name_len..name_end].iter().collect(),
+        quoted,
+        start: prefix[..prefix.len() - typed_len]
+            .iter()
+            .map(|c| c.len_utf16() as u32)
+            .sum(),
+    })
+}
+
+/// Key completions when the cursor sits in `NAME[` or `NAME["partial`
+pub fn dict_key_completions(
+    doc: &ParsedDocument,
+    position: Position,
+) -> Option<Vec<CompletionItem>> {
+    dict_key_context(doc, position)
+        .map(|context| get_dict_key_completions(&doc.symbols, &context, position))
+}
+
+/// Keys of the dict literal the indexed variable was bound to
+fn get_dict_key_completions(
+    symbols: &SymbolTable,
+    context: &DictKeyContext,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let Some(keys) = symbols.dict_keys.get(&context.name) else {
+        return Vec::new();
+    };
+    let range = Range {
+        start: Position {
+            line: position.line,
+            character: context.start,
+        },
+        end: position,
+    };
+
+    keys.iter()
+        .filter_map(|key| {
+            let (label, new_text) = match key {
+                DictKey::Str(key) => {
+                    let escaped = key.replace('\\', "\\\\").replace('"', "\\\"");
+                    let new_text = if context.quoted {
+                        escaped
+                    } else {
+                        format!("\"{}\"", escaped)
+                    };
+                    (key.clone(), new_text)
+                }
+                // A number key can't be typed inside a string
+                DictKey::Num(key) if !context.quoted => (key.to_string(), key.to_string()),
+                _ => return None,
+            };
+            Some(CompletionItem {
+                label,
+                kind: Some(CompletionItemKind::PROPERTY),
+                detail: Some(format!("Key of {}", context.name)),
+                filter_text: Some(new_text.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                ..Default::default()
+            })
+        })
+        .collect()
 }
 
 /// Get keyword completions
-fn get_keyword_completions() -> Vec<CompletionItem> {
-    let keywords = vec![
-        ("Set", "变量赋值", "Set VAR value"),
-        ("Func", "函数定义", "Func NAME(params) { ... }"),
-        ("Return", "返回值", "Return value"),
-        ("If", "条件判断", "If (condition) { ... }"),
-        ("Elif", "否则如果", "Elif (condition) { ... }"),
-        ("Else", "否则", "Else { ... }"),
-        ("While", "循环", "While (condition) { ... }"),
-        ("For", "遍历", "For VAR In collection { ... }"),
-        ("In", "循环关键字", "For X In [1,2,3] { ... }"),
-        ("Break", "跳出循环", "Break"),
-        ("Continue", "继续下一次循环", "Continue"),
-        ("Generator", "生成器定义", "Generator NAME(params) { ... }"),
-        ("Yield", "生成值", "Yield value"),
-        ("Lazy", "惰性求值", "Lazy NAME(expr)"),
-        ("Force", "强制求值", "Force(lazy_value)"),
-        ("Switch", "分支", "Switch (value) { Case x: ... }"),
-        ("Case", "分支情况", "Case value: statements"),
-        ("Default", "默认分支", "Default: statements"),
-        ("Import", "导入模块", "Import {NAME} From \"path\""),
-        ("Export", "导出符号", "Export NAME"),
-        ("From", "导入来源", "Import X From \"path\""),
-        ("As", "别名", "Import X As Y From \"path\""),
-        ("Lambda", "匿名函数", "Lambda X -> expr"),
-        ("True", "布尔真", "True"),
-        ("False", "布尔假", "False"),
-        ("Null", "空值", "Null"),
-    ];
-
-    keywords
-        .into_iter()
-        .map(|(keyword, desc, example)| CompletionItem {
-            label: keyword.to_string(),
+fn get_keyword_completions(locale: Locale) -> Vec<CompletionItem> {
+    KEYWORDS
+        .iter()
+        .map(|doc| CompletionItem {
+            label: doc.keyword.to_string(),
             kind: Some(CompletionItemKind::KEYWORD),
-            detail: Some(desc.to_string()),
+            detail: Some(doc.summary(locale).to_string()),
             documentation: Some(Documentation::MarkupContent(MarkupContent {
                 kind: MarkupKind::Markdown,
-                value: format!("**{}**\n\n{}\n\n```aether\n{}\n```", keyword, desc, example),
+                value: doc.markdown(locale),
             })),
-            insert_text: Some(keyword.to_string()),
-            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            insert_text: Some(doc.snippet.unwrap_or(doc.keyword).to_string()),
+            insert_text_format: Some(if doc.snippet.is_some() {
+                InsertTextFormat::SNIPPET
+            } else {
+                InsertTextFormat::PLAIN_TEXT
+            }),
             ..Default::default()
         })
         .collect()
@@ -77,8 +541,13 @@ fn get_variable_completions(symbols: &SymbolTable) -> Vec<CompletionItem> {
         .variables
         .iter()
         .map(|var| CompletionItem {
-            label: var.name.clone(),
-            kind: Some(CompletionItemKind::VARIABLE),
+            label: var.name.to_string(),
+            // A variable bound to a lambda completes like a function
+            kind: Some(if var.flavor == SymbolFlavor::Lambda {
+                CompletionItemKind::FUNCTION
+            } else {
+                CompletionItemKind::VARIABLE
+            }),
             detail: var
                 .detail
                 .clone()
@@ -91,8 +560,10 @@ fn get_variable_completions(symbols: &SymbolTable) -> Vec<CompletionItem> {
             } else {
                 None
             },
-            insert_text: Some(var.name.clone()),
+            insert_text: Some(var.name.to_string()),
             insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            commit_characters: (var.flavor == SymbolFlavor::Lambda)
+                .then(builtins::function_commit_characters),
             ..Default::default()
         })
         .collect()
@@ -104,7 +575,7 @@ fn get_function_completions(symbols: &SymbolTable) -> Vec<CompletionItem> {
         .functions
         .iter()
         .map(|func| CompletionItem {
-            label: func.name.clone(),
+            label: func.name.to_string(),
             kind: Some(CompletionItemKind::FUNCTION),
             detail: func
                 .detail
@@ -118,9 +589,331 @@ fn get_function_completions(symbols: &SymbolTable) -> Vec<CompletionItem> {
             } else {
                 None
             },
-            insert_text: Some(format!("{}($1)", func.name)),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            insert_text: Some(func.name.to_string()),
+            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            commit_characters: Some(builtins::function_commit_characters()),
+            tags: func
+                .deprecated
+                .as_ref()
+                .map(|_| vec![CompletionItemTag::DEPRECATED]),
             ..Default::default()
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn parse(text: &str) -> ParsedDocument {
+        crate::parser::Parser::new(text).parse()
+    }
+
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.ae"),
+            "Func ADD(A, B) {\n    Return A + B\n}\nSet PI 3.14\nExport ADD\nExport PI\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("util")).unwrap();
+        fs::write(
+            dir.path().join("util").join("strings.ae"),
+            "Func SHOUT(S) {\n    Return UPPER(S)\n}\nExport SHOUT\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("main.ae"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_import_name_completion() {
+        let dir = workspace();
+        let text = "Import {PI, } From \"./lib.ae\"";
+        let position = Position::new(0, 12);
+
+        let context = import_context(&parse(text), position).unwrap();
+        assert_eq!(
+            context,
+            ImportContext::Names {
+                path: "./lib.ae".to_string(),
+                existing: vec!["PI".to_string()],
+            }
+        );
+
+        let main = dir.path().join("main.ae");
+        let items = import_completions(
+            &context,
+            position,
+            Some(&main),
+            &[dir.path().to_path_buf()],
+            &WorkspaceIndex::new(),
+            &ModuleCache::new(),
+        );
+        let labels: Vec<(&str, Option<CompletionItemKind>)> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.kind))
+            .collect();
+        assert_eq!(labels, vec![("ADD", Some(CompletionItemKind::FUNCTION))]);
+
+        // A name being typed in CJK isn't cut mid-character
+        assert_eq!(
+            import_context(
+                &parse("Import {PI, 名字, E} From \"./lib.ae\""),
+                Position::new(0, 14)
+            ),
+            Some(ImportContext::Names {
+                path: "./lib.ae".to_string(),
+                existing: vec!["PI".to_string(), "E".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_import_path_completion() {
+        let dir = workspace();
+        let text = "Import {ADD} From \"./";
+        let position = Position::new(0, 21);
+
+        let context = import_context(&parse(text), position).unwrap();
+        assert_eq!(
+            context,
+            ImportContext::Path {
+                typed: "./".to_string(),
+                start: 19,
+            }
+        );
+
+        let main = dir.path().join("main.ae");
+        let items = import_completions(
+            &context,
+            position,
+            Some(&main),
+            &[dir.path().to_path_buf()],
+            &WorkspaceIndex::new(),
+            &ModuleCache::new(),
+        );
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["./lib.ae", "./util/strings.ae"]);
+        assert!(
+            items
+                .iter()
+                .all(|item| item.kind == Some(CompletionItemKind::MODULE))
+        );
+
+        let index = WorkspaceIndex::new();
+        for module in modules::discover_workspace(dir.path(), &Default::default()) {
+            index.index_file(&module);
+        }
+        let indexed = import_completions(
+            &context,
+            position,
+            Some(&main),
+            &[dir.path().to_path_buf()],
+            &index,
+            &ModuleCache::new(),
+        );
+        let indexed_labels: Vec<&str> = indexed.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(indexed_labels, labels);
+    }
+
+    #[test]
+    fn test_dict_key_completion() {
+        let text = "Set CONFIG {\"host\": \"localhost\", \"port\": 8080, \"debug\": False}\nSet CONFIG[\"retries\"] 3\nPRINTLN(CONFIG[\"\"])";
+        let doc = crate::parser::Parser::new(text).parse();
+        let position = Position::new(2, 16);
+
+        assert_eq!(
+            dict_key_context(&doc, position),
+            Some(DictKeyContext {
+                name: "CONFIG".to_string(),
+                quoted: true,
+                start: 16,
+            })
+        );
+        let labels: Vec<String> = get_completions(&doc, position, 0, Locale::Zh)
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, vec!["host", "port", "debug", "retries"]);
+
+        // `CONFIG[` alone doesn't parse yet, so pair its context with the symbols above
+        let context = dict_key_context(&parse("CONFIG["), Position::new(0, 7)).unwrap();
+        assert!(!context.quoted);
+        let items = get_dict_key_completions(&doc.symbols, &context, Position::new(0, 7));
+        assert_eq!(items.len(), 4);
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "\"host\"");
+
+        // Name keys are strings, number keys are typed bare, computed keys are unknown
+        let text =
+            "Set CODES {OK: 1, 404: \"missing\", [NAME]: 0}\nSet CODES[500] 2\nPRINTLN(CODES[])";
+        let doc = crate::parser::Parser::new(text).parse();
+        let edits = |position| -> Vec<String> {
+            get_completions(&doc, position, 0, Locale::Zh)
+                .items
+                .into_iter()
+                .map(|item| match item.text_edit {
+                    Some(CompletionTextEdit::Edit(edit)) => edit.new_text,
+                    _ => panic!("expected a text edit"),
+                })
+                .collect()
+        };
+        assert_eq!(edits(Position::new(2, 14)), vec!["\"OK\"", "404", "500"]);
+        let context = dict_key_context(&parse("CODES[\""), Position::new(0, 7)).unwrap();
+        let labels: Vec<String> =
+            get_dict_key_completions(&doc.symbols, &context, Position::new(0, 7))
+                .into_iter()
+                .map(|item| item.label)
+                .collect();
+        assert_eq!(labels, vec!["OK"]);
+    }
+
+    #[test]
+    fn test_contexts_after_astral_characters() {
+        // `🙂` takes two UTF-16 units, so the cursor columns are one past the char counts
+        let text = "PRINTLN(\"🙂\", CONFIG[\"po";
+        assert_eq!(
+            dict_key_context(&parse(text), Position::new(0, 24)),
+            Some(DictKeyContext {
+                name: "CONFIG".to_string(),
+                quoted: true,
+                start: 22,
+            })
+        );
+        assert_eq!(
+            word_before(&parse("Set 🙂 TOT"), Position::new(0, 10)),
+            "TOT"
+        );
+        assert_eq!(
+            member_context(&parse("PRINTLN(\"🙂\", MATH.SQ"), Position::new(0, 22)),
+            Some("MATH".to_string())
+        );
+        assert_eq!(
+            import_context(&parse("Import {A} From \"./🙂/li"), Position::new(0, 24)),
+            Some(ImportContext::Path {
+                typed: "./🙂/li".to_string(),
+                start: 17,
+            })
+        );
+    }
+
+    #[test]
+    fn test_no_import_context_outside_imports() {
+        assert!(import_context(&parse("Set X \"./"), Position::new(0, 9)).is_none());
+        assert!(
+            import_context(&parse("Import {A} From \"./a.ae\""), Position::new(0, 24)).is_none()
+        );
+    }
+
+    const SHADOWING: &str = "Func MAP(XS, F) {\n    Return XS\n}\nSet TOTAL 0\nPRINTLN(TOTAL)";
+
+    #[test]
+    fn test_completion_ranking_buckets() {
+        let doc = crate::parser::Parser::new(SHADOWING).parse();
+        let items = get_completions(&doc, Position::new(4, 0), 0, Locale::Zh).items;
+
+        let bucket = |item: &CompletionItem| item.sort_text.as_ref().unwrap()[..1].to_string();
+        let buckets: Vec<String> = items.iter().map(bucket).collect();
+        let mut sorted = buckets.clone();
+        sorted.sort();
+        assert_eq!(buckets, sorted);
+
+        let local: Vec<&str> = items
+            .iter()
+            .filter(|item| bucket(item) == "0")
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(local, vec!["MAP", "TOTAL"]);
+        assert!(
+            items
+                .iter()
+                .any(|item| item.label == "Set" && bucket(item) == "1")
+        );
+        assert!(
+            items
+                .iter()
+                .any(|item| item.label == "FILTER" && bucket(item) == "2")
+        );
+    }
+
+    #[test]
+    fn test_user_function_shadows_builtin() {
+        let doc = crate::parser::Parser::new(SHADOWING).parse();
+        let items = get_completions(&doc, Position::new(4, 0), 0, Locale::Zh).items;
+
+        let maps: Vec<&CompletionItem> = items.iter().filter(|item| item.label == "MAP").collect();
+        assert_eq!(maps.len(), 1);
+        assert_eq!(maps[0].kind, Some(CompletionItemKind::FUNCTION));
+        assert_eq!(
+            maps[0].detail.as_deref(),
+            Some("Function: MAP(XS, F) { ... }")
+        );
+        let Some(Documentation::MarkupContent(doc)) = &maps[0].documentation else {
+            panic!("expected markdown documentation");
+        };
+        assert!(doc.value.contains("遮蔽了内置函数 `MAP`"));
+    }
+
+    #[test]
+    fn test_completion_prefiltering_marks_list_incomplete() {
+        let doc = crate::parser::Parser::new("Set FIRST 1\nPRINTLN(FI)").parse();
+        let list = get_completions(&doc, Position::new(1, 10), 5, Locale::Zh);
+        assert!(list.is_incomplete);
+        assert!(list.items.len() <= 5);
+        assert!(
+            list.items
+                .iter()
+                .all(|item| item.label.to_uppercase().starts_with("FI"))
+        );
+        assert_eq!(list.items[0].label, "FIRST");
+
+        let list = get_completions(&doc, Position::new(1, 10), 0, Locale::Zh);
+        assert!(!list.is_incomplete);
+    }
+
+    #[test]
+    fn test_case_labels_offer_the_values_of_a_string_status() {
+        let text = "Set STATUS \"pending\"\nIf (READY) {\n    Set STATUS \"done\"\n}\n\
+                    If (STATUS == \"failed\") {\n    PRINTLN(\"retry\")\n}\n\
+                    Switch (STATUS) {\n    Case \"done\":\n        Break\n    Case \n}";
+        let doc = crate::parser::Parser::new(text).parse();
+        let list = get_completions(&doc, Position::new(10, 9), 0, Locale::En);
+        let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["\"pending\"", "\"failed\"", "Default:"]);
+        assert_eq!(list.items[0].kind, Some(CompletionItemKind::VALUE));
+        assert_eq!(list.items[0].insert_text.as_deref(), Some("\"pending\":"));
+
+        let Some(CompletionTextEdit::Edit(edit)) = &list.items[2].text_edit else {
+            panic!("expected Default: to replace the Case keyword");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(10, 4), Position::new(10, 9))
+        );
+        assert_eq!(edit.new_text, "Default:\n\t$0");
+        assert_eq!(
+            list.items[2].insert_text_format,
+            Some(InsertTextFormat::SNIPPET)
+        );
+    }
+
+    #[test]
+    fn test_case_labels_fall_back_to_constants() {
+        let text = "Set RED 0\nSet GREEN 1\nSet SHOW Lambda C -> PRINTLN(C)\nSet COLOR READ()\n\
+                    Switch (COLOR) {\n    Case \n    Default:\n        Break\n}";
+        let doc = crate::parser::Parser::new(text).parse();
+        let labels: Vec<String> = get_completions(&doc, Position::new(5, 9), 0, Locale::En)
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        // No keywords or builtins, no lambdas, not the switched value itself, and
+        // no second Default
+        assert_eq!(labels, vec!["RED", "GREEN"]);
+    }
+}
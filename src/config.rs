@@ -0,0 +1,236 @@
+//! User-configurable server settings
+//!
+//! Settings arrive either as `initializationOptions` or through
+//! `workspace/didChangeConfiguration`, optionally nested under an `aether` key.
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use crate::extra_builtins::ExtraBuiltinsSource;
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Overrides the client's locale for hover, completion and diagnostic text
+    pub locale: Option<String>,
+    pub organize_imports: OrganizeImportsSettings,
+    pub completion: CompletionSettings,
+    pub lint: LintSettings,
+    pub index: IndexSettings,
+    pub analysis: AnalysisSettings,
+    pub perf: PerfSettings,
+    pub outline: OutlineSettings,
+    /// Most diagnostics reported per document, errors first; 0 reports all
+    pub max_problems: Option<usize>,
+    /// Host-provided builtins: an inline array or a path to a JSON/TOML file
+    pub extra_builtins: Option<ExtraBuiltinsSource>,
+    /// Read `if`, `WHILE` and other miscased keywords as keywords where a
+    /// statement starts, warning about each
+    pub lenient_keywords: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnalysisSettings {
+    /// Run the cross-file checks on every change instead of only on save
+    pub slow_checks_on_change: bool,
+    /// Let imports name any top-level definition, not only `Export`ed ones
+    pub implicit_exports: bool,
+    /// Split every document into cells at `// ---` lines, parsed one by one,
+    /// as `aether-repl:` buffers always are
+    pub cells: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutlineSettings {
+    /// Hold each run of consecutive top-level `Set`s in one container of the
+    /// nested outline, instead of listing them flat
+    pub group_sets: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PerfSettings {
+    /// Log a request or analysis step that takes at least this long, with its
+    /// usual timings; 0 logs none
+    pub slow_threshold_ms: u64,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IndexSettings {
+    /// Patterns, written like the lines of a root's `.aetherignore`, of files and
+    /// directories left out of the workspace index
+    pub exclude: Vec<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LintSettings {
+    /// Report calls to File/System builtins, for sandboxed embeddings
+    pub io: bool,
+    /// Report `Throw`s that no `Try` in the file can catch
+    pub uncaught_throw: bool,
+    pub complexity: ComplexitySettings,
+    pub shadowing: ShadowingSettings,
+    pub indentation: IndentationSettings,
+    pub heuristics: HeuristicSettings,
+    /// "Explain" opens the code's documentation page, where the client can,
+    /// instead of showing the explanation in a message
+    pub explain_with_docs: bool,
+}
+
+/// Hints that guess at intent from how a loop is written, on unless disabled
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HeuristicSettings {
+    /// Hint when a ForIndexed body uses its index like a value and its value
+    /// only as an index
+    pub swapped_loop_variables: bool,
+    /// Hint that a For over a dict gives its keys
+    pub dict_iteration: bool,
+}
+
+impl Default for HeuristicSettings {
+    fn default() -> Self {
+        HeuristicSettings {
+            swapped_loop_variables: true,
+            dict_iteration: true,
+        }
+    }
+}
+
+/// The indentation lint, off unless enabled
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IndentationSettings {
+    /// Report lines mixing tabs and spaces, and lines in a block indented off
+    /// the `width` grid
+    pub enabled: bool,
+    /// Columns per indentation level, and per tab
+    pub width: usize,
+    /// Indent with tabs rather than spaces
+    pub use_tabs: bool,
+}
+
+impl Default for IndentationSettings {
+    fn default() -> Self {
+        IndentationSettings {
+            enabled: false,
+            width: 4,
+            use_tabs: false,
+        }
+    }
+}
+
+/// How bindings that hide a builtin or an outer binding are reported
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShadowingSettings {
+    pub severity: ShadowingSeverity,
+    /// Also report bindings named like a builtin function
+    pub builtins: bool,
+}
+
+impl Default for ShadowingSettings {
+    fn default() -> Self {
+        ShadowingSettings {
+            severity: ShadowingSeverity::default(),
+            builtins: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ShadowingSeverity {
+    Off,
+    #[default]
+    Information,
+    Warning,
+}
+
+/// Limits for the function complexity lints; 0 turns a check off
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ComplexitySettings {
+    /// Most parameters a function may declare
+    pub max_params: usize,
+    /// Deepest `If`/`While`/`For`/`Switch` nesting allowed in a function body
+    pub max_nesting: usize,
+    /// Most statements in a function body, nested blocks included
+    pub max_statements: usize,
+}
+
+impl Default for ComplexitySettings {
+    fn default() -> Self {
+        ComplexitySettings {
+            max_params: 6,
+            max_nesting: 5,
+            max_statements: 80,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OrganizeImportsSettings {
+    /// Drop imported names that the unused-import lint flags
+    pub remove_unused: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CompletionSettings {
+    /// Above this many candidates the server filters by the typed prefix; 0 disables it
+    pub max_items: usize,
+    /// Templates offered at the start of a statement, next to the built-in ones
+    pub snippets: Vec<UserSnippet>,
+}
+
+#[cfg(feature = "server")]
+impl Default for CompletionSettings {
+    fn default() -> Self {
+        CompletionSettings {
+            max_items: 200,
+            snippets: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+/// A snippet from the settings; one with a built-in's label replaces it
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UserSnippet {
+    pub label: String,
+    /// The inserted text, in LSP snippet syntax
+    pub body: String,
+    pub description: Option<String>,
+}
+
+#[cfg(feature = "server")]
+/// `maxProblems` when the client doesn't set it
+pub const DEFAULT_MAX_PROBLEMS: usize = 500;
+
+#[cfg(feature = "server")]
+impl Settings {
+    pub fn max_problems(&self) -> usize {
+        self.max_problems.unwrap_or(DEFAULT_MAX_PROBLEMS)
+    }
+
+    /// Read settings from a JSON payload
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        let section = value.get("aether").unwrap_or(value);
+        serde_json::from_value(section.clone())
+    }
+}
@@ -0,0 +1,95 @@
+//! Folding names that read the same
+//!
+//! `USER_ID`, `User_Id` and `ＵSER_ID` (a fullwidth `Ｕ`) are different names to
+//! Aether but look alike to a reader. [`normalize`] maps them to one form:
+//! uppercased, fullwidth ASCII folded to ASCII, and Cyrillic and Greek capitals
+//! that look like Latin ones replaced by those. Other compatibility forms, such
+//! as mathematical alphanumerics and ligatures, are left as they are. The lookalike-name lint groups definitions by it, and
+//! did-you-mean suggestions compare names through it.
+
+/// Cyrillic and Greek capitals drawn the same as a Latin capital
+const LOOKALIKES: &[(char, char)] = &[
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('Ѕ', 'S'),
+    ('І', 'I'),
+    ('Ј', 'J'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Т', 'T'),
+    ('У', 'Y'),
+    ('Х', 'X'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'I'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Υ', 'Y'),
+    ('Χ', 'X'),
+];
+
+/// Fullwidth ASCII (`Ａ`, `＿`, `１`) and the ideographic space as ASCII
+fn fullwidth_fold(ch: char) -> char {
+    match ch {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+        '\u{3000}' => ' ',
+        _ => ch,
+    }
+}
+
+/// One character of the normalized form
+fn fold(ch: char) -> impl Iterator<Item = char> {
+    fullwidth_fold(ch).to_uppercase().map(|upper| {
+        LOOKALIKES
+            .iter()
+            .find(|(lookalike, _)| *lookalike == upper)
+            .map_or(upper, |(_, latin)| *latin)
+    })
+}
+
+/// The form names that read the same share
+pub fn normalize(name: &str) -> String {
+    name.chars().flat_map(fold).collect()
+}
+
+/// The characters of `name` that only look like the letters they are read as,
+/// as opposed to differing from them by case
+pub fn lookalike_chars(name: &str) -> Vec<char> {
+    name.chars()
+        .filter(|ch| !fold(*ch).eq(ch.to_uppercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("User_Id"), "USER_ID");
+        assert_eq!(normalize("ＵSER＿ID"), "USER_ID");
+        // Cyrillic А and Е, lowercase and capital
+        assert_eq!(normalize("dаtЕ"), "DATE");
+        assert_eq!(normalize("ΚΟΡ"), "KOP");
+        assert_eq!(normalize("名字"), "名字");
+    }
+
+    #[test]
+    fn test_lookalike_chars() {
+        assert_eq!(lookalike_chars("User_Id"), Vec::<char>::new());
+        assert_eq!(lookalike_chars("ＵSER_ID"), vec!['Ｕ']);
+        assert_eq!(lookalike_chars("СOUNT"), vec!['С']);
+    }
+}
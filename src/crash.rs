@@ -0,0 +1,122 @@
+//! Surviving panics in request handlers
+//!
+//! A panic in parsing or analysis would otherwise unwind through the server
+//! loop and take every open file down with it. Handlers run inside
+//! [`catch_unwind`]; the panic is reported and the request gets an empty
+//! answer. With `--crash-dump <dir>` the text of the offending document is
+//! also written out so it can be attached to a bug report.
+
+use std::any::Any;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static DUMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Write documents that make a handler panic to `dir`; only the first call has an effect
+pub fn enable_dumps(dir: PathBuf) {
+    let _ = DUMP_DIR.set(dir);
+}
+
+/// Save `text` for a bug report if dumps are enabled
+pub fn dump(uri: &str, text: &str) -> Option<io::Result<PathBuf>> {
+    DUMP_DIR.get().map(|dir| write_dump(dir, uri, text))
+}
+
+fn write_dump(dir: &Path, uri: &str, text: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    let name: String = uri
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = dir.join(format!("crash-{}-{}", millis, name));
+    fs::write(&path, format!("// {}\n{}", uri, text))?;
+    Ok(path)
+}
+
+/// The message a panic was raised with
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Resolve to `Err` with the panic payload if polling `future` panics
+pub fn catch_unwind<F: Future>(future: F) -> CatchUnwind<F> {
+    CatchUnwind(Box::pin(future))
+}
+
+pub struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catch_unwind_reports_message() {
+        let answer = catch_unwind(async { 42 }).await;
+        assert_eq!(answer.unwrap(), 42);
+
+        let index = 3;
+        let payload = catch_unwind(async move { [1, 2][index] })
+            .await
+            .unwrap_err();
+        assert!(panic_message(&*payload).contains("index out of bounds"));
+    }
+
+    #[test]
+    fn test_write_dump() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_dump(
+            &dir.path().join("dumps"),
+            "file:///project/my%20file.ae",
+            "Set X 1",
+        )
+        .unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            name.starts_with("crash-") && name.ends_with("-my_20file.ae"),
+            "{}",
+            name
+        );
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "// file:///project/my%20file.ae\nSet X 1"
+        );
+    }
+}
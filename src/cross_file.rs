@@ -0,0 +1,663 @@
+//! Checks that read other modules
+//!
+//! These are slower than the per-document lints in `diagnostics`, so the
+//! backend runs them when a document is saved (or on every change when
+//! `analysis.slowChecksOnChange` is set) and keeps their results apart from
+//! the fast ones.
+
+use std::path::{Path, PathBuf};
+
+use lsp_types::*;
+
+use crate::ast::{Expr, ExprKind, Ident};
+use crate::confusables;
+use crate::diagnostic_codes as codes;
+use crate::diagnostics::{deprecated_call_diagnostic, deprecation_replacement};
+use crate::locale::Locale;
+use crate::modules;
+use crate::parser::ParsedDocument;
+use crate::visit::{Visitor, walk_expr};
+use crate::workspace::{ModuleSummary, WorkspaceIndex};
+
+/// Suggestions further than this many edits from the typed name aren't offered
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// What the cross-file checks need beyond the document and the index
+pub struct CrossFileOptions<'a> {
+    pub locale: Locale,
+    /// Every top-level definition counts as exported, not only `Export`ed names
+    pub implicit_exports: bool,
+    /// The editor's copy of an open module, newer than the indexed one from disk
+    pub open_module: &'a dyn Fn(&Path) -> Option<ModuleSummary>,
+    /// Workspace folders searched for modules not found next to the document
+    pub roots: &'a [PathBuf],
+}
+
+/// Cross-file diagnostics for the document saved at `document_path`
+pub fn analyze(
+    doc: &ParsedDocument,
+    document_path: &Path,
+    index: &WorkspaceIndex,
+    options: &CrossFileOptions,
+) -> Vec<Diagnostic> {
+    if !doc.errors.is_empty() {
+        return Vec::new();
+    }
+    let Some(base_dir) = document_path.parent() else {
+        return Vec::new();
+    };
+    let mut diagnostics = check_imported_names(doc, base_dir, index, options);
+    diagnostics.extend(check_namespace_members(doc, base_dir, index, options));
+    diagnostics.extend(check_import_cycles(doc, document_path, index, options));
+    diagnostics.extend(check_deprecated_imports(doc, base_dir, index, options));
+    diagnostics
+}
+
+impl CrossFileOptions<'_> {
+    fn module(&self, path: &Path, index: &WorkspaceIndex) -> Option<ModuleSummary> {
+        (self.open_module)(path).or_else(|| index.summary_or_load(path))
+    }
+
+    /// The names a module lets others import
+    fn provided_names<'s>(&self, summary: &'s ModuleSummary) -> Vec<&'s str> {
+        let mut names: Vec<&str> = summary
+            .exports
+            .iter()
+            .map(|export| export.name.as_str())
+            .collect();
+        if self.implicit_exports {
+            names.extend(summary.symbols.iter().map(|symbol| symbol.name.as_str()));
+        }
+        names
+    }
+}
+
+/// Flag imported names the target module doesn't provide. Whole-module imports
+/// bind no names to check, and missing modules are E005's job.
+fn check_imported_names(
+    doc: &ParsedDocument,
+    base_dir: &Path,
+    index: &WorkspaceIndex,
+    options: &CrossFileOptions,
+) -> Vec<Diagnostic> {
+    let locale = options.locale;
+    let mut diagnostics = Vec::new();
+    for import in doc.ast.imports().iter().filter(|import| import.braced) {
+        let module = modules::resolve_import_in(base_dir, options.roots, &import.path);
+        let Some(summary) = options.module(&module, index) else {
+            continue;
+        };
+        let available = options.provided_names(&summary);
+
+        for name in &import.names {
+            if available.contains(&name.as_str()) {
+                continue;
+            }
+            let suggestion = closest_name(name.as_str(), &available);
+            diagnostics.push(Diagnostic {
+                range: name.span,
+                severity: Some(codes::NOT_EXPORTED.severity),
+                code: codes::NOT_EXPORTED.lsp_code(),
+                code_description: codes::NOT_EXPORTED.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match (locale, suggestion) {
+                    (Locale::Zh, Some(suggestion)) => format!(
+                        "模块 '{}' 没有导出 '{}'\n是否想导入: {}",
+                        import.path, name.name, suggestion
+                    ),
+                    (Locale::Zh, None) => {
+                        format!("模块 '{}' 没有导出 '{}'", import.path, name.name)
+                    }
+                    (Locale::En, Some(suggestion)) => format!(
+                        "'{}' is not exported by '{}'\nDid you mean: {}",
+                        name.name, import.path, suggestion
+                    ),
+                    (Locale::En, None) => {
+                        format!("'{}' is not exported by '{}'", name.name, import.path)
+                    }
+                },
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Flag `NAMESPACE.MEMBER` where the module imported as `NAMESPACE` has no such export
+fn check_namespace_members(
+    doc: &ParsedDocument,
+    base_dir: &Path,
+    index: &WorkspaceIndex,
+    options: &CrossFileOptions,
+) -> Vec<Diagnostic> {
+    let mut collector = MemberCollector::default();
+    collector.visit_block(&doc.ast);
+
+    let mut diagnostics = Vec::new();
+    for (namespace, member) in collector.members {
+        let Some(import) = doc.ast.namespace_import(namespace) else {
+            continue;
+        };
+        let module = modules::resolve_import_in(base_dir, options.roots, &import.path);
+        let Some(summary) = options.module(&module, index) else {
+            continue;
+        };
+        let available = options.provided_names(&summary);
+        if available.contains(&member.name.as_str()) {
+            continue;
+        }
+        let suggestion = closest_name(&member.name, &available);
+        let message = match options.locale {
+            Locale::Zh => format!("模块 '{}' 没有成员 '{}'", import.path, member.name),
+            Locale::En => format!("Module '{}' has no member '{}'", import.path, member.name),
+        };
+        diagnostics.push(Diagnostic {
+            range: member.span,
+            severity: Some(codes::NO_SUCH_MEMBER.severity),
+            code: codes::NO_SUCH_MEMBER.lsp_code(),
+            code_description: codes::NO_SUCH_MEMBER.code_description(),
+            source: Some("aether-lint".to_string()),
+            message: match (options.locale, suggestion) {
+                (Locale::Zh, Some(suggestion)) => {
+                    format!("{}\n是否想访问: {}", message, suggestion)
+                }
+                (Locale::En, Some(suggestion)) => {
+                    format!("{}\nDid you mean: {}", message, suggestion)
+                }
+                (_, None) => message,
+            },
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+    diagnostics
+}
+
+/// Flag calls of imported functions their module marks `@deprecated`, whether
+/// imported by name (`OLD(...)`) or through a namespace (`LIB.OLD(...)`)
+fn check_deprecated_imports(
+    doc: &ParsedDocument,
+    base_dir: &Path,
+    index: &WorkspaceIndex,
+    options: &CrossFileOptions,
+) -> Vec<Diagnostic> {
+    let mut collector = CallCollector::default();
+    collector.visit_block(&doc.ast);
+    if collector.callees.is_empty() {
+        return Vec::new();
+    }
+    let summary_of = |path: &str| {
+        options.module(
+            &modules::resolve_import_in(base_dir, options.roots, path),
+            index,
+        )
+    };
+    // Replacements for a name imported by name must be callable here too
+    let is_local_function = |word: &str| {
+        doc.symbols
+            .functions
+            .iter()
+            .chain(&doc.symbols.imports)
+            .any(|symbol| symbol.name.as_str() == word)
+    };
+
+    // Each deprecated binding: the local name, the original one and the note
+    let mut deprecated: Vec<(&str, String, String)> = Vec::new();
+    for import in doc.ast.imports().iter().filter(|import| import.braced) {
+        let Some(summary) = summary_of(&import.path) else {
+            continue;
+        };
+        for (name, alias) in import.names.iter().zip(&import.aliases) {
+            if let Some(note) = summary
+                .symbol(&name.name)
+                .and_then(|symbol| symbol.deprecated.clone())
+            {
+                let binding = alias.as_ref().unwrap_or(name);
+                deprecated.push((binding.as_str(), name.to_string(), note));
+            }
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for callee in collector.callees {
+        match &callee.kind {
+            ExprKind::Identifier(name) => {
+                let Some((_, original, note)) = deprecated
+                    .iter()
+                    .find(|(binding, _, _)| *binding == name.as_str())
+                else {
+                    continue;
+                };
+                diagnostics.push(deprecated_call_diagnostic(
+                    callee.span,
+                    original,
+                    note,
+                    deprecation_replacement(note, original, is_local_function),
+                    options.locale,
+                ));
+            }
+            ExprKind::Member { object, member } => {
+                let ExprKind::Identifier(namespace) = &object.kind else {
+                    continue;
+                };
+                let Some(summary) = doc
+                    .ast
+                    .namespace_import(namespace)
+                    .and_then(|import| summary_of(&import.path))
+                else {
+                    continue;
+                };
+                let Some(note) = summary
+                    .symbol(&member.name)
+                    .and_then(|symbol| symbol.deprecated.as_ref())
+                else {
+                    continue;
+                };
+                let provided = options.provided_names(&summary);
+                diagnostics.push(deprecated_call_diagnostic(
+                    member.span,
+                    &member.name,
+                    note,
+                    deprecation_replacement(note, &member.name, |word| provided.contains(&word)),
+                    options.locale,
+                ));
+            }
+            _ => {}
+        }
+    }
+    diagnostics
+}
+
+/// The callee of every call
+#[derive(Default)]
+struct CallCollector<'a> {
+    callees: Vec<&'a Expr>,
+}
+
+impl<'a> Visitor<'a> for CallCollector<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Call { func, .. } = &expr.kind {
+            self.callees.push(func);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Every `NAME.MEMBER` access whose object is a plain identifier
+#[derive(Default)]
+struct MemberCollector<'a> {
+    members: Vec<(&'a str, &'a Ident)>,
+}
+
+impl<'a> Visitor<'a> for MemberCollector<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Member { object, member } = &expr.kind
+            && let ExprKind::Identifier(namespace) = &object.kind
+        {
+            self.members.push((namespace, member));
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// The candidate fewest edits away from `name`, if any is close enough; names
+/// that differ only by case or lookalike characters count as no edit apart
+fn closest_name<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let name = confusables::normalize(name);
+    candidates
+        .iter()
+        .map(|candidate| {
+            let distance = edit_distance(&name, &confusables::normalize(candidate));
+            (distance, *candidate)
+        })
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Flag each import through which the document ends up importing itself
+fn check_import_cycles(
+    doc: &ParsedDocument,
+    document_path: &Path,
+    index: &WorkspaceIndex,
+    options: &CrossFileOptions,
+) -> Vec<Diagnostic> {
+    let locale = options.locale;
+    let base_dir = document_path.parent().unwrap_or(Path::new(""));
+    let mut diagnostics = Vec::new();
+    for import in doc.ast.imports() {
+        let target = modules::resolve_import_in(base_dir, options.roots, &import.path);
+        let chain = if target == document_path {
+            Vec::new()
+        } else {
+            match index.import_chain(&target, document_path) {
+                Some(chain) => chain,
+                None => continue,
+            }
+        };
+
+        let mut cycle: Vec<&Path> = vec![document_path, &target];
+        cycle.extend(chain.iter().map(|(_, step)| step.path.as_path()));
+        let cycle = cycle
+            .iter()
+            .map(|path| display_path(path, base_dir))
+            .collect::<Vec<_>>()
+            .join(" → ");
+
+        let related: Vec<DiagnosticRelatedInformation> = chain
+            .iter()
+            .filter_map(|(module, step)| {
+                Some(DiagnosticRelatedInformation {
+                    location: Location::new(Url::from_file_path(module).ok()?, step.range),
+                    message: match locale {
+                        Locale::Zh => format!(
+                            "'{}' 导入了 '{}'",
+                            display_path(module, base_dir),
+                            display_path(&step.path, base_dir)
+                        ),
+                        Locale::En => format!(
+                            "'{}' imports '{}'",
+                            display_path(module, base_dir),
+                            display_path(&step.path, base_dir)
+                        ),
+                    },
+                })
+            })
+            .collect();
+
+        diagnostics.push(Diagnostic {
+            range: import.span,
+            severity: Some(codes::IMPORT_CYCLE.severity),
+            code: codes::IMPORT_CYCLE.lsp_code(),
+            code_description: codes::IMPORT_CYCLE.code_description(),
+            source: Some("aether-lint".to_string()),
+            message: match locale {
+                Locale::Zh => format!("循环导入: {}", cycle),
+                Locale::En => format!("Import cycle: {}", cycle),
+            },
+            related_information: (!related.is_empty()).then_some(related),
+            tags: None,
+            data: None,
+        });
+    }
+    diagnostics
+}
+
+/// A module path relative to the document's directory, the way an import would spell it
+fn display_path(path: &Path, base_dir: &Path) -> String {
+    let common = path
+        .components()
+        .zip(base_dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative: PathBuf = base_dir.components().skip(common).map(|_| "..").collect();
+    relative.extend(path.components().skip(common));
+    relative.display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::workspace;
+    use std::fs;
+
+    fn analyze_file(path: &Path, index: &WorkspaceIndex) -> Vec<Diagnostic> {
+        let doc = Parser::new(&fs::read_to_string(path).unwrap()).parse();
+        let options = CrossFileOptions {
+            locale: Locale::En,
+            implicit_exports: false,
+            open_module: &|_| None,
+            roots: &[],
+        };
+        analyze(&doc, path, index, &options)
+    }
+
+    #[test]
+    fn test_imported_names_against_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("lib.ae"),
+            "Func HELPER(X) {\n    Return X\n}\nSet INTERNAL 1\nExport HELPER",
+        )
+        .unwrap();
+        fs::write(
+            root.join("main.ae"),
+            "Import {HELPER} From \"./lib.ae\"\n\
+             Import {HELPERR} From \"./lib.ae\"\n\
+             Import {HELPER As H, INTERNAL As I} From \"./lib.ae\"\n\
+             Import LIB From \"./lib.ae\"\n\
+             Import {ANYTHING} From \"./missing.ae\"",
+        )
+        .unwrap();
+        let index = WorkspaceIndex::new();
+
+        let diagnostics = analyze_file(&root.join("main.ae"), &index);
+        let found: Vec<(u32, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    "'HELPERR' is not exported by './lib.ae'\nDid you mean: HELPER"
+                ),
+                (2, "'INTERNAL' is not exported by './lib.ae'"),
+            ]
+        );
+        assert_eq!(diagnostics[0].range.start.character, 8);
+        assert_eq!(diagnostics[1].range.start.character, 21);
+
+        // Top-level definitions count when exports are implicit, and open buffers win over disk
+        let doc = Parser::new(&fs::read_to_string(root.join("main.ae")).unwrap()).parse();
+        let edited = workspace::summarize(
+            &root.join("lib.ae"),
+            &Parser::new("Func HELPERR() {\n    Return 1\n}\nExport HELPERR").parse(),
+            &[],
+        );
+        let options = CrossFileOptions {
+            locale: Locale::En,
+            implicit_exports: true,
+            open_module: &|path| (path == root.join("lib.ae")).then(|| edited.clone()),
+            roots: &[],
+        };
+        let messages: Vec<String> = analyze(&doc, &root.join("main.ae"), &index, &options)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'HELPER' is not exported by './lib.ae'\nDid you mean: HELPERR",
+                "'HELPER' is not exported by './lib.ae'\nDid you mean: HELPERR",
+                "'INTERNAL' is not exported by './lib.ae'",
+            ]
+        );
+        assert_eq!(edit_distance("HELPER", "HELPERR"), 1);
+        assert_eq!(edit_distance("ADD", "SUB"), 3);
+        // A case or lookalike difference beats a one-letter typo
+        assert_eq!(
+            closest_name("Helper", &["HELPERS", "HELPER"]),
+            Some("HELPER")
+        );
+        assert_eq!(
+            closest_name("ＨELPER", &["HELPERS", "HELPER"]),
+            Some("HELPER")
+        );
+    }
+
+    #[test]
+    fn test_namespace_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("utils.ae"),
+            "Func PARSE_CONFIG(PATH) {\n    Return PATH\n}\nExport PARSE_CONFIG",
+        )
+        .unwrap();
+        fs::write(
+            root.join("main.ae"),
+            "Import UTILS From \"./utils.ae\"\n\
+             Set CONFIG UTILS.PARSE_CONFIG(\"a.json\")\n\
+             Set OTHER UTILS.PARSE_CONFIGS(\"b.json\")\n\
+             PRINTLN(UTILS.VERSION, CONFIG.PARSE_CONFIGS)",
+        )
+        .unwrap();
+
+        let diagnostics = analyze_file(&root.join("main.ae"), &WorkspaceIndex::new());
+        let found: Vec<(Position, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    Position::new(2, 16),
+                    "Module './utils.ae' has no member 'PARSE_CONFIGS'\nDid you mean: PARSE_CONFIG"
+                ),
+                (
+                    Position::new(3, 14),
+                    "Module './utils.ae' has no member 'VERSION'"
+                ),
+            ]
+        );
+        assert!(
+            diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.code == Some(NumberOrString::String("E007".into())))
+        );
+    }
+
+    #[test]
+    fn test_import_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("lib")).unwrap();
+        fs::write(
+            root.join("a.ae"),
+            "Import {B} From \"./lib/b.ae\"\nSet A 1\nExport A",
+        )
+        .unwrap();
+        fs::write(
+            root.join("lib/b.ae"),
+            "Set B 2\nImport {C} From \"./c.ae\"\nExport B",
+        )
+        .unwrap();
+        fs::write(
+            root.join("lib/c.ae"),
+            "Import {A} From \"../a.ae\"\nSet C 3\nExport C",
+        )
+        .unwrap();
+        fs::write(
+            root.join("self.ae"),
+            "Set S 1\nImport {S} From \"./self.ae\"\nExport S",
+        )
+        .unwrap();
+        fs::write(root.join("leaf.ae"), "Import {A} From \"./a.ae\"").unwrap();
+        let index = WorkspaceIndex::new();
+
+        let diagnostics = analyze_file(&root.join("a.ae"), &index);
+        assert_eq!(diagnostics.len(), 1);
+        let cycle = &diagnostics[0];
+        assert_eq!(cycle.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            cycle.message,
+            "Import cycle: a.ae → lib/b.ae → lib/c.ae → a.ae"
+        );
+        assert_eq!(cycle.range.start, Position::new(0, 0));
+        let related = cycle.related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 2);
+        assert_eq!(
+            related[0].location.uri,
+            Url::from_file_path(root.join("lib/b.ae")).unwrap()
+        );
+        assert_eq!(related[0].location.range.start.line, 1);
+        assert_eq!(related[1].message, "'lib/c.ae' imports 'a.ae'");
+
+        // The other members of the cycle see it from their side
+        let from_c = analyze_file(&root.join("lib/c.ae"), &index);
+        assert_eq!(
+            from_c[0].message,
+            "Import cycle: c.ae → ../a.ae → b.ae → c.ae"
+        );
+
+        let self_import = analyze_file(&root.join("self.ae"), &index);
+        assert_eq!(self_import[0].message, "Import cycle: self.ae → self.ae");
+        assert_eq!(self_import[0].range.start.line, 1);
+        assert!(self_import[0].related_information.is_none());
+
+        // Importing into a cycle isn't part of it
+        assert!(analyze_file(&root.join("leaf.ae"), &index).is_empty());
+    }
+
+    #[test]
+    fn test_calls_of_deprecated_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("lib.ae"),
+            "// @deprecated use NEW_SUM\nFunc OLD_SUM(XS) {\n    Return 0\n}\n\
+             Func NEW_SUM(XS) {\n    Return 1\n}\nExport OLD_SUM\nExport NEW_SUM",
+        )
+        .unwrap();
+        fs::write(
+            root.join("main.ae"),
+            "Import {OLD_SUM} From \"./lib.ae\"\nImport LIB From \"./lib.ae\"\n\
+             PRINTLN(OLD_SUM([1]), LIB.OLD_SUM([2]))",
+        )
+        .unwrap();
+        let index = WorkspaceIndex::new();
+
+        let diagnostics = analyze_file(&root.join("main.ae"), &index);
+        let found: Vec<(Range, &str, Option<&serde_json::Value>)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range,
+                    diagnostic.message.as_str(),
+                    diagnostic.data.as_ref(),
+                )
+            })
+            .collect();
+        let replacement = serde_json::json!({ "replacement": "NEW_SUM" });
+        assert_eq!(
+            found,
+            vec![
+                // NEW_SUM isn't imported by name, so only the namespaced call
+                // can switch to it
+                (
+                    Range::new(Position::new(2, 8), Position::new(2, 15)),
+                    "'OLD_SUM' is deprecated: use NEW_SUM",
+                    None
+                ),
+                (
+                    Range::new(Position::new(2, 26), Position::new(2, 33)),
+                    "'OLD_SUM' is deprecated: use NEW_SUM",
+                    Some(&replacement)
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,978 @@
+//! Catalog of every diagnostic code the server reports
+//!
+//! Parse errors get their code from the [`ParseError`](crate::parser::ParseError)
+//! variant and each lint rule names its constant here, so codes never depend on
+//! the (localized) message text. `aether/diagnosticCodes` serves the catalog to
+//! clients for a problems-panel legend.
+
+use lsp_types::{CodeDescription, DiagnosticSeverity, NumberOrString, Url};
+use serde::Serialize;
+
+const DOCS_BASE: &str = "https://github.com/xiaozuhui/aether-lang/wiki/diagnostics";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticCode {
+    pub code: &'static str,
+    pub severity: DiagnosticSeverity,
+    pub description: &'static str,
+    pub description_en: &'static str,
+    /// Page explaining the code; `None` for the shared diagnostics page
+    docs: Option<&'static str>,
+}
+
+impl DiagnosticCode {
+    const fn new(
+        code: &'static str,
+        severity: DiagnosticSeverity,
+        description: &'static str,
+        description_en: &'static str,
+    ) -> Self {
+        DiagnosticCode {
+            code,
+            severity,
+            description,
+            description_en,
+            docs: None,
+        }
+    }
+
+    pub fn lsp_code(&self) -> Option<NumberOrString> {
+        Some(NumberOrString::String(self.code.to_string()))
+    }
+
+    pub fn docs_url(&self) -> String {
+        match self.docs {
+            Some(url) => url.to_string(),
+            None => format!("{}#{}", DOCS_BASE, self.code.to_lowercase()),
+        }
+    }
+
+    pub fn code_description(&self) -> Option<CodeDescription> {
+        Url::parse(&self.docs_url())
+            .ok()
+            .map(|href| CodeDescription { href })
+    }
+
+    pub fn catalog_entry(&self) -> DiagnosticCodeEntry {
+        DiagnosticCodeEntry {
+            code: self.code,
+            severity: match self.severity {
+                DiagnosticSeverity::ERROR => "error",
+                DiagnosticSeverity::WARNING => "warning",
+                DiagnosticSeverity::INFORMATION => "information",
+                _ => "hint",
+            },
+            description: self.description,
+            description_en: self.description_en,
+            docs_url: self.docs_url(),
+        }
+    }
+}
+
+pub const SYNTAX_ERROR: DiagnosticCode = DiagnosticCode::new(
+    "E000",
+    DiagnosticSeverity::ERROR,
+    "语法错误",
+    "Syntax error",
+);
+pub const INVALID_IDENTIFIER: DiagnosticCode = DiagnosticCode::new(
+    "E001",
+    DiagnosticSeverity::ERROR,
+    "无效的标识符",
+    "Invalid identifier",
+);
+pub const UNEXPECTED_TOKEN: DiagnosticCode = DiagnosticCode::new(
+    "E002",
+    DiagnosticSeverity::ERROR,
+    "此处不能开始表达式",
+    "Unexpected token where an expression should start",
+);
+pub const EXPECTED_TOKEN: DiagnosticCode = DiagnosticCode::new(
+    "E003",
+    DiagnosticSeverity::ERROR,
+    "缺少预期的符号",
+    "Expected a different token",
+);
+pub const INVALID_EXPRESSION: DiagnosticCode = DiagnosticCode::new(
+    "E004",
+    DiagnosticSeverity::ERROR,
+    "无效的表达式",
+    "Invalid expression",
+);
+pub const MISSING_MODULE: DiagnosticCode = DiagnosticCode::new(
+    "E005",
+    DiagnosticSeverity::ERROR,
+    "导入的模块不存在",
+    "Imported module not found",
+);
+pub const NOT_EXPORTED: DiagnosticCode = DiagnosticCode::new(
+    "E006",
+    DiagnosticSeverity::ERROR,
+    "导入的名称未被模块导出",
+    "Imported name is not exported by the module",
+);
+pub const NO_SUCH_MEMBER: DiagnosticCode = DiagnosticCode::new(
+    "E007",
+    DiagnosticSeverity::ERROR,
+    "模块没有该成员",
+    "Module has no such member",
+);
+pub const ASSIGN_IN_CONDITION: DiagnosticCode = DiagnosticCode::new(
+    "E008",
+    DiagnosticSeverity::ERROR,
+    "条件中用了 '=' 而不是 '=='",
+    "'=' instead of '==' in a condition",
+);
+pub const ASSIGN_WITHOUT_SET: DiagnosticCode = DiagnosticCode::new(
+    "E009",
+    DiagnosticSeverity::ERROR,
+    "用 '=' 而不是 Set 赋值",
+    "Assignment with '=' instead of Set",
+);
+pub const NOT_ITERABLE: DiagnosticCode = DiagnosticCode::new(
+    "E010",
+    DiagnosticSeverity::ERROR,
+    "For 循环遍历数字、布尔值或 Null 字面量",
+    "For loop over a Number, Boolean or Null literal",
+);
+pub const CALLBACK_ARITY: DiagnosticCode = DiagnosticCode::new(
+    "E011",
+    DiagnosticSeverity::ERROR,
+    "回调函数的参数个数与内置函数传入的不符",
+    "Callback takes a different number of arguments than the builtin passes",
+);
+pub const UNEXPECTED_EOF: DiagnosticCode = DiagnosticCode::new(
+    "E012",
+    DiagnosticSeverity::ERROR,
+    "文件意外结束",
+    "Unexpected end of file",
+);
+pub const NAMING_CONVENTION: DiagnosticCode = DiagnosticCode {
+    docs: Some("https://github.com/xiaozuhui/aether-lang/wiki/naming-conventions"),
+    ..DiagnosticCode::new(
+        "W001",
+        DiagnosticSeverity::WARNING,
+        "名称应使用 UPPER_SNAKE_CASE",
+        "Name should use UPPER_SNAKE_CASE",
+    )
+};
+pub const UNUSED_IMPORT: DiagnosticCode = DiagnosticCode::new(
+    "W002",
+    DiagnosticSeverity::HINT,
+    "未使用的导入",
+    "Unused import",
+);
+pub const BUILTIN_ARITY: DiagnosticCode = DiagnosticCode::new(
+    "W003",
+    DiagnosticSeverity::WARNING,
+    "内置函数参数个数错误",
+    "Wrong number of arguments to a builtin",
+);
+pub const IO_USAGE: DiagnosticCode = DiagnosticCode::new(
+    "W004",
+    DiagnosticSeverity::INFORMATION,
+    "调用了文件或系统 IO 内置函数",
+    "Call to a File/System IO builtin",
+);
+pub const DEPRECATED_BUILTIN: DiagnosticCode = DiagnosticCode::new(
+    "W005",
+    DiagnosticSeverity::WARNING,
+    "已弃用的内置函数",
+    "Deprecated builtin",
+);
+pub const UNDEFINED_IDENTIFIER: DiagnosticCode = DiagnosticCode::new(
+    "W006",
+    DiagnosticSeverity::WARNING,
+    "未定义的标识符",
+    "Undefined identifier",
+);
+pub const IMPORT_CYCLE: DiagnosticCode = DiagnosticCode::new(
+    "W007",
+    DiagnosticSeverity::WARNING,
+    "循环导入",
+    "Import cycle",
+);
+pub const LAZY_NEVER_FORCED: DiagnosticCode = DiagnosticCode::new(
+    "W008",
+    DiagnosticSeverity::HINT,
+    "Lazy 值从未被 Force",
+    "Lazy value is never forced",
+);
+pub const UNUSED_EXPRESSION: DiagnosticCode = DiagnosticCode::new(
+    "W009",
+    DiagnosticSeverity::WARNING,
+    "表达式的结果未被使用",
+    "Expression result is unused",
+);
+pub const SELF_ASSIGNMENT: DiagnosticCode = DiagnosticCode::new(
+    "W010",
+    DiagnosticSeverity::WARNING,
+    "变量赋值给自身",
+    "Variable assigned to itself",
+);
+pub const DEAD_STORE: DiagnosticCode = DiagnosticCode::new(
+    "W011",
+    DiagnosticSeverity::HINT,
+    "赋值在被读取前就被覆盖",
+    "Assignment is overwritten before it is read",
+);
+pub const TOO_MANY_PARAMS: DiagnosticCode = DiagnosticCode::new(
+    "W012",
+    DiagnosticSeverity::WARNING,
+    "函数参数过多",
+    "Function has too many parameters",
+);
+pub const TOO_DEEPLY_NESTED: DiagnosticCode = DiagnosticCode::new(
+    "W013",
+    DiagnosticSeverity::WARNING,
+    "函数中的代码块嵌套过深",
+    "Function nests blocks too deeply",
+);
+pub const TOO_MANY_STATEMENTS: DiagnosticCode = DiagnosticCode::new(
+    "W014",
+    DiagnosticSeverity::WARNING,
+    "函数语句过多",
+    "Function has too many statements",
+);
+pub const SHADOWED_BUILTIN: DiagnosticCode = DiagnosticCode::new(
+    "W015",
+    DiagnosticSeverity::INFORMATION,
+    "绑定遮蔽了内置函数",
+    "Binding shadows a builtin function",
+);
+pub const SHADOWED_BINDING: DiagnosticCode = DiagnosticCode::new(
+    "W016",
+    DiagnosticSeverity::INFORMATION,
+    "绑定遮蔽了外层作用域中的同名绑定",
+    "Binding shadows one in an enclosing scope",
+);
+pub const FORCE_NOT_LAZY: DiagnosticCode = DiagnosticCode::new(
+    "W017",
+    DiagnosticSeverity::INFORMATION,
+    "Force 的参数不是 Lazy 值",
+    "Force applied to a value that is not Lazy",
+);
+pub const UNCAUGHT_THROW: DiagnosticCode = DiagnosticCode::new(
+    "W018",
+    DiagnosticSeverity::INFORMATION,
+    "抛出的错误不会被任何 Try 捕获",
+    "Thrown error is not caught by any Try",
+);
+pub const EMPTY_CATCH: DiagnosticCode = DiagnosticCode::new(
+    "W019",
+    DiagnosticSeverity::WARNING,
+    "空的 Catch 块会静默吞掉错误",
+    "Empty Catch block silently swallows the error",
+);
+pub const TRY_CANNOT_FAIL: DiagnosticCode = DiagnosticCode::new(
+    "W020",
+    DiagnosticSeverity::HINT,
+    "Try 块中没有可能失败的代码",
+    "Try block cannot fail",
+);
+pub const PRECISION_LOSS: DiagnosticCode = DiagnosticCode::new(
+    "W021",
+    DiagnosticSeverity::WARNING,
+    "小数字面量无法用 f64 精确表示，会被舍入",
+    "Fractional literal is rounded to fit an f64",
+);
+pub const LOOP_NEVER_ENDS: DiagnosticCode = DiagnosticCode::new(
+    "W022",
+    DiagnosticSeverity::WARNING,
+    "循环条件中的变量在循环体内从未被修改",
+    "Loop condition reads nothing the loop body changes",
+);
+pub const TEMPLATE_ARGUMENTS: DiagnosticCode = DiagnosticCode::new(
+    "W023",
+    DiagnosticSeverity::WARNING,
+    "格式模板的占位符数量与参数数量不符",
+    "Format template placeholders don't match the arguments",
+);
+pub const UNKNOWN_DIRECTIVE: DiagnosticCode = DiagnosticCode::new(
+    "W024",
+    DiagnosticSeverity::INFORMATION,
+    "未知的脚本头指令",
+    "Unknown header directive",
+);
+pub const MALFORMED_REQUIRES: DiagnosticCode = DiagnosticCode::new(
+    "W025",
+    DiagnosticSeverity::WARNING,
+    "requires 指令的版本号格式不正确",
+    "Malformed version in a requires directive",
+);
+pub const MISPLACED_DIRECTIVE: DiagnosticCode = DiagnosticCode::new(
+    "W026",
+    DiagnosticSeverity::WARNING,
+    "脚本头指令出现在第一条语句之后",
+    "Header directive after the first statement",
+);
+pub const KEYWORD_CASING: DiagnosticCode = DiagnosticCode::new(
+    "W027",
+    DiagnosticSeverity::WARNING,
+    "关键字的大小写不正确",
+    "Keyword written in the wrong case",
+);
+pub const NOT_A_GENERATOR: DiagnosticCode = DiagnosticCode::new(
+    "W028",
+    DiagnosticSeverity::INFORMATION,
+    "For 循环遍历的是普通函数的调用，而不是 Generator",
+    "For loop iterates over a call to a plain function, not a Generator",
+);
+pub const GENERATOR_ARITHMETIC: DiagnosticCode = DiagnosticCode::new(
+    "W029",
+    DiagnosticSeverity::WARNING,
+    "对 Generator 调用的结果做算术运算",
+    "Arithmetic on the result of a Generator call",
+);
+pub const SET_WITH_EQUALS: DiagnosticCode = DiagnosticCode::new(
+    "W030",
+    DiagnosticSeverity::WARNING,
+    "Set 语句中多余的 '='",
+    "Set statement with '='",
+);
+pub const INDENTATION: DiagnosticCode = DiagnosticCode::new(
+    "W031",
+    DiagnosticSeverity::HINT,
+    "缩进混用制表符和空格，或未对齐缩进宽度",
+    "Indentation mixes tabs and spaces, or is off the indent width",
+);
+pub const NON_ITERABLE_VALUE: DiagnosticCode = DiagnosticCode::new(
+    "W032",
+    DiagnosticSeverity::WARNING,
+    "For 循环遍历的调用或变量给出的值不可遍历",
+    "For loop over a call or variable whose value isn't iterable",
+);
+pub const STRING_ITERATION: DiagnosticCode = DiagnosticCode::new(
+    "W033",
+    DiagnosticSeverity::HINT,
+    "For 循环逐个遍历字符串的字符",
+    "For loop over the characters of a string",
+);
+pub const SWAPPED_LOOP_VARIABLES: DiagnosticCode = DiagnosticCode::new(
+    "W034",
+    DiagnosticSeverity::HINT,
+    "ForIndexed 的索引变量与值变量可能写反了",
+    "ForIndexed index and value variables look swapped",
+);
+pub const DICT_ITERATION: DiagnosticCode = DiagnosticCode::new(
+    "W035",
+    DiagnosticSeverity::HINT,
+    "For 循环遍历字典得到的是键",
+    "For loop over a dict gives its keys",
+);
+pub const INDEX_OUT_OF_RANGE: DiagnosticCode = DiagnosticCode::new(
+    "W036",
+    DiagnosticSeverity::WARNING,
+    "常量索引超出数组字面量的长度",
+    "Constant index outside an array literal",
+);
+pub const LOOKALIKE_NAME: DiagnosticCode = DiagnosticCode::new(
+    "W037",
+    DiagnosticSeverity::WARNING,
+    "名称与另一个名称仅大小写或形近字符不同",
+    "Name differs from another only by case or lookalike characters",
+);
+pub const DEPRECATED_FUNCTION: DiagnosticCode = DiagnosticCode::new(
+    "W038",
+    DiagnosticSeverity::WARNING,
+    "调用了标记为 @deprecated 的函数",
+    "Call to a function marked @deprecated",
+);
+
+/// Every code, in code order
+pub const ALL: &[DiagnosticCode] = &[
+    SYNTAX_ERROR,
+    INVALID_IDENTIFIER,
+    UNEXPECTED_TOKEN,
+    EXPECTED_TOKEN,
+    INVALID_EXPRESSION,
+    MISSING_MODULE,
+    NOT_EXPORTED,
+    NO_SUCH_MEMBER,
+    ASSIGN_IN_CONDITION,
+    ASSIGN_WITHOUT_SET,
+    NOT_ITERABLE,
+    CALLBACK_ARITY,
+    UNEXPECTED_EOF,
+    NAMING_CONVENTION,
+    UNUSED_IMPORT,
+    BUILTIN_ARITY,
+    IO_USAGE,
+    DEPRECATED_BUILTIN,
+    UNDEFINED_IDENTIFIER,
+    IMPORT_CYCLE,
+    LAZY_NEVER_FORCED,
+    UNUSED_EXPRESSION,
+    SELF_ASSIGNMENT,
+    DEAD_STORE,
+    TOO_MANY_PARAMS,
+    TOO_DEEPLY_NESTED,
+    TOO_MANY_STATEMENTS,
+    SHADOWED_BUILTIN,
+    SHADOWED_BINDING,
+    FORCE_NOT_LAZY,
+    UNCAUGHT_THROW,
+    EMPTY_CATCH,
+    TRY_CANNOT_FAIL,
+    PRECISION_LOSS,
+    LOOP_NEVER_ENDS,
+    TEMPLATE_ARGUMENTS,
+    UNKNOWN_DIRECTIVE,
+    MALFORMED_REQUIRES,
+    MISPLACED_DIRECTIVE,
+    KEYWORD_CASING,
+    NOT_A_GENERATOR,
+    GENERATOR_ARITHMETIC,
+    SET_WITH_EQUALS,
+    INDENTATION,
+    NON_ITERABLE_VALUE,
+    STRING_ITERATION,
+    SWAPPED_LOOP_VARIABLES,
+    DICT_ITERATION,
+    INDEX_OUT_OF_RANGE,
+    LOOKALIKE_NAME,
+    DEPRECATED_FUNCTION,
+];
+
+/// A code as serialized for the `aether/diagnosticCodes` request
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticCodeEntry {
+    pub code: &'static str,
+    pub severity: &'static str,
+    pub description: &'static str,
+    pub description_en: &'static str,
+    pub docs_url: String,
+}
+
+#[cfg(feature = "server")]
+pub fn catalog() -> Vec<DiagnosticCodeEntry> {
+    ALL.iter().map(DiagnosticCode::catalog_entry).collect()
+}
+
+/// The parse errors; a broken parse can't be waved away, so they can't be
+/// suppressed
+const PARSE_ERRORS: &[DiagnosticCode] = &[
+    SYNTAX_ERROR,
+    INVALID_IDENTIFIER,
+    UNEXPECTED_TOKEN,
+    EXPECTED_TOKEN,
+    INVALID_EXPRESSION,
+    UNEXPECTED_EOF,
+];
+
+impl DiagnosticCode {
+    /// Whether an `aether-disable-next-line` comment can silence the code
+    pub fn suppressible(&self) -> bool {
+        !PARSE_ERRORS.contains(self)
+    }
+
+    /// Why the code is reported and what triggers it, as "Explain" shows it
+    pub fn explanation(&self) -> Option<&'static Explanation> {
+        EXPLANATIONS
+            .iter()
+            .find(|(code, _)| *code == self.code)
+            .map(|(_, explanation)| explanation)
+    }
+}
+
+/// The code whose id is `code`, such as `W001`
+pub fn by_code(code: &str) -> Option<&'static DiagnosticCode> {
+    ALL.iter().find(|entry| entry.code == code)
+}
+
+/// The long form of a code, behind the "Explain" code action
+#[derive(Debug)]
+pub struct Explanation {
+    pub rationale: &'static str,
+    pub rationale_en: &'static str,
+    /// Code that is reported
+    pub example: &'static str,
+}
+
+const fn explain(
+    rationale: &'static str,
+    rationale_en: &'static str,
+    example: &'static str,
+) -> Explanation {
+    Explanation {
+        rationale,
+        rationale_en,
+        example,
+    }
+}
+
+/// One per code in [`ALL`], in the same order
+const EXPLANATIONS: &[(&str, Explanation)] = &[
+    (
+        "E000",
+        explain(
+            "解析器无法理解这段代码，其后的内容在修复前不会被分析。",
+            "The parser can't make sense of the code here; what follows isn't analyzed until it is fixed.",
+            "Set X 1 2 3 )",
+        ),
+    ),
+    (
+        "E001",
+        explain(
+            "名称只能由字母、数字和下划线组成，且不能以数字开头。",
+            "Names are letters, digits and underscores, and don't start with a digit.",
+            "Set 1ST_PLACE \"gold\"",
+        ),
+    ),
+    (
+        "E002",
+        explain(
+            "这里应当是一个表达式，但出现的符号不能开始表达式。",
+            "An expression should start here, but the token can't begin one.",
+            "Set X * 2",
+        ),
+    ),
+    (
+        "E003",
+        explain(
+            "语句缺少必需的符号，常见的是未闭合的括号或花括号。",
+            "The statement is missing a token it needs, most often a closing bracket or brace.",
+            "Func F(A {\n    Return A\n}",
+        ),
+    ),
+    (
+        "E004",
+        explain(
+            "表达式开了头却无法完成，例如字符串直到文件末尾都没有闭合的引号。",
+            "The expression starts but can't be completed, such as a string with no closing quote before the end of the file.",
+            "Set GREETING \"hello",
+        ),
+    ),
+    (
+        "E005",
+        explain(
+            "导入路径在文档所在目录和工作区文件夹中都找不到对应的模块，运行时导入会失败。",
+            "No module is found at the import path, next to the document or in a workspace folder, so the import fails at run time.",
+            "Import {HELPER} From \"./missing.ae\"",
+        ),
+    ),
+    (
+        "E006",
+        explain(
+            "模块只提供它 Export 的名称；导入未导出的名称会在运行时失败。",
+            "A module offers only the names it Exports; importing any other name fails at run time.",
+            "Import {INTERNAL} From \"./lib.ae\"",
+        ),
+    ),
+    (
+        "E007",
+        explain(
+            "整体导入的模块上访问了它没有导出的成员。",
+            "The member isn't exported by the module imported whole under that name.",
+            "Import UTILS From \"./utils.ae\"\nUTILS.NOT_THERE()",
+        ),
+    ),
+    (
+        "E008",
+        explain(
+            "Aether 的条件中 '=' 不是比较运算符；比较请用 '=='。",
+            "'=' doesn't compare in an Aether condition; '==' does.",
+            "If (X = 5) {\n    PRINTLN(X)\n}",
+        ),
+    ),
+    (
+        "E009",
+        explain(
+            "Aether 用 Set 语句赋值，'=' 不能单独赋值。",
+            "Aether assigns with the Set statement; '=' alone doesn't assign.",
+            "COUNT = COUNT + 1",
+        ),
+    ),
+    (
+        "E010",
+        explain(
+            "For 只能遍历数组、字典、字符串或 Generator；数字、布尔值和 Null 不可遍历。",
+            "For iterates arrays, dicts, strings and Generators; a Number, Boolean or Null can't be iterated.",
+            "For X In 10 {\n    PRINTLN(X)\n}",
+        ),
+    ),
+    (
+        "E011",
+        explain(
+            "MAP 和 FILTER 用一个参数（元素）调用回调，REDUCE 用两个（累加值和元素）；回调的参数个数不符会在运行时出错。",
+            "MAP and FILTER call their callback with one argument, the element, and REDUCE with two, the accumulator and the element; a callback taking another number fails at runtime.",
+            "Set EVENS FILTER(NUMBERS, Lambda (I, X) -> ((X % 2) == 0))",
+        ),
+    ),
+    (
+        "E012",
+        explain(
+            "文件在语句或代码块结束前就结束了，通常是少了 '}'。",
+            "The file ends before a statement or block does, usually for want of a '}'.",
+            "If (X > 1) {\n    PRINTLN(X)",
+        ),
+    ),
+    (
+        "W001",
+        explain(
+            "Aether 的变量和函数按约定使用 UPPER_SNAKE_CASE，便于和关键字区分。",
+            "Aether variables and functions are UPPER_SNAKE_CASE by convention, which sets them apart from keywords.",
+            "Set retryCount 3",
+        ),
+    ),
+    (
+        "W002",
+        explain(
+            "导入的名称在文件中从未使用，可以删除。",
+            "The imported name is never used in the file and can go.",
+            "Import {HELPER} From \"./lib.ae\"",
+        ),
+    ),
+    (
+        "W003",
+        explain(
+            "内置函数接受的参数个数是固定的，多传或少传都会在运行时出错。",
+            "Builtins take a fixed number of arguments; more or fewer fail at run time.",
+            "Set N LENGTH([1], [2])",
+        ),
+    ),
+    (
+        "W004",
+        explain(
+            "在沙箱中嵌入 Aether 的宿主可能禁止文件和系统 IO；此提示默认关闭。",
+            "Hosts embedding Aether in a sandbox may forbid file and system IO; this note is off by default.",
+            "Set TEXT READFILE(\"data.txt\")",
+        ),
+    ),
+    (
+        "W005",
+        explain(
+            "该内置函数（通常由 extraBuiltins 声明）已弃用，将来的版本可能移除，请改用提示中的替代函数。",
+            "The builtin (usually one declared through extraBuiltins) is deprecated and may be removed; use the replacement the message names.",
+            "Set BODY HTTP_FETCH(URL)",
+        ),
+    ),
+    (
+        "W006",
+        explain(
+            "名称在使用前没有被 Set、Func、参数或 Import 定义，运行时会出错。",
+            "Nothing defines the name before it is used (a Set, Func, parameter or Import), so it fails at run time.",
+            "PRINTLN(TOTAL)",
+        ),
+    ),
+    (
+        "W007",
+        explain(
+            "模块之间相互导入，加载顺序决定了哪一方先看到未定义的名称。",
+            "The modules import each other, and load order decides which one sees undefined names.",
+            "// a.ae\nImport {B} From \"./b.ae\"\n// b.ae\nImport {A} From \"./a.ae\"",
+        ),
+    ),
+    (
+        "W008",
+        explain(
+            "Lazy 值只在第一次 Force 时求值；从未 Force 的 Lazy 值不会产生任何效果。",
+            "A Lazy value is evaluated on its first Force; one never forced does nothing.",
+            "Lazy DATA(LOAD())",
+        ),
+    ),
+    (
+        "W009",
+        explain(
+            "表达式的结果被丢弃了，通常是漏写了 Set 或 Return。",
+            "The expression's result is thrown away, usually for a missing Set or Return.",
+            "X + 1",
+        ),
+    ),
+    (
+        "W010",
+        explain(
+            "把变量赋值给自身没有任何效果。",
+            "Assigning a variable to itself has no effect.",
+            "Set X X",
+        ),
+    ),
+    (
+        "W011",
+        explain(
+            "这个值在被读取之前就被再次赋值覆盖了，第一次赋值是多余的。",
+            "The value is overwritten before anything reads it, so the first assignment is wasted.",
+            "Set X 1\nSet X 2\nPRINTLN(X)",
+        ),
+    ),
+    (
+        "W012",
+        explain(
+            "参数太多的函数难以调用和阅读；可以把相关参数合并为一个字典。上限可在 lint.complexity 中配置。",
+            "Functions with many parameters are hard to call and read; related ones can travel in a dict. The limit is set under lint.complexity.",
+            "Func F(A, B, C, D, E, F2, G) {\n    Return A\n}",
+        ),
+    ),
+    (
+        "W013",
+        explain(
+            "嵌套过深的代码块难以理解；可以提前返回或拆分函数。上限可在 lint.complexity 中配置。",
+            "Deeply nested blocks are hard to follow; return early or split the function. The limit is set under lint.complexity.",
+            "Func F(X) {\n    If (X) {\n        While (X) {\n            ...\n        }\n    }\n}",
+        ),
+    ),
+    (
+        "W014",
+        explain(
+            "语句过多的函数难以理解和测试；可以拆分成更小的函数。上限可在 lint.complexity 中配置。",
+            "Long functions are hard to follow and test; split them into smaller ones. The limit is set under lint.complexity.",
+            "Func MAIN() {\n    ...\n}",
+        ),
+    ),
+    (
+        "W015",
+        explain(
+            "与内置函数同名的绑定会让该内置函数在此作用域中不可用。",
+            "A binding named like a builtin makes the builtin unreachable in its scope.",
+            "Set LENGTH 10",
+        ),
+    ),
+    (
+        "W016",
+        explain(
+            "内层绑定与外层作用域中的绑定同名，外层的值在内层不可见。",
+            "The inner binding has the name of one in an enclosing scope, hiding the outer value.",
+            "Set X 1\nFunc F(X) {\n    Return X\n}",
+        ),
+    ),
+    (
+        "W017",
+        explain(
+            "Force 用于对 Lazy 值求值；对普通值使用 Force 没有意义。",
+            "Force evaluates Lazy values; forcing anything else does nothing useful.",
+            "Set X 1\nPRINTLN(Force(X))",
+        ),
+    ),
+    (
+        "W018",
+        explain(
+            "没有任何 Try 能捕获这个错误，它会终止脚本。此提示默认关闭。",
+            "No Try catches the error, so it ends the script. This note is off by default.",
+            "Throw \"failed\"",
+        ),
+    ),
+    (
+        "W019",
+        explain(
+            "空的 Catch 块会静默吞掉错误，让问题难以发现。",
+            "An empty Catch swallows the error silently, hiding the problem.",
+            "Try {\n    RISKY()\n} Catch E {\n}",
+        ),
+    ),
+    (
+        "W020",
+        explain(
+            "Try 块中没有可能失败的代码，Try 是多余的。",
+            "Nothing in the Try block can fail, so the Try is redundant.",
+            "Try {\n    Set X 1\n} Catch E {\n    PRINTLN(E)\n}",
+        ),
+    ),
+    (
+        "W021",
+        explain(
+            "这个小数无法用 f64 精确表示，运行时使用的是舍入后的值。",
+            "The fraction has no exact f64 form, so the program sees a rounded value.",
+            "Set BIG 9007199254740993.0",
+        ),
+    ),
+    (
+        "W022",
+        explain(
+            "循环体从不修改条件读取的任何变量，条件一旦成立循环就不会结束。",
+            "The loop body changes nothing the condition reads, so once true the loop never ends.",
+            "While (I < 10) {\n    PRINTLN(I)\n}",
+        ),
+    ),
+    (
+        "W023",
+        explain(
+            "格式模板中的占位符数量应与传入的参数数量一致。",
+            "A format template needs as many arguments as it has placeholders.",
+            "FORMAT(\"{} and {}\", A)",
+        ),
+    ),
+    (
+        "W024",
+        explain(
+            "脚本头中只识别已知的指令，其他指令会被忽略。",
+            "Only known directives mean anything in the header; others are ignored.",
+            "//! colour: blue",
+        ),
+    ),
+    (
+        "W025",
+        explain(
+            "requires 指令需要形如 1.2 或 1.2.3 的版本号。",
+            "A requires directive takes a version such as 1.2 or 1.2.3.",
+            "//! requires: latest",
+        ),
+    ),
+    (
+        "W026",
+        explain(
+            "脚本头指令只在第一条语句之前有效。",
+            "Header directives only count before the first statement.",
+            "Set X 1\n//! requires: 1.2",
+        ),
+    ),
+    (
+        "W027",
+        explain(
+            "Aether 的关键字区分大小写；写错大小写的关键字会被当作普通名称。",
+            "Aether keywords are case-sensitive; a miscased one reads as a plain name.",
+            "if (X) {\n    PRINTLN(X)\n}",
+        ),
+    ),
+    (
+        "W028",
+        explain(
+            "For 遍历的是普通函数返回的值，而不是 Generator 逐个产生的值；如需逐个产生，请把函数定义为 Generator。",
+            "For iterates what a plain function returns, not values yielded one by one; define a Generator for that.",
+            "Func NUMBERS() {\n    Return [1, 2]\n}\nFor N In NUMBERS() {\n}",
+        ),
+    ),
+    (
+        "W029",
+        explain(
+            "调用 Generator 得到的是生成器对象，不是数字，不能直接参与算术运算。",
+            "Calling a Generator gives a Generator object, not a number, so arithmetic on it fails.",
+            "Set TOTAL COUNT_UP(3) + 1",
+        ),
+    ),
+    (
+        "W030",
+        explain(
+            "Set 语句的形式是 `Set NAME value`，不需要 '='。",
+            "The Set statement is `Set NAME value`, without '='.",
+            "Set X = 10",
+        ),
+    ),
+    (
+        "W031",
+        explain(
+            "混用制表符和空格，或未对齐缩进宽度的缩进，在不同编辑器中显示不一致。此检查默认关闭。",
+            "Indentation that mixes tabs and spaces, or sits off the indent width, looks different from editor to editor. Off by default.",
+            "For X In [1] {\n  PRINTLN(X)\n}",
+        ),
+    ),
+    (
+        "W032",
+        explain(
+            "遍历的调用或变量给出的是数字、布尔值或 Null，不可遍历。",
+            "The call or variable gives a Number, Boolean or Null, which can't be iterated.",
+            "For X In SUM([1, 2]) {\n}",
+        ),
+    ),
+    (
+        "W033",
+        explain(
+            "遍历字符串会逐个得到它的字符，这可能不是本意。",
+            "Iterating a string gives its characters one at a time, which may not be what was meant.",
+            "For C In \"abc\" {\n    PRINTLN(C)\n}",
+        ),
+    ),
+    (
+        "W034",
+        explain(
+            "ForIndexed 先绑定索引再绑定值；循环体的用法表明两者可能写反了。可在 lint.heuristics 中关闭。",
+            "ForIndexed binds the index first, then the value; the body uses them the other way round. Turn off under lint.heuristics.",
+            "For V, I In ARR {\n    PRINTLN(ARR[I], V)\n}",
+        ),
+    ),
+    (
+        "W035",
+        explain(
+            "遍历字典得到的是键；需要键和值时请遍历 ITEMS(DICT)。可在 lint.heuristics 中关闭。",
+            "Iterating a dict gives its keys; iterate ITEMS(DICT) for keys and values. Turn off under lint.heuristics.",
+            "For K In CONFIG {\n    PRINTLN(K)\n}",
+        ),
+    ),
+    (
+        "W036",
+        explain(
+            "数组由字面量创建且在文件中从未被修改（没有 PUSH、POP、Set ARR[...] 或重新赋值），常量索引为负或不小于其长度时运行必然出错。",
+            "The array comes from a literal and is never changed in the file (no PUSH, POP, Set ARR[...] or reassignment), so a constant index that is negative or not below its length always fails at run time.",
+            "Set ARR [1, 2, 3]\nSet FIRST ARR[5]",
+        ),
+    ),
+    (
+        "W037",
+        explain(
+            "两个名称只差大小写，或只差全角字母、西里尔或希腊字母这类看起来与拉丁字母一样的字符时，Aether 把它们当作不同的变量，读代码的人却分不出来。每个后出现的写法都会被标出，并指向最先定义的那个。",
+            "Aether treats two names as different variables when they differ only by case, or by characters that look like Latin letters (fullwidth letters, Cyrillic or Greek capitals), but a reader can't tell them apart. Each later spelling is flagged, pointing at the one defined first.",
+            "Set USER_ID 1\nSet User_Id 2",
+        ),
+    ),
+    (
+        "W038",
+        explain(
+            "函数的文档注释里有 `// @deprecated` 标记，说明作者不希望再使用它；标记后面的文字通常指明替代的函数。替代函数在当前文件可用时，快速修复会改为调用它。导入的函数在其模块中被标记时同样会提示。",
+            "The function's doc comment has a `// @deprecated` tag, so its author wants it no longer used; the text after the tag usually names the replacement. When that function is available in the file, a quick fix calls it instead. Imported functions marked in their own module are reported too.",
+            "// @deprecated use NEW_TOTAL instead\nFunc OLD_TOTAL(XS) {\n    Return SUM(XS)\n}\nFunc NEW_TOTAL(XS) {\n    Return SUM(XS)\n}\nPRINTLN(OLD_TOTAL([1, 2]))",
+        ),
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(code: &str) -> &'static DiagnosticCode {
+        ALL.iter().find(|entry| entry.code == code).unwrap()
+    }
+
+    #[test]
+    fn test_catalog_is_ordered_and_unique() {
+        let codes: Vec<&str> = ALL.iter().map(|entry| entry.code).collect();
+        let mut sorted = codes.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(codes, sorted);
+        assert!(ALL.iter().all(|entry| entry.code_description().is_some()));
+        assert_eq!(
+            lookup("W001").docs_url(),
+            "https://github.com/xiaozuhui/aether-lang/wiki/naming-conventions"
+        );
+        assert_eq!(
+            lookup("E005").docs_url(),
+            "https://github.com/xiaozuhui/aether-lang/wiki/diagnostics#e005"
+        );
+    }
+
+    #[test]
+    fn test_every_code_is_explained() {
+        let explained: Vec<&str> = EXPLANATIONS.iter().map(|(code, _)| *code).collect();
+        let codes: Vec<&str> = ALL.iter().map(|entry| entry.code).collect();
+        assert_eq!(explained, codes);
+        assert!(EXPLANATIONS.iter().all(|(_, explanation)| {
+            !explanation.rationale.is_empty()
+                && !explanation.rationale_en.is_empty()
+                && !explanation.example.is_empty()
+        }));
+        assert!(NAMING_CONVENTION.suppressible());
+        assert!(!EXPECTED_TOKEN.suppressible());
+    }
+
+    /// Every string literal shaped like a code (E or W and three digits), whether
+    /// a rule emits it or a test expects it, must be catalogued
+    #[test]
+    fn test_catalog_covers_codes_in_sources() {
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut unknown = Vec::new();
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            for literal in text.split('"').skip(1).step_by(2) {
+                let bytes = literal.as_bytes();
+                let looks_like_code = bytes.len() == 4
+                    && matches!(bytes[0], b'E' | b'W')
+                    && bytes[1..].iter().all(u8::is_ascii_digit);
+                if looks_like_code && !ALL.iter().any(|entry| entry.code == literal) {
+                    unknown.push(format!("{} in {}", literal, path.display()));
+                }
+            }
+        }
+        assert!(unknown.is_empty(), "uncatalogued codes: {:?}", unknown);
+    }
+}
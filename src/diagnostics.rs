@@ -1,163 +1,4042 @@
 //! Diagnostics engine for Aether code analysis
 
-use crate::lexer::Lexer;
-use crate::parser::{CompatParseError, ParsedDocument};
-use crate::token::Token;
-use tower_lsp::lsp_types::*;
+use crate::array_bounds;
+use crate::ast::{
+    self, BinOp, Expr, ExprKind, Ident, ImportInfo, Program, Stmt, StmtKind, UnaryOp, span_contains,
+};
+use crate::builtins;
+use crate::call_hierarchy::CallGraph;
+use crate::config::{
+    ComplexitySettings, HeuristicSettings, IndentationSettings, ShadowingSettings,
+    ShadowingSeverity,
+};
+use crate::confusables;
+use crate::diagnostic_codes as codes;
+use crate::folding;
+use crate::format_template;
+use crate::generators;
+use crate::header;
+use crate::intern::Name;
+use crate::locale::Locale;
+use crate::loops;
+use crate::modules;
+use crate::occurrences::Role;
+use crate::parser::{NamingIssue, ParsedDocument, StrayAssignKind};
+use crate::suppression;
+use crate::types::{Types, ValueType};
+use crate::visit::{Visitor, walk_block, walk_expr, walk_stmt};
+use lsp_types::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 pub struct DiagnosticEngine;
 
+#[cfg(test)]
+pub type Rule = fn(&Program) -> Vec<Diagnostic>;
+
+#[cfg(test)]
+thread_local! {
+    /// An extra rule tests can install, e.g. to exercise failure handling
+    pub static INJECTED_RULE: std::cell::Cell<Option<Rule>> = const { std::cell::Cell::new(None) };
+}
+
+/// Knobs that change which diagnostics are produced and how they read
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnalysisOptions<'a> {
+    pub locale: Locale,
+    /// Report calls to File/System builtins (for sandboxed embeddings)
+    pub report_io: bool,
+    /// Report `Throw`s no `Try` in the file catches
+    pub report_uncaught_throw: bool,
+    /// Directory of the document, for resolving import paths; `None` skips the module check
+    pub base_dir: Option<&'a Path>,
+    /// Workspace folders searched for modules not found next to the document
+    pub roots: &'a [PathBuf],
+    /// The document itself, for related information; `None` leaves it out
+    pub uri: Option<&'a Url>,
+    pub complexity: ComplexitySettings,
+    pub shadowing: ShadowingSettings,
+    pub indentation: IndentationSettings,
+    pub heuristics: HeuristicSettings,
+}
+
 impl DiagnosticEngine {
-    pub fn analyze(parsed: &ParsedDocument, text: &str) -> Vec<Diagnostic> {
+    pub fn analyze(parsed: &ParsedDocument, options: &AnalysisOptions) -> Vec<Diagnostic> {
+        let locale = options.locale;
         let mut diagnostics = Vec::new();
 
         // 1. 检查语法错误（优先级最高）
-        diagnostics.extend(Self::parse_errors_to_diagnostics(&parsed.errors));
+        diagnostics.extend(Self::parse_errors_to_diagnostics(parsed));
 
-        // 2. 检查命名约定（如果没有语法错误）
-        if parsed.errors.is_empty() {
-            diagnostics.extend(Self::check_naming_convention(text));
+        // 2. 检查命名约定等规则; with syntax errors only the recovered statements are
+        // checked, and names mentioned in the skipped regions count as defined and used
+        let program = &parsed.ast;
+        let mentioned = parsed.names_in_errors();
+        let mut lints = Vec::new();
+        lints.extend(Self::check_naming_convention(parsed, locale));
+        lints.extend(Self::check_keyword_casing(parsed, locale));
+        lints.extend(Self::check_stray_assigns(parsed, locale));
+        lints.extend(Self::check_unused_imports(program, &mentioned, locale));
+        lints.extend(Self::check_builtin_arity(program, locale));
+        lints.extend(Self::check_callback_arity(parsed, locale));
+        lints.extend(Self::check_deprecated_builtins(program, locale));
+        lints.extend(Self::check_deprecated_functions(parsed, locale));
+        lints.extend(Self::check_format_templates(program, locale));
+        lints.extend(Self::check_generator_calls(program, locale));
+        lints.extend(Self::check_for_iterables(program, locale));
+        lints.extend(Self::check_index_bounds(parsed, locale));
+        lints.extend(Self::check_lookalike_names(parsed, options.uri, locale));
+        lints.extend(Self::check_loop_heuristics(
+            program,
+            &options.heuristics,
+            locale,
+        ));
+        lints.extend(Self::check_undefined_identifiers(
+            program, &mentioned, locale,
+        ));
+        lints.extend(Self::check_lazy_never_forced(program, &mentioned, locale));
+        lints.extend(Self::check_force_not_lazy(program, &mentioned, locale));
+        lints.extend(Self::check_unused_expressions(program, locale));
+        lints.extend(Self::check_float_precision(parsed, locale));
+        lints.extend(Self::check_indentation(
+            parsed,
+            &options.indentation,
+            locale,
+        ));
+        lints.extend(Self::check_redundant_assignments(
+            program,
+            options.uri,
+            locale,
+        ));
+        lints.extend(Self::check_function_complexity(
+            program,
+            &options.complexity,
+            locale,
+        ));
+        lints.extend(Self::check_shadowing(
+            program,
+            &options.shadowing,
+            options.uri,
+            locale,
+        ));
+        if let Some(base_dir) = options.base_dir {
+            lints.extend(Self::check_missing_modules(
+                program,
+                base_dir,
+                options.roots,
+                locale,
+            ));
+        }
+        if options.report_io {
+            lints.extend(Self::check_io_usage(program, locale));
+        }
+        lints.extend(Self::check_try_catch(program, locale));
+        lints.extend(Self::check_loops_never_ending(program, locale));
+        if options.report_uncaught_throw {
+            lints.extend(Self::check_uncaught_throws(program, locale));
+        }
+        #[cfg(test)]
+        if let Some(rule) = INJECTED_RULE.get() {
+            lints.extend(rule(program));
         }
+        lints.retain(|lint| {
+            !parsed
+                .errors
+                .iter()
+                .any(|error| ranges_overlap(error.span, lint.range))
+        });
+        diagnostics.extend(lints);
+        // Nothing inside a string or comment is code, whatever it looks like
+        diagnostics.retain(|diagnostic| !parsed.mask.covers(diagnostic.range));
+        // ...except header directives, which are comments by definition
+        diagnostics.extend(Self::check_header_directives(parsed, locale));
+        suppression::retain_unsuppressed(parsed.ast.comments(), &mut diagnostics);
 
         diagnostics
     }
 
-    /// Convert parse errors to LSP diagnostics with precise location
-    fn parse_errors_to_diagnostics(errors: &[CompatParseError]) -> Vec<Diagnostic> {
-        errors
+    /// Order all of a document's diagnostics by severity, errors first, and keep at most
+    /// `max_problems` of them (0 keeps all). A final note on line 0 counts the dropped ones.
+    pub fn limit_problems(
+        mut diagnostics: Vec<Diagnostic>,
+        max_problems: usize,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        diagnostics
+            .sort_by_key(|diagnostic| diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR));
+        if max_problems == 0 || diagnostics.len() <= max_problems {
+            return diagnostics;
+        }
+        let hidden = diagnostics.len() - max_problems;
+        diagnostics.truncate(max_problems);
+        diagnostics.push(Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            source: Some("aether-lint".to_string()),
+            message: match locale {
+                Locale::Zh => format!("还有 {} 个问题未显示", hidden),
+                Locale::En => format!("{} more problems not shown", hidden),
+            },
+            ..Default::default()
+        });
+        diagnostics
+    }
+
+    /// Convert parse errors to LSP diagnostics under the offending token
+    fn parse_errors_to_diagnostics(parsed: &ParsedDocument) -> Vec<Diagnostic> {
+        parsed
+            .errors
             .iter()
             .map(|error| {
-                let start_pos = Position {
-                    line: error.line.saturating_sub(1) as u32,
-                    character: error.column.saturating_sub(1) as u32,
+                let code = error.code();
+                let start = error.token.start;
+                // A token running over several lines, e.g. an unterminated
+                // string, is underlined to the end of its first line
+                let end = if error.token.end.line == start.line {
+                    error.token.end
+                } else {
+                    Position::new(
+                        start.line,
+                        parsed.line_index.line_len(&parsed.text, start.line),
+                    )
                 };
 
-                // Try to estimate error range based on message
-                let error_length = Self::estimate_error_length(&error.message);
+                Diagnostic {
+                    range: Range { start, end },
+                    severity: Some(code.severity),
+                    code: code.lsp_code(),
+                    source: Some("aether-parser".to_string()),
+                    message: error.message(),
+                    related_information: None,
+                    tags: None,
+                    code_description: code.code_description(),
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Warn about definitions the parser found named outside UPPER_SNAKE_CASE, and hint
+    /// at such parameters
+    fn check_naming_convention(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        parsed
+            .naming_issues
+            .iter()
+            .map(|NamingIssue { name, is_param }| Diagnostic {
+                range: name.span,
+                severity: Some(if *is_param {
+                    DiagnosticSeverity::HINT
+                } else {
+                    codes::NAMING_CONVENTION.severity
+                }),
+                code: codes::NAMING_CONVENTION.lsp_code(),
+                code_description: codes::NAMING_CONVENTION.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!(
+                        "{} '{}' 应使用 UPPER_SNAKE_CASE 格式\n建议: {}",
+                        if *is_param { "参数名" } else { "变量名" },
+                        name,
+                        Self::suggest_upper_snake_case(name.as_str())
+                    ),
+                    Locale::En => format!(
+                        "{} '{}' should use UPPER_SNAKE_CASE\nSuggestion: {}",
+                        if *is_param { "Parameter" } else { "Name" },
+                        name,
+                        Self::suggest_upper_snake_case(name.as_str())
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            })
+            .collect()
+    }
+
+    /// Flag imported names that are never referenced in the file
+    fn check_unused_imports(
+        program: &Program,
+        mentioned: &HashSet<Name>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        Self::unused_imports(program)
+            .into_iter()
+            .filter(|ident| !mentioned.contains(ident.as_str()))
+            .map(|ident| Diagnostic {
+                range: ident.span,
+                severity: Some(codes::UNUSED_IMPORT.severity),
+                code: codes::UNUSED_IMPORT.lsp_code(),
+                code_description: codes::UNUSED_IMPORT.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("导入的 '{}' 未被使用", ident.name),
+                    Locale::En => format!("Imported '{}' is never used", ident.name),
+                },
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                related_information: None,
+                data: None,
+            })
+            .collect()
+    }
+
+    /// Flag imports whose module file does not exist
+    fn check_missing_modules(
+        program: &Program,
+        base_dir: &Path,
+        roots: &[PathBuf],
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        program
+            .imports()
+            .iter()
+            .filter(|import| !modules::resolve_import_in(base_dir, roots, &import.path).is_file())
+            .map(|import| Diagnostic {
+                range: import.span,
+                severity: Some(codes::MISSING_MODULE.severity),
+                code: codes::MISSING_MODULE.lsp_code(),
+                code_description: codes::MISSING_MODULE.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("找不到模块 '{}'", import.path),
+                    Locale::En => format!("Module '{}' not found", import.path),
+                },
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect()
+    }
+
+    /// Flag calls to builtins the registry marks as deprecated
+    fn check_deprecated_builtins(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let user_defined = defined_names(program);
+        let mut diagnostics = Vec::new();
+
+        ast::for_each_expr(program, &mut |expr| {
+            if let ExprKind::Call { func, .. } = &expr.kind
+                && let ExprKind::Identifier(name) = &func.kind
+                && !user_defined.contains(name.as_str())
+                && let Some(builtin) = builtins::lookup(name)
+                && let Some(replacement) = builtin.deprecated
+            {
+                diagnostics.push(Diagnostic {
+                    range: func.span,
+                    severity: Some(codes::DEPRECATED_BUILTIN.severity),
+                    code: codes::DEPRECATED_BUILTIN.lsp_code(),
+                    code_description: codes::DEPRECATED_BUILTIN.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => {
+                            format!("内置函数 '{}' 已弃用，请改用 {}", builtin.name, replacement)
+                        }
+                        Locale::En => format!(
+                            "Builtin '{}' is deprecated; use {} instead",
+                            builtin.name, replacement
+                        ),
+                    },
+                    tags: Some(vec![DiagnosticTag::DEPRECATED]),
+                    related_information: None,
+                    data: None,
+                });
+            }
+        });
+
+        diagnostics
+    }
+
+    /// Warn at every call of a function whose doc comment says `@deprecated`,
+    /// except the calls inside the function itself
+    fn check_deprecated_functions(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        let symbols = &parsed.symbols;
+        let is_function = |word: &str| {
+            symbols
+                .functions
+                .iter()
+                .chain(&symbols.imports)
+                .any(|symbol| symbol.name.as_str() == word)
+        };
+        let mut diagnostics = Vec::new();
+        ast::for_each_expr(&parsed.ast, &mut |expr| {
+            if let ExprKind::Call { func, .. } = &expr.kind
+                && let ExprKind::Identifier(name) = &func.kind
+                && let Some(function) = symbols
+                    .functions
+                    .iter()
+                    .find(|function| function.name.as_str() == name.as_str())
+                && let Some(note) = &function.deprecated
+                && !(function.range.start <= func.span.start && func.span.end <= function.range.end)
+            {
+                diagnostics.push(deprecated_call_diagnostic(
+                    func.span,
+                    name,
+                    note,
+                    deprecation_replacement(note, name, is_function),
+                    locale,
+                ));
+            }
+        });
+        diagnostics
+    }
+
+    /// Warn about `FORMAT`-style calls whose literal template has more or fewer
+    /// `{}` than arguments after it
+    fn check_format_templates(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let user_defined = defined_names(program);
+        let mut diagnostics = Vec::new();
+
+        ast::for_each_expr(program, &mut |expr| {
+            let Some(call) = format_template::template_call(expr) else {
+                return;
+            };
+            if call.matches() || user_defined.contains(call.function) {
+                return;
+            }
+            diagnostics.push(Diagnostic {
+                range: expr.span,
+                severity: Some(codes::TEMPLATE_ARGUMENTS.severity),
+                code: codes::TEMPLATE_ARGUMENTS.lsp_code(),
+                code_description: codes::TEMPLATE_ARGUMENTS.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!(
+                        "{} 的模板有 {} 个占位符，但其后有 {} 个参数",
+                        call.function, call.placeholders, call.arguments
+                    ),
+                    Locale::En => format!(
+                        "{} template has {} placeholder(s) but {} argument(s) follow it",
+                        call.function, call.placeholders, call.arguments
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+
+        diagnostics
+    }
+
+    /// Warn about keywords the parser only took as keywords because lenient
+    /// keywords are on, like `while` for `While`
+    fn check_keyword_casing(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        parsed
+            .keyword_casings
+            .iter()
+            .map(|casing| Diagnostic {
+                range: casing.span,
+                severity: Some(codes::KEYWORD_CASING.severity),
+                code: codes::KEYWORD_CASING.lsp_code(),
+                code_description: codes::KEYWORD_CASING.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("关键字应写作 `{}`", casing.keyword),
+                    Locale::En => format!("The keyword is spelled `{}`", casing.keyword),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            })
+            .collect()
+    }
 
+    /// Report the `=` signs the parser read past: in `Set X = 10`, in a
+    /// condition meant to compare, and in `X = 10` without `Set`
+    fn check_stray_assigns(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        parsed
+            .stray_assigns
+            .iter()
+            .map(|stray| {
+                let (code, message) = match &stray.kind {
+                    StrayAssignKind::InSet => (
+                        &codes::SET_WITH_EQUALS,
+                        locale
+                            .pick(
+                                "Aether 的 Set 语句不使用 '='",
+                                "Aether's Set statement doesn't use '='",
+                            )
+                            .to_string(),
+                    ),
+                    StrayAssignKind::InCondition => (
+                        &codes::ASSIGN_IN_CONDITION,
+                        locale
+                            .pick("比较请使用 '=='", "use '==' for comparison")
+                            .to_string(),
+                    ),
+                    StrayAssignKind::WithoutSet { name, value } => {
+                        let value = literal_text(parsed, *value).unwrap_or_else(|| "…".to_string());
+                        let suggestion = format!("Set {} {}", name, value);
+                        (
+                            &codes::ASSIGN_WITHOUT_SET,
+                            match locale {
+                                Locale::Zh => format!("Aether 用 Set 赋值：`{}`", suggestion),
+                                Locale::En => format!("Aether assigns with Set: `{}`", suggestion),
+                            },
+                        )
+                    }
+                };
                 Diagnostic {
-                    range: Range {
-                        start: start_pos,
-                        end: Position {
-                            line: start_pos.line,
-                            character: start_pos.character + error_length,
+                    range: stray.span,
+                    severity: Some(code.severity),
+                    code: code.lsp_code(),
+                    code_description: code.code_description(),
+                    source: Some("aether-parser".to_string()),
+                    message,
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Check the `//!` header: keys tools don't know, a `requires` that isn't a
+    /// version, and directives after the code has started
+    fn check_header_directives(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        parsed
+            .directives()
+            .into_iter()
+            .filter_map(|directive| {
+                let (code, message) = if !directive.leading {
+                    (
+                        &codes::MISPLACED_DIRECTIVE,
+                        match locale {
+                            Locale::Zh => format!(
+                                "指令 '{}' 出现在第一条语句之后，不属于脚本头，会被当作普通注释",
+                                directive.key
+                            ),
+                            Locale::En => format!(
+                                "Directive '{}' comes after the first statement, so it is not part of the header and is just a comment",
+                                directive.key
+                            ),
+                        },
+                    )
+                } else if !directive.is_known() {
+                    (
+                        &codes::UNKNOWN_DIRECTIVE,
+                        match locale {
+                            Locale::Zh => format!(
+                                "未知的脚本头指令 '{}'，已知的有：{}",
+                                directive.key,
+                                header::KNOWN_KEYS.join(", ")
+                            ),
+                            Locale::En => format!(
+                                "Unknown header directive '{}'; known ones are {}",
+                                directive.key,
+                                header::KNOWN_KEYS.join(", ")
+                            ),
+                        },
+                    )
+                } else if directive.key == "requires" && !header::valid_version(&directive.value) {
+                    (
+                        &codes::MALFORMED_REQUIRES,
+                        match locale {
+                            Locale::Zh => format!(
+                                "'{}' 不是版本号，应为 1.2 或 1.2.3 这样的形式",
+                                directive.value
+                            ),
+                            Locale::En => format!(
+                                "'{}' is not a version like 1.2 or 1.2.3",
+                                directive.value
+                            ),
+                        },
+                    )
+                } else {
+                    return None;
+                };
+                Some(Diagnostic {
+                    range: directive.span,
+                    severity: Some(code.severity),
+                    code: code.lsp_code(),
+                    code_description: code.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message,
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Flag `For` loops over a call to a function that returns nothing, likely
+    /// meant to be a Generator, and arithmetic on what a Generator call gives
+    fn check_generator_calls(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let callables = generators::callables(program);
+        if callables.is_empty() {
+            return Vec::new();
+        }
+        let mut diagnostics = Vec::new();
+        let lint = |code: &codes::DiagnosticCode, range: Range, message: String| Diagnostic {
+            range,
+            severity: Some(code.severity),
+            code: code.lsp_code(),
+            code_description: code.code_description(),
+            source: Some("aether-lint".to_string()),
+            message,
+            tags: None,
+            related_information: None,
+            data: None,
+        };
+
+        ast::for_each_stmt(program, &mut |stmt| {
+            let (StmtKind::For { iterable, .. } | StmtKind::ForIndexed { iterable, .. }) =
+                &stmt.kind
+            else {
+                return;
+            };
+            let Some(name) = generators::callee(iterable) else {
+                return;
+            };
+            if callables.get(name)
+                != Some(&generators::Callable::Function {
+                    returns_value: false,
+                })
+            {
+                return;
+            }
+            diagnostics.push(lint(
+                &codes::NOT_A_GENERATOR,
+                iterable.span,
+                match locale {
+                    Locale::Zh => format!(
+                        "'{}' 是普通函数而不是 Generator，也不返回可遍历的值；是否想调用一个 Generator？",
+                        name
+                    ),
+                    Locale::En => format!(
+                        "'{}' is a function, not a Generator, and returns nothing to iterate; did you mean to call a Generator?",
+                        name
+                    ),
+                },
+            ));
+        });
+
+        ast::for_each_expr(program, &mut |expr| {
+            let operands = match &expr.kind {
+                ExprKind::Binary {
+                    left,
+                    op:
+                        BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulo,
+                    right,
+                } => vec![&**left, &**right],
+                ExprKind::Unary {
+                    op: UnaryOp::Minus,
+                    expr,
+                } => vec![&**expr],
+                _ => return,
+            };
+            for operand in operands {
+                if let Some(name) = generators::generator_call(operand, &callables) {
+                    diagnostics.push(lint(
+                        &codes::GENERATOR_ARITHMETIC,
+                        operand.span,
+                        match locale {
+                            Locale::Zh => format!(
+                                "'{}' 是 Generator，调用它得到的是生成器对象而不是数值；请用 For 遍历它",
+                                name
+                            ),
+                            Locale::En => format!(
+                                "'{}' is a Generator, so calling it gives a generator object, not a number; iterate it with For",
+                                name
+                            ),
                         },
+                    ));
+                }
+            }
+        });
+
+        diagnostics
+    }
+
+    /// Flag identifiers that are never bound and aren't builtin functions or constants
+    fn check_undefined_identifiers(
+        program: &Program,
+        mentioned: &HashSet<Name>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let bound = bound_names(program);
+        let mut diagnostics = Vec::new();
+
+        ast::for_each_expr(program, &mut |expr| {
+            if let ExprKind::Identifier(name) = &expr.kind
+                && !bound.contains(name.as_str())
+                && !mentioned.contains(name)
+                && !builtins::is_builtin_name(name)
+            {
+                diagnostics.push(Diagnostic {
+                    range: expr.span,
+                    severity: Some(codes::UNDEFINED_IDENTIFIER.severity),
+                    code: codes::UNDEFINED_IDENTIFIER.lsp_code(),
+                    code_description: codes::UNDEFINED_IDENTIFIER.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => format!("未定义的标识符 '{}'", name),
+                        Locale::En => format!("Undefined identifier '{}'", name),
                     },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: Some(NumberOrString::String(Self::error_code_from_message(
-                        &error.message,
-                    ))),
-                    source: Some("aether-parser".to_string()),
-                    message: error.message.clone(),
                     related_information: None,
                     tags: None,
-                    code_description: None,
+                    // For the quick fix importing it from another module
+                    data: Some(serde_json::json!({ "name": name.as_str() })),
+                });
+            }
+        });
+
+        diagnostics
+    }
+
+    /// Flag builtin calls whose argument count doesn't match the registry signature
+    fn check_builtin_arity(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let user_defined = defined_names(program);
+        let mut diagnostics = Vec::new();
+
+        ast::for_each_expr(program, &mut |expr| {
+            let ExprKind::Call { func, args } = &expr.kind else {
+                return;
+            };
+            let ExprKind::Identifier(name) = &func.kind else {
+                return;
+            };
+            if user_defined.contains(name.as_str()) {
+                return;
+            }
+            let Some(builtin) = builtins::lookup(name) else {
+                return;
+            };
+
+            let (min, max) = builtin.arity();
+            if args.len() >= min && max.is_none_or(|max| args.len() <= max) {
+                return;
+            }
+            let expected = match (min, max) {
+                (min, Some(max)) if min == max => min.to_string(),
+                (min, Some(max)) => format!("{}-{}", min, max),
+                (min, None) => match locale {
+                    Locale::Zh => format!("至少 {}", min),
+                    Locale::En => format!("at least {}", min),
+                },
+            };
+
+            diagnostics.push(Diagnostic {
+                range: expr.span,
+                severity: Some(codes::BUILTIN_ARITY.severity),
+                code: codes::BUILTIN_ARITY.lsp_code(),
+                code_description: codes::BUILTIN_ARITY.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!(
+                        "内置函数 '{}' 需要 {} 个参数，实际传入 {} 个\n签名: {}",
+                        builtin.name,
+                        expected,
+                        args.len(),
+                        builtin.signature
+                    ),
+                    Locale::En => format!(
+                        "Builtin '{}' expects {} argument(s) but got {}\nSignature: {}",
+                        builtin.name,
+                        expected,
+                        args.len(),
+                        builtin.signature
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+
+        diagnostics
+    }
+
+    /// Flag callbacks of MAP, FILTER and REDUCE taking a different number of
+    /// arguments than the builtin calls them with: lambda literals, and functions
+    /// or lambda-bound variables passed by name
+    fn check_callback_arity(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        let mut checker = CallbackArityChecker {
+            parsed,
+            locale,
+            user_defined: defined_names(&parsed.ast),
+            locals: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        checker.visit_block(&parsed.ast);
+        checker.diagnostics
+    }
+
+    /// Hint at `Lazy` bindings that no `Force(NAME)` in the file ever evaluates
+    fn check_lazy_never_forced(
+        program: &Program,
+        mentioned: &HashSet<Name>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let mut forced = HashSet::new();
+        ast::for_each_expr(program, &mut |expr| {
+            if let ExprKind::Force(inner) = &expr.kind
+                && let ExprKind::Identifier(name) = &inner.kind
+            {
+                forced.insert(name.as_str());
+            }
+        });
+
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            if let StmtKind::LazyDef { name, .. } = &stmt.kind
+                && !forced.contains(name.as_str())
+                && !mentioned.contains(name.as_str())
+            {
+                diagnostics.push(Diagnostic {
+                    range: name.span,
+                    severity: Some(codes::LAZY_NEVER_FORCED.severity),
+                    code: codes::LAZY_NEVER_FORCED.lsp_code(),
+                    code_description: codes::LAZY_NEVER_FORCED.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => format!("惰性值 '{}' 从未被 Force 求值", name),
+                        Locale::En => format!("Lazy value '{}' is never forced", name),
+                    },
+                    tags: None,
+                    related_information: None,
                     data: None,
+                });
+            }
+        });
+        diagnostics
+    }
+
+    /// Point out `Force(...)` of a value that can't be Lazy: a literal, a lambda, an
+    /// operator's result, or a name only ever bound to such values or to functions
+    fn check_force_not_lazy(
+        program: &Program,
+        mentioned: &HashSet<Name>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let never_lazy = never_lazy_names(program);
+        let mut diagnostics = Vec::new();
+        ast::for_each_expr(program, &mut |expr| {
+            let ExprKind::Force(inner) = &expr.kind else {
+                return;
+            };
+            let known = match &inner.kind {
+                ExprKind::Identifier(name) => {
+                    !mentioned.contains(name) && never_lazy.get(name.as_str()) == Some(&true)
                 }
+                _ => is_never_lazy(inner),
+            };
+            if known {
+                diagnostics.push(Diagnostic {
+                    range: expr.span,
+                    severity: Some(codes::FORCE_NOT_LAZY.severity),
+                    code: codes::FORCE_NOT_LAZY.lsp_code(),
+                    code_description: codes::FORCE_NOT_LAZY.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => "Force 的参数不是 Lazy 值，Force 没有任何效果".to_string(),
+                        Locale::En => {
+                            "Force is applied to a value that is not Lazy and has no effect"
+                                .to_string()
+                        }
+                    },
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                });
+            }
+        });
+        diagnostics
+    }
+
+    /// Warn about expression statements whose result is discarded without effect
+    fn check_unused_expressions(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let mut finder = UnusedExpressionFinder::default();
+        finder.visit_block(program);
+        finder
+            .found
+            .into_iter()
+            .map(|(stmt, set_target)| Diagnostic {
+                range: stmt.span,
+                severity: Some(codes::UNUSED_EXPRESSION.severity),
+                code: codes::UNUSED_EXPRESSION.lsp_code(),
+                code_description: codes::UNUSED_EXPRESSION.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match (locale, set_target) {
+                    (Locale::Zh, None) => "表达式的结果未被使用".to_string(),
+                    (Locale::En, None) => "Expression result is unused".to_string(),
+                    (Locale::Zh, Some(name)) => {
+                        format!("表达式的结果未被使用；是否想写 `Set {} ...`？", name)
+                    }
+                    (Locale::En, Some(name)) => format!(
+                        "Expression result is unused; did you mean `Set {} ...`?",
+                        name
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
             })
             .collect()
     }
 
-    /// Estimate error length for better range highlighting
-    fn estimate_error_length(message: &str) -> u32 {
-        if message.contains("identifier") {
-            10
-        } else if message.contains("Expected") {
-            5
-        } else if message.contains("UPPER_SNAKE_CASE") {
-            15
-        } else {
-            8
-        }
+    /// Check what `For` loops iterate: a Number, Boolean or Null literal can't
+    /// be, nor can a builtin call or variable known to give one; a string
+    /// literal is, character by character
+    fn check_for_iterables(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let types = Types::new(program);
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            let (StmtKind::For { iterable, .. } | StmtKind::ForIndexed { iterable, .. }) =
+                &stmt.kind
+            else {
+                return;
+            };
+            let (code, message) = match &iterable.kind {
+                ExprKind::Number(_)
+                | ExprKind::BigInteger(_)
+                | ExprKind::Boolean(_)
+                | ExprKind::Null => (
+                    &codes::NOT_ITERABLE,
+                    locale
+                        .pick("表达式不可遍历", "expression is not iterable")
+                        .to_string(),
+                ),
+                ExprKind::String(_) => (
+                    &codes::STRING_ITERATION,
+                    locale
+                        .pick(
+                            "遍历字符串会逐个得到它的字符",
+                            "Iterating a string gives its characters one by one",
+                        )
+                        .to_string(),
+                ),
+                _ => {
+                    let found = types.of(iterable);
+                    if !matches!(
+                        found,
+                        ValueType::Number | ValueType::Boolean | ValueType::Null
+                    ) {
+                        return;
+                    }
+                    let type_name = found.name(locale);
+                    let message = match (&iterable.kind, generators::callee(iterable)) {
+                        (_, Some(name)) => match locale {
+                            Locale::Zh => format!("'{}' 返回{}，不可遍历", name, type_name),
+                            Locale::En => {
+                                format!("'{}' returns a {}, which is not iterable", name, type_name)
+                            }
+                        },
+                        (ExprKind::Identifier(name), None) => match locale {
+                            Locale::Zh => format!("'{}' 的值是{}，不可遍历", name, type_name),
+                            Locale::En => {
+                                format!("'{}' holds a {}, which is not iterable", name, type_name)
+                            }
+                        },
+                        _ => match locale {
+                            Locale::Zh => format!("表达式的值是{}，不可遍历", type_name),
+                            Locale::En => format!(
+                                "The expression gives a {}, which is not iterable",
+                                type_name
+                            ),
+                        },
+                    };
+                    (&codes::NON_ITERABLE_VALUE, message)
+                }
+            };
+            diagnostics.push(Diagnostic {
+                range: iterable.span,
+                severity: Some(code.severity),
+                code: code.lsp_code(),
+                code_description: code.code_description(),
+                source: Some("aether-lint".to_string()),
+                message,
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
+    }
+
+    /// Flag constant indices outside an array literal the file never resizes
+    fn check_index_bounds(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        ast::for_each_expr(&parsed.ast, &mut |expr| {
+            let ExprKind::Index { object, index } = &expr.kind else {
+                return;
+            };
+            let ExprKind::Identifier(name) = &object.kind else {
+                return;
+            };
+            let Some(constant) = array_bounds::constant_index(index) else {
+                return;
+            };
+            let Some(length) = array_bounds::known_length(parsed, name)
+                .filter(|length| array_bounds::out_of_range(constant, *length))
+            else {
+                return;
+            };
+            let code = &codes::INDEX_OUT_OF_RANGE;
+            diagnostics.push(Diagnostic {
+                range: expr.span,
+                severity: Some(code.severity),
+                code: code.lsp_code(),
+                code_description: code.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!(
+                        "索引 {} 超出范围：'{}' 是 {} 个元素的数组字面量",
+                        constant, name, length
+                    ),
+                    Locale::En => format!(
+                        "Index {} is out of range: '{}' is an array literal of {} elements",
+                        constant, name, length
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
     }
 
-    /// Extract error code from message
-    fn error_code_from_message(message: &str) -> String {
-        if message.contains("UPPER_SNAKE_CASE") {
-            "E001".to_string()
-        } else if message.contains("Unexpected token") {
-            "E002".to_string()
-        } else if message.contains("Expected") {
-            "E003".to_string()
-        } else if message.contains("Invalid expression") {
-            "E004".to_string()
-        } else {
-            "E000".to_string()
+    /// Flag each definition whose name reads the same as an earlier, differently
+    /// spelled one; definitions of the very same name are not this rule's concern
+    fn check_lookalike_names(
+        parsed: &ParsedDocument,
+        uri: Option<&Url>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let mut definitions: Vec<(&str, Range)> = parsed
+            .occurrences
+            .iter()
+            .flat_map(|(name, occurrences)| {
+                occurrences
+                    .iter()
+                    .filter(|occurrence| matches!(occurrence.role, Role::Definition | Role::Import))
+                    .map(move |occurrence| (name.as_str(), occurrence.span))
+            })
+            .collect();
+        definitions.sort_by_key(|(_, span)| span.start);
+
+        let mut first: HashMap<String, (&str, Range)> = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for (name, span) in definitions {
+            let (original, original_span) = *first
+                .entry(confusables::normalize(name))
+                .or_insert((name, span));
+            if original == name {
+                continue;
+            }
+            let mut lookalikes = confusables::lookalike_chars(original);
+            for ch in confusables::lookalike_chars(name) {
+                if !lookalikes.contains(&ch) {
+                    lookalikes.push(ch);
+                }
+            }
+            let listed = lookalikes
+                .iter()
+                .map(|ch| format!("'{}' (U+{:04X})", ch, *ch as u32))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let code = &codes::LOOKALIKE_NAME;
+            diagnostics.push(Diagnostic {
+                range: span,
+                severity: Some(code.severity),
+                code: code.lsp_code(),
+                code_description: code.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match (locale, lookalikes.is_empty()) {
+                    (Locale::Zh, true) => {
+                        format!("'{}' 与 '{}' 仅大小写不同", name, original)
+                    }
+                    (Locale::Zh, false) => format!(
+                        "'{}' 看起来与 '{}' 相同，但用了形近字符：{}",
+                        name, original, listed
+                    ),
+                    (Locale::En, true) => {
+                        format!("'{}' differs from '{}' only by case", name, original)
+                    }
+                    (Locale::En, false) => format!(
+                        "'{}' looks the same as '{}' but uses lookalike characters: {}",
+                        name, original, listed
+                    ),
+                },
+                tags: None,
+                related_information: uri.map(|uri| {
+                    vec![DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), original_span),
+                        message: match locale {
+                            Locale::Zh => format!("'{}' 首先在此定义", original),
+                            Locale::En => format!("'{}' is first defined here", original),
+                        },
+                    }]
+                }),
+                data: None,
+            });
         }
+        diagnostics
     }
 
-    fn check_naming_convention(text: &str) -> Vec<Diagnostic> {
+    /// Hint at loops that likely don't do what was meant: a ForIndexed whose
+    /// variables are used the other way round, and a For over a dict, which
+    /// gives its keys
+    fn check_loop_heuristics(
+        program: &Program,
+        settings: &HeuristicSettings,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        if !settings.swapped_loop_variables && !settings.dict_iteration {
+            return Vec::new();
+        }
+        let types = Types::new(program);
         let mut diagnostics = Vec::new();
-        let mut lexer = Lexer::new(text);
-        let mut prev_token = Token::EOF;
+        ast::for_each_stmt(program, &mut |stmt| {
+            let (code, range, message) = match &stmt.kind {
+                StmtKind::ForIndexed {
+                    index_var,
+                    value_var,
+                    body,
+                    ..
+                } if settings.swapped_loop_variables
+                    && loops::swapped_variables(index_var, value_var, body) =>
+                {
+                    let message = match locale {
+                        Locale::Zh => format!(
+                            "ForIndexed 先绑定索引再绑定值：'{}' 像是值，'{}' 像是索引，两者可能写反了",
+                            index_var.as_str(),
+                            value_var.as_str()
+                        ),
+                        Locale::En => format!(
+                            "ForIndexed binds the index first, then the value: '{}' is used like a value and '{}' like an index, so they may be swapped",
+                            index_var.as_str(),
+                            value_var.as_str()
+                        ),
+                    };
+                    let range = Range::new(index_var.span.start, value_var.span.end);
+                    (&codes::SWAPPED_LOOP_VARIABLES, range, message)
+                }
+                StmtKind::For { iterable, .. } if settings.dict_iteration => {
+                    let Some(dict) = loops::dict_variable(iterable, &types) else {
+                        return;
+                    };
+                    let message = match locale {
+                        Locale::Zh => format!(
+                            "遍历字典 '{}' 得到的是键；需要键和值时请遍历 ITEMS({})",
+                            dict, dict
+                        ),
+                        Locale::En => format!(
+                            "Iterating the dict '{}' gives its keys; iterate ITEMS({}) for keys and values",
+                            dict, dict
+                        ),
+                    };
+                    (&codes::DICT_ITERATION, iterable.span, message)
+                }
+                _ => return,
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(code.severity),
+                code: code.lsp_code(),
+                code_description: code.code_description(),
+                source: Some("aether-lint".to_string()),
+                message,
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
+    }
+
+    /// Hint at lines whose indentation mixes tabs and spaces, or which sit in a
+    /// block off the indent width's grid, when the lint is enabled. The fix in
+    /// each diagnostic's data is the line's indentation in the configured style.
+    fn check_indentation(
+        parsed: &ParsedDocument,
+        settings: &IndentationSettings,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let width = settings.width;
+        if !settings.enabled || width == 0 {
+            return Vec::new();
+        }
+        let blocks = folding::token_structure(&parsed.text).blocks;
+        parsed
+            .text
+            .lines()
+            .enumerate()
+            .filter_map(|(line, text)| {
+                let line = line as u32;
+                let code = text.trim_start_matches([' ', '\t']);
+                let indent = &text[..text.len() - code.len()];
+                // Blank lines have no indentation, and string lines no code
+                if indent.is_empty()
+                    || code.is_empty()
+                    || parsed.mask.contains(Position::new(line, 0))
+                {
+                    return None;
+                }
+                let columns = indent.chars().fold(0, |column, c| match c {
+                    '\t' => (column / width + 1) * width,
+                    _ => column + 1,
+                });
+                // A `}` leading its line sits at the depth of its `{`
+                let depth = blocks
+                    .iter()
+                    .filter(|block| {
+                        block.start.line < line
+                            && (line < block.end.line
+                                || (line == block.end.line
+                                    && block.end.character as usize > indent.len() + 1))
+                    })
+                    .count();
+
+                let mixed = indent.contains(' ') && indent.contains('\t');
+                let off_grid = depth > 0 && columns % width != 0;
+                let message = if mixed {
+                    locale
+                        .pick(
+                            "缩进混用了制表符和空格",
+                            "Indentation mixes tabs and spaces",
+                        )
+                        .to_string()
+                } else if off_grid {
+                    match locale {
+                        Locale::Zh => format!("缩进为 {} 列，不是 {} 的倍数", columns, width),
+                        Locale::En => format!(
+                            "Indentation of {} columns isn't a multiple of {}",
+                            columns, width
+                        ),
+                    }
+                } else {
+                    return None;
+                };
+                let columns = if off_grid { depth * width } else { columns };
+                let fixed = if settings.use_tabs {
+                    "\t".repeat(columns.div_ceil(width))
+                } else {
+                    " ".repeat(columns)
+                };
 
-        loop {
-            let line = lexer.line();
-            let column = lexer.column();
-            let token = lexer.next_token();
+                Some(Diagnostic {
+                    range: Range::new(
+                        Position::new(line, 0),
+                        Position::new(line, indent.len() as u32),
+                    ),
+                    severity: Some(codes::INDENTATION.severity),
+                    code: codes::INDENTATION.lsp_code(),
+                    code_description: codes::INDENTATION.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message,
+                    tags: None,
+                    related_information: None,
+                    data: Some(serde_json::json!({ "indent": fixed })),
+                })
+            })
+            .collect()
+    }
 
-            if token == Token::EOF {
-                break;
+    /// Warn about fractional literals whose digits change on the way through an
+    /// f64, e.g. `9007199254740993.0`, which runs as `9007199254740992`
+    fn check_float_precision(parsed: &ParsedDocument, locale: Locale) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        ast::for_each_expr(&parsed.ast, &mut |expr| {
+            let ExprKind::Number(value) = expr.kind else {
+                return;
+            };
+            let Some(literal) = literal_text(parsed, expr.span) else {
+                return;
+            };
+            if !literal.contains('.')
+                || decimal_digits(&literal) == decimal_digits(&value.to_string())
+            {
+                return;
             }
+            diagnostics.push(Diagnostic {
+                range: expr.span,
+                severity: Some(codes::PRECISION_LOSS.severity),
+                code: codes::PRECISION_LOSS.lsp_code(),
+                code_description: codes::PRECISION_LOSS.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("{} 超出 f64 的精度，实际的值是 {}", literal, value),
+                    Locale::En => format!(
+                        "{} is beyond the precision of an f64 and becomes {}",
+                        literal, value
+                    ),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
+    }
 
-            // 检查标识符命名（跳过关键字后的标识符定义）
-            if let Token::Identifier(name) = &token {
-                // Skip checking for function/generator parameters (after Func/Generator keyword and parentheses)
-                let is_definition = matches!(
-                    prev_token,
-                    Token::Set | Token::Func | Token::Generator | Token::Lazy
-                );
+    /// Warn about `Set X X` and hint at assignments overwritten before anything reads them
+    fn check_redundant_assignments(
+        program: &Program,
+        uri: Option<&Url>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let user_defined = defined_names(program);
+        let mut finder = RedundantAssignmentFinder {
+            user_defined: &user_defined,
+            self_assignments: Vec::new(),
+            dead_stores: Vec::new(),
+        };
+        finder.visit_block(program);
 
-                // Only check variable/function names, not all identifiers
-                if is_definition && !Self::is_valid_aether_name(name) {
-                    diagnostics.push(Diagnostic {
-                        range: Range {
-                            start: Position {
-                                line: line.saturating_sub(1) as u32,
-                                character: column.saturating_sub(1).max(0) as u32,
+        let mut diagnostics: Vec<Diagnostic> = finder
+            .self_assignments
+            .into_iter()
+            .map(|(stmt, name)| Diagnostic {
+                range: stmt.span,
+                severity: Some(codes::SELF_ASSIGNMENT.severity),
+                code: codes::SELF_ASSIGNMENT.lsp_code(),
+                code_description: codes::SELF_ASSIGNMENT.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("将 '{}' 赋值给自身没有任何效果", name),
+                    Locale::En => format!("Assigning '{}' to itself has no effect", name),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            })
+            .collect();
+        diagnostics.extend(
+            finder
+                .dead_stores
+                .into_iter()
+                .map(|(stmt, name, overwrite)| Diagnostic {
+                    range: stmt.span,
+                    severity: Some(codes::DEAD_STORE.severity),
+                    code: codes::DEAD_STORE.lsp_code(),
+                    code_description: codes::DEAD_STORE.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => format!("赋给 '{}' 的值在被读取前就被覆盖", name),
+                        Locale::En => {
+                            format!(
+                                "Value assigned to '{}' is overwritten before it is read",
+                                name
+                            )
+                        }
+                    },
+                    tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                    related_information: uri.map(|uri| {
+                        vec![DiagnosticRelatedInformation {
+                            location: Location::new(uri.clone(), overwrite.span),
+                            message: match locale {
+                                Locale::Zh => "在此处被覆盖".to_string(),
+                                Locale::En => "Overwritten here".to_string(),
                             },
-                            end: Position {
-                                line: line.saturating_sub(1) as u32,
-                                character: (column.saturating_sub(1) + name.len()) as u32,
+                        }]
+                    }),
+                    data: None,
+                }),
+        );
+        diagnostics
+    }
+
+    /// Warn about functions with too many parameters, too deep nesting or too many
+    /// statements, once per exceeded limit, on the function's name
+    fn check_function_complexity(
+        program: &Program,
+        limits: &ComplexitySettings,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            let (StmtKind::FuncDef { name, params, body }
+            | StmtKind::GeneratorDef { name, params, body }) = &stmt.kind
+            else {
+                return;
+            };
+            // Each check with its measured value, its limit and the unit it counts
+            let measures = [
+                (
+                    &codes::TOO_MANY_PARAMS,
+                    params.len(),
+                    limits.max_params,
+                    ("个参数", "parameters"),
+                ),
+                (
+                    &codes::TOO_DEEPLY_NESTED,
+                    ast::nesting_depth(body),
+                    limits.max_nesting,
+                    ("层嵌套", "levels of nesting"),
+                ),
+                (
+                    &codes::TOO_MANY_STATEMENTS,
+                    ast::statement_count(body),
+                    limits.max_statements,
+                    ("条语句", "statements"),
+                ),
+            ];
+            for (code, measured, limit, (unit_zh, unit_en)) in measures {
+                if limit == 0 || measured <= limit {
+                    continue;
+                }
+                diagnostics.push(Diagnostic {
+                    range: name.span,
+                    severity: Some(code.severity),
+                    code: code.lsp_code(),
+                    code_description: code.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => format!(
+                            "函数 '{}' 有 {} {}（上限 {}）",
+                            name, measured, unit_zh, limit
+                        ),
+                        Locale::En => format!(
+                            "Function '{}' has {} {} (limit {})",
+                            name, measured, unit_en, limit
+                        ),
+                    },
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                });
+            }
+        });
+        diagnostics
+    }
+
+    /// Point out `Set`s, parameters and loop variables named like a builtin function
+    /// or like a binding of an enclosing function, lambda or the file itself.
+    /// Names starting with `_` are exempt.
+    fn check_shadowing(
+        program: &Program,
+        settings: &ShadowingSettings,
+        uri: Option<&Url>,
+        locale: Locale,
+    ) -> Vec<Diagnostic> {
+        let severity = match settings.severity {
+            ShadowingSeverity::Off => return Vec::new(),
+            ShadowingSeverity::Information => DiagnosticSeverity::INFORMATION,
+            ShadowingSeverity::Warning => DiagnosticSeverity::WARNING,
+        };
+        let mut finder = ShadowFinder::default();
+        finder.check_scope(&[], program);
+
+        let mut diagnostics = Vec::new();
+        for (binding, shadowed) in finder.found {
+            let (code, message, related) = match shadowed {
+                Some(outer) => (
+                    &codes::SHADOWED_BINDING,
+                    match locale {
+                        Locale::Zh => format!("'{}' 遮蔽了外层作用域中的同名绑定", binding),
+                        Locale::En => {
+                            format!("'{}' shadows a binding in an enclosing scope", binding)
+                        }
+                    },
+                    uri.map(|uri| {
+                        vec![DiagnosticRelatedInformation {
+                            location: Location::new(uri.clone(), outer.span),
+                            message: match locale {
+                                Locale::Zh => "被遮蔽的定义".to_string(),
+                                Locale::En => "Shadowed definition".to_string(),
                             },
-                        },
-                        severity: Some(DiagnosticSeverity::WARNING),
-                        code: Some(NumberOrString::String("W001".to_string())),
-                        code_description: Some(CodeDescription {
-                            href: Url::parse(
-                                "https://github.com/xiaozuhui/aether-lang/wiki/naming-conventions",
+                        }]
+                    }),
+                ),
+                None if settings.builtins => (
+                    &codes::SHADOWED_BUILTIN,
+                    match locale {
+                        Locale::Zh => format!("'{}' 遮蔽了同名的内置函数", binding),
+                        Locale::En => {
+                            format!(
+                                "'{}' shadows the builtin function of the same name",
+                                binding
                             )
-                            .unwrap_or_else(|_| Url::parse("file:///").unwrap()),
-                        }),
-                        source: Some("aether-lint".to_string()),
-                        message: format!(
-                            "变量名 '{}' 应使用 UPPER_SNAKE_CASE 格式\n建议: {}",
-                            name,
-                            Self::suggest_upper_snake_case(name)
+                        }
+                    },
+                    None,
+                ),
+                None => continue,
+            };
+            diagnostics.push(Diagnostic {
+                range: binding.span,
+                severity: Some(severity),
+                code: code.lsp_code(),
+                code_description: code.code_description(),
+                source: Some("aether-lint".to_string()),
+                message,
+                tags: None,
+                related_information: related,
+                data: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Warn about `While` loops whose condition only reads variables the body never
+    /// writes, so it can't change from one iteration to the next. A condition with
+    /// calls, indexing or member access, or a body that can leave the loop, might
+    /// still end, so those are left alone.
+    fn check_loops_never_ending(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            let StmtKind::While { condition, body } = &stmt.kind else {
+                return;
+            };
+            let Some(read) = condition_names(condition) else {
+                return;
+            };
+            if read.is_empty() {
+                return;
+            }
+            let mut writes = LoopWrites::default();
+            writes.visit_block(body);
+            if writes.exits || read.iter().any(|name| writes.written.contains(name)) {
+                return;
+            }
+            let names = read
+                .iter()
+                .map(|name| format!("'{}'", name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            diagnostics.push(Diagnostic {
+                range: condition.span,
+                severity: Some(codes::LOOP_NEVER_ENDS.severity),
+                code: codes::LOOP_NEVER_ENDS.lsp_code(),
+                code_description: codes::LOOP_NEVER_ENDS.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => format!("循环可能永远不会结束：循环体中没有修改 {}", names),
+                    Locale::En => format!("Loop may never end: the body never changes {}", names),
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
+    }
+
+    /// Warn about empty `Catch` blocks and hint at `Try` blocks with nothing that can
+    /// throw: no `Throw` and no calls
+    fn check_try_catch(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            let StmtKind::Try {
+                body,
+                error_var,
+                handler,
+            } = &stmt.kind
+            else {
+                return;
+            };
+            if handler.is_empty() {
+                // `Catch (ERROR)` has a name to underline; otherwise the closing brace
+                let range = match error_var {
+                    Some(error_var) => error_var.span,
+                    None => Range::new(
+                        Position::new(
+                            stmt.span.end.line,
+                            stmt.span.end.character.saturating_sub(1),
                         ),
-                        tags: None,
-                        related_information: None,
-                        data: None,
-                    });
-                }
+                        stmt.span.end,
+                    ),
+                };
+                diagnostics.push(Diagnostic {
+                    range,
+                    severity: Some(codes::EMPTY_CATCH.severity),
+                    code: codes::EMPTY_CATCH.lsp_code(),
+                    code_description: codes::EMPTY_CATCH.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => "Catch 块为空，错误被静默吞掉".to_string(),
+                        Locale::En => {
+                            "Empty Catch block: the error is silently swallowed".to_string()
+                        }
+                    },
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                });
             }
 
-            prev_token = token;
-        }
+            let mut can_fail = false;
+            ast::for_each_stmt(body, &mut |inner| {
+                can_fail |= matches!(inner.kind, StmtKind::Throw(_));
+            });
+            ast::for_each_expr(body, &mut |expr| {
+                can_fail |= matches!(expr.kind, ExprKind::Call { .. });
+            });
+            if !can_fail {
+                diagnostics.push(Diagnostic {
+                    range: Range::new(
+                        stmt.span.start,
+                        Position::new(stmt.span.start.line, stmt.span.start.character + 3),
+                    ),
+                    severity: Some(codes::TRY_CANNOT_FAIL.severity),
+                    code: codes::TRY_CANNOT_FAIL.lsp_code(),
+                    code_description: codes::TRY_CANNOT_FAIL.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => "Try 块中没有 Throw 也没有函数调用，不会失败".to_string(),
+                        Locale::En => {
+                            "Try block cannot fail: it has no Throw and no calls".to_string()
+                        }
+                    },
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                });
+            }
+        });
+        diagnostics
+    }
+
+    /// Point out `Throw`s outside any `Try` whose function is never called, directly
+    /// or through other functions, from inside a `Try` in this file
+    fn check_uncaught_throws(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let graph = CallGraph::build(program);
+        // Each `Try` body with the function it is written in
+        let mut try_bodies: Vec<(Range, Option<&str>)> = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            if let StmtKind::Try { body, .. } = &stmt.kind
+                && let (Some(first), Some(last)) = (body.first(), body.last())
+            {
+                try_bodies.push((
+                    Range::new(first.span.start, last.span.end),
+                    graph.enclosing_function(stmt.span.start),
+                ));
+            }
+        });
+        let in_try = |range: Range| {
+            try_bodies.iter().any(|(body, function)| {
+                span_contains(*body, range.start)
+                    && graph.enclosing_function(range.start) == *function
+            })
+        };
+        let protected = graph.reachable_from(in_try);
+
+        let mut diagnostics = Vec::new();
+        ast::for_each_stmt(program, &mut |stmt| {
+            if !matches!(stmt.kind, StmtKind::Throw(_)) || in_try(stmt.span) {
+                return;
+            }
+            if graph
+                .enclosing_function(stmt.span.start)
+                .is_some_and(|function| protected.contains(function))
+            {
+                return;
+            }
+            diagnostics.push(Diagnostic {
+                range: stmt.span,
+                severity: Some(codes::UNCAUGHT_THROW.severity),
+                code: codes::UNCAUGHT_THROW.lsp_code(),
+                code_description: codes::UNCAUGHT_THROW.code_description(),
+                source: Some("aether-lint".to_string()),
+                message: match locale {
+                    Locale::Zh => "抛出的错误不会被本文件中的任何 Try 捕获".to_string(),
+                    Locale::En => {
+                        "The error thrown here is not caught by any Try in this file".to_string()
+                    }
+                },
+                tags: None,
+                related_information: None,
+                data: None,
+            });
+        });
+        diagnostics
+    }
+
+    /// Point out every call to a File/System builtin
+    fn check_io_usage(program: &Program, locale: Locale) -> Vec<Diagnostic> {
+        let user_defined = defined_names(program);
+        let mut diagnostics = Vec::new();
+
+        ast::for_each_expr(program, &mut |expr| {
+            if let ExprKind::Call { func, .. } = &expr.kind
+                && let ExprKind::Identifier(name) = &func.kind
+                && !user_defined.contains(name.as_str())
+                && let Some(builtin) = builtins::lookup(name)
+                && builtin.performs_io()
+            {
+                diagnostics.push(Diagnostic {
+                    range: func.span,
+                    severity: Some(codes::IO_USAGE.severity),
+                    code: codes::IO_USAGE.lsp_code(),
+                    code_description: codes::IO_USAGE.code_description(),
+                    source: Some("aether-lint".to_string()),
+                    message: match locale {
+                        Locale::Zh => format!("脚本执行了 I/O 操作: {}", builtin.name),
+                        Locale::En => format!("script performs I/O: {}", builtin.name),
+                    },
+                    tags: None,
+                    related_information: None,
+                    data: None,
+                });
+            }
+        });
 
         diagnostics
     }
 
+    /// Collect the bindings (alias if present, otherwise the name) of unused imports
+    pub fn unused_imports(program: &Program) -> Vec<&Ident> {
+        let mut collector = ReferenceCollector::default();
+        collector.visit_block(program);
+        let referenced = collector.refs;
+
+        program
+            .imports()
+            .iter()
+            .flat_map(ImportInfo::bindings)
+            .filter(|binding| !referenced.contains(binding.as_str()))
+            .collect()
+    }
+
     /// Suggest UPPER_SNAKE_CASE version of a name
     fn suggest_upper_snake_case(name: &str) -> String {
         name.to_uppercase()
     }
+}
 
-    fn is_valid_aether_name(name: &str) -> bool {
-        !name.is_empty()
-            && name
-                .chars()
-                .all(|c| c.is_ascii_uppercase() || c == '_' || c.is_ascii_digit())
-            && !name.chars().next().unwrap().is_ascii_digit()
+/// W038 on `callee`, calling the deprecated `name`; with a `replacement`, the
+/// data carries it for the quick fix that calls it instead
+pub fn deprecated_call_diagnostic(
+    callee: Range,
+    name: &str,
+    note: &str,
+    replacement: Option<&str>,
+    locale: Locale,
+) -> Diagnostic {
+    let code = &codes::DEPRECATED_FUNCTION;
+    Diagnostic {
+        range: callee,
+        severity: Some(code.severity),
+        code: code.lsp_code(),
+        code_description: code.code_description(),
+        source: Some("aether-lint".to_string()),
+        message: match (locale, note.is_empty()) {
+            (Locale::Zh, true) => format!("'{}' 已弃用", name),
+            (Locale::Zh, false) => format!("'{}' 已弃用：{}", name, note),
+            (Locale::En, true) => format!("'{}' is deprecated", name),
+            (Locale::En, false) => format!("'{}' is deprecated: {}", name, note),
+        },
+        tags: Some(vec![DiagnosticTag::DEPRECATED]),
+        related_information: None,
+        data: replacement.map(|replacement| serde_json::json!({ "replacement": replacement })),
+    }
+}
+
+/// The first word of a deprecation `note` that names a function other than
+/// `name`, by `is_function`
+pub fn deprecation_replacement<'n>(
+    note: &'n str,
+    name: &str,
+    is_function: impl Fn(&str) -> bool,
+) -> Option<&'n str> {
+    note.split(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .filter(|word| !word.is_empty() && *word != name)
+        .find(|word| is_function(word))
+}
+
+/// Names bound anywhere in the program, which shadow builtins of the same name
+fn defined_names(program: &[Stmt]) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    let mut pending: Vec<&[Stmt]> = vec![program];
+    while let Some(body) = pending.pop() {
+        for stmt in body {
+            match &stmt.kind {
+                StmtKind::Set { name, .. } | StmtKind::LazyDef { name, .. } => {
+                    names.insert(name.as_str());
+                }
+                StmtKind::FuncDef { name, body, .. }
+                | StmtKind::GeneratorDef { name, body, .. } => {
+                    names.insert(name.as_str());
+                    pending.push(body);
+                }
+                StmtKind::Import {
+                    names: imported,
+                    aliases,
+                    ..
+                } => {
+                    for (name, alias) in imported.iter().zip(aliases) {
+                        names.insert(alias.as_ref().unwrap_or(name).as_str());
+                    }
+                }
+                _ => pending.extend(stmt.child_blocks()),
+            }
+        }
+    }
+    names
+}
+
+/// The source of a single-line `span`
+fn literal_text(parsed: &ParsedDocument, span: Range) -> Option<String> {
+    if span.start.line != span.end.line {
+        return None;
+    }
+    parsed.line_index.line_text(&parsed.text, span.start.line)?;
+    let start = parsed
+        .line_index
+        .position_to_offset(&parsed.text, span.start);
+    let end = parsed.line_index.position_to_offset(&parsed.text, span.end);
+    parsed.text.get(start..end).map(str::to_string)
+}
+
+/// A decimal numeral as its significant digits and the place of the decimal
+/// point among them, so `1.50`, `01.5` and `1.5` compare equal; the sign is
+/// left out, so `-00.5` and `-0.5` do too
+fn decimal_digits(numeral: &str) -> (String, isize) {
+    let numeral = numeral.trim_start_matches('-');
+    let (integer, fraction) = numeral.split_once('.').unwrap_or((numeral, ""));
+    let digits = format!("{}{}", integer, fraction);
+    let significant = digits.trim_start_matches('0');
+    let point = integer.len() as isize - (digits.len() - significant.len()) as isize;
+    let significant = significant.trim_end_matches('0');
+    if significant.is_empty() {
+        return (String::new(), 0);
+    }
+    (significant.to_string(), point)
+}
+
+/// Whether two ranges share at least one character
+fn ranges_overlap(a: Range, b: Range) -> bool {
+    let key = |position: Position| (position.line, position.character);
+    key(a.start) < key(b.end) && key(b.start) < key(a.end)
+}
+
+/// Every name a read could resolve to: definitions plus parameters and loop variables.
+/// Scoping is ignored, so a name bound anywhere counts as bound everywhere.
+fn bound_names(program: &[Stmt]) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    let mut blocks: Vec<&[Stmt]> = vec![program];
+    ast::for_each_expr(program, &mut |expr| {
+        if let ExprKind::Lambda { params, body } = &expr.kind {
+            names.extend(params.iter().map(|param| param.as_str()));
+            blocks.push(body);
+        }
+    });
+
+    for block in blocks {
+        names.extend(defined_names(block));
+        let mut pending = vec![block];
+        while let Some(body) = pending.pop() {
+            for stmt in body {
+                match &stmt.kind {
+                    StmtKind::FuncDef { params, body, .. }
+                    | StmtKind::GeneratorDef { params, body, .. } => {
+                        names.extend(params.iter().map(|param| param.as_str()));
+                        pending.push(body);
+                    }
+                    StmtKind::For { var, .. } => {
+                        names.insert(var.as_str());
+                    }
+                    StmtKind::ForIndexed {
+                        index_var,
+                        value_var,
+                        ..
+                    } => {
+                        names.insert(index_var.as_str());
+                        names.insert(value_var.as_str());
+                    }
+                    StmtKind::Try {
+                        error_var: Some(error_var),
+                        ..
+                    } => {
+                        names.insert(error_var.as_str());
+                    }
+                    _ => {}
+                }
+                pending.extend(stmt.child_blocks());
+            }
+        }
+    }
+    names
+}
+
+/// Collects every identifier that is read, called, or exported
+#[derive(Default)]
+struct ReferenceCollector<'a> {
+    refs: HashSet<&'a str>,
+}
+
+impl<'a> Visitor<'a> for ReferenceCollector<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Export(name) = &stmt.kind {
+            self.refs.insert(name.as_str());
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Identifier(name) = &expr.kind {
+            self.refs.insert(name.as_str());
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Finds expression statements whose value is thrown away without any effect
+#[derive(Default)]
+struct UnusedExpressionFinder<'a> {
+    /// Each statement, and the name it starts with when it looks like a mangled `Set`
+    found: Vec<(&'a Stmt, Option<&'a str>)>,
+}
+
+impl<'a> UnusedExpressionFinder<'a> {
+    /// Check the statements of one block; the last statement of a lambda body is
+    /// left alone since it may become the lambda's value
+    fn check_block(&mut self, body: &'a [Stmt], lambda_body: bool) {
+        let mut statements = body.iter().enumerate().peekable();
+        while let Some((i, stmt)) = statements.next() {
+            let StmtKind::Expression(expr) = &stmt.kind else {
+                continue;
+            };
+            if has_effect(expr) || (lambda_body && i + 1 == body.len()) {
+                continue;
+            }
+            // `X 1` parses as two statements on one line; report them as one
+            let set_target = match (&expr.kind, statements.peek()) {
+                (ExprKind::Identifier(name), Some((_, next)))
+                    if next.span.start.line == stmt.span.end.line =>
+                {
+                    statements.next();
+                    Some(name.as_str())
+                }
+                _ => None,
+            };
+            self.found.push((stmt, set_target));
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for UnusedExpressionFinder<'a> {
+    fn visit_block(&mut self, body: &'a [Stmt]) {
+        self.check_block(body, false);
+        walk_block(self, body);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { body, .. } = &expr.kind {
+            self.check_block(body, true);
+            walk_block(self, body);
+            return;
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Finds `Set X X` and plain assignments that the next write to the same name in
+/// the same block overwrites with nothing reading the name in between
+struct RedundantAssignmentFinder<'a, 'b> {
+    user_defined: &'b HashSet<&'a str>,
+    self_assignments: Vec<(&'a Stmt, &'a str)>,
+    /// The dead assignment, its name and the statement overwriting it
+    dead_stores: Vec<(&'a Stmt, &'a str, &'a Stmt)>,
+}
+
+impl<'a> RedundantAssignmentFinder<'a, '_> {
+    fn check_block(&mut self, body: &'a [Stmt]) {
+        for (i, stmt) in body.iter().enumerate() {
+            let StmtKind::Set { name, value } = &stmt.kind else {
+                continue;
+            };
+            if matches!(&value.kind, ExprKind::Identifier(read) if read == name.as_str()) {
+                self.self_assignments.push((stmt, name.as_str()));
+                continue;
+            }
+            for later in &body[i + 1..] {
+                if let StmtKind::Set {
+                    name: overwritten,
+                    value,
+                } = &later.kind
+                    && overwritten == name
+                {
+                    if !self.touches(name, |touch| touch.visit_expr(value)) {
+                        self.dead_stores.push((stmt, name.as_str(), later));
+                    }
+                    break;
+                }
+                if self.touches(name, |touch| touch.visit_stmt(later)) {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn touches(&self, name: &str, visit: impl FnOnce(&mut NameTouch<'a, '_>)) -> bool {
+        let mut touch = NameTouch {
+            name,
+            user_defined: self.user_defined,
+            function_depth: 0,
+            touched: false,
+        };
+        visit(&mut touch);
+        touch.touched
+    }
+}
+
+impl<'a> Visitor<'a> for RedundantAssignmentFinder<'a, '_> {
+    fn visit_block(&mut self, body: &'a [Stmt]) {
+        self.check_block(body);
+        walk_block(self, body);
+    }
+}
+
+/// The variables a loop condition reads, in order of first read; `None` when it
+/// calls, indexes or reaches into anything, whose value the body might change
+/// without naming a variable
+fn condition_names(condition: &Expr) -> Option<Vec<&str>> {
+    let mut reads = ConditionReads::default();
+    reads.visit_expr(condition);
+    (!reads.opaque).then_some(reads.names)
+}
+
+#[derive(Default)]
+struct ConditionReads<'a> {
+    names: Vec<&'a str>,
+    opaque: bool,
+}
+
+impl<'a> Visitor<'a> for ConditionReads<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                if !self.names.contains(&name.as_str()) {
+                    self.names.push(name);
+                }
+            }
+            ExprKind::Call { .. }
+            | ExprKind::Index { .. }
+            | ExprKind::Member { .. }
+            | ExprKind::Force(_)
+            | ExprKind::If { .. }
+            | ExprKind::Lambda { .. } => self.opaque = true,
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// The names a loop body binds or assigns, and whether it can leave the loop.
+/// Function and lambda bodies only run when called, so they are skipped.
+#[derive(Default)]
+struct LoopWrites<'a> {
+    written: HashSet<&'a str>,
+    /// A `Break` of this loop, or a `Return` or `Throw`
+    exits: bool,
+    /// Loops nested in the body, whose `Break`s stay inside them
+    loop_depth: usize,
+}
+
+impl<'a> Visitor<'a> for LoopWrites<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, .. } | StmtKind::LazyDef { name, .. } => {
+                self.written.insert(name);
+            }
+            StmtKind::SetIndex { object, .. } => {
+                // `Set ITEMS[0] ...` changes ITEMS
+                let mut root = object;
+                while let ExprKind::Index { object, .. } | ExprKind::Member { object, .. } =
+                    &root.kind
+                {
+                    root = object;
+                }
+                if let ExprKind::Identifier(name) = &root.kind {
+                    self.written.insert(name);
+                }
+            }
+            StmtKind::For { var, .. } => {
+                self.written.insert(var);
+            }
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.written
+                    .extend([index_var.as_str(), value_var.as_str()]);
+            }
+            StmtKind::Import { names, aliases, .. } => {
+                for (name, alias) in names.iter().zip(aliases) {
+                    self.written.insert(alias.as_ref().unwrap_or(name));
+                }
+            }
+            StmtKind::Try { error_var, .. } => {
+                self.written.extend(error_var.as_deref());
+            }
+            StmtKind::FuncDef { name, .. } | StmtKind::GeneratorDef { name, .. } => {
+                self.written.insert(name);
+                return;
+            }
+            StmtKind::Break => self.exits |= self.loop_depth == 0,
+            StmtKind::Return(_) | StmtKind::Throw(_) => self.exits = true,
+            _ => {}
+        }
+        let nested_loop = matches!(
+            stmt.kind,
+            StmtKind::While { .. } | StmtKind::For { .. } | StmtKind::ForIndexed { .. }
+        );
+        self.loop_depth += usize::from(nested_loop);
+        walk_stmt(self, stmt);
+        self.loop_depth -= usize::from(nested_loop);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if !matches!(expr.kind, ExprKind::Lambda { .. }) {
+            walk_expr(self, expr);
+        }
+    }
+}
+
+/// Decides whether code between two writes might observe the first one: reading or
+/// rebinding the name (also inside function and lambda bodies), leaving the block
+/// early, or running code it can't see into (calls to non-builtins, `Force`).
+/// Writes in conditional branches and loops count too, so they never make a store dead.
+struct NameTouch<'a, 'b> {
+    name: &'b str,
+    user_defined: &'b HashSet<&'a str>,
+    /// How many function or lambda bodies we are inside; their code doesn't run here
+    function_depth: usize,
+    touched: bool,
+}
+
+impl<'a> Visitor<'a> for NameTouch<'a, '_> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        let name = self.name;
+        self.touched |= match &stmt.kind {
+            StmtKind::Set { name: bound, .. }
+            | StmtKind::LazyDef { name: bound, .. }
+            | StmtKind::Export(bound) => bound == name,
+            StmtKind::For { var, .. } => var == name,
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => index_var == name || value_var == name,
+            StmtKind::Import { names, aliases, .. } => names
+                .iter()
+                .chain(aliases.iter().flatten())
+                .any(|bound| bound == name),
+            StmtKind::FuncDef { name: bound, .. } | StmtKind::GeneratorDef { name: bound, .. } => {
+                bound == name
+            }
+            StmtKind::Try { error_var, .. } => error_var.as_ref().is_some_and(|var| var == name),
+            StmtKind::Return(_)
+            | StmtKind::Yield(_)
+            | StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Throw(_) => self.function_depth == 0,
+            StmtKind::SetIndex { .. }
+            | StmtKind::While { .. }
+            | StmtKind::Switch { .. }
+            | StmtKind::Expression(_) => false,
+        };
+        if let StmtKind::FuncDef { params, body, .. }
+        | StmtKind::GeneratorDef { params, body, .. } = &stmt.kind
+        {
+            self.touched |= params.iter().any(|param| param == name);
+            self.function_depth += 1;
+            self.visit_block(body);
+            self.function_depth -= 1;
+            return;
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(read) => self.touched |= read == self.name,
+            ExprKind::Call { func, .. } if self.function_depth == 0 => {
+                self.touched |= !matches!(
+                    &func.kind,
+                    ExprKind::Identifier(callee)
+                        if !self.user_defined.contains(callee.as_str())
+                            && builtins::lookup(callee).is_some()
+                );
+            }
+            ExprKind::Force(_) => self.touched |= self.function_depth == 0,
+            ExprKind::Lambda { params, body } => {
+                self.touched |= params.iter().any(|param| param == self.name);
+                self.function_depth += 1;
+                self.visit_block(body);
+                self.function_depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Walks the file for E011, keeping the bindings of each enclosing function and
+/// lambda so a callback passed by name resolves like the call would
+struct CallbackArityChecker<'a> {
+    parsed: &'a ParsedDocument,
+    locale: Locale,
+    user_defined: HashSet<&'a str>,
+    /// Parameters and locals of each enclosing function or lambda, innermost last
+    locals: Vec<HashSet<&'a str>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> CallbackArityChecker<'a> {
+    fn check_scope(&mut self, params: &'a [Ident], body: &'a [Stmt]) {
+        let mut scope = ScopeBindings::default();
+        for param in params {
+            scope.bind(param, true);
+        }
+        scope.visit_block(body);
+        self.locals.push(scope.first.into_keys().collect());
+        self.visit_block(body);
+        self.locals.pop();
+    }
+
+    fn check_call(&mut self, expr: &'a Expr) {
+        let ExprKind::Call { func, args } = &expr.kind else {
+            return;
+        };
+        let ExprKind::Identifier(name) = &func.kind else {
+            return;
+        };
+        if self.user_defined.contains(name.as_str()) {
+            return;
+        }
+        let Some(builtin) = builtins::lookup(name) else {
+            return;
+        };
+        let Some(callback) = builtin.callback() else {
+            return;
+        };
+        let Some(argument) = args.get(callback.arg) else {
+            return;
+        };
+        let (takes, function) = match &argument.kind {
+            ExprKind::Lambda { params, .. } => (params.len(), None),
+            // A parameter or local of an enclosing function isn't the global of that name
+            ExprKind::Identifier(function)
+                if self
+                    .locals
+                    .iter()
+                    .any(|locals| locals.contains(function.as_str())) =>
+            {
+                return;
+            }
+            ExprKind::Identifier(function) => match self.parsed.symbols.callable_params(function) {
+                Some(params) => (params.len(), Some(function)),
+                None => return,
+            },
+            _ => return,
+        };
+        if takes == callback.arity {
+            return;
+        }
+
+        let code = &codes::CALLBACK_ARITY;
+        self.diagnostics.push(Diagnostic {
+            range: argument.span,
+            severity: Some(code.severity),
+            code: code.lsp_code(),
+            code_description: code.code_description(),
+            source: Some("aether-lint".to_string()),
+            message: match (self.locale, function) {
+                (Locale::Zh, None) => format!(
+                    "{} 调用回调时传入 {} 个参数，但该 Lambda 接受 {} 个",
+                    builtin.name, callback.arity, takes
+                ),
+                (Locale::Zh, Some(function)) => format!(
+                    "{} 调用回调时传入 {} 个参数，但 '{}' 接受 {} 个",
+                    builtin.name, callback.arity, function, takes
+                ),
+                (Locale::En, None) => format!(
+                    "{} calls its callback with {} argument(s), but the lambda takes {}",
+                    builtin.name, callback.arity, takes
+                ),
+                (Locale::En, Some(function)) => format!(
+                    "{} calls its callback with {} argument(s), but '{}' takes {}",
+                    builtin.name, callback.arity, function, takes
+                ),
+            },
+            tags: None,
+            related_information: None,
+            data: None,
+        });
+    }
+}
+
+impl<'a> Visitor<'a> for CallbackArityChecker<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::FuncDef { params, body, .. }
+            | StmtKind::GeneratorDef { params, body, .. } => self.check_scope(params, body),
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { params, body } = &expr.kind {
+            self.check_scope(params, body);
+            return;
+        }
+        self.check_call(expr);
+        walk_expr(self, expr);
+    }
+}
+
+/// Walks the file one scope at a time (the file, then each function and lambda
+/// body) and finds new bindings hiding a builtin or an enclosing scope's binding
+#[derive(Default)]
+struct ShadowFinder<'a> {
+    /// First binding of each name in every enclosing scope, innermost last
+    enclosing: Vec<HashMap<&'a str, &'a Ident>>,
+    /// Each offending binding with the binding it hides, `None` for a builtin
+    found: Vec<(&'a Ident, Option<&'a Ident>)>,
+}
+
+impl<'a> ShadowFinder<'a> {
+    fn check_scope(&mut self, params: &'a [Ident], body: &'a [Stmt]) {
+        let mut scope = ScopeBindings::default();
+        for param in params {
+            scope.bind(param, true);
+        }
+        scope.visit_block(body);
+
+        for binding in &scope.reportable {
+            if binding.starts_with('_') {
+                continue;
+            }
+            let outer = self
+                .enclosing
+                .iter()
+                .rev()
+                .find_map(|bindings| bindings.get(binding.as_str()).copied());
+            if outer.is_some() || builtins::lookup(binding).is_some() {
+                self.found.push((binding, outer));
+            }
+        }
+
+        self.enclosing.push(scope.first);
+        for (params, body) in scope.nested {
+            self.check_scope(params, body);
+        }
+        self.enclosing.pop();
+    }
+}
+
+/// The bindings made directly in one scope, not inside nested function or lambda bodies
+#[derive(Default)]
+struct ScopeBindings<'a> {
+    first: HashMap<&'a str, &'a Ident>,
+    /// First `Set`, parameter or loop variable of each name
+    reportable: Vec<&'a Ident>,
+    /// Parameters and bodies of the functions and lambdas defined in the scope
+    nested: Vec<(&'a [Ident], &'a [Stmt])>,
+}
+
+impl<'a> ScopeBindings<'a> {
+    fn bind(&mut self, name: &'a Ident, reportable: bool) {
+        if !self.first.contains_key(name.as_str()) {
+            self.first.insert(name.as_str(), name);
+            if reportable {
+                self.reportable.push(name);
+            }
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for ScopeBindings<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, .. } => self.bind(name, true),
+            StmtKind::For { var, .. } => self.bind(var, true),
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.bind(index_var, true);
+                self.bind(value_var, true);
+            }
+            StmtKind::Try {
+                error_var: Some(error_var),
+                ..
+            } => self.bind(error_var, true),
+            StmtKind::LazyDef { name, .. } => self.bind(name, false),
+            StmtKind::Import { names, aliases, .. } => {
+                for (name, alias) in names.iter().zip(aliases) {
+                    self.bind(alias.as_ref().unwrap_or(name), false);
+                }
+            }
+            StmtKind::FuncDef { name, params, body }
+            | StmtKind::GeneratorDef { name, params, body } => {
+                self.bind(name, false);
+                self.nested.push((params, body));
+                return;
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { params, body } = &expr.kind {
+            self.nested.push((params, body));
+            return;
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// For each name bound in `program`, whether every binding is known not to be Lazy
+fn never_lazy_names(program: &Program) -> HashMap<&str, bool> {
+    fn bind<'a>(never_lazy: &mut HashMap<&'a str, bool>, name: &'a Ident, known: bool) {
+        *never_lazy.entry(name.as_str()).or_insert(true) &= known;
+    }
+
+    let mut never_lazy = HashMap::new();
+    ast::for_each_stmt(program, &mut |stmt| match &stmt.kind {
+        StmtKind::Set { name, value } => bind(&mut never_lazy, name, is_never_lazy(value)),
+        StmtKind::FuncDef { name, params, .. } | StmtKind::GeneratorDef { name, params, .. } => {
+            bind(&mut never_lazy, name, true);
+            for param in params {
+                bind(&mut never_lazy, param, false);
+            }
+        }
+        StmtKind::LazyDef { name, .. } => bind(&mut never_lazy, name, false),
+        StmtKind::For { var, .. } => bind(&mut never_lazy, var, false),
+        StmtKind::ForIndexed {
+            index_var,
+            value_var,
+            ..
+        } => {
+            bind(&mut never_lazy, index_var, false);
+            bind(&mut never_lazy, value_var, false);
+        }
+        StmtKind::Try {
+            error_var: Some(error_var),
+            ..
+        } => bind(&mut never_lazy, error_var, false),
+        StmtKind::Import { names, aliases, .. } => {
+            for name in names.iter().chain(aliases.iter().flatten()) {
+                bind(&mut never_lazy, name, false);
+            }
+        }
+        _ => {}
+    });
+    ast::for_each_expr(program, &mut |expr| {
+        if let ExprKind::Lambda { params, .. } = &expr.kind {
+            for param in params {
+                bind(&mut never_lazy, param, false);
+            }
+        }
+    });
+    never_lazy
+}
+
+/// Whether `expr` evaluates to something other than a Lazy value whatever the names
+/// in it are bound to
+fn is_never_lazy(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Number(_)
+            | ExprKind::BigInteger(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null
+            | ExprKind::Array(_)
+            | ExprKind::Dict(_)
+            | ExprKind::Binary { .. }
+            | ExprKind::Unary { .. }
+            | ExprKind::Lambda { .. }
+    )
+}
+
+/// Whether evaluating `expr` on its own line can do anything: calls, forcing a
+/// lazy value (which runs its expression) and `If` blocks holding statements
+fn has_effect(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Call { .. } | ExprKind::If { .. } | ExprKind::Force(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn messages(text: &str, locale: Locale) -> Vec<String> {
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            locale,
+            ..Default::default()
+        };
+        DiagnosticEngine::analyze(&parsed, &options)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    fn messages_with_lines(text: &str, locale: Locale) -> Vec<(u32, String)> {
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            locale,
+            ..Default::default()
+        };
+        DiagnosticEngine::analyze(&parsed, &options)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_lints_outside_syntax_errors() {
+        let text = r#"Import {UNUSED_THING, HELPER} From "./lib.ae"
+Func BROKEN(X) {
+    Lazy alsoBad(X +
+    Return HELPER(alsoBad)
+}
+Func GOOD() {
+    Lazy badName(1)
+    Return Force(badName)
+}
+PRINTLN(GOOD(), BROKEN(1))"#;
+        let parsed = Parser::new(text).parse();
+        assert_eq!(parsed.errors.len(), 1);
+        let found: Vec<(u32, String)> = DiagnosticEngine::analyze(
+            &parsed,
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .map(|diagnostic| match diagnostic.code {
+            Some(NumberOrString::String(code)) => (diagnostic.range.start.line, code),
+            _ => (diagnostic.range.start.line, String::new()),
+        })
+        .collect();
+        // The broken function gets only its syntax error; HELPER and BROKEN are
+        // mentioned there, so neither is reported unused or undefined
+        assert_eq!(
+            found,
+            vec![
+                (2, "E002".to_string()),
+                (6, "W001".to_string()),
+                (0, "W002".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_codes_are_stable() {
+        for (text, code, range) in [
+            ("Set X (1 +", "E002", (10, 10)),
+            ("Set X )", "E002", (6, 7)),
+            ("Func ADD(A, B {\n}", "E003", (14, 15)),
+            ("While (True {\n}", "E003", (12, 13)),
+            ("Set 123 1", "E003", (4, 7)),
+            ("Set S \"open", "E004", (6, 11)),
+        ] {
+            let parsed = Parser::new(text).parse();
+            for locale in [Locale::En, Locale::Zh] {
+                let found: Vec<(Option<NumberOrString>, Range)> = DiagnosticEngine::analyze(
+                    &parsed,
+                    &AnalysisOptions {
+                        locale,
+                        ..Default::default()
+                    },
+                )
+                .into_iter()
+                .filter(|diagnostic| diagnostic.source.as_deref() == Some("aether-parser"))
+                .map(|diagnostic| (diagnostic.code, diagnostic.range))
+                .collect();
+                assert_eq!(
+                    found,
+                    vec![(
+                        Some(NumberOrString::String(code.to_string())),
+                        Range::new(Position::new(0, range.0), Position::new(0, range.1))
+                    )],
+                    "{}",
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_error_ranges_stay_on_their_line() {
+        // The range covers the offending token; one running past its line stops
+        // at the end of the line
+        for (text, range) in [
+            ("Set X )", (6, 7)),
+            ("Set X )\nSet Y 1", (6, 7)),
+            ("Set 名 )", (6, 7)),
+            ("Set X Func", (10, 10)),
+            ("Func F(\"ab\ncd\") {\n}", (7, 10)),
+        ] {
+            let parsed = Parser::new(text).parse();
+            let ranges: Vec<Range> =
+                DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default())
+                    .into_iter()
+                    .filter(|diagnostic| diagnostic.source.as_deref() == Some("aether-parser"))
+                    .map(|diagnostic| diagnostic.range)
+                    .collect();
+            assert_eq!(
+                ranges,
+                vec![Range::new(
+                    Position::new(0, range.0),
+                    Position::new(0, range.1)
+                )],
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_limit_problems_keeps_errors_first() {
+        let mut text = String::new();
+        for i in 0..600 {
+            text.push_str(&format!("PRINTLN(MISSING_{})\n", i));
+        }
+        text.push_str("Import {UNUSED} From \"./lib.ae\"\nSet BROKEN (1 +\n");
+        let parsed = Parser::new(&text).parse();
+        let all = DiagnosticEngine::analyze(
+            &parsed,
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        );
+        assert_eq!(all.len(), 602);
+
+        let limited = DiagnosticEngine::limit_problems(all.clone(), 500, Locale::En);
+        assert_eq!(limited.len(), 501);
+        assert_eq!(limited[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert!(
+            limited[1..500]
+                .iter()
+                .all(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::WARNING))
+        );
+        let summary = &limited[500];
+        assert_eq!(summary.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(summary.range, Range::default());
+        assert_eq!(summary.message, "102 more problems not shown");
+
+        assert_eq!(
+            DiagnosticEngine::limit_problems(all, 0, Locale::En).len(),
+            602
+        );
+    }
+
+    #[test]
+    fn test_naming_warning_is_localized() {
+        let text = "Lazy myVar(1)\nPRINTLN(Force(myVar))";
+        assert_eq!(
+            messages(text, Locale::Zh),
+            vec!["变量名 'myVar' 应使用 UPPER_SNAKE_CASE 格式\n建议: MYVAR"]
+        );
+        assert_eq!(
+            messages(text, Locale::En),
+            vec!["Name 'myVar' should use UPPER_SNAKE_CASE\nSuggestion: MYVAR"]
+        );
+    }
+
+    /// Line, start and end character, and severity of each W001 diagnostic
+    fn naming_ranges(parsed: &ParsedDocument) -> Vec<(u32, u32, u32, DiagnosticSeverity)> {
+        DiagnosticEngine::analyze(
+            parsed,
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .filter(|diagnostic| diagnostic.code == codes::NAMING_CONVENTION.lsp_code())
+        .map(|diagnostic| {
+            (
+                diagnostic.range.start.line,
+                diagnostic.range.start.character,
+                diagnostic.range.end.character,
+                diagnostic.severity.unwrap(),
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_lowercase_names_are_warnings_not_errors() {
+        let text = "Set total 0\nFunc addOne(x) {\n    Return x + 1\n}\nGenerator evens(n) {\n    Yield n\n}\nLazy later(1)\nSet total addOne(Force(later))\nSet items[0] total\nPRINTLN(evens(2), items)";
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.ast.len(), 7);
+
+        let warnings = naming_ranges(&parsed);
+        // Parameters may be lowercase so they only get a hint, and `Set items[0]`
+        // isn't a definition
+        let (warning, hint) = (DiagnosticSeverity::WARNING, DiagnosticSeverity::HINT);
+        assert_eq!(
+            warnings,
+            vec![
+                (0, 4, 9, warning),
+                (1, 5, 11, warning),
+                (1, 12, 13, hint),
+                (4, 10, 15, warning),
+                (4, 16, 17, hint),
+                (7, 5, 10, warning),
+                (8, 4, 9, warning)
+            ]
+        );
+
+        for name in ["total", "addOne", "evens", "later"] {
+            assert!(parsed.symbols.find_by_name(name).is_some(), "{}", name);
+        }
+        #[cfg(feature = "server")]
+        {
+            let completions =
+                crate::completion::get_completions(&parsed, Position::new(10, 0), 0, Locale::En);
+            for name in ["total", "addOne", "evens", "later"] {
+                assert!(
+                    completions.items.iter().any(|item| item.label == name),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_naming_ranges_sit_under_the_name() {
+        let (warning, hint) = (DiagnosticSeverity::WARNING, DiagnosticSeverity::HINT);
+        let text = "Func RUN() {\n\t\tSet\ttotal 1\n\tReturn total\n}\n/* 计数器 */ Set count 0\nSet X Lambda item -> item\nlower(1)";
+        let parsed = Parser::new(text).parse();
+        assert_eq!(
+            naming_ranges(&parsed),
+            vec![(1, 6, 11, warning), (4, 14, 19, warning), (5, 13, 17, hint)]
+        );
+        // A lowercase name at column 0 that defines nothing isn't flagged
+        let parsed = Parser::new("total\nSet total 1").parse();
+        assert_eq!(naming_ranges(&parsed), vec![(1, 4, 9, warning)]);
+        let parsed = Parser::new("Func f(a) {\n    Return a\n}").parse();
+        assert_eq!(
+            naming_ranges(&parsed),
+            vec![(0, 5, 6, warning), (0, 7, 8, hint)]
+        );
+    }
+
+    #[test]
+    fn test_ranges_count_utf16_units_after_astral_characters() {
+        // `🙂` is one character but two UTF-16 units
+        let parsed = Parser::new("Set S \"🙂\" Set bad 1").parse();
+        assert_eq!(
+            naming_ranges(&parsed),
+            vec![(0, 15, 18, DiagnosticSeverity::WARNING)]
+        );
+
+        let parsed = Parser::new("PRINTLN(\"🙂\", MISSING)").parse();
+        let undefined: Vec<Range> = DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default())
+            .into_iter()
+            .filter(|diagnostic| diagnostic.message.contains("MISSING"))
+            .map(|diagnostic| diagnostic.range)
+            .collect();
+        assert_eq!(
+            undefined,
+            vec![Range::new(Position::new(0, 14), Position::new(0, 21))]
+        );
+
+        let parsed = Parser::new("Set S \"🙂\" )").parse();
+        let errors: Vec<Range> = DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default())
+            .into_iter()
+            .filter(|diagnostic| diagnostic.source.as_deref() == Some("aether-parser"))
+            .map(|diagnostic| diagnostic.range)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![Range::new(Position::new(0, 11), Position::new(0, 12))]
+        );
+    }
+
+    #[test]
+    fn test_import_aliases_are_the_local_names() {
+        let text = "Import {long_helper As lh, OTHER_HELPER As OH, lower} From \"./lib.ae\"\nPRINTLN(lh(1), lower)";
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        // Only the alias is named here; the imported names belong to the other module
+        assert_eq!(
+            naming_ranges(&parsed),
+            vec![(0, 23, 25, DiagnosticSeverity::WARNING)]
+        );
+        // Unused is about the binding: OH is never used, OTHER_HELPER never could be
+        let unused: Vec<(u32, u32)> =
+            DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default())
+                .into_iter()
+                .filter(|diagnostic| diagnostic.code == codes::UNUSED_IMPORT.lsp_code())
+                .map(|diagnostic| {
+                    (
+                        diagnostic.range.start.character,
+                        diagnostic.range.end.character,
+                    )
+                })
+                .collect();
+        assert_eq!(unused, vec![(43, 45)]);
+    }
+
+    #[test]
+    fn test_no_diagnostics_inside_multiline_strings() {
+        let text = "Set SAMPLE \"\"\"\nImport {a} From \"./gone.ae\"\nSet lower UNDEFINED(1,\n\"\"\"\nPRINTLN(SAMPLE)";
+        assert!(messages(text, Locale::En).is_empty());
+
+        // Even a rule that reports inside one is silenced there
+        fn flag_lower_and_sample(_: &Program) -> Vec<Diagnostic> {
+            [(2, 4, 9), (4, 8, 14)]
+                .into_iter()
+                .map(|(line, start, end)| Diagnostic {
+                    range: Range::new(Position::new(line, start), Position::new(line, end)),
+                    message: "flagged".to_string(),
+                    ..Default::default()
+                })
+                .collect()
+        }
+        INJECTED_RULE.set(Some(flag_lower_and_sample));
+        let found = messages_with_lines(text, Locale::En);
+        INJECTED_RULE.set(None);
+        assert_eq!(found, vec![(4, "flagged".to_string())]);
+    }
+
+    #[test]
+    fn test_lines_after_multiline_strings_and_block_comments() {
+        let lines = |text: &str| -> Vec<u32> {
+            messages_with_lines(text, Locale::En)
+                .into_iter()
+                .filter(|(_, message)| message.contains("lowered"))
+                .map(|(line, _)| line)
+                .collect()
+        };
+        let comment = "/* one\ntwo\nthree */\n";
+        let string = "Set TEXT \"\"\"a\nb\nc\nd\ne\"\"\"\nPRINTLN(TEXT)\n";
+        assert_eq!(lines(&format!("{}Set lowered 1", comment)), vec![3]);
+        assert_eq!(lines(&format!("{}Set lowered 1", string)), vec![6]);
+        assert_eq!(
+            lines(&format!("{}{}{}Set lowered 1", comment, string, comment)),
+            vec![12]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_strings_report_where_they_begin() {
+        for (text, message) in [
+            (
+                "Set X 1\nSet Y \"\"\"a\nb\nc",
+                "Unterminated multiline string",
+            ),
+            ("Set X 1\nSet Y \"a\nb", "Unterminated string"),
+        ] {
+            let parsed = Parser::new(text).parse();
+            let diagnostics = DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default());
+            assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+            assert_eq!(
+                diagnostics[0].range,
+                Range::new(
+                    Position::new(1, 6),
+                    Position::new(1, text.lines().nth(1).unwrap().len() as u32)
+                )
+            );
+            assert!(
+                diagnostics[0].message.starts_with(&format!(
+                    "Parse error at line 2, column 7: Invalid expression - {}",
+                    message
+                )),
+                "{}",
+                diagnostics[0].message
+            );
+        }
+    }
+
+    #[test]
+    fn test_unused_expression_warning() {
+        let text = r#"Set X 1
+"hello"
+X + 1
+PRINTLN(X)
+X 5
+Set DOUBLE Func(N) {
+    N + 1
+    N * 2
+}
+If (X > 0) {
+    PRINTLN("positive")
+}
+Lazy LATER(PRINTLN(X))
+Force(LATER)
+PRINTLN(DOUBLE(X))"#;
+        assert_eq!(
+            messages_with_lines(text, Locale::En),
+            vec![
+                (1, "Expression result is unused".to_string()),
+                (2, "Expression result is unused".to_string()),
+                (
+                    4,
+                    "Expression result is unused; did you mean `Set X ...`?".to_string()
+                ),
+                (6, "Expression result is unused".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redundant_assignments() {
+        let text = r#"Set X 1
+Set X X
+Set X 2
+Set Y 1
+If (X > 0) {
+    Set Y 2
+}
+Set Y 3
+Set Z 1
+Set SHOW Lambda N -> Z + N
+Set Z 2
+Set W 1
+PRINTLN(W)
+Set W 2
+Func RUN(A) {
+    Set T A
+    Set T A * 2
+    Return T
+}
+Set V 1
+RUN(1)
+Set V 2
+Set U 1
+Set U U + 1
+Set U LENGTH([U])
+PRINTLN(X, Y, Z, W, V, U, SHOW)"#;
+        let uri = Url::parse("file:///project/main.ae").unwrap();
+        let parsed = Parser::new(text).parse();
+        let found: Vec<(u32, DiagnosticSeverity, String, Option<u32>)> = DiagnosticEngine::analyze(
+            &parsed,
+            &AnalysisOptions {
+                locale: Locale::En,
+                uri: Some(&uri),
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.range.start.line,
+                diagnostic.severity.unwrap(),
+                diagnostic.message,
+                diagnostic
+                    .related_information
+                    .map(|related| related[0].location.range.start.line),
+            )
+        })
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    DiagnosticSeverity::WARNING,
+                    "Assigning 'X' to itself has no effect".to_string(),
+                    None
+                ),
+                (
+                    15,
+                    DiagnosticSeverity::HINT,
+                    "Value assigned to 'T' is overwritten before it is read".to_string(),
+                    Some(16)
+                ),
+            ]
+        );
+
+        // The branch write to `Y` and the lambda reading `Z` keep their stores alive;
+        // removing them makes both first assignments dead
+        let escaped =
+            "Set Y 1\nSet Y 3\nSet Z 1\nSet SHOW Lambda N -> N\nSet Z 2\nPRINTLN(Y, Z, SHOW)";
+        let dead: Vec<(u32, Vec<DiagnosticTag>)> =
+            DiagnosticEngine::analyze(&Parser::new(escaped).parse(), &AnalysisOptions::default())
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.tags.unwrap()))
+                .collect();
+        assert_eq!(
+            dead,
+            vec![
+                (0, vec![DiagnosticTag::UNNECESSARY]),
+                (2, vec![DiagnosticTag::UNNECESSARY]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_complexity_limits() {
+        fn complexity(text: &str, complexity: ComplexitySettings) -> Vec<(u32, String)> {
+            let parsed = Parser::new(text).parse();
+            assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+            DiagnosticEngine::analyze(
+                &parsed,
+                &AnalysisOptions {
+                    locale: Locale::En,
+                    complexity,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .filter(|diagnostic| {
+                [
+                    codes::TOO_MANY_PARAMS.lsp_code(),
+                    codes::TOO_DEEPLY_NESTED.lsp_code(),
+                    codes::TOO_MANY_STATEMENTS.lsp_code(),
+                ]
+                .contains(&diagnostic.code)
+            })
+            .map(|diagnostic| (diagnostic.range.start.character, diagnostic.message))
+            .collect()
+        }
+        let params = |count: usize| {
+            let names: Vec<String> = (0..count).map(|i| format!("P{}", i)).collect();
+            format!("Func WIDE({}) {{\n    Return P0\n}}", names.join(", "))
+        };
+        let nested = |depth: usize| {
+            let mut body = "Return 1".to_string();
+            for _ in 0..depth {
+                body = format!("If (True) {{\n{}\n}}", body);
+            }
+            format!("Generator DEEP() {{\n{}\n}}", body)
+        };
+        let long = |count: usize| {
+            let lines: Vec<&str> = vec!["    PRINTLN(1)"; count];
+            format!("Func LONG() {{\n{}\n}}", lines.join("\n"))
+        };
+        let defaults = ComplexitySettings::default();
+
+        assert!(complexity(&params(6), defaults).is_empty());
+        assert_eq!(
+            complexity(&params(7), defaults),
+            vec![(5, "Function 'WIDE' has 7 parameters (limit 6)".to_string())]
+        );
+        assert!(complexity(&nested(5), defaults).is_empty());
+        assert_eq!(
+            complexity(&nested(6), defaults),
+            vec![(
+                10,
+                "Function 'DEEP' has 6 levels of nesting (limit 5)".to_string()
+            )]
+        );
+        assert!(complexity(&long(80), defaults).is_empty());
+        assert_eq!(
+            complexity(&long(81), defaults),
+            vec![(
+                5,
+                "Function 'LONG' has 81 statements (limit 80)".to_string()
+            )]
+        );
+
+        let custom = ComplexitySettings {
+            max_params: 0,
+            max_nesting: 1,
+            max_statements: 2,
+        };
+        assert!(complexity(&params(30), custom).is_empty());
+        assert_eq!(complexity(&nested(2), custom).len(), 2);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let text = r#"Set MAP [1, 2]
+Set TOTAL 0
+Func ADD(TOTAL, _LIMIT) {
+    For ITEM In MAP {
+        Set _LIMIT ITEM
+    }
+    Return TOTAL
+}
+Set ITEM 3
+Set EACH Lambda ITEM -> ITEM + TOTAL
+Func COUNT(FILTER) {
+    Set MAP 1
+    Return FILTER
+}
+PRINTLN(ADD(1, 2), EACH, COUNT(1))"#;
+        let uri = Url::parse("file:///project/main.ae").unwrap();
+        let parsed = Parser::new(text).parse();
+        let shadowing =
+            |settings: ShadowingSettings| -> Vec<(u32, DiagnosticSeverity, String, Option<u32>)> {
+                DiagnosticEngine::analyze(
+                    &parsed,
+                    &AnalysisOptions {
+                        locale: Locale::En,
+                        uri: Some(&uri),
+                        shadowing: settings,
+                        ..Default::default()
+                    },
+                )
+                .into_iter()
+                .map(|diagnostic| {
+                    (
+                        diagnostic.range.start.line,
+                        diagnostic.severity.unwrap(),
+                        diagnostic.message,
+                        diagnostic
+                            .related_information
+                            .map(|related| related[0].location.range.start.line),
+                    )
+                })
+                .collect()
+            };
+
+        let info = DiagnosticSeverity::INFORMATION;
+        assert_eq!(
+            shadowing(ShadowingSettings::default()),
+            vec![
+                (
+                    0,
+                    info,
+                    "'MAP' shadows the builtin function of the same name".to_string(),
+                    None
+                ),
+                (
+                    2,
+                    info,
+                    "'TOTAL' shadows a binding in an enclosing scope".to_string(),
+                    Some(1)
+                ),
+                (
+                    3,
+                    info,
+                    "'ITEM' shadows a binding in an enclosing scope".to_string(),
+                    Some(8)
+                ),
+                (
+                    9,
+                    info,
+                    "'ITEM' shadows a binding in an enclosing scope".to_string(),
+                    Some(8)
+                ),
+                (
+                    10,
+                    info,
+                    "'FILTER' shadows the builtin function of the same name".to_string(),
+                    None
+                ),
+                (
+                    11,
+                    info,
+                    "'MAP' shadows a binding in an enclosing scope".to_string(),
+                    Some(0)
+                ),
+            ]
+        );
+
+        let raised = shadowing(ShadowingSettings {
+            severity: ShadowingSeverity::Warning,
+            builtins: false,
+        });
+        assert_eq!(
+            raised
+                .iter()
+                .map(|(line, severity, _, _)| (*line, *severity))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, DiagnosticSeverity::WARNING),
+                (3, DiagnosticSeverity::WARNING),
+                (9, DiagnosticSeverity::WARNING),
+                (11, DiagnosticSeverity::WARNING),
+            ]
+        );
+        assert!(
+            shadowing(ShadowingSettings {
+                severity: ShadowingSeverity::Off,
+                builtins: true,
+            })
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_force_not_lazy() {
+        let text = r#"Lazy LATER(1)
+Set MAYBE LATER
+Set COUNT 3
+Func RUN(ARG) {
+    Return Force(ARG)
+}
+PRINTLN(Force(LATER), Force(MAYBE), Force(COUNT), Force(RUN), Force([1]), Force(RUN(1)))"#;
+        let found: Vec<(u32, u32)> = DiagnosticEngine::analyze(
+            &Parser::new(text).parse(),
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .filter(|diagnostic| diagnostic.code == codes::FORCE_NOT_LAZY.lsp_code())
+        .map(|diagnostic| {
+            (
+                diagnostic.range.start.line,
+                diagnostic.range.start.character,
+            )
+        })
+        .collect();
+        assert_eq!(found, vec![(6, 36), (6, 50), (6, 62)]);
+    }
+
+    #[test]
+    fn test_float_precision_warning() {
+        let text = "Set BELOW 9007199254740992.0\nSet ABOVE 9007199254740993.0\nSet TENTH -0.10\nSet LONG 3.14159265358979323846\nSet BIG 9007199254740993\nPRINTLN(BELOW, ABOVE, TENTH, LONG, BIG)\nSet HALF -00.5\nSet QUARTER 00.25";
+        let found: Vec<(u32, String)> = DiagnosticEngine::analyze(
+            &Parser::new(text).parse(),
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .filter(|diagnostic| diagnostic.code == codes::PRECISION_LOSS.lsp_code())
+        .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    "9007199254740993.0 is beyond the precision of an f64 and becomes 9007199254740992"
+                        .to_string()
+                ),
+                (
+                    3,
+                    "3.14159265358979323846 is beyond the precision of an f64 and becomes 3.141592653589793"
+                        .to_string()
+                ),
+            ]
+        );
+
+        assert_eq!(decimal_digits("001.500"), decimal_digits("1.5"));
+        assert_eq!(decimal_digits("0.0"), decimal_digits("0"));
+        assert_eq!(decimal_digits("-00.5"), decimal_digits("-0.5"));
+        assert_ne!(decimal_digits("0.15"), decimal_digits("1.5"));
+    }
+
+    #[test]
+    fn test_loop_never_ending() {
+        let never_ends = |text: &str| -> Vec<(Range, String)> {
+            DiagnosticEngine::analyze(
+                &Parser::new(text).parse(),
+                &AnalysisOptions {
+                    locale: Locale::En,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code == codes::LOOP_NEVER_ENDS.lsp_code())
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect()
+        };
+
+        assert_eq!(
+            never_ends("Set I 0\nWhile (I < 10) {\n    PRINTLN(I)\n}"),
+            vec![(
+                Range::new(Position::new(1, 7), Position::new(1, 13)),
+                "Loop may never end: the body never changes 'I'".to_string()
+            )]
+        );
+        // Writes inside a nested function don't run in the loop
+        assert_eq!(
+            never_ends(
+                "Set I 0\nWhile (I < 10) {\n    Func STEP() {\n        Set I (I + 1)\n    }\n    Set F Lambda X -> X\n}"
+            )
+            .len(),
+            1
+        );
+
+        for fine in [
+            "Set I 0\nWhile (I < 10) {\n    PRINTLN(I)\n    Set I (I + 1)\n}",
+            // Nested blocks count
+            "Set I 0\nWhile (I < 10) {\n    If (True) {\n        Set I (I + 1)\n    }\n}",
+            "Set ITEMS [1]\nWhile (ITEMS) {\n    Set ITEMS[0] Null\n}",
+            // A call can change anything
+            "Set I 0\nWhile (LEN(QUEUE) > I) {\n    PRINTLN(I)\n}",
+            "Set I 0\nWhile (I < 10) {\n    If (READY()) {\n        Break\n    }\n}",
+            // No variables at all is a different problem
+            "While (True) {\n    PRINTLN(1)\n}",
+        ] {
+            assert!(never_ends(fine).is_empty(), "{}", fine);
+        }
+        // A `Break` of an inner loop doesn't leave the outer one
+        assert_eq!(
+            never_ends("Set I 0\nWhile (I < 10) {\n    For X In [1] {\n        Break\n    }\n}")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_try_catch_lints() {
+        let text = r#"Func DIVIDE(A, B) {
+    If (B == 0) {
+        Throw "division by zero"
+    }
+    Return A / B
+}
+Func SAFE(A) {
+    Return DIVIDE(A, 2)
+}
+Func LOUD() {
+    Throw "always"
+}
+Try {
+    Set RESULT SAFE(10)
+} Catch (ERROR) {
+}
+Try {
+    Set TOTAL (1 + 2)
+} Catch {
+    PRINTLN("unreachable")
+}
+Try {
+    Throw "inline"
+} Catch {}
+Throw "top level"
+PRINTLN(RESULT, TOTAL, LOUD)"#;
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let lints = |report_uncaught_throw: bool| -> Vec<(String, Range)> {
+            DiagnosticEngine::analyze(
+                &parsed,
+                &AnalysisOptions {
+                    locale: Locale::En,
+                    report_uncaught_throw,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .filter_map(|diagnostic| match diagnostic.code {
+                Some(NumberOrString::String(code))
+                    if ["W018", "W019", "W020"].contains(&code.as_str()) =>
+                {
+                    Some((code, diagnostic.range))
+                }
+                _ => None,
+            })
+            .collect()
+        };
+        let range = |line, start, end_line, end| {
+            Range::new(Position::new(line, start), Position::new(end_line, end))
+        };
+
+        let expected = vec![
+            ("W019".to_string(), range(14, 9, 14, 14)),
+            ("W020".to_string(), range(16, 0, 16, 3)),
+            ("W019".to_string(), range(23, 9, 23, 10)),
+        ];
+        assert_eq!(lints(false), expected);
+
+        // DIVIDE is reached from a Try through SAFE; LOUD never is
+        let mut with_uncaught = expected;
+        with_uncaught.extend([
+            ("W018".to_string(), range(10, 4, 10, 18)),
+            ("W018".to_string(), range(24, 0, 24, 17)),
+        ]);
+        assert_eq!(lints(true), with_uncaught);
+    }
+
+    #[test]
+    fn test_lazy_never_forced_hint() {
+        let text = "Lazy USED(1)\nLazy IDLE(2)\nFunc RUN() {\n    Lazy INNER(3)\n    Return Force(USED)\n}";
+        let parsed = Parser::new(text).parse();
+        let hints: Vec<(u32, String)> = DiagnosticEngine::analyze(
+            &parsed,
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::HINT))
+        .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+        .collect();
+        assert_eq!(
+            hints,
+            vec![
+                (1, "Lazy value 'IDLE' is never forced".to_string()),
+                (3, "Lazy value 'INNER' is never forced".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builtin_arity_warnings() {
+        let text =
+            "Set A PADSTART(\"7\", 3, \"0\", 1)\nSet B PADEND(\"x\", 4)\nSet C CONCAT()\nPRINTLN()";
+        let warnings: Vec<(u32, String)> = messages_with_lines(text, Locale::En);
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    0,
+                    "Builtin 'PADSTART' expects 2-3 argument(s) but got 4\nSignature: PADSTART(string, length, [pad])".to_string()
+                ),
+                (
+                    2,
+                    "Builtin 'CONCAT' expects at least 1 argument(s) but got 0\nSignature: CONCAT(array, others...)".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_callback_arity_errors() {
+        let text = "Set NUMBERS [1, 2, 3]\n\
+                    Set EVENS FILTER(NUMBERS, Lambda (I, X) -> ((X % 2) == 0))\n\
+                    Set SUM REDUCE(NUMBERS, Lambda (ACC, X) -> (ACC + X), 0)\n\
+                    Func DOUBLE(X) {\n    Return X * 2\n}\n\
+                    Func ADD(A, B) {\n    Return A + B\n}\n\
+                    Set DOUBLED MAP(NUMBERS, DOUBLE)\n\
+                    Set SUMMED REDUCE(NUMBERS, DOUBLE, 0)\n\
+                    Set PAIRED MAP(NUMBERS, ADD)\n\
+                    Set IS_BIG Lambda (X) -> (X > 2)\n\
+                    Set BIG FILTER(NUMBERS, IS_BIG)\n\
+                    Set OTHER MAP(NUMBERS, SOMETHING_ELSE)\n\
+                    Func F(A, B) {\n    Return A + B\n}\n\
+                    Func APPLY(XS, F) {\n    Return MAP(XS, F)\n}";
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            ..Default::default()
+        };
+        let errors: Vec<(Range, String)> = DiagnosticEngine::analyze(&parsed, &options)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code == codes::CALLBACK_ARITY.lsp_code())
+            .inspect(|diagnostic| assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR)))
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    Range::new(Position::new(1, 26), Position::new(1, 57)),
+                    "FILTER calls its callback with 1 argument(s), but the lambda takes 2"
+                        .to_string()
+                ),
+                (
+                    Range::new(Position::new(10, 27), Position::new(10, 33)),
+                    "REDUCE calls its callback with 2 argument(s), but 'DOUBLE' takes 1"
+                        .to_string()
+                ),
+                (
+                    Range::new(Position::new(11, 24), Position::new(11, 27)),
+                    "MAP calls its callback with 1 argument(s), but 'ADD' takes 2".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_template_placeholders() {
+        let text = "Set NAME \"Ann\"\nSet AGE 3\n\
+                    Set A FORMAT(\"Hello {} you are {}\", NAME)\n\
+                    Set B FORMAT(\"Hello {}\", NAME, AGE)\n\
+                    Set C FORMAT(\"{{}} braces, {} name\", NAME)\n\
+                    Set T \"{} {}\"\n\
+                    Set D FORMAT(T, NAME)\n\
+                    PRINTLN(\"{} of {}\", AGE)\n\
+                    PRINTLN(\"no placeholders\", AGE)";
+        assert_eq!(
+            messages_with_lines(text, Locale::En),
+            vec![
+                (
+                    2,
+                    "FORMAT template has 2 placeholder(s) but 1 argument(s) follow it".to_string()
+                ),
+                (
+                    3,
+                    "FORMAT template has 1 placeholder(s) but 2 argument(s) follow it".to_string()
+                ),
+                (
+                    7,
+                    "PRINTLN template has 2 placeholder(s) but 1 argument(s) follow it".to_string()
+                ),
+            ]
+        );
+
+        let parsed = Parser::new("Set A FORMAT(\"{}\")").parse();
+        let diagnostics = DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(0, 6), Position::new(0, 18))
+        );
+    }
+
+    #[test]
+    fn test_lenient_keywords_warn_once_per_keyword() {
+        let text = "set total 0\nfor v in [1, 2] {\n    set total (total + v)\n}\n\
+                    if (total > 2) {\n    set total 2\n} else {\n    set total 0\n}";
+        let parsed = Parser::new(text).lenient_keywords(true).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            ..Default::default()
+        };
+        let casing: Vec<(u32, String)> = DiagnosticEngine::analyze(&parsed, &options)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code == codes::KEYWORD_CASING.lsp_code())
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        let expected = [
+            (0, "Set"),
+            (1, "For"),
+            (1, "In"),
+            (2, "Set"),
+            (4, "If"),
+            (5, "Set"),
+            (6, "Else"),
+            (7, "Set"),
+        ];
+        assert_eq!(
+            casing,
+            expected
+                .iter()
+                .map(|(line, keyword)| (*line, format!("The keyword is spelled `{}`", keyword)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_stray_assigns() {
+        let text = "Set X = 10\nY = X + 1\nIf (X = 5) {\n    PRINTLN(X)\n}\nZ = [1,\n    2]\nW = \"😀\" + X";
+        let parsed = Parser::new(text).parse();
+        let stray = |locale| -> Vec<(Range, Option<DiagnosticSeverity>, String)> {
+            let options = AnalysisOptions {
+                locale,
+                ..Default::default()
+            };
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .filter(|diagnostic| {
+                    [
+                        codes::SET_WITH_EQUALS.lsp_code(),
+                        codes::ASSIGN_IN_CONDITION.lsp_code(),
+                        codes::ASSIGN_WITHOUT_SET.lsp_code(),
+                    ]
+                    .contains(&diagnostic.code)
+                })
+                .map(|diagnostic| (diagnostic.range, diagnostic.severity, diagnostic.message))
+                .collect()
+        };
+        let at = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 1),
+            )
+        };
+        assert_eq!(
+            stray(Locale::En),
+            vec![
+                (
+                    at(0, 6),
+                    Some(DiagnosticSeverity::WARNING),
+                    "Aether's Set statement doesn't use '='".to_string()
+                ),
+                (
+                    at(1, 2),
+                    Some(DiagnosticSeverity::ERROR),
+                    "Aether assigns with Set: `Set Y X + 1`".to_string()
+                ),
+                (
+                    at(2, 6),
+                    Some(DiagnosticSeverity::ERROR),
+                    "use '==' for comparison".to_string()
+                ),
+                (
+                    at(5, 2),
+                    Some(DiagnosticSeverity::ERROR),
+                    "Aether assigns with Set: `Set Z …`".to_string()
+                ),
+                (
+                    at(7, 2),
+                    Some(DiagnosticSeverity::ERROR),
+                    "Aether assigns with Set: `Set W \"😀\" + X`".to_string()
+                ),
+            ]
+        );
+        let zh: Vec<String> = stray(Locale::Zh)
+            .into_iter()
+            .map(|(_, _, message)| message)
+            .collect();
+        assert_eq!(zh[0], "Aether 的 Set 语句不使用 '='");
+        assert_eq!(zh[2], "比较请使用 '=='");
+    }
+
+    #[test]
+    fn test_indentation_lint() {
+        let text = "Func F(X) {\n\t  Set A 1\n   Set B 2\n    Set S \"line\n  two\"\n\
+                    \x20   If (X) {\n\t\tPRINTLN(A)\n      }\n    Return [1,\n        2]\n}\n  Set C 3";
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let indentation = |settings| -> Vec<(u32, String, String)> {
+            let options = AnalysisOptions {
+                locale: Locale::En,
+                indentation: settings,
+                ..Default::default()
+            };
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .filter(|diagnostic| diagnostic.code == codes::INDENTATION.lsp_code())
+                .map(|diagnostic| {
+                    assert_eq!(diagnostic.range.start.character, 0);
+                    let fix = diagnostic.data.unwrap()["indent"]
+                        .as_str()
+                        .unwrap()
+                        .to_string();
+                    (diagnostic.range.start.line, diagnostic.message, fix)
+                })
+                .collect()
+        };
+
+        assert!(indentation(IndentationSettings::default()).is_empty());
+        let enabled = IndentationSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        let spaces = "    ".to_string();
+        // The string's second line and the tab-indented line are left alone
+        assert_eq!(
+            indentation(enabled),
+            vec![
+                (
+                    1,
+                    "Indentation mixes tabs and spaces".to_string(),
+                    spaces.clone()
+                ),
+                (
+                    2,
+                    "Indentation of 3 columns isn't a multiple of 4".to_string(),
+                    spaces.clone()
+                ),
+                (
+                    7,
+                    "Indentation of 6 columns isn't a multiple of 4".to_string(),
+                    spaces
+                ),
+            ]
+        );
+
+        let tabs: Vec<String> = indentation(IndentationSettings {
+            use_tabs: true,
+            ..enabled
+        })
+        .into_iter()
+        .map(|(_, _, fix)| fix)
+        .collect();
+        assert_eq!(tabs, vec!["\t", "\t", "\t"]);
+    }
+
+    #[test]
+    fn test_for_over_non_iterables() {
+        let text = "For X In 5 {\n    PRINTLN(X)\n}\nFor X In True {\n    PRINTLN(X)\n}\n\
+                    For I, X In Null {\n    PRINTLN(X)\n}\nFor C In \"abc\" {\n    PRINTLN(C)\n}\n\
+                    For X In SUM([1, 2]) {\n    PRINTLN(X)\n}\nSet N LENGTH([1])\nSet N (N * 2)\n\
+                    For X In N {\n    PRINTLN(X)\n}\nFor X In [1, 2] {\n    PRINTLN(X)\n}\n\
+                    For X In SPLIT(\"a b\", \" \") {\n    PRINTLN(X)\n}\n\
+                    Func F(ITEMS) {\n    For X In ITEMS {\n        PRINTLN(X)\n    }\n}";
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            ..Default::default()
+        };
+        let codes = [
+            codes::NOT_ITERABLE.lsp_code(),
+            codes::NON_ITERABLE_VALUE.lsp_code(),
+            codes::STRING_ITERATION.lsp_code(),
+        ];
+        let found: Vec<(Range, Option<DiagnosticSeverity>, String)> =
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .filter(|diagnostic| codes.contains(&diagnostic.code))
+                .map(|diagnostic| (diagnostic.range, diagnostic.severity, diagnostic.message))
+                .collect();
+        let at =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+        let not_iterable = "expression is not iterable".to_string();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    at(0, 9, 10),
+                    Some(DiagnosticSeverity::ERROR),
+                    not_iterable.clone()
+                ),
+                (
+                    at(3, 9, 13),
+                    Some(DiagnosticSeverity::ERROR),
+                    not_iterable.clone()
+                ),
+                (at(6, 12, 16), Some(DiagnosticSeverity::ERROR), not_iterable),
+                (
+                    at(9, 9, 14),
+                    Some(DiagnosticSeverity::HINT),
+                    "Iterating a string gives its characters one by one".to_string()
+                ),
+                (
+                    at(12, 9, 20),
+                    Some(DiagnosticSeverity::WARNING),
+                    "'SUM' returns a Number, which is not iterable".to_string()
+                ),
+                (
+                    at(17, 9, 10),
+                    Some(DiagnosticSeverity::WARNING),
+                    "'N' holds a Number, which is not iterable".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_index_out_of_range() {
+        let warnings = |text: &str| -> Vec<(Range, String)> {
+            let parsed = Parser::new(text).parse();
+            let options = AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            };
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .filter(|diagnostic| diagnostic.code == codes::INDEX_OUT_OF_RANGE.lsp_code())
+                .map(|diagnostic| (diagnostic.range, diagnostic.message))
+                .collect()
+        };
+        assert_eq!(
+            warnings("Set ARR [1, 2, 3]\nSet FIRST ARR[5]\nSet LAST ARR[2]\nPRINTLN(FIRST, LAST)"),
+            vec![(
+                Range::new(Position::new(1, 10), Position::new(1, 16)),
+                "Index 5 is out of range: 'ARR' is an array literal of 3 elements".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("Set ARR [1, 2, 3]\nPRINTLN(ARR[-1])"),
+            vec![(
+                Range::new(Position::new(1, 8), Position::new(1, 15)),
+                "Index -1 is out of range: 'ARR' is an array literal of 3 elements".to_string()
+            )]
+        );
+        // PUSH later grows the array, so its length isn't known
+        assert!(warnings("Set ARR [1, 2, 3]\nPUSH(ARR, 4)\nPRINTLN(ARR[3])").is_empty());
+    }
+
+    fn lookalike_warnings(text: &str) -> Vec<(Range, String, Option<Range>)> {
+        let uri = Url::parse("file:///project/main.ae").unwrap();
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            uri: Some(&uri),
+            ..Default::default()
+        };
+        DiagnosticEngine::analyze(&parsed, &options)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code == codes::LOOKALIKE_NAME.lsp_code())
+            .map(|diagnostic| {
+                (
+                    diagnostic.range,
+                    diagnostic.message,
+                    diagnostic
+                        .related_information
+                        .map(|related| related[0].location.range),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_names_differing_only_by_case() {
+        let line = |line: u32, start: u32, end: u32| {
+            Range::new(Position::new(line, start), Position::new(line, end))
+        };
+        assert_eq!(
+            lookalike_warnings(
+                "Set USER_ID 1\nFunc SHOW(User_Id) {\n    PRINTLN(User_Id)\n}\nSHOW(USER_ID)"
+            ),
+            vec![(
+                line(1, 10, 17),
+                "'User_Id' differs from 'USER_ID' only by case".to_string(),
+                Some(line(0, 4, 11))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_names_differing_by_confusable_characters() {
+        assert_eq!(
+            lookalike_warnings("Set ＵSER_ID 1\nSet USER_ID 2\nPRINTLN(ＵSER_ID, USER_ID)"),
+            vec![(
+                Range::new(Position::new(1, 4), Position::new(1, 11)),
+                "'USER_ID' looks the same as 'ＵSER_ID' but uses lookalike characters: 'Ｕ' (U+FF35)"
+                    .to_string(),
+                Some(Range::new(Position::new(0, 4), Position::new(0, 11)))
+            )]
+        );
+        // Cyrillic С in the second COUNT
+        let found = lookalike_warnings("Set COUNT 1\nSet СOUNT 2\nPRINTLN(COUNT, СOUNT)");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1.contains("'С' (U+0421)"), "{}", found[0].1);
+    }
+
+    #[test]
+    fn test_identical_names_are_not_lookalikes() {
+        // A second Set of the same name, or the same parameter in two functions
+        assert!(
+            lookalike_warnings(
+                "Set TOTAL 1\nSet TOTAL 2\nFunc A(X) {\n    Return X\n}\nFunc B(X) {\n    Return X\n}\nPRINTLN(TOTAL, A(1), B(2))"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_loop_heuristics() {
+        let text = "Set ARR [1, 2]\nFor V, I In ARR {\n    PRINTLN(V)\n    PRINTLN(ARR[I])\n}\n\
+                    For V, I In ARR {\n    PRINTLN(V, I)\n}\n\
+                    Set AGES {\"ann\": 3}\nFor NAME In AGES {\n    PRINTLN(NAME)\n}\n\
+                    For X In ARR {\n    PRINTLN(X)\n}";
+        let parsed = Parser::new(text).parse();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let hints = |heuristics| -> Vec<(Range, String)> {
+            let options = AnalysisOptions {
+                locale: Locale::En,
+                heuristics,
+                ..Default::default()
+            };
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .filter(|diagnostic| {
+                    [
+                        codes::SWAPPED_LOOP_VARIABLES.lsp_code(),
+                        codes::DICT_ITERATION.lsp_code(),
+                    ]
+                    .contains(&diagnostic.code)
+                })
+                .map(|diagnostic| (diagnostic.range, diagnostic.message))
+                .collect()
+        };
+        let at =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+        // The second loop prints both variables, which says nothing of intent
+        assert_eq!(
+            hints(HeuristicSettings::default()),
+            vec![
+                (
+                    at(1, 4, 8),
+                    "ForIndexed binds the index first, then the value: 'V' is used like a value \
+                     and 'I' like an index, so they may be swapped"
+                        .to_string()
+                ),
+                (
+                    at(9, 12, 16),
+                    "Iterating the dict 'AGES' gives its keys; iterate ITEMS(AGES) for keys and values"
+                        .to_string()
+                ),
+            ]
+        );
+        let off = HeuristicSettings {
+            swapped_loop_variables: false,
+            dict_iteration: false,
+        };
+        assert!(hints(off).is_empty());
+    }
+
+    #[test]
+    fn test_generator_call_semantics() {
+        let text = "Generator COUNT_UP(N) {\n    Yield N\n}\n\
+                    Func SHOW(N) {\n    PRINTLN(N)\n}\n\
+                    Func ITEMS() {\n    Return [1, 2]\n}\n\
+                    For I In SHOW(3) {\n    PRINTLN(I)\n}\n\
+                    For I, V In COUNT_UP(3) {\n    PRINTLN(V)\n}\n\
+                    For I In ITEMS() {\n    PRINTLN(I)\n}\n\
+                    Set TOTAL COUNT_UP(3) + 1\n\
+                    Set G COUNT_UP(3)\n\
+                    Set NEG -COUNT_UP(1)";
+        assert_eq!(
+            messages_with_lines(text, Locale::En),
+            vec![
+                (
+                    9,
+                    "'SHOW' is a function, not a Generator, and returns nothing to iterate; did you mean to call a Generator?"
+                        .to_string()
+                ),
+                (
+                    18,
+                    "'COUNT_UP' is a Generator, so calling it gives a generator object, not a number; iterate it with For"
+                        .to_string()
+                ),
+                (
+                    20,
+                    "'COUNT_UP' is a Generator, so calling it gives a generator object, not a number; iterate it with For"
+                        .to_string()
+                ),
+            ]
+        );
+
+        // Names that may be bound to something else aren't resolved
+        let rebound = "Func SHOW() {\n    PRINTLN(1)\n}\nSet SHOW LIST_OF()\nFor I In SHOW() {\n    PRINTLN(I)\n}\n\
+                       Func F(STEP) {\n    Return STEP() * 2\n}\nFor I In UNKNOWN() {\n    PRINTLN(I)\n}";
+        assert!(
+            messages(rebound, Locale::En)
+                .iter()
+                .all(|message| !message.contains("Generator")),
+            "{:?}",
+            messages(rebound, Locale::En)
+        );
+    }
+
+    #[test]
+    fn test_header_directives() {
+        let text = "//! name: deploy\n//! owner: ops\n//! requires: 1.x\n//! requires: 2.1\n\
+                    Set A 1\n//! version: 3\nPRINTLN(A)";
+        assert_eq!(
+            messages_with_lines(text, Locale::En),
+            vec![
+                (
+                    1,
+                    "Unknown header directive 'owner'; known ones are name, description, author, version, requires"
+                        .to_string()
+                ),
+                (2, "'1.x' is not a version like 1.2 or 1.2.3".to_string()),
+                (
+                    5,
+                    "Directive 'version' comes after the first statement, so it is not part of the header and is just a comment"
+                        .to_string()
+                ),
+            ]
+        );
+
+        let parsed = Parser::new(text).parse();
+        let diagnostics = DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default());
+        let severities: Vec<_> = diagnostics.iter().map(|d| d.severity).collect();
+        assert_eq!(
+            severities,
+            vec![
+                Some(DiagnosticSeverity::INFORMATION),
+                Some(DiagnosticSeverity::WARNING),
+                Some(DiagnosticSeverity::WARNING),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_function_shadows_builtin_arity() {
+        let text = "Func COUNT(XS) {\n    Return LENGTH(XS)\n}\nSet N COUNT([1, 2])";
+        assert!(messages(text, Locale::Zh).is_empty());
+    }
+
+    #[test]
+    fn test_io_lint_is_opt_in() {
+        let text = "Set CONTENT READFILE(\"a.txt\")\nIf (EXISTS(\"b.txt\")) {\n    EXIT(1)\n}";
+        let parsed = Parser::new(text).parse();
+        assert!(DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default()).is_empty());
+
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            report_io: true,
+            ..Default::default()
+        };
+        let infos: Vec<(u32, Option<DiagnosticSeverity>, String)> =
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .map(|diagnostic| {
+                    (
+                        diagnostic.range.start.line,
+                        diagnostic.severity,
+                        diagnostic.message,
+                    )
+                })
+                .collect();
+        let info = Some(DiagnosticSeverity::INFORMATION);
+        assert_eq!(
+            infos,
+            vec![
+                (0, info, "script performs I/O: READFILE".to_string()),
+                (1, info, "script performs I/O: EXISTS".to_string()),
+                (2, info, "script performs I/O: EXIT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deprecated_function_call_warns() {
+        let text = "// @deprecated use NEW_TOTAL instead\nFunc OLD_TOTAL(XS) {\n    Return OLD_TOTAL(XS)\n}\n\
+                    Func NEW_TOTAL(XS) {\n    Return 1\n}\n// @deprecated\nFunc GONE() {\n    Return 2\n}\n\
+                    PRINTLN(OLD_TOTAL([1]), NEW_TOTAL([1]), GONE())";
+        let parsed = Parser::new(text).parse();
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            ..Default::default()
+        };
+        let found: Vec<(Position, String, Option<serde_json::Value>)> =
+            DiagnosticEngine::analyze(&parsed, &options)
+                .into_iter()
+                .inspect(|diagnostic| {
+                    assert_eq!(diagnostic.tags, Some(vec![DiagnosticTag::DEPRECATED]));
+                    assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+                })
+                .map(|diagnostic| (diagnostic.range.start, diagnostic.message, diagnostic.data))
+                .collect();
+        // The recursive call inside OLD_TOTAL itself isn't flagged
+        assert_eq!(
+            found,
+            vec![
+                (
+                    Position::new(11, 8),
+                    "'OLD_TOTAL' is deprecated: use NEW_TOTAL instead".to_string(),
+                    Some(serde_json::json!({ "replacement": "NEW_TOTAL" }))
+                ),
+                (
+                    Position::new(11, 40),
+                    "'GONE' is deprecated".to_string(),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_identifier_warning() {
+        assert!(messages("Set R (2 * PI)", Locale::En).is_empty());
+        assert_eq!(
+            messages(
+                "Func AREA(R) {\n    Return MAP([R], Lambda X -> X * X * PI)\n}\nFor I In RANGE(0, 3) {\n    PRINTLN(AREA(I))\n}",
+                Locale::En
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages_with_lines("Set A 1\nSet B (A + RADIUS)", Locale::En),
+            vec![(1, "Undefined identifier 'RADIUS'".to_string())]
+        );
+        // Computed dict keys are expressions like any other
+        assert_eq!(
+            messages_with_lines(
+                "Set KEY \"a\"\nSet D {[KEY]: 1, [MISSING]: 2, NAME: 3}\nPRINTLN(D)",
+                Locale::En
+            ),
+            vec![(1, "Undefined identifier 'MISSING'".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_missing_module_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.ae"), "Set PI 3.14\nExport PI").unwrap();
+        let text = "Import {PI} From \"./lib.ae\"\nImport {E} From \"./gone.ae\"\nPRINTLN(PI, E)";
+        let options = AnalysisOptions {
+            locale: Locale::En,
+            base_dir: Some(dir.path()),
+            ..Default::default()
+        };
+        let diagnostics = DiagnosticEngine::analyze(&Parser::new(text).parse(), &options);
+        let errors: Vec<(u32, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(errors, vec![(1, "Module './gone.ae' not found")]);
     }
 }
@@ -0,0 +1,362 @@
+//! Renaming a string key of a dict variable
+//!
+//! `CONFIG["retry_count"]` names no symbol, so rename can't reach it. The
+//! `aether.renameDictKey` command rewrites the key under the cursor wherever it
+//! indexes the same variable, and in the dict literals the variable is set
+//! from. Like the rest of name resolution it goes by name and ignores scopes.
+//! An access whose key is computed, or a literal that merely contains the key,
+//! may or may not mean the same entry; those are reported rather than changed.
+//! The code action carries no new key: the client asks for one and adds it to
+//! the command's arguments.
+
+use lsp_types::*;
+
+use crate::ast::{DictKey, Expr, ExprKind, Program, Span, Stmt, StmtKind, span_contains};
+use crate::lexer::Lexer;
+use crate::parser::ParsedDocument;
+use crate::request_error::RequestError;
+use crate::token::Token;
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+
+/// Command id; arguments are the document URI, the position of the key and
+/// the new key
+pub const RENAME_DICT_KEY_COMMAND: &str = "aether.renameDictKey";
+
+/// A string key used to index a variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictKeyTarget {
+    pub variable: String,
+    pub key: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DictKeyRename {
+    pub edits: Vec<TextEdit>,
+    /// Keys of the same variable that can't be told apart statically: computed
+    /// ones, and literals that only contain the renamed key
+    pub skipped: Vec<Span>,
+}
+
+/// The variable and key of the `NAME["key"]` whose key is under `position`
+pub fn target_at(program: &Program, position: Position) -> Option<DictKeyTarget> {
+    let mut finder = TargetFinder {
+        position,
+        found: None,
+    };
+    finder.visit_block(program);
+    finder.found
+}
+
+/// The edits renaming the key under `position` to `new_key`
+pub fn rename_dict_key(
+    doc: &ParsedDocument,
+    uri: &Url,
+    position: Position,
+    new_key: &str,
+) -> Result<DictKeyRename, RequestError> {
+    if doc.lost_ast() {
+        return Err(RequestError::AnalysisUnavailable(uri.clone()));
+    }
+    let target =
+        target_at(&doc.ast, position).ok_or(RequestError::NotARenamableSymbol(position))?;
+
+    let mut collector = AccessCollector {
+        target: &target,
+        accesses: Vec::new(),
+        skipped: Vec::new(),
+        literals: Vec::new(),
+    };
+    collector.visit_block(&doc.ast);
+
+    let quoted = string_literal(new_key);
+    let mut edits: Vec<TextEdit> = collector
+        .accesses
+        .iter()
+        .map(|&range| TextEdit {
+            range,
+            new_text: quoted.clone(),
+        })
+        .collect();
+    let mut skipped = collector.skipped;
+
+    // The AST drops where dict keys are; read them back from the tokens
+    let tokens = tokens(&doc.text);
+    for literal in &collector.literals {
+        let ExprKind::Dict(pairs) = &literal.kind else {
+            continue;
+        };
+        skipped.extend(pairs.iter().filter_map(|(key, _)| match key {
+            DictKey::Computed(key) => Some(key.span),
+            _ => None,
+        }));
+        for (token, span) in keys_of(&tokens, literal.span) {
+            match token {
+                Token::String(key) if *key == target.key => edits.push(TextEdit {
+                    range: span,
+                    new_text: quoted.clone(),
+                }),
+                Token::String(key) if key.contains(&target.key) => skipped.push(span),
+                Token::Identifier(key) if key.as_str() == target.key => edits.push(TextEdit {
+                    range: span,
+                    new_text: if is_identifier(new_key) {
+                        new_key.to_string()
+                    } else {
+                        quoted.clone()
+                    },
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    edits.sort_by_key(|edit| edit.range.start);
+    skipped.sort_by_key(|span| span.start);
+    Ok(DictKeyRename { edits, skipped })
+}
+
+/// The code action offered on the key of `NAME["key"]`; the client supplies
+/// the new key when it runs the command, so it is only offered to clients
+/// announcing they prompt for it
+pub fn rename_dict_key_action(
+    doc: &ParsedDocument,
+    uri: &Url,
+    position: Position,
+) -> Option<CodeAction> {
+    let target = target_at(&doc.ast, position)?;
+    let title = format!("Rename key \"{}\" of {}", target.key, target.variable);
+    Some(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::REFACTOR),
+        command: Some(Command {
+            title,
+            command: RENAME_DICT_KEY_COMMAND.to_string(),
+            arguments: Some(vec![serde_json::json!(uri), serde_json::json!(position)]),
+        }),
+        ..Default::default()
+    })
+}
+
+struct TargetFinder {
+    position: Position,
+    found: Option<DictKeyTarget>,
+}
+
+impl TargetFinder {
+    fn check(&mut self, object: &Expr, index: &Expr) {
+        if let ExprKind::Identifier(variable) = &object.kind
+            && let ExprKind::String(key) = &index.kind
+            && span_contains(index.span, self.position)
+        {
+            self.found = Some(DictKeyTarget {
+                variable: variable.to_string(),
+                key: key.clone(),
+            });
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for TargetFinder {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::SetIndex { object, index, .. } = &stmt.kind {
+            self.check(object, index);
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Index { object, index } = &expr.kind {
+            self.check(object, index);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+struct AccessCollector<'t, 'a> {
+    target: &'t DictKeyTarget,
+    /// Index expressions that are exactly the key
+    accesses: Vec<Span>,
+    skipped: Vec<Span>,
+    /// Dict literals the variable is set from
+    literals: Vec<&'a Expr>,
+}
+
+impl<'a> AccessCollector<'_, 'a> {
+    fn check(&mut self, object: &Expr, index: &Expr) {
+        if !matches!(&object.kind, ExprKind::Identifier(name) if name.as_str() == self.target.variable)
+        {
+            return;
+        }
+        match &index.kind {
+            ExprKind::String(key) if *key == self.target.key => self.accesses.push(index.span),
+            ExprKind::String(key) if key.contains(&self.target.key) => {
+                self.skipped.push(index.span)
+            }
+            // Another key, or an array position
+            ExprKind::String(_) | ExprKind::Number(_) => {}
+            _ => self.skipped.push(index.span),
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for AccessCollector<'_, 'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, value }
+                if name.name.as_str() == self.target.variable
+                    && matches!(value.kind, ExprKind::Dict(_)) =>
+            {
+                self.literals.push(value);
+            }
+            StmtKind::SetIndex { object, index, .. } => self.check(object, index),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Index { object, index } = &expr.kind {
+            self.check(object, index);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+fn tokens(text: &str) -> Vec<(Token, Span)> {
+    let mut lexer = Lexer::new(text);
+    let mut tokens = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Token::EOF => return tokens,
+            token => tokens.push((token, lexer.token_span())),
+        }
+    }
+}
+
+/// The key tokens of the dict literal spanning `span`: followed by a colon,
+/// and not inside a nested literal, call or block
+fn keys_of(tokens: &[(Token, Span)], span: Span) -> Vec<(&Token, Span)> {
+    let start = tokens.partition_point(|(_, token)| token.start < span.start);
+    let end = tokens.partition_point(|(_, token)| token.end <= span.end);
+    let Some(inside) = tokens.get(start..end) else {
+        return Vec::new();
+    };
+
+    let mut keys = Vec::new();
+    let mut depth = 0usize;
+    for (index, (token, span)) in inside.iter().enumerate() {
+        match token {
+            Token::LeftBrace | Token::LeftBracket | Token::LeftParen => depth += 1,
+            Token::RightBrace | Token::RightBracket | Token::RightParen => {
+                depth = depth.saturating_sub(1)
+            }
+            Token::String(_) | Token::Identifier(_)
+                if depth == 1 && matches!(inside.get(index + 1), Some((Token::Colon, _))) =>
+            {
+                keys.push((token, *span));
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+fn string_literal(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn spans(text: &str, spans: &[Span]) -> Vec<String> {
+        let lines: Vec<&str> = text.lines().collect();
+        spans
+            .iter()
+            .map(|span| {
+                lines[span.start.line as usize]
+                    .chars()
+                    .skip(span.start.character as usize)
+                    .take((span.end.character - span.start.character) as usize)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_renames_every_literal_access_and_the_initializer() {
+        let text = "Set CONFIG {\"retry_count\": 3, \"host\": \"localhost\"}\n\
+                    PRINTLN(CONFIG[\"retry_count\"])\n\
+                    Set CONFIG[\"retry_count\"] CONFIG[\"retry_count\"] + 1\n\
+                    Set KEY \"retry_count\"\n\
+                    PRINTLN(CONFIG[KEY])\n\
+                    PRINTLN(OTHER[\"retry_count\"], CONFIG[\"host\"])";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///config.ae").unwrap();
+
+        let target = target_at(&doc.ast, Position::new(1, 18)).unwrap();
+        assert_eq!(
+            target,
+            DictKeyTarget {
+                variable: "CONFIG".to_string(),
+                key: "retry_count".to_string(),
+            }
+        );
+
+        let rename = rename_dict_key(&doc, &uri, Position::new(1, 18), "retries").unwrap();
+        let ranges: Vec<Span> = rename.edits.iter().map(|edit| edit.range).collect();
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| range.start.line)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 2]
+        );
+        assert!(
+            spans(text, &ranges)
+                .iter()
+                .all(|literal| literal == "\"retry_count\"")
+        );
+        assert!(
+            rename
+                .edits
+                .iter()
+                .all(|edit| edit.new_text == "\"retries\"")
+        );
+        // `CONFIG[KEY]` might be the same entry
+        assert_eq!(spans(text, &rename.skipped), vec!["KEY"]);
+    }
+
+    #[test]
+    fn test_partial_and_computed_keys_are_skipped() {
+        let text = "Set C {retry: 1, \"retry_max\": 2, [K]: 3, \"inner\": {\"retry\": 4}}\n\
+                    PRINTLN(C[\"retry\"], C[\"retry\" + \"_max\"])";
+        let doc = Parser::new(text).parse();
+        let uri = Url::parse("file:///c.ae").unwrap();
+
+        let rename = rename_dict_key(&doc, &uri, Position::new(1, 12), "attempts").unwrap();
+        let ranges: Vec<Span> = rename.edits.iter().map(|edit| edit.range).collect();
+        assert_eq!(spans(text, &ranges), vec!["retry", "\"retry\""]);
+        assert_eq!(rename.edits[0].new_text, "attempts");
+        assert_eq!(
+            spans(text, &rename.skipped),
+            vec!["\"retry_max\"", "K", "\"retry\" + \"_max\""]
+        );
+    }
+
+    #[test]
+    fn test_only_string_index_keys_are_targets() {
+        let doc = Parser::new("Set A [1, 2]\nPRINTLN(A[0], A[I])").parse();
+        let uri = Url::parse("file:///a.ae").unwrap();
+        assert_eq!(
+            rename_dict_key(&doc, &uri, Position::new(1, 10), "x"),
+            Err(RequestError::NotARenamableSymbol(Position::new(1, 10)))
+        );
+        assert!(rename_dict_key_action(&doc, &uri, Position::new(1, 16)).is_none());
+    }
+}
@@ -0,0 +1,281 @@
+//! Doc comment skeletons for functions and generators
+//!
+//! The comment run right above a definition is its documentation (see
+//! `symbols::doc_comment`). With none there, "Generate doc comment" writes one:
+//! a placeholder summary, a `// @param NAME description` line per parameter
+//! and `// @returns description` when the body returns a value. When the
+//! `@param` tags of an existing comment no longer list the parameters in
+//! order, "Update doc comment" rewrites just those tags, keeping the line of
+//! every parameter that is still there.
+
+use std::collections::HashMap;
+
+use lsp_types::*;
+
+use crate::ast::{self, Expr, ExprKind, Ident, Stmt, StmtKind};
+use crate::parser::ParsedDocument;
+use crate::symbols;
+use crate::visit::{Visitor, walk_stmt};
+
+/// What a new tag says until someone writes it
+const PLACEHOLDER: &str = "description";
+
+/// The parameter an `@param` line documents, if it is one
+pub fn param_tag(comment: &str) -> Option<&str> {
+    let rest = comment
+        .trim_start_matches('/')
+        .trim_start()
+        .strip_prefix("@param")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    rest.split_whitespace().next()
+}
+
+/// "Generate doc comment" or "Update doc comment" for the function or
+/// generator defined on the line of `position`
+pub fn doc_comment_action(
+    doc: &ParsedDocument,
+    uri: &Url,
+    position: Position,
+) -> Option<CodeAction> {
+    let (stmt, name, params, body) = definition_on_line(&doc.ast, position.line)?;
+    let lines: Vec<&str> = doc.text.lines().collect();
+    let line = lines.get(stmt.span.start.line as usize)?;
+    let indent: String = line.chars().take_while(|ch| ch.is_whitespace()).collect();
+
+    let comments = symbols::doc_comment_run(doc.ast.comments(), stmt.span.start.line);
+    let (title, edit) = match comments.as_slice() {
+        [] => {
+            let mut skeleton = vec![format!("{}// TODO: describe {}", indent, name)];
+            skeleton.extend(params.iter().map(|param| new_tag(&indent, param)));
+            if returns_value(body) {
+                skeleton.push(format!("{}// @returns {}", indent, PLACEHOLDER));
+            }
+            let start = Position::new(stmt.span.start.line, 0);
+            let edit = TextEdit {
+                range: Range::new(start, start),
+                new_text: format!("{}\n", skeleton.join("\n")),
+            };
+            ("Generate doc comment", edit)
+        }
+        // A block comment isn't rewritten line by line
+        comments if comments.iter().any(|comment| comment.block) => return None,
+        comments => {
+            let tagged: Vec<&str> = comments
+                .iter()
+                .filter_map(|comment| param_tag(&comment.text))
+                .collect();
+            if tagged.iter().copied().eq(params.iter().map(Ident::as_str)) {
+                return None;
+            }
+            let first = comments.first()?.span.start.line;
+            let last = comments.last()?.span.end.line;
+            let existing = &lines[first as usize..=last as usize];
+            let edit = TextEdit {
+                range: Range::new(
+                    Position::new(first, 0),
+                    Position::new(last, existing.last()?.chars().count() as u32),
+                ),
+                new_text: reconcile(existing, params).join("\n"),
+            };
+            ("Update doc comment", edit)
+        }
+    };
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The innermost `Func` or `Generator` whose definition starts on `line`
+fn definition_on_line(program: &[Stmt], line: u32) -> Option<(&Stmt, &Ident, &[Ident], &[Stmt])> {
+    let mut found = None;
+    ast::for_each_stmt(program, &mut |stmt| match &stmt.kind {
+        StmtKind::FuncDef { name, params, body }
+        | StmtKind::GeneratorDef { name, params, body }
+            if stmt.span.start.line == line =>
+        {
+            found = Some((stmt, name, params.as_slice(), body.as_slice()));
+        }
+        _ => {}
+    });
+    found
+}
+
+fn new_tag(indent: &str, param: &Ident) -> String {
+    format!("{}// @param {} {}", indent, param, PLACEHOLDER)
+}
+
+/// `existing` comment lines with the `@param` lines replaced by one per
+/// parameter, where the first of them was (or before `@returns`, or at the end)
+fn reconcile(existing: &[&str], params: &[Ident]) -> Vec<String> {
+    let indent: String = existing
+        .first()
+        .map(|line| line.chars().take_while(|ch| ch.is_whitespace()).collect())
+        .unwrap_or_default();
+    let kept: HashMap<&str, &str> = existing
+        .iter()
+        .filter_map(|line| Some((param_tag(line.trim_start())?, *line)))
+        .collect();
+    let tags: Vec<String> = params
+        .iter()
+        .map(|param| match kept.get(param.as_str()) {
+            Some(line) => line.to_string(),
+            None => new_tag(&indent, param),
+        })
+        .collect();
+
+    let at = existing
+        .iter()
+        .position(|line| param_tag(line.trim_start()).is_some())
+        .or_else(|| {
+            existing.iter().position(|line| {
+                line.trim_start()
+                    .trim_start_matches('/')
+                    .trim_start()
+                    .starts_with("@returns")
+            })
+        })
+        .unwrap_or(existing.len());
+    let mut lines: Vec<String> = Vec::new();
+    for (index, line) in existing.iter().enumerate() {
+        if index == at {
+            lines.extend(tags.iter().cloned());
+        }
+        if param_tag(line.trim_start()).is_none() {
+            lines.push(line.to_string());
+        }
+    }
+    if at == existing.len() {
+        lines.extend(tags);
+    }
+    lines
+}
+
+/// Whether `body` has a `Return` of something other than Null, not counting
+/// nested functions and lambdas
+fn returns_value(body: &[Stmt]) -> bool {
+    struct Returns(bool);
+
+    impl<'a> Visitor<'a> for Returns {
+        fn visit_stmt(&mut self, stmt: &'a Stmt) {
+            match &stmt.kind {
+                StmtKind::Return(value) => self.0 |= value.kind != ExprKind::Null,
+                StmtKind::FuncDef { .. } | StmtKind::GeneratorDef { .. } => {}
+                _ => walk_stmt(self, stmt),
+            }
+        }
+
+        fn visit_expr(&mut self, _expr: &'a Expr) {}
+    }
+
+    let mut returns = Returns(false);
+    returns.visit_block(body);
+    returns.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// The edited text after applying the action on `line`, with its title
+    fn apply(text: &str, line: u32) -> Option<(String, String)> {
+        let uri = Url::parse("file:///test.ae").unwrap();
+        let doc = Parser::new(text).parse();
+        let action = doc_comment_action(&doc, &uri, Position::new(line, 2))?;
+        let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let (start, end) = (edit.range.start, edit.range.end);
+        let head = &lines[start.line as usize][..start.character as usize];
+        let tail = lines
+            .get(end.line as usize)
+            .map(|line| line[end.character as usize..].to_string())
+            .unwrap_or_default();
+        let replaced = format!("{}{}{}", head, edit.new_text, tail);
+        lines.splice(
+            start.line as usize..=end.line as usize,
+            replaced.lines().map(str::to_string).collect::<Vec<_>>(),
+        );
+        Some((action.title, lines.join("\n")))
+    }
+
+    #[test]
+    fn test_generate_doc_comment() {
+        let (title, text) = apply(
+            "Set X 1\nFunc ADD(A, B) {\n    Set F Lambda N -> N\n    Return A + B\n}",
+            1,
+        )
+        .unwrap();
+        assert_eq!(title, "Generate doc comment");
+        assert_eq!(
+            text,
+            "Set X 1\n// TODO: describe ADD\n// @param A description\n// @param B description\n\
+             // @returns description\nFunc ADD(A, B) {\n    Set F Lambda N -> N\n    Return A + B\n}"
+        );
+
+        // Nested and indented; returning Null, or only from a nested function,
+        // returns nothing
+        let (_, text) = apply(
+            "Func OUTER() {\n    Generator EACH(XS) {\n        Func INNER() {\n            Return 1\n        }\n        Return Null\n    }\n}",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            text.lines().skip(1).take(3).collect::<Vec<_>>(),
+            vec![
+                "    // TODO: describe EACH",
+                "    // @param XS description",
+                "    Generator EACH(XS) {"
+            ]
+        );
+        // Only on the definition's own line
+        assert_eq!(apply("Func F() {\n    Return 1\n}", 1), None);
+    }
+
+    #[test]
+    fn test_update_after_adding_a_parameter() {
+        let (title, text) = apply(
+            "// Adds numbers\n// @param A the first\n// @returns the sum\nFunc ADD(A, B) {\n    Return A + B\n}",
+            3,
+        )
+        .unwrap();
+        assert_eq!(title, "Update doc comment");
+        assert_eq!(
+            text,
+            "// Adds numbers\n// @param A the first\n// @param B description\n// @returns the sum\n\
+             Func ADD(A, B) {\n    Return A + B\n}"
+        );
+        // Tags that already match need nothing
+        assert_eq!(
+            apply("// @param A the first\nFunc F(A) {\n    Return A\n}", 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_update_keeps_descriptions_of_remaining_parameters() {
+        let (_, text) = apply(
+            "    // Scales\n    // @param OLD gone now\n    // @param FACTOR   how much, at least 1\nFunc SCALE(FACTOR, VALUE) {\n    Return FACTOR * VALUE\n}",
+            3,
+        )
+        .unwrap();
+        assert_eq!(
+            text,
+            "    // Scales\n    // @param FACTOR   how much, at least 1\n    // @param VALUE description\n\
+             Func SCALE(FACTOR, VALUE) {\n    Return FACTOR * VALUE\n}"
+        );
+        // A comment without any tags gets them at the end
+        let (_, text) = apply("// Shows it\nFunc SHOW(X) {\n    PRINTLN(X)\n}", 1).unwrap();
+        assert!(text.starts_with("// Shows it\n// @param X description\nFunc SHOW(X)"));
+    }
+}
@@ -0,0 +1,87 @@
+//! One key per document, however the client spells its URI
+//!
+//! Clients disagree on how to write the same file: `file:///C:/proj/A.ae`,
+//! `file:///c%3A/proj/a.ae`, a percent-encoded space or a spelled-out one. The
+//! server's per-document maps are keyed by [`document_key`] so every spelling
+//! lands on the same entry; the client's own URI is kept apart for anything
+//! sent back to it.
+
+use lsp_types::Url;
+
+/// The key of the document at `uri`. For `file` URIs the path is
+/// percent-decoded (except `%2F`, which would split a segment), backslashes
+/// become `/`, a trailing `/` is dropped, and a path starting with a drive
+/// letter is lowercased whole, as Windows compares paths regardless of case.
+/// Other schemes name a buffer, not a path, and are kept as they are.
+pub fn document_key(uri: &Url) -> String {
+    if uri.scheme() != "file" {
+        return uri.to_string();
+    }
+    let mut path = decode(uri.path()).replace('\\', "/");
+    if path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    let drive = path.as_bytes();
+    if drive.len() >= 3 && drive[0] == b'/' && drive[1].is_ascii_alphabetic() && drive[2] == b':' {
+        path = path.to_lowercase();
+    }
+    let host = uri.host_str().unwrap_or_default().to_lowercase();
+    format!("file://{}{}", host, path)
+}
+
+/// `path` with its percent-escapes decoded, `%2F` aside; as written if the
+/// escapes don't decode to UTF-8
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .filter(|hex| !hex.eq_ignore_ascii_case("2f"))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(uri: &str) -> String {
+        document_key(&Url::parse(uri).unwrap())
+    }
+
+    #[test]
+    fn test_spellings_of_one_file_share_a_key() {
+        let windows = key("file:///C:/proj/A.ae");
+        assert_eq!(windows, "file:///c:/proj/a.ae");
+        for spelling in [
+            "file:///c%3A/proj/a.ae",
+            "file:///C%3a/Proj/A.ae",
+            "file:///c:/proj/a.ae/",
+        ] {
+            assert_eq!(key(spelling), windows, "{}", spelling);
+        }
+        assert_eq!(
+            key("file:///home/me/my%20project/Main.ae"),
+            "file:///home/me/my project/Main.ae"
+        );
+        assert_eq!(key("file:///home/me/a%2Fb.ae"), "file:///home/me/a%2Fb.ae");
+        assert_eq!(key("file://Server/share/x.ae"), "file://server/share/x.ae");
+        // Case matters outside Windows paths, and in other schemes
+        assert_ne!(key("file:///home/A.ae"), key("file:///home/a.ae"));
+        assert_eq!(key("untitled:Untitled-1"), "untitled:Untitled-1");
+    }
+}
@@ -0,0 +1,242 @@
+//! Host-provided builtins declared through the `aether.extraBuiltins` setting
+//!
+//! Embeddings often expose their own functions (`HTTP_GET`, `DB_QUERY`, ...).
+//! The setting is either an inline array of definitions or a path to a JSON
+//! or TOML file containing them; a relative path is resolved against the
+//! workspace root.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::builtins::{self, BuiltinFunction};
+
+/// Category given to definitions that don't name one
+const DEFAULT_CATEGORY: &str = "Host";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ExtraBuiltinsSource {
+    Inline(Vec<BuiltinSpec>),
+    Path(String),
+}
+
+/// One builtin as written in the settings or a definitions file
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltinSpec {
+    pub name: String,
+    /// Defaults to `NAME(params...)` when omitted
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub params: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub description_en: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub examples: Vec<String>,
+    #[serde(default)]
+    pub deprecated: Option<String>,
+}
+
+/// Layout of a definitions file: `{"builtins": [...]}` / `[[builtins]]`, or a bare JSON array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DefinitionsFile {
+    Table { builtins: Vec<BuiltinSpec> },
+    List(Vec<BuiltinSpec>),
+}
+
+impl DefinitionsFile {
+    fn into_specs(self) -> Vec<BuiltinSpec> {
+        match self {
+            DefinitionsFile::Table { builtins } | DefinitionsFile::List(builtins) => builtins,
+        }
+    }
+}
+
+/// Read the definitions named by `source`
+pub fn load(source: &ExtraBuiltinsSource, root: Option<&Path>) -> Result<Vec<BuiltinSpec>, String> {
+    let path = match source {
+        ExtraBuiltinsSource::Inline(specs) => return Ok(specs.clone()),
+        ExtraBuiltinsSource::Path(path) => match root {
+            Some(root) => root.join(path),
+            None => Path::new(path).to_path_buf(),
+        },
+    };
+
+    let text = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let file: DefinitionsFile = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?
+    } else {
+        serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?
+    };
+    Ok(file.into_specs())
+}
+
+/// The definitions currently in the registry
+static INSTALLED: Mutex<Vec<BuiltinSpec>> = Mutex::new(Vec::new());
+
+/// Put `specs` in the builtin registry
+///
+/// Registry entries are `'static`, so every install leaks the previous set;
+/// reloading the same definitions keeps the registry as it is.
+pub fn install(specs: Vec<BuiltinSpec>) {
+    let mut installed = INSTALLED.lock().unwrap_or_else(PoisonError::into_inner);
+    if *installed == specs {
+        return;
+    }
+    builtins::set_extra_builtins(
+        specs
+            .iter()
+            .cloned()
+            .map(BuiltinSpec::into_builtin)
+            .collect(),
+    );
+    *installed = specs;
+}
+
+impl BuiltinSpec {
+    /// Convert to a registry entry; the strings are leaked to match the static core table
+    pub fn into_builtin(self) -> BuiltinFunction {
+        let name = self.name.to_uppercase();
+        let signature = self
+            .signature
+            .unwrap_or_else(|| format!("{}({})", name, self.params.join(", ")));
+        let examples: Vec<&'static str> = self.examples.into_iter().map(leak).collect();
+
+        BuiltinFunction {
+            description_en: self
+                .description_en
+                .map_or_else(|| leak(self.description.clone()), leak),
+            description: leak(self.description),
+            name: leak(name),
+            signature: leak(signature),
+            category: self.category.map_or(DEFAULT_CATEGORY, leak),
+            examples: Box::leak(examples.into_boxed_slice()),
+            deprecated: self.deprecated.map(leak),
+        }
+    }
+}
+
+fn leak(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::{AnalysisOptions, DiagnosticEngine};
+    use crate::locale::Locale;
+    use crate::parser::Parser;
+    use lsp_types::{CompletionItemTag, DiagnosticTag, HoverContents, Position};
+
+    const DEFINITIONS: &str = r#"
+[[builtins]]
+name = "HTTP_GET"
+params = ["url", "[headers]"]
+description = "发送 GET 请求"
+descriptionEn = "Send a GET request"
+category = "Network"
+examples = ['Set BODY HTTP_GET("https://example.com")']
+
+[[builtins]]
+name = "db_query"
+signature = "DB_QUERY(sql, params...)"
+description = "执行查询"
+
+[[builtins]]
+name = "HTTP_FETCH"
+params = ["url"]
+description = "发送 GET 请求"
+deprecated = "HTTP_GET"
+"#;
+
+    #[test]
+    fn test_extra_builtins_from_toml_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("host.toml"), DEFINITIONS).unwrap();
+        let specs = load(
+            &ExtraBuiltinsSource::Path("host.toml".to_string()),
+            Some(dir.path()),
+        )
+        .unwrap();
+        install(specs.clone());
+
+        let http_get = builtins::lookup("http_get").unwrap();
+        // Reloading the same definitions keeps the registry
+        install(specs);
+        assert!(std::ptr::eq(
+            builtins::lookup("HTTP_GET").unwrap(),
+            http_get
+        ));
+        assert_eq!(http_get.signature, "HTTP_GET(url, [headers])");
+        assert_eq!(http_get.arity(), (1, Some(2)));
+        assert_eq!(builtins::lookup("DB_QUERY").unwrap().category, "Host");
+
+        let HoverContents::Markup(hover) =
+            builtins::builtin_to_hover(http_get, Locale::En).contents
+        else {
+            panic!("expected markdown hover");
+        };
+        assert!(hover.value.contains("Send a GET request"));
+
+        // A deprecated definition is tagged in completion and hover
+        let fetch = builtins::lookup("HTTP_FETCH").unwrap();
+        let HoverContents::Markup(hover) = builtins::builtin_to_hover(fetch, Locale::En).contents
+        else {
+            panic!("expected markdown hover");
+        };
+        assert!(hover.value.starts_with("> **Deprecated**: use `HTTP_GET`"));
+        let completions = builtins::builtin_to_completion_items(Locale::Zh);
+        let tags = |label: &str| {
+            completions
+                .iter()
+                .find(|item| item.label == label)
+                .unwrap()
+                .tags
+                .clone()
+        };
+        assert_eq!(
+            tags("HTTP_FETCH"),
+            Some(vec![CompletionItemTag::DEPRECATED])
+        );
+        assert_eq!(tags("HTTP_GET"), None);
+
+        let text = "Set A HTTP_GET()\nSet B DB_QUERY(\"SELECT 1\", 2, 3)\nSet C HTTP_FETCH(\"/\")";
+        let diagnostics = DiagnosticEngine::analyze(
+            &Parser::new(text).parse(),
+            &AnalysisOptions {
+                locale: Locale::En,
+                ..Default::default()
+            },
+        );
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Builtin 'HTTP_GET' expects 1-2 argument(s) but got 0\nSignature: HTTP_GET(url, [headers])",
+                "Builtin 'HTTP_FETCH' is deprecated; use HTTP_GET instead",
+            ]
+        );
+        assert_eq!(diagnostics[1].tags, Some(vec![DiagnosticTag::DEPRECATED]));
+        assert_eq!(diagnostics[1].range.start, Position::new(2, 6));
+    }
+
+    #[test]
+    fn test_malformed_definitions_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("host.json"), "{\"builtins\": [{}]}").unwrap();
+        let source = ExtraBuiltinsSource::Path("host.json".to_string());
+        assert!(load(&source, Some(dir.path())).is_err());
+    }
+}
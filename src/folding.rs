@@ -0,0 +1,322 @@
+//! Folding ranges, and a coarse structure for documents that don't parse
+//!
+//! Folding normally follows the AST. When the parse recovered nothing at all,
+//! [`fallback_structure`] pairs braces in the token stream and picks out
+//! `Func`/`Generator` names instead, so one stray token doesn't leave a large
+//! file without folding or outline until it is fixed.
+
+use lsp_types::Range;
+#[cfg(feature = "server")]
+use lsp_types::{
+    DocumentSymbol, FoldingRange, FoldingRangeKind, Location, SymbolInformation, SymbolKind, Url,
+};
+
+use crate::ast::Comment;
+#[cfg(feature = "server")]
+use crate::ast::{self, ExprKind, StmtKind};
+use crate::lexer::Lexer;
+#[cfg(feature = "server")]
+use crate::parser::ParsedDocument;
+#[cfg(feature = "server")]
+use crate::set_runs;
+use crate::token::Token;
+
+#[cfg(feature = "server")]
+/// Folding ranges of a document, from its AST or else from its tokens
+pub fn folding_ranges(doc: &ParsedDocument) -> Vec<FoldingRange> {
+    if let Some(structure) = fallback_structure(doc) {
+        let mut ranges: Vec<FoldingRange> = structure
+            .blocks
+            .iter()
+            .filter_map(|block| fold(*block, None))
+            .collect();
+        ranges.extend(comment_folds(&structure.comments));
+        return sorted(ranges);
+    }
+
+    let mut ranges = Vec::new();
+    ast::for_each_stmt(&doc.ast, &mut |stmt| {
+        if matches!(
+            stmt.kind,
+            StmtKind::FuncDef { .. }
+                | StmtKind::GeneratorDef { .. }
+                | StmtKind::While { .. }
+                | StmtKind::For { .. }
+                | StmtKind::ForIndexed { .. }
+                | StmtKind::Switch { .. }
+                | StmtKind::Try { .. }
+        ) {
+            ranges.extend(fold(stmt.span, None));
+        }
+    });
+    ast::for_each_expr(&doc.ast, &mut |expr| {
+        if matches!(
+            expr.kind,
+            ExprKind::If { .. } | ExprKind::Lambda { .. } | ExprKind::Array(_) | ExprKind::Dict(_)
+        ) {
+            ranges.extend(fold(expr.span, None));
+        }
+    });
+    ranges.extend(comment_folds(doc.ast.comments()));
+    ranges.extend(
+        set_runs::set_runs(&doc.ast)
+            .iter()
+            .filter_map(|run| fold_lines(run.range, Some(FoldingRangeKind::Region))),
+    );
+
+    // Adjacent imports at the top fold together
+    let imports = doc.ast.imports();
+    if let (Some(first), Some(last)) = (imports.first(), imports.last()) {
+        let range = Range::new(first.span.start, last.span.end);
+        ranges.extend(fold_lines(range, Some(FoldingRangeKind::Imports)));
+    }
+    sorted(ranges)
+}
+
+#[cfg(feature = "server")]
+/// Fold everything but the last line, which keeps a closing `}` visible
+fn fold(range: Range, kind: Option<FoldingRangeKind>) -> Option<FoldingRange> {
+    (range.end.line > range.start.line + 1).then(|| FoldingRange {
+        start_line: range.start.line,
+        start_character: None,
+        end_line: range.end.line - 1,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    })
+}
+
+#[cfg(feature = "server")]
+/// Fold every line of `range`
+fn fold_lines(range: Range, kind: Option<FoldingRangeKind>) -> Option<FoldingRange> {
+    (range.end.line > range.start.line).then_some(FoldingRange {
+        start_line: range.start.line,
+        start_character: None,
+        end_line: range.end.line,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    })
+}
+
+#[cfg(feature = "server")]
+/// Block comments over several lines, and runs of line comments on consecutive lines
+fn comment_folds(comments: &[Comment]) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut run: Option<Range> = None;
+    for comment in comments {
+        if comment.block {
+            ranges.extend(fold_lines(comment.span, Some(FoldingRangeKind::Comment)));
+            continue;
+        }
+        match &mut run {
+            Some(range) if comment.span.start.line == range.end.line + 1 => {
+                range.end = comment.span.end;
+            }
+            _ => {
+                if let Some(range) = run.take() {
+                    ranges.extend(fold_lines(range, Some(FoldingRangeKind::Comment)));
+                }
+                run = Some(comment.span);
+            }
+        }
+    }
+    if let Some(range) = run {
+        ranges.extend(fold_lines(range, Some(FoldingRangeKind::Comment)));
+    }
+    ranges
+}
+
+#[cfg(feature = "server")]
+fn sorted(mut ranges: Vec<FoldingRange>) -> Vec<FoldingRange> {
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges.dedup_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+/// What a token scan can tell about a document that doesn't parse
+#[derive(Debug, Default)]
+pub struct FallbackStructure {
+    /// From each `{` to its matching `}`
+    pub blocks: Vec<Range>,
+    pub comments: Vec<Comment>,
+    pub functions: Vec<FallbackFunction>,
+}
+
+/// A `Func NAME` or `Generator NAME`, without parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackFunction {
+    pub name: String,
+    pub generator: bool,
+    /// From the keyword to the `}` closing the first block after it
+    pub range: Range,
+    pub selection_range: Range,
+}
+
+#[cfg(feature = "server")]
+impl FallbackFunction {
+    #[allow(deprecated)] // `DocumentSymbol::deprecated` must still be initialized
+    pub fn document_symbol(&self) -> DocumentSymbol {
+        DocumentSymbol {
+            name: self.name.clone(),
+            detail: None,
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            range: self.range,
+            selection_range: self.selection_range,
+            children: None,
+        }
+    }
+
+    #[allow(deprecated)]
+    pub fn symbol_information(&self, uri: &Url) -> SymbolInformation {
+        SymbolInformation {
+            name: self.name.clone(),
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            location: Location::new(uri.clone(), self.range),
+            container_name: None,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+/// The token-based structure of `doc`, if its parse recovered no statements
+/// from a text that has some
+pub fn fallback_structure(doc: &ParsedDocument) -> Option<FallbackStructure> {
+    doc.lost_ast().then(|| token_structure(&doc.text))
+}
+
+/// What a token scan finds in `text`, whether or not it parses
+pub fn token_structure(text: &str) -> FallbackStructure {
+    let mut lexer = Lexer::new(text);
+    let mut structure = FallbackStructure::default();
+    let mut open_braces: Vec<Range> = Vec::new();
+    // Functions waiting for their body to open, then for it to close
+    let mut pending: Option<FallbackFunction> = None;
+    let mut open_functions: Vec<(usize, FallbackFunction)> = Vec::new();
+    let mut previous: Option<(Token, Range)> = None;
+    loop {
+        let token = lexer.next_token();
+        let span = lexer.token_span();
+        match &token {
+            Token::EOF => break,
+            Token::Identifier(name) => {
+                if let Some((keyword @ (Token::Func | Token::Generator), keyword_span)) = &previous
+                {
+                    pending = Some(FallbackFunction {
+                        name: name.to_string(),
+                        generator: *keyword == Token::Generator,
+                        range: Range::new(keyword_span.start, span.end),
+                        selection_range: span,
+                    });
+                }
+            }
+            Token::LeftBrace => {
+                open_braces.push(span);
+                if let Some(function) = pending.take() {
+                    open_functions.push((open_braces.len(), function));
+                }
+            }
+            Token::RightBrace => {
+                if let Some(open) = open_braces.pop() {
+                    structure.blocks.push(Range::new(open.start, span.end));
+                }
+                if open_functions
+                    .last()
+                    .is_some_and(|(depth, _)| *depth > open_braces.len())
+                    && let Some((_, mut function)) = open_functions.pop()
+                {
+                    function.range.end = span.end;
+                    structure.functions.push(function);
+                }
+            }
+            _ => {}
+        }
+        previous = Some((token, span));
+    }
+    // Bodies left open run to the end of the text
+    structure
+        .functions
+        .extend(open_functions.into_iter().map(|(_, function)| function));
+    structure.functions.extend(pending);
+    structure
+        .functions
+        .sort_by_key(|function| (function.range.start.line, function.range.start.character));
+    structure
+        .blocks
+        .sort_by_key(|block| (block.start.line, block.start.character));
+    structure.comments = lexer.take_comments();
+    structure
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use lsp_types::Position;
+
+    fn lines(ranges: &[FoldingRange]) -> Vec<(u32, u32)> {
+        ranges
+            .iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_follows_the_ast() {
+        let text = "Import {A} From \"./a.ae\"\nImport {B} From \"./b.ae\"\n// Adds\n// two numbers\nFunc ADD(X, Y) {\n    If (X > 0) {\n        Return X + Y\n    }\n    Return Y\n}\nSet CONFIG {\n    \"a\": 1\n}";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let ranges = folding_ranges(&doc);
+        assert_eq!(
+            lines(&ranges),
+            vec![(0, 1), (2, 3), (4, 8), (5, 6), (10, 11)]
+        );
+        assert_eq!(ranges[0].kind, Some(FoldingRangeKind::Imports));
+        assert_eq!(ranges[1].kind, Some(FoldingRangeKind::Comment));
+        assert!(fallback_structure(&doc).is_none());
+    }
+
+    #[test]
+    fn test_runs_of_sets_fold_as_regions() {
+        let text =
+            "Set A 1\nSet B 2\nSet C 3\nFunc F() {\n    Set D 1\n    Set E 2\n    Set F 3\n}";
+        let ranges = folding_ranges(&Parser::new(text).parse());
+        assert_eq!(lines(&ranges), vec![(0, 2), (3, 6)]);
+        assert_eq!(ranges[0].kind, Some(FoldingRangeKind::Region));
+        // Only top-level runs count
+        assert_eq!(ranges[1].kind, None);
+    }
+
+    #[test]
+    fn test_fallback_after_early_syntax_error() {
+        let text = "Set CONFIG [\n// Adds\n// two numbers\nFunc ADD(X, Y) {\n    If (X > 0) {\n        Return X + Y\n    }\n    Return Y\n}\nSet LIMITS {\nGenerator COUNT(N) {\n    Yield \"}\"\n}\n";
+        let doc = Parser::new(text).parse();
+        assert!(doc.ast.is_empty());
+
+        let structure = fallback_structure(&doc).unwrap();
+        let names: Vec<(&str, bool)> = structure
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.generator))
+            .collect();
+        assert_eq!(names, vec![("ADD", false), ("COUNT", true)]);
+        assert_eq!(
+            structure.functions[0].range,
+            Range::new(Position::new(3, 0), Position::new(8, 1))
+        );
+        assert_eq!(
+            structure.functions[1].selection_range,
+            Range::new(Position::new(10, 10), Position::new(10, 15))
+        );
+
+        // The `}` in the string closes nothing, and the unclosed `{` folds nothing
+        assert_eq!(
+            lines(&folding_ranges(&doc)),
+            vec![(1, 2), (3, 7), (4, 5), (10, 11)]
+        );
+    }
+}
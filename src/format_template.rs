@@ -0,0 +1,86 @@
+//! Placeholders of `FORMAT` templates
+//!
+//! `FORMAT("Hello {}", NAME)` fills each `{}` with the next argument, and
+//! `{{` and `}}` stand for literal braces. `PRINTLN` and `PRINT` format the same
+//! way when their first argument is a template. Only a literal template can be
+//! counted; one held in a variable is left alone.
+
+use crate::ast::{Expr, ExprKind};
+
+/// A call whose first argument is a literal template
+#[derive(Debug)]
+pub struct TemplateCall<'a> {
+    /// `FORMAT`, `PRINTLN` or `PRINT`
+    pub function: &'a str,
+    /// The string literal
+    #[cfg(feature = "server")]
+    pub template: &'a Expr,
+    pub placeholders: usize,
+    /// Arguments after the template
+    pub arguments: usize,
+}
+
+impl TemplateCall<'_> {
+    pub fn matches(&self) -> bool {
+        self.placeholders == self.arguments
+    }
+}
+
+/// The template of `call`, if it is a `FORMAT` call with a literal template,
+/// or a `PRINTLN`/`PRINT` call whose first argument is a literal with a `{}`
+pub fn template_call(call: &Expr) -> Option<TemplateCall<'_>> {
+    let ExprKind::Call { func, args } = &call.kind else {
+        return None;
+    };
+    let ExprKind::Identifier(function) = &func.kind else {
+        return None;
+    };
+    let template = args.first()?;
+    let ExprKind::String(text) = &template.kind else {
+        return None;
+    };
+    let placeholders = count_placeholders(text);
+    let formats = match function.as_str() {
+        "FORMAT" => true,
+        "PRINTLN" | "PRINT" => placeholders > 0,
+        _ => false,
+    };
+    formats.then(|| TemplateCall {
+        function: function.as_str(),
+        #[cfg(feature = "server")]
+        template,
+        placeholders,
+        arguments: args.len() - 1,
+    })
+}
+
+/// The `{}` in `template`, not counting the escapes `{{` and `}}`
+pub fn count_placeholders(template: &str) -> usize {
+    let mut count = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => {}
+            '{' if chars.next_if_eq(&'}').is_some() => count += 1,
+            '}' => {
+                chars.next_if_eq(&'}');
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_placeholders() {
+        assert_eq!(count_placeholders("Hello {} you are {}"), 2);
+        assert_eq!(count_placeholders("{{}} is literal, {} is not"), 1);
+        assert_eq!(count_placeholders("{{{}}}"), 1);
+        assert_eq!(count_placeholders("{name} {"), 0);
+        assert_eq!(count_placeholders(""), 0);
+    }
+}
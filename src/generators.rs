@@ -0,0 +1,242 @@
+//! Which names are Generators, and what calling one gives
+//!
+//! `Set G COUNT_UP(5)` doesn't run `COUNT_UP`'s body: it makes a generator
+//! object, which a `For` loop then drains. Mixing the two up is a common slip,
+//! in both directions. Resolution goes by name like the rest of the lints, but
+//! a name bound any other way as well (a variable, a parameter, an import, or
+//! both a `Func` and a `Generator`) could be either, so it resolves to nothing.
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "server")]
+use lsp_types::Position;
+
+#[cfg(feature = "server")]
+use crate::ast::{self, Node, span_contains};
+use crate::ast::{Expr, ExprKind, Ident, Program, Stmt, StmtKind};
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+
+/// What a name defined with a body is, when that's certain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callable {
+    Function {
+        /// Some `Return` gives a value other than `Null`
+        returns_value: bool,
+    },
+    Generator,
+}
+
+/// The names in `program` that certainly are a `Func` or a `Generator`
+pub fn callables(program: &Program) -> HashMap<&str, Callable> {
+    let mut collector = Collector::default();
+    collector.visit_block(program);
+    collector
+        .definitions
+        .into_iter()
+        .filter(|(name, _)| !collector.rebound.contains(name))
+        .filter_map(|(name, kinds)| {
+            let first = *kinds.first()?;
+            kinds
+                .iter()
+                .all(|kind| std::mem::discriminant(kind) == std::mem::discriminant(&first))
+                .then(|| {
+                    // A function defined twice returns a value if either may
+                    let returns_value = kinds.iter().any(|kind| {
+                        matches!(
+                            kind,
+                            Callable::Function {
+                                returns_value: true
+                            }
+                        )
+                    });
+                    let callable = match first {
+                        Callable::Generator => Callable::Generator,
+                        Callable::Function { .. } => Callable::Function { returns_value },
+                    };
+                    (name, callable)
+                })
+        })
+        .collect()
+}
+
+/// The name `expr` calls, if it is a direct call `NAME(...)`
+pub fn callee(expr: &Expr) -> Option<&str> {
+    let ExprKind::Call { func, .. } = &expr.kind else {
+        return None;
+    };
+    match &func.kind {
+        ExprKind::Identifier(name) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+/// The generator `expr` calls, if it is a call to a name that certainly is one
+pub fn generator_call<'a>(expr: &'a Expr, callables: &HashMap<&str, Callable>) -> Option<&'a str> {
+    callee(expr).filter(|name| callables.get(name) == Some(&Callable::Generator))
+}
+
+#[cfg(feature = "server")]
+/// The Generator named at `position` when it is the callee of a call
+pub fn generator_called_at(program: &Program, position: Position) -> Option<&str> {
+    let path = ast::node_at_position(program, position)?;
+    let call = path.nodes.iter().rev().find_map(|node| match node {
+        Node::Expr(
+            expr @ Expr {
+                kind: ExprKind::Call { func, .. },
+                ..
+            },
+        ) if span_contains(func.span, position) => Some(*expr),
+        _ => None,
+    })?;
+    generator_call(call, &callables(program))
+}
+
+#[derive(Default)]
+struct Collector<'a> {
+    definitions: HashMap<&'a str, Vec<Callable>>,
+    /// Names also bound some other way, so their calls can't be resolved
+    rebound: HashSet<&'a str>,
+}
+
+impl<'a> Collector<'a> {
+    fn rebind(&mut self, name: &'a Ident) {
+        self.rebound.insert(name.as_str());
+    }
+
+    fn rebind_all(&mut self, names: &'a [Ident]) {
+        for name in names {
+            self.rebind(name);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Collector<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::FuncDef { name, params, body } => {
+                let callable = Callable::Function {
+                    returns_value: returns_value(body),
+                };
+                self.definitions
+                    .entry(name.as_str())
+                    .or_default()
+                    .push(callable);
+                self.rebind_all(params);
+            }
+            StmtKind::GeneratorDef { name, params, .. } => {
+                self.definitions
+                    .entry(name.as_str())
+                    .or_default()
+                    .push(Callable::Generator);
+                self.rebind_all(params);
+            }
+            StmtKind::Set { name, .. } | StmtKind::LazyDef { name, .. } => self.rebind(name),
+            StmtKind::For { var, .. } => self.rebind(var),
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.rebind(index_var);
+                self.rebind(value_var);
+            }
+            StmtKind::Import { names, aliases, .. } => {
+                for (name, alias) in names.iter().zip(aliases) {
+                    self.rebind(alias.as_ref().unwrap_or(name));
+                }
+            }
+            StmtKind::Try {
+                error_var: Some(error_var),
+                ..
+            } => self.rebind(error_var),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { params, .. } = &expr.kind {
+            self.rebind_all(params);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Whether a `Return` in `body`, outside nested definitions, gives a value
+fn returns_value(body: &[Stmt]) -> bool {
+    struct Finder(bool);
+
+    impl<'a> Visitor<'a> for Finder {
+        fn visit_stmt(&mut self, stmt: &'a Stmt) {
+            match &stmt.kind {
+                StmtKind::Return(expr) if !matches!(expr.kind, ExprKind::Null) => self.0 = true,
+                StmtKind::FuncDef { .. } | StmtKind::GeneratorDef { .. } => {}
+                _ => walk_stmt(self, stmt),
+            }
+        }
+
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            if !matches!(expr.kind, ExprKind::Lambda { .. }) {
+                walk_expr(self, expr);
+            }
+        }
+    }
+
+    let mut finder = Finder(false);
+    finder.visit_block(body);
+    finder.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_only_certain_definitions_resolve() {
+        let text = "Generator COUNT_UP(N) {\n    Yield N\n}\n\
+                    Func LOG(X) {\n    PRINTLN(X)\n    Return\n}\n\
+                    Func TWICE(X) {\n    Set F Lambda Y -> Y\n    Return X * 2\n}\n\
+                    Func EITHER() {\n    Return 1\n}\nGenerator EITHER() {\n    Yield 1\n}\n\
+                    Func REBOUND() {\n    Return\n}\nSet REBOUND 1\n\
+                    Func OUTER(N) {\n    Func INNER() {\n        Return N\n    }\n}";
+        let doc = Parser::new(text).parse();
+        let callables = callables(&doc.ast);
+        assert_eq!(callables.get("COUNT_UP"), Some(&Callable::Generator));
+        assert_eq!(
+            callables.get("LOG"),
+            Some(&Callable::Function {
+                returns_value: false
+            })
+        );
+        assert_eq!(
+            callables.get("TWICE"),
+            Some(&Callable::Function {
+                returns_value: true
+            })
+        );
+        // Only `INNER`'s `Return` gives a value
+        assert_eq!(
+            callables.get("OUTER"),
+            Some(&Callable::Function {
+                returns_value: false
+            })
+        );
+        assert_eq!(callables.get("EITHER"), None);
+        assert_eq!(callables.get("REBOUND"), None);
+        assert_eq!(callables.get("N"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_generator_called_at() {
+        let doc =
+            Parser::new("Generator EACH(N) {\n    Yield N\n}\nSet G EACH(LENGTH(EACH(1)))").parse();
+        let at = |character| generator_called_at(&doc.ast, Position::new(3, character));
+        assert_eq!(at(7), Some("EACH"));
+        assert_eq!(at(19), Some("EACH"));
+        // The arguments, and a builtin called in them
+        assert_eq!(at(12), None);
+        assert_eq!(at(25), None);
+    }
+}
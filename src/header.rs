@@ -0,0 +1,141 @@
+//! Header directives: `//! key: value` lines at the top of a script
+//!
+//! ```text
+//! //! name: deploy
+//! //! requires: 1.2
+//! ```
+//!
+//! They describe the script to tools rather than to the interpreter, which
+//! reads them as comments. Only the lines before the first statement form the
+//! header; one further down is a comment that looks like a directive.
+
+#[cfg(feature = "server")]
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+
+use crate::ast::{Comment, Span};
+#[cfg(feature = "server")]
+use crate::locale::Locale;
+
+/// The keys tools know what to do with
+pub const KNOWN_KEYS: &[&str] = &["name", "description", "author", "version", "requires"];
+
+/// One `//!` line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub key: String,
+    /// Empty when the line has no `:`
+    pub value: String,
+    /// The whole comment
+    pub span: Span,
+    /// Before the first statement, so part of the header
+    pub leading: bool,
+}
+
+impl Directive {
+    pub fn is_known(&self) -> bool {
+        KNOWN_KEYS.contains(&self.key.as_str())
+    }
+}
+
+/// The `//!` line comments in `comments`; those starting before `code_start`,
+/// where the first statement begins, are leading
+pub fn directives(comments: &[Comment], code_start: Option<Span>) -> Vec<Directive> {
+    comments
+        .iter()
+        .filter(|comment| !comment.block)
+        .filter_map(|comment| {
+            let line = comment.text.strip_prefix("//!")?;
+            let (key, value) = line.split_once(':').unwrap_or((line, ""));
+            Some(Directive {
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+                span: comment.span,
+                leading: code_start.is_none_or(|code| comment.span.start < code.start),
+            })
+        })
+        .collect()
+}
+
+/// The header as key/value pairs, in source order
+pub fn metadata(directives: &[Directive]) -> Vec<(String, String)> {
+    directives
+        .iter()
+        .filter(|directive| directive.leading)
+        .map(|directive| (directive.key.clone(), directive.value.clone()))
+        .collect()
+}
+
+/// Whether `requires` names a version: one to three dot-separated numbers
+pub fn valid_version(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('.').collect();
+    parts.len() <= 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(feature = "server")]
+/// The header as a hover: a table of its directives
+pub fn header_hover(metadata: &[(String, String)], locale: Locale) -> Option<Hover> {
+    if metadata.is_empty() {
+        return None;
+    }
+    let mut value = format!(
+        "**{}**\n\n| | |\n|---|---|\n",
+        locale.pick("脚本头", "Script header")
+    );
+    for (key, entry) in metadata {
+        value.push_str(&format!("| `{}` | {} |\n", key, entry.replace('|', "\\|")));
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_leading_directives_form_the_metadata() {
+        let doc = Parser::new(
+            "//! name: deploy\n// a plain comment\n//! requires: 1.2\n//!bare\n\nSet A 1\n//! version: 2\n/*! not a directive */",
+        )
+        .parse();
+        assert_eq!(
+            doc.metadata,
+            vec![
+                ("name".to_string(), "deploy".to_string()),
+                ("requires".to_string(), "1.2".to_string()),
+                ("bare".to_string(), String::new()),
+            ]
+        );
+
+        let directives = doc.directives();
+        assert_eq!(directives.len(), 4);
+        assert!(!directives[3].leading);
+        assert_eq!(directives[3].span.start.line, 6);
+    }
+
+    #[test]
+    fn test_only_comments_is_all_header() {
+        let doc = Parser::new("//! name: empty\n//! author: me").parse();
+        assert_eq!(doc.metadata.len(), 2);
+    }
+
+    #[test]
+    fn test_valid_version() {
+        assert!(valid_version("1"));
+        assert!(valid_version("1.2.10"));
+        assert!(!valid_version(""));
+        assert!(!valid_version("1.x"));
+        assert!(!valid_version(">=1.2"));
+        assert!(!valid_version("1.2.3.4"));
+        assert!(!valid_version("1..2"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Files left out of the workspace index
+//!
+//! Generated and vendored `.ae` files would otherwise fill workspace symbols,
+//! import-path completion and auto-import suggestions. Each workspace root can
+//! list patterns in a `.aetherignore` file, one per line, and the
+//! `index.exclude` setting adds more. They are globs in the manner of
+//! `.gitignore`:
+//!
+//! - a pattern without a `/` matches a file or directory name anywhere
+//! - one with a `/` is relative to the root, and covers everything below it
+//! - a trailing `/` only matches directories
+//! - `*` and `?` match within one name, `**` any number of directories
+//! - blank lines and lines starting with `#` are skipped
+
+use std::fs;
+use std::path::{Component, Path};
+
+/// The file at a workspace root listing its patterns
+pub const IGNORE_FILE: &str = ".aetherignore";
+
+/// Compiled patterns of one workspace root
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    segments: Vec<Segment>,
+    dir_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// `**`
+    AnyDirs,
+    Glob(Vec<char>),
+}
+
+impl IgnoreRules {
+    /// Compile `patterns`, skipping blank and comment lines
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        IgnoreRules {
+            patterns: patterns.into_iter().filter_map(Pattern::compile).collect(),
+        }
+    }
+
+    /// The patterns of `root`'s ignore file, if it has one, followed by `exclude`
+    pub fn load(root: &Path, exclude: &[String]) -> Self {
+        let file = fs::read_to_string(root.join(IGNORE_FILE)).unwrap_or_default();
+        IgnoreRules::new(file.lines().chain(exclude.iter().map(String::as_str)))
+    }
+
+    /// Whether `relative`, a path below the root, or anything above it is ignored
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        let names: Vec<String> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        self.patterns.iter().any(|pattern| {
+            // The path itself, or one of the directories it is in
+            (1..=names.len()).any(|len| {
+                (!pattern.dir_only || len < names.len() || is_dir)
+                    && matches_path(&pattern.segments, &names[..len])
+            })
+        })
+    }
+}
+
+impl Pattern {
+    fn compile(line: &str) -> Option<Pattern> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+
+        let mut segments = Vec::new();
+        if !anchored {
+            segments.push(Segment::AnyDirs);
+        }
+        segments.extend(
+            line.split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(|segment| match segment {
+                    "**" => Segment::AnyDirs,
+                    glob => Segment::Glob(glob.chars().collect()),
+                }),
+        );
+        segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Glob(_)))
+            .then_some(Pattern { segments, dir_only })
+    }
+}
+
+fn matches_path(segments: &[Segment], names: &[String]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((Segment::AnyDirs, rest)) => {
+            matches_path(rest, names) || (!names.is_empty() && matches_path(segments, &names[1..]))
+        }
+        Some((Segment::Glob(glob), rest)) => names.split_first().is_some_and(|(name, others)| {
+            matches_name(glob, &name.chars().collect::<Vec<_>>()) && matches_path(rest, others)
+        }),
+    }
+}
+
+fn matches_name(glob: &[char], name: &[char]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((ch, rest)) => name.first() == Some(ch) && matches_name(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(patterns: &[&str], path: &str) -> bool {
+        IgnoreRules::new(patterns.iter().copied()).is_ignored(Path::new(path), false)
+    }
+
+    #[test]
+    fn test_names_and_paths() {
+        // A name matches at any depth, along with everything inside it
+        assert!(ignored(&["generated"], "generated/out.ae"));
+        assert!(ignored(&["generated"], "app/generated/deep/out.ae"));
+        assert!(!ignored(&["generated"], "app/generated_by_hand.ae"));
+        // A path is relative to the root
+        assert!(ignored(&["vendor/examples"], "vendor/examples/demo.ae"));
+        assert!(ignored(&["/vendor/examples"], "vendor/examples/demo.ae"));
+        assert!(!ignored(
+            &["vendor/examples"],
+            "app/vendor/examples/demo.ae"
+        ));
+        assert!(!ignored(&["# generated", "", "   "], "generated/out.ae"));
+    }
+
+    #[test]
+    fn test_globs() {
+        assert!(ignored(&["*.gen.ae"], "src/parser.gen.ae"));
+        assert!(!ignored(&["*.gen.ae"], "src/parser.ae"));
+        assert!(ignored(&["build-?"], "build-1/main.ae"));
+        assert!(ignored(&["**/fixtures/*.ae"], "tests/unit/fixtures/a.ae"));
+        assert!(ignored(&["examples/**/old"], "examples/a/b/old/x.ae"));
+        assert!(ignored(&["examples/**/old"], "examples/old/x.ae"));
+        assert!(!ignored(&["src/*.ae"], "src/nested/x.ae"));
+    }
+
+    #[test]
+    fn test_directory_patterns() {
+        let rules = IgnoreRules::new(["out/"]);
+        assert!(rules.is_ignored(Path::new("out/main.ae"), false));
+        assert!(rules.is_ignored(Path::new("out"), true));
+        assert!(!rules.is_ignored(Path::new("out"), false));
+        assert_eq!(
+            IgnoreRules::new(["# nothing", "**"]),
+            IgnoreRules::default()
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Documentation for Aether keywords, shared by completion and hover
+
+use crate::locale::Locale;
+
+pub struct KeywordDoc {
+    pub keyword: &'static str,
+    /// One-line summary, used as the completion detail
+    pub summary: &'static str,
+    pub summary_en: &'static str,
+    pub details: &'static str,
+    pub details_en: &'static str,
+    pub example: &'static str,
+    /// What completion inserts, as an LSP snippet; `None` inserts the keyword
+    pub snippet: Option<&'static str>,
+}
+
+impl KeywordDoc {
+    pub fn summary(&self, locale: Locale) -> &'static str {
+        locale.pick(self.summary, self.summary_en)
+    }
+
+    /// Markdown shown in hover and completion documentation
+    pub fn markdown(&self, locale: Locale) -> String {
+        format!(
+            "**{}** — {}\n\n{}\n\n```aether\n{}\n```",
+            self.keyword,
+            self.summary(locale),
+            locale.pick(self.details, self.details_en),
+            self.example
+        )
+    }
+}
+
+/// Keywords are case-sensitive, matching the lexer
+pub fn find_keyword(word: &str) -> Option<&'static KeywordDoc> {
+    KEYWORDS.iter().find(|doc| doc.keyword == word)
+}
+
+pub const KEYWORDS: &[KeywordDoc] = &[
+    KeywordDoc {
+        keyword: "Set",
+        summary: "变量赋值",
+        summary_en: "Assign a variable",
+        details: "绑定或重新绑定一个变量，也可以给数组或字典的元素赋值。",
+        details_en: "Binds or rebinds a variable; also assigns to an array or dict element.",
+        example: "Set COUNT 0\nSet CONFIG[\"debug\"] True",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Func",
+        summary: "函数定义",
+        summary_en: "Define a function",
+        details: "定义一个具名函数，函数体中用 `Return` 返回结果。",
+        details_en: "Defines a named function; use `Return` to produce its result.",
+        example: "Func ADD(A, B) {\n    Return A + B\n}",
+        snippet: Some("Func ${1:NAME}(${2:PARAMS}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "Return",
+        summary: "返回值",
+        summary_en: "Return a value",
+        details: "结束当前函数并返回给定的值。",
+        details_en: "Ends the current function with the given value.",
+        example: "Return A + B",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "If",
+        summary: "条件判断",
+        summary_en: "Conditional",
+        details: "条件为真时执行代码块，可接 `Elif` 与 `Else` 分支。",
+        details_en: "Runs a block when the condition holds; may be followed by `Elif` and `Else`.",
+        example: "If (X > 0) {\n    PRINTLN(\"positive\")\n}",
+        snippet: Some("If (${1:CONDITION}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "Elif",
+        summary: "否则如果",
+        summary_en: "Else-if branch",
+        details: "前面的条件都不成立时检查另一个条件。",
+        details_en: "Checks another condition when the previous ones failed.",
+        example: "If (X > 0) {\n    PRINTLN(\"positive\")\n} Elif (X < 0) {\n    PRINTLN(\"negative\")\n}",
+        snippet: Some("Elif (${1:CONDITION}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "Else",
+        summary: "否则",
+        summary_en: "Otherwise branch",
+        details: "所有条件都不成立时执行。",
+        details_en: "Runs when no condition of the `If` chain held.",
+        example: "If (X > 0) {\n    PRINTLN(\"positive\")\n} Else {\n    PRINTLN(\"not positive\")\n}",
+        snippet: Some("Else {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "While",
+        summary: "循环",
+        summary_en: "Loop while a condition holds",
+        details: "每次迭代前检查条件，条件为假时退出循环。",
+        details_en: "Checks the condition before every iteration and stops once it is false.",
+        example: "While (I < 10) {\n    Set I (I + 1)\n}",
+        snippet: Some("While (${1:CONDITION}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "For",
+        summary: "遍历",
+        summary_en: "Iterate over a collection",
+        details: "依次绑定集合中的每个元素；`For I, X In ...` 同时提供下标。",
+        details_en: "Binds each element of a collection in turn; `For I, X In ...` also binds the index.",
+        example: "For X In [1, 2, 3] {\n    PRINTLN(X)\n}",
+        snippet: Some("For ${1:ITEM} In ${2:ITEMS} {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "In",
+        summary: "循环关键字",
+        summary_en: "Loop keyword",
+        details: "在 `For` 循环中分隔循环变量与被遍历的集合。",
+        details_en: "Separates the loop variable from the collection in a `For` loop.",
+        example: "For X In [1, 2, 3] {\n    PRINTLN(X)\n}",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Break",
+        summary: "跳出循环",
+        summary_en: "Exit the loop",
+        details: "立即结束最内层的循环。",
+        details_en: "Ends the innermost loop immediately.",
+        example: "While (True) {\n    Break\n}",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Continue",
+        summary: "继续下一次循环",
+        summary_en: "Skip to the next iteration",
+        details: "跳过本次迭代剩余的语句。",
+        details_en: "Skips the rest of the current iteration.",
+        example: "For X In ITEMS {\n    If (X == Null) {\n        Continue\n    }\n}",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Generator",
+        summary: "生成器定义",
+        summary_en: "Define a generator",
+        details: "定义一个生成器函数，用 `Yield` 逐个产出值，调用时返回可遍历的序列。",
+        details_en: "Defines a generator that produces values one at a time with `Yield`; calling it returns an iterable sequence.",
+        example: "Generator COUNT_UP(N) {\n    Set I 0\n    While (I < N) {\n        Yield I\n        Set I (I + 1)\n    }\n}",
+        snippet: Some("Generator ${1:NAME}(${2:PARAMS}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "Yield",
+        summary: "生成值",
+        summary_en: "Produce a value",
+        details: "在生成器中产出下一个值并暂停执行。",
+        details_en: "Produces the next value of a generator and suspends it.",
+        example: "Yield I",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Lazy",
+        summary: "惰性求值",
+        summary_en: "Lazy evaluation",
+        details: "定义一个惰性值：表达式在第一次被 `Force` 时才求值。",
+        details_en: "Defines a lazy value whose expression is only evaluated when first forced with `Force`.",
+        example: "Lazy DATA(LOAD_DATA())",
+        snippet: Some("Lazy ${1:NAME}(${2:EXPR})"),
+    },
+    KeywordDoc {
+        keyword: "Force",
+        summary: "强制求值",
+        summary_en: "Force evaluation",
+        details: "立即计算惰性值并返回结果。",
+        details_en: "Evaluates a lazy value now and returns its result.",
+        example: "Set RESULT Force(DATA)",
+        snippet: Some("Force(${1:VALUE})"),
+    },
+    KeywordDoc {
+        keyword: "Switch",
+        summary: "分支",
+        summary_en: "Branch on a value",
+        details: "将值与每个 `Case` 比较，执行第一个匹配的分支，都不匹配时执行 `Default`。",
+        details_en: "Compares a value against each `Case` and runs the first match, or `Default` if none match.",
+        example: "Switch (X) {\n    Case 1:\n        PRINTLN(\"one\")\n    Default:\n        PRINTLN(\"other\")\n}",
+        snippet: Some("Switch (${1:VALUE}) {\n\tCase ${2:1}:\n\t\t$0\n\tDefault:\n\t\t\n}"),
+    },
+    KeywordDoc {
+        keyword: "Case",
+        summary: "分支情况",
+        summary_en: "Switch case",
+        details: "`Switch` 中的一个分支。",
+        details_en: "One branch of a `Switch`.",
+        example: "Case 1:\n    PRINTLN(\"one\")",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Default",
+        summary: "默认分支",
+        summary_en: "Default branch",
+        details: "没有 `Case` 匹配时执行的分支。",
+        details_en: "The branch taken when no `Case` matches.",
+        example: "Default:\n    PRINTLN(\"other\")",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Import",
+        summary: "导入模块",
+        summary_en: "Import from a module",
+        details: "从另一个模块导入导出的符号。",
+        details_en: "Brings exported symbols of another module into scope.",
+        example: "Import {ADD, SUB} From \"./math\"",
+        snippet: Some("Import {${1:NAME}} From \"${2:./module}\""),
+    },
+    KeywordDoc {
+        keyword: "Export",
+        summary: "导出符号",
+        summary_en: "Export a symbol",
+        details: "让其他模块可以导入该符号。",
+        details_en: "Makes a symbol importable by other modules.",
+        example: "Export ADD",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "From",
+        summary: "导入来源",
+        summary_en: "Import source",
+        details: "指定 `Import` 的模块路径。",
+        details_en: "Names the module path of an `Import`.",
+        example: "Import ADD From \"./math\"",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "As",
+        summary: "别名",
+        summary_en: "Alias",
+        details: "以另一个名字导入符号。",
+        details_en: "Imports a symbol under another name.",
+        example: "Import ADD As PLUS From \"./math\"",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Lambda",
+        summary: "匿名函数",
+        summary_en: "Anonymous function",
+        details: "创建匿名函数，可写成箭头形式或带代码块的形式。",
+        details_en: "Creates an anonymous function, either in arrow form or with a block body.",
+        example: "Set DOUBLED MAP(NUMBERS, Lambda X -> X * 2)",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Throw",
+        summary: "抛出错误",
+        summary_en: "Raise an error",
+        details: "抛出一个值作为错误，终止当前执行。",
+        details_en: "Raises a value as an error, aborting the current execution.",
+        example: "Throw \"invalid input\"",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Try",
+        summary: "捕获错误",
+        summary_en: "Catch errors",
+        details: "执行代码块，其中抛出的错误交给 `Catch` 块处理。",
+        details_en: "Runs a block and hands any error thrown in it to the `Catch` block.",
+        example: "Try {\n    Set RESULT DIVIDE(10, 0)\n} Catch (ERROR) {\n    PRINTLN(ERROR)\n}",
+        snippet: Some("Try {\n\t$0\n} Catch (${1:ERR}) {\n\t\n}"),
+    },
+    KeywordDoc {
+        keyword: "Catch",
+        summary: "错误处理",
+        summary_en: "Handle an error",
+        details: "`Try` 块抛出错误时执行，括号中的变量绑定被抛出的值。",
+        details_en: "Runs when the `Try` block throws; the variable in parentheses is bound to the thrown value.",
+        example: "Try {\n    RISKY()\n} Catch (ERROR) {\n    PRINTLN(ERROR)\n}",
+        snippet: Some("Catch (${1:ERR}) {\n\t$0\n}"),
+    },
+    KeywordDoc {
+        keyword: "True",
+        summary: "布尔真",
+        summary_en: "Boolean true",
+        details: "布尔字面量。",
+        details_en: "The boolean literal true.",
+        example: "Set ENABLED True",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "False",
+        summary: "布尔假",
+        summary_en: "Boolean false",
+        details: "布尔字面量。",
+        details_en: "The boolean literal false.",
+        example: "Set ENABLED False",
+        snippet: None,
+    },
+    KeywordDoc {
+        keyword: "Null",
+        summary: "空值",
+        summary_en: "Null value",
+        details: "表示没有值。",
+        details_en: "Represents the absence of a value.",
+        example: "Set RESULT Null",
+        snippet: None,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{self, Token};
+
+    #[test]
+    fn test_every_keyword_is_lexed_as_a_keyword() {
+        for doc in KEYWORDS {
+            assert!(
+                Token::keyword(doc.keyword).is_some(),
+                "{} is not a keyword",
+                doc.keyword
+            );
+        }
+        assert!(find_keyword("lazy").is_none());
+    }
+
+    #[test]
+    fn test_every_lexer_keyword_is_documented() {
+        let documented: Vec<&str> = KEYWORDS.iter().map(|doc| doc.keyword).collect();
+        let lexed: Vec<&str> = token::KEYWORDS
+            .iter()
+            .map(|(keyword, _)| *keyword)
+            .collect();
+        assert_eq!(documented, lexed);
+        for (keyword, token) in token::KEYWORDS {
+            assert_eq!(Token::keyword(keyword).as_ref(), Some(token), "{}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_snippets_are_well_formed() {
+        for doc in KEYWORDS {
+            let Some(snippet) = doc.snippet else {
+                continue;
+            };
+            assert!(snippet.starts_with(doc.keyword), "{}", snippet);
+            assert!(
+                snippet.contains("$0") || snippet.contains("${1:"),
+                "{}",
+                snippet
+            );
+            assert_eq!(
+                snippet.matches('{').count(),
+                snippet.matches('}').count(),
+                "{}",
+                snippet
+            );
+        }
+        assert_eq!(
+            find_keyword("Try").unwrap().snippet,
+            Some("Try {\n\t$0\n} Catch (${1:ERR}) {\n\t\n}")
+        );
+    }
+}
@@ -2,7 +2,19 @@
 //!
 //! Converts source code into a stream of tokens
 
+use crate::ast::{Comment, Span};
+use crate::intern::Interner;
 use crate::token::Token;
+use lsp_types::Position;
+
+/// A string literal the input ends inside
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnterminatedString {
+    /// Its opening quotes
+    pub opening: Span,
+    /// Opened with `"""` rather than `"`
+    pub multiline: bool,
+}
 
 /// Lexer state
 pub struct Lexer {
@@ -13,30 +25,70 @@ pub struct Lexer {
     line: usize,          // current line number (for error reporting)
     column: usize,        // current column number (for error reporting)
     had_whitespace_before_token: bool, // whether whitespace was skipped before current token
+    token_start: usize,   // offset of the first char of the last token
+    token_end: usize,     // offset just past the last token
+    line_starts: Vec<usize>, // offset of the first char of every line
+    utf16_before: Option<Vec<usize>>, // UTF-16 units before each offset, if needed
+    comments: Vec<Comment>, // comments skipped so far
+    strings: Vec<Span>,   // spans of the terminated string literals read so far
+    unterminated_string: Option<UnterminatedString>, // string literal the input ends inside
+    interner: Interner,   // one shared allocation per identifier name
+    scratch: String,      // identifier text being read, reused between identifiers
 }
 
 impl Lexer {
     /// Create a new lexer from input string
     pub fn new(input: &str) -> Self {
+        let input: Vec<char> = input.chars().collect();
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            input
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(i, _)| i + 1),
+        );
+        // Columns are counted in UTF-16 units, which only differ from chars
+        // once a char outside the BMP shows up
+        let utf16_before = input.iter().any(|c| c.len_utf16() > 1).then(|| {
+            std::iter::once(0)
+                .chain(input.iter().scan(0, |units, c| {
+                    *units += c.len_utf16();
+                    Some(*units)
+                }))
+                .collect()
+        });
+
         let mut lexer = Lexer {
-            input: input.chars().collect(),
+            input,
             position: 0,
             read_position: 0,
             ch: '\0',
             line: 1,
             column: 0,
             had_whitespace_before_token: false,
+            token_start: 0,
+            token_end: 0,
+            line_starts,
+            utf16_before,
+            comments: Vec::new(),
+            strings: Vec::new(),
+            unterminated_string: None,
+            interner: Interner::default(),
+            scratch: String::new(),
         };
         lexer.read_char(); // Initialize by reading the first character
         lexer
     }
 
     /// Get current line number
+    #[cfg(test)]
     pub fn line(&self) -> usize {
         self.line
     }
 
     /// Get current column number
+    #[cfg(test)]
     pub fn column(&self) -> usize {
         self.column
     }
@@ -46,6 +98,62 @@ impl Lexer {
         self.had_whitespace_before_token
     }
 
+    /// Get the source span of the last token returned by `next_token`
+    pub fn token_span(&self) -> Span {
+        Span {
+            start: self.offset_to_position(self.token_start),
+            end: self.offset_to_position(self.token_end),
+        }
+    }
+
+    /// Take the comments skipped so far, in source order
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    /// Take the spans of the string literals read so far, quotes included, in
+    /// source order; an unterminated string is not one
+    pub fn take_strings(&mut self) -> Vec<Span> {
+        std::mem::take(&mut self.strings)
+    }
+
+    /// The string literal the input ends inside, if any
+    pub fn unterminated_string(&self) -> Option<UnterminatedString> {
+        self.unterminated_string
+    }
+
+    fn record_comment(&mut self, start: usize, block: bool) {
+        let end = self.position.min(self.input.len());
+        let span_start = self.offset_to_position(start);
+        let line_start = self.line_starts[span_start.line as usize];
+        self.comments.push(Comment {
+            text: self.input[start..end].iter().collect(),
+            span: Span {
+                start: span_start,
+                end: self.offset_to_position(end),
+            },
+            block,
+            trailing: self.input[line_start..start]
+                .iter()
+                .any(|ch| !ch.is_whitespace()),
+        });
+    }
+
+    /// Convert a char offset into a zero-based line/character position, the
+    /// character counted in UTF-16 units as LSP expects
+    fn offset_to_position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = match &self.utf16_before {
+            Some(units) => units[offset] - units[start],
+            None => offset - start,
+        };
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
     /// Read the next character and advance position
     fn read_char(&mut self) {
         if self.read_position >= self.input.len() {
@@ -89,7 +197,18 @@ impl Lexer {
     pub fn next_token(&mut self) -> Token {
         let had_ws = self.skip_whitespace();
         self.had_whitespace_before_token = had_ws;
+        self.token_start = self.position.min(self.input.len());
+
+        let token = self.read_token();
+        self.token_end = self.position.min(self.input.len());
+        if let Token::String(_) = token {
+            self.strings.push(self.token_span());
+        }
+        token
+    }
 
+    /// Read the token starting at the current character
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
             // Operators
             '+' => Token::Plus,
@@ -174,6 +293,7 @@ impl Lexer {
             '[' => Token::LeftBracket,
             ']' => Token::RightBracket,
             ',' => Token::Comma,
+            '.' => Token::Dot,
             ':' => Token::Colon,
             ';' => Token::Semicolon,
 
@@ -222,21 +342,20 @@ impl Lexer {
 
     /// Skip single-line comment (// ...)
     fn skip_line_comment(&mut self) {
+        let start = self.position;
         while self.ch != '\n' && self.ch != '\0' {
             self.read_char();
         }
+        self.record_comment(start, false);
     }
 
     /// Skip block comment (/* ... */)
     fn skip_block_comment(&mut self) {
+        let start = self.position;
         self.read_char(); // skip '/'
         self.read_char(); // skip '*'
 
         while !(self.ch == '*' && self.peek_char() == '/') && self.ch != '\0' {
-            if self.ch == '\n' {
-                self.line += 1;
-                self.column = 0;
-            }
             self.read_char();
         }
 
@@ -244,6 +363,7 @@ impl Lexer {
             self.read_char(); // skip '*'
             self.read_char(); // skip '/'
         }
+        self.record_comment(start, true);
     }
 
     /// Read an identifier or keyword
@@ -255,8 +375,10 @@ impl Lexer {
             self.read_char();
         }
 
-        let ident: String = self.input[start..self.position].iter().collect();
-        Token::lookup_keyword(&ident)
+        self.scratch.clear();
+        self.scratch.extend(&self.input[start..self.position]);
+        Token::keyword(&self.scratch)
+            .unwrap_or_else(|| Token::Identifier(self.interner.intern(&self.scratch)))
     }
 
     /// Read a number (integer or float)
@@ -277,8 +399,8 @@ impl Lexer {
 
         let num_str: String = self.input[start..self.position].iter().collect();
 
-        // 如果是整数且位数较多（超过15位,接近f64精度极限),作为大整数处理
-        if !has_dot && num_str.len() > 15 {
+        // 超过 2^53 的整数无法用 f64 精确表示,作为大整数处理
+        if !has_dot && exceeds_safe_integer(&num_str) {
             return Token::BigInteger(num_str);
         }
 
@@ -301,15 +423,12 @@ impl Lexer {
                     self.read_char(); // Skip escaped character
                 }
             } else {
-                if self.ch == '\n' {
-                    self.line += 1;
-                    self.column = 0;
-                }
                 self.read_char();
             }
         }
 
         if self.ch == '\0' {
+            self.record_unterminated_string(start - 1, false);
             return Token::Illegal('"'); // Unterminated string
         }
 
@@ -322,6 +441,7 @@ impl Lexer {
 
     /// Read a multiline string literal (""" ... """)
     fn read_multiline_string(&mut self) -> Token {
+        let opening = self.position;
         // Skip the opening """
         self.read_char(); // Skip first "
         self.read_char(); // Skip second "
@@ -332,6 +452,7 @@ impl Lexer {
         // Read until we find closing """
         loop {
             if self.ch == '\0' {
+                self.record_unterminated_string(opening, true);
                 return Token::Illegal('"'); // Unterminated multiline string
             }
 
@@ -348,16 +469,21 @@ impl Lexer {
                 return Token::String(self.process_escapes(&string));
             }
 
-            // Handle newlines for line tracking
-            if self.ch == '\n' {
-                self.line += 1;
-                self.column = 0;
-            }
-
             self.read_char();
         }
     }
 
+    fn record_unterminated_string(&mut self, opening: usize, multiline: bool) {
+        let quotes = if multiline { 3 } else { 1 };
+        self.unterminated_string = Some(UnterminatedString {
+            opening: Span {
+                start: self.offset_to_position(opening),
+                end: self.offset_to_position(opening + quotes),
+            },
+            multiline,
+        });
+    }
+
     /// Process escape sequences in strings
     fn process_escapes(&self, s: &str) -> String {
         let mut result = String::new();
@@ -385,3 +511,80 @@ impl Lexer {
         result
     }
 }
+
+/// 2^53, the largest integer below which every integer is exact in an f64
+const MAX_SAFE_INTEGER: &str = "9007199254740992";
+
+/// Whether the decimal digits `digits` spell an integer greater than 2^53
+fn exceeds_safe_integer(digits: &str) -> bool {
+    let significant = digits.trim_start_matches('0');
+    // Longer means larger; at equal length the digits compare like the values
+    (significant.len(), significant) > (MAX_SAFE_INTEGER.len(), MAX_SAFE_INTEGER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(text: &str) -> Token {
+        Lexer::new(text).next_token()
+    }
+
+    #[test]
+    fn test_newlines_in_strings_and_comments_count_once() {
+        let mut lexer = Lexer::new("/* a\nb */ \"\"\"c\nd\"\"\" \"e\nf\" X");
+        // Stops on `X`, with the lexer on the character after it
+        while !matches!(lexer.next_token(), Token::Identifier(_)) {}
+        assert_eq!((lexer.line(), lexer.column()), (4, 5));
+        assert_eq!(lexer.unterminated_string(), None);
+
+        let mut lexer = Lexer::new("X\n  \"\"\"open\n");
+        while lexer.next_token() != Token::EOF {}
+        let unterminated = lexer.unterminated_string().unwrap();
+        assert!(unterminated.multiline);
+        assert_eq!(
+            (unterminated.opening.start, unterminated.opening.end),
+            (Position::new(1, 2), Position::new(1, 5))
+        );
+    }
+
+    #[test]
+    fn test_big_integers_start_past_two_to_the_53() {
+        assert_eq!(
+            number("9007199254740991"),
+            Token::Number(9007199254740991.0)
+        );
+        assert_eq!(
+            number("9007199254740992"),
+            Token::Number(9007199254740992.0)
+        );
+        assert_eq!(
+            number("9007199254740993"),
+            Token::BigInteger("9007199254740993".to_string())
+        );
+        assert_eq!(
+            number("10000000000000000"),
+            Token::BigInteger("10000000000000000".to_string())
+        );
+        // Sixteen digits that fit were once promoted by length alone
+        assert_eq!(
+            number("1234567890123456"),
+            Token::Number(1234567890123456.0)
+        );
+        // A fractional literal is always a Number, however long
+        assert_eq!(
+            number("9007199254740993.0"),
+            Token::Number(9007199254740992.0)
+        );
+    }
+
+    #[test]
+    fn test_leading_zeros_do_not_make_a_big_integer() {
+        assert_eq!(number("00000000000000000042"), Token::Number(42.0));
+        assert_eq!(number("0000000000000000"), Token::Number(0.0));
+        assert_eq!(
+            number("0009007199254740993"),
+            Token::BigInteger("0009007199254740993".to_string())
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Analysis of the Aether language, and the language server built on it
+//!
+//! [`analyze`] parses a text and checks it in one call:
+//!
+//! ```
+//! let analysis = aether_lsp::analyze("Func ADD(A, B) {\n    Return A + B\n}\nSet TOTAL ADD(1, 2)\nPRINTLN(TOTAL)");
+//! assert!(analysis.diagnostics.is_empty(), "{:?}", analysis.diagnostics);
+//! assert_eq!(analysis.ast.len(), 3);
+//! assert!(analysis.symbols.find_by_name("TOTAL").is_some());
+//! ```
+//!
+//! Parse errors and lints come back together as LSP diagnostics, so they can
+//! be reported the way an editor would show them:
+//!
+//! ```
+//! use aether_lsp::lsp_types::Position;
+//!
+//! let analysis = aether_lsp::analyze("Set TOTAL (1 +\nPRINTLN(MISSING)");
+//! let first = &analysis.diagnostics[0];
+//! assert_eq!(first.source.as_deref(), Some("aether-parser"));
+//! assert_eq!(first.range.start, Position::new(0, 14));
+//! ```
+//!
+//! The modules behind the facade are public for finer control, e.g. to lex a
+//! text or parse it without running the lints. The language server itself is
+//! behind the default `server` feature; without it the crate pulls in neither
+//! tokio nor tower-lsp.
+
+mod array_bounds;
+pub mod ast;
+pub mod builtins;
+mod call_hierarchy;
+mod config;
+mod confusables;
+mod diagnostic_codes;
+pub mod diagnostics;
+mod folding;
+mod format_template;
+mod generators;
+mod header;
+mod intern;
+pub mod lexer;
+mod line_index;
+pub mod locale;
+mod loops;
+mod mask;
+mod modules;
+mod occurrences;
+pub mod parser;
+mod printer;
+#[cfg(test)]
+mod robustness;
+mod suppression;
+pub mod symbols;
+#[cfg(test)]
+mod test_alloc;
+pub mod token;
+mod types;
+mod visit;
+
+#[cfg(feature = "server")]
+mod backend;
+#[cfg(feature = "server")]
+mod case_labels;
+#[cfg(feature = "server")]
+mod cells;
+#[cfg(feature = "server")]
+mod client_caps;
+#[cfg(feature = "server")]
+mod code_actions;
+#[cfg(feature = "server")]
+mod completion;
+#[cfg(feature = "server")]
+mod crash;
+#[cfg(feature = "server")]
+mod cross_file;
+#[cfg(feature = "server")]
+mod dict_key_rename;
+#[cfg(feature = "server")]
+mod doc_skeleton;
+#[cfg(feature = "server")]
+mod document_key;
+#[cfg(feature = "server")]
+mod extra_builtins;
+#[cfg(feature = "server")]
+mod ignore;
+#[cfg(feature = "server")]
+mod keywords;
+#[cfg(feature = "server")]
+mod literals;
+#[cfg(feature = "server")]
+mod logging;
+#[cfg(feature = "server")]
+mod perf;
+#[cfg(feature = "server")]
+mod request_error;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+mod set_runs;
+#[cfg(feature = "server")]
+mod signature_help;
+#[cfg(feature = "server")]
+mod snippets;
+#[cfg(feature = "server")]
+mod token_diff;
+#[cfg(feature = "server")]
+mod workspace;
+
+pub use diagnostics::AnalysisOptions;
+pub use intern::Name;
+pub use line_index::LineIndex;
+pub use lsp_types;
+
+use ast::Program;
+use diagnostics::DiagnosticEngine;
+use lsp_types::Diagnostic;
+use parser::Parser;
+use symbols::SymbolTable;
+
+/// What [`analyze`] found in a text
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The statements that parsed; a statement with a syntax error is skipped
+    pub ast: Program,
+    pub symbols: SymbolTable,
+    /// Syntax errors first, then lints
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parse and check `text` with the default options: messages in the default
+/// locale, and imports left unresolved since the text has no location
+pub fn analyze(text: &str) -> Analysis {
+    analyze_with(text, &AnalysisOptions::default())
+}
+
+/// Parse and check `text`
+///
+/// ```
+/// use aether_lsp::{AnalysisOptions, locale::Locale};
+///
+/// let options = AnalysisOptions {
+///     locale: Locale::En,
+///     ..Default::default()
+/// };
+/// let analysis = aether_lsp::analyze_with("Set total 1\nPRINTLN(total)", &options);
+/// assert!(analysis.diagnostics[0].message.starts_with("Name 'total' should use UPPER_SNAKE_CASE"));
+/// ```
+pub fn analyze_with(text: &str, options: &AnalysisOptions) -> Analysis {
+    let parsed = Parser::new(text).parse();
+    let diagnostics = DiagnosticEngine::analyze(&parsed, options);
+    Analysis {
+        ast: parsed.ast,
+        symbols: parsed.symbols,
+        diagnostics,
+    }
+}
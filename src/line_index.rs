@@ -0,0 +1,140 @@
+//! Converting between byte offsets and LSP positions
+//!
+//! The text is UTF-8, but LSP positions count UTF-16 code units from the start
+//! of a line. A [`LineIndex`] records where each line starts once per document
+//! version, so a conversion only walks the line it lands on instead of
+//! splitting the whole text again.
+
+use lsp_types::Position;
+
+/// Where the lines of one text start; every method takes that same text
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+    /// Byte offset of the first character of each line, starting with 0
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(offset, _)| offset + 1));
+        LineIndex { line_starts }
+    }
+
+    /// Line `line` without its line break, or `None` past the last line. A text
+    /// ending in a line break has an empty last line after it.
+    pub fn line_text<'t>(&self, text: &'t str, line: u32) -> Option<&'t str> {
+        let start = *self.line_starts.get(line as usize)?;
+        let end = self
+            .line_starts
+            .get(line as usize + 1)
+            .map_or(text.len(), |next| next - 1);
+        let line = &text[start..end];
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+
+    /// Length of line `line` in UTF-16 code units; 0 past the last line
+    pub fn line_len(&self, text: &str, line: u32) -> u32 {
+        self.line_text(text, line).map_or(0, utf16_len)
+    }
+
+    /// The position of byte `offset`. An offset inside a character counts from
+    /// the start of the character, and one past the end is the end of the text.
+    pub fn offset_to_position(&self, text: &str, offset: usize) -> Position {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        Position::new(line as u32, utf16_len(&text[start..offset]))
+    }
+
+    /// The byte offset of `position`. A character past the end of its line is
+    /// the end of the line, and a line past the last is the end of the text.
+    pub fn position_to_offset(&self, text: &str, position: Position) -> usize {
+        let Some(line) = self.line_text(text, position.line) else {
+            return text.len();
+        };
+        let start = self.line_starts[position.line as usize];
+        let mut units = 0;
+        for (index, ch) in line.char_indices() {
+            if units >= position.character {
+                return start + index;
+            }
+            units += ch.len_utf16() as u32;
+        }
+        start + line.len()
+    }
+}
+
+/// The index of an empty text
+impl Default for LineIndex {
+    fn default() -> Self {
+        LineIndex::new("")
+    }
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(char::len_utf16).sum::<usize>() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_text_has_one_empty_line() {
+        let index = LineIndex::new("");
+        assert_eq!(index.line_text("", 0), Some(""));
+        assert_eq!(index.line_text("", 1), None);
+        assert_eq!(index.offset_to_position("", 0), Position::new(0, 0));
+        assert_eq!(index.offset_to_position("", 5), Position::new(0, 0));
+        assert_eq!(index.position_to_offset("", Position::new(3, 7)), 0);
+    }
+
+    #[test]
+    fn test_last_line_with_and_without_line_break() {
+        let text = "Set A 1\r\nSet B 2";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_text(text, 0), Some("Set A 1"));
+        assert_eq!(index.line_text(text, 1), Some("Set B 2"));
+        assert_eq!(index.line_text(text, 2), None);
+        assert_eq!(
+            index.offset_to_position(text, text.len()),
+            Position::new(1, 7)
+        );
+        // Past the end of a line stops before its line break
+        assert_eq!(index.position_to_offset(text, Position::new(0, 40)), 7);
+        assert_eq!(
+            index.position_to_offset(text, Position::new(5, 0)),
+            text.len()
+        );
+
+        let text = "Set A 1\n";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_text(text, 1), Some(""));
+        assert_eq!(index.offset_to_position(text, 8), Position::new(1, 0));
+        assert_eq!(index.position_to_offset(text, Position::new(1, 0)), 8);
+    }
+
+    #[test]
+    fn test_columns_count_utf16_units() {
+        // `名` is 3 bytes and 1 unit, `🙂` is 4 bytes and 2 units
+        let text = "// 名\nSet A \"🙂\" + B";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_len(text, 0), 4);
+        assert_eq!(index.line_len(text, 1), 14);
+
+        let b = text.rfind('B').unwrap();
+        assert_eq!(index.offset_to_position(text, b), Position::new(1, 13));
+        assert_eq!(index.position_to_offset(text, Position::new(1, 13)), b);
+        // Inside a character counts from its start
+        let emoji = text.find('🙂').unwrap();
+        assert_eq!(
+            index.offset_to_position(text, emoji + 2),
+            Position::new(1, 7)
+        );
+        assert_eq!(index.position_to_offset(text, Position::new(0, 4)), 6);
+    }
+}
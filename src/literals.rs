@@ -0,0 +1,230 @@
+//! Hover details for numeric literals and format templates
+
+use lsp_types::*;
+
+use crate::ast::{Expr, ExprKind, Node, NodePath};
+use crate::format_template::template_call;
+use crate::locale::Locale;
+use crate::symbols::SymbolTable;
+
+/// Integers up to this magnitude are exact in an f64
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Hover for a `Number` or `BigInteger` literal; `None` for any other expression
+pub fn literal_hover(expr: &Expr, locale: Locale) -> Option<Hover> {
+    let value = match &expr.kind {
+        ExprKind::Number(number) => number_details(*number, locale),
+        ExprKind::BigInteger(digits) => big_integer_details(digits, locale),
+        _ => return None,
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(expr.span),
+    })
+}
+
+/// Hover for the literal template of a `FORMAT`-style call at the end of `path`:
+/// how many placeholders it has and how many arguments fill them
+pub fn template_hover(path: &NodePath, symbols: &SymbolTable, locale: Locale) -> Option<Hover> {
+    let [.., Node::Expr(call), Node::Expr(template)] = path.nodes.as_slice() else {
+        return None;
+    };
+    let call = template_call(call)
+        .filter(|call| std::ptr::eq(call.template, *template))
+        .filter(|call| symbols.find_by_name(call.function).is_none())?;
+
+    let mut lines = vec![
+        format!(
+            "**{}** `{}`",
+            locale.pick("格式模板", "Format template"),
+            call.function
+        ),
+        format!(
+            "- {}: {}",
+            locale.pick("占位符", "Placeholders"),
+            call.placeholders
+        ),
+        format!("- {}: {}", locale.pick("参数", "Arguments"), call.arguments),
+    ];
+    if !call.matches() {
+        lines.push(format!(
+            "- {}",
+            locale.pick("数量不符", "The counts don't match")
+        ));
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: lines.join("\n"),
+        }),
+        range: Some(template.span),
+    })
+}
+
+fn number_details(number: f64, locale: Locale) -> String {
+    let mut lines = vec![format!(
+        "**{}** `{}`",
+        locale.pick("数字", "Number"),
+        number
+    )];
+
+    if number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER {
+        let integer = number as i64;
+        let sign = if integer < 0 { "-" } else { "" };
+        lines.push(format!(
+            "- {}: `{}0x{:X}`",
+            locale.pick("十六进制", "Hexadecimal"),
+            sign,
+            integer.unsigned_abs()
+        ));
+    }
+
+    let magnitude = number.abs();
+    if magnitude != 0.0 && !(1e-4..1e15).contains(&magnitude) {
+        lines.push(format!(
+            "- {}: `{:e}`",
+            locale.pick("科学计数法", "Scientific"),
+            number
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn big_integer_details(digits: &str, locale: Locale) -> String {
+    let count = digits
+        .trim_start_matches('0')
+        .chars()
+        .filter(char::is_ascii_digit)
+        .count();
+    [
+        format!("**{}** `{}`", locale.pick("大整数", "BigInteger"), digits),
+        format!(
+            "- {}: `0x{}`",
+            locale.pick("十六进制", "Hexadecimal"),
+            decimal_to_hex(digits)
+        ),
+        format!(
+            "- {}",
+            match locale {
+                Locale::Zh => format!("共 {} 位，超出 f64 的精确表示范围", count),
+                Locale::En => format!("{} digits, beyond what an f64 represents exactly", count),
+            }
+        ),
+        format!(
+            "- {}",
+            locale.pick(
+                "以任意精度整数保存，整数运算结果精确，不会舍入",
+                "Kept as an arbitrary-precision integer, so integer arithmetic on it is exact"
+            )
+        ),
+    ]
+    .join("\n")
+}
+
+/// Convert a decimal digit string of any length to upper-case hex
+fn decimal_to_hex(digits: &str) -> String {
+    let mut decimal: Vec<u32> = digits.chars().filter_map(|ch| ch.to_digit(10)).collect();
+    let mut hex = Vec::new();
+
+    while decimal.iter().any(|&digit| digit != 0) {
+        // Long division by 16, keeping the remainder as the next hex digit
+        let mut remainder = 0;
+        for digit in decimal.iter_mut() {
+            let current = remainder * 10 + *digit;
+            *digit = current / 16;
+            remainder = current % 16;
+        }
+        hex.push(
+            char::from_digit(remainder, 16)
+                .unwrap()
+                .to_ascii_uppercase(),
+        );
+    }
+
+    if hex.is_empty() {
+        return "0".to_string();
+    }
+    hex.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::find_expr_at;
+    use crate::parser::Parser;
+
+    fn hover_at(text: &str, character: u32) -> Option<String> {
+        let doc = Parser::new(text).parse();
+        let expr = find_expr_at(&doc.ast, Position::new(0, character))?;
+        let HoverContents::Markup(content) = literal_hover(expr, Locale::En)?.contents else {
+            panic!("expected markdown hover");
+        };
+        Some(content.value)
+    }
+
+    #[test]
+    fn test_number_literal_hover() {
+        assert_eq!(
+            hover_at("Set MASK 255", 10).unwrap(),
+            "**Number** `255`\n- Hexadecimal: `0xFF`"
+        );
+        assert_eq!(
+            hover_at("Set TINY 0.00001", 10).unwrap(),
+            "**Number** `0.00001`\n- Scientific: `1e-5`"
+        );
+        assert_eq!(
+            hover_at("Set MASK -255", 10).unwrap(),
+            "**Number** `-255`\n- Hexadecimal: `-0xFF`"
+        );
+        assert_eq!(hover_at("Set NAME \"x\"", 10), None);
+    }
+
+    #[test]
+    fn test_template_hover() {
+        let template_hover_at = |text: &str, character: u32| {
+            let doc = Parser::new(text).parse();
+            let path = crate::ast::node_at_position(&doc.ast, Position::new(0, character))?;
+            let HoverContents::Markup(content) =
+                template_hover(&path, &doc.symbols, Locale::En)?.contents
+            else {
+                panic!("expected markdown hover");
+            };
+            Some(content.value)
+        };
+
+        assert_eq!(
+            template_hover_at("Set MSG FORMAT(\"Hello {} you are {}\", NAME)", 18).unwrap(),
+            "**Format template** `FORMAT`\n- Placeholders: 2\n- Arguments: 1\n- The counts don't match"
+        );
+        assert_eq!(
+            template_hover_at("PRINTLN(\"{} of {}\", DONE, TOTAL)", 10).unwrap(),
+            "**Format template** `PRINTLN`\n- Placeholders: 2\n- Arguments: 2"
+        );
+        // Not the template, not a template, and a user function named FORMAT
+        assert_eq!(template_hover_at("PRINTLN(\"{}\", \"x\")", 15), None);
+        assert_eq!(template_hover_at("PRINTLN(\"done\")", 10), None);
+        assert_eq!(
+            template_hover_at("FORMAT(\"{}\")\nFunc FORMAT(T) {\n    Return T\n}", 8),
+            None
+        );
+    }
+
+    #[test]
+    fn test_big_integer_literal_hover() {
+        let hover = hover_at("Set BIG 12345678901234567890", 10).unwrap();
+        assert_eq!(
+            hover,
+            "**BigInteger** `12345678901234567890`\n- Hexadecimal: `0xAB54A98CEB1F0AD2`\n- 20 digits, beyond what an f64 represents exactly\n- Kept as an arbitrary-precision integer, so integer arithmetic on it is exact"
+        );
+
+        // Leading zeros are not counted
+        let hover = hover_at("Set BIG 0009007199254740993", 10).unwrap();
+        assert!(hover.contains("- 16 digits,"), "{}", hover);
+        assert!(hover.contains("`0x20000000000001`"), "{}", hover);
+    }
+}
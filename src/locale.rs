@@ -0,0 +1,48 @@
+//! Language selection for user-facing text
+//!
+//! Chinese is the primary language of the project and the fallback for any
+//! locale without its own translations.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Locale {
+    /// Map a BCP 47 tag such as `en-US` or `zh-CN` to a supported locale
+    pub fn from_tag(tag: &str) -> Self {
+        let language = tag
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Locale::En,
+            _ => Locale::Zh,
+        }
+    }
+
+    /// Choose between the Chinese and English variants of a text
+    pub fn pick<'a>(self, zh: &'a str, en: &'a str) -> &'a str {
+        match self {
+            Locale::Zh => zh,
+            Locale::En => en,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_from_tag() {
+        assert_eq!(Locale::from_tag("en"), Locale::En);
+        assert_eq!(Locale::from_tag("en-US"), Locale::En);
+        assert_eq!(Locale::from_tag("zh-CN"), Locale::Zh);
+        assert_eq!(Locale::from_tag("fr"), Locale::Zh);
+        assert_eq!(Locale::from_tag(""), Locale::Zh);
+    }
+}
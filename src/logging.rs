@@ -0,0 +1,124 @@
+//! Server-side logging, kept off the LSP output channel
+//!
+//! Routine events (parses, lookups) go here at `debug`/`info` instead of
+//! `window/logMessage`. Messages reach stderr and, with `--log-file`, a file
+//! that can be attached to bug reports. The threshold comes from
+//! `--log-level` or the `AETHER_LSP_LOG` environment variable.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable read when `--log-level` isn't given
+pub const LOG_LEVEL_ENV: &str = "AETHER_LSP_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(text: &str) -> Option<LogLevel> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" | "trace" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        })
+    }
+}
+
+struct Logger {
+    level: LogLevel,
+    file: Option<Mutex<File>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Set the threshold and optional log file; only the first call has an effect
+pub fn init(level: LogLevel, file: Option<&Path>) -> std::io::Result<()> {
+    let file = match file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+    let _ = LOGGER.set(Logger { level, file });
+    Ok(())
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off
+        && level
+            <= LOGGER
+                .get()
+                .map_or(LogLevel::default(), |logger| logger.level)
+}
+
+pub fn log(level: LogLevel, message: &str) {
+    if !enabled(level) {
+        return;
+    }
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    let line = format!("[{:.3} {}] {}", seconds, level, message);
+
+    eprintln!("{}", line);
+    if let Some(file) = LOGGER.get().and_then(|logger| logger.file.as_ref())
+        && let Ok(mut file) = file.lock()
+    {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+pub fn error(message: &str) {
+    log(LogLevel::Error, message);
+}
+
+pub fn debug(message: &str) {
+    log(LogLevel::Debug, message);
+}
+
+pub fn info(message: &str) {
+    log(LogLevel::Info, message);
+}
+
+pub fn warn(message: &str) {
+    log(LogLevel::Warn, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(LogLevel::parse("DEBUG"), Some(LogLevel::Debug));
+        assert_eq!(LogLevel::parse(" warning "), Some(LogLevel::Warn));
+        assert_eq!(LogLevel::parse("loud"), None);
+        assert!(LogLevel::Error < LogLevel::Debug);
+    }
+}
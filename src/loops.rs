@@ -0,0 +1,183 @@
+//! Guesses at what a `For` loop meant, for the soft loop checks
+//!
+//! `For I, V In ARR` binds the index first, but other languages put the value
+//! first; and `For K In DICT` gives keys, where values are often expected.
+//! Both checks read intent from usage, so they are hints and can be turned off.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "server")]
+use lsp_types::Position;
+
+use crate::ast::{BinOp, Expr, ExprKind, Ident, Stmt};
+#[cfg(feature = "server")]
+use crate::ast::{Program, StmtKind};
+use crate::types::{Types, ValueType};
+use crate::visit::{Visitor, walk_expr};
+
+/// How a loop variable is used in the body
+#[derive(Debug, Default, Clone, Copy)]
+struct Uses {
+    /// As the index of `X[...]`
+    index: usize,
+    /// As an operand of arithmetic or an ordering comparison
+    arithmetic: usize,
+    /// Anywhere else
+    other: usize,
+}
+
+/// Whether the body of `For INDEX, VALUE In ...` uses the index only like a
+/// value and the value only as an index, as if the two were swapped
+pub fn swapped_variables(index_var: &Ident, value_var: &Ident, body: &[Stmt]) -> bool {
+    let mut counter = UseCounter {
+        names: [index_var.as_str(), value_var.as_str()],
+        uses: HashMap::new(),
+    };
+    counter.visit_block(body);
+    let uses = |name: &Ident| counter.uses.get(name.as_str()).copied().unwrap_or_default();
+    let (index, value) = (uses(index_var), uses(value_var));
+    index.other > 0
+        && index.index == 0
+        && index.arithmetic == 0
+        && value.index > 0
+        && value.arithmetic == 0
+        && value.other == 0
+}
+
+/// The variable `iterable` names, if it certainly holds a dict
+pub fn dict_variable<'a>(iterable: &'a Expr, types: &Types) -> Option<&'a str> {
+    match &iterable.kind {
+        ExprKind::Identifier(name) if types.of(iterable) == ValueType::Dict => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "server")]
+/// The dict iterated by the `For` whose keyword is at `position`
+pub fn dict_loop_at(program: &Program, position: Position) -> Option<&str> {
+    let mut iterable = None;
+    crate::ast::for_each_stmt(program, &mut |stmt| {
+        if let StmtKind::For { iterable: expr, .. } = &stmt.kind
+            && stmt.span.start.line == position.line
+            && (stmt.span.start.character..=stmt.span.start.character + 3)
+                .contains(&position.character)
+        {
+            iterable = Some(expr);
+        }
+    });
+    dict_variable(iterable?, &Types::new(program))
+}
+
+struct UseCounter<'a> {
+    names: [&'a str; 2],
+    uses: HashMap<&'a str, Uses>,
+}
+
+impl<'a> UseCounter<'a> {
+    /// The loop variable `expr` is, if it is one
+    fn variable(&self, expr: &Expr) -> Option<&'a str> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self
+                .names
+                .iter()
+                .find(|variable| **variable == name.as_str())
+                .copied(),
+            _ => None,
+        }
+    }
+
+    fn count(&mut self, expr: &Expr, role: fn(&mut Uses)) -> bool {
+        let Some(name) = self.variable(expr) else {
+            return false;
+        };
+        role(self.uses.entry(name).or_default());
+        true
+    }
+}
+
+impl<'a> Visitor<'a> for UseCounter<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Index { object, index } => {
+                self.visit_expr(object);
+                if !self.count(index, |uses| uses.index += 1) {
+                    self.visit_expr(index);
+                }
+            }
+            ExprKind::Binary {
+                left,
+                op:
+                    BinOp::Add
+                    | BinOp::Subtract
+                    | BinOp::Multiply
+                    | BinOp::Divide
+                    | BinOp::Modulo
+                    | BinOp::Less
+                    | BinOp::LessEqual
+                    | BinOp::Greater
+                    | BinOp::GreaterEqual,
+                right,
+            } => {
+                for operand in [left, right] {
+                    if !self.count(operand, |uses| uses.arithmetic += 1) {
+                        self.visit_expr(operand);
+                    }
+                }
+            }
+            _ => {
+                if !self.count(expr, |uses| uses.other += 1) {
+                    walk_expr(self, expr);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::StmtKind;
+    use crate::parser::Parser;
+
+    fn swapped(text: &str) -> bool {
+        let doc = Parser::new(text).parse();
+        match &doc.ast[1].kind {
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                body,
+                ..
+            } => swapped_variables(index_var, value_var, body),
+            other => panic!("expected a ForIndexed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_swapped_variables() {
+        assert!(swapped(
+            "Set ARR [1]\nFor V, I In ARR {\n    PRINTLN(V)\n    PRINTLN(ARR[I])\n}"
+        ));
+        // The usual order, and bodies that don't tell
+        assert!(!swapped(
+            "Set ARR [1]\nFor I, V In ARR {\n    PRINTLN(ARR[I], V)\n}"
+        ));
+        assert!(!swapped(
+            "Set ARR [1]\nFor V, I In ARR {\n    PRINTLN(V, I)\n}"
+        ));
+        assert!(!swapped(
+            "Set ARR [1]\nFor V, I In ARR {\n    PRINTLN(ARR[I], V + 1)\n}"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_dict_loop_at() {
+        let doc = Parser::new(
+            "Set AGES {\"ann\": 3}\nFor NAME In AGES {\n    PRINTLN(NAME)\n}\nFor X In [1] {\n}",
+        )
+        .parse();
+        assert_eq!(dict_loop_at(&doc.ast, Position::new(1, 1)), Some("AGES"));
+        assert_eq!(dict_loop_at(&doc.ast, Position::new(1, 6)), None);
+        assert_eq!(dict_loop_at(&doc.ast, Position::new(4, 1)), None);
+    }
+}
@@ -1,23 +1,133 @@
-use tower_lsp::{LspService, Server};
+use std::path::PathBuf;
 
-mod ast;
-mod backend;
-mod builtins;
-mod completion;
-mod diagnostics;
-mod lexer;
-mod parser;
-mod symbols;
-mod token;
+use aether_lsp::server::{self, LogLevel};
 
-use backend::AetherLspBackend;
+/// Command-line options
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    log_level: Option<LogLevel>,
+    log_file: Option<PathBuf>,
+    /// Where to save documents that made a handler panic
+    crash_dump: Option<PathBuf>,
+    /// Print the version and exit instead of serving
+    version: bool,
+    help: bool,
+}
+
+const USAGE: &str = "\
+Usage: aether-lsp [OPTIONS]
+
+Serves the Aether language over stdin and stdout.
+
+Options:
+  --log-level <LEVEL>  error, warn, info, debug or trace
+  --log-file <PATH>    also append logs to PATH
+  --crash-dump <DIR>   save the documents that made a handler panic
+  --stdio              accepted for compatibility; stdio is the only transport
+  -V, --version        print the version and exit
+  -h, --help           print this help and exit";
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-level" => {
+                let value = args.next().ok_or("--log-level needs a value")?;
+                options.log_level =
+                    Some(LogLevel::parse(&value).ok_or(format!("unknown log level '{}'", value))?);
+            }
+            "--log-file" => {
+                options.log_file = Some(args.next().ok_or("--log-file needs a path")?.into());
+            }
+            "--crash-dump" => {
+                options.crash_dump =
+                    Some(args.next().ok_or("--crash-dump needs a directory")?.into());
+            }
+            // Editors commonly pass this; stdio is the only transport
+            "--stdio" => {}
+            "--version" | "-V" => options.version = true,
+            "--help" | "-h" => options.help = true,
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    Ok(options)
+}
 
 #[tokio::main]
 async fn main() {
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("aether-lsp: {}", err);
+        std::process::exit(2);
+    });
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if options.version {
+        println!("aether-lsp {}", server::version());
+        return;
+    }
+    let level = options
+        .log_level
+        .or_else(|| {
+            std::env::var(server::LOG_LEVEL_ENV)
+                .ok()
+                .and_then(|value| LogLevel::parse(&value))
+        })
+        .unwrap_or_default();
+    if let Err(err) = server::init_logging(level, options.log_file.as_deref()) {
+        eprintln!("aether-lsp: cannot open log file: {}", err);
+        std::process::exit(2);
+    }
+    if let Some(dir) = options.crash_dump {
+        server::enable_crash_dumps(dir);
+    }
+
+    server::serve_stdio().await;
+}
 
-    let (service, socket) = LspService::new(|client| AetherLspBackend::new(client));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Server::new(stdin, stdout, socket).serve(service).await;
+    #[test]
+    fn test_parse_args() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["--stdio"]), Ok(Options::default()));
+        assert_eq!(
+            args(&[
+                "--log-level",
+                "debug",
+                "--log-file",
+                "/tmp/aether.log",
+                "--crash-dump",
+                "/tmp/aether-crashes",
+            ]),
+            Ok(Options {
+                log_level: Some(LogLevel::Debug),
+                log_file: Some(PathBuf::from("/tmp/aether.log")),
+                crash_dump: Some(PathBuf::from("/tmp/aether-crashes")),
+                ..Options::default()
+            })
+        );
+        assert_eq!(
+            args(&["--version"]),
+            Ok(Options {
+                version: true,
+                ..Options::default()
+            })
+        );
+        assert_eq!(args(&["-V"]), args(&["--version"]));
+        assert_eq!(
+            args(&["-h"]),
+            Ok(Options {
+                help: true,
+                ..Options::default()
+            })
+        );
+        assert!(args(&["--log-level", "loud"]).is_err());
+        assert!(args(&["--log-file"]).is_err());
+        assert!(args(&["--crash-dump"]).is_err());
+    }
 }
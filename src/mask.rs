@@ -0,0 +1,130 @@
+//! Where the strings and comments of a text lie
+//!
+//! Features that read the text around the cursor instead of the AST, like the
+//! word under it or the line-based completion contexts, must not act inside a
+//! string or comment: there `Set` and `TOTAL` are just characters, e.g. in a
+//! `"""` string holding sample code. The lexer records every string it reads
+//! and comment it skips, and a [`Mask`] keeps them sorted for lookups.
+
+use lsp_types::{Position, Range};
+
+use crate::ast::{Comment, Span};
+
+/// The strings and comments of one text, sorted by start and disjoint
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mask {
+    regions: Vec<Region>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    span: Span,
+    /// A line comment runs to the end of its line, so the end is still inside
+    /// it; the closing quote or `*/` ends a string or block comment
+    open_end: bool,
+}
+
+impl Mask {
+    /// The mask of terminated string literals `strings` and `comments`, both
+    /// in source order as the lexer gives them
+    pub fn new(strings: &[Span], comments: &[Comment]) -> Self {
+        let mut regions: Vec<Region> = strings
+            .iter()
+            .map(|&span| Region {
+                span,
+                open_end: false,
+            })
+            .chain(comments.iter().map(|comment| Region {
+                span: comment.span,
+                open_end: !comment.block,
+            }))
+            .collect();
+        regions.sort_by_key(|region| region.span.start);
+        Mask { regions }
+    }
+
+    /// One mask of the regions of `masks`, which cover disjoint parts of a text
+    pub fn merged(masks: impl IntoIterator<Item = Mask>) -> Self {
+        let mut regions: Vec<Region> = masks.into_iter().flat_map(|mask| mask.regions).collect();
+        regions.sort_by_key(|region| region.span.start);
+        Mask { regions }
+    }
+
+    /// The string or comment `position` is inside: past its opening quote or
+    /// `//`, and before the closing quote or `*/`
+    pub fn region_at(&self, position: Position) -> Option<Span> {
+        let after = self
+            .regions
+            .partition_point(|region| region.span.start < position);
+        let region = self.regions[..after].last()?;
+        let inside = position < region.span.end || (region.open_end && position == region.span.end);
+        inside.then_some(region.span)
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.region_at(position).is_some()
+    }
+
+    /// Whether all of `range` lies inside a single string or comment
+    pub fn covers(&self, range: Range) -> bool {
+        self.region_at(range.start)
+            .is_some_and(|region| range.end <= region.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::token::Token;
+
+    fn mask(text: &str) -> Mask {
+        let mut lexer = Lexer::new(text);
+        while lexer.next_token() != Token::EOF {}
+        Mask::new(&lexer.take_strings(), &lexer.take_comments())
+    }
+
+    #[test]
+    fn test_inside_strings_and_comments() {
+        let text = "Set A \"Set B\" // note\nSet C \"\"\"\nSet D 1\n\"\"\" /* x */ + 1";
+        let mask = mask(text);
+        let inside = |line, character| mask.contains(Position::new(line, character));
+
+        // Between the quotes of a string, not on either side of them
+        assert!(!inside(0, 6));
+        assert!(inside(0, 7));
+        assert!(inside(0, 12));
+        assert!(!inside(0, 13));
+        // A line comment to the end of its line
+        assert!(!inside(0, 14));
+        assert!(inside(0, 17));
+        assert!(inside(0, 21));
+        // A multiline string, all of its lines
+        assert!(inside(1, 9));
+        assert!(inside(2, 4));
+        assert!(inside(3, 2));
+        assert!(!inside(3, 3));
+        // A block comment ends with its `*/`
+        assert!(inside(3, 6));
+        assert!(!inside(3, 11));
+        assert!(!inside(1, 2));
+    }
+
+    #[test]
+    fn test_unterminated_string_masks_nothing() {
+        // Otherwise typing a quote would blank out the rest of the document
+        let mask = mask("PRINTLN(\"Set\nSet A 1");
+        assert!(!mask.contains(Position::new(0, 10)));
+        assert!(!mask.contains(Position::new(1, 4)));
+    }
+
+    #[test]
+    fn test_covers_only_ranges_inside_one_region() {
+        let mask = mask("Set A \"TOTAL\" + \"B\"");
+        let range = |start, end| Range::new(Position::new(0, start), Position::new(0, end));
+        assert!(mask.covers(range(7, 12)));
+        assert!(!mask.covers(range(6, 13)));
+        assert!(!mask.covers(range(7, 18)));
+        assert!(!mask.covers(range(0, 3)));
+    }
+}
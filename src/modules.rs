@@ -0,0 +1,310 @@
+//! Loading, caching and discovery of Aether modules on disk
+//!
+//! Imported modules are parsed on demand and cached by path; an entry is
+//! reparsed when the file's modification time changes.
+
+use std::path::{Component, Path, PathBuf};
+#[cfg(feature = "server")]
+use std::{fs, time::SystemTime};
+
+#[cfg(feature = "server")]
+use dashmap::DashMap;
+#[cfg(feature = "server")]
+use lsp_types::SymbolKind;
+
+#[cfg(feature = "server")]
+use crate::ignore::IgnoreRules;
+#[cfg(feature = "server")]
+use crate::parser::{ParsedDocument, Parser};
+
+/// File extension of Aether source files
+#[cfg(feature = "server")]
+pub const MODULE_EXTENSION: &str = "ae";
+
+/// Directories never searched for modules
+#[cfg(feature = "server")]
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Limits keeping module discovery cheap on large trees
+#[cfg(feature = "server")]
+const MAX_DISCOVERY_DEPTH: usize = 8;
+#[cfg(feature = "server")]
+const MAX_DISCOVERED_MODULES: usize = 500;
+#[cfg(feature = "server")]
+const MAX_INDEXED_MODULES: usize = 5000;
+
+/// A symbol named by an `Export` statement
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: Option<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+struct CachedModule {
+    modified: Option<SystemTime>,
+    exports: Vec<ExportedSymbol>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Default)]
+pub struct ModuleCache {
+    modules: DashMap<PathBuf, CachedModule>,
+}
+
+#[cfg(feature = "server")]
+impl ModuleCache {
+    pub fn new() -> Self {
+        ModuleCache::default()
+    }
+
+    /// Exported symbols of the module at `path`, or `None` if it can't be read
+    pub fn exports(&self, path: &Path) -> Option<Vec<ExportedSymbol>> {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if let Some(cached) = self.modules.get(path)
+            && cached.modified == modified
+        {
+            return Some(cached.exports.clone());
+        }
+
+        let text = fs::read_to_string(path).ok()?;
+        let exports = exported_symbols(&Parser::new(&text).parse());
+        self.modules.insert(
+            path.to_path_buf(),
+            CachedModule {
+                modified,
+                exports: exports.clone(),
+            },
+        );
+        Some(exports)
+    }
+
+    /// Forget the cached parse of `path`, e.g. after it changed on disk
+    pub fn invalidate(&self, path: &Path) {
+        self.modules.remove(path);
+    }
+}
+
+#[cfg(feature = "server")]
+/// Symbols exported by a parsed module, with kinds taken from its symbol table
+pub fn exported_symbols(doc: &ParsedDocument) -> Vec<ExportedSymbol> {
+    doc.ast
+        .exports()
+        .iter()
+        .map(|name| {
+            let symbol = doc
+                .symbols
+                .functions
+                .iter()
+                .chain(&doc.symbols.variables)
+                .find(|symbol| symbol.name == name.as_str());
+            ExportedSymbol {
+                name: name.name.to_string(),
+                kind: symbol.map_or(SymbolKind::VARIABLE, |symbol| symbol.kind),
+                detail: symbol.and_then(|symbol| symbol.detail.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Resolve an import path relative to the importing document's directory
+pub fn resolve_import(base_dir: &Path, import_path: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in base_dir.join(import_path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// Where [`locate_import`] found a module
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportBase {
+    /// Next to the importing document, or nowhere
+    Document,
+    /// Under this workspace root
+    Root(PathBuf),
+}
+
+/// Resolve an import path relative to the importing document's directory, falling
+/// back to each workspace root in order. A module found nowhere resolves relative
+/// to the document, so a missing-module error names the path as written.
+pub fn locate_import(
+    base_dir: &Path,
+    roots: &[PathBuf],
+    import_path: &str,
+) -> (PathBuf, ImportBase) {
+    let relative = resolve_import(base_dir, import_path);
+    if relative.is_file() {
+        return (relative, ImportBase::Document);
+    }
+    roots
+        .iter()
+        .map(|root| (resolve_import(root, import_path), root))
+        .find(|(path, _)| path.is_file())
+        .map(|(path, root)| (path, ImportBase::Root(root.clone())))
+        .unwrap_or((relative, ImportBase::Document))
+}
+
+/// [`locate_import`] without how the module was found
+pub fn resolve_import_in(base_dir: &Path, roots: &[PathBuf], import_path: &str) -> PathBuf {
+    locate_import(base_dir, roots, import_path).0
+}
+
+#[cfg(feature = "server")]
+/// Find `.ae` files under the given roots, skipping hidden and build directories
+pub fn discover_modules(roots: &[&Path]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for root in roots {
+        walk(root, 0, MAX_DISCOVERED_MODULES, &|_| false, &mut found);
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+#[cfg(feature = "server")]
+/// Find every `.ae` file of a workspace for indexing, leaving out what `rules` ignore
+pub fn discover_workspace(root: &Path, rules: &IgnoreRules) -> Vec<PathBuf> {
+    let excluded = |path: &Path| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        rules.is_ignored(relative, path.is_dir())
+    };
+    let mut found = Vec::new();
+    walk(root, 0, MAX_INDEXED_MODULES, &excluded, &mut found);
+    found.sort();
+    found
+}
+
+#[cfg(feature = "server")]
+fn walk(
+    dir: &Path,
+    depth: usize,
+    limit: usize,
+    excluded: &dyn Fn(&Path) -> bool,
+    found: &mut Vec<PathBuf>,
+) {
+    if depth > MAX_DISCOVERY_DEPTH || found.len() >= limit {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut entries: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    for path in entries {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name.starts_with('.') || excluded(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name) {
+                walk(&path, depth + 1, limit, excluded, found);
+            }
+        } else if path.extension().is_some_and(|ext| ext == MODULE_EXTENSION) {
+            found.push(path);
+            if found.len() >= limit {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+/// Spell `target` relative to `from_dir` the way `Import` paths are written
+pub fn relative_import_path(from_dir: &Path, target: &Path) -> String {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = Vec::new();
+    if common == from.len() {
+        parts.push(".".to_string());
+    } else {
+        parts.extend(std::iter::repeat_n("..".to_string(), from.len() - common));
+    }
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_relative_import_path() {
+        let base = Path::new("/work/app");
+        assert_eq!(
+            relative_import_path(base, Path::new("/work/app/lib.ae")),
+            "./lib.ae"
+        );
+        assert_eq!(
+            relative_import_path(base, Path::new("/work/shared/util.ae")),
+            "../shared/util.ae"
+        );
+        assert_eq!(
+            resolve_import(base, "../shared/./util.ae"),
+            PathBuf::from("/work/shared/util.ae")
+        );
+    }
+
+    #[test]
+    fn test_locate_import_falls_back_to_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, shared) = (dir.path().join("app"), dir.path().join("shared"));
+        fs::create_dir_all(app.join("src")).unwrap();
+        fs::create_dir_all(shared.join("lib")).unwrap();
+        fs::write(app.join("src/near.ae"), "").unwrap();
+        fs::write(shared.join("lib/util.ae"), "").unwrap();
+        let roots = [app.clone(), shared.clone()];
+        let base = app.join("src");
+
+        assert_eq!(
+            locate_import(&base, &roots, "./near.ae"),
+            (app.join("src/near.ae"), ImportBase::Document)
+        );
+        assert_eq!(
+            locate_import(&base, &roots, "lib/util.ae"),
+            (shared.join("lib/util.ae"), ImportBase::Root(shared.clone()))
+        );
+        assert_eq!(
+            locate_import(&base, &roots, "./missing.ae"),
+            (app.join("src/missing.ae"), ImportBase::Document)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_exported_symbols_use_real_kinds() {
+        let doc = Parser::new(
+            "Func ADD(A, B) {\n    Return A + B\n}\nSet PI 3.14\nExport ADD\nExport PI",
+        )
+        .parse();
+        let exports = exported_symbols(&doc);
+        assert_eq!(
+            exports
+                .iter()
+                .map(|export| (export.name.as_str(), export.kind))
+                .collect::<Vec<_>>(),
+            vec![("ADD", SymbolKind::FUNCTION), ("PI", SymbolKind::VARIABLE)]
+        );
+    }
+}
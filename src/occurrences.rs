@@ -0,0 +1,482 @@
+//! Index of every place an identifier appears, rebuilt on each parse
+//!
+//! References, highlights, rename and go-to-definition all resolve names
+//! through this index instead of scanning the text, so a name inside a string
+//! or comment is never mistaken for a use. Scoping is ignored like elsewhere:
+//! the first binding of a name is its definition and later `Set`s are writes.
+
+use std::collections::HashMap;
+use std::ops::Index;
+
+#[cfg(feature = "server")]
+use crate::ast::span_contains;
+use crate::ast::{Expr, ExprKind, Ident, Program, Span, Stmt, StmtKind};
+use crate::intern::Name;
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+use lsp_types::DocumentHighlightKind;
+#[cfg(feature = "server")]
+use lsp_types::Position;
+
+/// Occurrences of each name in source order, and of all names by position
+#[derive(Debug, Clone, Default)]
+pub struct Occurrences {
+    by_name: HashMap<Name, Vec<Occurrence>>,
+    /// Where each occurrence starts, sorted, with its name and place in that
+    /// name's list. Identifiers never overlap, so the cursor is in at most one.
+    #[cfg(feature = "server")]
+    by_position: Vec<(Position, Name, usize)>,
+}
+
+impl Occurrences {
+    fn new(by_name: HashMap<Name, Vec<Occurrence>>) -> Self {
+        #[cfg(feature = "server")]
+        let by_position = {
+            let mut by_position: Vec<(Position, Name, usize)> = by_name
+                .iter()
+                .flat_map(|(name, list)| {
+                    list.iter().enumerate().map(move |(index, occurrence)| {
+                        (occurrence.span.start, name.clone(), index)
+                    })
+                })
+                .collect();
+            by_position.sort_by_key(|(start, ..)| *start);
+            by_position
+        };
+        Occurrences {
+            by_name,
+            #[cfg(feature = "server")]
+            by_position,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Vec<Occurrence>> {
+        self.by_name.get(name)
+    }
+
+    /// Every name with its occurrences, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&Name, &Vec<Occurrence>)> {
+        self.by_name.iter()
+    }
+
+    #[cfg(test)]
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+}
+
+impl Index<&str> for Occurrences {
+    type Output = Vec<Occurrence>;
+
+    fn index(&self, name: &str) -> &Vec<Occurrence> {
+        &self.by_name[name]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// First `Set`, a function/generator/lazy name, a parameter or a loop variable
+    Definition,
+    /// A later `Set` of an already bound name, or the target of `Set X[...]`
+    Write,
+    Read,
+    /// The callee of a call
+    Call,
+    /// The local binding an `Import` introduces
+    Import,
+    Export,
+}
+
+impl Role {
+    pub fn highlight_kind(self) -> DocumentHighlightKind {
+        match self {
+            Role::Definition | Role::Write | Role::Import => DocumentHighlightKind::WRITE,
+            Role::Read | Role::Call | Role::Export => DocumentHighlightKind::READ,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Occurrence {
+    pub span: Span,
+    pub role: Role,
+}
+
+/// Build the index for a parsed program
+pub fn collect(program: &Program) -> Occurrences {
+    let mut collector = OccurrenceCollector::default();
+    collector.visit_block(program);
+    Occurrences::new(collector.occurrences)
+}
+
+#[cfg(feature = "server")]
+/// The name and occurrence under the cursor; the end of an identifier still counts
+pub fn find_at(occurrences: &Occurrences, position: Position) -> Option<(&str, &Occurrence)> {
+    let after = occurrences
+        .by_position
+        .partition_point(|(start, ..)| *start <= position);
+    let (_, name, index) = occurrences.by_position[..after].last()?;
+    let occurrence = &occurrences.by_name[name][*index];
+    span_contains(occurrence.span, position).then_some((name.as_str(), occurrence))
+}
+
+#[cfg(feature = "server")]
+/// Where `name` is bound in this document: its definition, otherwise its import
+pub fn definition<'a>(occurrences: &'a Occurrences, name: &str) -> Option<&'a Occurrence> {
+    let list = occurrences.get(name)?;
+    list.iter()
+        .find(|occurrence| occurrence.role == Role::Definition)
+        .or_else(|| {
+            list.iter()
+                .find(|occurrence| occurrence.role == Role::Import)
+        })
+}
+
+#[cfg(feature = "server")]
+/// Every occurrence of the parameter or loop variable under the cursor, for editing
+/// them together. `None` unless all of them sit inside the function, lambda or loop
+/// that binds the name: globals, exports and reused names are left to rename.
+pub fn linked_editing_ranges(
+    program: &Program,
+    occurrences: &Occurrences,
+    position: Position,
+) -> Option<Vec<Span>> {
+    let (name, _) = find_at(occurrences, position)?;
+    let mut finder = LocalBinders::default();
+    finder.visit_block(program);
+    // The innermost construct binding the name around the cursor
+    let scope = finder
+        .binders
+        .iter()
+        .filter(|(binder, scope)| binder.name == name && span_contains(*scope, position))
+        .map(|(_, scope)| *scope)
+        .max_by_key(|scope| (scope.start.line, scope.start.character))?;
+
+    let list = &occurrences[name];
+    list.iter()
+        .all(|occurrence| {
+            span_contains(scope, occurrence.span.start) && span_contains(scope, occurrence.span.end)
+        })
+        .then(|| list.iter().map(|occurrence| occurrence.span).collect())
+}
+
+#[cfg(feature = "server")]
+/// Parameters and loop variables with the span of the construct binding them
+#[derive(Default)]
+struct LocalBinders<'a> {
+    binders: Vec<(&'a Ident, Span)>,
+}
+
+#[cfg(feature = "server")]
+impl<'a> Visitor<'a> for LocalBinders<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::FuncDef { params, .. } | StmtKind::GeneratorDef { params, .. } => {
+                self.binders
+                    .extend(params.iter().map(|param| (param, stmt.span)));
+            }
+            StmtKind::For { var, .. } => self.binders.push((var, stmt.span)),
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.binders.push((index_var, stmt.span));
+                self.binders.push((value_var, stmt.span));
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { params, .. } = &expr.kind {
+            self.binders
+                .extend(params.iter().map(|param| (param, expr.span)));
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[derive(Default)]
+struct OccurrenceCollector {
+    occurrences: HashMap<Name, Vec<Occurrence>>,
+}
+
+impl OccurrenceCollector {
+    fn push(&mut self, name: &Name, span: Span, role: Role) {
+        self.occurrences
+            .entry(name.clone())
+            .or_default()
+            .push(Occurrence { span, role });
+    }
+
+    fn define(&mut self, ident: &Ident) {
+        self.push(&ident.name, ident.span, Role::Definition);
+    }
+
+    /// A `Set`: the definition the first time a name is bound, a write afterwards
+    fn assign(&mut self, ident: &Ident) {
+        let bound = self.occurrences.get(&ident.name).is_some_and(|list| {
+            list.iter()
+                .any(|occurrence| matches!(occurrence.role, Role::Definition | Role::Import))
+        });
+        let role = if bound { Role::Write } else { Role::Definition };
+        self.push(&ident.name, ident.span, role);
+    }
+}
+
+impl<'a> Visitor<'a> for OccurrenceCollector {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, .. } => self.assign(name),
+            StmtKind::SetIndex {
+                object,
+                index,
+                value,
+            } => {
+                if let ExprKind::Identifier(name) = &object.kind {
+                    self.push(name, object.span, Role::Write);
+                } else {
+                    self.visit_expr(object);
+                }
+                self.visit_expr(index);
+                self.visit_expr(value);
+                return;
+            }
+            StmtKind::FuncDef { name, params, .. }
+            | StmtKind::GeneratorDef { name, params, .. } => {
+                self.define(name);
+                params.iter().for_each(|param| self.define(param));
+            }
+            StmtKind::LazyDef { name, .. } => self.define(name),
+            StmtKind::For { var, .. } => self.define(var),
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.define(index_var);
+                self.define(value_var);
+            }
+            StmtKind::Try {
+                error_var: Some(error_var),
+                ..
+            } => self.define(error_var),
+            StmtKind::Import { names, aliases, .. } => {
+                for (name, alias) in names.iter().zip(aliases) {
+                    let binding = alias.as_ref().unwrap_or(name);
+                    self.push(&binding.name, binding.span, Role::Import);
+                }
+            }
+            StmtKind::Export(name) => self.push(&name.name, name.span, Role::Export),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Call { func, args } => {
+                if let ExprKind::Identifier(name) = &func.kind {
+                    self.push(name, func.span, Role::Call);
+                    args.iter().for_each(|arg| self.visit_expr(arg));
+                    return;
+                }
+            }
+            ExprKind::Identifier(name) => self.push(name, expr.span, Role::Read),
+            ExprKind::Lambda { params, .. } => params.iter().for_each(|param| self.define(param)),
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn roles(occurrences: &Occurrences, name: &str) -> Vec<(u32, u32, Role)> {
+        occurrences[name]
+            .iter()
+            .map(|occurrence| {
+                (
+                    occurrence.span.start.line,
+                    occurrence.span.start.character,
+                    occurrence.role,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_occurrence_roles() {
+        let text = r#"Import {LOAD As FETCH} From "./io"
+Set TOTAL 0
+Func ADD(ITEM) {
+    Set TOTAL (TOTAL + ITEM)
+    Return TOTAL
+}
+Set CONFIG {"name": "TOTAL"}
+Set CONFIG["size"] FETCH()
+For I, VALUE In CONFIG {
+    ADD(VALUE)
+}
+PRINTLN(MAP([1], Lambda ITEM -> ITEM * I))
+Export ADD
+"#;
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let occurrences = &doc.occurrences;
+
+        assert_eq!(
+            roles(occurrences, "TOTAL"),
+            vec![
+                (1, 4, Role::Definition),
+                (3, 8, Role::Write),
+                (3, 15, Role::Read),
+                (4, 11, Role::Read),
+            ]
+        );
+        assert_eq!(
+            roles(occurrences, "ADD"),
+            vec![
+                (2, 5, Role::Definition),
+                (9, 4, Role::Call),
+                (12, 7, Role::Export)
+            ]
+        );
+        assert_eq!(
+            roles(occurrences, "ITEM"),
+            vec![
+                (2, 9, Role::Definition),
+                (3, 23, Role::Read),
+                (11, 24, Role::Definition),
+                (11, 32, Role::Read),
+            ]
+        );
+        assert_eq!(
+            roles(occurrences, "CONFIG"),
+            vec![
+                (6, 4, Role::Definition),
+                (7, 4, Role::Write),
+                (8, 16, Role::Read)
+            ]
+        );
+        assert_eq!(
+            roles(occurrences, "FETCH"),
+            vec![(0, 16, Role::Import), (7, 19, Role::Call)]
+        );
+        assert_eq!(
+            roles(occurrences, "I"),
+            vec![(8, 4, Role::Definition), (11, 39, Role::Read)]
+        );
+        assert_eq!(roles(occurrences, "PRINTLN"), vec![(11, 0, Role::Call)]);
+        // Only the alias is bound locally, and string contents are never identifiers
+        assert!(!occurrences.contains_key("LOAD"));
+        assert_eq!(occurrences["TOTAL"].len(), 4);
+
+        #[cfg(feature = "server")]
+        {
+            let (name, occurrence) = find_at(occurrences, Position::new(9, 6)).unwrap();
+            assert_eq!((name, occurrence.role), ("ADD", Role::Call));
+            assert_eq!(
+                definition(occurrences, "ADD").unwrap().span.start,
+                Position::new(2, 5)
+            );
+            assert_eq!(definition(occurrences, "FETCH").unwrap().role, Role::Import);
+            assert!(definition(occurrences, "PRINTLN").is_none());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_linked_editing_ranges() {
+        let text = r#"Set TOTAL 0
+Func SCALE(FACTOR, VALUE) {
+    For ITEM In VALUE {
+        Set TOTAL (TOTAL + ITEM * FACTOR)
+    }
+    Return TOTAL
+}
+Func SHIFT(VALUE) {
+    Return VALUE + 1
+}
+PRINTLN(MAP([1], Lambda N -> N * 2))
+Export SCALE
+"#;
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let linked = |line, character| {
+            linked_editing_ranges(&doc.ast, &doc.occurrences, Position::new(line, character)).map(
+                |spans| {
+                    spans
+                        .iter()
+                        .map(|span| (span.start.line, span.start.character))
+                        .collect::<Vec<_>>()
+                },
+            )
+        };
+
+        // A parameter, from its declaration or a use; a loop variable; a lambda parameter
+        assert_eq!(linked(1, 12), Some(vec![(1, 11), (3, 34)]));
+        assert_eq!(linked(3, 36), Some(vec![(1, 11), (3, 34)]));
+        assert_eq!(linked(2, 9), Some(vec![(2, 8), (3, 27)]));
+        assert_eq!(linked(10, 24), Some(vec![(10, 24), (10, 29)]));
+
+        // A global, an exported function, and a parameter name reused by another function
+        assert_eq!(linked(3, 12), None);
+        assert_eq!(linked(1, 6), None);
+        assert_eq!(linked(1, 20), None);
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_lambda_passed_as_an_argument_binds_its_names() {
+        let text = "PRINTLN(MAP(XS, Func(X) {\n    Set Y (X * 2)\n    Return Y\n}))";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let occurrences = &doc.occurrences;
+
+        assert_eq!(
+            roles(occurrences, "X"),
+            vec![(0, 21, Role::Definition), (1, 11, Role::Read)]
+        );
+        assert_eq!(
+            roles(occurrences, "Y"),
+            vec![(1, 8, Role::Definition), (2, 11, Role::Read)]
+        );
+        assert_eq!(
+            definition(occurrences, "X").unwrap().span.start,
+            Position::new(0, 21)
+        );
+        // The parameter is local to the lambda, so its uses are edited together
+        let linked = linked_editing_ranges(&doc.ast, occurrences, Position::new(1, 11)).unwrap();
+        assert_eq!(linked.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_find_at_matches_scanning_every_name() {
+        let text: String = (0..300)
+            .map(|i| {
+                format!(
+                    "Set V{i} (V{} + LEN([\"V{i}\"]))\nPRINTLN(V{i}, V0)\n",
+                    i / 2
+                )
+            })
+            .collect();
+        let occurrences = collect(&Parser::new(&text).parse().ast);
+        for line in 0..600 {
+            for character in 0..30 {
+                let position = Position::new(line, character);
+                let scanned = occurrences.by_name.iter().find_map(|(name, list)| {
+                    list.iter()
+                        .find(|occurrence| span_contains(occurrence.span, position))
+                        .map(|occurrence| (name.as_str(), occurrence))
+                });
+                assert_eq!(find_at(&occurrences, position), scanned, "{:?}", position);
+            }
+        }
+    }
+}
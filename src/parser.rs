@@ -2,10 +2,24 @@
 //!
 //! Converts a stream of tokens into an Abstract Syntax Tree (AST)
 
-use crate::ast::{BinOp, Expr, Program, Stmt, UnaryOp};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::ast::{
+    BinOp, DictKey, Expr, ExprKind, Ident, Program, Span, Stmt, StmtKind, UnaryOp, span_contains,
+};
+use crate::diagnostic_codes::{self, DiagnosticCode};
+use crate::header;
+use crate::intern::Name;
 use crate::lexer::Lexer;
+use crate::line_index::LineIndex;
+use crate::mask::Mask;
+use crate::occurrences::{self, Occurrences};
 use crate::symbols::SymbolTable;
-use crate::token::Token;
+use crate::token::{KEYWORDS, Token};
+use lsp_types::Position;
 
 /// Parse errors with location information
 #[derive(Debug, Clone, PartialEq)]
@@ -21,6 +35,11 @@ pub enum ParseError {
         column: usize,
     },
     InvalidNumber(String),
+    /// A token that can't start an expression where one should
+    ExpectedExpression {
+        line: usize,
+        column: usize,
+    },
     InvalidExpression {
         message: String,
         line: usize,
@@ -37,6 +56,11 @@ pub enum ParseError {
         line: usize,
         column: usize,
     },
+    /// Blocks or expressions nested deeper than [`MAX_NESTING`]
+    TooDeep {
+        line: usize,
+        column: usize,
+    },
 }
 
 impl std::fmt::Display for ParseError {
@@ -62,6 +86,13 @@ impl std::fmt::Display for ParseError {
                 )
             }
             ParseError::InvalidNumber(s) => write!(f, "Parse error: Invalid number: {}", s),
+            ParseError::ExpectedExpression { line, column } => {
+                write!(
+                    f,
+                    "Parse error at line {}, column {}: Invalid expression - Unexpected token in expression",
+                    line, column
+                )
+            }
             ParseError::InvalidExpression {
                 message,
                 line,
@@ -96,12 +127,82 @@ impl std::fmt::Display for ParseError {
                     line, column, name, reason
                 )
             }
+            ParseError::TooDeep { line, column } => {
+                write!(
+                    f,
+                    "Parse error at line {}, column {}: Nested more than {} levels deep",
+                    line, column, MAX_NESTING
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
+impl ParseError {
+    /// One-based line and column of the offending token, when known
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            ParseError::UnexpectedToken { line, column, .. }
+            | ParseError::UnexpectedEOF { line, column }
+            | ParseError::ExpectedExpression { line, column }
+            | ParseError::InvalidExpression { line, column, .. }
+            | ParseError::InvalidStatement { line, column, .. }
+            | ParseError::InvalidIdentifier { line, column, .. }
+            | ParseError::TooDeep { line, column } => Some((*line, *column)),
+            ParseError::InvalidNumber(_) => None,
+        }
+    }
+
+    /// The stable diagnostic code for this kind of error
+    pub fn code(&self) -> &'static DiagnosticCode {
+        match self {
+            ParseError::UnexpectedToken { .. } => &diagnostic_codes::EXPECTED_TOKEN,
+            ParseError::UnexpectedEOF { .. } => &diagnostic_codes::UNEXPECTED_EOF,
+            ParseError::ExpectedExpression { .. } => &diagnostic_codes::UNEXPECTED_TOKEN,
+            ParseError::InvalidExpression { .. } => &diagnostic_codes::INVALID_EXPRESSION,
+            ParseError::InvalidIdentifier { .. } => &diagnostic_codes::INVALID_IDENTIFIER,
+            ParseError::InvalidNumber(_)
+            | ParseError::InvalidStatement { .. }
+            | ParseError::TooDeep { .. } => &diagnostic_codes::SYNTAX_ERROR,
+        }
+    }
+}
+
+/// Whether `name` follows the UPPER_SNAKE_CASE convention for variables and functions
+pub fn is_upper_snake_case(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_numeric())
+        && name
+            .chars()
+            .all(|c| c.is_uppercase() || c.is_numeric() || c == '_')
+}
+
+/// How deeply statements and expressions may nest; deeper input is an error
+/// rather than a stack overflow
+pub const MAX_NESTING: usize = 128;
+
+/// The keywords a statement can start with
+const STATEMENT_KEYWORDS: &[Token] = &[
+    Token::Set,
+    Token::Func,
+    Token::Generator,
+    Token::Lazy,
+    Token::Return,
+    Token::Yield,
+    Token::Break,
+    Token::Continue,
+    Token::While,
+    Token::For,
+    Token::Switch,
+    Token::Import,
+    Token::Export,
+    Token::Throw,
+    Token::Try,
+    Token::If,
+];
+
 /// Operator precedence (higher number = higher precedence)
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 enum Precedence {
@@ -114,7 +215,7 @@ enum Precedence {
     Product = 6,    // *, /, %
     Prefix = 7,     // -, !
     Call = 8,       // func()
-    Index = 9,      // array[index]
+    Index = 9,      // array[index], module.member
 }
 
 /// Parser state
@@ -123,26 +224,231 @@ pub struct Parser {
     lexer: Lexer,
     current_token: Token,
     peek_token: Token,
-    current_line: usize,
-    current_column: usize,
     current_had_whitespace: bool, // whether whitespace preceded current_token
     peek_had_whitespace: bool,    // whether whitespace preceded peek_token
+    current_span: Span,           // source span of current_token
+    peek_span: Span,              // source span of peek_token
+    prev_end: Position,           // end of the last consumed token (ignoring separators)
+    naming_issues: Vec<NamingIssue>, // names outside UPPER_SNAKE_CASE
+    lenient_keywords: bool,       // read `if` or `WHILE` where a keyword fits as the keyword
+    keyword_casings: Vec<KeywordCasing>, // keywords read that way
+    stray_assigns: Vec<StrayAssign>, // `=` read past where Aether has none
+    depth: usize,                 // statements and expressions being parsed, innermost included
 }
 
 /// Compatibility wrapper expected by other modules
 #[derive(Debug, Clone, Default)]
 pub struct ParsedDocument {
     pub text: String,
+    /// Line starts of `text`, for converting offsets and positions
+    pub line_index: LineIndex,
+    /// Strings and comments of `text`, which text-scanning features stay out of
+    pub mask: Mask,
     pub ast: Program,
     pub symbols: SymbolTable,
+    /// Where each identifier appears and in what role
+    pub occurrences: Occurrences,
     pub errors: Vec<CompatParseError>,
+    /// Names that aren't UPPER_SNAKE_CASE; a convention, so not parse errors
+    pub naming_issues: Vec<NamingIssue>,
+    /// Keywords spelled in another case, read as keywords under
+    /// [`Parser::lenient_keywords`]
+    pub keyword_casings: Vec<KeywordCasing>,
+    /// `=` signs written where Aether has none, which the parser read past
+    pub stray_assigns: Vec<StrayAssign>,
+    /// The `//! key: value` header lines, see [`crate::header`]
+    pub metadata: Vec<(String, String)>,
+    /// The latest earlier version that parsed to an AST, kept while this one
+    /// recovers none; see [`ParsedDocument::usable`]
+    pub last_good: Option<Arc<ParsedDocument>>,
+}
+
+/// What read-only features work from: a document, or while its text recovers
+/// no AST, the analysis of its last good version paired with the current text
+pub struct Usable<'a> {
+    doc: Cow<'a, ParsedDocument>,
+    /// The AST, symbols and occurrences come from an older text, so their
+    /// positions may be a little off
+    pub stale: bool,
 }
 
+impl Deref for Usable<'_> {
+    type Target = ParsedDocument;
+
+    fn deref(&self) -> &ParsedDocument {
+        &self.doc
+    }
+}
+
+/// A definition or parameter named outside UPPER_SNAKE_CASE
+#[derive(Debug, Clone)]
+pub struct NamingIssue {
+    pub name: Ident,
+    /// Parameters may be lowercase, so they are only discouraged
+    pub is_param: bool,
+}
+
+/// A keyword written in the wrong case, like `while` or `FUNC`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordCasing {
+    pub span: Span,
+    /// How the keyword is spelled
+    pub keyword: &'static str,
+}
+
+/// An `=` written out of habit from other languages: Aether's `Set X 10` has
+/// none, and its comparison is `==`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrayAssign {
+    /// The `=` itself
+    pub span: Span,
+    /// Where the token after it starts, so a fix can take the spacing too
+    pub next: Position,
+    pub kind: StrayAssignKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StrayAssignKind {
+    /// `Set X = 10`, read as `Set X 10`
+    InSet,
+    /// `If (X = 5)`, read as `==`
+    InCondition,
+    /// `X = 10` as a statement, read as `Set X 10`
+    WithoutSet { name: Ident, value: Span },
+}
+
+/// A statement that failed to parse, and how the parser got past it
 #[derive(Debug, Clone)]
 pub struct CompatParseError {
-    pub message: String,
+    pub error: ParseError,
+    /// One-based position of the offending token
     pub line: usize,
     pub column: usize,
+    /// The offending token itself
+    pub token: Span,
+    /// The source skipped to recover: from the failed statement to where parsing resumed
+    pub span: Span,
+}
+
+impl CompatParseError {
+    pub fn message(&self) -> String {
+        self.error.to_string()
+    }
+
+    pub fn code(&self) -> &'static DiagnosticCode {
+        self.error.code()
+    }
+}
+
+impl ParsedDocument {
+    /// Whether the parse recovered no statement from a text that has some
+    pub fn lost_ast(&self) -> bool {
+        self.ast.is_empty() && !self.text.trim().is_empty()
+    }
+
+    /// Remember `previous` as the last good version if this parse lost the AST,
+    /// or whatever `previous` remembered if it lost it too
+    pub fn keep_last_good(&mut self, previous: &ParsedDocument) {
+        if !self.lost_ast() {
+            return;
+        }
+        self.last_good = if previous.lost_ast() {
+            previous.last_good.clone()
+        } else {
+            Some(Arc::new(previous.clone()))
+        };
+    }
+
+    /// This document with `text` in place of its own, when the two differ only in
+    /// comments and whitespace inside lines ([`crate::token_diff`]). Symbols are
+    /// rebuilt so documentation taken from comments stays current.
+    pub fn with_comments_from(&self, text: &str) -> ParsedDocument {
+        let mut lexer = Lexer::new(text);
+        while lexer.next_token() != Token::EOF {}
+        let mut ast = self.ast.clone();
+        ast.set_comments(lexer.take_comments());
+        let line_index = LineIndex::new(text);
+        let metadata = header::metadata(&header::directives(
+            ast.comments(),
+            code_start(&ast, &self.errors),
+        ));
+        ParsedDocument {
+            text: text.to_string(),
+            metadata,
+            mask: Mask::new(&lexer.take_strings(), ast.comments()),
+            symbols: SymbolTable::from_ast(&ast, text, &line_index),
+            line_index,
+            ast,
+            ..self.clone()
+        }
+    }
+
+    /// This document, or its last good version while it has no AST. The text
+    /// is always the current one, so cursor-relative lookups stay accurate.
+    pub fn usable(&self) -> Usable<'_> {
+        match &self.last_good {
+            Some(last_good) if self.lost_ast() => Usable {
+                doc: Cow::Owned(ParsedDocument {
+                    text: self.text.clone(),
+                    line_index: self.line_index.clone(),
+                    mask: self.mask.clone(),
+                    errors: self.errors.clone(),
+                    metadata: self.metadata.clone(),
+                    last_good: None,
+                    ..ParsedDocument::clone(last_good)
+                }),
+                stale: true,
+            },
+            _ => Usable {
+                doc: Cow::Borrowed(self),
+                stale: false,
+            },
+        }
+    }
+
+    /// Every `//!` line, in and after the header
+    pub fn directives(&self) -> Vec<header::Directive> {
+        header::directives(self.ast.comments(), code_start(&self.ast, &self.errors))
+    }
+
+    /// Identifiers mentioned in the regions skipped after parse errors. Rules that
+    /// reason about the whole file treat these as both defined and used.
+    pub fn names_in_errors(&self) -> HashSet<Name> {
+        let mut names = HashSet::new();
+        if self.errors.is_empty() {
+            return names;
+        }
+        let mut lexer = Lexer::new(&self.text);
+        loop {
+            match lexer.next_token() {
+                Token::EOF => break,
+                Token::Identifier(name)
+                    if self
+                        .errors
+                        .iter()
+                        .any(|error| span_contains(error.span, lexer.token_span().start)) =>
+                {
+                    names.insert(name);
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+}
+
+/// Where the code starts: the first statement, or a failed one before it
+fn code_start(ast: &Program, errors: &[CompatParseError]) -> Option<Span> {
+    let statement = ast.first().map(|stmt| stmt.span);
+    let error = errors.first().map(|error| error.span);
+    match (statement, error) {
+        (Some(statement), Some(error)) => Some(if error.start < statement.start {
+            error
+        } else {
+            statement
+        }),
+        (statement, error) => statement.or(error),
+    }
 }
 
 impl Parser {
@@ -151,31 +457,75 @@ impl Parser {
         let mut lexer = Lexer::new(input);
         let current = lexer.next_token();
         let current_ws = lexer.had_whitespace();
+        let current_span = lexer.token_span();
         let peek = lexer.next_token();
         let peek_ws = lexer.had_whitespace();
-        let line = lexer.line();
-        let column = lexer.column();
+        let peek_span = lexer.token_span();
 
         Parser {
             input_text: input.to_string(),
             lexer,
             current_token: current,
             peek_token: peek,
-            current_line: line,
-            current_column: column,
             current_had_whitespace: current_ws,
             peek_had_whitespace: peek_ws,
+            current_span,
+            peek_span,
+            prev_end: Position::default(),
+            naming_issues: Vec::new(),
+            lenient_keywords: false,
+            keyword_casings: Vec::new(),
+            stray_assigns: Vec::new(),
+            depth: 0,
         }
     }
 
+    /// Read an identifier that spells a keyword in another case as that keyword
+    /// where the keyword fits: at the start of a statement, or where the grammar
+    /// expects it, like `in` after `For X`. Elsewhere `IF` stays a name.
+    pub fn lenient_keywords(mut self, lenient: bool) -> Self {
+        self.lenient_keywords = lenient;
+        self
+    }
+
+    /// Run `parse` one nesting level deeper, failing past [`MAX_NESTING`]
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::TooDeep {
+                line: self.current_line(),
+                column: self.current_column(),
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     /// Advance to the next token
     fn next_token(&mut self) {
+        if !matches!(self.current_token, Token::Newline | Token::Semicolon) {
+            self.prev_end = self.current_span.end;
+        }
         self.current_token = self.peek_token.clone();
         self.current_had_whitespace = self.peek_had_whitespace;
+        self.current_span = self.peek_span;
         self.peek_token = self.lexer.next_token();
         self.peek_had_whitespace = self.lexer.had_whitespace();
-        self.current_line = self.lexer.line();
-        self.current_column = self.lexer.column();
+        self.peek_span = self.lexer.token_span();
+    }
+
+    /// One-based line where the current token starts, as reported in errors
+    fn current_line(&self) -> usize {
+        self.current_span.start.line as usize + 1
+    }
+
+    /// One-based column where the current token starts, as reported in errors
+    fn current_column(&self) -> usize {
+        self.current_span.start.character as usize + 1
     }
 
     /// Skip newline tokens (they're optional in many places)
@@ -185,69 +535,144 @@ impl Parser {
         }
     }
 
+    /// Step past an `=` at the current token, noting it as `kind`; returns
+    /// whether there was one
+    fn skip_stray_assign(&mut self, kind: impl FnOnce() -> StrayAssignKind) -> bool {
+        if self.current_token != Token::Assign {
+            return false;
+        }
+        let span = self.current_span;
+        self.next_token();
+        self.stray_assigns.push(StrayAssign {
+            span,
+            next: self.current_span.start,
+            kind: kind(),
+        });
+        true
+    }
+
+    /// Whether the current token is `keyword`, taking an identifier spelling it
+    /// in another case as the keyword under [`Parser::lenient_keywords`]
+    fn at_keyword(&mut self, keyword: &Token) -> bool {
+        if self.lenient_keywords
+            && let Token::Identifier(name) = &self.current_token
+            && let Some((spelling, _)) = KEYWORDS
+                .iter()
+                .find(|(spelling, token)| token == keyword && spelling.eq_ignore_ascii_case(name))
+        {
+            self.keyword_casings.push(KeywordCasing {
+                span: self.current_span,
+                keyword: spelling,
+            });
+            self.current_token = keyword.clone();
+        }
+        self.current_token == *keyword
+    }
+
+    /// Under [`Parser::lenient_keywords`], read an identifier starting a statement
+    /// as the statement keyword it spells, unless what follows makes it a name
+    fn lenient_statement_keyword(&mut self) {
+        let Token::Identifier(name) = &self.current_token else {
+            return;
+        };
+        let Some(keyword) = STATEMENT_KEYWORDS.iter().find(|keyword| {
+            KEYWORDS
+                .iter()
+                .any(|(spelling, token)| token == *keyword && spelling.eq_ignore_ascii_case(name))
+        }) else {
+            return;
+        };
+        let takes_parens = matches!(keyword, Token::If | Token::While | Token::Switch);
+        let continues_name = match &self.peek_token {
+            // `TRY.COUNT`, `SET = 1`, `RETURN + 1`
+            Token::Dot
+            | Token::Assign
+            | Token::Plus
+            | Token::Multiply
+            | Token::Divide
+            | Token::Modulo
+            | Token::Equal
+            | Token::NotEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Less
+            | Token::LessEqual
+            | Token::And
+            | Token::Or
+            | Token::Arrow
+            | Token::Comma
+            | Token::Colon
+            | Token::RightParen
+            | Token::RightBracket => true,
+            // `FOR[0]` indexes and `BREAK(X)` calls, but `if(X)` is a condition
+            Token::LeftBracket | Token::LeftParen => !self.peek_had_whitespace && !takes_parens,
+            _ => false,
+        };
+        if !continues_name {
+            self.at_keyword(keyword);
+        }
+    }
+
     /// Check if current token matches expected, advance if true
     fn expect_token(&mut self, expected: Token) -> Result<(), ParseError> {
-        if self.current_token == expected {
+        if self.at_keyword(&expected) {
             self.next_token();
             Ok(())
         } else {
             Err(ParseError::UnexpectedToken {
                 expected: format!("{:?}", expected),
                 found: self.current_token.clone(),
-                line: self.current_line,
-                column: self.current_column,
+                line: self.current_line(),
+                column: self.current_column(),
             })
         }
     }
 
-    /// Helper to check if identifier follows naming convention (UPPER_SNAKE_CASE)
-    fn validate_identifier(&self, name: &str) -> Result<(), ParseError> {
-        self.validate_identifier_internal(name, false)
+    /// Check the name of a definition: a name that can't be an identifier is an error,
+    /// one outside UPPER_SNAKE_CASE only a naming issue, reported later as a warning
+    fn check_definition_name(&mut self, name: &str) -> Result<(), ParseError> {
+        self.validate_identifier_internal(name, false)?;
+        self.note_naming(&Ident::new(name.to_string(), self.current_span), false);
+        Ok(())
+    }
+
+    fn note_naming(&mut self, name: &Ident, is_param: bool) {
+        if !is_upper_snake_case(&name.name) {
+            self.naming_issues.push(NamingIssue {
+                name: name.clone(),
+                is_param,
+            });
+        }
     }
 
-    /// Helper to check if identifier follows naming convention
+    /// Helper to check that an identifier is usable as a name
     /// For function parameters, we allow more flexible naming (can use lowercase)
     fn validate_identifier_internal(&self, name: &str, is_param: bool) -> Result<(), ParseError> {
         // Check it doesn't start with a number
-        if name.chars().next().map_or(false, |c| c.is_numeric()) {
+        if name.chars().next().is_some_and(|c| c.is_numeric()) {
             return Err(ParseError::InvalidIdentifier {
                 name: name.to_string(),
                 reason: "标识符不能以数字开头".to_string(),
-                line: self.current_line,
-                column: self.current_column,
+                line: self.current_line(),
+                column: self.current_column(),
             });
         }
 
-        // For function parameters, allow lowercase letters
-        if is_param {
-            let is_valid = name
-                .chars()
-                .all(|c| c.is_alphabetic() || c.is_numeric() || c == '_');
-
-            if !is_valid {
-                return Err(ParseError::InvalidIdentifier {
-                    name: name.to_string(),
-                    reason: "参数名只能包含字母、数字和下划线".to_string(),
-                    line: self.current_line,
-                    column: self.current_column,
-                });
-            }
-        } else {
-            // For variables and function names, require uppercase
-            let is_valid = name
-                .chars()
-                .all(|c| c.is_uppercase() || c.is_numeric() || c == '_');
-
-            if !is_valid {
-                return Err(ParseError::InvalidIdentifier {
-                    name: name.to_string(),
-                    reason:
-                        "变量名和函数名必须使用全大写字母和下划线（例如：MY_VAR, CALCULATE_SUM）"
-                            .to_string(),
-                    line: self.current_line,
-                    column: self.current_column,
-                });
-            }
+        let is_valid = name
+            .chars()
+            .all(|c| c.is_alphabetic() || c.is_numeric() || c == '_');
+        if !is_valid {
+            let reason = if is_param {
+                "参数名只能包含字母、数字和下划线"
+            } else {
+                "名称只能包含字母、数字和下划线"
+            };
+            return Err(ParseError::InvalidIdentifier {
+                name: name.to_string(),
+                reason: reason.to_string(),
+                line: self.current_line(),
+                column: self.current_column(),
+            });
         }
 
         Ok(())
@@ -276,12 +701,12 @@ impl Parser {
             Token::Plus | Token::Minus => Precedence::Sum,
             Token::Multiply | Token::Divide | Token::Modulo => Precedence::Product,
             Token::LeftParen => Precedence::Call,
-            Token::LeftBracket => Precedence::Index,
+            Token::LeftBracket | Token::Dot => Precedence::Index,
             _ => Precedence::Lowest,
         }
     }
 
-    /// Parse a complete program
+    /// Parse a complete program, stopping at the first error
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
         let mut statements = Vec::new();
 
@@ -293,39 +718,120 @@ impl Parser {
             self.skip_newlines();
         }
 
-        Ok(statements)
+        Ok(Program::new(statements, self.lexer.take_comments()))
     }
 
-    /// Compatibility parse() used by backend/diagnostics/completion
+    /// Compatibility parse() used by backend/diagnostics/completion.
+    ///
+    /// A statement that fails to parse is recorded as an error and skipped up to
+    /// the next line starting with a statement keyword in the first column, so
+    /// the rest of the file still produces an AST.
     pub fn parse(&mut self) -> ParsedDocument {
-        match self.parse_program() {
-            Ok(ast) => {
-                // Extract symbols from the AST
-                let symbols = SymbolTable::from_ast(&ast, &self.input_text);
-
-                ParsedDocument {
-                    text: self.input_text.clone(),
-                    ast,
-                    symbols,
-                    errors: Vec::new(),
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
+
+        self.skip_newlines();
+        while self.current_token != Token::EOF {
+            let start = self.current_span.start;
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => {
+                    // Errors are raised at the token that doesn't fit
+                    let position = (self.current_line(), self.current_column());
+                    let (line, column) = e.position().unwrap_or(position);
+                    let token = if (line, column) == position {
+                        self.current_span
+                    } else {
+                        let start = Position::new(line as u32 - 1, column as u32 - 1);
+                        Span { start, end: start }
+                    };
+                    self.synchronize();
+                    errors.push(CompatParseError {
+                        error: e,
+                        line,
+                        column,
+                        token,
+                        span: Span {
+                            start,
+                            end: self.prev_end.max(start),
+                        },
+                    });
                 }
             }
-            Err(e) => ParsedDocument {
-                text: self.input_text.clone(),
-                ast: Vec::new(),
-                symbols: SymbolTable::new(),
-                errors: vec![CompatParseError {
-                    message: e.to_string(),
-                    line: self.current_line,
-                    column: self.current_column,
-                }],
-            },
+            self.skip_newlines();
+        }
+
+        let ast = Program::new(statements, self.lexer.take_comments());
+        let mask = Mask::new(&self.lexer.take_strings(), ast.comments());
+        // Extract symbols from the AST
+        let line_index = LineIndex::new(&self.input_text);
+        let symbols = SymbolTable::from_ast(&ast, &self.input_text, &line_index);
+        let occurrences = occurrences::collect(&ast);
+        let metadata = header::metadata(&header::directives(
+            ast.comments(),
+            code_start(&ast, &errors),
+        ));
+
+        ParsedDocument {
+            text: self.input_text.clone(),
+            line_index,
+            mask,
+            ast,
+            symbols,
+            occurrences,
+            errors,
+            naming_issues: std::mem::take(&mut self.naming_issues),
+            keyword_casings: std::mem::take(&mut self.keyword_casings),
+            stray_assigns: std::mem::take(&mut self.stray_assigns),
+            metadata,
+            last_good: None,
+        }
+    }
+
+    /// Skip at least one token, then up to a statement keyword that starts a line
+    fn synchronize(&mut self) {
+        if self.current_token == Token::EOF {
+            return;
+        }
+        loop {
+            let at_line_start = self.current_token == Token::Newline;
+            self.next_token();
+            if self.current_token == Token::EOF {
+                return;
+            }
+            if at_line_start
+                && self.current_span.start.character == 0
+                && matches!(
+                    self.current_token,
+                    Token::Set
+                        | Token::Func
+                        | Token::Generator
+                        | Token::Lazy
+                        | Token::Import
+                        | Token::Export
+                        | Token::While
+                        | Token::For
+                        | Token::Switch
+                        | Token::Throw
+                        | Token::Try
+                )
+            {
+                return;
+            }
         }
     }
 
     /// Parse a statement
     fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
-        match &self.current_token {
+        self.nested(Self::parse_statement_at_depth)
+    }
+
+    fn parse_statement_at_depth(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.current_span.start;
+        if self.lenient_keywords {
+            self.lenient_statement_keyword();
+        }
+        let kind = match &self.current_token {
             Token::Set => self.parse_set_statement(),
             Token::Func => self.parse_func_definition(),
             Token::Generator => self.parse_generator_definition(),
@@ -340,12 +846,28 @@ impl Parser {
             Token::Import => self.parse_import_statement(),
             Token::Export => self.parse_export_statement(),
             Token::Throw => self.parse_throw_statement(),
+            Token::Try => self.parse_try_statement(),
             _ => self.parse_expression_statement(),
+        }?;
+
+        Ok(Stmt::new(kind, self.span_from(start)))
+    }
+
+    /// Span from `start` to the end of the last consumed token
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            start,
+            end: self.prev_end,
         }
     }
 
+    /// Build an expression spanning from `start` to the last consumed token
+    fn finish_expr(&self, start: Position, kind: ExprKind) -> Expr {
+        Expr::new(kind, self.span_from(start))
+    }
+
     /// Parse: Set NAME value
-    fn parse_set_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_set_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Set'
 
         // Parse the left-hand side (target)
@@ -354,20 +876,21 @@ impl Parser {
 
         let name = match &self.current_token {
             Token::Identifier(n) => {
-                self.validate_identifier(n)?;
-                n.clone()
+                self.validate_identifier_internal(n, false)?;
+                Ident::new(n.clone(), self.current_span)
             }
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
 
         self.next_token(); // move past identifier
+        self.skip_stray_assign(|| StrayAssignKind::InSet);
 
         // Check if followed by '[' for index access
         // CRITICAL: Distinguish between:
@@ -387,7 +910,8 @@ impl Parser {
                 if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
                     self.next_token();
                 }
-                return Ok(Stmt::Set { name, value });
+                self.note_naming(&name, false);
+                return Ok(StmtKind::Set { name, value });
             }
 
             // No space before '[' means this is: Set NAME[index] value
@@ -400,12 +924,13 @@ impl Parser {
                 return Err(ParseError::UnexpectedToken {
                     expected: "']' for index access".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
 
             self.next_token(); // skip ']'
+            self.skip_stray_assign(|| StrayAssignKind::InSet);
 
             // Now parse the value to assign
             let value = self.parse_expression(Precedence::Lowest)?;
@@ -414,8 +939,11 @@ impl Parser {
                 self.next_token();
             }
 
-            return Ok(Stmt::SetIndex {
-                object: Box::new(Expr::Identifier(name)),
+            return Ok(StmtKind::SetIndex {
+                object: Box::new(Expr::new(
+                    ExprKind::Identifier(name.name.clone()),
+                    name.span,
+                )),
                 index: Box::new(index),
                 value,
             });
@@ -428,25 +956,26 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Stmt::Set { name, value })
+        self.note_naming(&name, false);
+        Ok(StmtKind::Set { name, value })
     }
 
     /// Parse: Func NAME (params) { body }
-    fn parse_func_definition(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_func_definition(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Func'
 
         let name = match &self.current_token {
             Token::Identifier(name) => {
-                // Validate function name
-                self.validate_identifier(name)?;
-                name.clone()
+                let name = name.clone();
+                self.check_definition_name(&name)?;
+                Ident::new(name, self.current_span)
             }
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -464,21 +993,25 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Stmt::FuncDef { name, params, body })
+        Ok(StmtKind::FuncDef { name, params, body })
     }
 
     /// Parse: Generator NAME (params) { body }
-    fn parse_generator_definition(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_generator_definition(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Generator'
 
         let name = match &self.current_token {
-            Token::Identifier(name) => name.clone(),
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.check_definition_name(&name)?;
+                Ident::new(name, self.current_span)
+            }
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -496,21 +1029,25 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Stmt::GeneratorDef { name, params, body })
+        Ok(StmtKind::GeneratorDef { name, params, body })
     }
 
     /// Parse: Lazy NAME (expr)
-    fn parse_lazy_definition(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_lazy_definition(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Lazy'
 
         let name = match &self.current_token {
-            Token::Identifier(name) => name.clone(),
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.check_definition_name(&name)?;
+                Ident::new(name, self.current_span)
+            }
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -526,73 +1063,74 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Stmt::LazyDef { name, expr })
+        Ok(StmtKind::LazyDef { name, expr })
     }
 
     /// Parse: Return expr
-    fn parse_return_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_return_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Return'
 
-        let expr =
-            if self.current_token == Token::Newline || self.current_token == Token::RightBrace {
-                Expr::Null
-            } else {
-                self.parse_expression(Precedence::Lowest)?
-            };
+        let expr = self.parse_optional_operand()?;
 
         if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Stmt::Return(expr))
+        Ok(StmtKind::Return(expr))
     }
 
     /// Parse: Yield expr
-    fn parse_yield_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_yield_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Yield'
 
-        let expr =
-            if self.current_token == Token::Newline || self.current_token == Token::RightBrace {
-                Expr::Null
-            } else {
-                self.parse_expression(Precedence::Lowest)?
-            };
+        let expr = self.parse_optional_operand()?;
 
         if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Stmt::Yield(expr))
+        Ok(StmtKind::Yield(expr))
+    }
+
+    /// The operand of `Return` or `Yield`, `Null` when the statement ends right
+    /// after the keyword
+    fn parse_optional_operand(&mut self) -> Result<Expr, ParseError> {
+        match self.current_token {
+            Token::Newline | Token::Semicolon | Token::RightBrace | Token::EOF => {
+                Ok(Expr::new(ExprKind::Null, self.current_span))
+            }
+            _ => self.parse_expression(Precedence::Lowest),
+        }
     }
 
     /// Parse: Break
-    fn parse_break_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_break_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Break'
 
         if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Stmt::Break)
+        Ok(StmtKind::Break)
     }
 
     /// Parse: Continue
-    fn parse_continue_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_continue_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Continue'
 
         if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Stmt::Continue)
+        Ok(StmtKind::Continue)
     }
 
     /// Parse: While (condition) { body }
-    fn parse_while_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_while_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'While'
         self.expect_token(Token::LeftParen)?;
 
-        let condition = self.parse_expression(Precedence::Lowest)?;
+        let condition = self.parse_condition()?;
 
         self.expect_token(Token::RightParen)?;
         self.skip_newlines();
@@ -602,21 +1140,21 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Stmt::While { condition, body })
+        Ok(StmtKind::While { condition, body })
     }
 
     /// Parse: For VAR In ITERABLE { body }
-    fn parse_for_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_for_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'For'
 
         let first_var = match &self.current_token {
-            Token::Identifier(name) => name.clone(),
+            Token::Identifier(name) => Ident::new(name.clone(), self.current_span),
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -628,13 +1166,13 @@ impl Parser {
             self.next_token(); // skip comma
 
             let second_var = match &self.current_token {
-                Token::Identifier(name) => name.clone(),
+                Token::Identifier(name) => Ident::new(name.clone(), self.current_span),
                 _ => {
                     return Err(ParseError::UnexpectedToken {
                         expected: "identifier".to_string(),
                         found: self.current_token.clone(),
-                        line: self.current_line,
-                        column: self.current_column,
+                        line: self.current_line(),
+                        column: self.current_column(),
                     });
                 }
             };
@@ -651,7 +1189,7 @@ impl Parser {
 
             self.expect_token(Token::RightBrace)?;
 
-            return Ok(Stmt::ForIndexed {
+            return Ok(StmtKind::ForIndexed {
                 index_var: first_var,
                 value_var: second_var,
                 iterable,
@@ -671,7 +1209,7 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Stmt::For {
+        Ok(StmtKind::For {
             var: first_var,
             iterable,
             body,
@@ -679,7 +1217,7 @@ impl Parser {
     }
 
     /// Parse: Switch (expr) { Case val: ... Default: ... }
-    fn parse_switch_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_switch_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Switch'
         self.expect_token(Token::LeftParen)?;
 
@@ -694,15 +1232,15 @@ impl Parser {
         let mut default = None;
 
         while self.current_token != Token::RightBrace && self.current_token != Token::EOF {
-            if self.current_token == Token::Case {
+            if self.at_keyword(&Token::Case) {
                 self.next_token();
                 let case_expr = self.parse_expression(Precedence::Lowest)?;
                 self.expect_token(Token::Colon)?;
                 self.skip_newlines();
 
                 let mut case_body = Vec::new();
-                while self.current_token != Token::Case
-                    && self.current_token != Token::Default
+                while !self.at_keyword(&Token::Case)
+                    && !self.at_keyword(&Token::Default)
                     && self.current_token != Token::RightBrace
                     && self.current_token != Token::EOF
                 {
@@ -711,7 +1249,7 @@ impl Parser {
                 }
 
                 cases.push((case_expr, case_body));
-            } else if self.current_token == Token::Default {
+            } else if self.at_keyword(&Token::Default) {
                 self.next_token();
                 self.expect_token(Token::Colon)?;
                 self.skip_newlines();
@@ -731,53 +1269,58 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Stmt::Switch {
+        Ok(StmtKind::Switch {
             expr,
             cases,
             default,
         })
     }
 
+    /// Parse an optional `As ALIAS` after an imported name. The alias is the
+    /// name this file picks, so it gets the naming check; the imported name
+    /// belongs to the other module.
+    fn parse_import_alias(&mut self) -> Option<Ident> {
+        if !self.at_keyword(&Token::As) {
+            return None;
+        }
+        self.next_token();
+        let Token::Identifier(alias) = &self.current_token else {
+            return None;
+        };
+        let alias = Ident::new(alias.clone(), self.current_span);
+        self.note_naming(&alias, false);
+        self.next_token();
+        Some(alias)
+    }
+
     /// Parse: Import {NAME1, NAME2} From "path"
-    fn parse_import_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_import_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Import'
 
         let mut names = Vec::new();
         let mut aliases = Vec::new();
+        let braced = self.current_token == Token::LeftBrace;
 
         // Import {NAME1, NAME2, ...}
-        if self.current_token == Token::LeftBrace {
+        if braced {
             self.next_token();
             self.skip_newlines();
 
             while self.current_token != Token::RightBrace && self.current_token != Token::EOF {
                 let name = match &self.current_token {
-                    Token::Identifier(n) => n.clone(),
+                    Token::Identifier(n) => Ident::new(n.clone(), self.current_span),
                     _ => {
                         return Err(ParseError::UnexpectedToken {
                             expected: "identifier".to_string(),
                             found: self.current_token.clone(),
-                            line: self.current_line,
-                            column: self.current_column,
+                            line: self.current_line(),
+                            column: self.current_column(),
                         });
                     }
                 };
 
                 self.next_token();
-
-                // Check for alias: as ALIAS
-                let alias = if self.current_token == Token::As {
-                    self.next_token();
-                    if let Token::Identifier(a) = &self.current_token.clone() {
-                        let alias_name = a.clone();
-                        self.next_token();
-                        Some(alias_name)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
+                let alias = self.parse_import_alias();
 
                 names.push(name);
                 aliases.push(alias);
@@ -794,30 +1337,18 @@ impl Parser {
         } else {
             // Import NAME
             let name = match &self.current_token {
-                Token::Identifier(n) => n.clone(),
+                Token::Identifier(n) => Ident::new(n.clone(), self.current_span),
                 _ => {
                     return Err(ParseError::UnexpectedToken {
                         expected: "identifier".to_string(),
                         found: self.current_token.clone(),
-                        line: self.current_line,
-                        column: self.current_column,
+                        line: self.current_line(),
+                        column: self.current_column(),
                     });
                 }
             };
             self.next_token();
-
-            let alias = if self.current_token == Token::As {
-                self.next_token();
-                if let Token::Identifier(a) = &self.current_token.clone() {
-                    let alias_name = a.clone();
-                    self.next_token();
-                    Some(alias_name)
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+            let alias = self.parse_import_alias();
 
             names.push(name);
             aliases.push(alias);
@@ -831,8 +1362,8 @@ impl Parser {
                 return Err(ParseError::UnexpectedToken {
                     expected: "string".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -843,25 +1374,26 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Stmt::Import {
+        Ok(StmtKind::Import {
             names,
             path,
             aliases,
+            braced,
         })
     }
 
     /// Parse: Export NAME
-    fn parse_export_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_export_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Export'
 
         let name = match &self.current_token {
-            Token::Identifier(n) => n.clone(),
+            Token::Identifier(n) => Ident::new(n.clone(), self.current_span),
             _ => {
                 return Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found: self.current_token.clone(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -872,11 +1404,11 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Stmt::Export(name))
+        Ok(StmtKind::Export(name))
     }
 
     /// Parse: Throw expr
-    fn parse_throw_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_throw_statement(&mut self) -> Result<StmtKind, ParseError> {
         self.next_token(); // skip 'Throw'
 
         let expr = self.parse_expression(Precedence::Lowest)?;
@@ -885,43 +1417,123 @@ impl Parser {
             self.next_token();
         }
 
-        Ok(Stmt::Throw(expr))
+        Ok(StmtKind::Throw(expr))
+    }
+
+    /// Parse: Try { body } Catch (ERROR) { handler }, the `(ERROR)` being optional
+    fn parse_try_statement(&mut self) -> Result<StmtKind, ParseError> {
+        self.next_token(); // skip 'Try'
+        self.skip_newlines();
+        self.expect_token(Token::LeftBrace)?;
+        let body = self.parse_block()?;
+        self.expect_token(Token::RightBrace)?;
+        self.skip_newlines();
+        self.expect_token(Token::Catch)?;
+
+        let error_var = if self.current_token == Token::LeftParen {
+            self.next_token(); // skip '('
+            let Token::Identifier(name) = &self.current_token else {
+                return Err(ParseError::UnexpectedToken {
+                    expected: "identifier".to_string(),
+                    found: self.current_token.clone(),
+                    line: self.current_line(),
+                    column: self.current_column(),
+                });
+            };
+            self.validate_identifier_internal(name, false)?;
+            let error_var = Ident::new(name.clone(), self.current_span);
+            self.next_token();
+            self.expect_token(Token::RightParen)?;
+            Some(error_var)
+        } else {
+            None
+        };
+
+        self.skip_newlines();
+        self.expect_token(Token::LeftBrace)?;
+        let handler = self.parse_block()?;
+        self.expect_token(Token::RightBrace)?;
+
+        Ok(StmtKind::Try {
+            body,
+            error_var,
+            handler,
+        })
     }
 
     /// Parse expression as statement
-    fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn parse_expression_statement(&mut self) -> Result<StmtKind, ParseError> {
+        if let Token::Identifier(name) = &self.current_token
+            && self.peek_token == Token::Assign
+        {
+            // `X = 10`: read as the `Set X 10` it means
+            let name = Ident::new(name.clone(), self.current_span);
+            self.next_token();
+            let (span, next) = (self.current_span, self.peek_span.start);
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            self.stray_assigns.push(StrayAssign {
+                span,
+                next,
+                kind: StrayAssignKind::WithoutSet {
+                    name: name.clone(),
+                    value: value.span,
+                },
+            });
+            if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
+                self.next_token();
+            }
+            return Ok(StmtKind::Set { name, value });
+        }
+
         let expr = self.parse_expression(Precedence::Lowest)?;
 
         if self.current_token == Token::Newline || self.current_token == Token::Semicolon {
             self.next_token();
         }
 
-        Ok(Stmt::Expression(expr))
+        Ok(StmtKind::Expression(expr))
+    }
+
+    /// Parse the condition of an `If`, `Elif` or `While`, reading `X = 5` as the
+    /// `X == 5` it almost certainly means
+    fn parse_condition(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
+        let left = self.parse_expression(Precedence::Lowest)?;
+        if !self.skip_stray_assign(|| StrayAssignKind::InCondition) {
+            return Ok(left);
+        }
+        let right = self.parse_expression(Precedence::Lowest)?;
+        Ok(self.finish_expr(
+            start,
+            ExprKind::Binary {
+                left: Box::new(left),
+                op: BinOp::Equal,
+                right: Box::new(right),
+            },
+        ))
     }
 
     /// Parse parameter list: (A, B, C)
-    fn parse_parameter_list(&mut self) -> Result<Vec<String>, ParseError> {
+    fn parse_parameter_list(&mut self) -> Result<Vec<Ident>, ParseError> {
         let mut params = Vec::new();
 
         if self.current_token == Token::RightParen {
             return Ok(params);
         }
 
-        loop {
-            match &self.current_token {
-                Token::Identifier(name) => {
-                    // Validate parameter name (allow flexible naming)
-                    self.validate_identifier_internal(name, true)?;
-                    params.push(name.clone());
-                    self.next_token();
+        while let Token::Identifier(name) = &self.current_token {
+            // Validate parameter name (allow flexible naming)
+            self.validate_identifier_internal(name, true)?;
+            let param = Ident::new(name.clone(), self.current_span);
+            self.note_naming(&param, true);
+            params.push(param);
+            self.next_token();
 
-                    if self.current_token == Token::Comma {
-                        self.next_token();
-                    } else {
-                        break;
-                    }
-                }
-                _ => break,
+            if self.current_token == Token::Comma {
+                self.next_token();
+            } else {
+                break;
             }
         }
 
@@ -944,9 +1556,15 @@ impl Parser {
 
     /// Parse an expression using Pratt parsing
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expr, ParseError> {
+        self.nested(|parser| parser.parse_expression_at_depth(precedence))
+    }
+
+    fn parse_expression_at_depth(&mut self, precedence: Precedence) -> Result<Expr, ParseError> {
         let mut left = self.parse_prefix()?;
 
-        // After parse_prefix, current_token is at the first token after the prefix expression
+        // After parse_prefix, current_token is at the first token after the prefix expression.
+        // A `:` has no precedence, so it ends a `Case` label without being a
+        // terminator of its own; dict keys are parsed apart from their values.
         while precedence < self.current_precedence()
             && self.current_token != Token::Newline
             && self.current_token != Token::Semicolon
@@ -955,7 +1573,6 @@ impl Parser {
             && self.current_token != Token::RightBracket
             && self.current_token != Token::RightBrace
             && self.current_token != Token::Comma
-            && self.current_token != Token::Colon
         {
             left = self.parse_infix(left)?;
         }
@@ -965,35 +1582,36 @@ impl Parser {
 
     /// Parse prefix expressions
     fn parse_prefix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         match &self.current_token.clone() {
             Token::Number(n) => {
                 let num = *n;
                 self.next_token();
-                Ok(Expr::Number(num))
+                Ok(self.finish_expr(start, ExprKind::Number(num)))
             }
             Token::BigInteger(s) => {
                 let big_int_str = s.clone();
                 self.next_token();
-                Ok(Expr::BigInteger(big_int_str))
+                Ok(self.finish_expr(start, ExprKind::BigInteger(big_int_str)))
             }
             Token::String(s) => {
                 let string = s.clone();
                 self.next_token();
-                Ok(Expr::String(string))
+                Ok(self.finish_expr(start, ExprKind::String(string)))
             }
             Token::Boolean(b) => {
                 let bool_val = *b;
                 self.next_token();
-                Ok(Expr::Boolean(bool_val))
+                Ok(self.finish_expr(start, ExprKind::Boolean(bool_val)))
             }
             Token::Null => {
                 self.next_token();
-                Ok(Expr::Null)
+                Ok(self.finish_expr(start, ExprKind::Null))
             }
             Token::Identifier(name) => {
                 let ident = name.clone();
                 self.next_token();
-                Ok(Expr::Identifier(ident))
+                Ok(self.finish_expr(start, ExprKind::Identifier(ident)))
             }
             Token::LeftParen => self.parse_grouped_expression(),
             Token::LeftBracket => self.parse_array_literal(),
@@ -1003,10 +1621,23 @@ impl Parser {
             Token::If => self.parse_if_expression(),
             Token::Func => self.parse_lambda_expression(),
             Token::Lambda => self.parse_lambda_arrow_expression(),
-            _ => Err(ParseError::InvalidExpression {
-                message: "Unexpected token in expression".to_string(),
-                line: self.current_line,
-                column: self.current_column,
+            Token::Force => self.parse_force_expression(),
+            Token::Illegal('"') if let Some(unterminated) = self.lexer.unterminated_string() => {
+                let opening = unterminated.opening;
+                Err(ParseError::InvalidExpression {
+                    message: if unterminated.multiline {
+                        "Unterminated multiline string: no closing \"\"\" before the end of the file"
+                    } else {
+                        "Unterminated string: no closing quote before the end of the file"
+                    }
+                    .to_string(),
+                    line: opening.start.line as usize + 1,
+                    column: opening.start.character as usize + 1,
+                })
+            }
+            _ => Err(ParseError::ExpectedExpression {
+                line: self.current_line(),
+                column: self.current_column(),
             }),
         }
     }
@@ -1029,6 +1660,7 @@ impl Parser {
             | Token::Or => self.parse_binary_expression(left),
             Token::LeftParen => self.parse_call_expression(left),
             Token::LeftBracket => self.parse_index_expression(left),
+            Token::Dot => self.parse_member_expression(left),
             _ => Ok(left),
         }
     }
@@ -1048,13 +1680,14 @@ impl Parser {
             Err(ParseError::UnexpectedToken {
                 expected: "RightParen".to_string(),
                 found: self.current_token.clone(),
-                line: self.current_line,
-                column: self.current_column,
+                line: self.current_line(),
+                column: self.current_column(),
             })
         }
     }
     /// Parse array literal: [1, 2, 3]
     fn parse_array_literal(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip '['
 
         let mut elements = Vec::new();
@@ -1076,11 +1709,12 @@ impl Parser {
 
         self.expect_token(Token::RightBracket)?;
 
-        Ok(Expr::Array(elements))
+        Ok(self.finish_expr(start, ExprKind::Array(elements)))
     }
 
     /// Parse dictionary literal: {key: value, ...}
     fn parse_dict_literal(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip '{'
 
         let mut pairs = Vec::new();
@@ -1088,20 +1722,7 @@ impl Parser {
         self.skip_newlines();
 
         while self.current_token != Token::RightBrace && self.current_token != Token::EOF {
-            let key = match &self.current_token {
-                Token::Identifier(k) => k.clone(),
-                Token::String(k) => k.clone(),
-                _ => {
-                    return Err(ParseError::UnexpectedToken {
-                        expected: "identifier or string".to_string(),
-                        found: self.current_token.clone(),
-                        line: self.current_line,
-                        column: self.current_column,
-                    });
-                }
-            };
-
-            self.next_token();
+            let key = self.parse_dict_key()?;
             self.expect_token(Token::Colon)?;
 
             let value = self.parse_expression(Precedence::Lowest)?;
@@ -1120,16 +1741,50 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Expr::Dict(pairs))
+        Ok(self.finish_expr(start, ExprKind::Dict(pairs)))
     }
 
-    /// Parse unary expression: -expr or !expr
+    /// Parse the key of a dict entry, up to its colon: a name, a string, a
+    /// number or `[EXPR]`
+    fn parse_dict_key(&mut self) -> Result<DictKey, ParseError> {
+        let key = match &self.current_token {
+            Token::Identifier(key) => DictKey::Ident(key.to_string()),
+            Token::String(key) => DictKey::Str(key.clone()),
+            Token::Number(key) => DictKey::Num(*key),
+            Token::LeftBracket => {
+                self.next_token();
+                let key = self.parse_expression(Precedence::Lowest)?;
+                self.expect_token(Token::RightBracket)?;
+                return Ok(DictKey::Computed(key));
+            }
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    expected: "identifier, string, number or [expression]".to_string(),
+                    found: self.current_token.clone(),
+                    line: self.current_line(),
+                    column: self.current_column(),
+                });
+            }
+        };
+        self.next_token();
+        Ok(key)
+    }
+
+    /// Parse unary expression: -expr or !expr. A minus written right against a
+    /// number is part of the literal, so `-5` is the number -5.
     fn parse_unary_expression(&mut self, op: UnaryOp) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip operator
+        let negative_literal = op == UnaryOp::Minus
+            && !self.current_had_whitespace
+            && matches!(self.current_token, Token::Number(_));
 
         let expr = self.parse_expression(Precedence::Prefix)?;
 
-        Ok(Expr::unary(op, expr))
+        if negative_literal && let ExprKind::Number(number) = expr.kind {
+            return Ok(self.finish_expr(start, ExprKind::Number(-number)));
+        }
+        Ok(self.finish_expr(start, ExprKind::unary(op, expr)))
     }
 
     /// Parse binary expression: left op right
@@ -1151,8 +1806,8 @@ impl Parser {
             _ => {
                 return Err(ParseError::InvalidExpression {
                     message: "Invalid binary operator".to_string(),
-                    line: self.current_line,
-                    column: self.current_column,
+                    line: self.current_line(),
+                    column: self.current_column(),
                 });
             }
         };
@@ -1162,7 +1817,8 @@ impl Parser {
 
         let right = self.parse_expression(precedence)?;
 
-        Ok(Expr::binary(left, op, right))
+        let start = left.span.start;
+        Ok(self.finish_expr(start, ExprKind::binary(left, op, right)))
     }
 
     /// Parse function call: func(arg1, arg2, ...)
@@ -1186,7 +1842,8 @@ impl Parser {
 
         self.expect_token(Token::RightParen)?;
 
-        Ok(Expr::call(func, args))
+        let start = func.span.start;
+        Ok(self.finish_expr(start, ExprKind::call(func, args)))
     }
 
     /// Parse index expression: object[index]
@@ -1197,15 +1854,48 @@ impl Parser {
 
         self.expect_token(Token::RightBracket)?;
 
-        Ok(Expr::index(object, index))
+        let start = object.span.start;
+        Ok(self.finish_expr(start, ExprKind::index(object, index)))
+    }
+
+    /// Parse Force(expr)
+    fn parse_force_expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
+        self.next_token(); // skip 'Force'
+        self.expect_token(Token::LeftParen)?;
+        let expr = self.parse_expression(Precedence::Lowest)?;
+        self.expect_token(Token::RightParen)?;
+        Ok(self.finish_expr(start, ExprKind::Force(Box::new(expr))))
+    }
+
+    /// Parse member access: namespace.MEMBER
+    fn parse_member_expression(&mut self, object: Expr) -> Result<Expr, ParseError> {
+        self.next_token(); // skip '.'
+
+        let member = match &self.current_token {
+            Token::Identifier(name) => Ident::new(name.clone(), self.current_span),
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    expected: "identifier".to_string(),
+                    found: self.current_token.clone(),
+                    line: self.current_line(),
+                    column: self.current_column(),
+                });
+            }
+        };
+        self.next_token();
+
+        let start = object.span.start;
+        Ok(self.finish_expr(start, ExprKind::member(object, member)))
     }
 
     /// Parse if expression: If (cond) { ... } Elif (cond) { ... } Else { ... }
     fn parse_if_expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip 'If'
         self.expect_token(Token::LeftParen)?;
 
-        let condition = self.parse_expression(Precedence::Lowest)?;
+        let condition = self.parse_condition()?;
 
         self.expect_token(Token::RightParen)?;
         self.skip_newlines();
@@ -1217,11 +1907,11 @@ impl Parser {
         self.skip_newlines();
 
         let mut elif_branches = Vec::new();
-        while self.current_token == Token::Elif {
+        while self.at_keyword(&Token::Elif) {
             self.next_token();
             self.expect_token(Token::LeftParen)?;
 
-            let elif_cond = self.parse_expression(Precedence::Lowest)?;
+            let elif_cond = self.parse_condition()?;
 
             self.expect_token(Token::RightParen)?;
             self.skip_newlines();
@@ -1235,7 +1925,7 @@ impl Parser {
             elif_branches.push((elif_cond, elif_body));
         }
 
-        let else_branch = if self.current_token == Token::Else {
+        let else_branch = if self.at_keyword(&Token::Else) {
             self.next_token();
             self.skip_newlines();
             self.expect_token(Token::LeftBrace)?;
@@ -1249,16 +1939,20 @@ impl Parser {
             None
         };
 
-        Ok(Expr::If {
-            condition: Box::new(condition),
-            then_branch,
-            elif_branches,
-            else_branch,
-        })
+        Ok(self.finish_expr(
+            start,
+            ExprKind::If {
+                condition: Box::new(condition),
+                then_branch,
+                elif_branches,
+                else_branch,
+            },
+        ))
     }
 
     /// Parse lambda expression: Func(params) { body }
     fn parse_lambda_expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip 'Func'
         self.expect_token(Token::LeftParen)?;
 
@@ -1272,11 +1966,12 @@ impl Parser {
 
         self.expect_token(Token::RightBrace)?;
 
-        Ok(Expr::Lambda { params, body })
+        Ok(self.finish_expr(start, ExprKind::Lambda { params, body }))
     }
 
     /// Parse lambda arrow expression: Lambda X -> expr or Lambda (X, Y) -> expr
     fn parse_lambda_arrow_expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span.start;
         self.next_token(); // skip 'Lambda'
 
         let params = if self.current_token == Token::LeftParen {
@@ -1290,7 +1985,8 @@ impl Parser {
             match &self.current_token {
                 Token::Identifier(name) => {
                     self.validate_identifier_internal(name, true)?;
-                    let param = name.clone();
+                    let param = Ident::new(name.clone(), self.current_span);
+                    self.note_naming(&param, true);
                     self.next_token();
                     vec![param]
                 }
@@ -1298,8 +1994,8 @@ impl Parser {
                     return Err(ParseError::UnexpectedToken {
                         expected: "identifier or '('".to_string(),
                         found: self.current_token.clone(),
-                        line: self.current_line,
-                        column: self.current_column,
+                        line: self.current_line(),
+                        column: self.current_column(),
                     });
                 }
             }
@@ -1312,9 +2008,10 @@ impl Parser {
         let expr = self.parse_expression(Precedence::Lowest)?;
 
         // Wrap the expression in a Return statement
-        let body = vec![Stmt::Return(expr)];
+        let span = expr.span;
+        let body = vec![Stmt::new(StmtKind::Return(expr), span)];
 
-        Ok(Expr::Lambda { params, body })
+        Ok(self.finish_expr(start, ExprKind::Lambda { params, body }))
     }
 }
 
@@ -1329,10 +2026,10 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::Set { name, value } => {
+        match &program[0].kind {
+            StmtKind::Set { name, value } => {
                 assert_eq!(name, "X");
-                assert_eq!(*value, Expr::Number(10.0));
+                assert_eq!(value.kind, ExprKind::Number(10.0));
             }
             _ => panic!("Expected Set statement"),
         }
@@ -1345,19 +2042,19 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::Set { name, value } => {
+        match &program[0].kind {
+            StmtKind::Set { name, value } => {
                 assert_eq!(name, "X");
                 // Should be: 5 + (3 * 2) due to precedence
-                match value {
-                    Expr::Binary { left, op, right } => {
-                        assert_eq!(**left, Expr::Number(5.0));
+                match &value.kind {
+                    ExprKind::Binary { left, op, right } => {
+                        assert_eq!(left.kind, ExprKind::Number(5.0));
                         assert_eq!(*op, BinOp::Add);
-                        match &**right {
-                            Expr::Binary { left, op, right } => {
-                                assert_eq!(**left, Expr::Number(3.0));
+                        match &right.kind {
+                            ExprKind::Binary { left, op, right } => {
+                                assert_eq!(left.kind, ExprKind::Number(3.0));
                                 assert_eq!(*op, BinOp::Multiply);
-                                assert_eq!(**right, Expr::Number(2.0));
+                                assert_eq!(right.kind, ExprKind::Number(2.0));
                             }
                             _ => panic!("Expected binary expression"),
                         }
@@ -1380,10 +2077,10 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::FuncDef { name, params, body } => {
+        match &program[0].kind {
+            StmtKind::FuncDef { name, params, body } => {
                 assert_eq!(name, "ADD");
-                assert_eq!(params, &vec!["A".to_string(), "B".to_string()]);
+                assert_eq!(params, &["A", "B"]);
                 assert_eq!(body.len(), 1);
             }
             _ => panic!("Expected FuncDef"),
@@ -1397,12 +2094,15 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::Expression(Expr::Call { func, args }) => {
-                assert_eq!(**func, Expr::Identifier("ADD".to_string()));
+        match &program[0].kind {
+            StmtKind::Expression(Expr {
+                kind: ExprKind::Call { func, args },
+                ..
+            }) => {
+                assert_eq!(func.kind, ExprKind::Identifier("ADD".into()));
                 assert_eq!(args.len(), 2);
-                assert_eq!(args[0], Expr::Number(5.0));
-                assert_eq!(args[1], Expr::Number(3.0));
+                assert_eq!(args[0].kind, ExprKind::Number(5.0));
+                assert_eq!(args[1].kind, ExprKind::Number(3.0));
             }
             _ => panic!("Expected function call"),
         }
@@ -1415,15 +2115,15 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::Set { name, value } => {
+        match &program[0].kind {
+            StmtKind::Set { name, value } => {
                 assert_eq!(name, "ARR");
-                match value {
-                    Expr::Array(elements) => {
+                match &value.kind {
+                    ExprKind::Array(elements) => {
                         assert_eq!(elements.len(), 3);
-                        assert_eq!(elements[0], Expr::Number(1.0));
-                        assert_eq!(elements[1], Expr::Number(2.0));
-                        assert_eq!(elements[2], Expr::Number(3.0));
+                        assert_eq!(elements[0].kind, ExprKind::Number(1.0));
+                        assert_eq!(elements[1].kind, ExprKind::Number(2.0));
+                        assert_eq!(elements[2].kind, ExprKind::Number(3.0));
                     }
                     _ => panic!("Expected array"),
                 }
@@ -1432,6 +2132,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_negative_number_literals() {
+        fn numbers(text: &str) -> Vec<f64> {
+            let doc = Parser::new(text).parse();
+            assert!(doc.errors.is_empty(), "{}\n{:?}", text, doc.errors);
+            let mut numbers = Vec::new();
+            crate::ast::for_each_expr(&doc.ast, &mut |expr| {
+                if let ExprKind::Number(number) = expr.kind {
+                    numbers.push(number);
+                }
+            });
+            numbers
+        }
+
+        assert_eq!(numbers("Set X -5"), vec![-5.0]);
+        assert_eq!(numbers("Set ARR [-1, 2, -3.5]"), vec![-1.0, 2.0, -3.5]);
+        assert_eq!(numbers("F(-1, -2)"), vec![-1.0, -2.0]);
+        assert_eq!(numbers("Set D {\"a\": -1, \"b\": 2}"), vec![-1.0, 2.0]);
+        assert_eq!(
+            numbers("Switch (X) {\n    Case -1:\n        Break\n    Case -2: Break\n}"),
+            vec![-1.0, -2.0]
+        );
+        assert_eq!(numbers("Set ARR[-1] -2"), vec![-1.0, -2.0]);
+        assert_eq!(numbers("Set X 2 * -3"), vec![2.0, -3.0]);
+        // An infix minus subtracts, whatever the spacing
+        assert_eq!(numbers("Set X 3 -5"), vec![3.0, 5.0]);
+        assert_eq!(numbers("Set X [1 -2]"), vec![1.0, 2.0]);
+
+        let doc = Parser::new("Set X -5\nSet Y - 5\nSet Z -(5)\nSet W -5[0]").parse();
+        let values: Vec<&Expr> = doc
+            .ast
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StmtKind::Set { value, .. } => value,
+                _ => panic!("Expected Set statement"),
+            })
+            .collect();
+        // The literal covers its sign
+        assert_eq!(values[0].span.start.character, 6);
+        assert_eq!(values[0].span.end.character, 8);
+        // A spaced or parenthesized operand, or one with a postfix, is negated
+        for value in &values[1..] {
+            assert!(
+                matches!(
+                    value.kind,
+                    ExprKind::Unary {
+                        op: UnaryOp::Minus,
+                        ..
+                    }
+                ),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_dict_keys() {
+        let doc =
+            Parser::new("Set D {NAME: 1, \"name\": 2, 3: 4, [K + 1]: 5, OUTER: {INNER: X > 0}}")
+                .parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let StmtKind::Set { value, .. } = &doc.ast[0].kind else {
+            panic!("Expected Set statement");
+        };
+        let ExprKind::Dict(pairs) = &value.kind else {
+            panic!("Expected dict");
+        };
+        let keys: Vec<&DictKey> = pairs.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys[0], &DictKey::Ident("NAME".to_string()));
+        assert_eq!(keys[1], &DictKey::Str("name".to_string()));
+        assert_eq!(keys[2], &DictKey::Num(3.0));
+        assert!(matches!(
+            keys[3],
+            DictKey::Computed(Expr {
+                kind: ExprKind::Binary { op: BinOp::Add, .. },
+                ..
+            })
+        ));
+        assert_eq!(keys[4], &DictKey::Ident("OUTER".to_string()));
+        // The value after a colon is a whole expression, dicts included
+        let ExprKind::Dict(inner) = &pairs[4].1.kind else {
+            panic!("Expected nested dict");
+        };
+        assert_eq!(inner[0].0, DictKey::Ident("INNER".to_string()));
+        assert!(matches!(
+            inner[0].1.kind,
+            ExprKind::Binary {
+                op: BinOp::Greater,
+                ..
+            }
+        ));
+
+        for text in ["Set D {(1): 2}", "Set D {[1: 2}", "Set D {TRUE_KEY 1}"] {
+            assert!(!Parser::new(text).parse().errors.is_empty(), "{}", text);
+        }
+    }
+
     #[test]
     fn test_parse_if_expression() {
         let input = r#"
@@ -1445,14 +2243,18 @@ mod tests {
         let program = parser.parse_program().unwrap();
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::Expression(Expr::If {
-                condition,
-                then_branch,
-                else_branch,
+        match &program[0].kind {
+            StmtKind::Expression(Expr {
+                kind:
+                    ExprKind::If {
+                        condition,
+                        then_branch,
+                        else_branch,
+                        ..
+                    },
                 ..
             }) => {
-                assert!(matches!(**condition, Expr::Binary { .. }));
+                assert!(matches!(condition.kind, ExprKind::Binary { .. }));
                 assert_eq!(then_branch.len(), 1);
                 assert!(else_branch.is_some());
             }
@@ -1477,17 +2279,441 @@ mod tests {
         }
 
         assert_eq!(program.len(), 1);
-        match &program[0] {
-            Stmt::For {
+        match &program[0].kind {
+            StmtKind::For {
                 var,
                 iterable,
                 body,
             } => {
                 assert_eq!(var, "I");
-                assert!(matches!(iterable, Expr::Call { .. }));
+                assert!(matches!(iterable.kind, ExprKind::Call { .. }));
                 assert_eq!(body.len(), 1);
             }
             _ => panic!("Expected For statement"),
         }
     }
+
+    #[test]
+    fn test_statement_and_expression_spans() {
+        let input = "Set X 1\nFunc ADD(A, B) {\n    Return (A + B)\n}\n";
+        let mut parser = Parser::new(input);
+        let program = parser.parse_program().unwrap();
+
+        assert_eq!(program.len(), 2);
+        let set_span = program[0].span;
+        assert_eq!((set_span.start.line, set_span.start.character), (0, 0));
+        assert_eq!((set_span.end.line, set_span.end.character), (0, 7));
+
+        let func_span = program[1].span;
+        assert_eq!((func_span.start.line, func_span.start.character), (1, 0));
+        assert_eq!((func_span.end.line, func_span.end.character), (3, 1));
+
+        match &program[1].kind {
+            StmtKind::FuncDef { name, params, body } => {
+                assert_eq!(
+                    name.span.start,
+                    Position {
+                        line: 1,
+                        character: 5
+                    }
+                );
+                assert_eq!(
+                    params[1].span.start,
+                    Position {
+                        line: 1,
+                        character: 12
+                    }
+                );
+                match &body[0].kind {
+                    StmtKind::Return(expr) => {
+                        assert_eq!(
+                            expr.span.start,
+                            Position {
+                                line: 2,
+                                character: 12
+                            }
+                        );
+                        assert_eq!(
+                            expr.span.end,
+                            Position {
+                                line: 2,
+                                character: 17
+                            }
+                        );
+                    }
+                    _ => panic!("Expected Return statement"),
+                }
+            }
+            _ => panic!("Expected FuncDef"),
+        }
+    }
+
+    #[test]
+    fn test_program_collects_imports_exports_and_comments() {
+        let input = "// helpers\nSet X 1\nImport {ADD, SUB As MINUS} From \"./math\"\nExport X\n/* tail\n   note */\nImport MUL From \"./mul\"\nExport Y\n";
+        let program = Parser::new(input).parse_program().unwrap();
+
+        assert_eq!(program.len(), 5);
+        let imports: Vec<(&str, Vec<&str>)> = program
+            .imports()
+            .iter()
+            .map(|import| {
+                (
+                    import.path.as_str(),
+                    import.bindings().map(|name| name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            imports,
+            vec![("./math", vec!["ADD", "MINUS"]), ("./mul", vec!["MUL"])]
+        );
+        assert_eq!(program.imports()[1].span.start.line, 6);
+
+        let exports: Vec<&str> = program.exports().iter().map(|name| name.as_str()).collect();
+        assert_eq!(exports, vec!["X", "Y"]);
+
+        let comments = program.comments();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].text, "// helpers");
+        assert!(!comments[0].block);
+        assert_eq!(comments[1].text, "/* tail\n   note */");
+        assert!(comments[1].block);
+        assert_eq!(comments[1].span.start, Position::new(4, 0));
+        assert_eq!(comments[1].span.end, Position::new(5, 10));
+    }
+
+    #[test]
+    fn test_parse_member_access() {
+        let program = Parser::new("Set CONFIG UTILS.PARSE(\"a.json\")[0]\nSet RATE 1.5")
+            .parse_program()
+            .unwrap();
+        let StmtKind::Set { value, .. } = &program[0].kind else {
+            panic!("expected Set");
+        };
+        let ExprKind::Index { object, .. } = &value.kind else {
+            panic!("expected Index, got {:?}", value.kind);
+        };
+        let ExprKind::Call { func, .. } = &object.kind else {
+            panic!("expected Call, got {:?}", object.kind);
+        };
+        let ExprKind::Member { object, member } = &func.kind else {
+            panic!("expected Member, got {:?}", func.kind);
+        };
+        assert_eq!(object.kind, ExprKind::Identifier("UTILS".into()));
+        assert_eq!(member.name, "PARSE");
+        assert_eq!(member.span.start, Position::new(0, 17));
+        assert_eq!(func.span.end, Position::new(0, 22));
+        assert!(matches!(
+            &program[1].kind,
+            StmtKind::Set { value, .. } if value.kind == ExprKind::Number(1.5)
+        ));
+
+        assert!(Parser::new("UTILS.").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_force_expression() {
+        let doc = Parser::new("Lazy LAZY_SUM(1 + 2)\nSet V Force(LAZY_SUM)").parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let StmtKind::Set { name, value } = &doc.ast[1].kind else {
+            panic!("expected Set, got {:?}", doc.ast[1].kind);
+        };
+        assert_eq!(name, "V");
+        let ExprKind::Force(inner) = &value.kind else {
+            panic!("expected Force, got {:?}", value.kind);
+        };
+        assert_eq!(inner.kind, ExprKind::Identifier("LAZY_SUM".into()));
+        assert_eq!(
+            (value.span.start.character, value.span.end.character),
+            (6, 21)
+        );
+
+        // Without parentheses the statement is skipped and parsing resumes on the next line
+        let doc = Parser::new("Set V Force LAZY_SUM\nSet W 1").parse();
+        assert_eq!(doc.errors.len(), 1, "{:?}", doc.errors);
+        assert_eq!(doc.errors[0].code().code, "E003");
+        assert_eq!((doc.errors[0].line, doc.errors[0].column), (1, 13));
+        assert_eq!(doc.ast.len(), 1);
+        assert!(matches!(&doc.ast[0].kind, StmtKind::Set { name, .. } if name == "W"));
+    }
+
+    #[test]
+    fn test_parse_recovers_after_errors() {
+        let text = "Set A 1\nFunc BROKEN( {\n    Return 1\n}\nSet B (2 +\nFunc GOOD() {\n    Return A\n}\n";
+        let doc = Parser::new(text).parse();
+        let names: Vec<Name> = doc
+            .ast
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StmtKind::Set { name, .. } | StmtKind::FuncDef { name, .. } => name.name.clone(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(names, vec!["A", "GOOD"]);
+
+        let spans: Vec<(u32, u32)> = doc
+            .errors
+            .iter()
+            .map(|error| (error.span.start.line, error.span.end.line))
+            .collect();
+        assert_eq!(spans, vec![(1, 3), (4, 4)]);
+        assert!(doc.symbols.find_by_name("GOOD").is_some());
+        assert!(doc.names_in_errors().contains("BROKEN"));
+        assert!(!doc.names_in_errors().contains("GOOD"));
+    }
+
+    #[test]
+    fn test_error_positions_point_at_offending_token() {
+        let position = |text: &str| {
+            let doc = Parser::new(text).parse();
+            assert_eq!(doc.errors.len(), 1, "{:?}", doc.errors);
+            (doc.errors[0].line, doc.errors[0].column)
+        };
+        // At the start of a line
+        assert_eq!(position("Set A 1\n) B"), (2, 1));
+        // Mid-line: the `{` where `)` was expected
+        assert_eq!(position("Func ADD(A, B {\n}"), (1, 15));
+        assert_eq!(position("Set A (1 + )"), (1, 12));
+        // Right after a newline: the first token of the next line, not the one after it
+        assert_eq!(position("Set A 1\n\nSet 2 3"), (3, 5));
+        // A dangling operator reports the line break that ended the expression
+        assert_eq!(position("Set A (1 +\nSet B 2"), (1, 11));
+
+        // The structured error keeps what was expected and the token found
+        let doc = Parser::new("Func ADD(A, B {\n}").parse();
+        let error = &doc.errors[0];
+        assert!(matches!(
+            &error.error,
+            ParseError::UnexpectedToken { expected, found: Token::LeftBrace, .. } if expected == "RightParen"
+        ));
+        assert_eq!(
+            error.token,
+            Span {
+                start: Position::new(0, 14),
+                end: Position::new(0, 15)
+            }
+        );
+        assert_eq!(
+            error.message(),
+            "Parse error at line 1, column 15: Expected RightParen, found LeftBrace"
+        );
+    }
+
+    #[test]
+    fn test_usable_falls_back_to_last_good_parse() {
+        let good = Parser::new("Func ADD(X, Y) {\n    Return X + Y\n}").parse();
+        assert!(!good.usable().stale);
+
+        let mut broken = Parser::new("Func ADD(X, Y) {\n    Return X + (Y\n}").parse();
+        assert!(broken.lost_ast());
+        broken.keep_last_good(&good);
+        let usable = broken.usable();
+        assert!(usable.stale);
+        assert!(usable.symbols.find_by_name("ADD").is_some());
+        assert_eq!(usable.text, broken.text);
+        assert_eq!(usable.errors.len(), 1);
+
+        // Breaking it further still remembers the version that parsed
+        let mut worse = Parser::new("Func ADD(X, Y) {\n    Return X + (Y,\n}").parse();
+        worse.keep_last_good(&broken);
+        assert!(worse.usable().symbols.find_by_name("ADD").is_some());
+
+        // Once it parses again there's nothing to fall back to
+        let mut fixed = Parser::new("Func ADD(X, Y) {\n    Return X + Y\n}\n").parse();
+        fixed.keep_last_good(&worse);
+        assert!(fixed.last_good.is_none());
+    }
+
+    #[test]
+    fn test_with_comments_from_refreshes_documentation() {
+        let doc = Parser::new("// Adds\nFunc ADD(X, Y) {\n    Return X + Y\n}").parse();
+        let edited =
+            doc.with_comments_from("// Sums two numbers\nFunc ADD(X, Y) {\n    Return X + Y\n}");
+        assert_eq!(edited.ast.comments()[0].text, "// Sums two numbers");
+        let documentation = &edited.symbols.find_by_name("ADD").unwrap().documentation;
+        assert!(
+            documentation.contains("Sums two numbers"),
+            "{}",
+            documentation
+        );
+    }
+
+    #[test]
+    fn test_repeated_identifiers_share_one_name() {
+        let doc = Parser::new("Set TOTAL 0\nSet TOTAL (TOTAL + 1)").parse();
+        let mut names = Vec::new();
+        for stmt in &doc.ast {
+            if let StmtKind::Set { name, value } = &stmt.kind {
+                names.push(name.name.as_ptr());
+                if let ExprKind::Binary { left, .. } = &value.kind
+                    && let ExprKind::Identifier(name) = &left.kind
+                {
+                    names.push(name.as_ptr());
+                }
+            }
+        }
+        assert_eq!(names.len(), 3);
+        assert!(names.iter().all(|&name| name == names[0]));
+        let symbol = doc.symbols.find_by_name("TOTAL").unwrap();
+        assert_eq!(symbol.name.as_ptr(), names[0]);
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error_not_a_crash() {
+        let depth = MAX_NESTING * 20;
+        let text = format!("Set X {}1{}\nSet Y 2", "(".repeat(depth), ")".repeat(depth));
+        let doc = Parser::new(&text).parse();
+        assert!(
+            doc.errors
+                .iter()
+                .any(|error| error.message().contains("levels deep")),
+            "{:?}",
+            doc.errors
+        );
+        // Parsing picks up again on the next line
+        assert!(doc.symbols.find_by_name("Y").is_some());
+
+        let blocks = format!(
+            "{}Set Z 1{}",
+            "If True {\n".repeat(depth),
+            "\n}".repeat(depth)
+        );
+        assert!(!Parser::new(&blocks).parse().errors.is_empty());
+
+        // Nesting within the limit still parses
+        let shallow = format!("Set X {}1{}", "(".repeat(50), ")".repeat(50));
+        assert!(Parser::new(&shallow).parse().errors.is_empty());
+    }
+
+    const LOWERCASE: &str = "import {helper as h} from \"./lib.ae\"\n\
+                             func add(a, b) {\n    return a + b\n}\n\
+                             set total 0\n\
+                             for i, v in [1, 2] {\n    set total add(total, v)\n}\n\
+                             while (total > 10) {\n    break\n}\n\
+                             if (total > 1) {\n    set total 1\n} elif (total < 0) {\n    set total 0\n} else {\n    continue\n}\n\
+                             switch (total) {\n    case 1:\n        set total 2\n    default:\n        set total 3\n}\n\
+                             try {\n    throw \"x\"\n} catch (e) {\n    set total 4\n}";
+
+    #[test]
+    fn test_lenient_keywords_read_miscased_keywords() {
+        let strict = Parser::new(LOWERCASE).parse();
+        assert!(!strict.errors.is_empty());
+        assert!(strict.keyword_casings.is_empty());
+
+        let doc = Parser::new(LOWERCASE).lenient_keywords(true).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        assert_eq!(doc.ast.len(), 8);
+        let keywords: Vec<&str> = doc
+            .keyword_casings
+            .iter()
+            .map(|casing| casing.keyword)
+            .collect();
+        assert_eq!(
+            keywords,
+            vec![
+                "Import", "As", "From", "Func", "Return", "Set", "For", "In", "Set", "While",
+                "Break", "If", "Set", "Elif", "Set", "Else", "Continue", "Switch", "Case", "Set",
+                "Default", "Set", "Try", "Throw", "Catch", "Set",
+            ]
+        );
+        let first = &doc.keyword_casings[0];
+        assert_eq!(
+            first.span,
+            Span::new(Position::new(0, 0), Position::new(0, 6))
+        );
+    }
+
+    #[test]
+    fn test_lenient_keywords_leave_names_alone() {
+        // Names in expression position, and statements that use one as a value
+        let text = "Set IF 1\nSet FOR [1]\nPRINTLN(IF + 1)\nIF + 1\nFOR[0]\nTRY.COUNT";
+        let doc = Parser::new(text).lenient_keywords(true).parse();
+        assert!(doc.keyword_casings.is_empty(), "{:?}", doc.keyword_casings);
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        assert_eq!(doc.ast.len(), 6);
+        assert_eq!(Parser::new(text).parse().errors.len(), doc.errors.len());
+    }
+
+    #[test]
+    fn test_stray_assigns_are_read_past() {
+        let text = "Set X = 10\nY = X + 1\nIf (X = 5) {\n    PRINTLN(X)\n}\n\
+                    While (Y = 3) {\n    Set Y 1\n}\nSet ARR[0] = 2";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        assert!(matches!(&doc.ast[0].kind, StmtKind::Set { name, .. } if name.as_str() == "X"));
+        assert!(matches!(&doc.ast[1].kind, StmtKind::Set { name, .. } if name.as_str() == "Y"));
+        let StmtKind::Expression(Expr {
+            kind: ExprKind::If { condition, .. },
+            ..
+        }) = &doc.ast[2].kind
+        else {
+            panic!("expected an If, got {:?}", doc.ast[2].kind);
+        };
+        assert!(matches!(
+            condition.kind,
+            ExprKind::Binary {
+                op: BinOp::Equal,
+                ..
+            }
+        ));
+        assert!(matches!(doc.ast[4].kind, StmtKind::SetIndex { .. }));
+
+        let strays: Vec<(u32, u32, u32)> = doc
+            .stray_assigns
+            .iter()
+            .map(|stray| {
+                (
+                    stray.span.start.line,
+                    stray.span.start.character,
+                    stray.next.character,
+                )
+            })
+            .collect();
+        assert_eq!(
+            strays,
+            vec![(0, 6, 8), (1, 2, 4), (2, 6, 8), (5, 9, 11), (8, 11, 13)]
+        );
+        assert_eq!(
+            doc.stray_assigns[1].kind,
+            StrayAssignKind::WithoutSet {
+                name: Ident::new("Y", Span::new(Position::new(1, 0), Position::new(1, 1))),
+                value: Span::new(Position::new(1, 4), Position::new(1, 9)),
+            }
+        );
+        assert_eq!(doc.stray_assigns[3].kind, StrayAssignKind::InCondition);
+    }
+
+    /// `cargo test --release bench_parse -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_parse_large_generated_file() {
+        let mut text = String::new();
+        for i in 0..1250 {
+            text.push_str(&format!(
+                "Func COMPUTE_{i}(ITEMS, FACTOR) {{\n    Set TOTAL 0\n    For ITEM In ITEMS {{\n        Set TOTAL (TOTAL + ITEM * FACTOR)\n    }}\n    Return TOTAL\n}}\nSet RESULT_{i} COMPUTE_{i}([1, 2, 3], {i})\n"
+            ));
+        }
+
+        let mut fastest = std::time::Duration::MAX;
+        for _ in 0..5 {
+            let start = std::time::Instant::now();
+            let doc = Parser::new(&text).parse();
+            fastest = fastest.min(start.elapsed());
+            assert_eq!(doc.symbols.functions.len(), 1250);
+        }
+        crate::test_alloc::reset_peak();
+        let before = crate::test_alloc::stats();
+        let doc = Parser::new(&text).parse();
+        let after = crate::test_alloc::stats();
+        eprintln!(
+            "parsed {} lines in {:?}: {} allocations, peak {} KiB, {} KiB kept",
+            text.lines().count(),
+            fastest,
+            after.allocations - before.allocations,
+            (after.peak - before.live) / 1024,
+            (after.live - before.live) / 1024,
+        );
+        drop(doc);
+    }
 }
@@ -0,0 +1,118 @@
+//! How long request handlers and analysis steps take
+//!
+//! "Completion feels slow" is hard to act on without numbers. Each handler
+//! records its duration under its name; recording is one clock read and a short
+//! lock on that name's slot, and the percentiles `aether/perf` reports are only
+//! worked out when it asks.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+/// Durations kept per handler for the percentiles
+const WINDOW: usize = 128;
+
+#[derive(Debug, Default)]
+pub struct PerfStats {
+    handlers: DashMap<&'static str, Samples>,
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+    count: u64,
+    last: Duration,
+    /// The latest [`WINDOW`] durations, oldest overwritten first
+    recent: Vec<Duration>,
+    next: usize,
+}
+
+/// One handler's timings, as `aether/perf` reports them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HandlerPerf {
+    pub count: u64,
+    pub last_ms: f64,
+    /// Over the latest runs, at most 128 of them
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl PerfStats {
+    pub fn record(&self, handler: &'static str, elapsed: Duration) {
+        let mut samples = self.handlers.entry(handler).or_default();
+        samples.count += 1;
+        samples.last = elapsed;
+        if samples.recent.len() < WINDOW {
+            samples.recent.push(elapsed);
+        } else {
+            let next = samples.next;
+            samples.recent[next] = elapsed;
+        }
+        samples.next = (samples.next + 1) % WINDOW;
+    }
+
+    pub fn handler(&self, handler: &str) -> Option<HandlerPerf> {
+        self.handlers.get(handler).map(|samples| samples.summary())
+    }
+
+    /// Every handler that ran at least once, by name
+    pub fn report(&self) -> BTreeMap<&'static str, HandlerPerf> {
+        self.handlers
+            .iter()
+            .map(|entry| (*entry.key(), entry.summary()))
+            .collect()
+    }
+}
+
+impl Samples {
+    fn summary(&self) -> HandlerPerf {
+        let mut sorted = self.recent.clone();
+        sorted.sort_unstable();
+        HandlerPerf {
+            count: self.count,
+            last_ms: millis(self.last),
+            p50_ms: millis(percentile(&sorted, 50)),
+            p95_ms: millis(percentile(&sorted, 95)),
+        }
+    }
+}
+
+/// The nearest-rank percentile of `sorted`
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied().unwrap_or_default()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles_over_the_window() {
+        let stats = PerfStats::default();
+        for ms in 1..=20 {
+            stats.record("hover", Duration::from_millis(ms));
+        }
+        let hover = stats.handler("hover").unwrap();
+        assert_eq!(hover.count, 20);
+        assert_eq!(hover.last_ms, 20.0);
+        assert_eq!(hover.p50_ms, 10.0);
+        assert_eq!(hover.p95_ms, 19.0);
+
+        // Old durations leave the window
+        for _ in 0..WINDOW {
+            stats.record("hover", Duration::from_millis(2));
+        }
+        let hover = stats.handler("hover").unwrap();
+        assert_eq!(hover.count, 20 + WINDOW as u64);
+        assert_eq!(hover.p95_ms, 2.0);
+        assert!(stats.handler("completion").is_none());
+        assert_eq!(stats.report().len(), 1);
+    }
+}
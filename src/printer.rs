@@ -0,0 +1,532 @@
+//! Source renderer for AST nodes
+//!
+//! Produces parseable Aether code. Indentation uses four spaces per level on
+//! top of a caller-provided base indent, so snippets can be spliced into
+//! existing lines.
+
+use std::fmt;
+
+use crate::ast::{BinOp, DictKey, Expr, ExprKind, Ident, Stmt, StmtKind, UnaryOp};
+
+const INDENT_UNIT: &str = "    ";
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&expr_to_source(self, ""))
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&stmt_to_source(self, ""))
+    }
+}
+
+/// Render a whole program, one top-level statement per line
+#[cfg(test)]
+pub fn program_to_source(program: &[Stmt]) -> String {
+    program
+        .iter()
+        .map(|stmt| stmt_to_source(stmt, ""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a statement; nested blocks are indented relative to `indent`
+pub fn stmt_to_source(stmt: &Stmt, indent: &str) -> String {
+    let mut out = String::new();
+    write_stmt(&mut out, stmt, indent);
+    out
+}
+
+/// Render an expression; nested blocks are indented relative to `indent`
+pub fn expr_to_source(expr: &Expr, indent: &str) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, expr, indent);
+    out
+}
+
+/// Render `Lambda X -> expr` / `Lambda (A, B) -> expr`
+pub fn arrow_lambda_source(params: &[Ident], body: &Expr, indent: &str) -> String {
+    let params = if params.len() == 1 {
+        params[0].name.to_string()
+    } else {
+        format!("({})", join_idents(params))
+    };
+    format!("Lambda {} -> {}", params, expr_to_source(body, indent))
+}
+
+/// Render `Func(A, B) { ... }` with one statement per line
+pub fn block_lambda_source(params: &[Ident], body: &[Stmt], indent: &str) -> String {
+    let mut out = format!("Func({}) ", join_idents(params));
+    write_block(&mut out, body, indent);
+    out
+}
+
+fn join_idents(idents: &[Ident]) -> String {
+    idents
+        .iter()
+        .map(|ident| ident.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Write `{`, the indented statements, and the closing `}` at `indent`
+fn write_block(out: &mut String, body: &[Stmt], indent: &str) {
+    let inner = format!("{}{}", indent, INDENT_UNIT);
+    out.push_str("{\n");
+    for stmt in body {
+        out.push_str(&inner);
+        write_stmt(out, stmt, &inner);
+        out.push('\n');
+    }
+    out.push_str(indent);
+    out.push('}');
+}
+
+fn write_stmt(out: &mut String, stmt: &Stmt, indent: &str) {
+    match &stmt.kind {
+        StmtKind::Set { name, value } => {
+            out.push_str(&format!("Set {} ", name));
+            write_expr(out, value, indent);
+        }
+        StmtKind::SetIndex {
+            object,
+            index,
+            value,
+        } => {
+            out.push_str("Set ");
+            write_operand(out, object, indent);
+            out.push('[');
+            write_expr(out, index, indent);
+            out.push_str("] ");
+            write_expr(out, value, indent);
+        }
+        StmtKind::FuncDef { name, params, body } => {
+            out.push_str(&format!("Func {}({}) ", name, join_idents(params)));
+            write_block(out, body, indent);
+        }
+        StmtKind::GeneratorDef { name, params, body } => {
+            out.push_str(&format!("Generator {}({}) ", name, join_idents(params)));
+            write_block(out, body, indent);
+        }
+        StmtKind::LazyDef { name, expr } => {
+            out.push_str(&format!("Lazy {}(", name));
+            write_expr(out, expr, indent);
+            out.push(')');
+        }
+        StmtKind::Return(expr) => write_keyword_expr(out, "Return", expr, indent),
+        StmtKind::Yield(expr) => write_keyword_expr(out, "Yield", expr, indent),
+        StmtKind::Break => out.push_str("Break"),
+        StmtKind::Continue => out.push_str("Continue"),
+        StmtKind::While { condition, body } => {
+            out.push_str("While (");
+            write_expr(out, condition, indent);
+            out.push_str(") ");
+            write_block(out, body, indent);
+        }
+        StmtKind::For {
+            var,
+            iterable,
+            body,
+        } => {
+            out.push_str(&format!("For {} In ", var));
+            write_expr(out, iterable, indent);
+            out.push(' ');
+            write_block(out, body, indent);
+        }
+        StmtKind::ForIndexed {
+            index_var,
+            value_var,
+            iterable,
+            body,
+        } => {
+            out.push_str(&format!("For {}, {} In ", index_var, value_var));
+            write_expr(out, iterable, indent);
+            out.push(' ');
+            write_block(out, body, indent);
+        }
+        StmtKind::Switch {
+            expr,
+            cases,
+            default,
+        } => {
+            let case_indent = format!("{}{}", indent, INDENT_UNIT);
+            let body_indent = format!("{}{}", case_indent, INDENT_UNIT);
+            out.push_str("Switch (");
+            write_expr(out, expr, indent);
+            out.push_str(") {\n");
+            for (value, body) in cases {
+                out.push_str(&case_indent);
+                out.push_str("Case ");
+                write_expr(out, value, &case_indent);
+                out.push_str(":\n");
+                for stmt in body {
+                    out.push_str(&body_indent);
+                    write_stmt(out, stmt, &body_indent);
+                    out.push('\n');
+                }
+            }
+            if let Some(body) = default {
+                out.push_str(&case_indent);
+                out.push_str("Default:\n");
+                for stmt in body {
+                    out.push_str(&body_indent);
+                    write_stmt(out, stmt, &body_indent);
+                    out.push('\n');
+                }
+            }
+            out.push_str(indent);
+            out.push('}');
+        }
+        StmtKind::Import {
+            names,
+            path,
+            aliases,
+            braced,
+        } => {
+            let entries: Vec<String> = names
+                .iter()
+                .zip(aliases)
+                .map(|(name, alias)| match alias {
+                    Some(alias) => format!("{} As {}", name, alias),
+                    None => name.name.to_string(),
+                })
+                .collect();
+            if *braced {
+                out.push_str(&format!("Import {{{}}} From ", entries.join(", ")));
+            } else {
+                out.push_str(&format!("Import {} From ", entries.join(", ")));
+            }
+            write_string_literal(out, path);
+        }
+        StmtKind::Export(name) => out.push_str(&format!("Export {}", name)),
+        StmtKind::Throw(expr) => {
+            out.push_str("Throw ");
+            write_expr(out, expr, indent);
+        }
+        StmtKind::Try {
+            body,
+            error_var,
+            handler,
+        } => {
+            out.push_str("Try ");
+            write_block(out, body, indent);
+            match error_var {
+                Some(error_var) => out.push_str(&format!(" Catch ({}) ", error_var)),
+                None => out.push_str(" Catch "),
+            }
+            write_block(out, handler, indent);
+        }
+        StmtKind::Expression(expr) => write_expr(out, expr, indent),
+    }
+}
+
+/// `Return`/`Yield` omit a `Null` operand, which the parser restores
+fn write_keyword_expr(out: &mut String, keyword: &str, expr: &Expr, indent: &str) {
+    out.push_str(keyword);
+    if expr.kind != ExprKind::Null {
+        out.push(' ');
+        write_expr(out, expr, indent);
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr, indent: &str) {
+    match &expr.kind {
+        ExprKind::Number(n) => out.push_str(&n.to_string()),
+        ExprKind::BigInteger(digits) => out.push_str(digits),
+        ExprKind::String(s) => write_string_literal(out, s),
+        ExprKind::Boolean(true) => out.push_str("True"),
+        ExprKind::Boolean(false) => out.push_str("False"),
+        ExprKind::Null => out.push_str("Null"),
+        ExprKind::Identifier(name) => out.push_str(name),
+        ExprKind::Array(elements) => {
+            out.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, element, indent);
+            }
+            out.push(']');
+        }
+        ExprKind::Dict(pairs) => {
+            out.push('{');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                match key {
+                    DictKey::Ident(name) => out.push_str(name),
+                    DictKey::Str(key) => write_string_literal(out, key),
+                    DictKey::Num(key) => out.push_str(&key.to_string()),
+                    DictKey::Computed(key) => {
+                        out.push('[');
+                        write_expr(out, key, indent);
+                        out.push(']');
+                    }
+                }
+                out.push_str(": ");
+                write_expr(out, value, indent);
+            }
+            out.push('}');
+        }
+        ExprKind::Binary { left, op, right } => {
+            let precedence = binop_precedence(*op);
+            write_binary_operand(out, left, indent, |p| p < precedence);
+            out.push_str(&format!(" {} ", binop_symbol(*op)));
+            write_binary_operand(out, right, indent, |p| p <= precedence);
+        }
+        ExprKind::Unary { op, expr } => {
+            out.push_str(match op {
+                UnaryOp::Minus => "-",
+                UnaryOp::Not => "!",
+            });
+            write_operand(out, expr, indent);
+        }
+        ExprKind::Call { func, args } => {
+            write_operand(out, func, indent);
+            out.push('(');
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, arg, indent);
+            }
+            out.push(')');
+        }
+        ExprKind::Index { object, index } => {
+            write_operand(out, object, indent);
+            out.push('[');
+            write_expr(out, index, indent);
+            out.push(']');
+        }
+        ExprKind::Force(expr) => {
+            out.push_str("Force(");
+            write_expr(out, expr, indent);
+            out.push(')');
+        }
+        ExprKind::Member { object, member } => {
+            write_operand(out, object, indent);
+            out.push('.');
+            out.push_str(&member.name);
+        }
+        ExprKind::If {
+            condition,
+            then_branch,
+            elif_branches,
+            else_branch,
+        } => {
+            out.push_str("If (");
+            write_expr(out, condition, indent);
+            out.push_str(") ");
+            write_block(out, then_branch, indent);
+            for (elif_condition, elif_body) in elif_branches {
+                out.push_str(" Elif (");
+                write_expr(out, elif_condition, indent);
+                out.push_str(") ");
+                write_block(out, elif_body, indent);
+            }
+            if let Some(else_body) = else_branch {
+                out.push_str(" Else ");
+                write_block(out, else_body, indent);
+            }
+        }
+        ExprKind::Lambda { params, body } => match single_return(body) {
+            Some(expr) => out.push_str(&arrow_lambda_source(params, expr, indent)),
+            None => out.push_str(&block_lambda_source(params, body, indent)),
+        },
+    }
+}
+
+/// The returned expression when a body is exactly `Return expr`
+pub fn single_return(body: &[Stmt]) -> Option<&Expr> {
+    match body {
+        [
+            Stmt {
+                kind: StmtKind::Return(expr),
+                ..
+            },
+        ] => Some(expr),
+        _ => None,
+    }
+}
+
+/// Write an operand of a postfix/prefix operator, parenthesizing compound expressions
+fn write_operand(out: &mut String, expr: &Expr, indent: &str) {
+    if is_atomic(expr) {
+        write_expr(out, expr, indent);
+    } else {
+        out.push('(');
+        write_expr(out, expr, indent);
+        out.push(')');
+    }
+}
+
+fn write_binary_operand(
+    out: &mut String,
+    expr: &Expr,
+    indent: &str,
+    needs_parens: impl Fn(u8) -> bool,
+) {
+    let parenthesize = match &expr.kind {
+        ExprKind::Binary { op, .. } => needs_parens(binop_precedence(*op)),
+        ExprKind::If { .. } | ExprKind::Lambda { .. } => true,
+        _ => false,
+    };
+    if parenthesize {
+        out.push('(');
+        write_expr(out, expr, indent);
+        out.push(')');
+    } else {
+        write_expr(out, expr, indent);
+    }
+}
+
+fn is_atomic(expr: &Expr) -> bool {
+    match expr.kind {
+        ExprKind::Binary { .. }
+        | ExprKind::Unary { .. }
+        | ExprKind::If { .. }
+        | ExprKind::Lambda { .. } => false,
+        // `-5[0]` would index 5 and negate the element
+        ExprKind::Number(n) => n.is_sign_positive(),
+        _ => true,
+    }
+}
+
+fn write_string_literal(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+/// Binding strength of a binary operator, mirroring the parser's precedence table
+fn binop_precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => 1,
+        BinOp::And => 2,
+        BinOp::Equal | BinOp::NotEqual => 3,
+        BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => 4,
+        BinOp::Add | BinOp::Subtract => 5,
+        BinOp::Multiply | BinOp::Divide | BinOp::Modulo => 6,
+    }
+}
+
+fn binop_symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Subtract => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::Modulo => "%",
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::Less => "<",
+        BinOp::LessEqual => "<=",
+        BinOp::Greater => ">",
+        BinOp::GreaterEqual => ">=",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Programs covering every statement and expression form
+    const FIXTURES: &[&str] = &[
+        "Set X 1\nSet NAME \"a \\\"quoted\\\" line\\n\"\nSet BIG 123456789012345678901",
+        "Set Y (1 + 2) * 3 - 4 / (5 % 2)\nSet Z !(A && B) || C == -D",
+        "Set ITEMS [1, [2, 3], {\"k\": Null, \"b\": False}]\nSet ITEMS[0] ITEMS[1][0]",
+        "Set D {NAME: 1, \"s\": 2, 3.5: [K], [K + 1]: {INNER: -1}}",
+        "Func ADD(A, B) {\n    Return A + B\n}\nGenerator GEN(N) {\n    Yield N\n    Yield\n}",
+        "Lazy DATA(LOAD(\"x\"))\nExport ADD\nThrow \"boom\"",
+        "Try {\n    RISKY()\n} Catch (ERROR) {\n    PRINTLN(ERROR)\n}\nTry {\n    RISKY()\n} Catch {\n}",
+        "While (I < 10) {\n    If (I == 5) {\n        Break\n    } Elif (I > 7) {\n        Continue\n    } Else {\n        Set I (I + 1)\n    }\n}",
+        "For X In [1, 2] {\n    PRINTLN(X)\n}\nFor I, X In LIST {\n    PRINTLN(I, X)\n}",
+        "Switch (X) {\n    Case 1:\n        PRINTLN(\"one\")\n    Case \"two\":\n        Break\n    Default:\n        PRINTLN(\"other\")\n}",
+        "Import {ADD As PLUS, SUB} From \"./math\"\nImport HELPER From \"../lib/helper\"",
+        "Set F Lambda X -> X * 2\nSet G Lambda (A, B) -> A + B\nSet H Func(X) {\n    Set Y (X * 2)\n    Return Y\n}",
+        "Set R MAP(FILTER(XS, Lambda X -> X > 0), Func(X) {\n    PRINTLN(X)\n    Return X\n})",
+        "Set N [-1, 2 - -3, -A, (-5)[0], -(-5), !(-1), F(-1.5)]\nSwitch (N) {\n    Case -1:\n        Break\n}",
+    ];
+
+    fn assert_round_trip(source: &str) {
+        let original = Parser::new(source).parse();
+        assert!(
+            original.errors.is_empty(),
+            "{}\n{:?}",
+            source,
+            original.errors
+        );
+
+        let printed = program_to_source(&original.ast);
+        let reparsed = Parser::new(&printed).parse();
+        assert!(
+            reparsed.errors.is_empty(),
+            "{}\n{:?}",
+            printed,
+            reparsed.errors
+        );
+        assert_eq!(original.ast, reparsed.ast, "printed as:\n{}", printed);
+
+        // Printing is a fixed point after one pass
+        assert_eq!(program_to_source(&reparsed.ast), printed);
+    }
+
+    #[test]
+    fn test_round_trip_fixtures() {
+        for fixture in FIXTURES {
+            assert_round_trip(fixture);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            // Some examples use syntax the parser doesn't support yet
+            if Parser::new(&source).parse().errors.is_empty() {
+                assert_round_trip(&source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_literals_print_without_parens() {
+        let doc = Parser::new("Set N [- 1, -(1), -1, F(-2)[0], (-2)[0]]").parse();
+        assert_eq!(
+            program_to_source(&doc.ast),
+            "Set N [-1, -1, -1, F(-2)[0], (-2)[0]]"
+        );
+    }
+
+    #[test]
+    fn test_display_matches_renderer() {
+        let doc = Parser::new("Func ADD(A, B) {\n    Return A + B\n}").parse();
+        assert_eq!(
+            doc.ast[0].to_string(),
+            "Func ADD(A, B) {\n    Return A + B\n}"
+        );
+        let StmtKind::FuncDef { body, .. } = &doc.ast[0].kind else {
+            panic!("expected a function");
+        };
+        let StmtKind::Return(expr) = &body[0].kind else {
+            panic!("expected a return");
+        };
+        assert_eq!(expr.to_string(), "A + B");
+    }
+}
@@ -0,0 +1,131 @@
+//! Why a request could not be answered
+//!
+//! Most requests answer a miss with an empty result, as the spec expects of
+//! hover or completion, and only log the reason. Rename and the custom
+//! requests may fail instead: each reason has its own JSON-RPC code so a
+//! client can tell an unknown document from a symbol that can't be renamed.
+
+use std::fmt;
+
+use lsp_types::{Position, Url};
+use serde_json::json;
+use tower_lsp::jsonrpc::{Error, ErrorCode};
+
+use crate::locale::Locale;
+
+/// Outside both ranges JSON-RPC and LSP reserve
+const FIRST_CODE: i64 = -32901;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    /// Neither open in the editor nor readable from disk
+    DocumentNotOpen(Url),
+    /// Past the last line, or past the end of its line
+    PositionOutOfRange(Position),
+    /// Nothing at the position is defined in the document: a builtin, a
+    /// keyword, a literal or whitespace
+    NotARenamableSymbol(Position),
+    /// The document has no AST right now, so names can't be resolved
+    AnalysisUnavailable(Url),
+    /// Not usable as an Aether name
+    InvalidName(String),
+}
+
+impl RequestError {
+    pub fn code(&self) -> i64 {
+        FIRST_CODE
+            - match self {
+                RequestError::DocumentNotOpen(_) => 0,
+                RequestError::PositionOutOfRange(_) => 1,
+                RequestError::NotARenamableSymbol(_) => 2,
+                RequestError::AnalysisUnavailable(_) => 3,
+                RequestError::InvalidName(_) => 4,
+            }
+    }
+
+    /// A stable name for the reason, sent along as the error's data
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RequestError::DocumentNotOpen(_) => "documentNotOpen",
+            RequestError::PositionOutOfRange(_) => "positionOutOfRange",
+            RequestError::NotARenamableSymbol(_) => "notARenamableSymbol",
+            RequestError::AnalysisUnavailable(_) => "analysisUnavailable",
+            RequestError::InvalidName(_) => "invalidName",
+        }
+    }
+
+    pub fn message(&self, locale: Locale) -> String {
+        match (self, locale) {
+            (RequestError::DocumentNotOpen(uri), Locale::Zh) => format!("文档 {} 未打开", uri),
+            (RequestError::DocumentNotOpen(uri), Locale::En) => {
+                format!("Document {} is not open", uri)
+            }
+            (RequestError::PositionOutOfRange(position), Locale::Zh) => format!(
+                "位置 {}:{} 超出文档范围",
+                position.line, position.character
+            ),
+            (RequestError::PositionOutOfRange(position), Locale::En) => format!(
+                "Position {}:{} is outside the document",
+                position.line, position.character
+            ),
+            (RequestError::NotARenamableSymbol(_), _) => locale
+                .pick(
+                    "这里没有可以重命名的符号：只有本文件定义或导入的名字可以重命名",
+                    "Nothing to rename here: only names defined or imported in this file can be renamed",
+                )
+                .to_string(),
+            (RequestError::AnalysisUnavailable(_), _) => locale
+                .pick(
+                    "文档存在语法错误，暂时无法分析",
+                    "The document has syntax errors and can't be analyzed right now",
+                )
+                .to_string(),
+            (RequestError::InvalidName(name), Locale::Zh) => {
+                format!("'{}' 不是有效的名字，请使用 UPPER_SNAKE_CASE", name)
+            }
+            (RequestError::InvalidName(name), Locale::En) => {
+                format!("'{}' is not a valid name; use UPPER_SNAKE_CASE", name)
+            }
+        }
+    }
+
+    pub fn into_jsonrpc(self, locale: Locale) -> Error {
+        Error {
+            code: ErrorCode::ServerError(self.code()),
+            message: self.message(locale).into(),
+            data: Some(json!({ "reason": self.reason() })),
+        }
+    }
+}
+
+/// The reason in English, for logs
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.reason(), self.message(Locale::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_distinct_and_unreserved() {
+        let uri = Url::parse("file:///a.ae").unwrap();
+        let errors = [
+            RequestError::DocumentNotOpen(uri.clone()),
+            RequestError::PositionOutOfRange(Position::new(9, 0)),
+            RequestError::NotARenamableSymbol(Position::new(0, 0)),
+            RequestError::AnalysisUnavailable(uri),
+            RequestError::InvalidName("total".to_string()),
+        ];
+        let mut codes: Vec<i64> = errors.iter().map(RequestError::code).collect();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        for code in codes {
+            // -32099..=-32000 belongs to JSON-RPC, -32899..=-32800 to LSP
+            assert!(!(-32099..=-32000).contains(&code), "{}", code);
+            assert!(!(-32899..=-32800).contains(&code), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Property tests for the lexer and parser
+//!
+//! Random token soup and random mutations of valid programs must keep three
+//! promises: the lexer always reaches `EOF`, the parser never panics and only
+//! reports errors inside the document, and printing a program that parsed
+//! cleanly gives source that parses back to the same print.
+//!
+//! Inputs that once broke one of these live in `tests/corpus/`, one file each,
+//! and are replayed on every run. To keep a new crasher, e.g. one proptest
+//! shrank, write it to a file and run
+//!
+//! ```text
+//! AETHER_CRASHER=path/to/input cargo test add_crasher -- --ignored
+//! ```
+//!
+//! which copies it into the corpus under a name derived from its content.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use proptest::prelude::*;
+
+use crate::ast::Span;
+use crate::lexer::Lexer;
+use crate::parser::{ParsedDocument, Parser};
+use crate::printer;
+use crate::token::Token;
+
+/// Check every invariant on `text`, panicking with the first one broken
+fn check(text: &str) {
+    check_lexer_terminates(text);
+    let doc = Parser::new(text).parse();
+    check_error_positions(text, &doc);
+    if doc.errors.is_empty() {
+        check_print_round_trip(&doc);
+    }
+}
+
+fn check_lexer_terminates(text: &str) {
+    // Every token but `EOF` consumes at least one character
+    let limit = text.chars().count() + 1;
+    let mut lexer = Lexer::new(text);
+    for _ in 0..limit {
+        if lexer.next_token() == Token::EOF {
+            return;
+        }
+    }
+    assert_eq!(lexer.next_token(), Token::EOF, "lexer never reached EOF");
+}
+
+fn check_error_positions(text: &str, doc: &ParsedDocument) {
+    // Lexer columns count UTF-16 units
+    let lines: Vec<usize> = text
+        .split('\n')
+        .map(|line| line.encode_utf16().count())
+        .collect();
+    let inside = |line: u32, character: u32| {
+        lines
+            .get(line as usize)
+            .is_some_and(|&len| character as usize <= len)
+    };
+    let span_inside = |span: Span| {
+        inside(span.start.line, span.start.character)
+            && inside(span.end.line, span.end.character)
+            && span.start <= span.end
+    };
+    for error in &doc.errors {
+        assert!(
+            error.line >= 1
+                && error.column >= 1
+                && inside(error.line as u32 - 1, error.column as u32 - 1),
+            "{} reported at {}:{} outside the document",
+            error.message(),
+            error.line,
+            error.column
+        );
+        assert!(
+            span_inside(error.span) && span_inside(error.token),
+            "{} spans {:?} at {:?}",
+            error.message(),
+            error.span,
+            error.token
+        );
+    }
+}
+
+fn check_print_round_trip(doc: &ParsedDocument) {
+    let printed = printer::program_to_source(&doc.ast);
+    let reparsed = Parser::new(&printed).parse();
+    assert!(
+        reparsed.errors.is_empty(),
+        "printed program does not parse: {:?}\n{}",
+        reparsed.errors,
+        printed
+    );
+    assert_eq!(
+        printer::program_to_source(&reparsed.ast),
+        printed,
+        "printing is not a fixpoint"
+    );
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus")
+}
+
+/// Pieces of Aether source, valid and otherwise
+const FRAGMENTS: &[&str] = &[
+    "Set",
+    "Func",
+    "Generator",
+    "Lazy",
+    "Force",
+    "Return",
+    "Yield",
+    "If",
+    "Elif",
+    "Else",
+    "While",
+    "For",
+    "In",
+    "Break",
+    "Continue",
+    "Switch",
+    "Case",
+    "Default",
+    "Import",
+    "Export",
+    "From",
+    "As",
+    "Lambda",
+    "Throw",
+    "Try",
+    "Catch",
+    "True",
+    "False",
+    "Null",
+    "X",
+    "TOTAL",
+    "_",
+    "名字",
+    "🙂",
+    "é",
+    "0",
+    "42",
+    "3.14",
+    "1.",
+    ".5",
+    "12345678901234567890",
+    "\"text\"",
+    "\"",
+    "\"open",
+    "\"\\\"",
+    "\"\\",
+    "'",
+    "(",
+    ")",
+    "[",
+    "]",
+    "{",
+    "}",
+    ",",
+    ":",
+    ";",
+    ".",
+    "+",
+    "-",
+    "*",
+    "/",
+    "%",
+    "=",
+    "==",
+    "!=",
+    "<",
+    "<=",
+    ">",
+    ">=",
+    "&&",
+    "||",
+    "!",
+    "->",
+    "//",
+    "// note",
+    "/*",
+    "*/",
+    "/* block */",
+    "\\",
+    "@",
+    "#",
+    "$",
+    "\0",
+];
+
+const SEPARATORS: &[&str] = &["", " ", " ", "\n", "\t", "\r\n"];
+
+fn token_soup() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        (
+            prop::sample::select(FRAGMENTS),
+            prop::sample::select(SEPARATORS),
+        ),
+        0..80,
+    )
+    .prop_map(|pieces| {
+        pieces
+            .into_iter()
+            .flat_map(|(fragment, separator)| [fragment, separator])
+            .collect()
+    })
+}
+
+const FIXTURES: &[&str] = &[
+    include_str!("../examples/test.aether"),
+    include_str!("../examples/test_builtins.aether"),
+    include_str!("../examples/test_comments.aether"),
+    include_str!("../examples/test_symbols.aether"),
+];
+
+#[derive(Debug, Clone)]
+enum Mutation {
+    Delete { at: f64, len: usize },
+    Insert { at: f64, fragment: &'static str },
+    Duplicate { at: f64, len: usize },
+    Truncate { at: f64 },
+}
+
+fn mutation() -> impl Strategy<Value = Mutation> {
+    let at = 0.0..1.0f64;
+    prop_oneof![
+        (at.clone(), 1..12usize).prop_map(|(at, len)| Mutation::Delete { at, len }),
+        (at.clone(), prop::sample::select(FRAGMENTS))
+            .prop_map(|(at, fragment)| Mutation::Insert { at, fragment }),
+        (at.clone(), 1..40usize).prop_map(|(at, len)| Mutation::Duplicate { at, len }),
+        at.prop_map(|at| Mutation::Truncate { at }),
+    ]
+}
+
+/// The char boundary nearest `at` of the way through `text`
+fn boundary(text: &str, at: f64) -> usize {
+    let mut offset = (text.len() as f64 * at) as usize;
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// `len` characters of `text` from `start`, as a byte range end
+fn chars_end(text: &str, start: usize, len: usize) -> usize {
+    text[start..]
+        .char_indices()
+        .nth(len)
+        .map_or(text.len(), |(offset, _)| start + offset)
+}
+
+fn mutate(fixture: &str, mutations: &[Mutation]) -> String {
+    let mut text = fixture.to_string();
+    for mutation in mutations {
+        match *mutation {
+            Mutation::Delete { at, len } => {
+                let start = boundary(&text, at);
+                let end = chars_end(&text, start, len);
+                text.replace_range(start..end, "");
+            }
+            Mutation::Insert { at, fragment } => {
+                let start = boundary(&text, at);
+                text.insert_str(start, fragment);
+            }
+            Mutation::Duplicate { at, len } => {
+                let start = boundary(&text, at);
+                let end = chars_end(&text, start, len);
+                let copy = text[start..end].to_string();
+                text.insert_str(end, &copy);
+            }
+            Mutation::Truncate { at } => {
+                let end = boundary(&text, at);
+                text.truncate(end);
+            }
+        }
+    }
+    text
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: 512,
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn prop_token_soup_keeps_invariants(text in token_soup()) {
+        check(&text);
+    }
+
+    #[test]
+    fn prop_mutated_programs_keep_invariants(
+        fixture in prop::sample::select(FIXTURES),
+        mutations in prop::collection::vec(mutation(), 1..6),
+    ) {
+        check(&mutate(fixture, &mutations));
+    }
+}
+
+#[test]
+fn test_fixtures_keep_invariants() {
+    for fixture in FIXTURES {
+        check(fixture);
+    }
+}
+
+#[test]
+fn test_corpus_replays() {
+    let mut replayed = 0;
+    for entry in std::fs::read_dir(corpus_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        let bytes = std::fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&bytes);
+        let result = std::panic::catch_unwind(|| check(&text));
+        assert!(result.is_ok(), "{} broke an invariant", path.display());
+        replayed += 1;
+    }
+    assert!(replayed > 0, "the corpus is empty");
+}
+
+/// Copy the file named by `AETHER_CRASHER` into the corpus; see the module docs
+#[test]
+#[ignore]
+fn add_crasher() {
+    let source = std::env::var("AETHER_CRASHER").expect("set AETHER_CRASHER to the input file");
+    let bytes = std::fs::read(&source).unwrap();
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let target = corpus_dir().join(format!("crash-{:016x}.ae", hasher.finish()));
+    std::fs::write(&target, &bytes).unwrap();
+    eprintln!("added {}", target.display());
+    check(&String::from_utf8_lossy(&bytes));
+}
@@ -0,0 +1,2963 @@
+//! The language server: the analysis behind LSP requests over stdio
+
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tower_lsp::jsonrpc::{Error, FromParams, Method, Result};
+use tower_lsp::{ClientSocket, LspService, Server};
+
+use crate::backend::AetherLspBackend;
+use crate::builtins::{CatalogEntry, CatalogParams};
+
+pub use crate::crash::enable_dumps as enable_crash_dumps;
+pub use crate::logging::{LOG_LEVEL_ENV, LogLevel, init as init_logging};
+
+/// The crate version, followed by the commit when the build set `AETHER_LSP_GIT_HASH`
+pub fn version() -> String {
+    match option_env!("AETHER_LSP_GIT_HASH") {
+        Some(hash) if !hash.is_empty() => format!("{} ({})", env!("CARGO_PKG_VERSION"), hash),
+        _ => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// The language server with Aether's custom requests registered
+pub(crate) fn build_service() -> (LspService<AetherLspBackend>, ClientSocket) {
+    LspService::build(AetherLspBackend::new)
+        .custom_method("aether/builtins", BuiltinCatalog)
+        .custom_method("aether/builtinDoc", AetherLspBackend::builtin_doc)
+        .custom_method("aether/diagnosticCodes", AetherLspBackend::diagnostic_codes)
+        .custom_method("aether/serverStatus", AetherLspBackend::server_status)
+        .custom_method("aether/documentMeta", AetherLspBackend::document_meta)
+        .custom_method("aether/perf", AetherLspBackend::perf_report)
+        .custom_method("$/setTrace", AetherLspBackend::set_trace)
+        .finish()
+}
+
+/// Params a client may leave out, or send as `null`, to get the defaults
+///
+/// tower-lsp rejects a request without params before an `async fn(&self, P)`
+/// handler runs, so methods taking these are registered through a [`Method`].
+struct OptionalParams<P>(P);
+
+impl<P: DeserializeOwned + Default + Send + 'static> FromParams for OptionalParams<P> {
+    fn from_params(params: Option<Value>) -> Result<Self> {
+        match params {
+            None | Some(Value::Null) => Ok(OptionalParams(P::default())),
+            Some(params) => serde_json::from_value(params)
+                .map(OptionalParams)
+                .map_err(|err| Error::invalid_params(err.to_string())),
+        }
+    }
+}
+
+/// `aether/builtins`, whose category filter is optional
+#[derive(Clone)]
+struct BuiltinCatalog;
+
+impl<'a> Method<&'a AetherLspBackend, OptionalParams<CatalogParams>, Result<Vec<CatalogEntry>>>
+    for BuiltinCatalog
+{
+    type Future = Pin<Box<dyn Future<Output = Result<Vec<CatalogEntry>>> + Send + 'a>>;
+
+    fn invoke(
+        &self,
+        server: &'a AetherLspBackend,
+        params: OptionalParams<CatalogParams>,
+    ) -> Self::Future {
+        Box::pin(server.builtin_catalog(params.0))
+    }
+}
+
+/// Serve one client over stdin and stdout until it exits
+pub async fn serve_stdio() {
+    let (service, socket) = build_service();
+    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, builtins, diagnostic_codes, diagnostics, locale};
+    use serde_json::json;
+    use tower::{Service, ServiceExt};
+    use tower_lsp::jsonrpc::Request;
+
+    async fn request(
+        service: &mut LspService<AetherLspBackend>,
+        method: &'static str,
+        params: Value,
+    ) -> Value {
+        let request = Request::build(method).params(params).id(1).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, result) = response.into_parts();
+        result.unwrap()
+    }
+
+    /// The error a request fails with
+    async fn request_error(
+        service: &mut LspService<AetherLspBackend>,
+        method: &'static str,
+        params: Value,
+    ) -> Value {
+        let request = Request::build(method).params(params).id(1).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, result) = response.into_parts();
+        serde_json::to_value(result.unwrap_err()).unwrap()
+    }
+
+    async fn initialized_service() -> LspService<AetherLspBackend> {
+        let (mut service, _socket) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        service
+    }
+
+    async fn open(service: &mut LspService<AetherLspBackend>, uri: &str, text: &str) {
+        let open = Request::build("textDocument/didOpen")
+            .params(json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "aether",
+                    "version": 1,
+                    "text": text,
+                }
+            }))
+            .finish();
+        service.ready().await.unwrap().call(open).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_builtin_catalog_request() {
+        let mut service = initialized_service().await;
+
+        let catalog = request(
+            &mut service,
+            "aether/builtins",
+            json!({ "category": "String" }),
+        )
+        .await;
+        let entries = catalog.as_array().unwrap();
+        assert!(entries.iter().all(|entry| entry["category"] == "String"));
+        let padstart = entries
+            .iter()
+            .find(|entry| entry["name"] == "PADSTART")
+            .unwrap();
+        assert_eq!(
+            padstart,
+            &json!({
+                "name": "PADSTART",
+                "signature": "PADSTART(string, length, [pad])",
+                "params": [
+                    { "name": "string", "optional": false, "variadic": false },
+                    { "name": "length", "optional": false, "variadic": false },
+                    { "name": "pad", "optional": true, "variadic": false },
+                ],
+                "category": "String",
+                "description": "在开头填充字符串至指定长度",
+                "descriptionEn": "Pad the start of a string to a given length",
+                "examples": ["Set ID PADSTART(\"42\", 5, \"0\")"],
+                "deprecated": null,
+            })
+        );
+
+        let all = request(&mut service, "aether/builtins", json!({})).await;
+        assert!(all.as_array().unwrap().len() > entries.len());
+
+        // The filter is optional: no params, or null, lists everything
+        assert_eq!(
+            request(&mut service, "aether/builtins", Value::Null).await,
+            all
+        );
+        let request = Request::build("aether/builtins").id(2).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.into_parts().1.unwrap(), all);
+    }
+
+    #[tokio::test]
+    async fn test_server_status_request() {
+        let mut service = initialized_service().await;
+        open(
+            &mut service,
+            "file:///tmp/status.ae",
+            "Set total 1\nSet B (\n",
+        )
+        .await;
+
+        let request = Request::build("aether/serverStatus").id(1).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        let status = response.into_parts().1.unwrap();
+        assert_eq!(status["version"], version());
+        assert!(status["uptimeSeconds"].is_u64());
+        assert_eq!(status["documents"], 1);
+        assert_eq!(status["openDocuments"], 1);
+        assert_eq!(status["workspaceRoots"], json!([]));
+        assert!(status["settings"].is_object());
+        let diagnostics = &status["diagnostics"];
+        assert!(diagnostics["error"].as_u64().unwrap() >= 1, "{}", status);
+        assert!(diagnostics["warning"].as_u64().unwrap() >= 1, "{}", status);
+        assert!(diagnostics["information"].is_u64());
+        assert!(diagnostics["hint"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_document_meta_request_and_header_hover() {
+        let mut service = initialized_service().await;
+        let uri = "file:///tmp/meta.ae";
+        open(
+            &mut service,
+            uri,
+            "//! name: deploy\n//! requires: 1.2\nSet A 1\n//! author: late",
+        )
+        .await;
+
+        let meta = request(
+            &mut service,
+            "aether/documentMeta",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert_eq!(
+            meta,
+            json!([
+                { "key": "name", "value": "deploy" },
+                { "key": "requires", "value": "1.2" },
+            ])
+        );
+
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 5 },
+            }),
+        )
+        .await;
+        let value = hover["contents"]["value"].as_str().unwrap();
+        assert!(value.contains("| `requires` | 1.2 |"), "{}", value);
+
+        let error = request_error(
+            &mut service,
+            "aether/documentMeta",
+            json!({ "textDocument": { "uri": "untitled:missing" } }),
+        )
+        .await;
+        assert_eq!(error["data"]["reason"], "documentNotOpen");
+    }
+
+    #[tokio::test]
+    async fn test_perf_request_reports_handler_timings() {
+        let mut service = initialized_service().await;
+        let uri = "file:///tmp/perf.ae";
+        open(&mut service, uri, "Set TOTAL 1\nPRINTLN(TOTAL)").await;
+        let at = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 10 },
+        });
+        request(&mut service, "textDocument/hover", at.clone()).await;
+        request(&mut service, "textDocument/hover", at.clone()).await;
+        request(&mut service, "textDocument/completion", at).await;
+
+        let request = Request::build("aether/perf").id(1).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        let perf = response.into_parts().1.unwrap();
+        assert_eq!(perf["hover"]["count"], 2, "{}", perf);
+        assert_eq!(perf["completion"]["count"], 1, "{}", perf);
+        assert_eq!(perf["parse"]["count"], 1, "{}", perf);
+        assert_eq!(perf["diagnostics"]["count"], 1, "{}", perf);
+        for handler in ["hover", "completion", "parse", "diagnostics"] {
+            let stats = &perf[handler];
+            let (last, p50, p95) = (
+                stats["lastMs"].as_f64().unwrap(),
+                stats["p50Ms"].as_f64().unwrap(),
+                stats["p95Ms"].as_f64().unwrap(),
+            );
+            assert!((0.0..10_000.0).contains(&last), "{}", perf);
+            assert!(0.0 <= p50 && p50 <= p95, "{}", perf);
+        }
+        assert!(perf.get("rename").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostic_codes_request() {
+        let mut service = initialized_service().await;
+
+        let request = Request::build("aether/diagnosticCodes").id(1).finish();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .unwrap();
+        let catalog = response.into_parts().1.unwrap();
+        let entries = catalog.as_array().unwrap();
+        assert_eq!(entries.len(), diagnostic_codes::ALL.len());
+        let unused = entries
+            .iter()
+            .find(|entry| entry["code"] == "W002")
+            .unwrap();
+        assert_eq!(
+            unused,
+            &json!({
+                "code": "W002",
+                "severity": "hint",
+                "description": "未使用的导入",
+                "descriptionEn": "Unused import",
+                "docsUrl": "https://github.com/xiaozuhui/aether-lang/wiki/diagnostics#w002",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builtin_doc_request_matches_hover() {
+        let mut service = initialized_service().await;
+
+        let doc = request(&mut service, "aether/builtinDoc", json!({ "name": "map" })).await;
+        let lsp_types::HoverContents::Markup(hover) =
+            builtins::builtin_hover("MAP", locale::Locale::En)
+                .unwrap()
+                .contents
+        else {
+            panic!("expected markdown hover");
+        };
+        assert_eq!(doc, json!(hover.value));
+
+        let missing = request(&mut service, "aether/builtinDoc", json!({ "name": "NOPE" })).await;
+        assert_eq!(missing, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_hover() {
+        let mut service = initialized_service().await;
+        let uri = "file:///keywords.ae";
+        open(&mut service, uri, "Lazy DATA(42)\nSet RESULT Force(DATA)").await;
+
+        for (line, character, keyword) in [(0, 2, "Lazy"), (1, 13, "Force")] {
+            let hover = request(
+                &mut service,
+                "textDocument/hover",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": line, "character": character },
+                }),
+            )
+            .await;
+            let markdown = hover["contents"]["value"].as_str().unwrap();
+            assert!(
+                markdown.starts_with(&format!("**{}**", keyword)),
+                "{}",
+                markdown
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repl_buffer_cells_are_analyzed_apart() {
+        let mut service = initialized_service().await;
+        let uri = "aether-repl:session-1";
+        let text = "Func DOUBLE(N) {\n    Return N * 2\n}\n// ---\nSet BROKEN (1 +\n\
+                    // ---\nSet RESULT DOUBLE(21)\nPRINTLN(RESULT)\nRES";
+        open(&mut service, uri, text).await;
+
+        // Only cell 2's error: cell 3 parses, and its use of DOUBLE is defined
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        let items = report["items"].as_array().unwrap();
+        let lines: Vec<(&str, u64)> = items
+            .iter()
+            .map(|item| {
+                (
+                    item["source"].as_str().unwrap(),
+                    item["range"]["start"]["line"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert!(
+            lines
+                .iter()
+                .all(|(source, line)| *source != "aether-parser" || *line == 4),
+            "{:?}",
+            items
+        );
+        assert!(lines.contains(&("aether-parser", 4)), "{:?}", items);
+        assert!(
+            items
+                .iter()
+                .all(|item| item["message"] != "Undefined identifier 'DOUBLE'"),
+            "{:?}",
+            items
+        );
+
+        let symbols = request(
+            &mut service,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        let names: Vec<&str> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect();
+        assert!(
+            names.contains(&"DOUBLE") && names.contains(&"RESULT"),
+            "{:?}",
+            names
+        );
+
+        let labels_at = |line: u32, character: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            })
+        };
+        let labels = |completion: serde_json::Value| -> Vec<String> {
+            completion["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        // Cell 3 sees cell 1's function; cell 1 doesn't see cell 3's variable
+        let late = labels(request(&mut service, "textDocument/completion", labels_at(8, 3)).await);
+        assert!(late.contains(&"DOUBLE".to_string()));
+        assert!(late.contains(&"RESULT".to_string()));
+        let early = labels(request(&mut service, "textDocument/completion", labels_at(1, 4)).await);
+        assert!(early.contains(&"DOUBLE".to_string()));
+        assert!(!early.contains(&"RESULT".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_trailing_comment_documents_a_variable() {
+        let mut service = initialized_service().await;
+        let uri = "file:///trailing.ae";
+        open(
+            &mut service,
+            uri,
+            "Set RETRY_LIMIT 3 // max attempts before giving up\nPRINTLN(RETRY_LIMIT)\nRET",
+        )
+        .await;
+
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 10 },
+            }),
+        )
+        .await;
+        assert_eq!(
+            hover["contents"]["value"],
+            json!("max attempts before giving up")
+        );
+
+        let completion = request(
+            &mut service,
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 2, "character": 3 },
+            }),
+        )
+        .await;
+        let item = completion["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["label"] == "RETRY_LIMIT")
+            .unwrap();
+        assert!(
+            item["documentation"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("max attempts before giving up"),
+            "{}",
+            item
+        );
+    }
+
+    #[tokio::test]
+    async fn test_for_hover_over_a_dict() {
+        let mut service = initialized_service().await;
+        let uri = "file:///dict_loop.ae";
+        let text = "Set AGES {\"ann\": 3}\nFor NAME In AGES {\n    PRINTLN(NAME)\n}\n\
+                    For X In [1] {\n    PRINTLN(X)\n}";
+        open(&mut service, uri, text).await;
+
+        let hover_at = |line: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": 1 },
+            })
+        };
+        let hover = request(&mut service, "textDocument/hover", hover_at(1)).await;
+        let markdown = hover["contents"]["value"].as_str().unwrap();
+        assert!(markdown.starts_with("**For**"), "{}", markdown);
+        assert!(
+            markdown.contains(
+                "`AGES` is a dict, so this loop gives its **keys**; iterate `ITEMS(AGES)`"
+            ),
+            "{}",
+            markdown
+        );
+
+        let hover = request(&mut service, "textDocument/hover", hover_at(4)).await;
+        let markdown = hover["contents"]["value"].as_str().unwrap();
+        assert!(!markdown.contains("ITEMS"), "{}", markdown);
+    }
+
+    #[tokio::test]
+    async fn test_hover_on_an_index_shows_the_array_length() {
+        let mut service = initialized_service().await;
+        let uri = "file:///bounds.ae";
+        open(
+            &mut service,
+            uri,
+            "Set ARR [1, 2, 3]\nSet FIRST ARR[5]\nPRINTLN(FIRST)",
+        )
+        .await;
+
+        let hover_at = |character: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": character },
+            })
+        };
+        let hover = request(&mut service, "textDocument/hover", hover_at(14)).await;
+        assert_eq!(
+            hover["contents"]["value"],
+            "`ARR` comes from an array literal with 3 elements; index 5 is out of range"
+        );
+        assert_eq!(hover["range"]["start"]["character"], 10);
+
+        // The array's own name keeps its variable hover
+        let hover = request(&mut service, "textDocument/hover", hover_at(11)).await;
+        let markdown = hover["contents"]["value"].as_str().unwrap();
+        assert!(!markdown.contains("array literal"), "{}", markdown);
+    }
+
+    #[tokio::test]
+    async fn test_hover_word_after_multibyte_text() {
+        let mut service = initialized_service().await;
+        let uri = "file:///multibyte.ae";
+        // `🙂` takes two UTF-16 units and four bytes, `名` one unit and three bytes
+        open(
+            &mut service,
+            uri,
+            "Lazy DATA(42)\nSet RESULT [\"🙂名\", Force(DATA)]",
+        )
+        .await;
+
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 21 },
+            }),
+        )
+        .await;
+        let markdown = hover["contents"]["value"].as_str().unwrap();
+        assert!(markdown.starts_with("**Force**"), "{}", markdown);
+        assert_eq!(
+            hover["range"],
+            json!({
+                "start": { "line": 1, "character": 19 },
+                "end": { "line": 1, "character": 24 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_references_and_rename_use_occurrences() {
+        let mut service = initialized_service().await;
+        let uri = "file:///rename.ae";
+        open(
+            &mut service,
+            uri,
+            "Set COUNT 1\nPRINTLN(\"COUNT\", COUNT)\nSet COUNT (COUNT + 1)",
+        )
+        .await;
+        let position = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 19 },
+        });
+
+        let mut params = position.clone();
+        params["context"] = json!({ "includeDeclaration": false });
+        let references = request(&mut service, "textDocument/references", params).await;
+        let lines: Vec<(u64, u64)> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| {
+                let start = &location["range"]["start"];
+                (
+                    start["line"].as_u64().unwrap(),
+                    start["character"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(lines, vec![(1, 17), (2, 4), (2, 11)]);
+
+        let mut params = position;
+        params["newName"] = json!("TOTAL");
+        let edit = request(&mut service, "textDocument/rename", params).await;
+        let edits = edit["changes"][uri].as_array().unwrap();
+        assert_eq!(edits.len(), 4);
+        assert!(edits.iter().all(|edit| edit["newText"] == "TOTAL"));
+    }
+
+    #[tokio::test]
+    async fn test_hover_and_rename_follow_import_aliases() {
+        let mut service = initialized_service().await;
+        let uri = "file:///aliases.ae";
+        open(
+            &mut service,
+            uri,
+            "Import {LONG_HELPER_NAME As LH, PI} From \"./lib.ae\"\nPRINTLN(LH(PI))",
+        )
+        .await;
+        let at = |character| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": character },
+            })
+        };
+
+        let hover = request(&mut service, "textDocument/hover", at(9)).await;
+        assert_eq!(
+            hover["contents"]["value"],
+            "Imported from ./lib.ae (original: LONG_HELPER_NAME)"
+        );
+
+        // The alias and its uses change, the imported name never does
+        let edits = |edit: &Value| {
+            let mut edits: Vec<(u64, u64, String)> = edit["changes"][uri]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|edit| {
+                    let start = &edit["range"]["start"];
+                    (
+                        start["line"].as_u64().unwrap(),
+                        start["character"].as_u64().unwrap(),
+                        edit["newText"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect();
+            edits.sort();
+            edits
+        };
+        let mut params = at(9);
+        params["newName"] = json!("HELPER");
+        let edit = request(&mut service, "textDocument/rename", params).await;
+        assert_eq!(
+            edits(&edit),
+            vec![(0, 28, "HELPER".to_string()), (1, 8, "HELPER".to_string())]
+        );
+
+        // Without an alias, renaming adds one
+        let mut params = at(11);
+        params["newName"] = json!("CIRCLE");
+        let edit = request(&mut service, "textDocument/rename", params).await;
+        assert_eq!(
+            edits(&edit),
+            vec![
+                (0, 32, "PI As CIRCLE".to_string()),
+                (1, 11, "CIRCLE".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_explains_why_it_cannot() {
+        let mut service = initialized_service().await;
+        let uri = "file:///refuse.ae";
+        open(&mut service, uri, "Set COUNT 1\nPRINTLN(COUNT)").await;
+        let rename = |uri: &str, line: u32, character: u32, new_name: &str| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+                "newName": new_name,
+            })
+        };
+
+        // A builtin is defined nowhere in the file
+        let error = request_error(
+            &mut service,
+            "textDocument/rename",
+            rename(uri, 1, 3, "SHOW"),
+        )
+        .await;
+        assert_eq!(error["code"], -32903);
+        assert_eq!(error["data"], json!({ "reason": "notARenamableSymbol" }));
+        assert!(
+            error["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("Nothing to rename here")
+        );
+
+        for (params, code) in [
+            (rename("file:///missing.ae", 0, 4, "TOTAL"), -32901),
+            (rename(uri, 7, 0, "TOTAL"), -32902),
+            (rename(uri, 0, 40, "TOTAL"), -32902),
+            (rename(uri, 0, 5, "total"), -32905),
+        ] {
+            let error = request_error(&mut service, "textDocument/rename", params).await;
+            assert_eq!(error["code"], code, "{}", error);
+        }
+
+        // prepareRename gives the range to edit, or the same reason
+        let position = |character: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": character },
+            })
+        };
+        let range = request(&mut service, "textDocument/prepareRename", position(10)).await;
+        assert_eq!(
+            range,
+            json!({
+                "start": { "line": 1, "character": 8 },
+                "end": { "line": 1, "character": 13 },
+            })
+        );
+        let error = request_error(&mut service, "textDocument/prepareRename", position(2)).await;
+        assert_eq!(error["code"], -32903);
+
+        // A hover miss is still an empty answer
+        let hover = request(&mut service, "textDocument/hover", position(30)).await;
+        assert_eq!(hover, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_features_use_last_good_parse_while_broken() {
+        let mut service = initialized_service().await;
+        let uri = "file:///editing.ae";
+        open(
+            &mut service,
+            uri,
+            "// Adds two numbers\nFunc ADD(X, Y) {\n    Return X + Y\n}\n",
+        )
+        .await;
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "// Adds two numbers\nFunc ADD(X, Y) {\n    Return X + (Y\n}\n" }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+
+        let symbols = request(
+            &mut service,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert_eq!(symbols[0]["name"], json!("ADD"));
+
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 6 },
+            }),
+        )
+        .await;
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("Adds two numbers")
+        );
+
+        let completion = request(
+            &mut service,
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 2, "character": 20 },
+            }),
+        )
+        .await;
+        assert_eq!(completion["isIncomplete"], json!(true));
+        assert!(
+            completion["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|item| item["label"] == "ADD")
+        );
+
+        // Diagnostics describe the current text
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert_eq!(report["items"][0]["code"], json!("E003"));
+    }
+
+    #[tokio::test]
+    async fn test_folding_and_outline_survive_a_failed_parse() {
+        let mut service = initialized_service().await;
+        let uri = "file:///broken.ae";
+        open(
+            &mut service,
+            uri,
+            "Set CONFIG [\nFunc ADD(X, Y) {\n    Return X + Y\n}\nSet LIMITS {\nGenerator COUNT(N) {\n    Yield N\n}\n",
+        )
+        .await;
+        let params = json!({ "textDocument": { "uri": uri } });
+
+        let folds = request(&mut service, "textDocument/foldingRange", params.clone()).await;
+        assert_eq!(
+            folds,
+            json!([
+                { "startLine": 1, "endLine": 2 },
+                { "startLine": 5, "endLine": 6 },
+            ])
+        );
+
+        let symbols = request(&mut service, "textDocument/documentSymbol", params).await;
+        let names: Vec<&Value> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| &symbol["name"])
+            .collect();
+        assert_eq!(names, vec![&json!("ADD"), &json!("COUNT")]);
+        assert_eq!(symbols[0]["location"]["uri"], json!(uri));
+    }
+
+    #[tokio::test]
+    async fn test_runs_of_sets_fold_and_group_in_the_outline() {
+        let uri = "file:///config.ae";
+        let params = json!({ "textDocument": { "uri": uri } });
+        let outline = |symbols: Value| -> Vec<(String, usize)> {
+            symbols
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|symbol| {
+                    (
+                        symbol["name"].as_str().unwrap().to_string(),
+                        symbol["children"].as_array().map_or(0, Vec::len),
+                    )
+                })
+                .collect()
+        };
+        for group_sets in [false, true] {
+            let (mut service, _socket) = build_service();
+            request(
+                &mut service,
+                "initialize",
+                json!({
+                    "capabilities": { "textDocument": { "documentSymbol": {
+                        "hierarchicalDocumentSymbolSupport": true
+                    } } },
+                    "initializationOptions": { "aether": { "outline": { "groupSets": group_sets } } },
+                    "locale": "en",
+                }),
+            )
+            .await;
+            open(
+                &mut service,
+                uri,
+                "Set HOST 1\nSet PORT 2\nSet USER 3\n// === Limits ===\nSet RETRIES 4\n\
+                 Set TIMEOUT 5\nLazy BACKOFF(6)\nFunc RUN() {\n    Return HOST\n}",
+            )
+            .await;
+
+            let folds = request(&mut service, "textDocument/foldingRange", params.clone()).await;
+            assert_eq!(
+                folds,
+                json!([
+                    { "startLine": 0, "endLine": 2, "kind": "region" },
+                    { "startLine": 4, "endLine": 6, "kind": "region" },
+                    { "startLine": 7, "endLine": 8 },
+                ])
+            );
+
+            let symbols =
+                request(&mut service, "textDocument/documentSymbol", params.clone()).await;
+            if group_sets {
+                assert_eq!(
+                    outline(symbols),
+                    vec![
+                        ("Constants (3)".to_string(), 3),
+                        ("Limits".to_string(), 3),
+                        ("RUN".to_string(), 0)
+                    ]
+                );
+            } else {
+                // The flat list by default
+                assert_eq!(outline(symbols).len(), 7);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_linked_editing_of_loop_variables() {
+        let (mut service, _socket) = build_service();
+        let initialize = request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        assert_eq!(
+            initialize["capabilities"]["linkedEditingRangeProvider"],
+            json!(true)
+        );
+        let uri = "file:///linked.ae";
+        open(
+            &mut service,
+            uri,
+            "Set ITEMS [1, 2]\nFor ITEM In ITEMS {\n    PRINTLN(ITEM)\n}",
+        )
+        .await;
+        let linked =
+            async |service: &mut LspService<AetherLspBackend>, line: u32, character: u32| {
+                request(
+                    service,
+                    "textDocument/linkedEditingRange",
+                    json!({
+                        "textDocument": { "uri": uri },
+                        "position": { "line": line, "character": character },
+                    }),
+                )
+                .await
+            };
+
+        let ranges = linked(&mut service, 2, 13).await;
+        assert_eq!(ranges["wordPattern"], json!("[A-Za-z_][A-Za-z0-9_]*"));
+        let starts: Vec<&Value> = ranges["ranges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|range| &range["start"])
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                &json!({ "line": 1, "character": 4 }),
+                &json!({ "line": 2, "character": 12 })
+            ]
+        );
+        assert_eq!(linked(&mut service, 1, 14).await, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_member_completion_and_hover() {
+        let mut service = initialized_service().await;
+        open(
+            &mut service,
+            "file:///project/utils.ae",
+            "// Reads a config file\nFunc PARSE_CONFIG(PATH) {\n    Return PATH\n}\nSet VERSION 2\nSet PRIVATE 0\nExport PARSE_CONFIG\nExport VERSION",
+        )
+        .await;
+        let uri = "file:///project/main.ae";
+        let text = "Import UTILS From \"./utils.ae\"\nSet CONFIG UTILS.PARSE_CONFIG(\"a.json\")\nPRINTLN(UTILS.VERSION)";
+        open(&mut service, uri, text).await;
+
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 20 },
+            }),
+        )
+        .await;
+        assert_eq!(hover["contents"]["value"], json!("Reads a config file"));
+        assert_eq!(
+            hover["range"]["start"],
+            json!({ "line": 1, "character": 17 })
+        );
+
+        // While the member is being typed the document doesn't parse
+        for text in [text, &text.replace("UTILS.VERSION", "UTILS.")] {
+            open(&mut service, uri, text).await;
+            let completion = request(
+                &mut service,
+                "textDocument/completion",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 2, "character": 14 },
+                }),
+            )
+            .await;
+            let items = completion.as_array().unwrap();
+            let labels: Vec<&str> = items
+                .iter()
+                .map(|item| item["label"].as_str().unwrap())
+                .collect();
+            assert_eq!(labels, vec!["PARSE_CONFIG", "VERSION"]);
+            assert_eq!(
+                items[0]["documentation"]["value"],
+                json!("Reads a config file")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nothing_fires_inside_multiline_strings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("utils.ae"), "Set VERSION 2\nExport VERSION").unwrap();
+        let uri = lsp_types::Url::from_file_path(dir.path().join("main.ae")).unwrap();
+        let mut service = initialized_service().await;
+        let text = "Set CONFIG {\"debug\": True}\nSet SAMPLE \"\"\"\nImport {A} From \"./utils.ae\"\nSet lower UNDEFINED(1,\nPRINTLN(CONFIG[\"\n\"\"\"\nPRINTLN(SAMPLE, CONFIG[\"deb\"])\nImport {VERSION} From \"./u\"";
+        open(&mut service, uri.as_str(), text).await;
+        let at = |line: u32, character: u32| {
+            json!({
+                "textDocument": { "uri": uri.as_str() },
+                "position": { "line": line, "character": character },
+            })
+        };
+
+        // A keyword, a name, and a call being typed, all inside the sample
+        for (line, character) in [(2, 2), (3, 6), (3, 12)] {
+            let hover = request(&mut service, "textDocument/hover", at(line, character)).await;
+            assert_eq!(hover, Value::Null, "{}:{}", line, character);
+        }
+        let help = request(&mut service, "textDocument/signatureHelp", at(3, 22)).await;
+        assert_eq!(help, Value::Null);
+        for (line, character) in [(2, 20), (3, 10), (4, 16)] {
+            let completion =
+                request(&mut service, "textDocument/completion", at(line, character)).await;
+            assert_eq!(completion, Value::Null, "{}:{}", line, character);
+        }
+
+        // A key or module path typed in its own string still completes
+        let labels = |completion: Value| -> Vec<String> {
+            completion
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let completion = request(&mut service, "textDocument/completion", at(6, 27)).await;
+        assert_eq!(labels(completion), vec!["debug"]);
+        let completion = request(&mut service, "textDocument/completion", at(7, 26)).await;
+        assert_eq!(labels(completion), vec!["./utils.ae"]);
+    }
+
+    #[tokio::test]
+    async fn test_completion_routes_on_trigger_character() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("utils.ae"), "Set VERSION 2\nExport VERSION").unwrap();
+        let main = dir.path().join("main.ae");
+        let uri = lsp_types::Url::from_file_path(&main).unwrap();
+
+        let (mut service, _socket) = build_service();
+        let initialize = request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        assert_eq!(
+            initialize["capabilities"]["completionProvider"]["triggerCharacters"],
+            json!([".", "\"", "["])
+        );
+        open(
+            &mut service,
+            uri.as_str(),
+            "Set CONFIG {\"debug\": True, \"name\": \"x\"}\nPRINTLN(CONFIG[\nPRINTLN(CONFIG[\"\nPRINTLN(\"\nImport {A} From \"\nImport UTILS From \"./utils.ae\"\nPRINTLN(UTILS.\nSet N 1.",
+        )
+        .await;
+
+        let mut labels = async |line: u32, character: u32, trigger: Option<&str>| {
+            let context = match trigger {
+                Some(character) => json!({ "triggerKind": 2, "triggerCharacter": character }),
+                None => json!({ "triggerKind": 1 }),
+            };
+            let completion = request(
+                &mut service,
+                "textDocument/completion",
+                json!({
+                    "textDocument": { "uri": uri.as_str() },
+                    "position": { "line": line, "character": character },
+                    "context": context,
+                }),
+            )
+            .await;
+            let items = completion.get("items").unwrap_or(&completion);
+            items.as_array().map(|items| {
+                items
+                    .iter()
+                    .map(|item| item["label"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let keys = Some(vec!["debug".to_string(), "name".to_string()]);
+        assert_eq!(labels(1, 15, Some("[")).await, keys);
+        assert_eq!(labels(2, 16, Some("\"")).await, keys);
+        assert_eq!(labels(1, 15, None).await, keys);
+        assert_eq!(
+            labels(4, 17, Some("\"")).await,
+            Some(vec!["./utils.ae".to_string()])
+        );
+        assert_eq!(
+            labels(6, 14, Some(".")).await,
+            Some(vec!["VERSION".to_string()])
+        );
+
+        // Opening a string or typing a decimal point elsewhere offers nothing...
+        assert_eq!(labels(3, 9, Some("\"")).await, None);
+        assert_eq!(labels(7, 8, Some(".")).await, None);
+        // ...while invoking completion by hand there still gives the general list
+        let general = labels(7, 8, None).await.unwrap();
+        assert!(general.contains(&"CONFIG".to_string()), "{:?}", general);
+        assert!(general.contains(&"PRINTLN".to_string()), "{:?}", general);
+    }
+
+    #[tokio::test]
+    async fn test_goto_definition_follows_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("lib.ae"),
+            "Set PI 3.14\nFunc ADD(A, B) {\n    Return A + B\n}\nExport ADD",
+        )
+        .unwrap();
+        let main = dir.path().join("main.ae");
+        let uri = lsp_types::Url::from_file_path(&main).unwrap();
+
+        let mut service = initialized_service().await;
+        open(
+            &mut service,
+            uri.as_str(),
+            "Import {ADD As PLUS} From \"./lib.ae\"\nSet SUM PLUS(1, 2)",
+        )
+        .await;
+        let location = request(
+            &mut service,
+            "textDocument/definition",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 9 },
+            }),
+        )
+        .await;
+
+        let lib = lsp_types::Url::from_file_path(dir.path().join("lib.ae")).unwrap();
+        assert_eq!(location["uri"], json!(lib));
+        assert_eq!(
+            location["range"]["start"],
+            json!({ "line": 1, "character": 5 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requests_on_unopened_documents_read_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.ae");
+        std::fs::write(
+            &path,
+            "// Adds two numbers\nFunc ADD(A, B) {\n    Return A + B\n}\nSet SUM ADD(1, 2)",
+        )
+        .unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let hover_params = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 4, "character": 9 },
+        });
+
+        let mut service = initialized_service().await;
+        let hover = request(&mut service, "textDocument/hover", hover_params.clone()).await;
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("Adds two numbers"),
+            "{}",
+            hover
+        );
+        let location = request(
+            &mut service,
+            "textDocument/definition",
+            hover_params.clone(),
+        )
+        .await;
+        assert_eq!(
+            location["range"]["start"],
+            json!({ "line": 1, "character": 5 })
+        );
+
+        // Opening the file replaces the copy read from disk
+        open(
+            &mut service,
+            uri.as_str(),
+            "Func ADD(A, B) {\n    Return A + B\n}\n\nSet SUM ADD(1, 2)",
+        )
+        .await;
+        let location = request(&mut service, "textDocument/definition", hover_params).await;
+        assert_eq!(
+            location["range"]["start"],
+            json!({ "line": 0, "character": 5 })
+        );
+
+        let missing = lsp_types::Url::from_file_path(dir.path().join("nope.ae")).unwrap();
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": missing },
+                "position": { "line": 0, "character": 0 },
+            }),
+        )
+        .await;
+        assert_eq!(hover, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_rechecks_leave_unopened_documents_unpublished() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let unopened = lsp_types::Url::from_file_path(dir.path().join("lib.ae")).unwrap();
+        std::fs::write(dir.path().join("lib.ae"), "Set total 1\nPRINTLN(total)").unwrap();
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": unopened },
+                "position": { "line": 1, "character": 9 },
+            }),
+        )
+        .await;
+        assert_ne!(hover, Value::Null);
+
+        let folders = Request::build("workspace/didChangeWorkspaceFolders")
+            .params(json!({ "event": { "added": [], "removed": [] } }))
+            .finish();
+        service.ready().await.unwrap().call(folders).await.unwrap();
+        let marker = "file:///marker.ae";
+        open(&mut service, marker, "PRINTLN(1)").await;
+        let first = published.recv().await.unwrap();
+        assert_eq!(first["uri"], marker);
+    }
+
+    #[tokio::test]
+    async fn test_watched_file_changes_refresh_dependents() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib.ae");
+        let util = dir.path().join("util.ae");
+        std::fs::write(&util, "Set PI 3.14\nExport PI").unwrap();
+        std::fs::write(&lib, "Import {PI} From \"./util.ae\"\nExport PI").unwrap();
+        let main = lsp_types::Url::from_file_path(dir.path().join("main.ae")).unwrap();
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+
+        let mut notify = async |method: &'static str, params: Value| {
+            let notification = Request::build(method).params(params).finish();
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(notification)
+                .await
+                .unwrap();
+            published.recv().await.unwrap()
+        };
+        let messages = |params: Value| -> Vec<String> {
+            params["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|diagnostic| diagnostic["message"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let opened = notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": main,
+                    "languageId": "aether",
+                    "version": 1,
+                    "text": "Import {PI} From \"./lib.ae\"\nPRINTLN(PI)",
+                }
+            }),
+        )
+        .await;
+        assert!(messages(opened).is_empty());
+
+        // Deleting the imported module is reported in the open dependent
+        std::fs::remove_file(&lib).unwrap();
+        let lib_uri = lsp_types::Url::from_file_path(&lib).unwrap();
+        let deleted = notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [{ "uri": lib_uri, "type": 3 }] }),
+        )
+        .await;
+        assert_eq!(deleted["uri"], json!(main));
+        assert_eq!(messages(deleted), vec!["Module './lib.ae' not found"]);
+
+        // Restoring it clears the error; a change two imports away also reaches main
+        std::fs::write(&lib, "Import {PI} From \"./util.ae\"\nExport PI").unwrap();
+        let created = notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [{ "uri": lib_uri, "type": 1 }] }),
+        )
+        .await;
+        assert!(messages(created).is_empty());
+
+        let util_uri = lsp_types::Url::from_file_path(&util).unwrap();
+        let changed = notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [{ "uri": util_uri, "type": 2 }] }),
+        )
+        .await;
+        assert_eq!(changed["uri"], json!(main));
+    }
+
+    #[tokio::test]
+    async fn test_multi_root_imports() {
+        use futures::StreamExt;
+        use lsp_types::Url;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (root_a, root_b) = (dir.path().join("app"), dir.path().join("shared"));
+        std::fs::create_dir_all(root_a.join("src")).unwrap();
+        std::fs::create_dir_all(root_b.join("lib")).unwrap();
+        std::fs::write(
+            root_b.join("lib/greet.ae"),
+            "Func GREET() {\n    Return \"hi\"\n}\nExport GREET",
+        )
+        .unwrap();
+        let main = Url::from_file_path(root_a.join("src/main.ae")).unwrap();
+        let folder = |root: &std::path::Path| json!({ "uri": Url::from_file_path(root).unwrap(), "name": "folder" });
+
+        let (mut service, socket) = build_service();
+        tokio::spawn(socket.collect::<Vec<_>>());
+        let initialize = request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "textDocument": { "diagnostic": {} } },
+                "workspaceFolders": [folder(&root_a), folder(&root_b)],
+                "locale": "en",
+            }),
+        )
+        .await;
+        assert_eq!(
+            initialize["capabilities"]["workspace"]["workspaceFolders"]["changeNotifications"],
+            json!(true)
+        );
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialized)
+            .await
+            .unwrap();
+        open(
+            &mut service,
+            main.as_str(),
+            "Import {GREET} From \"lib/greet.ae\"\nPRINTLN(GREET())",
+        )
+        .await;
+
+        let codes = async |service: &mut LspService<AetherLspBackend>| {
+            let report = request(
+                service,
+                "textDocument/diagnostic",
+                json!({ "textDocument": { "uri": main.as_str() } }),
+            )
+            .await;
+            report["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["code"].as_str().unwrap_or_default().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(codes(&mut service).await.is_empty());
+
+        let links = request(
+            &mut service,
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": main.as_str() } }),
+        )
+        .await;
+        assert_eq!(
+            links,
+            json!([{
+                "range": {
+                    "start": { "line": 0, "character": 21 },
+                    "end": { "line": 0, "character": 33 },
+                },
+                "target": Url::from_file_path(root_b.join("lib/greet.ae")).unwrap(),
+                "tooltip": format!("Resolved from workspace folder {}", root_b.display()),
+            }])
+        );
+
+        // Background indexing covers every root
+        let mut found = Value::Null;
+        for _ in 0..100 {
+            found = request(
+                &mut service,
+                "workspace/symbol",
+                json!({ "query": "GREET" }),
+            )
+            .await;
+            if found.as_array().is_some_and(|symbols| !symbols.is_empty()) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            found[0]["location"]["uri"],
+            json!(Url::from_file_path(root_b.join("lib/greet.ae")).unwrap())
+        );
+
+        // Without root B the import no longer resolves; adding it back fixes it again
+        let change_folders = async |service: &mut LspService<AetherLspBackend>, event: Value| {
+            let change = Request::build("workspace/didChangeWorkspaceFolders")
+                .params(json!({ "event": event }))
+                .finish();
+            service.ready().await.unwrap().call(change).await.unwrap();
+        };
+        change_folders(
+            &mut service,
+            json!({ "added": [], "removed": [folder(&root_b)] }),
+        )
+        .await;
+        assert_eq!(codes(&mut service).await, vec!["E005"]);
+        let gone = request(
+            &mut service,
+            "workspace/symbol",
+            json!({ "query": "GREET" }),
+        )
+        .await;
+        assert_eq!(gone, json!([]));
+        change_folders(
+            &mut service,
+            json!({ "added": [folder(&root_b)], "removed": [] }),
+        )
+        .await;
+        assert!(codes(&mut service).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ignored_files_stay_out_of_workspace_symbols() {
+        use futures::StreamExt;
+        use lsp_types::Url;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("generated")).unwrap();
+        std::fs::write(root.join(".aetherignore"), "# build output\ngenerated/\n").unwrap();
+        std::fs::write(root.join("main.ae"), "Set MAIN_VALUE 1").unwrap();
+        let generated = root.join("generated/out.ae");
+        std::fs::write(&generated, "Set GENERATED_VALUE MISSING").unwrap();
+        let generated_uri = Url::from_file_path(&generated).unwrap();
+
+        let (mut service, socket) = build_service();
+        tokio::spawn(socket.collect::<Vec<_>>());
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "textDocument": { "diagnostic": {} } },
+                "rootUri": Url::from_file_path(root).unwrap(),
+                "locale": "en",
+            }),
+        )
+        .await;
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialized)
+            .await
+            .unwrap();
+
+        let symbols = async |service: &mut LspService<AetherLspBackend>, query: &str| {
+            let mut found = Vec::new();
+            for _ in 0..100 {
+                let result = request(service, "workspace/symbol", json!({ "query": query })).await;
+                found = result
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|symbol| symbol["name"].as_str().unwrap().to_string())
+                    .collect();
+                if !found.is_empty() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            found
+        };
+        assert_eq!(symbols(&mut service, "VALUE").await, vec!["MAIN_VALUE"]);
+
+        // Opened, the excluded file is analyzed but still not in workspace symbols
+        open(
+            &mut service,
+            generated_uri.as_str(),
+            "Set GENERATED_VALUE MISSING",
+        )
+        .await;
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": generated_uri } }),
+        )
+        .await;
+        assert!(
+            report["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|item| item["message"].as_str().unwrap().contains("MISSING"))
+        );
+        assert_eq!(symbols(&mut service, "VALUE").await, vec!["MAIN_VALUE"]);
+
+        // Emptying the ignore file indexes the folder again
+        std::fs::write(root.join(".aetherignore"), "").unwrap();
+        let watched = Request::build("workspace/didChangeWatchedFiles")
+            .params(json!({ "changes": [{
+                "uri": Url::from_file_path(root.join(".aetherignore")).unwrap(),
+                "type": 2,
+            }] }))
+            .finish();
+        service.ready().await.unwrap().call(watched).await.unwrap();
+        assert_eq!(
+            symbols(&mut service, "GENERATED").await,
+            vec!["GENERATED_VALUE"]
+        );
+    }
+
+    /// Many documents opened and edited at once over a real transport, so the
+    /// server dispatches them concurrently
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_documents_publish_once_per_final_version() {
+        use std::collections::HashMap;
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let (service, socket) = build_service();
+        let (mut to_server, server_input) = tokio::io::duplex(1 << 16);
+        let (server_output, from_server) = tokio::io::duplex(1 << 16);
+        tokio::spawn(Server::new(server_input, server_output, socket).serve(service));
+
+        // Every message the server sends, until it closes its output
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<Value>();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(from_server);
+            loop {
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).await.unwrap() == 0 {
+                        return;
+                    }
+                    match header.trim().strip_prefix("Content-Length: ") {
+                        Some(value) => length = value.parse().unwrap(),
+                        None if header.trim().is_empty() => break,
+                        None => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                let _ = sender.send(serde_json::from_slice(&body).unwrap());
+            }
+        });
+        let frame = |message: Value| {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+        };
+
+        let initialize = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": { "capabilities": {}, "locale": "en" },
+        });
+        to_server.write_all(&frame(initialize)).await.unwrap();
+        while received.recv().await.unwrap()["id"] != 1 {}
+
+        const DOCUMENTS: usize = 40;
+        let uri = |index: usize| format!("file:///stress/doc_{}.ae", index);
+        let mut batch = Vec::new();
+        for index in 0..DOCUMENTS {
+            batch.extend(frame(json!({
+                "jsonrpc": "2.0", "method": "textDocument/didOpen",
+                "params": { "textDocument": {
+                    "uri": uri(index), "languageId": "aether", "version": 1,
+                    "text": format!("Set first_{} 1", index),
+                } },
+            })));
+            batch.extend(frame(json!({
+                "jsonrpc": "2.0", "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": uri(index), "version": 2 },
+                    "contentChanges": [{ "text": format!("Set first_{} 1\nSet second_{} 2", index, index) }],
+                },
+            })));
+        }
+        to_server.write_all(&batch).await.unwrap();
+
+        // (version, number of diagnostics) of each publish, per document
+        let mut published: HashMap<String, Vec<(i64, usize)>> = HashMap::new();
+        let finished = |published: &HashMap<String, Vec<(i64, usize)>>| {
+            (0..DOCUMENTS).all(|index| {
+                published
+                    .get(&uri(index))
+                    .is_some_and(|versions| versions.iter().any(|(version, _)| *version == 2))
+            })
+        };
+        fn record(published: &mut HashMap<String, Vec<(i64, usize)>>, message: Value) {
+            if message["method"] == "textDocument/publishDiagnostics" {
+                let params = &message["params"];
+                published
+                    .entry(params["uri"].as_str().unwrap().to_string())
+                    .or_default()
+                    .push((
+                        params["version"].as_i64().unwrap(),
+                        params["diagnostics"].as_array().unwrap().len(),
+                    ));
+            }
+        }
+        tokio::time::timeout(std::time::Duration::from_secs(30), async {
+            while !finished(&published) {
+                record(&mut published, received.recv().await.unwrap());
+            }
+        })
+        .await
+        .expect("every document should get diagnostics for its final version");
+
+        // Shut down and take whatever was still on its way
+        let shutdown = json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" });
+        to_server.write_all(&frame(shutdown)).await.unwrap();
+        while let Some(message) = received.recv().await {
+            if message["id"] == 2 {
+                break;
+            }
+            record(&mut published, message);
+        }
+        let exit = json!({ "jsonrpc": "2.0", "method": "exit" });
+        to_server.write_all(&frame(exit)).await.unwrap();
+        drop(to_server);
+        while let Some(message) = received.recv().await {
+            record(&mut published, message);
+        }
+
+        for index in 0..DOCUMENTS {
+            let versions = &published[&uri(index)];
+            assert_eq!(
+                versions.iter().filter(|(version, _)| *version == 2).count(),
+                1,
+                "{}: {:?}",
+                uri(index),
+                versions
+            );
+            // Nothing from the first version after the final one
+            assert_eq!(
+                versions.last(),
+                Some(&(2, 2)),
+                "{}: {:?}",
+                uri(index),
+                versions
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_comment_edits_move_diagnostics_without_analysis() {
+        use futures::StreamExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        fn counting_rule(_: &ast::Program) -> Vec<lsp_types::Diagnostic> {
+            RUNS.fetch_add(1, Ordering::SeqCst);
+            Vec::new()
+        }
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        diagnostics::INJECTED_RULE.set(Some(counting_rule));
+
+        let uri = "file:///comments.ae";
+        open(&mut service, uri, "Set total 1\nPRINTLN(total)").await;
+        let lines = |params: Value| -> Vec<u64> {
+            params["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|diagnostic| diagnostic["range"]["start"]["line"].as_u64().unwrap())
+                .collect()
+        };
+        assert_eq!(lines(published.recv().await.unwrap()), vec![0]);
+        let runs = RUNS.load(Ordering::SeqCst);
+
+        let mut change = async |version: i32, text: &str| {
+            let change = Request::build("textDocument/didChange")
+                .params(json!({
+                    "textDocument": { "uri": uri, "version": version },
+                    "contentChanges": [{ "text": text }],
+                }))
+                .finish();
+            service.ready().await.unwrap().call(change).await.unwrap();
+        };
+
+        // A comment line above moves the warning down without analyzing again
+        change(2, "// Running total\n\nSet total 1\nPRINTLN(total)").await;
+        assert_eq!(lines(published.recv().await.unwrap()), vec![2]);
+        // Editing the comment in place moves nothing, so nothing is republished
+        change(3, "// Running sum\n\nSet total 1\nPRINTLN(total)").await;
+        assert_eq!(RUNS.load(Ordering::SeqCst), runs);
+
+        // A real edit is analyzed as usual
+        change(4, "// Running sum\n\nSet total 2\nPRINTLN(total)").await;
+        assert_eq!(lines(published.recv().await.unwrap()), vec![2]);
+        assert_eq!(RUNS.load(Ordering::SeqCst), runs + 1);
+        diagnostics::INJECTED_RULE.set(None);
+        assert!(published.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reindented_comment_is_linted_again() {
+        use futures::StreamExt;
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": {},
+                "locale": "en",
+                "initializationOptions": { "aether": { "lint": { "indentation": { "enabled": true } } } },
+            }),
+        )
+        .await;
+
+        let uri = "file:///indented.ae";
+        let text = "Func F(X) {\n    // identity\n    Return X\n}\nPRINTLN(F(1))";
+        open(&mut service, uri, text).await;
+        let hints = |params: Value| -> Vec<u64> {
+            params["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|diagnostic| diagnostic["code"] == "W031")
+                .map(|diagnostic| diagnostic["range"]["start"]["line"].as_u64().unwrap())
+                .collect()
+        };
+        assert!(hints(published.recv().await.unwrap()).is_empty());
+
+        // Only the comment moved, but off the indent width
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": text.replace("    //", "  //") }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        assert_eq!(hints(published.recv().await.unwrap()), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_applied_suppression_clears_the_warning() {
+        use futures::StreamExt;
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+
+        let uri = "file:///suppressed.ae";
+        let text = "Set total 1\nPRINTLN(total)";
+        open(&mut service, uri, text).await;
+        let opened = published.recv().await.unwrap();
+        let warning = opened["diagnostics"][0].clone();
+        assert_eq!(warning["code"], "W001");
+
+        let actions = request(
+            &mut service,
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": warning["range"],
+                "context": { "diagnostics": [warning] },
+            }),
+        )
+        .await;
+        let disable = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["title"] == "Disable W001 for this line")
+            .unwrap();
+        let edit = &disable["edit"]["changes"][uri][0];
+        assert_eq!(edit["range"]["start"], json!({ "line": 0, "character": 0 }));
+        let edited = format!("{}{}", edit["newText"].as_str().unwrap(), text);
+
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": edited }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        assert_eq!(published.recv().await.unwrap()["diagnostics"], json!([]));
+
+        // Taking the directive out again brings the warning back
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 3 },
+                "contentChanges": [{ "text": text }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        assert_eq!(
+            published.recv().await.unwrap()["diagnostics"][0]["code"],
+            "W001"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adding_a_deprecated_tag_warns_at_calls() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let lib = lsp_types::Url::from_file_path(dir.path().join("lib.ae")).unwrap();
+        let main = lsp_types::Url::from_file_path(dir.path().join("main.ae")).unwrap();
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let mut codes_of = async |uri: &lsp_types::Url| loop {
+            let params: Value = published.recv().await.unwrap();
+            if params["uri"] == json!(uri) {
+                break params["diagnostics"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|diagnostic| diagnostic["code"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+            }
+        };
+
+        let text = "Func OLD_SUM() {\n    Return 1\n}\nExport OLD_SUM\nPRINTLN(OLD_SUM())";
+        std::fs::write(dir.path().join("lib.ae"), text).unwrap();
+        open(&mut service, lib.as_str(), text).await;
+        assert!(codes_of(&lib).await.is_empty());
+        open(
+            &mut service,
+            main.as_str(),
+            "Import {OLD_SUM} From \"./lib.ae\"\nPRINTLN(OLD_SUM())",
+        )
+        .await;
+        assert!(codes_of(&main).await.is_empty());
+
+        // Only a comment is added, yet calls here and in the importer are flagged
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": lib, "version": 2 },
+                "contentChanges": [{ "text": format!("// @deprecated\n{}", text) }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        assert_eq!(codes_of(&lib).await, vec!["W038"]);
+        assert_eq!(codes_of(&main).await, vec!["W038"]);
+    }
+
+    #[tokio::test]
+    async fn test_header_directive_edits_are_diagnosed() {
+        use futures::StreamExt;
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let uri = "file:///header.ae";
+        open(&mut service, uri, "//! requires: 1.2\nSet TOTAL 1").await;
+        assert_eq!(published.recv().await.unwrap()["diagnostics"], json!([]));
+
+        let mut change = async |version: i32, text: &str| {
+            let change = Request::build("textDocument/didChange")
+                .params(json!({
+                    "textDocument": { "uri": uri, "version": version },
+                    "contentChanges": [{ "text": text }],
+                }))
+                .finish();
+            service.ready().await.unwrap().call(change).await.unwrap();
+            published.recv().await.unwrap()["diagnostics"].clone()
+        };
+        // Breaking the directive, and fixing it again, only edits a comment
+        let broken = change(2, "//! requires: soon\nSet TOTAL 1").await;
+        assert_eq!(broken[0]["code"], "W025");
+        let fixed = change(3, "//! requires: 1.3\nSet TOTAL 1").await;
+        assert_eq!(fixed, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_saved_cross_file_errors_survive_edits() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.ae"), "Set ADD 1\nExport ADD").unwrap();
+        let main = lsp_types::Url::from_file_path(dir.path().join("main.ae")).unwrap();
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        let capabilities = request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        assert_eq!(
+            capabilities["capabilities"]["textDocumentSync"]["save"],
+            json!({ "includeText": true })
+        );
+
+        let mut notify = async |method: &'static str, params: Value| {
+            let notification = Request::build(method).params(params).finish();
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(notification)
+                .await
+                .unwrap();
+            published.recv().await.unwrap()
+        };
+        let codes = |params: Value| -> Vec<String> {
+            params["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|diagnostic| diagnostic["code"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let text = "Import {ADD, SUB} From \"./lib.ae\"\nPRINTLN(ADD, SUB)";
+        let opened = notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": main, "languageId": "aether", "version": 1, "text": text }
+            }),
+        )
+        .await;
+        assert!(codes(opened).is_empty());
+
+        let saved = notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": main }, "text": text }),
+        )
+        .await;
+        assert_eq!(codes(saved.clone()), vec!["E006"]);
+        assert_eq!(
+            saved["diagnostics"][0]["message"],
+            json!("'SUB' is not exported by './lib.ae'")
+        );
+
+        // The fast republish after an edit keeps the last save's cross-file errors
+        let changed = notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": main, "version": 2 },
+                "contentChanges": [{ "text": format!("{}\nSet UNUSED_VALUE 1", text) }]
+            }),
+        )
+        .await;
+        assert_eq!(codes(changed), vec!["E006"]);
+    }
+
+    #[tokio::test]
+    async fn test_removed_export_rechecks_open_importers() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let lib_text = "Func HELPER() {\n    Return 1\n}\nExport HELPER";
+        std::fs::write(dir.path().join("lib.ae"), lib_text).unwrap();
+        let lib = lsp_types::Url::from_file_path(dir.path().join("lib.ae")).unwrap();
+        let main = lsp_types::Url::from_file_path(dir.path().join("main.ae")).unwrap();
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+
+        let mut notify = async |method: &'static str, params: Value| {
+            let notification = Request::build(method).params(params).finish();
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(notification)
+                .await
+                .unwrap();
+        };
+        let mut next_for = async |uri: &lsp_types::Url| loop {
+            let params = published.recv().await.unwrap();
+            if params["uri"] == json!(uri) {
+                return params["diagnostics"].as_array().unwrap().clone();
+            }
+        };
+
+        for (uri, text) in [
+            (&lib, lib_text),
+            (
+                &main,
+                "Import {HELPER} From \"./lib.ae\"\nPRINTLN(HELPER())",
+            ),
+        ] {
+            notify(
+                "textDocument/didOpen",
+                json!({
+                    "textDocument": { "uri": uri, "languageId": "aether", "version": 1, "text": text }
+                }),
+            )
+            .await;
+        }
+        assert!(next_for(&main).await.is_empty());
+
+        // main.ae is never touched; dropping the export alone re-diagnoses it
+        notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": lib, "version": 2 },
+                "contentChanges": [{ "text": "Func HELPER() {\n    Return 1\n}" }]
+            }),
+        )
+        .await;
+        let diagnostics = next_for(&main).await;
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0]["code"], json!("E006"));
+        assert_eq!(diagnostics[0]["severity"], json!(1));
+        assert_eq!(
+            diagnostics[0]["message"],
+            json!("'HELPER' is not exported by './lib.ae'")
+        );
+
+        // Putting it back clears the error the same way
+        notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": lib, "version": 3 },
+                "contentChanges": [{ "text": lib_text }]
+            }),
+        )
+        .await;
+        assert!(next_for(&main).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_uri_spellings_share_one_document() {
+        use futures::StreamExt;
+
+        let (mut service, mut socket) = build_service();
+        let (sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = socket.next().await {
+                if message.method() == "textDocument/publishDiagnostics" {
+                    let _ = sender.send(message.params().cloned().unwrap());
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let mut notify = async |method: &'static str, params: Value| {
+            let notification = Request::build(method).params(params).finish();
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(notification)
+                .await
+                .unwrap();
+            published.recv().await.unwrap()
+        };
+
+        // The same Windows file, as two clients (or one, inconsistently) spell it
+        let upper = "file:///C:/proj/A.ae";
+        let encoded = "file:///c%3A/proj/a.ae";
+        let opened = notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": upper, "languageId": "aether", "version": 1,
+                    "text": "Set COUNT 1\nPRINTLN(COUNT)"
+                }
+            }),
+        )
+        .await;
+        assert_eq!(opened["uri"], json!(upper));
+        let changed = notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": encoded, "version": 2 },
+                "contentChanges": [{ "text": "Set TOTAL 2\nPRINTLN(TOTAL, MISSING)" }]
+            }),
+        )
+        .await;
+        // Published under the spelling the client just used
+        assert_eq!(
+            lsp_types::Url::parse(changed["uri"].as_str().unwrap())
+                .unwrap()
+                .path(),
+            "/c%3A/proj/a.ae"
+        );
+        assert_eq!(changed["diagnostics"].as_array().unwrap().len(), 1);
+
+        let status = Request::build("aether/serverStatus").id(1).finish();
+        let status = service
+            .ready()
+            .await
+            .unwrap()
+            .call(status)
+            .await
+            .unwrap()
+            .unwrap()
+            .into_parts()
+            .1
+            .unwrap();
+        assert_eq!(status["documents"], 1);
+        assert_eq!(status["openDocuments"], 1);
+
+        // Either spelling answers from the edited text
+        for uri in [upper, encoded] {
+            let hover = request(
+                &mut service,
+                "textDocument/hover",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 1, "character": 10 },
+                }),
+            )
+            .await;
+            assert_eq!(
+                hover["contents"]["value"],
+                json!("Variable: TOTAL"),
+                "{}",
+                uri
+            );
+            let symbols = request(
+                &mut service,
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": uri } }),
+            )
+            .await;
+            assert_eq!(symbols.as_array().unwrap().len(), 1, "{}", uri);
+            assert_eq!(symbols[0]["name"], json!("TOTAL"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_responses_follow_client_capabilities() {
+        let rich = json!({
+            "textDocument": {
+                "diagnostic": {},
+                "hover": { "contentFormat": ["markdown", "plaintext"] },
+                "completion": { "completionItem": { "documentationFormat": ["markdown"] } },
+                "publishDiagnostics": { "relatedInformation": true },
+            }
+        });
+        let plain = json!({
+            "textDocument": {
+                "diagnostic": {},
+                "hover": { "contentFormat": ["plaintext"] },
+                "completion": { "completionItem": { "documentationFormat": ["plaintext"] } },
+            }
+        });
+
+        for (capabilities, markdown) in [(rich, true), (plain, false)] {
+            let (mut service, _) = build_service();
+            request(
+                &mut service,
+                "initialize",
+                json!({ "capabilities": capabilities, "locale": "en" }),
+            )
+            .await;
+            let uri = "file:///caps.ae";
+            open(&mut service, uri, "Set X 1\nSet X 2\nPRINTLN(X)").await;
+            let position = json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 2, "character": 3 },
+            });
+
+            let hover = request(&mut service, "textDocument/hover", position.clone()).await;
+            let value = hover["contents"]["value"].as_str().unwrap();
+            if markdown {
+                assert_eq!(hover["contents"]["kind"], "markdown");
+                assert!(value.starts_with("## PRINTLN"), "{}", value);
+            } else {
+                assert_eq!(hover["contents"]["kind"], "plaintext");
+                assert!(value.starts_with("PRINTLN (builtin)"), "{}", value);
+                assert!(!value.contains("**") && !value.contains("```"), "{}", value);
+            }
+
+            let completion = request(&mut service, "textDocument/completion", position).await;
+            let println = completion["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|item| item["label"] == "PRINTLN")
+                .unwrap();
+            assert_eq!(
+                println["documentation"]["kind"].is_string(),
+                markdown,
+                "{}",
+                println["documentation"]
+            );
+            assert_eq!(println["documentation"].is_string(), !markdown);
+
+            let report = request(
+                &mut service,
+                "textDocument/diagnostic",
+                json!({ "textDocument": { "uri": uri } }),
+            )
+            .await;
+            let dead_store = report["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|item| item["code"] == "W011")
+                .unwrap();
+            assert_eq!(dead_store.get("relatedInformation").is_some(), markdown);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pulled_diagnostics_report_unchanged() {
+        let (mut service, _) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": { "textDocument": { "diagnostic": {} } }, "locale": "en" }),
+        )
+        .await;
+        let uri = "file:///pull.ae";
+        open(&mut service, uri, "Set total 1").await;
+
+        let first = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert_eq!(first["kind"], "full");
+        assert_eq!(first["items"].as_array().unwrap().len(), 1);
+
+        let second = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri }, "previousResultId": first["resultId"] }),
+        )
+        .await;
+        assert_eq!(
+            second,
+            json!({ "kind": "unchanged", "resultId": first["resultId"] })
+        );
+
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "Set TOTAL 1" }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        let edited = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri }, "previousResultId": first["resultId"] }),
+        )
+        .await;
+        assert_eq!(edited["kind"], "full");
+        assert_ne!(edited["resultId"], first["resultId"]);
+        assert!(edited["items"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_snippets_only_at_statement_start() {
+        let (mut service, _) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": {
+                    "textDocument": { "completion": { "completionItem": { "snippetSupport": true } } }
+                },
+                "initializationOptions": { "aether": { "completion": { "snippets": [
+                    { "label": "retry", "body": "RETRY(${1:TASK}, ${2:3})$0", "description": "Retry a task" }
+                ] } } },
+                "locale": "en",
+            }),
+        )
+        .await;
+        let uri = "file:///snippets.ae";
+        open(&mut service, uri, "Set A 1\n    re\nPRINTLN(re)").await;
+        let snippets = |completion: Value| -> Vec<String> {
+            completion["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|item| item["kind"] == json!(lsp_types::CompletionItemKind::SNIPPET))
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let at = |line: u32, character: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            })
+        };
+
+        let start = request(&mut service, "textDocument/completion", at(1, 6)).await;
+        let labels = snippets(start.clone());
+        assert!(labels.contains(&"retry".to_string()), "{:?}", labels);
+        assert!(labels.contains(&"fori".to_string()), "{:?}", labels);
+        let retry = start["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["label"] == "retry")
+            .unwrap();
+        assert_eq!(retry["insertText"], "RETRY(${1:TASK}, ${2:3})$0");
+        assert_eq!(retry["detail"], "Retry a task");
+
+        // Inside an expression, and after `Set A`
+        let argument = request(&mut service, "textDocument/completion", at(2, 10)).await;
+        assert!(snippets(argument).is_empty());
+        let value = request(&mut service, "textDocument/completion", at(0, 6)).await;
+        assert!(snippets(value).is_empty());
+
+        // A client that can't expand snippets gets none
+        let mut plain = initialized_service().await;
+        open(&mut plain, uri, "Set A 1\n    re\nPRINTLN(re)").await;
+        let start = request(&mut plain, "textDocument/completion", at(1, 6)).await;
+        assert!(snippets(start).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lenient_keywords_setting() {
+        let (mut service, _) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "textDocument": { "diagnostic": {} } },
+                "initializationOptions": { "aether": { "lenientKeywords": true } },
+                "locale": "en",
+            }),
+        )
+        .await;
+        let uri = "file:///lenient.ae";
+        open(
+            &mut service,
+            uri,
+            "SET X 1\nwhile (X < 3) {\n    Set X (X + 1)\n}",
+        )
+        .await;
+
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        let items = report["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2, "{:?}", items);
+        assert!(items.iter().all(|item| item["code"] == "W027"));
+
+        let actions = request(
+            &mut service,
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": items[1]["range"],
+                "context": { "diagnostics": [items[1]], "only": ["quickfix"] },
+            }),
+        )
+        .await;
+        let titles: Vec<&str> = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|action| action["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Write `While`",
+                "Explain aether-lint(W027)",
+                "Disable W027 for this line"
+            ],
+            "{}",
+            actions
+        );
+        assert_eq!(actions[0]["diagnostics"][0]["code"], "W027");
+        assert_eq!(
+            actions[0]["edit"]["changes"][uri][0]["newText"],
+            json!("While")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pulled_diagnostics_are_capped() {
+        let (mut service, _) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "textDocument": { "diagnostic": {} } },
+                "initializationOptions": { "aether": { "maxProblems": 3 } },
+                "locale": "en",
+            }),
+        )
+        .await;
+        let uri = "file:///capped.ae";
+        let text: String = (0..5)
+            .map(|i| format!("PRINTLN(MISSING_{})\n", i))
+            .chain(["Set BROKEN (1 +".to_string()])
+            .collect();
+        open(&mut service, uri, &text).await;
+
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        let items: Vec<(String, String)> = report["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["code"].as_str().unwrap_or_default().to_string(),
+                    item["message"]
+                        .as_str()
+                        .unwrap()
+                        .lines()
+                        .next()
+                        .unwrap()
+                        .to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].0, "E002");
+        assert_eq!(items[1].0, "W006");
+        assert_eq!(
+            items[3],
+            (String::new(), "3 more problems not shown".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_panicking_rule_does_not_stop_the_server() {
+        use futures::StreamExt;
+
+        fn panicking_rule(_: &ast::Program) -> Vec<lsp_types::Diagnostic> {
+            panic!("injected rule failure")
+        }
+
+        let (mut service, socket) = build_service();
+        let drain = tokio::spawn(socket.collect::<Vec<_>>());
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let uri = "file:///panic.ae";
+        open(&mut service, uri, "Lazy DATA(42)").await;
+
+        diagnostics::INJECTED_RULE.set(Some(panicking_rule));
+        let change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "Lazy DATA(43)" }],
+            }))
+            .finish();
+        service.ready().await.unwrap().call(change).await.unwrap();
+        diagnostics::INJECTED_RULE.set(None);
+
+        // The last good version still answers requests
+        let hover = request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 2 },
+            }),
+        )
+        .await;
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .starts_with("**Lazy**")
+        );
+
+        drop(service);
+        let errors: Vec<String> = drain
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|message| message.method() == "window/logMessage")
+            .filter_map(|message| message.params().cloned())
+            .filter(|params| params["type"] == 1)
+            .map(|params| params["message"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!["Internal error in analysis for file:///panic.ae: injected rule failure"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_dict_key_command() {
+        use futures::{SinkExt, StreamExt};
+        use tower_lsp::jsonrpc::Response;
+
+        let (mut service, socket) = build_service();
+        let (mut requests, mut replies) = socket.split();
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = requests.next().await {
+                if message.method() == "workspace/applyEdit" {
+                    let id = message.id().cloned().unwrap();
+                    let reply = Response::from_ok(id, json!({ "applied": true }));
+                    replies.send(reply).await.unwrap();
+                }
+                if message.method() != "textDocument/publishDiagnostics" {
+                    let _ = sender.send((message.method().to_string(), message.params().cloned()));
+                }
+            }
+        });
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "experimental": { "renameDictKeyPrompt": true } },
+                "locale": "en",
+            }),
+        )
+        .await;
+        let uri = "file:///dict.ae";
+        let text = "Set CONFIG {\"retry_count\": 3}\n\
+                    PRINTLN(CONFIG[\"retry_count\"])\n\
+                    Set CONFIG[\"retry_count\"] 5\n\
+                    PRINTLN(CONFIG[\"retry_count\"], CONFIG[KEY])";
+        open(&mut service, uri, text).await;
+
+        let actions = request(
+            &mut service,
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": { "start": { "line": 1, "character": 18 }, "end": { "line": 1, "character": 18 } },
+                "context": { "diagnostics": [] },
+            }),
+        )
+        .await;
+        let action = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["command"]["command"] == "aether.renameDictKey")
+            .unwrap();
+        assert_eq!(action["title"], "Rename key \"retry_count\" of CONFIG");
+
+        let mut arguments = action["command"]["arguments"].as_array().unwrap().clone();
+        arguments.push(json!("retries"));
+        let result = request(
+            &mut service,
+            "workspace/executeCommand",
+            json!({ "command": "aether.renameDictKey", "arguments": arguments }),
+        )
+        .await;
+        assert_eq!(result, Value::Null);
+
+        let (method, message) = received.recv().await.unwrap();
+        assert_eq!(method, "window/showMessage");
+        assert_eq!(
+            message.unwrap()["message"],
+            "Left 1 key(s) unchanged that may or may not be the same one (lines 4)"
+        );
+        let (method, edit) = received.recv().await.unwrap();
+        assert_eq!(method, "workspace/applyEdit");
+        let edits = edit.unwrap()["edit"]["changes"][uri].clone();
+        let lines: Vec<u64> = edits
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edit| {
+                assert_eq!(edit["newText"], "\"retries\"");
+                edit["range"]["start"]["line"].as_u64().unwrap()
+            })
+            .collect();
+        assert_eq!(lines, vec![0, 1, 2, 3]);
+
+        // A client that can't ask for the new key isn't offered the action
+        let (mut plain, _socket) = build_service();
+        request(
+            &mut plain,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        open(&mut plain, uri, text).await;
+        let actions = request(
+            &mut plain,
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": { "start": { "line": 1, "character": 18 }, "end": { "line": 1, "character": 18 } },
+                "context": { "diagnostics": [] },
+            }),
+        )
+        .await;
+        assert!(
+            actions
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|action| action["command"]["command"] != "aether.renameDictKey")
+        );
+
+        // Without the new key, and away from any key
+        let error = request_error(
+            &mut service,
+            "workspace/executeCommand",
+            json!({ "command": "aether.renameDictKey", "arguments": [uri, { "line": 1, "character": 18 }] }),
+        )
+        .await;
+        assert_eq!(error["code"], -32602);
+        let error = request_error(
+            &mut service,
+            "workspace/executeCommand",
+            json!({ "command": "aether.renameDictKey", "arguments": [uri, { "line": 1, "character": 2 }, "x"] }),
+        )
+        .await;
+        assert_eq!(error["data"]["reason"], "notARenamableSymbol");
+    }
+
+    /// Methods the server sent to the client while hovering, with tracing set to `trace`
+    async fn client_traffic_during_hover(trace: &str) -> Vec<String> {
+        use futures::StreamExt;
+
+        let (mut service, socket) = build_service();
+        let drain = tokio::spawn(
+            socket
+                .map(|message| message.method().to_string())
+                .collect::<Vec<_>>(),
+        );
+        request(
+            &mut service,
+            "initialize",
+            json!({ "capabilities": {}, "locale": "en" }),
+        )
+        .await;
+        let set_trace = Request::build("$/setTrace")
+            .params(json!({ "value": trace }))
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(set_trace)
+            .await
+            .unwrap();
+
+        let uri = "file:///trace.ae";
+        open(&mut service, uri, "Set TOTAL SUM([1, 2])").await;
+        request(
+            &mut service,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 11 },
+            }),
+        )
+        .await;
+
+        // Dropping the service closes the client channel and ends the drain
+        drop(service);
+        drain
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|method| method != "textDocument/publishDiagnostics")
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_no_log_traffic_unless_tracing() {
+        assert_eq!(
+            client_traffic_during_hover("off").await,
+            Vec::<String>::new()
+        );
+
+        let traced = client_traffic_during_hover("verbose").await;
+        assert!(!traced.is_empty());
+        assert!(traced.iter().all(|method| method == "$/logTrace"));
+    }
+
+    #[tokio::test]
+    async fn test_untitled_document_features() {
+        let (mut service, _) = build_service();
+        request(
+            &mut service,
+            "initialize",
+            json!({
+                "capabilities": { "textDocument": { "diagnostic": {} } },
+                "locale": "en",
+            }),
+        )
+        .await;
+        let uri = "untitled:Untitled-1";
+        open(
+            &mut service,
+            uri,
+            "Import {HELPER} From \"./lib.ae\"\nImport MATH From \"../math.ae\"\n\
+             // Adds one\nFunc ADD_ONE(N) {\n    Return N + 1\n}\n\
+             Set TOTAL ADD_ONE(HELPER(1))\nPRINTLN(TOTAL, MATH)\n",
+        )
+        .await;
+        let at = |line, character| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            })
+        };
+
+        // No file to resolve the imports against, so nothing to say about them
+        let report = request(
+            &mut service,
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert_eq!(report["items"], json!([]));
+
+        let hover = request(&mut service, "textDocument/hover", at(6, 11)).await;
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("Adds one"),
+            "{}",
+            hover
+        );
+
+        let completion = request(&mut service, "textDocument/completion", at(7, 10)).await;
+        let labels: Vec<&str> = completion["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|item| item["label"].as_str())
+            .collect();
+        assert!(labels.contains(&"TOTAL") && labels.contains(&"ADD_ONE"));
+
+        let symbols = request(
+            &mut service,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        let symbols = symbols.as_array().unwrap();
+        assert_eq!(symbols.len(), 4, "{:?}", symbols);
+        assert!(
+            symbols
+                .iter()
+                .all(|symbol| symbol["location"]["uri"] == uri)
+        );
+
+        let definition = request(&mut service, "textDocument/definition", at(6, 11)).await;
+        assert_eq!(definition["uri"], uri);
+        assert_eq!(definition["range"]["start"]["line"], 3);
+
+        let mut rename = at(7, 9);
+        rename["newName"] = json!("SUM");
+        let edit = request(&mut service, "textDocument/rename", rename).await;
+        assert_eq!(edit["changes"][uri].as_array().unwrap().len(), 2);
+
+        // Without a module file to go to, an imported name goes to its import
+        let imported = request(&mut service, "textDocument/definition", at(6, 19)).await;
+        assert_eq!(imported["uri"], uri);
+        assert_eq!(imported["range"]["start"]["line"], 0);
+
+        // Links need the file's location, so there are none
+        let links = request(
+            &mut service,
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+        assert!(links.is_null() || links == json!([]), "{}", links);
+    }
+}
@@ -0,0 +1,176 @@
+//! Runs of consecutive top-level `Set` and `Lazy` statements
+//!
+//! Configuration-heavy scripts open with dozens of `Set`s that bury the
+//! functions in the outline. Each run of [`MIN_RUN`] or more folds as a region,
+//! and the nested outline can hold it in one container. A section header
+//! comment like `// === Network ===` ends a run and names the next one.
+
+use lsp_types::{DocumentSymbol, Range, SymbolKind};
+
+use crate::ast::{Comment, Program, StmtKind};
+use crate::locale::Locale;
+
+/// The fewest statements that make a run
+pub const MIN_RUN: usize = 3;
+
+/// Consecutive top-level `Set`/`Lazy` statements
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetRun {
+    /// From the first statement's start to the last one's end
+    pub range: Range,
+    pub statements: usize,
+    /// The text of the section header right before the run, if any
+    pub section: Option<String>,
+}
+
+/// The section a `// === Name ===` comment starts, `Some("")` if unnamed
+fn section_header(comment: &Comment) -> Option<String> {
+    if comment.block || comment.trailing {
+        return None;
+    }
+    let text = comment.text.strip_prefix("//")?.trim();
+    text.starts_with("===").then(|| {
+        text.trim_matches(|ch: char| ch == '=' || ch.is_whitespace())
+            .to_string()
+    })
+}
+
+/// The runs of `program`, in source order
+pub fn set_runs(program: &Program) -> Vec<SetRun> {
+    let headers: Vec<(Range, String)> = program
+        .comments()
+        .iter()
+        .filter_map(|comment| Some((comment.span, section_header(comment)?)))
+        .collect();
+    // The last header between `after` (exclusive) and `before`
+    let header_between = |after: Option<Range>, before: Range| {
+        headers
+            .iter()
+            .filter(|(span, _)| after.is_none_or(|after| span.start >= after.end))
+            .rfind(|(span, _)| span.end <= before.start)
+            .map(|(_, name)| name.clone())
+    };
+
+    let mut runs: Vec<SetRun> = Vec::new();
+    let mut current: Option<SetRun> = None;
+    let mut previous: Option<Range> = None;
+    for stmt in program.statements() {
+        let header = header_between(previous, stmt.span);
+        previous = Some(stmt.span);
+        if !matches!(stmt.kind, StmtKind::Set { .. } | StmtKind::LazyDef { .. }) {
+            runs.extend(current.take());
+            continue;
+        }
+        match &mut current {
+            Some(run) if header.is_none() => {
+                run.range.end = stmt.span.end;
+                run.statements += 1;
+            }
+            _ => {
+                runs.extend(current.take());
+                current = Some(SetRun {
+                    range: stmt.span,
+                    statements: 1,
+                    section: header.filter(|name| !name.is_empty()),
+                });
+            }
+        }
+    }
+    runs.extend(current);
+    runs.retain(|run| run.statements >= MIN_RUN);
+    runs
+}
+
+/// `symbols`, a nested outline, with the top-level entries inside each run
+/// moved into one container named after its section, in the order they came
+#[allow(deprecated)] // `DocumentSymbol::deprecated` must still be initialized
+pub fn group_runs(
+    symbols: Vec<DocumentSymbol>,
+    runs: &[SetRun],
+    locale: Locale,
+) -> Vec<DocumentSymbol> {
+    let mut grouped: Vec<DocumentSymbol> = Vec::new();
+    let mut containers: Vec<Option<usize>> = vec![None; runs.len()];
+    for symbol in symbols {
+        let run = runs.iter().position(|run| {
+            run.range.start <= symbol.range.start && symbol.range.end <= run.range.end
+        });
+        let Some(run) = run else {
+            grouped.push(symbol);
+            continue;
+        };
+        match containers[run] {
+            Some(index) => grouped[index]
+                .children
+                .get_or_insert_with(Vec::new)
+                .push(symbol),
+            None => {
+                containers[run] = Some(grouped.len());
+                grouped.push(DocumentSymbol {
+                    name: match &runs[run].section {
+                        Some(section) => section.clone(),
+                        None => format!(
+                            "{} ({})",
+                            locale.pick("常量", "Constants"),
+                            runs[run].statements
+                        ),
+                    },
+                    detail: None,
+                    kind: SymbolKind::NAMESPACE,
+                    tags: None,
+                    deprecated: None,
+                    range: runs[run].range,
+                    selection_range: symbol.selection_range,
+                    children: Some(vec![symbol]),
+                });
+            }
+        }
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn runs(text: &str) -> Vec<(u32, u32, usize, Option<String>)> {
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        set_runs(&doc.ast)
+            .into_iter()
+            .map(|run| {
+                (
+                    run.range.start.line,
+                    run.range.end.line,
+                    run.statements,
+                    run.section,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_runs() {
+        assert_eq!(
+            runs("Set A 1\nSet B 2\n\nLazy C(3)\nFunc F() {\n}\nSet D 1\nSet E 2"),
+            vec![(0, 3, 3, None)]
+        );
+        // A multiline value belongs to its statement; comments don't break a run
+        assert_eq!(
+            runs("Set A {\n    \"x\": 1\n}\n// the limit\nSet B 2\nSet C 3\nPRINTLN(A)"),
+            vec![(0, 5, 3, None)]
+        );
+    }
+
+    #[test]
+    fn test_section_headers_split_and_name_runs() {
+        let text = "// === Network ===\nSet HOST 1\nSet PORT 2\nSet TIMEOUT 3\n\
+                    // =====\nSet A 1\nSet B 2\nSet C 3\n\
+                    // === Tiny ===\nSet D 1\nSet E 2";
+        assert_eq!(
+            runs(text),
+            vec![(1, 3, 3, Some("Network".to_string())), (5, 7, 3, None)]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Signature help for the call being typed
+//!
+//! The call is found by scanning the text before the cursor rather than the
+//! AST, since the document rarely parses while arguments are half written.
+
+use lsp_types::*;
+
+use crate::builtins;
+use crate::intern::Name;
+use crate::locale::Locale;
+use crate::mask::Mask;
+use crate::parser::ParsedDocument;
+
+/// The function whose argument list the cursor is in, and which argument it is on
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallContext {
+    pub callee: String,
+    pub active_parameter: u32,
+}
+
+/// An open bracket before the cursor
+struct Open {
+    bracket: char,
+    /// The identifier right before a `(`, unless it's a member access
+    callee: Option<String>,
+    commas: u32,
+}
+
+/// The innermost unclosed call around `position`. Brackets inside the strings
+/// and comments of `mask` don't count, and there is none inside one.
+pub fn call_context(text: &str, mask: &Mask, position: Position) -> Option<CallContext> {
+    if mask.contains(position) {
+        return None;
+    }
+    let mut stack: Vec<Open> = Vec::new();
+    for (line_number, line) in text.lines().enumerate().take(position.line as usize + 1) {
+        let mut chars: Vec<char> = line.chars().collect();
+        // Positions count UTF-16 units: the column each char starts at
+        let columns: Vec<u32> = chars
+            .iter()
+            .scan(0, |units, ch| {
+                let column = *units;
+                *units += ch.len_utf16() as u32;
+                Some(column)
+            })
+            .collect();
+        let index_at = |character: u32| columns.partition_point(|&column| column < character);
+        if line_number == position.line as usize {
+            chars.truncate(index_at(position.character));
+        }
+        let line_number = line_number as u32;
+        let mut index = 0;
+        // A string or comment carried over from an earlier line
+        if let Some(region) = mask.region_at(Position::new(line_number, 0)) {
+            if region.end.line > line_number {
+                continue;
+            }
+            index = index_at(region.end.character);
+        }
+        while index < chars.len() {
+            // Jump over a string or comment starting here
+            if matches!(chars[index], '"' | '/')
+                && let Some(region) = mask.region_at(Position::new(line_number, columns[index] + 1))
+                && region.start == Position::new(line_number, columns[index])
+            {
+                if region.end.line > line_number {
+                    break;
+                }
+                index = index_at(region.end.character);
+                continue;
+            }
+            match chars[index] {
+                '"' => {
+                    index += 1;
+                    while index < chars.len() && chars[index] != '"' {
+                        index += if chars[index] == '\\' { 2 } else { 1 };
+                    }
+                    // Still inside the string at the cursor: no help until it closes
+                    if index >= chars.len() && line_number == position.line {
+                        return None;
+                    }
+                }
+                '/' if chars.get(index + 1) == Some(&'/') => break,
+                '(' => stack.push(Open {
+                    bracket: '(',
+                    callee: callee_before(&chars[..index]),
+                    commas: 0,
+                }),
+                '[' | '{' => stack.push(Open {
+                    bracket: chars[index],
+                    callee: None,
+                    commas: 0,
+                }),
+                ')' | ']' | '}' => {
+                    stack.pop();
+                }
+                ',' => {
+                    if let Some(open) = stack.last_mut() {
+                        open.commas += 1;
+                    }
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+    }
+
+    let open = stack.pop().filter(|open| open.bracket == '(')?;
+    Some(CallContext {
+        callee: open.callee?,
+        active_parameter: open.commas,
+    })
+}
+
+fn callee_before(chars: &[char]) -> Option<String> {
+    let start = chars
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |index| index + 1);
+    if start == chars.len() || (start > 0 && chars[start - 1] == '.') {
+        return None;
+    }
+    Some(chars[start..].iter().collect())
+}
+
+/// Signature help for a user function, a variable bound to a lambda, or a builtin
+pub fn signature_help(
+    doc: &ParsedDocument,
+    position: Position,
+    locale: Locale,
+) -> Option<SignatureHelp> {
+    let context = call_context(&doc.text, &doc.mask, position)?;
+
+    let signature = if let Some(params) = doc.symbols.callable_params(&context.callee) {
+        let documentation = doc
+            .symbols
+            .find_by_name(&context.callee)
+            .map(|symbol| symbol.documentation.clone())
+            .filter(|documentation| !documentation.is_empty());
+        let label = format!("{}({})", context.callee, params.join(", "));
+        signature_information(label, params.iter().map(Name::as_str), documentation)
+    } else {
+        let builtin = builtins::lookup(&context.callee)?;
+        let params = builtin.params();
+        let mut signature = signature_information(
+            builtin.signature.to_string(),
+            params.iter().map(|param| param.name),
+            Some(builtin.description(locale).to_string()),
+        );
+        // Every argument past the last parameter still belongs to a variadic one
+        if params.last().is_some_and(|param| param.variadic) {
+            signature.active_parameter =
+                Some(context.active_parameter.min(params.len() as u32 - 1));
+        }
+        signature
+    };
+
+    let active_parameter = signature
+        .active_parameter
+        .unwrap_or(context.active_parameter);
+    Some(SignatureHelp {
+        signatures: vec![signature],
+        active_signature: Some(0),
+        active_parameter: Some(active_parameter),
+    })
+}
+
+/// Parameters are labelled by their offsets in `label`, searched after the `(`
+fn signature_information<'a>(
+    label: String,
+    params: impl Iterator<Item = &'a str>,
+    documentation: Option<String>,
+) -> SignatureInformation {
+    let mut offset = label.find('(').map_or(0, |index| index + 1);
+    let parameters = params
+        .filter_map(|name| {
+            let start = offset + label[offset..].find(name)?;
+            offset = start + name.len();
+            let utf16 = |byte: usize| label[..byte].encode_utf16().count() as u32;
+            Some(ParameterInformation {
+                label: ParameterLabel::LabelOffsets([utf16(start), utf16(offset)]),
+                documentation: None,
+            })
+        })
+        .collect();
+    SignatureInformation {
+        label,
+        documentation: documentation.map(|value| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            })
+        }),
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_call_context() {
+        let context = |text: &str, line, character| {
+            let mask = Parser::new(text).parse().mask;
+            call_context(text, &mask, Position::new(line, character))
+                .map(|context| (context.callee, context.active_parameter))
+        };
+        assert_eq!(
+            context("PRINTLN(ADD(1, [2, 3], ", 0, 23),
+            Some(("ADD".to_string(), 2))
+        );
+        assert_eq!(
+            context("ADD(1, \"a, (b\", ", 0, 16),
+            Some(("ADD".to_string(), 2))
+        );
+        assert_eq!(
+            context("ADD(\n    1,\n    ", 2, 4),
+            Some(("ADD".to_string(), 1))
+        );
+        assert_eq!(context("ADD(1) + ", 0, 9), None);
+        assert_eq!(context("ADD(\"a, ", 0, 8), None);
+        assert_eq!(context("UTILS.ADD(", 0, 10), None);
+        // Brackets in multiline strings and comments, and calls typed inside them
+        assert_eq!(
+            context("ADD(1, \"\"\"\n  ) (\n\"\"\" /* ) */, ", 2, 14),
+            Some(("ADD".to_string(), 2))
+        );
+        assert_eq!(context("Set A \"\"\"\nPRINTLN(\n\"\"\"", 1, 8), None);
+        assert_eq!(context("/*\nADD(1, \n*/", 1, 7), None);
+    }
+
+    #[test]
+    fn test_lambda_binding_signature() {
+        let binding = "Set HANDLER Lambda (EVENT, RETRIES) -> EVENT";
+        let doc = Parser::new(&format!("{}\nHANDLER(5, ", binding)).parse();
+        let help = signature_help(&doc, Position::new(1, 11), Locale::En).unwrap();
+        let signature = &help.signatures[0];
+        assert_eq!(signature.label, "HANDLER(EVENT, RETRIES)");
+        assert_eq!(
+            signature.parameters.as_ref().unwrap()[1].label,
+            ParameterLabel::LabelOffsets([15, 22])
+        );
+        assert_eq!(help.active_parameter, Some(1));
+
+        // Bound to something else as well, the variable is no longer callable
+        let doc = Parser::new(&format!("{}\nSet HANDLER 0\nHANDLER(", binding)).parse();
+        assert!(signature_help(&doc, Position::new(2, 8), Locale::En).is_none());
+    }
+
+    #[test]
+    fn test_builtin_signature_variadic() {
+        let doc = Parser::new("PRINTLN(1, 2, 3").parse();
+        let help = signature_help(&doc, Position::new(0, 15), Locale::En).unwrap();
+        assert_eq!(help.signatures[0].label, "PRINTLN(value...)");
+        assert_eq!(help.active_parameter, Some(0));
+    }
+}
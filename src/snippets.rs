@@ -0,0 +1,155 @@
+//! Multi-statement templates offered by completion
+//!
+//! Unlike a keyword's snippet, which completes the one statement it starts, a
+//! template lays out several: a script skeleton, a generator with its loop.
+//! They only make sense where a statement starts, so completion offers them
+//! there and only to clients that expand snippets.
+
+use lsp_types::*;
+
+use crate::client_caps::snippet_to_plaintext;
+use crate::config::UserSnippet;
+use crate::locale::Locale;
+
+pub struct Snippet {
+    pub label: &'static str,
+    pub description: &'static str,
+    pub description_en: &'static str,
+    /// In LSP snippet syntax, ending at `$0`
+    pub body: &'static str,
+}
+
+pub const SNIPPETS: &[Snippet] = &[
+    Snippet {
+        label: "main",
+        description: "脚本骨架：MAIN 函数及其调用",
+        description_en: "Script skeleton: a MAIN function and its call",
+        body: "// ${1:DESCRIPTION}\n\nFunc MAIN() {\n\t$0\n}\n\nMAIN()",
+    },
+    Snippet {
+        label: "fori",
+        description: "带下标的 For 循环",
+        description_en: "For loop with an index",
+        body: "For ${1:I}, ${2:V} In ${3:ARR} {\n\t$0\n}",
+    },
+    Snippet {
+        label: "genrange",
+        description: "产出一个区间的生成器",
+        description_en: "Generator yielding a range",
+        body: "Generator ${1:RANGE}(${2:START}, ${3:STOP}) {\n\tSet ${4:I} $2\n\tWhile ($4 < $3) {\n\t\tYield $4\n\t\tSet $4 ($4 + 1)\n\t}\n}\n$0",
+    },
+    Snippet {
+        label: "trycatch",
+        description: "Try/Catch 错误处理",
+        description_en: "Try/Catch error handling",
+        body: "Try {\n\t$1\n} Catch (${2:ERROR}) {\n\tPRINTLN($2)\n}\n$0",
+    },
+    Snippet {
+        label: "imports",
+        description: "导入块：具名导入和命名空间导入",
+        description_en: "Import block: named and namespace imports",
+        body: "Import {${1:NAME}} From \"${2:./module.ae}\"\nImport ${3:MODULE} From \"${4:./module.ae}\"\n$0",
+    },
+];
+
+/// Whether only indentation precedes the word being typed at `position`
+pub fn at_statement_start(text: &str, position: Position) -> bool {
+    // Past the last line is the empty line after a final newline
+    let line = text.lines().nth(position.line as usize).unwrap_or("");
+    line.chars()
+        .take(position.character as usize)
+        .collect::<String>()
+        .trim_start()
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The templates as completion items, those from the settings first so one can
+/// replace a built-in of the same label
+pub fn snippet_completions(user: &[UserSnippet], locale: Locale) -> Vec<CompletionItem> {
+    let user = user
+        .iter()
+        .filter(|snippet| !snippet.label.is_empty() && !snippet.body.is_empty())
+        .map(|snippet| {
+            item(
+                &snippet.label,
+                snippet.description.as_deref(),
+                &snippet.body,
+            )
+        });
+    let builtin = SNIPPETS.iter().map(|snippet| {
+        item(
+            snippet.label,
+            Some(locale.pick(snippet.description, snippet.description_en)),
+            snippet.body,
+        )
+    });
+    user.chain(builtin).collect()
+}
+
+fn item(label: &str, description: Option<&str>, body: &str) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: description.map(str::to_string),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```aether\n{}\n```", snippet_to_plaintext(body)),
+        })),
+        insert_text: Some(body.to_string()),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_builtin_snippets_expand_to_valid_code() {
+        for snippet in SNIPPETS {
+            assert!(snippet.body.ends_with("$0") || snippet.body.contains("\t$0\n"));
+            let text = snippet_to_plaintext(snippet.body);
+            let parsed = Parser::new(&text).parse();
+            assert!(
+                parsed.errors.is_empty(),
+                "{}: {:?}",
+                snippet.label,
+                parsed.errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_statement_start() {
+        let text = "Set A 1\n    fo\nPRINTLN(fo)\n";
+        assert!(at_statement_start(text, Position::new(1, 6)));
+        assert!(at_statement_start(text, Position::new(1, 0)));
+        assert!(!at_statement_start(text, Position::new(2, 10)));
+        assert!(!at_statement_start(text, Position::new(0, 6)));
+        // The empty line after the last newline
+        assert!(at_statement_start(text, Position::new(3, 0)));
+    }
+
+    #[test]
+    fn test_user_snippets_come_first() {
+        let user = [
+            UserSnippet {
+                label: "main".to_string(),
+                body: "MAIN()$0".to_string(),
+                description: None,
+            },
+            UserSnippet::default(),
+        ];
+        let items = snippet_completions(&user, Locale::En);
+        assert_eq!(items.len(), SNIPPETS.len() + 1);
+        assert_eq!(items[0].insert_text.as_deref(), Some("MAIN()$0"));
+        assert!(
+            items
+                .iter()
+                .all(|item| item.kind == Some(CompletionItemKind::SNIPPET))
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! `// aether-disable-next-line W001` comments, silencing codes on the next line
+//!
+//! Codes are listed after the directive, separated by spaces or commas; a bare
+//! directive silences every suppressible code on the line. Parse errors can't
+//! be silenced ([`DiagnosticCode::suppressible`]).
+
+use std::collections::HashMap;
+
+use lsp_types::{Diagnostic, NumberOrString};
+
+use crate::ast::Comment;
+use crate::diagnostic_codes;
+#[cfg(feature = "server")]
+use crate::diagnostic_codes::DiagnosticCode;
+
+pub const DIRECTIVE: &str = "aether-disable-next-line";
+
+#[cfg(feature = "server")]
+/// The comment silencing `code` on the line below it
+pub fn comment(code: &DiagnosticCode) -> String {
+    format!("// {} {}", DIRECTIVE, code.code)
+}
+
+/// The codes a line comment silences, if it is a directive; empty for all
+pub fn directive_codes(comment: &str) -> Option<Vec<&str>> {
+    let rest = comment
+        .strip_prefix("//")?
+        .trim_start()
+        .strip_prefix(DIRECTIVE)?;
+    if !rest.is_empty() && !rest.starts_with([' ', '\t', ',']) {
+        return None;
+    }
+    Some(
+        rest.split([' ', '\t', ','])
+            .filter(|code| !code.is_empty())
+            .collect(),
+    )
+}
+
+/// Drop the diagnostics a directive in `comments` silences
+pub fn retain_unsuppressed(comments: &[Comment], diagnostics: &mut Vec<Diagnostic>) {
+    let mut silenced: HashMap<u32, Vec<&str>> = HashMap::new();
+    for comment in comments.iter().filter(|comment| !comment.block) {
+        if let Some(codes) = directive_codes(&comment.text) {
+            silenced
+                .entry(comment.span.end.line + 1)
+                .or_default()
+                .extend(codes);
+        }
+    }
+    if silenced.is_empty() {
+        return;
+    }
+    diagnostics.retain(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        let Some(codes) = silenced.get(&diagnostic.range.start.line) else {
+            return true;
+        };
+        let suppressible = diagnostic_codes::by_code(code).is_some_and(|code| code.suppressible());
+        !(suppressible && (codes.is_empty() || codes.contains(&code.as_str())))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "server")]
+    use crate::diagnostic_codes as codes;
+    use crate::diagnostics::{AnalysisOptions, DiagnosticEngine};
+    use crate::parser::Parser;
+
+    fn reported(text: &str) -> Vec<(u32, String)> {
+        let parsed = Parser::new(text).parse();
+        DiagnosticEngine::analyze(&parsed, &AnalysisOptions::default())
+            .into_iter()
+            .map(|diagnostic| {
+                let Some(NumberOrString::String(code)) = diagnostic.code else {
+                    panic!("uncoded diagnostic {:?}", diagnostic);
+                };
+                (diagnostic.range.start.line, code)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_directives_silence_the_next_line() {
+        #[cfg(feature = "server")]
+        assert_eq!(
+            comment(&codes::NAMING_CONVENTION),
+            "// aether-disable-next-line W001"
+        );
+        assert_eq!(directive_codes("//aether-disable-next-line"), Some(vec![]));
+        assert_eq!(
+            directive_codes("// aether-disable-next-line W001, W006"),
+            Some(vec!["W001", "W006"])
+        );
+        assert_eq!(directive_codes("// aether-disable-next-lines"), None);
+        assert_eq!(directive_codes("// see aether-disable-next-line"), None);
+
+        let text = "// aether-disable-next-line W001\nSet lowered 1\nSet other 2\n\
+                    // aether-disable-next-line W006\nSet third 3\n\
+                    Func F() {\n    // aether-disable-next-line\n    PRINTLN(fourth, MISSING)\n}";
+        assert_eq!(
+            reported(text),
+            vec![(2, "W001".to_string()), (4, "W001".to_string())]
+        );
+        // A broken parse stays reported
+        assert_eq!(
+            reported("// aether-disable-next-line\nSet X (1"),
+            vec![(1, "E003".to_string())]
+        );
+    }
+}
@@ -1,22 +1,154 @@
 //! Symbol table for tracking variables, functions, etc.
 
-use crate::ast::{Expr, Program, Stmt};
-use tower_lsp::lsp_types::*;
+use std::collections::HashMap;
+
+use crate::ast::{Comment, DictKey, ExprKind, Ident, Program, Stmt, StmtKind};
+use crate::intern::Name;
+use crate::line_index::LineIndex;
+use crate::visit::{Visitor, walk_stmt};
+use lsp_types::*;
 
 #[derive(Debug, Clone, Default)]
 pub struct SymbolTable {
     pub variables: Vec<SymbolInfo>,
     pub functions: Vec<SymbolInfo>,
+    /// Local names bound by `Import`: the alias where there is one, otherwise
+    /// the imported name. Not part of the outline.
+    pub imports: Vec<SymbolInfo>,
+    /// One MODULE entry per `Import` statement, named by its path, for the
+    /// outline; the imported names are its detail
+    pub import_statements: Vec<SymbolInfo>,
+    /// One INTERFACE entry per `Export`, for the outline
+    pub exports: Vec<SymbolInfo>,
+    /// Literal keys known for variables bound to dict literals, in source order;
+    /// only `Str` and `Num`, since a name key is the string it spells
+    pub dict_keys: HashMap<String, Vec<DictKey>>,
+    /// Element counts of variables bound to array literals, from the first such
+    /// `Set`; whether the array keeps that length is [`crate::array_bounds`]'s call
+    pub array_lengths: HashMap<String, usize>,
+    /// Positions of the symbols above, rebuilt with the table
+    index: PositionIndex,
+}
+
+/// Symbols by position, so a lookup at the cursor is a binary search. Symbols
+/// are numbered variables first, then functions as in the outline, then imports.
+#[derive(Debug, Clone, Default)]
+struct PositionIndex {
+    /// Name ranges, sorted by start; single tokens that never overlap
+    names: Vec<(Range, usize)>,
+    /// Function ranges sorted by start, each with the position in this list of
+    /// the innermost function around it. Bodies nest or are disjoint.
+    bodies: Vec<(Range, usize, Option<usize>)>,
+}
+
+impl PositionIndex {
+    fn new(table: &SymbolTable) -> Self {
+        let symbols = table
+            .variables
+            .iter()
+            .chain(&table.functions)
+            .chain(&table.imports);
+        let mut names: Vec<(Range, usize)> = symbols
+            .enumerate()
+            .map(|(index, symbol)| (symbol.selection_range, index))
+            .collect();
+        names.sort_by_key(|(range, _)| range.start);
+
+        let mut functions: Vec<(Range, usize)> = table
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| (function.range, table.variables.len() + index))
+            .collect();
+        functions.sort_by_key(|(range, _)| range.start);
+        let mut bodies = Vec::with_capacity(functions.len());
+        let mut open: Vec<usize> = Vec::new();
+        for (range, symbol) in functions {
+            while let Some(&outer) = open.last() {
+                let outer: &(Range, usize, Option<usize>) = &bodies[outer];
+                if range_contains(outer.0, range) {
+                    break;
+                }
+                open.pop();
+            }
+            bodies.push((range, symbol, open.last().copied()));
+            open.push(bodies.len() - 1);
+        }
+        PositionIndex { names, bodies }
+    }
+
+    /// The symbol whose name is at `position`; the end of a name still counts
+    fn name_at(&self, position: Position) -> Option<usize> {
+        let after = self
+            .names
+            .partition_point(|(range, _)| range.start <= position);
+        let (range, symbol) = self.names[..after].last()?;
+        (position <= range.end).then_some(*symbol)
+    }
+
+    /// The innermost function whose range contains `range`, other than one
+    /// spanning exactly `range`
+    fn body_around(&self, range: Range) -> Option<usize> {
+        let after = self
+            .bodies
+            .partition_point(|(body, ..)| body.start <= range.start);
+        let mut candidate = after.checked_sub(1);
+        while let Some(index) = candidate {
+            let (body, symbol, parent) = self.bodies[index];
+            if body != range && range_contains(body, range) {
+                return Some(symbol);
+            }
+            candidate = parent;
+        }
+        None
+    }
+}
+
+fn range_contains(outer: Range, inner: Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
 }
 
 #[derive(Debug, Clone)]
 pub struct SymbolInfo {
-    pub name: String,
+    pub name: Name,
     pub kind: SymbolKind,
+    /// The whole defining statement, through the closing `}` of a body
     pub range: Range,
+    /// Just the name
     pub selection_range: Range,
     pub documentation: String,
     pub detail: Option<String>,
+    pub flavor: SymbolFlavor,
+    /// Parameter names of a function, or of the lambda a variable is bound to
+    pub params: Vec<Name>,
+    /// What the `@deprecated` tag of a function's doc comment says, `Some("")`
+    /// for a bare tag
+    pub deprecated: Option<String>,
+}
+
+impl SymbolInfo {
+    /// `[DEPRECATED]` for a deprecated function, for outlines
+    pub fn deprecation_tags(&self) -> Option<Vec<SymbolTag>> {
+        self.deprecated
+            .as_ref()
+            .map(|_| vec![SymbolTag::DEPRECATED])
+    }
+}
+
+/// What kind of binding a symbol is, beyond its LSP kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolFlavor {
+    #[default]
+    Plain,
+    /// A `Generator`, reported with kind FUNCTION
+    Generator,
+    /// A `Lazy` binding: read-only and evaluated on the first `Force`
+    Lazy,
+    /// A variable only ever bound to lambdas taking the same parameters, callable
+    /// like a function
+    Lambda,
+    /// A name bound by `Import`, defined in another module
+    Import,
 }
 
 impl SymbolTable {
@@ -24,172 +156,318 @@ impl SymbolTable {
         SymbolTable {
             variables: Vec::new(),
             functions: Vec::new(),
+            imports: Vec::new(),
+            import_statements: Vec::new(),
+            exports: Vec::new(),
+            dict_keys: HashMap::new(),
+            array_lengths: HashMap::new(),
+            index: PositionIndex::default(),
         }
     }
 
     /// Add a variable symbol to the table
-    pub fn add_variable(&mut self, name: String, range: Range, detail: Option<String>) {
+    pub fn add_variable(
+        &mut self,
+        name: Name,
+        range: Range,
+        selection_range: Range,
+        detail: Option<String>,
+    ) {
         self.variables.push(SymbolInfo {
             name,
             kind: SymbolKind::VARIABLE,
             range,
-            selection_range: range,
+            selection_range,
             documentation: String::new(),
             detail,
+            flavor: SymbolFlavor::Plain,
+            params: Vec::new(),
+            deprecated: None,
         });
     }
 
     /// Add a function symbol to the table
     pub fn add_function(
         &mut self,
-        name: String,
+        name: Name,
         range: Range,
-        params: Vec<String>,
+        selection_range: Range,
+        params: Vec<Name>,
         detail: Option<String>,
+        flavor: SymbolFlavor,
     ) {
         let param_str = params.join(", ");
+        let label = match flavor {
+            SymbolFlavor::Generator => "Generator",
+            _ => "Function",
+        };
         self.functions.push(SymbolInfo {
             name: name.clone(),
             kind: SymbolKind::FUNCTION,
             range,
-            selection_range: range,
-            documentation: format!("Function: {}({})", name, param_str),
+            selection_range,
+            documentation: format!("{}: {}({})", label, name, param_str),
             detail,
+            flavor,
+            params,
+            deprecated: None,
         });
     }
 
     /// Extract symbols from AST
-    pub fn from_ast(ast: &Program, text: &str) -> Self {
-        let mut table = SymbolTable::new();
-
-        for stmt in ast {
-            extract_symbols_from_stmt(stmt, &mut table, text);
-        }
-
+    pub fn from_ast(ast: &Program, text: &str, lines: &LineIndex) -> Self {
+        let mut collector = SymbolCollector {
+            table: SymbolTable::new(),
+            text,
+            lines,
+            comments: ast.comments(),
+        };
+        collector.visit_block(ast);
+        let mut table = collector.table;
+        table.demote_rebound_lambdas();
+        table.describe_exports();
+        table.index = PositionIndex::new(&table);
         table
     }
 
-    pub fn find_at_position(&self, position: Position) -> Option<&SymbolInfo> {
-        // Check variables
-        for var in &self.variables {
-            if position_in_range(position, var.range) {
-                return Some(var);
-            }
+    /// Give each export the detail of what it exports, which may be defined
+    /// after the `Export`
+    fn describe_exports(&mut self) {
+        let details: Vec<Option<String>> = self
+            .exports
+            .iter()
+            .map(|export| {
+                self.find_by_name(&export.name)
+                    .and_then(|symbol| symbol.detail.clone())
+            })
+            .collect();
+        for (export, detail) in self.exports.iter_mut().zip(details) {
+            export.detail = detail;
         }
+    }
 
-        // Check functions
-        for func in &self.functions {
-            if position_in_range(position, func.range) {
-                return Some(func);
+    /// Drop the function-like metadata of a lambda binding when the variable is
+    /// also bound to something else, or to a lambda with other parameters
+    fn demote_rebound_lambdas(&mut self) {
+        let mut shapes: HashMap<&Name, Option<&Vec<Name>>> = HashMap::new();
+        for symbol in &self.variables {
+            let shape = (symbol.flavor == SymbolFlavor::Lambda).then_some(&symbol.params);
+            shapes
+                .entry(&symbol.name)
+                .and_modify(|existing| {
+                    if *existing != shape {
+                        *existing = None;
+                    }
+                })
+                .or_insert(shape);
+        }
+        let demoted: Vec<Name> = shapes
+            .into_iter()
+            .filter(|(_, shape)| shape.is_none())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for symbol in &mut self.variables {
+            if symbol.flavor == SymbolFlavor::Lambda && demoted.contains(&symbol.name) {
+                let signature = symbol.detail.take().unwrap_or_default();
+                symbol.documentation = symbol
+                    .documentation
+                    .strip_prefix(&signature)
+                    .unwrap_or_default()
+                    .trim_start()
+                    .to_string();
+                symbol.detail = Some(format!("Variable: {}", symbol.name));
+                symbol.flavor = SymbolFlavor::Plain;
+                symbol.params.clear();
             }
         }
+    }
 
-        None
+    /// Parameter names of the user function, or lambda binding, called `name`
+    pub fn callable_params(&self, name: &str) -> Option<&[Name]> {
+        self.functions
+            .iter()
+            .chain(&self.variables)
+            .find(|symbol| symbol.name == name)
+            .filter(|symbol| {
+                symbol.kind == SymbolKind::FUNCTION || symbol.flavor == SymbolFlavor::Lambda
+            })
+            .map(|symbol| symbol.params.as_slice())
     }
 
-    pub fn find_definition(&self, position: Position) -> Option<Location> {
-        if let Some(symbol) = self.find_at_position(position) {
-            return Some(Location {
-                uri: Url::parse("file:///dummy").unwrap(),
-                range: symbol.range,
-            });
+    /// Remember a literal key of the dict bound to `name`; computed keys are
+    /// not known until the program runs
+    pub fn add_dict_key(&mut self, name: &str, key: &DictKey) {
+        let key = match key {
+            DictKey::Ident(key) | DictKey::Str(key) => DictKey::Str(key.clone()),
+            DictKey::Num(key) => DictKey::Num(*key),
+            DictKey::Computed(_) => return,
+        };
+        let keys = self.dict_keys.entry(name.to_string()).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
         }
-        None
     }
 
-    pub fn to_document_symbols(&self) -> Vec<SymbolInformation> {
-        let mut symbols = Vec::new();
+    /// The variable, function or import called `name`
+    pub fn find_by_name(&self, name: &str) -> Option<&SymbolInfo> {
+        self.variables
+            .iter()
+            .chain(&self.functions)
+            .chain(&self.imports)
+            .find(|symbol| symbol.name == name)
+    }
 
-        for var in &self.variables {
-            symbols.push(SymbolInformation {
-                name: var.name.clone(),
-                kind: var.kind,
-                tags: None,
-                deprecated: None,
-                location: Location {
-                    uri: Url::parse("file:///dummy").unwrap(),
-                    range: var.range,
-                },
-                container_name: None,
-            });
+    /// The variable, function or import whose name is at `position`, so a later `Set`
+    /// of a name is found rather than its first one
+    pub fn find_at_position(&self, position: Position) -> Option<&SymbolInfo> {
+        self.index.name_at(position).map(|index| self.symbol(index))
+    }
+
+    /// Variables first, then functions, then imports
+    fn symbol(&self, index: usize) -> &SymbolInfo {
+        let Some(index) = index.checked_sub(self.variables.len()) else {
+            return &self.variables[index];
+        };
+        match index.checked_sub(self.functions.len()) {
+            Some(import) => &self.imports[import],
+            None => &self.functions[index],
         }
+    }
 
-        for func in &self.functions {
-            symbols.push(SymbolInformation {
-                name: func.name.clone(),
-                kind: func.kind,
+    /// Index of the innermost function whose body contains `symbol`
+    fn enclosing_function(&self, symbol: &SymbolInfo) -> Option<usize> {
+        self.index.body_around(symbol.range)
+    }
+
+    /// Flat outline entries located in `uri`; definitions inside a function name
+    /// it as their container, and imports and exports come first in "Imports"
+    /// and "Exports"
+    #[allow(deprecated)] // `SymbolInformation::deprecated` must still be initialized
+    pub fn to_document_symbols(&self, uri: &Url) -> Vec<SymbolInformation> {
+        let interface = [
+            (IMPORTS_CONTAINER, &self.import_statements),
+            (EXPORTS_CONTAINER, &self.exports),
+        ];
+        let interface = interface.into_iter().flat_map(|(container, entries)| {
+            entries.iter().map(move |entry| SymbolInformation {
+                name: entry.name.to_string(),
+                kind: entry.kind,
                 tags: None,
                 deprecated: None,
                 location: Location {
-                    uri: Url::parse("file:///dummy").unwrap(),
-                    range: func.range,
+                    uri: uri.clone(),
+                    range: entry.range,
                 },
-                container_name: None,
-            });
-        }
+                container_name: Some(container.to_string()),
+            })
+        });
 
-        symbols
+        let symbols: Vec<&SymbolInfo> = self.variables.iter().chain(&self.functions).collect();
+        let definitions = symbols.iter().map(|symbol| SymbolInformation {
+            name: symbol.name.to_string(),
+            kind: symbol.kind,
+            tags: symbol.deprecation_tags(),
+            deprecated: None,
+            location: Location {
+                uri: uri.clone(),
+                range: symbol.range,
+            },
+            container_name: self
+                .enclosing_function(symbol)
+                .map(|index| symbols[index].name.to_string()),
+        });
+        interface.chain(definitions).collect()
     }
 
-    pub fn rename_symbol(
-        &self,
-        _position: Position,
-        _new_name: &str,
-        _uri: &str,
-    ) -> Option<WorkspaceEdit> {
-        // TODO: 实现重命名
-        None
+    /// Outline entries with details, for clients that support hierarchical symbols.
+    /// Imports and exports are grouped in containers at the top; definitions inside
+    /// a function body are children of that function.
+    pub fn to_nested_document_symbols(&self) -> Vec<DocumentSymbol> {
+        let symbols: Vec<&SymbolInfo> = self.variables.iter().chain(&self.functions).collect();
+        let parents: Vec<Option<usize>> = symbols
+            .iter()
+            .map(|symbol| self.enclosing_function(symbol))
+            .collect();
+        let definitions = (0..symbols.len())
+            .filter(|&index| parents[index].is_none())
+            .map(|index| nested_symbol(&symbols, &parents, index));
+        container(IMPORTS_CONTAINER, &self.import_statements)
+            .into_iter()
+            .chain(container(EXPORTS_CONTAINER, &self.exports))
+            .chain(definitions)
+            .collect()
     }
 }
 
-/// Helper: Check if position is within range
-fn position_in_range(pos: Position, range: Range) -> bool {
-    if pos.line < range.start.line || pos.line > range.end.line {
-        return false;
-    }
-    if pos.line == range.start.line && pos.character < range.start.character {
-        return false;
-    }
-    if pos.line == range.end.line && pos.character > range.end.character {
-        return false;
-    }
-    true
-}
+const IMPORTS_CONTAINER: &str = "Imports";
+const EXPORTS_CONTAINER: &str = "Exports";
 
-/// Find comment for a variable by searching for "Set VARIABLE_NAME" pattern
-fn find_comment_for_variable(text: &str, var_name: &str) -> String {
-    let lines: Vec<&str> = text.lines().collect();
-
-    // Search for the line containing "Set VARIABLE_NAME"
-    for (idx, line) in lines.iter().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("Set ") || trimmed.starts_with("set ") {
-            // Check if this line contains our variable name
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if parts.len() >= 2 && parts[1] == var_name {
-                // Found the Set statement, now extract preceding comments
-                return extract_preceding_comment(text, idx);
-            }
-        }
-    }
-
-    String::new()
+/// A synthetic entry holding `entries`, spanning from the first to the last;
+/// `None` when there are none
+#[allow(deprecated)] // `DocumentSymbol::deprecated` must still be initialized
+fn container(name: &str, entries: &[SymbolInfo]) -> Option<DocumentSymbol> {
+    let (first, last) = (entries.first()?, entries.last()?);
+    let children = entries
+        .iter()
+        .map(|entry| DocumentSymbol {
+            name: entry.name.to_string(),
+            detail: entry.detail.clone(),
+            kind: entry.kind,
+            tags: None,
+            deprecated: None,
+            range: entry.range,
+            selection_range: entry.selection_range,
+            children: None,
+        })
+        .collect();
+    Some(DocumentSymbol {
+        name: name.to_string(),
+        detail: None,
+        kind: SymbolKind::NAMESPACE,
+        tags: None,
+        deprecated: None,
+        range: Range::new(first.range.start, last.range.end),
+        selection_range: first.range,
+        children: Some(children),
+    })
 }
 
-/// Extract comment from the lines above a given line
-fn extract_preceding_comment(text: &str, target_line: usize) -> String {
-    let lines: Vec<&str> = text.lines().collect();
-    if target_line == 0 || target_line > lines.len() {
-        return String::new();
+#[allow(deprecated)] // `DocumentSymbol::deprecated` must still be initialized
+fn nested_symbol(
+    symbols: &[&SymbolInfo],
+    parents: &[Option<usize>],
+    index: usize,
+) -> DocumentSymbol {
+    let symbol = symbols[index];
+    let children: Vec<DocumentSymbol> = (0..symbols.len())
+        .filter(|&child| parents[child] == Some(index))
+        .map(|child| nested_symbol(symbols, parents, child))
+        .collect();
+    DocumentSymbol {
+        name: symbol.name.to_string(),
+        detail: symbol.detail.clone(),
+        kind: symbol.kind,
+        tags: symbol.deprecation_tags(),
+        deprecated: None,
+        range: symbol.range,
+        selection_range: symbol.selection_range,
+        children: (!children.is_empty()).then_some(children),
     }
+}
 
+/// The comments above the `Set` statement on line `set_line`, looking past
+/// blank lines: a run of line comments, ending at most at one block comment
+fn find_comment_for_variable(lines: &LineIndex, text: &str, set_line: u32) -> String {
     let mut comments = Vec::new();
-    let mut line_idx = target_line.saturating_sub(1);
+    let mut line_idx = set_line;
 
     // Traverse upwards to collect comments
     while line_idx > 0 {
         line_idx -= 1;
-        let line = lines[line_idx].trim();
+        let Some(line) = lines.line_text(text, line_idx) else {
+            break;
+        };
+        let line = line.trim();
 
         if line.is_empty() {
             // Empty line - continue looking up
@@ -209,8 +487,7 @@ fn extract_preceding_comment(text: &str, target_line: usize) -> String {
             let mut block_idx = line_idx;
             let mut found_start = false;
 
-            loop {
-                let block_line = lines[block_idx];
+            while let Some(block_line) = lines.line_text(text, block_idx) {
                 block_lines.insert(0, block_line);
 
                 if block_line.trim().starts_with("/*") {
@@ -228,6 +505,7 @@ fn extract_preceding_comment(text: &str, target_line: usize) -> String {
                 // Extract content from block comment
                 let block_text = block_lines.join("\n");
                 let block_text = block_text
+                    .trim()
                     .trim_start_matches("/*")
                     .trim_end_matches("*/")
                     .trim();
@@ -243,159 +521,839 @@ fn extract_preceding_comment(text: &str, target_line: usize) -> String {
     comments.join("\n")
 }
 
-/// Extract symbols from a statement
-fn extract_symbols_from_stmt(stmt: &Stmt, table: &mut SymbolTable, text: &str) {
-    match stmt {
-        Stmt::Set { name, .. } => {
-            // Estimate line 0 as placeholder - we'll improve this with line tracking
-            let range = Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: 0,
-                    character: name.len() as u32,
-                },
-            };
+/// Collects definitions from every statement, however deeply nested
+struct SymbolCollector<'t> {
+    table: SymbolTable,
+    text: &'t str,
+    lines: &'t LineIndex,
+    comments: &'t [Comment],
+}
 
-            // Try to find the line where this Set statement appears
-            let comment = find_comment_for_variable(text, name);
-
-            let symbol = SymbolInfo {
-                name: name.clone(),
-                kind: SymbolKind::VARIABLE,
-                range,
-                selection_range: range,
-                documentation: if comment.is_empty() {
-                    String::new()
-                } else {
-                    comment
-                },
-                detail: Some(format!("Variable: {}", name)),
-            };
+/// A comment's text without its delimiters
+fn comment_text(comment: &Comment) -> &str {
+    let text = if comment.block {
+        comment.text.trim_start_matches("/*").trim_end_matches("*/")
+    } else {
+        comment.text.trim_start_matches('/')
+    };
+    text.trim()
+}
 
-            table.variables.push(symbol);
-        }
-        Stmt::FuncDef { name, params, body } => {
-            let range = Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: body.len() as u32,
-                    character: 0,
-                },
-            };
-            table.add_function(
-                name.clone(),
-                range,
-                params.clone(),
-                Some(format!(
-                    "Function: {}({}) {{ ... }}",
-                    name,
-                    params.join(", ")
-                )),
-            );
-
-            // Extract symbols from function body
-            for body_stmt in body {
-                extract_symbols_from_stmt(body_stmt, table, text);
-            }
+/// The run of comments ending on the line just above `line`, without delimiters
+fn doc_comment(comments: &[Comment], line: u32) -> String {
+    doc_comment_run(comments, line)
+        .into_iter()
+        .map(comment_text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The comments documenting a definition on `line`: the run ending on the line
+/// just above it. A comment trailing code documents that code, so it ends the run.
+pub(crate) fn doc_comment_run(comments: &[Comment], line: u32) -> Vec<&Comment> {
+    let mut run = Vec::new();
+    let mut next_line = line;
+    for comment in comments.iter().rev() {
+        if comment.span.start.line >= next_line {
+            continue;
         }
-        Stmt::GeneratorDef { name, params, body } => {
-            let range = Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: body.len() as u32,
-                    character: 0,
-                },
-            };
-            table.add_function(
-                name.clone(),
-                range,
-                params.clone(),
-                Some(format!(
-                    "Generator: {}({}) {{ ... }}",
-                    name,
-                    params.join(", ")
-                )),
-            );
-
-            for body_stmt in body {
-                extract_symbols_from_stmt(body_stmt, table, text);
-            }
+        if comment.span.end.line + 1 != next_line || comment.trailing {
+            break;
         }
-        Stmt::LazyDef { name, .. } => {
-            let range = Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: 0,
-                    character: name.len() as u32,
-                },
-            };
-            table.add_variable(name.clone(), range, Some(format!("Lazy: {}", name)));
+        run.push(comment);
+        next_line = comment.span.start.line;
+    }
+    run.reverse();
+    run
+}
+
+/// What the `@deprecated` line of `documentation` says after the tag, like
+/// `use NEW_NAME instead` from `// @deprecated use NEW_NAME instead`
+pub fn deprecation(documentation: &str) -> Option<String> {
+    documentation.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("@deprecated")?;
+        (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim().to_string())
+    })
+}
+
+/// The comment trailing the definition of `name` on its line, without delimiters
+fn trailing_comment<'c>(comments: &'c [Comment], name: &Ident) -> Option<&'c str> {
+    comments
+        .iter()
+        .find(|comment| {
+            comment.trailing
+                && comment.span.start.line == name.span.end.line
+                && comment.span.start.character >= name.span.end.character
+        })
+        .map(comment_text)
+}
+
+/// Documentation from the comments above a definition, followed by the one
+/// trailing it
+fn join_docs(preceding: String, trailing: Option<&str>) -> String {
+    match trailing {
+        Some(trailing) if preceding.is_empty() => trailing.to_string(),
+        Some(trailing) => format!("{}\n\n{}", preceding, trailing),
+        None => preceding,
+    }
+}
+
+impl<'a> Visitor<'a> for SymbolCollector<'_> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        self.record(stmt);
+        walk_stmt(self, stmt);
+    }
+}
+
+impl SymbolCollector<'_> {
+    /// Use the comments above a function definition, and the one trailing its
+    /// name, as its documentation
+    fn attach_doc_comment(&mut self, stmt: &Stmt, name: &Ident) {
+        let doc = join_docs(
+            doc_comment(self.comments, stmt.span.start.line),
+            trailing_comment(self.comments, name),
+        );
+        if let Some(function) = self.table.functions.last_mut()
+            && !doc.is_empty()
+        {
+            function.deprecated = deprecation(&doc);
+            function.documentation = doc;
         }
-        Stmt::While { body, .. } | Stmt::For { body, .. } | Stmt::ForIndexed { body, .. } => {
-            for body_stmt in body {
-                extract_symbols_from_stmt(body_stmt, table, text);
+    }
+
+    /// Record the symbol (if any) that `stmt` itself defines
+    fn record(&mut self, stmt: &Stmt) {
+        let table = &mut self.table;
+        let text = self.text;
+        match &stmt.kind {
+            StmtKind::Set { name, value } => {
+                match &value.kind {
+                    ExprKind::Dict(pairs) => {
+                        for (key, _) in pairs {
+                            table.add_dict_key(name, key);
+                        }
+                    }
+                    ExprKind::Array(elements) => {
+                        table
+                            .array_lengths
+                            .entry(name.to_string())
+                            .or_insert(elements.len());
+                    }
+                    _ => {}
+                }
+
+                let trailing = trailing_comment(self.comments, name);
+                let comment = join_docs(
+                    find_comment_for_variable(self.lines, text, stmt.span.start.line),
+                    trailing,
+                );
+
+                let symbol = match &value.kind {
+                    // `Set HANDLER Lambda X -> ...` is called like a function
+                    ExprKind::Lambda { params, .. } => {
+                        let params: Vec<Name> = params.iter().map(|p| p.name.clone()).collect();
+                        let signature = format!("Lambda: {}({})", name, params.join(", "));
+                        let comment =
+                            join_docs(doc_comment(self.comments, stmt.span.start.line), trailing);
+                        SymbolInfo {
+                            name: name.name.clone(),
+                            kind: SymbolKind::VARIABLE,
+                            range: stmt.span,
+                            selection_range: name.span,
+                            documentation: if comment.is_empty() {
+                                signature.clone()
+                            } else {
+                                format!("{}\n\n{}", signature, comment)
+                            },
+                            detail: Some(signature),
+                            flavor: SymbolFlavor::Lambda,
+                            params,
+                            deprecated: None,
+                        }
+                    }
+                    _ => SymbolInfo {
+                        name: name.name.clone(),
+                        kind: SymbolKind::VARIABLE,
+                        range: stmt.span,
+                        selection_range: name.span,
+                        documentation: if comment.is_empty() {
+                            String::new()
+                        } else {
+                            comment
+                        },
+                        detail: Some(format!("Variable: {}", name)),
+                        flavor: SymbolFlavor::Plain,
+                        params: Vec::new(),
+                        deprecated: None,
+                    },
+                };
+
+                table.variables.push(symbol);
             }
-        }
-        Stmt::Switch { cases, default, .. } => {
-            for (_, case_body) in cases {
-                for case_stmt in case_body {
-                    extract_symbols_from_stmt(case_stmt, table, text);
+            StmtKind::FuncDef { name, params, .. } => {
+                let param_names: Vec<Name> = params.iter().map(|p| p.name.clone()).collect();
+                let detail = format!("Function: {}({}) {{ ... }}", name, param_names.join(", "));
+                table.add_function(
+                    name.name.clone(),
+                    stmt.span,
+                    name.span,
+                    param_names,
+                    Some(detail),
+                    SymbolFlavor::Plain,
+                );
+                self.attach_doc_comment(stmt, name);
+            }
+            StmtKind::GeneratorDef { name, params, .. } => {
+                let param_names: Vec<Name> = params.iter().map(|p| p.name.clone()).collect();
+                let detail = format!("Generator: {}({}) {{ ... }}", name, param_names.join(", "));
+                table.add_function(
+                    name.name.clone(),
+                    stmt.span,
+                    name.span,
+                    param_names,
+                    Some(detail),
+                    SymbolFlavor::Generator,
+                );
+                self.attach_doc_comment(stmt, name);
+            }
+            StmtKind::LazyDef { name, expr } => {
+                table.add_variable(
+                    name.name.clone(),
+                    stmt.span,
+                    name.span,
+                    Some(format!("Lazy (unevaluated): {}", expr)),
+                );
+                if let Some(lazy) = table.variables.last_mut() {
+                    lazy.flavor = SymbolFlavor::Lazy;
+                    lazy.documentation = trailing_comment(self.comments, name)
+                        .unwrap_or_default()
+                        .to_string();
                 }
             }
-            if let Some(default_body) = default {
-                for default_stmt in default_body {
-                    extract_symbols_from_stmt(default_stmt, table, text);
+            StmtKind::Import {
+                names,
+                path,
+                aliases,
+                braced,
+            } => {
+                let listed = names
+                    .iter()
+                    .zip(aliases)
+                    .map(|(name, alias)| match alias {
+                        Some(alias) => format!("{} As {}", name, alias),
+                        None => name.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                table.import_statements.push(SymbolInfo {
+                    name: Name::from(path.as_str()),
+                    kind: SymbolKind::MODULE,
+                    range: stmt.span,
+                    selection_range: stmt.span,
+                    documentation: String::new(),
+                    detail: Some(if *braced {
+                        format!("{{{}}}", listed)
+                    } else {
+                        listed
+                    }),
+                    flavor: SymbolFlavor::Plain,
+                    params: Vec::new(),
+                    deprecated: None,
+                });
+                for (name, alias) in names.iter().zip(aliases) {
+                    let detail = match alias {
+                        Some(_) => format!("Imported from {} (original: {})", path, name),
+                        None => format!("Imported from {}", path),
+                    };
+                    let binding = alias.as_ref().unwrap_or(name);
+                    table.imports.push(SymbolInfo {
+                        name: binding.name.clone(),
+                        kind: SymbolKind::VARIABLE,
+                        range: stmt.span,
+                        selection_range: binding.span,
+                        documentation: String::new(),
+                        detail: Some(detail),
+                        flavor: SymbolFlavor::Import,
+                        params: Vec::new(),
+                        deprecated: None,
+                    });
                 }
             }
+            StmtKind::SetIndex { object, index, .. } => {
+                // `Set CONFIG["key"] value` extends the known keys of CONFIG
+                if let ExprKind::Identifier(name) = &object.kind
+                    && table.dict_keys.contains_key(name.as_str())
+                {
+                    match &index.kind {
+                        ExprKind::String(key) => {
+                            table.add_dict_key(name, &DictKey::Str(key.clone()))
+                        }
+                        ExprKind::Number(key) => table.add_dict_key(name, &DictKey::Num(*key)),
+                        _ => {}
+                    }
+                }
+            }
+            StmtKind::Export(name) => table.exports.push(SymbolInfo {
+                name: name.name.clone(),
+                kind: SymbolKind::INTERFACE,
+                range: stmt.span,
+                selection_range: name.span,
+                documentation: String::new(),
+                detail: None,
+                flavor: SymbolFlavor::Plain,
+                params: Vec::new(),
+                deprecated: None,
+            }),
+            _ => {}
         }
-        Stmt::Expression(expr) => {
-            extract_symbols_from_expr(expr, table, text);
-        }
-        _ => {}
     }
 }
 
-/// Extract symbols from an expression (for nested lambdas, if expressions, etc.)
-fn extract_symbols_from_expr(expr: &Expr, table: &mut SymbolTable, text: &str) {
-    match expr {
-        Expr::Lambda { params: _, body } => {
-            // Anonymous lambda - could track params if needed
-            for body_stmt in body {
-                extract_symbols_from_stmt(body_stmt, table, text);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::span_contains;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_symbols_inside_call_arguments_are_collected() {
+        let text = "PRINTLN(MAP([1], Func(X) {\n    Set DOUBLED (X * 2)\n    Return DOUBLED\n}))";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let names: Vec<&str> = doc
+            .symbols
+            .variables
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["DOUBLED"]);
+    }
+
+    #[test]
+    fn test_definitions_in_every_expression_position_are_collected() {
+        // One lambda defining a local in each place an expression can sit
+        let positions = [
+            (
+                "Return",
+                "Func OUTER() {\n    Return KEEP(Func(X) {\n        Set IN_RETURN X\n        Return IN_RETURN\n    })\n}",
+            ),
+            (
+                "Throw",
+                "Throw WRAP(Func(X) {\n    Set IN_THROW X\n    Return IN_THROW\n})",
+            ),
+            (
+                "SetIndex value",
+                "Set TABLE {}\nSet TABLE[\"k\"] Func(X) {\n    Set IN_SET_INDEX X\n    Return IN_SET_INDEX\n}",
+            ),
+            (
+                "SetIndex index",
+                "Set TABLE {}\nSet TABLE[KEY(Func(X) {\n    Set IN_INDEX X\n    Return IN_INDEX\n})] 1",
+            ),
+            (
+                "While condition",
+                "While (CHECK(Func(X) {\n    Set IN_WHILE X\n    Return IN_WHILE\n})) {\n    Break\n}",
+            ),
+            (
+                "If condition",
+                "If (CHECK(Func(X) {\n    Set IN_IF X\n    Return IN_IF\n})) {\n    PRINTLN(1)\n}",
+            ),
+            (
+                "Elif condition",
+                "If (False) {\n    PRINTLN(1)\n} Elif (CHECK(Func(X) {\n    Set IN_ELIF X\n    Return IN_ELIF\n})) {\n    PRINTLN(2)\n}",
+            ),
+            (
+                "Call argument",
+                "PRINTLN(MAP([1], Func(X) {\n    Set IN_ARGUMENT (X * 2)\n    Return IN_ARGUMENT\n}))",
+            ),
+            (
+                "Switch subject",
+                "Switch (PICK(Func(X) {\n    Set IN_SUBJECT X\n    Return IN_SUBJECT\n})) {\n    Case 1:\n        PRINTLN(1)\n}",
+            ),
+            (
+                "Switch case body",
+                "Switch (1) {\n    Case 1:\n        Set IN_CASE 1\n    Default:\n        Set IN_DEFAULT 2\n}",
+            ),
+            (
+                "For iterable",
+                "For ITEM In MAP([1], Func(X) {\n    Set IN_ITERABLE X\n    Return IN_ITERABLE\n}) {\n    PRINTLN(ITEM)\n}",
+            ),
+            (
+                "Array element",
+                "Set ALL [Func(X) {\n    Set IN_ARRAY X\n    Return IN_ARRAY\n}]",
+            ),
+            (
+                "Dict value",
+                "Set HANDLERS {on: Func(X) {\n    Set IN_DICT X\n    Return IN_DICT\n}}",
+            ),
+            (
+                "Force",
+                "PRINTLN(Force (Func(X) {\n    Set IN_FORCE X\n    Return IN_FORCE\n})(1))",
+            ),
+            (
+                "Lambda body",
+                "Set OUTER Lambda X -> MAP([X], Func(Y) {\n    Set IN_LAMBDA Y\n    Return IN_LAMBDA\n})",
+            ),
+        ];
+        for (position, text) in positions {
+            let doc = Parser::new(text).parse();
+            assert!(doc.errors.is_empty(), "{}: {:?}", position, doc.errors);
+            let locals: Vec<&str> = doc
+                .symbols
+                .variables
+                .iter()
+                .map(|symbol| symbol.name.as_str())
+                .filter(|name| name.starts_with("IN_"))
+                .collect();
+            assert!(!locals.is_empty(), "{}: nothing collected", position);
+
+            // The local is found under the cursor, not just by name
+            let local = doc.symbols.find_by_name(locals[0]).unwrap();
+            let found = doc
+                .symbols
+                .find_at_position(local.selection_range.start)
+                .map(|symbol| symbol.name.as_str());
+            assert_eq!(found, Some(locals[0]), "{}", position);
         }
-        Expr::If {
-            then_branch,
-            elif_branches,
-            else_branch,
-            ..
-        } => {
-            for stmt in then_branch {
-                extract_symbols_from_stmt(stmt, table, text);
-            }
-            for (_, elif_body) in elif_branches {
-                for stmt in elif_body {
-                    extract_symbols_from_stmt(stmt, table, text);
-                }
-            }
-            if let Some(else_body) = else_branch {
-                for stmt in else_body {
-                    extract_symbols_from_stmt(stmt, table, text);
-                }
-            }
+    }
+
+    #[test]
+    fn test_import_bindings_are_symbols() {
+        let text = "Import {LONG_HELPER_NAME As LH, PI} From \"./lib.ae\"\nPRINTLN(LH(PI))";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let alias = doc.symbols.find_by_name("LH").unwrap();
+        assert_eq!(alias.flavor, SymbolFlavor::Import);
+        assert_eq!(
+            alias.detail.as_deref(),
+            Some("Imported from ./lib.ae (original: LONG_HELPER_NAME)")
+        );
+        assert_eq!(
+            alias.selection_range,
+            Range::new(Position::new(0, 28), Position::new(0, 30))
+        );
+        assert_eq!(
+            doc.symbols.find_by_name("PI").unwrap().detail.as_deref(),
+            Some("Imported from ./lib.ae")
+        );
+        assert!(doc.symbols.find_by_name("LONG_HELPER_NAME").is_none());
+        assert_eq!(
+            doc.symbols
+                .find_at_position(Position::new(0, 29))
+                .map(|symbol| symbol.name.as_str()),
+            Some("LH")
+        );
+        // Only the statement is in the outline, not each binding
+        let outline = doc.symbols.to_nested_document_symbols();
+        assert_eq!(outline.len(), 1);
+        assert_eq!(outline[0].name, "Imports");
+    }
+
+    #[test]
+    fn test_lambda_bindings_are_callable() {
+        let text = "// Reacts to an event\nSet HANDLER Lambda (EVENT, RETRIES) -> EVENT\nSet ON_DONE Func(RESULT) {\n    Return RESULT\n}\nSet ON_DONE 0\nSet DOUBLE Lambda X -> X * 2\nSet DOUBLE Lambda Y -> Y * 2";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let handler = doc.symbols.find_by_name("HANDLER").unwrap();
+        assert_eq!(handler.flavor, SymbolFlavor::Lambda);
+        assert_eq!(
+            handler.detail.as_deref(),
+            Some("Lambda: HANDLER(EVENT, RETRIES)")
+        );
+        assert_eq!(
+            handler.documentation,
+            "Lambda: HANDLER(EVENT, RETRIES)\n\nReacts to an event"
+        );
+        assert_eq!(
+            doc.symbols.callable_params("HANDLER"),
+            Some(&["EVENT".into(), "RETRIES".into()][..])
+        );
+
+        // Rebound to a plain value, or to a lambda of another shape
+        for name in ["ON_DONE", "DOUBLE"] {
+            let symbol = doc.symbols.find_by_name(name).unwrap();
+            assert_eq!(symbol.flavor, SymbolFlavor::Plain, "{}", name);
+            assert_eq!(symbol.detail, Some(format!("Variable: {}", name)));
+            assert!(doc.symbols.callable_params(name).is_none());
         }
-        _ => {}
+    }
+
+    #[test]
+    fn test_symbol_flavors_in_outline() {
+        let text = "Func ADD(A, B) {\n    Return A + B\n}\nGenerator COUNT(N) {\n    Yield N\n}\nLazy TOTAL(ADD(1, 2))\nSet PLAIN 1";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let outline: Vec<(String, SymbolKind, Option<String>)> = doc
+            .symbols
+            .to_nested_document_symbols()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.detail))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (
+                    "TOTAL".to_string(),
+                    SymbolKind::VARIABLE,
+                    Some("Lazy (unevaluated): ADD(1, 2)".to_string())
+                ),
+                (
+                    "PLAIN".to_string(),
+                    SymbolKind::VARIABLE,
+                    Some("Variable: PLAIN".to_string())
+                ),
+                (
+                    "ADD".to_string(),
+                    SymbolKind::FUNCTION,
+                    Some("Function: ADD(A, B) { ... }".to_string())
+                ),
+                (
+                    "COUNT".to_string(),
+                    SymbolKind::FUNCTION,
+                    Some("Generator: COUNT(N) { ... }".to_string())
+                ),
+            ]
+        );
+
+        let flavor = |name: &str| doc.symbols.find_by_name(name).unwrap().flavor;
+        assert_eq!(flavor("TOTAL"), SymbolFlavor::Lazy);
+        assert_eq!(flavor("COUNT"), SymbolFlavor::Generator);
+        assert_eq!(flavor("ADD"), SymbolFlavor::Plain);
+        assert_eq!(
+            doc.symbols.find_by_name("COUNT").unwrap().documentation,
+            "Generator: COUNT(N)"
+        );
+    }
+
+    #[test]
+    fn test_function_doc_comments() {
+        let text = "// Unrelated\n\n/* Reads the config\n   at PATH */\n// Returns a dict\nFunc LOAD(PATH) {\n    Return PATH\n}\nFunc BARE() {\n    Return 1\n}";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        assert_eq!(
+            doc.symbols.find_by_name("LOAD").unwrap().documentation,
+            "Reads the config\n   at PATH\nReturns a dict"
+        );
+        assert_eq!(
+            doc.symbols.find_by_name("BARE").unwrap().documentation,
+            "Function: BARE()"
+        );
+    }
+
+    #[test]
+    fn test_variable_doc_comments() {
+        let text = "// Attempts before giving up\nSet RETRIES 3\nFunc RUN() {\n    /* Scratch\n       space */\n    Set BUFFER []\n    Return BUFFER\n}\nSet RETRIES 4\n\n// Shown\n\nSet PLAIN 0";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let documentation: Vec<&str> = doc
+            .symbols
+            .variables
+            .iter()
+            .map(|symbol| symbol.documentation.as_str())
+            .collect();
+        assert_eq!(
+            documentation,
+            vec![
+                "Attempts before giving up",
+                "Scratch\n       space",
+                "",
+                "Shown"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_doc_comments() {
+        let text = "Set RETRY_LIMIT 3 // max attempts before giving up\n\
+                    // Seconds between attempts\nSet DELAY 5\n\
+                    // Where results go\nSet OUT [] /* cleared per run */\n\
+                    Func RUN(N) { // runs N attempts\n    Set TRIES 0 // so far\n    Return TRIES\n}\n\
+                    Lazy DATA(RUN(1)) // fetched on first use\nSet AFTER 1";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let documentation = |name: &str| {
+            doc.symbols
+                .find_by_name(name)
+                .unwrap()
+                .documentation
+                .clone()
+        };
+        assert_eq!(
+            documentation("RETRY_LIMIT"),
+            "max attempts before giving up"
+        );
+        assert_eq!(documentation("DELAY"), "Seconds between attempts");
+        assert_eq!(documentation("OUT"), "Where results go\n\ncleared per run");
+        assert_eq!(documentation("RUN"), "runs N attempts");
+        // Inside a body, going by the definition's own line
+        assert_eq!(documentation("TRIES"), "so far");
+        assert_eq!(documentation("DATA"), "fetched on first use");
+        // A comment trailing the line above documents that line, not this one
+        assert_eq!(documentation("AFTER"), "");
+    }
+
+    #[test]
+    fn test_imports_and_exports_in_outline() {
+        let text = "Import {ADD, PI As CIRCLE} From \"./math.ae\"\nImport UTILS From \"./utils.ae\"\n\nFunc AREA(R) {\n    Return CIRCLE * R * R\n}\nSet UNIT 1\nExport AREA\nExport UNIT\nExport ADD";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let outline = doc.symbols.to_nested_document_symbols();
+        let top: Vec<(&str, SymbolKind)> = outline
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind))
+            .collect();
+        assert_eq!(
+            top,
+            vec![
+                ("Imports", SymbolKind::NAMESPACE),
+                ("Exports", SymbolKind::NAMESPACE),
+                ("UNIT", SymbolKind::VARIABLE),
+                ("AREA", SymbolKind::FUNCTION),
+            ]
+        );
+
+        let entries =
+            |symbol: &DocumentSymbol| -> Vec<(String, SymbolKind, Option<String>, Range)> {
+                symbol
+                    .children
+                    .iter()
+                    .flatten()
+                    .map(|entry| {
+                        (
+                            entry.name.clone(),
+                            entry.kind,
+                            entry.detail.clone(),
+                            entry.range,
+                        )
+                    })
+                    .collect()
+            };
+        let line =
+            |line: u32, end: u32| Range::new(Position::new(line, 0), Position::new(line, end));
+        assert_eq!(
+            entries(&outline[0]),
+            vec![
+                (
+                    "./math.ae".to_string(),
+                    SymbolKind::MODULE,
+                    Some("{ADD, PI As CIRCLE}".to_string()),
+                    line(0, 43)
+                ),
+                (
+                    "./utils.ae".to_string(),
+                    SymbolKind::MODULE,
+                    Some("UTILS".to_string()),
+                    line(1, 30)
+                ),
+            ]
+        );
+        assert_eq!(
+            outline[0].range,
+            Range::new(Position::new(0, 0), Position::new(1, 30))
+        );
+        assert_eq!(
+            entries(&outline[1]),
+            vec![
+                (
+                    "AREA".to_string(),
+                    SymbolKind::INTERFACE,
+                    Some("Function: AREA(R) { ... }".to_string()),
+                    line(7, 11)
+                ),
+                (
+                    "UNIT".to_string(),
+                    SymbolKind::INTERFACE,
+                    Some("Variable: UNIT".to_string()),
+                    line(8, 11)
+                ),
+                (
+                    "ADD".to_string(),
+                    SymbolKind::INTERFACE,
+                    Some("Imported from ./math.ae".to_string()),
+                    line(9, 10)
+                ),
+            ]
+        );
+        let export = &outline[1].children.as_ref().unwrap()[0];
+        assert_eq!(
+            export.selection_range,
+            Range::new(Position::new(7, 7), Position::new(7, 11))
+        );
+
+        let flat: Vec<(String, Option<String>)> = doc
+            .symbols
+            .to_document_symbols(&Url::parse("untitled:Untitled-1").unwrap())
+            .into_iter()
+            .map(|symbol| {
+                assert_eq!(symbol.location.uri.as_str(), "untitled:Untitled-1");
+                (symbol.name, symbol.container_name)
+            })
+            .take(5)
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                ("./math.ae".to_string(), Some("Imports".to_string())),
+                ("./utils.ae".to_string(), Some("Imports".to_string())),
+                ("AREA".to_string(), Some("Exports".to_string())),
+                ("UNIT".to_string(), Some("Exports".to_string())),
+                ("ADD".to_string(), Some("Exports".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outline_ranges_cover_definitions() {
+        let text = "Set LIMIT 3\nFunc OUTER(X) {\n    Set INNER_VALUE (X + 1)\n    Func HELPER() {\n        While (X < LIMIT) {\n            Set X (X + 1)\n        }\n    }\n    Return INNER_VALUE\n}\nGenerator EACH(N) {\n    Yield N\n}";
+        let doc = Parser::new(text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let outer = doc.symbols.find_by_name("OUTER").unwrap();
+        assert_eq!(
+            outer.range,
+            Range::new(Position::new(1, 0), Position::new(9, 1))
+        );
+        assert_eq!(
+            outer.selection_range,
+            Range::new(Position::new(1, 5), Position::new(1, 10))
+        );
+        let each = doc.symbols.find_by_name("EACH").unwrap();
+        assert_eq!(
+            (each.range.start.line, each.range.end),
+            (10, Position::new(12, 1))
+        );
+
+        // Breadcrumbs follow containment: descend to the innermost symbol around a position
+        let outline = doc.symbols.to_nested_document_symbols();
+        let mut path = Vec::new();
+        let mut level = Some(&outline);
+        let deep = Position::new(5, 16);
+        while let Some(symbol) = level.and_then(|symbols| {
+            symbols
+                .iter()
+                .find(|symbol| symbol.range.start <= deep && deep <= symbol.range.end)
+        }) {
+            path.push(symbol.name.as_str());
+            level = symbol.children.as_ref();
+        }
+        assert_eq!(path, vec!["OUTER", "HELPER", "X"]);
+
+        let top: Vec<&str> = outline.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(top, vec!["LIMIT", "OUTER", "EACH"]);
+        let containers: Vec<(String, Option<String>)> = doc
+            .symbols
+            .to_document_symbols(&Url::parse("file:///a.ae").unwrap())
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.container_name))
+            .collect();
+        assert!(containers.contains(&("INNER_VALUE".to_string(), Some("OUTER".to_string()))));
+        assert!(containers.contains(&("HELPER".to_string(), Some("OUTER".to_string()))));
+        assert!(containers.contains(&("LIMIT".to_string(), None)));
+    }
+
+    #[test]
+    fn test_position_index_matches_brute_force() {
+        // 1250 blocks of four symbols each, two levels of nested functions
+        let text: String = (0..1250)
+            .map(|i| {
+                format!(
+                    "Func OUTER_{i}(A) {{\n    Set VALUE_{i} A\n    Func INNER_{i}() {{\n        Set DEEP_{i} 1\n    }}\n    Return VALUE_{i}\n}}\n"
+                )
+            })
+            .collect();
+        let doc = Parser::new(&text).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+        let symbols = &doc.symbols;
+        let all: Vec<&SymbolInfo> = symbols.variables.iter().chain(&symbols.functions).collect();
+        assert_eq!(all.len(), 5000);
+
+        let positions: Vec<Position> = (0..text.lines().count() as u32)
+            .step_by(5)
+            .flat_map(|line| {
+                (0..24)
+                    .step_by(2)
+                    .map(move |character| Position::new(line, character))
+            })
+            .collect();
+        let brute_name_at = |position: Position| {
+            all.iter()
+                .find(|symbol| span_contains(symbol.selection_range, position))
+                .map(|symbol| symbol.selection_range)
+        };
+        let brute_enclosing = |symbol: &SymbolInfo| {
+            all.iter()
+                .enumerate()
+                .filter(|(_, function)| {
+                    function.kind == SymbolKind::FUNCTION
+                        && function.range != symbol.range
+                        && range_contains(function.range, symbol.range)
+                })
+                .max_by_key(|(_, function)| function.range.start)
+                .map(|(index, _)| index)
+        };
+
+        let brute: Vec<Option<Range>> = positions.iter().map(|&p| brute_name_at(p)).collect();
+        let sampled: Vec<&SymbolInfo> = all.iter().step_by(7).copied().collect();
+        let brute_parents: Vec<Option<usize>> =
+            sampled.iter().map(|s| brute_enclosing(s)).collect();
+
+        let indexed: Vec<Option<Range>> = positions
+            .iter()
+            .map(|&p| {
+                symbols
+                    .find_at_position(p)
+                    .map(|symbol| symbol.selection_range)
+            })
+            .collect();
+        let parents: Vec<Option<usize>> = sampled
+            .iter()
+            .map(|s| symbols.enclosing_function(s))
+            .collect();
+
+        assert_eq!(indexed, brute);
+        assert_eq!(parents, brute_parents);
+        assert!(indexed.iter().filter(|range| range.is_some()).count() > 1000);
+        assert!(parents.iter().filter(|parent| parent.is_some()).count() > 500);
+    }
+
+    #[test]
+    fn test_find_at_position_picks_the_binding_under_the_cursor() {
+        let doc = Parser::new("// First\nSet LIMIT 1\n// Raised\nSet LIMIT 2").parse();
+        let at = |line, character| {
+            doc.symbols
+                .find_at_position(Position::new(line, character))
+                .map(|symbol| symbol.documentation.as_str())
+        };
+        assert_eq!(at(1, 4), Some("First"));
+        assert_eq!(at(3, 9), Some("Raised"));
+        assert_eq!(at(3, 10), None);
+        assert_eq!(at(2, 4), None);
+    }
+
+    #[test]
+    fn test_deprecated_tag() {
+        let doc = Parser::new(
+            "// Old way\n// @deprecated use NEW_TOTAL instead\nFunc OLD_TOTAL(XS) {\n    Return 0\n}\n\
+             // @deprecated\nGenerator EACH() {\n    Yield 1\n}\n\
+             // @deprecatedly not\nFunc KEPT() {\n    Return 1\n}",
+        )
+        .parse();
+        let deprecated = |name: &str| doc.symbols.find_by_name(name).unwrap().deprecated.clone();
+        assert_eq!(
+            deprecated("OLD_TOTAL").as_deref(),
+            Some("use NEW_TOTAL instead")
+        );
+        assert_eq!(deprecated("EACH").as_deref(), Some(""));
+        assert_eq!(deprecated("KEPT"), None);
+        assert_eq!(
+            doc.symbols
+                .find_by_name("OLD_TOTAL")
+                .unwrap()
+                .deprecation_tags(),
+            Some(vec![SymbolTag::DEPRECATED])
+        );
     }
 }
@@ -0,0 +1,63 @@
+//! A counting global allocator for tests
+//!
+//! Counts are kept per thread, so a test measuring its own code isn't
+//! disturbed by tests running in parallel.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Allocation activity of the current thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocStats {
+    pub allocations: usize,
+    /// Bytes allocated on this thread and not yet freed
+    pub live: usize,
+    /// Highest `live` since the last [`reset_peak`]
+    pub peak: usize,
+}
+
+thread_local! {
+    static STATS: Cell<AllocStats> = const {
+        Cell::new(AllocStats { allocations: 0, live: 0, peak: 0 })
+    };
+}
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = STATS.try_with(|stats| {
+            let mut current = stats.get();
+            current.allocations += 1;
+            current.live += layout.size();
+            current.peak = current.peak.max(current.live);
+            stats.set(current);
+        });
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = STATS.try_with(|stats| {
+            let mut current = stats.get();
+            current.live = current.live.saturating_sub(layout.size());
+            stats.set(current);
+        });
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+pub fn stats() -> AllocStats {
+    STATS.with(Cell::get)
+}
+
+/// Start measuring the peak from what is live now
+pub fn reset_peak() {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        current.peak = current.live;
+        stats.set(current);
+    });
+}
@@ -2,6 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::intern::Name;
+
+#[allow(clippy::upper_case_acronyms)] // `EOF` is the conventional spelling
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Token {
     // Keywords
@@ -40,7 +43,7 @@ pub enum Token {
     Null,
 
     // Identifiers
-    Identifier(String),
+    Identifier(Name),
 
     // Operators
     Plus,
@@ -68,6 +71,7 @@ pub enum Token {
     LeftBrace,
     RightBrace,
     Comma,
+    Dot,
     Colon,
     Semicolon,
     Newline,
@@ -77,10 +81,44 @@ pub enum Token {
     Illegal(char),
 }
 
+/// Every keyword, and keyword-like literal, with the token it lexes to
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("Set", Token::Set),
+    ("Func", Token::Func),
+    ("Return", Token::Return),
+    ("If", Token::If),
+    ("Elif", Token::Elif),
+    ("Else", Token::Else),
+    ("While", Token::While),
+    ("For", Token::For),
+    ("In", Token::In),
+    ("Break", Token::Break),
+    ("Continue", Token::Continue),
+    ("Generator", Token::Generator),
+    ("Yield", Token::Yield),
+    ("Lazy", Token::Lazy),
+    ("Force", Token::Force),
+    ("Switch", Token::Switch),
+    ("Case", Token::Case),
+    ("Default", Token::Default),
+    ("Import", Token::Import),
+    ("Export", Token::Export),
+    ("From", Token::From),
+    ("As", Token::As),
+    ("Lambda", Token::Lambda),
+    ("Throw", Token::Throw),
+    ("Try", Token::Try),
+    ("Catch", Token::Catch),
+    ("True", Token::Boolean(true)),
+    ("False", Token::Boolean(false)),
+    ("Null", Token::Null),
+];
+
 impl Token {
-    /// Check if a string is a keyword, otherwise return it as an identifier
-    pub fn lookup_keyword(ident: &str) -> Token {
-        match ident {
+    /// The keyword (or keyword-like literal) spelled `ident`, if it is one
+    pub fn keyword(ident: &str) -> Option<Token> {
+        // A match rather than a scan of `KEYWORDS`: the lexer asks for every identifier
+        Some(match ident {
             "Set" => Token::Set,
             "Func" => Token::Func,
             "Return" => Token::Return,
@@ -110,7 +148,7 @@ impl Token {
             "True" => Token::Boolean(true),
             "False" => Token::Boolean(false),
             "Null" => Token::Null,
-            _ => Token::Identifier(ident.to_string()),
-        }
+            _ => return None,
+        })
     }
 }
@@ -0,0 +1,170 @@
+//! Recognizing edits that only touched comments or whitespace
+//!
+//! Two texts whose tokens match one for one, apart from comments and line
+//! breaks, parse to the same program and get the same diagnostics. What can
+//! still differ is the line each token is on; [`LineShift`] maps positions of
+//! the old text onto the new one so cached diagnostics can be moved instead of
+//! recomputed.
+
+use lsp_types::{Diagnostic, Position, Range, Url};
+
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+/// Where each line holding tokens in the old text went in the new one
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineShift {
+    /// `(old line, new line)` for the first token of each line, in order
+    anchors: Vec<(u32, u32)>,
+}
+
+/// A token as far as the parser can tell, without the line it's on
+#[derive(PartialEq)]
+struct Shape {
+    token: Token,
+    /// `F (X)` and `F(X)` parse differently
+    had_whitespace: bool,
+    start_character: u32,
+    /// Lines spanned, for strings that run over several
+    lines: u32,
+    end_character: u32,
+}
+
+/// Each token with the line it starts on, skipping line breaks
+fn shapes(text: &str) -> Vec<(u32, Shape)> {
+    let mut lexer = Lexer::new(text);
+    let mut shapes = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        match token {
+            Token::EOF => break,
+            Token::Newline => continue,
+            _ => {}
+        }
+        let span = lexer.token_span();
+        shapes.push((
+            span.start.line,
+            Shape {
+                token,
+                had_whitespace: lexer.had_whitespace(),
+                start_character: span.start.character,
+                lines: span.end.line - span.start.line,
+                end_character: span.end.character,
+            },
+        ));
+    }
+    shapes
+}
+
+/// How `new` shifts the lines of `old`, if the two differ only in comments,
+/// blank lines and whitespace that moves no token within its line. Tokens
+/// that shared a line must still share one, and tokens on separate lines must
+/// stay on separate lines, since line breaks end statements.
+pub fn line_shift(old: &str, new: &str) -> Option<LineShift> {
+    let old = shapes(old);
+    let new = shapes(new);
+    if old.len() != new.len() {
+        return None;
+    }
+
+    let mut anchors: Vec<(u32, u32)> = Vec::new();
+    for ((old_line, old_shape), (new_line, new_shape)) in old.iter().zip(&new) {
+        if old_shape != new_shape {
+            return None;
+        }
+        match anchors.last() {
+            Some(&(last_old, last_new)) if last_old == *old_line => {
+                if last_new != *new_line {
+                    return None;
+                }
+            }
+            Some(&(_, last_new)) if last_new == *new_line => return None,
+            _ => anchors.push((*old_line, *new_line)),
+        }
+    }
+    Some(LineShift { anchors })
+}
+
+impl LineShift {
+    /// No token changed lines
+    pub fn is_identity(&self) -> bool {
+        self.anchors.iter().all(|(old, new)| old == new)
+    }
+
+    /// A line of the old text moves with the last token line at or above it
+    fn line(&self, line: u32) -> u32 {
+        let anchor = match self.anchors.partition_point(|(old, _)| *old <= line) {
+            0 => self.anchors.first(),
+            index => self.anchors.get(index - 1),
+        };
+        match anchor {
+            Some(&(old, new)) => (line + new).saturating_sub(old),
+            None => line,
+        }
+    }
+
+    pub fn position(&self, position: Position) -> Position {
+        Position::new(self.line(position.line), position.character)
+    }
+
+    pub fn range(&self, range: Range) -> Range {
+        Range::new(self.position(range.start), self.position(range.end))
+    }
+
+    /// Move a diagnostic of the document at `uri`, and the related locations in that document
+    pub fn diagnostic(&self, uri: &Url, mut diagnostic: Diagnostic) -> Diagnostic {
+        diagnostic.range = self.range(diagnostic.range);
+        for related in diagnostic.related_information.iter_mut().flatten() {
+            if related.location.uri == *uri {
+                related.location.range = self.range(related.location.range);
+            }
+        }
+        diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_shift_ignores_comments_and_blank_lines() {
+        let old = "Set A 1\nFunc F(X) {\n    Return X\n}";
+        let new =
+            "// Counter\nSet A 1 // start\n\nFunc F(X) {\n    /* identity */\n    Return X\n}";
+        let shift = line_shift(old, new).unwrap();
+        assert!(!shift.is_identity());
+        assert_eq!(shift.position(Position::new(0, 4)), Position::new(1, 4));
+        assert_eq!(shift.position(Position::new(2, 11)), Position::new(5, 11));
+        assert_eq!(shift.position(Position::new(3, 1)), Position::new(6, 1));
+
+        let shift = line_shift(old, "Set A 1 // one\nFunc F(X) {\n    Return X\n}").unwrap();
+        assert!(shift.is_identity());
+    }
+
+    #[test]
+    fn test_line_shift_rejects_real_edits() {
+        let old = "Set A 1\nSet B 2";
+        // A changed token
+        assert!(line_shift(old, "Set A 1\nSet B 3").is_none());
+        // Joining or splitting lines changes where statements end
+        assert!(line_shift(old, "Set A 1 Set B 2").is_none());
+        assert!(line_shift("Set A (1 + 2)", "Set A (1 +\n2)").is_none());
+        // A token moved within its line
+        assert!(line_shift(old, "Set A  1\nSet B 2").is_none());
+    }
+
+    #[test]
+    fn test_diagnostic_follows_its_line() {
+        let uri = Url::parse("file:///a.ae").unwrap();
+        let shift = line_shift("Set a 1", "// note\n\nSet a 1").unwrap();
+        let diagnostic = Diagnostic {
+            range: Range::new(Position::new(0, 4), Position::new(0, 5)),
+            ..Default::default()
+        };
+        assert_eq!(
+            shift.diagnostic(&uri, diagnostic).range,
+            Range::new(Position::new(2, 4), Position::new(2, 5))
+        );
+    }
+}
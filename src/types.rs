@@ -0,0 +1,259 @@
+//! What kind of value an expression certainly gives, where that's plain
+//!
+//! Deliberately shallow: literals, operators, builtins known to return a
+//! Number, and variables whose every `Set` agrees. A name bound any other way
+//! as well (a parameter, a loop variable, an import, a definition) could hold
+//! anything, so it is [`ValueType::Unknown`], and rules stay silent on that.
+
+use std::collections::HashMap;
+
+use crate::ast::{BinOp, Expr, ExprKind, Ident, Program, Stmt, StmtKind, UnaryOp};
+use crate::builtins;
+use crate::locale::Locale;
+use crate::visit::{Visitor, walk_expr, walk_stmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Number,
+    String,
+    Boolean,
+    Null,
+    Array,
+    Dict,
+    Unknown,
+}
+
+impl ValueType {
+    /// The type as Aether's `TYPE` builtin names it
+    pub fn name(&self, locale: Locale) -> &'static str {
+        match self {
+            ValueType::Number => locale.pick("数字", "Number"),
+            ValueType::String => locale.pick("字符串", "String"),
+            ValueType::Boolean => locale.pick("布尔值", "Boolean"),
+            ValueType::Null => "Null",
+            ValueType::Array => locale.pick("数组", "Array"),
+            ValueType::Dict => locale.pick("字典", "Dict"),
+            ValueType::Unknown => locale.pick("未知", "Unknown"),
+        }
+    }
+}
+
+/// The variables of a program, for inferring the types of its expressions
+pub struct Types<'a> {
+    /// Each name's `Set` values; `None` once it is bound some other way
+    bindings: HashMap<&'a str, Option<Vec<&'a Expr>>>,
+    /// What each variable holds, as far as worked out
+    variables: HashMap<&'a str, ValueType>,
+}
+
+impl<'a> Types<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut collector = Collector::default();
+        collector.visit_block(program);
+        let mut types = Types {
+            bindings: collector.bindings,
+            variables: HashMap::new(),
+        };
+        // A variable starts out unknown-yet, then gets the type its values
+        // agree on, or Unknown; `Set N (N + 1)` keeps whatever `N` was
+        loop {
+            let mut changed = false;
+            for (name, values) in &types.bindings {
+                let Some(values) = values else {
+                    continue;
+                };
+                let Some(agreed) = values
+                    .iter()
+                    .filter_map(|value| types.infer(value))
+                    .reduce(|a, b| if a == b { a } else { ValueType::Unknown })
+                else {
+                    continue;
+                };
+                if types.variables.get(name) != Some(&agreed) {
+                    types.variables.insert(name, agreed);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return types;
+            }
+        }
+    }
+
+    /// The type `expr` certainly evaluates to
+    pub fn of(&self, expr: &Expr) -> ValueType {
+        self.infer(expr).unwrap_or(ValueType::Unknown)
+    }
+
+    /// `None` while nothing is known about the variable yet
+    fn variable(&self, name: &str) -> Option<ValueType> {
+        match self.bindings.get(name) {
+            Some(Some(_)) => self.variables.get(name).copied(),
+            Some(None) => Some(ValueType::Unknown),
+            None if builtins::lookup_constant(name).is_some() => Some(ValueType::Number),
+            None => Some(ValueType::Unknown),
+        }
+    }
+
+    /// `None` while it depends only on variables nothing is known about yet
+    fn infer(&self, expr: &Expr) -> Option<ValueType> {
+        let known = match &expr.kind {
+            ExprKind::Number(_) | ExprKind::BigInteger(_) => ValueType::Number,
+            ExprKind::String(_) => ValueType::String,
+            ExprKind::Boolean(_) => ValueType::Boolean,
+            ExprKind::Null => ValueType::Null,
+            ExprKind::Array(_) => ValueType::Array,
+            ExprKind::Dict(_) => ValueType::Dict,
+            ExprKind::Identifier(name) => return self.variable(name.as_str()),
+            ExprKind::Call { func, .. } => match &func.kind {
+                ExprKind::Identifier(name) if !self.bindings.contains_key(name.as_str()) => {
+                    match builtins::lookup(name.as_str()) {
+                        Some(builtin) if builtin.returns_number() => ValueType::Number,
+                        _ => ValueType::Unknown,
+                    }
+                }
+                _ => ValueType::Unknown,
+            },
+            ExprKind::Binary { left, op, right } => match op {
+                BinOp::Equal
+                | BinOp::NotEqual
+                | BinOp::Less
+                | BinOp::LessEqual
+                | BinOp::Greater
+                | BinOp::GreaterEqual => ValueType::Boolean,
+                BinOp::And | BinOp::Or => ValueType::Unknown,
+                BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulo => {
+                    // A side not known yet takes the other's type
+                    let (left, right) = match (self.infer(left), self.infer(right)) {
+                        (None, None) => return None,
+                        (Some(left), None) => (left, left),
+                        (None, Some(right)) => (right, right),
+                        (Some(left), Some(right)) => (left, right),
+                    };
+                    match (left, right) {
+                        (ValueType::Number, ValueType::Number) => ValueType::Number,
+                        (ValueType::String, ValueType::String) if *op == BinOp::Add => {
+                            ValueType::String
+                        }
+                        _ => ValueType::Unknown,
+                    }
+                }
+            },
+            ExprKind::Unary {
+                op: UnaryOp::Not, ..
+            } => ValueType::Boolean,
+            ExprKind::Unary {
+                op: UnaryOp::Minus,
+                expr,
+            } => match self.infer(expr)? {
+                ValueType::Number => ValueType::Number,
+                _ => ValueType::Unknown,
+            },
+            _ => ValueType::Unknown,
+        };
+        Some(known)
+    }
+}
+
+#[derive(Default)]
+struct Collector<'a> {
+    bindings: HashMap<&'a str, Option<Vec<&'a Expr>>>,
+}
+
+impl<'a> Collector<'a> {
+    fn set(&mut self, name: &'a Ident, value: &'a Expr) {
+        if let Some(values) = self
+            .bindings
+            .entry(name.as_str())
+            .or_insert(Some(Vec::new()))
+        {
+            values.push(value);
+        }
+    }
+
+    fn rebind(&mut self, name: &'a Ident) {
+        self.bindings.insert(name.as_str(), None);
+    }
+
+    fn rebind_all(&mut self, names: &'a [Ident]) {
+        for name in names {
+            self.rebind(name);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Collector<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Set { name, value } => self.set(name, value),
+            StmtKind::FuncDef { name, params, .. }
+            | StmtKind::GeneratorDef { name, params, .. } => {
+                self.rebind(name);
+                self.rebind_all(params);
+            }
+            StmtKind::LazyDef { name, .. } => self.rebind(name),
+            StmtKind::For { var, .. } => self.rebind(var),
+            StmtKind::ForIndexed {
+                index_var,
+                value_var,
+                ..
+            } => {
+                self.rebind(index_var);
+                self.rebind(value_var);
+            }
+            StmtKind::Import { names, aliases, .. } => {
+                for (name, alias) in names.iter().zip(aliases) {
+                    self.rebind(alias.as_ref().unwrap_or(name));
+                }
+            }
+            StmtKind::Try {
+                error_var: Some(error_var),
+                ..
+            } => self.rebind(error_var),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Lambda { params, .. } = &expr.kind {
+            self.rebind_all(params);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_variables_agree_or_are_unknown() {
+        let text = "Set TOTAL SUM([1, 2])\nSet TOTAL (TOTAL + 1)\nSet HALF TOTAL / 2\n\
+                    Set LABEL \"a\" + \"b\"\nSet MIXED 1\nSet MIXED \"one\"\n\
+                    Func F(ARG) {\n    Set DONE ARG > 1\n    Return ARG\n}\n\
+                    For I In [1] {\n    Set LAST I\n}\nSet I 0\nSet ANGLE PI * 2\n\
+                    Func LENGTH(X) {\n    Return X\n}\nSet SIZE LENGTH(1)";
+        let doc = Parser::new(text).parse();
+        let types = Types::new(&doc.ast);
+        let expected = [
+            ("TOTAL", ValueType::Number),
+            ("HALF", ValueType::Number),
+            ("LABEL", ValueType::String),
+            ("MIXED", ValueType::Unknown),
+            ("DONE", ValueType::Boolean),
+            ("ARG", ValueType::Unknown),
+            ("LAST", ValueType::Unknown),
+            ("I", ValueType::Unknown),
+            ("ANGLE", ValueType::Number),
+            // A user function named like a builtin
+            ("SIZE", ValueType::Unknown),
+            ("NEVER_SET", ValueType::Unknown),
+        ];
+        for (name, expected) in expected {
+            let inferred = types.variable(name).unwrap_or(ValueType::Unknown);
+            assert_eq!(inferred, expected, "{}", name);
+        }
+    }
+}
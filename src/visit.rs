@@ -0,0 +1,288 @@
+//! Read-only traversal of the AST
+//!
+//! Implement [`Visitor`] and override the hooks you care about; call the
+//! matching `walk_*` function from an override to keep descending. The walks
+//! match exhaustively so a new node kind can't be silently skipped.
+
+use crate::ast::{DictKey, Expr, ExprKind, Stmt, StmtKind};
+
+pub trait Visitor<'a> {
+    fn visit_block(&mut self, body: &'a [Stmt]) {
+        walk_block(self, body);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_block<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, body: &'a [Stmt]) {
+    for stmt in body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+/// Visit the expressions and nested blocks of `stmt`, in source order
+pub fn walk_stmt<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, stmt: &'a Stmt) {
+    match &stmt.kind {
+        StmtKind::Set { value, .. } => visitor.visit_expr(value),
+        StmtKind::SetIndex {
+            object,
+            index,
+            value,
+        } => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(index);
+            visitor.visit_expr(value);
+        }
+        StmtKind::FuncDef { body, .. } | StmtKind::GeneratorDef { body, .. } => {
+            visitor.visit_block(body)
+        }
+        StmtKind::LazyDef { expr, .. }
+        | StmtKind::Return(expr)
+        | StmtKind::Yield(expr)
+        | StmtKind::Throw(expr)
+        | StmtKind::Expression(expr) => visitor.visit_expr(expr),
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::For { iterable, body, .. } | StmtKind::ForIndexed { iterable, body, .. } => {
+            visitor.visit_expr(iterable);
+            visitor.visit_block(body);
+        }
+        StmtKind::Switch {
+            expr,
+            cases,
+            default,
+        } => {
+            visitor.visit_expr(expr);
+            for (case_expr, case_body) in cases {
+                visitor.visit_expr(case_expr);
+                visitor.visit_block(case_body);
+            }
+            if let Some(default_body) = default {
+                visitor.visit_block(default_body);
+            }
+        }
+        StmtKind::Try { body, handler, .. } => {
+            visitor.visit_block(body);
+            visitor.visit_block(handler);
+        }
+        StmtKind::Import { .. } | StmtKind::Export(_) | StmtKind::Break | StmtKind::Continue => {}
+    }
+}
+
+/// Visit the sub-expressions and nested blocks of `expr`, in source order
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+        ExprKind::Array(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::Dict(pairs) => {
+            for (key, value) in pairs {
+                if let DictKey::Computed(key) = key {
+                    visitor.visit_expr(key);
+                }
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Unary { expr, .. } => visitor.visit_expr(expr),
+        ExprKind::Call { func, args } => {
+            visitor.visit_expr(func);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Index { object, index } => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Member { object, .. } => visitor.visit_expr(object),
+        ExprKind::Force(expr) => visitor.visit_expr(expr),
+        ExprKind::If {
+            condition,
+            then_branch,
+            elif_branches,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(then_branch);
+            for (elif_condition, elif_body) in elif_branches {
+                visitor.visit_expr(elif_condition);
+                visitor.visit_block(elif_body);
+            }
+            if let Some(else_body) = else_branch {
+                visitor.visit_block(else_body);
+            }
+        }
+        ExprKind::Lambda { body, .. } => visitor.visit_block(body),
+        ExprKind::Number(_)
+        | ExprKind::BigInteger(_)
+        | ExprKind::String(_)
+        | ExprKind::Boolean(_)
+        | ExprKind::Null
+        | ExprKind::Identifier(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use std::collections::BTreeSet;
+
+    /// Records which variants were reached; the matches are deliberately wildcard-free
+    #[derive(Default)]
+    struct VariantCounter {
+        seen: BTreeSet<&'static str>,
+        nodes: usize,
+    }
+
+    impl<'a> Visitor<'a> for VariantCounter {
+        fn visit_stmt(&mut self, stmt: &'a Stmt) {
+            self.nodes += 1;
+            self.seen.insert(match &stmt.kind {
+                StmtKind::Set { .. } => "Set",
+                StmtKind::SetIndex { .. } => "SetIndex",
+                StmtKind::FuncDef { .. } => "FuncDef",
+                StmtKind::GeneratorDef { .. } => "GeneratorDef",
+                StmtKind::LazyDef { .. } => "LazyDef",
+                StmtKind::Return(_) => "Return",
+                StmtKind::Yield(_) => "Yield",
+                StmtKind::Break => "Break",
+                StmtKind::Continue => "Continue",
+                StmtKind::While { .. } => "While",
+                StmtKind::For { .. } => "For",
+                StmtKind::ForIndexed { .. } => "ForIndexed",
+                StmtKind::Switch { .. } => "Switch",
+                StmtKind::Import { .. } => "Import",
+                StmtKind::Export(_) => "Export",
+                StmtKind::Throw(_) => "Throw",
+                StmtKind::Try { .. } => "Try",
+                StmtKind::Expression(_) => "Expression",
+            });
+            walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            self.nodes += 1;
+            self.seen.insert(match &expr.kind {
+                ExprKind::Number(_) => "Number",
+                ExprKind::BigInteger(_) => "BigInteger",
+                ExprKind::String(_) => "String",
+                ExprKind::Boolean(_) => "Boolean",
+                ExprKind::Null => "Null",
+                ExprKind::Identifier(_) => "Identifier",
+                ExprKind::Array(_) => "Array",
+                ExprKind::Dict(_) => "Dict",
+                ExprKind::Binary { .. } => "Binary",
+                ExprKind::Unary { .. } => "Unary",
+                ExprKind::Call { .. } => "Call",
+                ExprKind::Index { .. } => "Index",
+                ExprKind::Member { .. } => "Member",
+                ExprKind::Force(_) => "Force",
+                ExprKind::If { .. } => "If",
+                ExprKind::Lambda { .. } => "Lambda",
+            });
+            walk_expr(self, expr);
+        }
+    }
+
+    const EVERY_VARIANT: &str = r#"Import {HELPER} From "./helper"
+Set CONFIG {"debug": True, "name": Null}
+Set CONFIG["level"] 12345678901234567890
+Func CHECK(X) {
+    If (!X) {
+        Throw "missing"
+    } Else {
+        Return X[0]
+    }
+}
+Generator COUNT(N) {
+    While (N > 0) {
+        Yield N
+        Set N (N - 1)
+    }
+}
+Lazy LATER(HELPER.RUN(1))
+Set NOW Force(LATER)
+For ITEM In [1, 2] {
+    Continue
+}
+For I, ITEM In [3] {
+    Break
+}
+Switch (CONFIG) {
+    Case "a":
+        PRINTLN(MAP([1], Lambda V -> V))
+    Default:
+        PRINTLN("b")
+}
+Try {
+    CHECK(CONFIG)
+} Catch (ERR) {
+    PRINTLN(ERR)
+}
+Export CHECK
+"#;
+
+    #[test]
+    fn test_visitor_reaches_every_variant() {
+        let doc = Parser::new(EVERY_VARIANT).parse();
+        assert!(doc.errors.is_empty(), "{:?}", doc.errors);
+
+        let mut counter = VariantCounter::default();
+        counter.visit_block(&doc.ast);
+        let expected: BTreeSet<&str> = [
+            "Set",
+            "SetIndex",
+            "FuncDef",
+            "GeneratorDef",
+            "LazyDef",
+            "Return",
+            "Yield",
+            "Break",
+            "Continue",
+            "While",
+            "For",
+            "ForIndexed",
+            "Switch",
+            "Import",
+            "Export",
+            "Throw",
+            "Try",
+            "Expression",
+            "Number",
+            "BigInteger",
+            "String",
+            "Boolean",
+            "Null",
+            "Identifier",
+            "Array",
+            "Dict",
+            "Binary",
+            "Unary",
+            "Call",
+            "Index",
+            "Member",
+            "Force",
+            "If",
+            "Lambda",
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(counter.seen, expected);
+        assert_eq!(counter.nodes, 75);
+    }
+}
@@ -0,0 +1,434 @@
+//! Background index of every module in the workspace
+//!
+//! At startup each `.ae` file under the workspace root is parsed once and
+//! reduced to a [`ModuleSummary`]. Workspace symbols, import completion and
+//! cross-file goto read the summaries instead of opening files on demand.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use dashmap::DashMap;
+use lsp_types::{Range, SymbolKind};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::ast::StmtKind;
+use crate::ignore::IgnoreRules;
+use crate::modules::{self, ExportedSymbol};
+use crate::parser::{ParsedDocument, Parser};
+
+/// Files parsed at the same time while indexing
+const MAX_CONCURRENT_PARSES: usize = 4;
+
+/// What the rest of the workspace needs to know about a module
+#[derive(Debug, Clone, Default)]
+pub struct ModuleSummary {
+    pub exports: Vec<ExportedSymbol>,
+    /// Top-level definitions, first binding of each name only
+    pub symbols: Vec<TopLevelSymbol>,
+    pub imports: Vec<ModuleImport>,
+}
+
+/// One `Import` statement of a module
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleImport {
+    /// Resolved path of the imported module
+    pub path: PathBuf,
+    /// The whole statement
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopLevelSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The whole defining statement
+    pub range: Range,
+    /// Just the name
+    pub selection_range: Range,
+    pub detail: Option<String>,
+    /// Doc comment above the definition, if any
+    pub documentation: String,
+    /// What the `@deprecated` tag of a function's doc comment says
+    pub deprecated: Option<String>,
+}
+
+impl ModuleSummary {
+    pub fn symbol(&self, name: &str) -> Option<&TopLevelSymbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+}
+
+/// Reduce a parsed module at `path` to its summary; imports resolve against `roots`
+/// where they aren't relative to the module
+pub fn summarize(path: &Path, doc: &ParsedDocument, roots: &[PathBuf]) -> ModuleSummary {
+    let mut symbols: Vec<TopLevelSymbol> = Vec::new();
+    for stmt in doc.ast.iter() {
+        let (name, kind) = match &stmt.kind {
+            StmtKind::Set { name, .. } | StmtKind::LazyDef { name, .. } => {
+                (name, SymbolKind::VARIABLE)
+            }
+            StmtKind::FuncDef { name, .. } | StmtKind::GeneratorDef { name, .. } => {
+                (name, SymbolKind::FUNCTION)
+            }
+            _ => continue,
+        };
+        if symbols.iter().any(|symbol| symbol.name == name.name) {
+            continue;
+        }
+        let info = doc.symbols.find_by_name(name);
+        symbols.push(TopLevelSymbol {
+            name: name.name.to_string(),
+            kind,
+            range: stmt.span,
+            selection_range: name.span,
+            detail: info.and_then(|symbol| symbol.detail.clone()),
+            documentation: info
+                .map(|symbol| symbol.documentation.clone())
+                .unwrap_or_default(),
+            deprecated: info.and_then(|symbol| symbol.deprecated.clone()),
+        });
+    }
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    ModuleSummary {
+        exports: modules::exported_symbols(doc),
+        symbols,
+        imports: doc
+            .ast
+            .imports()
+            .iter()
+            .map(|import| ModuleImport {
+                path: modules::resolve_import_in(base_dir, roots, &import.path),
+                range: import.span,
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    modules: DashMap<PathBuf, ModuleSummary>,
+    /// Workspace folders, in the order the client listed them
+    roots: RwLock<Vec<PathBuf>>,
+    /// What each folder leaves out of workspace-wide results
+    ignored: RwLock<HashMap<PathBuf, IgnoreRules>>,
+}
+
+impl WorkspaceIndex {
+    pub fn new() -> Self {
+        WorkspaceIndex::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots
+            .read()
+            .map(|roots| roots.clone())
+            .unwrap_or_default()
+    }
+
+    /// Add a workspace folder; `false` if it was already one
+    pub fn add_root(&self, root: PathBuf) -> bool {
+        let Ok(mut roots) = self.roots.write() else {
+            return false;
+        };
+        if roots.contains(&root) {
+            return false;
+        }
+        roots.push(root);
+        true
+    }
+
+    /// Forget a workspace folder along with the modules indexed under it that no
+    /// other folder contains
+    pub fn remove_root(&self, root: &Path) {
+        let remaining = match self.roots.write() {
+            Ok(mut roots) => {
+                roots.retain(|existing| existing != root);
+                roots.clone()
+            }
+            Err(_) => return,
+        };
+        self.modules.retain(|path, _| {
+            !path.starts_with(root) || remaining.iter().any(|other| path.starts_with(other))
+        });
+        if let Ok(mut ignored) = self.ignored.write() {
+            ignored.remove(root);
+        }
+    }
+
+    /// Replace the patterns of the workspace folder `root`
+    pub fn set_ignore_rules(&self, root: &Path, rules: IgnoreRules) {
+        if let Ok(mut ignored) = self.ignored.write() {
+            ignored.insert(root.to_path_buf(), rules);
+        }
+    }
+
+    /// Whether the file at `path` is ignored by the folder it is in. It can still
+    /// be loaded for its importers, but stays out of workspace-wide results.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let Ok(ignored) = self.ignored.read() else {
+            return false;
+        };
+        ignored.iter().any(|(root, rules)| {
+            path.strip_prefix(root)
+                .is_ok_and(|relative| rules.is_ignored(relative, false))
+        })
+    }
+
+    pub fn summary(&self, path: &Path) -> Option<ModuleSummary> {
+        self.modules.get(path).map(|summary| summary.clone())
+    }
+
+    /// The summary of `path`, indexing it first if it wasn't yet
+    pub fn summary_or_load(&self, path: &Path) -> Option<ModuleSummary> {
+        self.summary(path)
+            .or_else(|| self.index_file(path).then(|| self.summary(path)).flatten())
+    }
+
+    /// Every indexed module that isn't excluded, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .modules
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|path| !self.is_excluded(path))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Read and summarize the file at `path`; an unreadable file is dropped from the index
+    pub fn index_file(&self, path: &Path) -> bool {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let summary = summarize(path, &Parser::new(&text).parse(), &self.roots());
+                self.modules.insert(path.to_path_buf(), summary);
+                true
+            }
+            Err(_) => {
+                self.remove(path);
+                false
+            }
+        }
+    }
+
+    pub fn remove(&self, path: &Path) {
+        self.modules.remove(path);
+    }
+
+    /// Whether any of `targets` is among `imports` or the modules they import in turn
+    pub fn reaches(&self, imports: Vec<PathBuf>, targets: &HashSet<PathBuf>) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = imports;
+        while let Some(path) = pending.pop() {
+            if targets.contains(&path) {
+                return true;
+            }
+            if visited.insert(path.clone())
+                && let Some(summary) = self.modules.get(&path)
+            {
+                pending.extend(summary.imports.iter().map(|import| import.path.clone()));
+            }
+        }
+        false
+    }
+
+    /// The shortest chain of imports leading from `start` to `target`, loading modules
+    /// missing from the index on the way. Each step is the importing module and its import.
+    pub fn import_chain(
+        &self,
+        start: &Path,
+        target: &Path,
+    ) -> Option<Vec<(PathBuf, ModuleImport)>> {
+        let mut reached_from: HashMap<PathBuf, (PathBuf, ModuleImport)> = HashMap::new();
+        let mut visited = HashSet::from([start.to_path_buf()]);
+        let mut pending = VecDeque::from([start.to_path_buf()]);
+        while let Some(path) = pending.pop_front() {
+            let Some(summary) = self.summary_or_load(&path) else {
+                continue;
+            };
+            for import in summary.imports {
+                if import.path == target {
+                    let mut chain = vec![(path.clone(), import)];
+                    let mut current = path;
+                    while let Some((from, step)) = reached_from.remove(&current) {
+                        chain.push((from.clone(), step));
+                        current = from;
+                    }
+                    chain.reverse();
+                    return Some(chain);
+                }
+                if visited.insert(import.path.clone()) {
+                    pending.push_back(import.path.clone());
+                    reached_from.insert(import.path.clone(), (path.clone(), import));
+                }
+            }
+        }
+        None
+    }
+
+    /// The modules a file could import `name` from: those exporting it, or with
+    /// `implicit_exports` any defining it at the top level; by path, leaving out
+    /// excluded ones
+    pub fn exporters(&self, name: &str, implicit_exports: bool) -> Vec<PathBuf> {
+        let mut found: Vec<PathBuf> = self
+            .modules
+            .iter()
+            .filter(|entry| {
+                entry.exports.iter().any(|export| export.name == name)
+                    || (implicit_exports && entry.symbol(name).is_some())
+            })
+            .map(|entry| entry.key().clone())
+            .filter(|path| !self.is_excluded(path))
+            .collect();
+        found.sort();
+        found
+    }
+
+    /// Top-level symbols of modules that aren't excluded whose name contains
+    /// `query`, ignoring case, by path then name
+    pub fn search(&self, query: &str) -> Vec<(PathBuf, TopLevelSymbol)> {
+        let query = query.to_uppercase();
+        let mut found: Vec<(PathBuf, TopLevelSymbol)> = self
+            .modules
+            .iter()
+            .filter(|entry| !self.is_excluded(entry.key()))
+            .flat_map(|entry| {
+                entry
+                    .symbols
+                    .iter()
+                    .filter(|symbol| symbol.name.to_uppercase().contains(&query))
+                    .map(|symbol| (entry.key().clone(), symbol.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        found.sort_by(|a, b| (&a.0, &a.1.name).cmp(&(&b.0, &b.1.name)));
+        found
+    }
+}
+
+/// Parse `files` into the index in the background, at most a few at a time.
+/// Each task in the returned set finishes when its file is indexed.
+pub fn spawn_indexing(index: Arc<WorkspaceIndex>, files: Vec<PathBuf>) -> JoinSet<()> {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_PARSES));
+    let mut tasks = JoinSet::new();
+    for path in files {
+        let index = index.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+            let _ = tokio::task::spawn_blocking(move || index.index_file(&path)).await;
+        });
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_index_nested_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("lib/math")).unwrap();
+        fs::create_dir_all(root.join("generated")).unwrap();
+        fs::write(
+            root.join("main.ae"),
+            "Import {ADD} From \"./lib/math/ops.ae\"\nSet RESULT ADD(1, 2)\nSet RESULT 4\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("lib/math/ops.ae"),
+            "Import {CLAMP_TO} From \"../util.ae\"\nFunc ADD(A, B) {\n    Return A + B\n}\nGenerator EVENS(N) {\n    Yield N\n}\nExport ADD",
+        )
+        .unwrap();
+        fs::write(root.join("lib/util.ae"), "Lazy LIMIT(100)\nExport LIMIT").unwrap();
+        fs::write(root.join("generated/out.ae"), "Set GENERATED 1").unwrap();
+
+        let files = modules::discover_workspace(root, &IgnoreRules::new(["generated"]));
+        let index = Arc::new(WorkspaceIndex::new());
+        let mut tasks = spawn_indexing(index.clone(), files);
+        while tasks.join_next().await.is_some() {}
+
+        assert_eq!(
+            index.paths(),
+            vec![
+                root.join("lib/math/ops.ae"),
+                root.join("lib/util.ae"),
+                root.join("main.ae"),
+            ]
+        );
+
+        let main = index.summary(&root.join("main.ae")).unwrap();
+        assert_eq!(main.imports[0].path, root.join("lib/math/ops.ae"));
+        assert_eq!(main.imports[0].range.end.character, 37);
+        assert_eq!(main.symbols.len(), 1);
+        assert_eq!(main.symbols[0].range.start.line, 1);
+        assert!(main.exports.is_empty());
+
+        let ops = index.summary(&root.join("lib/math/ops.ae")).unwrap();
+        assert_eq!(ops.imports[0].path, root.join("lib/util.ae"));
+        let symbols: Vec<(&str, SymbolKind)> = ops
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("ADD", SymbolKind::FUNCTION),
+                ("EVENS", SymbolKind::FUNCTION)
+            ]
+        );
+        let add = ops.symbol("ADD").unwrap();
+        assert_eq!((add.range.start.line, add.range.end.line), (1, 3));
+        assert_eq!(add.selection_range.start.character, 5);
+        assert_eq!(ops.exports[0].name, "ADD");
+
+        let util = index.summary(&root.join("lib/util.ae")).unwrap();
+        assert_eq!(util.exports[0].name, "LIMIT");
+        assert_eq!(util.symbols[0].kind, SymbolKind::VARIABLE);
+
+        let found: Vec<String> = index
+            .search("e")
+            .into_iter()
+            .map(|(_, symbol)| symbol.name)
+            .collect();
+        assert_eq!(found, vec!["EVENS", "RESULT"]);
+
+        assert_eq!(
+            index.exporters("ADD", false),
+            vec![root.join("lib/math/ops.ae")]
+        );
+        assert!(index.exporters("EVENS", false).is_empty());
+        assert_eq!(
+            index.exporters("EVENS", true),
+            vec![root.join("lib/math/ops.ae")]
+        );
+
+        // Excluded modules can still be loaded but leave workspace-wide results
+        index.set_ignore_rules(root, IgnoreRules::new(["lib/math/"]));
+        assert!(index.is_excluded(&root.join("lib/math/ops.ae")));
+        assert!(index.summary(&root.join("lib/math/ops.ae")).is_some());
+        assert!(index.exporters("ADD", false).is_empty());
+        assert_eq!(
+            index.paths(),
+            vec![root.join("lib/util.ae"), root.join("main.ae")]
+        );
+        index.set_ignore_rules(root, IgnoreRules::default());
+
+        fs::remove_file(root.join("lib/util.ae")).unwrap();
+        assert!(!index.index_file(&root.join("lib/util.ae")));
+        assert!(index.summary(&root.join("lib/util.ae")).is_none());
+    }
+}
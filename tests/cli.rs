@@ -0,0 +1,31 @@
+#![cfg(feature = "server")]
+
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_version_exits_without_serving() {
+    // With stdin closed a server would exit too, but not print the version
+    let output = Command::new(env!("CARGO_BIN_EXE_aether-lsp"))
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(&format!("aether-lsp {}", env!("CARGO_PKG_VERSION"))),
+        "{}",
+        stdout
+    );
+    assert_eq!(stdout.lines().count(), 1);
+}
+
+#[test]
+fn test_unknown_argument_fails() {
+    let output = Command::new(env!("CARGO_BIN_EXE_aether-lsp"))
+        .arg("--serve")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -7,10 +7,29 @@ import {
     LanguageClientOptions,
     ServerOptions,
     Executable,
+    StaticFeature,
 } from 'vscode-languageclient/node';
 
 let client: LanguageClient;
 
+// 字典键重命名：服务器的代码操作只带 [uri, position]，新键名由客户端询问后补上
+const RENAME_DICT_KEY_COMMAND = 'aether.renameDictKey';
+
+// 告诉服务器本客户端会询问新键名，服务器才提供该代码操作
+const renameDictKeyPrompt: StaticFeature = {
+    fillClientCapabilities(capabilities) {
+        capabilities.experimental = {
+            ...capabilities.experimental,
+            renameDictKeyPrompt: true,
+        };
+    },
+    initialize() {},
+    getState() {
+        return { kind: 'static' };
+    },
+    dispose() {},
+};
+
 export function activate(context: ExtensionContext) {
     // 确定二进制文件名（Windows 需要 .exe 后缀）
     const binaryName = process.platform === 'win32' ? 'aether-lsp.exe' : 'aether-lsp';
@@ -57,11 +76,32 @@ export function activate(context: ExtensionContext) {
 
     // 客户端选项
     const clientOptions: LanguageClientOptions = {
-        documentSelector: [{ scheme: 'file', language: 'aether' }],
+        // 未保存（untitled）文档和 REPL 缓冲区也能使用单文件功能；
+        // git、diff 等只读视图不发给服务器
+        documentSelector: [
+            { scheme: 'file', language: 'aether' },
+            { scheme: 'untitled', language: 'aether' },
+            { scheme: 'aether-repl', language: 'aether' },
+        ],
         synchronize: {
             // 修复：正确监听 .aether 文件变化
             fileEvents: workspace.createFileSystemWatcher('**/*.aether'),
         },
+        middleware: {
+            executeCommand: async (command, args, next) => {
+                if (command === RENAME_DICT_KEY_COMMAND && args.length === 2) {
+                    const newKey = await window.showInputBox({
+                        prompt: '新的键名 (New key)',
+                        validateInput: (value) => (value.length === 0 ? '键名不能为空' : undefined),
+                    });
+                    if (newKey === undefined) {
+                        return undefined;
+                    }
+                    return next(command, [...args, newKey]);
+                }
+                return next(command, args);
+            },
+        },
     };
 
     // 创建 LSP 客户端
@@ -71,6 +111,7 @@ export function activate(context: ExtensionContext) {
         serverOptions,
         clientOptions
     );
+    client.registerFeature(renameDictKeyPrompt);
 
     // 启动客户端并处理错误
     client.start().catch((error) => {